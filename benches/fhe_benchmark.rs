@@ -1,12 +1,12 @@
 #[macro_use]
 extern crate criterion;
 
-use criterion::{black_box, Criterion, BenchmarkId};
+use criterion::{Criterion, BenchmarkId};
 use std::sync::Arc;
 use tonic::Request;
 
 use hermetic_fhe::api::{
-    DecryptBooleanRequest, DecryptIntegerRequest, EncryptBooleanRequest, EncryptIntegerRequest,
+    EncryptBooleanRequest, EncryptIntegerRequest,
     EvaluationRequest, FheService, KeyGenerationRequest, OperationType,
 };
 use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
@@ -23,6 +23,7 @@ fn setup_service() -> impl FheService {
 async fn generate_keys(service: &impl FheService, parameter_set: i32) -> (String, String) {
     let key_gen_request = Request::new(KeyGenerationRequest {
         parameter_set,
+        ..Default::default()
     });
     
     let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
@@ -36,6 +37,7 @@ async fn encrypt_boolean(service: &impl FheService, client_key_id: &str, value:
     let encrypt_request = Request::new(EncryptBooleanRequest {
         client_key_id: client_key_id.to_string(),
         value,
+        ..Default::default()
     });
     
     let encrypt_response = service.encrypt_boolean(encrypt_request).await.unwrap();
@@ -48,6 +50,7 @@ async fn encrypt_integer(service: &impl FheService, client_key_id: &str, value:
         client_key_id: client_key_id.to_string(),
         value,
         num_bits,
+        ..Default::default()
     });
     
     let encrypt_response = service.encrypt_integer(encrypt_request).await.unwrap();
@@ -97,11 +100,12 @@ fn bench_boolean_operations(c: &mut Criterion) {
                     let a_id = encrypt_boolean(&service, &client_key_id, true).await;
                     
                     // For NOT, we only need one operand
-                    if op_type == &(OperationType::Not as i32) {
+                    if op_type == OperationType::Not as i32 {
                         let eval_request = Request::new(EvaluationRequest {
                             server_key_id: server_key_id.clone(),
-                            operation: *op_type,
+                            operation: op_type,
                             operand_ids: vec![a_id.clone()],
+                            ..Default::default()
                         });
                         
                         service.evaluate_operation(eval_request).await.unwrap();
@@ -110,8 +114,9 @@ fn bench_boolean_operations(c: &mut Criterion) {
                         
                         let eval_request = Request::new(EvaluationRequest {
                             server_key_id: server_key_id.clone(),
-                            operation: *op_type,
+                            operation: op_type,
                             operand_ids: vec![a_id.clone(), b_id.clone()],
+                            ..Default::default()
                         });
                         
                         service.evaluate_operation(eval_request).await.unwrap();
@@ -148,8 +153,9 @@ fn bench_integer_operations(c: &mut Criterion) {
                     
                     let eval_request = Request::new(EvaluationRequest {
                         server_key_id: server_key_id.clone(),
-                        operation: *op_type,
+                        operation: op_type,
                         operand_ids: vec![a_id.clone(), b_id.clone()],
+                        ..Default::default()
                     });
                     
                     service.evaluate_operation(eval_request).await.unwrap();
@@ -181,6 +187,7 @@ fn bench_parameter_sets(c: &mut Criterion) {
                         server_key_id: server_key_id.clone(),
                         operation: OperationType::Add as i32,
                         operand_ids: vec![a_id.clone(), b_id.clone()],
+                        ..Default::default()
                     });
                     
                     service.evaluate_operation(eval_request).await.unwrap();
@@ -1,7 +1,77 @@
+// The tfhe-rs series the tfhe requirement in Cargo.toml resolves to. Persisted data is
+// stamped and checked by series, so this moves with the requirement and never on its own.
+const TFHE_SERIES: &str = "0.5";
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    println!("cargo:rerun-if-changed=proto/fhe_service.proto");
-    
-    tonic_build::compile_protos("proto/fhe_service.proto")?;
+    // Record the series, and the resolved release so the server can report it
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    println!("cargo:rustc-env=HERMETIC_FHE_TFHE_SERIES={}", TFHE_SERIES);
+    println!("cargo:rustc-env=HERMETIC_FHE_TFHE_VERSION={}", locked_version("tfhe", TFHE_SERIES));
+
+    // The generated gRPC code is only needed by the server feature
+    #[cfg(feature = "server")]
+    {
+        let protos = [
+            "proto/hermetic_fhe/v1/fhe_service.proto",
+            "proto/hermetic_fhe/v1/admin_service.proto",
+        ];
+        for proto in protos {
+            println!("cargo:rerun-if-changed={}", proto);
+        }
+        // Messages carrying plaintext get a Debug impl that redacts it, in service::logging,
+        // instead of the derived one. A message listed here but not there fails to compile.
+        const REDACTED_MESSAGES: [&str; 25] = [
+            "EncryptBooleanRequest",
+            "EncryptIntegerRequest",
+            "BooleanResponse",
+            "IntegerResponse",
+            "EncryptAndEvaluateRequest",
+            "EvaluateAndDecryptResponse",
+            "PlaintextValue",
+            "SetMembershipRequest",
+            "PirQueryRequest",
+            "MatchStringRequest",
+            "IncrementCounterRequest",
+            "QueryBloomFilterRequest",
+            "EncryptMatrixRequest",
+            "DecryptMatrixResponse",
+            "MatrixVectorProductRequest",
+            "MatrixScaleRequest",
+            "EncryptRealVectorRequest",
+            "RealVectorResponse",
+            "EncryptIntegerBatchRequest",
+            "IntegerBatchResponse",
+            "ModelLayer",
+            "EncryptTimestampRequest",
+            "TimestampResponse",
+            "CompareTimestampRequest",
+            "BucketTimestampRequest",
+        ];
+        let mut config = prost_build::Config::new();
+        config.skip_debug(REDACTED_MESSAGES.map(|message| format!(".hermetic_fhe.v1.{}", message)));
+        tonic_build::configure().compile_with_config(config, &protos, &["proto"])?;
+    }
     
     Ok(())
-} 
\ No newline at end of file
+}
+
+// Version of a package in the series as pinned in Cargo.lock, which may pin other series
+// of it for other dependents. Without a lock file, as when the crate builds as a
+// dependency, only the series is known.
+fn locked_version(package: &str, series: &str) -> String {
+    let lock = std::fs::read_to_string("Cargo.lock").unwrap_or_default();
+    let name_line = format!("name = \"{}\"", package);
+    let prefix = format!("{}.", series);
+    let mut lines = lock.lines();
+    while let Some(line) = lines.next() {
+        if line == name_line {
+            if let Some(version) = lines.next().and_then(|line| line.strip_prefix("version = ")) {
+                let version = version.trim_matches('"');
+                if version.starts_with(&prefix) {
+                    return version.to_string();
+                }
+            }
+        }
+    }
+    series.to_string()
+}
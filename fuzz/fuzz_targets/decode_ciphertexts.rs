@@ -0,0 +1,45 @@
+#![no_main]
+
+use hermetic_fhe::crypto::bgv::BgvCiphertext;
+use hermetic_fhe::crypto::canonical::{self, Canonical};
+use hermetic_fhe::crypto::ckks::CkksCiphertext;
+use hermetic_fhe::crypto::matrix::EncryptedMatrix;
+use hermetic_fhe::crypto::timestamp::EncryptedTimestamp;
+use libfuzzer_sys::fuzz_target;
+use tfhe::{FheBool, FheUint8};
+
+// Bytes as ImportCiphertext, IngestEncryptedRecords and a restored backup hand them to
+// the decoder. Anything it accepts must be safe to use and encode again.
+fn round_trip<T: Canonical>(bytes: &[u8], inspect: impl Fn(&T)) {
+    let Ok(value) = canonical::decode::<T>(bytes) else {
+        return;
+    };
+    inspect(&value);
+    let encoded = canonical::encode(&value).expect("an accepted value encodes");
+    canonical::decode::<T>(&encoded).expect("an encoded value decodes");
+}
+
+// The first byte picks the type the rest is read as
+fuzz_target!(|data: &[u8]| {
+    let Some((kind, bytes)) = data.split_first() else {
+        return;
+    };
+    match kind % 6 {
+        0 => round_trip::<FheBool>(bytes, |_| {}),
+        1 => round_trip::<FheUint8>(bytes, |_| {}),
+        2 => round_trip::<EncryptedMatrix>(bytes, |matrix| {
+            for row in 0..matrix.rows() {
+                assert_eq!(matrix.row(row).len(), matrix.cols());
+            }
+        }),
+        3 => round_trip::<CkksCiphertext>(bytes, |ciphertext| {
+            let _ = (ciphertext.level(), ciphertext.slots());
+        }),
+        4 => round_trip::<BgvCiphertext>(bytes, |ciphertext| {
+            let _ = (ciphertext.level(), ciphertext.slots());
+        }),
+        _ => round_trip::<EncryptedTimestamp>(bytes, |timestamp| {
+            let _ = timestamp.unit();
+        }),
+    }
+});
@@ -0,0 +1,68 @@
+#![no_main]
+
+use std::sync::{Arc, OnceLock};
+
+use hermetic_fhe::api::{
+    CircuitEvaluationRequest, EncryptAndEvaluateRequest, EvaluateAndDecryptRequest, EvaluationRequest,
+    FheService, ImportCiphertextRequest, MapOperationRequest, ValidateCircuitRequest,
+};
+use hermetic_fhe::crypto::fingerprint::fingerprint_bytes;
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::FheServiceImpl;
+use libfuzzer_sys::fuzz_target;
+use prost::Message;
+use tokio::runtime::{Builder, Runtime};
+use tonic::Request;
+
+// One service for the whole run. Nothing driven here needs keys, so none are generated.
+fn service() -> &'static (Runtime, FheServiceImpl) {
+    static SERVICE: OnceLock<(Runtime, FheServiceImpl)> = OnceLock::new();
+    SERVICE.get_or_init(|| {
+        let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+        let service = {
+            let _context = runtime.enter();
+            FheServiceImpl::new(Arc::new(KeyStore::new()), Arc::new(CiphertextStore::new()))
+        };
+        (runtime, service)
+    })
+}
+
+// Request bodies as they arrive off the wire. The first byte picks the message the rest
+// is decoded as; circuits and imports then go on through the service as far as they get
+// without keys.
+fuzz_target!(|data: &[u8]| {
+    let Some((kind, bytes)) = data.split_first() else {
+        return;
+    };
+    let (runtime, service) = service();
+    match kind % 7 {
+        0 => {
+            if let Ok(request) = ValidateCircuitRequest::decode(bytes) {
+                let _ = runtime.block_on(service.validate_circuit(Request::new(request)));
+            }
+        }
+        1 => {
+            if let Ok(mut request) = ImportCiphertextRequest::decode(bytes) {
+                // A matching fingerprint is what a real client sends, and gets the bytes as
+                // far as the decoder
+                request.fingerprint = fingerprint_bytes(&request.serialized_data);
+                let _ = runtime.block_on(service.import_ciphertext(Request::new(request)));
+            }
+        }
+        2 => {
+            let _ = EvaluationRequest::decode(bytes);
+        }
+        3 => {
+            let _ = CircuitEvaluationRequest::decode(bytes);
+        }
+        4 => {
+            let _ = EvaluateAndDecryptRequest::decode(bytes);
+        }
+        5 => {
+            let _ = EncryptAndEvaluateRequest::decode(bytes);
+        }
+        _ => {
+            let _ = MapOperationRequest::decode(bytes);
+        }
+    }
+});
@@ -0,0 +1,25 @@
+#![no_main]
+
+use hermetic_fhe::circuit::expression;
+use hermetic_fhe::circuit::{InputSpec, IssueKind, ValueType};
+use libfuzzer_sys::fuzz_target;
+
+// EvaluateOperation's expression field, as any client may send it
+fuzz_target!(|source: &str| {
+    let Ok(expression) = expression::parse(source) else {
+        return;
+    };
+    // Whatever the parser accepts compiles to a circuit whose wires all point somewhere,
+    // over one input per variable
+    let inputs = vec![Some(InputSpec::of(ValueType::Integer)); expression.variables.len()];
+    let report = expression.circuit.check(&inputs);
+    assert!(
+        report
+            .issues
+            .iter()
+            .all(|issue| issue.kind != IssueKind::InvalidWire && issue.kind != IssueKind::MissingInput),
+        "{:?}",
+        report.issues
+    );
+    assert_eq!(expression.circuit.outputs.len(), 1);
+});
@@ -0,0 +1,33 @@
+# Computes (a + b) * a on the server, first with a key pair the server holds, then with a
+# client key that never leaves this process. Start the server with `cargo run` first; pass
+# its endpoint as the first argument if it isn't listening on the default address.
+import sys
+
+import hermetic_fhe_py as fhe
+
+client = fhe.Client.connect(sys.argv[1] if len(sys.argv) > 1 else "http://127.0.0.1:50051")
+
+client_key_id, server_key_id = client.generate_keys()
+a = client.encrypt_integer(client_key_id, 6)
+b = client.encrypt_integer(client_key_id, 7)
+result = client.evaluate_expression(server_key_id, "(a + b) * a", {"a": a, "b": b})
+print("(6 + 7) * 6 =", client.decrypt_integer(client_key_id, result))
+
+# The same as a circuit: gate 0 adds the inputs, gate 1 multiplies that by the first
+[output] = client.evaluate_circuit(
+    server_key_id,
+    [a, b],
+    [("ADD", [("input", 0), ("input", 1)]), ("MULTIPLY", [("gate", 0), ("input", 0)])],
+    [("gate", 1)],
+)
+print("circuit:", client.decrypt_integer(client_key_id, output))
+
+# A local key encrypts and decrypts here; only ciphertexts cross the wire
+key = fhe.LocalKey.generate()
+uploaded = client.upload_integer(key, 42)
+print("round trip:", client.download_integer(key, uploaded))
+
+try:
+    client.decrypt_integer("missing", result)
+except fhe.FheError as error:
+    print("error:", error)
@@ -0,0 +1,36 @@
+import pytest
+
+from hermetic_fhe_py import FheError, _check_signature
+
+QUOTE = b"not a real quote"
+
+
+def test_only_true_accepts_a_quote():
+    _check_signature(lambda platform, quote: True, "tdx", QUOTE)
+
+
+@pytest.mark.parametrize(
+    "answer", [1, "rejected: unknown PCK certificate", {"valid": False}, [None], False, None]
+)
+def test_truthy_answers_other_than_true_refuse_the_quote(answer):
+    with pytest.raises(FheError, match="rather than True"):
+        _check_signature(lambda platform, quote: answer, "tdx", QUOTE)
+
+
+def test_a_verifier_that_raises_refuses_the_quote():
+    def verify(platform, quote):
+        raise RuntimeError("collateral unavailable")
+
+    with pytest.raises(FheError, match="collateral unavailable"):
+        _check_signature(verify, "sev-snp", QUOTE)
+
+
+def test_the_verifier_sees_the_platform_and_quote():
+    seen = []
+
+    def verify(platform, quote):
+        seen.append((platform, quote))
+        return True
+
+    _check_signature(verify, "sgx", QUOTE)
+    assert seen == [("sgx", QUOTE)]
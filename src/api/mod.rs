@@ -1,14 +1,75 @@
 // Include the generated proto code
-pub mod hermetic_fhe {
-    tonic::include_proto!("hermetic_fhe");
+pub mod v1 {
+    tonic::include_proto!("hermetic_fhe.v1");
 }
 
+// API versions this build serves, newest last
+pub const API_VERSIONS: &[&str] = &["v1"];
+
 // Re-export the proto types for easier access
-pub use hermetic_fhe::{
-    BooleanResponse, DecryptBooleanRequest, DecryptIntegerRequest, EncryptBooleanRequest,
-    EncryptIntegerRequest, EncryptedDataResponse, EvaluationRequest, EvaluationResponse,
-    IntegerResponse, KeyGenerationRequest, KeyGenerationResponse, OperationType,
+pub use v1::{
+    attestation_response, backup_ciphertext, backup_record, circuit_wire,
+    compare_timestamp_request, contribution, event_request, event_response,
+    increment_counter_request, plaintext_value, privacy_noise, result_sink, AggregationResponse,
+    AggregationSnapshot, AggregationWindow, ArgMaxRequest, ArgMaxResponse, AttestationRequest,
+    AttestationResponse, BackupChunk, BackupCiphertext, BackupFooter, BackupHeader, BackupKeyPair,
+    BackupManifest, BackupManifestEntry, BackupReEncryptionKey, BackupRecord, BackupSession,
+    BloomFilterMerge, BloomFilterResponse, BooleanResponse, BucketTimestampRequest,
+    CalibrateCostsRequest, CalibrateCostsResponse, CastBallotRequest, CheckpointOptions,
+    CiphertextChunk, CiphertextType, CircuitEvaluationRequest, CircuitEvaluationResponse,
+    CircuitGate, CircuitIntermediate, CircuitIssue, CircuitIssueKind, CircuitWire,
+    CloseElectionRequest, CloseSessionRequest, CloseSessionResponse, CompareTimestampRequest,
+    Contribution, ContributionSummary, CounterResponse, CreateAggregationRequest,
+    CreateBackupRequest, CreateBloomFilterRequest, CreateCounterRequest, CreateElectionRequest,
+    CreateSessionRequest, CreateSessionResponse, CreationOrder, CustomOperationInfo, DeclaredInput,
+    DecryptBooleanRequest, DecryptIntegerBatchRequest, DecryptIntegerRequest, DecryptMatrixRequest,
+    DecryptMatrixResponse, DecryptRealVectorRequest, DecryptTimestampRequest,
+    DeleteAggregationRequest, DeleteBloomFilterRequest, DeleteCiphertextsRequest,
+    DeleteCiphertextsResponse, DeleteCounterRequest, DeleteKeyAliasRequest, DeleteKeyAliasResponse,
+    DeleteKeyPairRequest, DeleteKeyPairResponse, DeletedCiphertextInfo, ElectionResponse,
+    EncryptAndEvaluateRequest, EncryptBooleanRequest, EncryptIntegerBatchRequest,
+    EncryptIntegerRequest, EncryptMatrixRequest, EncryptRealVectorRequest, EncryptTimestampRequest,
+    EncryptedDataResponse, EncryptedRecord, EstimateCostRequest, EstimateCostResponse,
+    EvaluateAndDecryptRequest, EvaluateAndDecryptResponse, EvaluationRequest, EvaluationResponse,
+    EventError, EventRequest, EventResponse, EvictSessionRequest, ExportCiphertextRequest,
+    ExportCiphertextResponse, GateLatency, GetLineageRequest, GetMapJobRequest,
+    GetMigrationRequest, GetTallyRequest, ImportCiphertextRequest, IncrementCounterRequest,
+    InferenceRequest, InferenceResponse, IngestSummary, IngestedRecord,
+    IntegerBatchEvaluationRequest, IntegerBatchOperation, IntegerBatchResponse, IntegerResponse,
+    InvokeCustomOperationRequest, InvokeCustomOperationResponse, JobCallback, JoinOperationRequest,
+    KeyAlias, KeyGenerationRequest, KeyGenerationResponse, KeyPairInfo, LibraryCircuitInfo,
+    LibraryCircuitRequest, LineageNode, LineageResponse, ListAggregationWindowsRequest,
+    ListAggregationWindowsResponse, ListCustomOperationsRequest, ListCustomOperationsResponse,
+    ListDeletedCiphertextsRequest, ListDeletedCiphertextsResponse, ListKeyAliasesRequest,
+    ListKeyAliasesResponse, ListKeysRequest, ListKeysResponse, ListLibraryCircuitsRequest,
+    ListLibraryCircuitsResponse, ListSessionsRequest, ListSessionsResponse, ListSubjectsRequest,
+    ListSubjectsResponse, MapJobStatus, MapOperationRequest, MappedRecord, MatchStringRequest,
+    MatchStringResponse, MatrixAddRequest, MatrixResponse, MatrixScaleRequest,
+    MatrixVectorProductRequest, MatrixVectorProductResponse, MemoryMetrics,
+    MergeBloomFiltersRequest, MetricSnapshot, MetricsRequest, MetricsResponse, MigratedCiphertext,
+    MigrationStatus, ModelLayer, OperationCount, OperationType, ParameterSetCosts, PirQueryRequest,
+    PlaintextValue, PrivacyBudget, PrivacyNoise, QueryBloomFilterRequest, QueryBloomFilterResponse,
+    QueryCiphertextsRequest, QueryCiphertextsResponse, RankedElement, ReEncryptRequest,
+    ReEncryptionKeyRequest, ReEncryptionKeyResponse, ReadAggregationRequest, ReadCounterRequest,
+    ReadCounterResponse, RealVectorEvaluationRequest, RealVectorOperation, RealVectorResponse,
+    ReduceOperationRequest, Reduction, ReleaseResultsRequest, ReleaseResultsResponse,
+    ReloadConfigRequest, ReloadConfigResponse, ResourceLimits, RestoreBackupResponse,
+    RestoreDeletedCiphertextsRequest, RestoreDeletedCiphertextsResponse, ResultSink, S3Location,
+    SeriesPoint, SeriesTransform, ServerFeatures, ServerInfoRequest, ServerInfoResponse,
+    SessionInfo, SetKeyAliasRequest, SetKeyOperationsRequest, SetKeyOperationsResponse,
+    SetMembershipRequest, ShredSubjectRequest, ShredSubjectResponse, SortVectorRequest,
+    SortVectorResponse, StartMigrationRequest, StatsRequest, StatsResponse, StoreMetrics,
+    StreamCiphertextsRequest, StringMatch, SubjectInfo, TagCiphertextRequest,
+    TagCiphertextResponse, TagFilter, TaggedCiphertext, TallyResponse, TenantQueueMetrics,
+    TimeUnit, TimestampComparison, TimestampDifferenceRequest, TimestampResponse,
+    TransformSeriesRequest, TransformSeriesResponse, UsageRecord, UsageRequest, UsageResponse,
+    ValidateCircuitRequest, ValidateCircuitResponse, WarmServerKeysRequest, WarmServerKeysResponse,
+    WorkerPoolMetrics,
 };
 
 // Re-export server
-pub use hermetic_fhe::fhe_service_server::{FheService, FheServiceServer}; 
\ No newline at end of file
+pub use v1::fhe_admin_service_server::{FheAdminService, FheAdminServiceServer};
+pub use v1::fhe_service_server::{FheService, FheServiceServer};
+
+// Re-export client
+pub use v1::{fhe_admin_service_client, fhe_service_client}; 
\ No newline at end of file
@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+
+use super::{BackendError, SlotOperation};
+use crate::crypto::bgv::{self, BgvCiphertext, EvaluationKey, SecretKey};
+use crate::crypto::{Ciphertext, CiphertextStore, KeyScheme, KeyStore, ReEncryption};
+
+// The BGV scheme, for exact arithmetic on batches of integers. Like the CKKS backend it
+// shares the TFHE backend's stores but only takes its own keys and ciphertexts. Slot
+// values wrap modulo bgv::PLAINTEXT_MODULUS, so sums stay exact as long as they fit in
+// [-bgv::MAX_VALUE, bgv::MAX_VALUE].
+#[derive(Clone)]
+pub struct BgvBackend {
+    key_store: Arc<KeyStore>,
+    ciphertext_store: Arc<CiphertextStore>,
+}
+
+impl BgvBackend {
+    pub fn new(key_store: Arc<KeyStore>, ciphertext_store: Arc<CiphertextStore>) -> Self {
+        Self {
+            key_store,
+            ciphertext_store,
+        }
+    }
+
+    pub fn scheme(&self) -> &'static str {
+        "BGV"
+    }
+
+    pub fn generate_keys(&self) -> Result<(String, String), BackendError> {
+        Ok(self.key_store.generate_bgv_keys()?)
+    }
+
+    pub fn encrypt_integer_batch(&self, client_key_id: &str, values: &[i64]) -> Result<String, BackendError> {
+        let secret_key = self.secret_key(client_key_id)?;
+        let encrypted = bgv::encrypt(&secret_key, values)?;
+        Ok(self.ciphertext_store.store(encrypted))
+    }
+
+    pub fn decrypt_integer_batch(&self, client_key_id: &str, id: &str) -> Result<Vec<i64>, BackendError> {
+        let secret_key = self.secret_key(client_key_id)?;
+        Ok(bgv::decrypt(&secret_key, &self.load(id)?))
+    }
+
+    pub fn evaluate(
+        &self,
+        server_key_id: &str,
+        operation: SlotOperation,
+        operand_ids: &[&str],
+    ) -> Result<String, BackendError> {
+        if operand_ids.len() != operation.arity() {
+            return Err(anyhow!(
+                "{:?} takes {} operands, got {}",
+                operation,
+                operation.arity(),
+                operand_ids.len()
+            )
+            .into());
+        }
+        let evaluation_key = self.evaluation_key(server_key_id)?;
+        let operands = operand_ids
+            .iter()
+            .map(|id| self.load(id))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let result = match (operation, operands.as_slice()) {
+            (SlotOperation::Add, [a, b]) => bgv::add(a, b),
+            (SlotOperation::Multiply, [a, b]) => {
+                if a.level().min(b.level()) == 0 {
+                    return Err(BackendError::DepthExhausted);
+                }
+                bgv::multiply(&evaluation_key, a, b)?
+            }
+            (SlotOperation::Rotate(steps), [a]) => bgv::rotate(&evaluation_key, a, steps),
+            _ => unreachable!("arity checked above"),
+        };
+        Ok(self.ciphertext_store.store(result))
+    }
+
+    // Key for re_encrypt from the source client key to the target one, both of this scheme
+    pub fn generate_re_encryption_key(
+        &self,
+        source_client_key_id: &str,
+        target_client_key_id: &str,
+    ) -> Result<String, BackendError> {
+        let source = self.secret_key(source_client_key_id)?;
+        let target = self.secret_key(target_client_key_id)?;
+        let key = bgv::generate_re_encryption_key(&source, &target);
+        Ok(self.key_store.store_re_encryption_key(
+            KeyScheme::Bgv,
+            source_client_key_id,
+            target_client_key_id,
+            key,
+        ))
+    }
+
+    // Copy of a ciphertext under the re-encryption key's target client key, made without
+    // decrypting it
+    pub fn re_encrypt(&self, re_encryption_key_id: &str, id: &str) -> Result<String, BackendError> {
+        let re_encryption = self.re_encryption_key(re_encryption_key_id)?;
+        let re_encrypted = bgv::re_encrypt(&re_encryption.key, &self.load(id)?);
+        Ok(self.ciphertext_store.store(re_encrypted))
+    }
+
+    pub fn remove(&self, id: &str) -> bool {
+        self.ciphertext_store.remove(id)
+    }
+
+    fn secret_key(&self, client_key_id: &str) -> Result<Arc<SecretKey>, BackendError> {
+        self.key_store
+            .get_bgv_secret_key(client_key_id)
+            .ok_or(BackendError::ClientKeyNotFound)
+    }
+
+    fn evaluation_key(&self, server_key_id: &str) -> Result<Arc<EvaluationKey>, BackendError> {
+        self.key_store
+            .get_bgv_evaluation_key(server_key_id)
+            .ok_or(BackendError::ServerKeyNotFound)
+    }
+
+    fn re_encryption_key(&self, re_encryption_key_id: &str) -> Result<Arc<ReEncryption>, BackendError> {
+        self.key_store
+            .get_re_encryption_key(re_encryption_key_id)
+            .filter(|re_encryption| re_encryption.scheme == KeyScheme::Bgv)
+            .ok_or(BackendError::ReEncryptionKeyNotFound)
+    }
+
+    fn load(&self, id: &str) -> Result<Arc<BgvCiphertext>, BackendError> {
+        match self.ciphertext_store.get(id) {
+            Some(Ciphertext::IntegerBatch(ciphertext)) => Ok(ciphertext),
+            Some(other) => Err(BackendError::TypeMismatch {
+                expected: "BgvCiphertext",
+                found: other.kind().type_name(),
+            }),
+            None => Err(BackendError::CiphertextNotFound(id.to_string())),
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use anyhow::{anyhow, Result};
+use uuid::Uuid;
+
+use super::{check_arity, BackendError, FheBackend};
+use crate::circuit::{Circuit, Operation, ValueType, Wire};
+use crate::crypto::sharded::ShardedMap;
+use crate::crypto::PARAMETER_SETS;
+
+// A "ciphertext" in the clear
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MockValue {
+    Boolean(bool),
+    Integer(u8),
+}
+
+impl MockValue {
+    pub fn value_type(self) -> ValueType {
+        match self {
+            MockValue::Boolean(_) => ValueType::Boolean,
+            MockValue::Integer(_) => ValueType::Integer,
+        }
+    }
+
+    // The tfhe type the value stands in for, as reported in type mismatch errors
+    fn type_name(self) -> &'static str {
+        match self {
+            MockValue::Boolean(_) => "FheBool",
+            MockValue::Integer(_) => "FheUint8",
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Entry {
+    value: MockValue,
+    // Client key of the pair the value belongs to
+    client_key_id: String,
+}
+
+// Plaintext stand-in for the TFHE backend, for integration tests that need its
+// semantics but not its cost. Values are held in the clear and every operation is plain
+// arithmetic with the same wrapping 8-bit behaviour as FheUint8, so a suite runs in
+// milliseconds. Using a value with the wrong key pair is an error here, where tfhe
+// would quietly produce garbage.
+// It keeps nothing secret and must never serve real traffic.
+pub struct MockFheBackend {
+    // Each key's counterpart in its pair
+    client_keys: ShardedMap<String>,
+    server_keys: ShardedMap<String>,
+    values: ShardedMap<Entry>,
+}
+
+impl MockFheBackend {
+    pub fn new() -> Self {
+        Self {
+            client_keys: ShardedMap::new(),
+            server_keys: ShardedMap::new(),
+            values: ShardedMap::new(),
+        }
+    }
+
+    // The plaintext behind an ID, without any key, for asserting on in tests
+    pub fn peek(&self, id: &str) -> Option<MockValue> {
+        self.values.get(id).map(|entry| entry.value)
+    }
+
+    // Values held
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn encrypt(&self, client_key_id: &str, value: MockValue) -> Result<String, BackendError> {
+        self.check_client_key(client_key_id)?;
+        let id = Uuid::new_v4().to_string();
+        self.values.insert(
+            id.clone(),
+            Entry {
+                value,
+                client_key_id: client_key_id.to_string(),
+            },
+        );
+        Ok(id)
+    }
+
+    fn decrypt(&self, client_key_id: &str, id: &str) -> Result<MockValue, BackendError> {
+        self.check_client_key(client_key_id)?;
+        self.load(client_key_id, id)
+    }
+
+    fn check_client_key(&self, client_key_id: &str) -> Result<(), BackendError> {
+        match self.client_keys.get(client_key_id) {
+            Some(_) => Ok(()),
+            None => Err(BackendError::ClientKeyNotFound),
+        }
+    }
+
+    fn client_key_for(&self, server_key_id: &str) -> Result<String, BackendError> {
+        self.server_keys
+            .get(server_key_id)
+            .ok_or(BackendError::ServerKeyNotFound)
+    }
+
+    fn load(&self, client_key_id: &str, id: &str) -> Result<MockValue, BackendError> {
+        let entry = self
+            .values
+            .get(id)
+            .ok_or_else(|| BackendError::CiphertextNotFound(id.to_string()))?;
+        if entry.client_key_id != client_key_id {
+            return Err(BackendError::KeyPairMismatch(id.to_string()));
+        }
+        Ok(entry.value)
+    }
+
+    fn load_all(&self, client_key_id: &str, ids: &[&str]) -> Result<Vec<MockValue>, BackendError> {
+        ids.iter().map(|id| self.load(client_key_id, id)).collect()
+    }
+}
+
+impl FheBackend for MockFheBackend {
+    fn scheme(&self) -> &'static str {
+        "Mock"
+    }
+
+    fn generate_keys(&self, parameter_set: &str) -> Result<(String, String), BackendError> {
+        if !PARAMETER_SETS.contains(&parameter_set) {
+            return Err(anyhow!("Invalid parameter set").into());
+        }
+
+        let client_key_id = Uuid::new_v4().to_string();
+        let server_key_id = Uuid::new_v4().to_string();
+        self.client_keys.insert(client_key_id.clone(), server_key_id.clone());
+        self.server_keys.insert(server_key_id.clone(), client_key_id.clone());
+        Ok((client_key_id, server_key_id))
+    }
+
+    fn encrypt_boolean(&self, client_key_id: &str, value: bool) -> Result<String, BackendError> {
+        self.encrypt(client_key_id, MockValue::Boolean(value))
+    }
+
+    fn encrypt_integer(&self, client_key_id: &str, value: u8) -> Result<String, BackendError> {
+        self.encrypt(client_key_id, MockValue::Integer(value))
+    }
+
+    fn decrypt_boolean(&self, client_key_id: &str, id: &str) -> Result<bool, BackendError> {
+        match self.decrypt(client_key_id, id)? {
+            MockValue::Boolean(value) => Ok(value),
+            other => Err(type_mismatch(MockValue::Boolean(false), other)),
+        }
+    }
+
+    fn decrypt_integer(&self, client_key_id: &str, id: &str) -> Result<u8, BackendError> {
+        match self.decrypt(client_key_id, id)? {
+            MockValue::Integer(value) => Ok(value),
+            other => Err(type_mismatch(MockValue::Integer(0), other)),
+        }
+    }
+
+    fn evaluate(
+        &self,
+        server_key_id: &str,
+        operation: Operation,
+        operand_ids: &[&str],
+    ) -> Result<String, BackendError> {
+        check_arity(operation, operand_ids.len())?;
+        let client_key_id = self.client_key_for(server_key_id)?;
+        let operands = self.load_all(&client_key_id, operand_ids)?;
+        self.encrypt(&client_key_id, apply(operation, &operands)?)
+    }
+
+    fn evaluate_circuit(
+        &self,
+        server_key_id: &str,
+        circuit: &Circuit,
+        input_ids: &[&str],
+    ) -> Result<Vec<String>, BackendError> {
+        let client_key_id = self.client_key_for(server_key_id)?;
+        let inputs = self.load_all(&client_key_id, input_ids)?;
+        let input_types: Vec<ValueType> = inputs.iter().map(|value| value.value_type()).collect();
+        circuit.validate(&input_types)?;
+
+        let mut values: Vec<MockValue> = Vec::with_capacity(circuit.gates.len());
+        for gate in &circuit.gates {
+            let operands: Vec<MockValue> =
+                gate.inputs.iter().map(|wire| read(*wire, &inputs, &values)).collect();
+            values.push(apply(gate.operation, &operands)?);
+        }
+
+        circuit
+            .outputs
+            .iter()
+            .map(|wire| self.encrypt(&client_key_id, read(*wire, &inputs, &values)))
+            .collect()
+    }
+
+    fn remove(&self, id: &str) -> bool {
+        self.values.remove(id).is_some()
+    }
+}
+
+impl Default for MockFheBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn type_mismatch(expected: MockValue, found: MockValue) -> BackendError {
+    BackendError::TypeMismatch {
+        expected: expected.type_name(),
+        found: found.type_name(),
+    }
+}
+
+// Only called on validated circuits, whose wires all point at inputs or earlier gates
+fn read(wire: Wire, inputs: &[MockValue], values: &[MockValue]) -> MockValue {
+    match wire {
+        Wire::Input(index) => inputs[index],
+        Wire::Gate(index) => values[index],
+    }
+}
+
+fn apply(operation: Operation, operands: &[MockValue]) -> Result<MockValue> {
+    use MockValue::{Boolean, Integer};
+
+    let result = match (operation, operands) {
+        (Operation::And, [Boolean(a), Boolean(b)]) => Boolean(a & b),
+        (Operation::Or, [Boolean(a), Boolean(b)]) => Boolean(a | b),
+        (Operation::Xor, [Boolean(a), Boolean(b)]) => Boolean(a ^ b),
+        (Operation::Not, [Boolean(a)]) => Boolean(!a),
+        (Operation::Add, [Integer(a), Integer(b)]) => Integer(a.wrapping_add(*b)),
+        (Operation::Subtract, [Integer(a), Integer(b)]) => Integer(a.wrapping_sub(*b)),
+        (Operation::Multiply, [Integer(a), Integer(b)]) => Integer(a.wrapping_mul(*b)),
+        (Operation::IsZero, [Integer(a)]) => Boolean(*a == 0),
+        (Operation::IsNonZero, [Integer(a)]) => Boolean(*a != 0),
+        (Operation::AbsDiff, [Integer(a), Integer(b)]) => Integer(a.abs_diff(*b)),
+        (Operation::SaturatingAdd, [Integer(a), Integer(b)]) => Integer(a.saturating_add(*b)),
+        (Operation::SaturatingSub, [Integer(a), Integer(b)]) => Integer(a.saturating_sub(*b)),
+        _ => return Err(anyhow!("Operands do not match {:?}", operation)),
+    };
+    Ok(result)
+}
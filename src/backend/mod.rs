@@ -0,0 +1,111 @@
+use anyhow::{anyhow, Result};
+use thiserror::Error;
+
+use crate::circuit::Circuit;
+
+pub mod bgv;
+pub mod ckks;
+pub mod plugin;
+pub mod self_test;
+pub mod tfhe_rs;
+#[cfg(feature = "mock-backend")]
+pub mod mock;
+
+pub use crate::circuit::Operation;
+pub use bgv::BgvBackend;
+pub use ckks::CkksBackend;
+pub use plugin::{OperationPlugin, OperationRegistry, PluginContext};
+pub use tfhe_rs::TfheBackend;
+
+// Why a backend call failed, in terms callers can map to their own errors without
+// knowing which scheme is underneath
+#[derive(Debug, Error)]
+pub enum BackendError {
+    #[error("Client key not found")]
+    ClientKeyNotFound,
+    #[error("Server key not found")]
+    ServerKeyNotFound,
+    #[error("Re-encryption key not found")]
+    ReEncryptionKeyNotFound,
+    #[error("Encrypted data {0} not found")]
+    CiphertextNotFound(String),
+    #[error("type mismatch: expected {expected}, found {found}")]
+    TypeMismatch {
+        expected: &'static str,
+        found: &'static str,
+    },
+    #[error("Encrypted data {0} belongs to a different key pair")]
+    KeyPairMismatch(String),
+    #[error("No multiplicative depth left on the operands")]
+    DepthExhausted,
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+// Slot-wise operations on the packed vectors of the CKKS and BGV backends
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlotOperation {
+    Add,
+    Multiply,
+    // Cyclic left rotation by a number of slots; negative rotates right
+    Rotate(i64),
+}
+
+impl SlotOperation {
+    pub fn arity(self) -> usize {
+        match self {
+            SlotOperation::Add | SlotOperation::Multiply => 2,
+            SlotOperation::Rotate(_) => 1,
+        }
+    }
+}
+
+// Key generation, encryption, decryption and evaluation for one FHE scheme. Keys and
+// ciphertexts stay inside the backend and are named by ID, so nothing above it has to
+// handle scheme-specific types. Evaluation runs on the calling thread.
+pub trait FheBackend: Send + Sync {
+    // Name of the scheme, as reported to clients
+    fn scheme(&self) -> &'static str;
+
+    fn generate_keys(&self, parameter_set: &str) -> Result<(String, String), BackendError>;
+
+    fn encrypt_boolean(&self, client_key_id: &str, value: bool) -> Result<String, BackendError>;
+
+    fn encrypt_integer(&self, client_key_id: &str, value: u8) -> Result<String, BackendError>;
+
+    fn decrypt_boolean(&self, client_key_id: &str, id: &str) -> Result<bool, BackendError>;
+
+    fn decrypt_integer(&self, client_key_id: &str, id: &str) -> Result<u8, BackendError>;
+
+    // Apply one operation to stored operands and store the result
+    fn evaluate(
+        &self,
+        server_key_id: &str,
+        operation: Operation,
+        operand_ids: &[&str],
+    ) -> Result<String, BackendError>;
+
+    // Evaluate a circuit over stored inputs, storing each output
+    fn evaluate_circuit(
+        &self,
+        server_key_id: &str,
+        circuit: &Circuit,
+        input_ids: &[&str],
+    ) -> Result<Vec<String>, BackendError>;
+
+    // False if the ID is unknown
+    fn remove(&self, id: &str) -> bool;
+}
+
+// Fail unless the operand count matches the operation, before anything is loaded
+pub(crate) fn check_arity(operation: Operation, operands: usize) -> Result<()> {
+    if operands != operation.arity() {
+        return Err(anyhow!(
+            "{:?} takes {} operands, got {}",
+            operation,
+            operation.arity(),
+            operands
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,103 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use tfhe::ServerKey;
+
+use super::{BackendError, TfheBackend};
+use crate::cancellation::Cancellation;
+use crate::circuit::Value;
+
+// Longest name a custom operation is registered under
+pub const MAX_OPERATION_NAME_LENGTH: usize = 64;
+
+// What a custom operation runs with. The server key is also set on the calling thread, so
+// the high-level tfhe operators work on the inputs directly.
+pub struct PluginContext<'a> {
+    pub server_key_id: &'a str,
+    pub server_key: &'a ServerKey,
+    // For stored ciphertexts the operation looks up or evaluates by ID
+    pub backend: &'a TfheBackend,
+    pub cancellation: &'a Cancellation,
+}
+
+impl PluginContext<'_> {
+    // Fails once the call is cancelled or past its deadline. Check between expensive steps,
+    // so an abandoned call stops early.
+    pub fn check(&self) -> Result<(), BackendError> {
+        self.cancellation
+            .check()
+            .map_err(|e| BackendError::Other(e.into()))
+    }
+}
+
+// A named operation added by a downstream crate, such as a proprietary circuit, that clients
+// run with InvokeCustomOperation without the service being forked. It is evaluated on a
+// worker thread like any other evaluation, and its outputs are stored as the call's results.
+pub trait OperationPlugin: Send + Sync {
+    // Name clients invoke it by, unique within a registry
+    fn name(&self) -> &str;
+
+    // One line for ListCustomOperations, saying what the inputs and outputs are
+    fn description(&self) -> &str {
+        ""
+    }
+
+    // Inputs it takes, checked before it runs; None takes any number
+    fn arity(&self) -> Option<usize> {
+        None
+    }
+
+    // Report inputs of the wrong type with BackendError::TypeMismatch, so clients get
+    // TYPE_MISMATCH rather than INTERNAL
+    fn evaluate(&self, context: &PluginContext<'_>, inputs: &[Value]) -> Result<Vec<Value>, BackendError>;
+}
+
+// Custom operations by name, registered by the application before the service starts
+#[derive(Clone, Default)]
+pub struct OperationRegistry {
+    plugins: BTreeMap<String, Arc<dyn OperationPlugin>>,
+}
+
+impl OperationRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Names are 1 to 64 letters, digits, '_', '-' or '.', and are not reused
+    pub fn register(&mut self, plugin: Arc<dyn OperationPlugin>) -> Result<()> {
+        let name = plugin.name().to_string();
+        let valid = name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+        if name.is_empty() || name.len() > MAX_OPERATION_NAME_LENGTH || !valid {
+            return Err(anyhow!(
+                "Operation name '{}' must be 1 to {} letters, digits, '_', '-' or '.'",
+                name,
+                MAX_OPERATION_NAME_LENGTH
+            ));
+        }
+        if self.plugins.contains_key(&name) {
+            return Err(anyhow!("An operation named '{}' is already registered", name));
+        }
+        self.plugins.insert(name, plugin);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn OperationPlugin>> {
+        self.plugins.get(name).cloned()
+    }
+
+    // In name order
+    pub fn plugins(&self) -> impl Iterator<Item = &Arc<dyn OperationPlugin>> {
+        self.plugins.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.plugins.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plugins.is_empty()
+    }
+}
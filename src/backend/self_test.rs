@@ -0,0 +1,210 @@
+use std::env;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+
+use super::{BackendError, BgvBackend, CkksBackend, FheBackend, Operation, SlotOperation, TfheBackend};
+use crate::crypto::{parameter_config, CiphertextStore, KeyStore, PARAMETER_SETS};
+
+// Operands of the TFHE round trip; their sum wraps, so a carry has to cross every block
+const INTEGER_OPERANDS: (u8, u8) = (173, 94);
+
+// Operands of the CKKS and BGV round trips, with negative and fractional slots
+const REAL_OPERANDS: ([f64; 3], [f64; 3]) = ([1.5, -2.25, 1000.0], [0.25, 4.0, -999.5]);
+const BATCH_OPERANDS: ([i64; 3], [i64; 3]) = ([7, -3, 30000], [5, 11, -30001]);
+
+// Furthest a CKKS sum may decrypt from the exact one
+const REAL_TOLERANCE: f64 = 1e-3;
+
+// Which TFHE parameter sets to check at startup. CKKS and BGV have one set each and are
+// always checked.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SelfTestConfig {
+    pub parameter_sets: Vec<String>,
+}
+
+impl SelfTestConfig {
+    // HERMETIC_FHE_SELF_TEST=1 turns the self-test on. HERMETIC_FHE_SELF_TEST_PARAMETER_SETS
+    // is a comma-separated list of the parameter sets checked, every one if unset.
+    pub fn from_env() -> Result<Option<Self>> {
+        match env::var("HERMETIC_FHE_SELF_TEST").as_deref().map(str::trim) {
+            Err(_) | Ok("0") | Ok("false") | Ok("") => return Ok(None),
+            Ok("1") | Ok("true") => {}
+            Ok(_) => return Err(anyhow!("HERMETIC_FHE_SELF_TEST must be 1 or 0")),
+        }
+        let parameter_sets = env::var("HERMETIC_FHE_SELF_TEST_PARAMETER_SETS")
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|set| !set.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_else(|_| PARAMETER_SETS.map(str::to_string).to_vec());
+        Self::new(parameter_sets).map(Some)
+    }
+
+    pub fn new(parameter_sets: Vec<String>) -> Result<Self> {
+        for parameter_set in &parameter_sets {
+            parameter_config(parameter_set)
+                .map_err(|_| anyhow!("Unknown parameter set '{}' for the self-test", parameter_set))?;
+        }
+        Ok(Self { parameter_sets })
+    }
+}
+
+// How long one backend took to make a throwaway key pair and then encrypt two values, add
+// them and decrypt the sum
+#[derive(Clone, Debug)]
+pub struct SelfTestCheck {
+    pub scheme: &'static str,
+    // Empty for the schemes with a single parameter set
+    pub parameter_set: String,
+    pub key_generation: Duration,
+    pub round_trip: Duration,
+}
+
+impl SelfTestCheck {
+    // The scheme, and the parameter set if it has several, e.g. "TFHE DEFAULT"
+    pub fn name(&self) -> String {
+        match self.parameter_set.as_str() {
+            "" => self.scheme.to_string(),
+            parameter_set => format!("{} {}", self.scheme, parameter_set),
+        }
+    }
+}
+
+// An encrypt, add and decrypt round trip on each backend, under keys generated for it in
+// stores of its own, so nothing it does is visible to clients. A sum that decrypts wrong
+// points at a miscompiled or misbehaving arithmetic path on this machine, and fails the
+// test before any user data reaches it.
+pub fn run(config: &SelfTestConfig) -> Result<Vec<SelfTestCheck>> {
+    let key_store = Arc::new(KeyStore::new());
+    let ciphertext_store = Arc::new(CiphertextStore::new());
+    let mut checks = Vec::new();
+
+    let tfhe = TfheBackend::new(key_store.clone(), ciphertext_store.clone());
+    for parameter_set in &config.parameter_sets {
+        checks.push(check_tfhe(&tfhe, parameter_set)?);
+    }
+    checks.push(check_ckks(&CkksBackend::new(
+        key_store.clone(),
+        ciphertext_store.clone(),
+    ))?);
+    checks.push(check_bgv(&BgvBackend::new(key_store, ciphertext_store))?);
+    Ok(checks)
+}
+
+fn check_tfhe(backend: &TfheBackend, parameter_set: &str) -> Result<SelfTestCheck> {
+    let failed = |e: BackendError| anyhow!("TFHE self-test with {} failed: {}", parameter_set, e);
+    let started = Instant::now();
+    let (client_key_id, server_key_id) = backend.generate_keys(parameter_set).map_err(failed)?;
+    let key_generation = started.elapsed();
+
+    let started = Instant::now();
+    let (a, b) = INTEGER_OPERANDS;
+    let a_id = backend.encrypt_integer(&client_key_id, a).map_err(failed)?;
+    let b_id = backend.encrypt_integer(&client_key_id, b).map_err(failed)?;
+    let sum_id = backend
+        .evaluate(&server_key_id, Operation::Add, &[&a_id, &b_id])
+        .map_err(failed)?;
+    let sum = backend.decrypt_integer(&client_key_id, &sum_id).map_err(failed)?;
+    let round_trip = started.elapsed();
+    if sum != a.wrapping_add(b) {
+        return Err(anyhow!(
+            "TFHE self-test with {} failed: {} + {} decrypted to {}, not {}",
+            parameter_set,
+            a,
+            b,
+            sum,
+            a.wrapping_add(b)
+        ));
+    }
+    Ok(SelfTestCheck {
+        scheme: backend.scheme(),
+        parameter_set: parameter_set.to_string(),
+        key_generation,
+        round_trip,
+    })
+}
+
+fn check_ckks(backend: &CkksBackend) -> Result<SelfTestCheck> {
+    let failed = |e: BackendError| anyhow!("CKKS self-test failed: {}", e);
+    let started = Instant::now();
+    let (client_key_id, server_key_id) = backend.generate_keys().map_err(failed)?;
+    let key_generation = started.elapsed();
+
+    let started = Instant::now();
+    let (a, b) = REAL_OPERANDS;
+    let a_id = backend.encrypt_real_vector(&client_key_id, &a).map_err(failed)?;
+    let b_id = backend.encrypt_real_vector(&client_key_id, &b).map_err(failed)?;
+    let sum_id = backend
+        .evaluate(&server_key_id, SlotOperation::Add, &[&a_id, &b_id])
+        .map_err(failed)?;
+    let sum = backend
+        .decrypt_real_vector(&client_key_id, &sum_id)
+        .map_err(failed)?;
+    let round_trip = started.elapsed();
+    let expected: Vec<f64> = a.iter().zip(&b).map(|(a, b)| a + b).collect();
+    let close = sum.len() == expected.len()
+        && sum
+            .iter()
+            .zip(&expected)
+            .all(|(sum, expected)| (sum - expected).abs() <= REAL_TOLERANCE);
+    if !close {
+        return Err(anyhow!(
+            "CKKS self-test failed: {:?} + {:?} decrypted to {:?}, not {:?}",
+            a,
+            b,
+            sum,
+            expected
+        ));
+    }
+    Ok(SelfTestCheck {
+        scheme: backend.scheme(),
+        parameter_set: String::new(),
+        key_generation,
+        round_trip,
+    })
+}
+
+fn check_bgv(backend: &BgvBackend) -> Result<SelfTestCheck> {
+    let failed = |e: BackendError| anyhow!("BGV self-test failed: {}", e);
+    let started = Instant::now();
+    let (client_key_id, server_key_id) = backend.generate_keys().map_err(failed)?;
+    let key_generation = started.elapsed();
+
+    let started = Instant::now();
+    let (a, b) = BATCH_OPERANDS;
+    let a_id = backend
+        .encrypt_integer_batch(&client_key_id, &a)
+        .map_err(failed)?;
+    let b_id = backend
+        .encrypt_integer_batch(&client_key_id, &b)
+        .map_err(failed)?;
+    let sum_id = backend
+        .evaluate(&server_key_id, SlotOperation::Add, &[&a_id, &b_id])
+        .map_err(failed)?;
+    let sum = backend
+        .decrypt_integer_batch(&client_key_id, &sum_id)
+        .map_err(failed)?;
+    let round_trip = started.elapsed();
+    let expected: Vec<i64> = a.iter().zip(&b).map(|(a, b)| a + b).collect();
+    if sum != expected {
+        return Err(anyhow!(
+            "BGV self-test failed: {:?} + {:?} decrypted to {:?}, not {:?}",
+            a,
+            b,
+            sum,
+            expected
+        ));
+    }
+    Ok(SelfTestCheck {
+        scheme: backend.scheme(),
+        parameter_set: String::new(),
+        key_generation,
+        round_trip,
+    })
+}
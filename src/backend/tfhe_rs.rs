@@ -0,0 +1,171 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use tfhe::prelude::{FheDecrypt, FheTryEncrypt};
+use tfhe::{ClientKey, FheBool, FheUint8, ServerKey};
+
+use super::{check_arity, BackendError, FheBackend, Operation};
+use crate::circuit::{apply, Circuit, EvaluationOptions, Value};
+use crate::crypto::provenance::Derivation;
+use crate::crypto::{Ciphertext, CiphertextKind, CiphertextStore, KeyPolicy, KeyStore};
+
+// The TFHE scheme through tfhe-rs, over a key store and ciphertext store that callers
+// may share with code still using them directly
+#[derive(Clone)]
+pub struct TfheBackend {
+    key_store: Arc<KeyStore>,
+    ciphertext_store: Arc<CiphertextStore>,
+}
+
+impl TfheBackend {
+    pub fn new(key_store: Arc<KeyStore>, ciphertext_store: Arc<CiphertextStore>) -> Self {
+        Self {
+            key_store,
+            ciphertext_store,
+        }
+    }
+
+    // A key pair restricted by the policy; see KeyStore::generate_keys_with_policy
+    pub fn generate_keys_with_policy(
+        &self,
+        parameter_set: &str,
+        policy: KeyPolicy,
+    ) -> Result<(String, String), BackendError> {
+        Ok(self.key_store.generate_keys_with_policy(parameter_set, policy)?)
+    }
+
+    fn client_key(&self, client_key_id: &str) -> Result<Arc<ClientKey>, BackendError> {
+        self.key_store
+            .get_client_key(client_key_id)
+            .ok_or(BackendError::ClientKeyNotFound)
+    }
+
+    fn server_key(&self, server_key_id: &str) -> Result<Arc<ServerKey>, BackendError> {
+        self.key_store
+            .get_server_key(server_key_id)
+            .ok_or(BackendError::ServerKeyNotFound)
+    }
+
+    fn load(&self, id: &str) -> Result<Value, BackendError> {
+        match self.ciphertext_store.get(id) {
+            Some(Ciphertext::Boolean(ciphertext)) => Ok(Value::Boolean(ciphertext)),
+            Some(Ciphertext::Integer(ciphertext)) => Ok(Value::Integer(ciphertext)),
+            Some(other) => Err(BackendError::TypeMismatch {
+                expected: "FheBool or FheUint8",
+                found: other.kind().type_name(),
+            }),
+            None => Err(BackendError::CiphertextNotFound(id.to_string())),
+        }
+    }
+
+    fn load_all(&self, ids: &[&str]) -> Result<Vec<Value>, BackendError> {
+        ids.iter().map(|id| self.load(id)).collect()
+    }
+}
+
+// Mismatch between the kind a caller asked for and the kind it got
+fn type_mismatch(expected: CiphertextKind, found: &Value) -> BackendError {
+    let found = match found {
+        Value::Boolean(_) => CiphertextKind::Boolean,
+        Value::Integer(_) => CiphertextKind::Integer,
+    };
+    BackendError::TypeMismatch {
+        expected: expected.type_name(),
+        found: found.type_name(),
+    }
+}
+
+impl FheBackend for TfheBackend {
+    fn scheme(&self) -> &'static str {
+        "TFHE"
+    }
+
+    fn generate_keys(&self, parameter_set: &str) -> Result<(String, String), BackendError> {
+        Ok(self.key_store.generate_keys(parameter_set)?)
+    }
+
+    fn encrypt_boolean(&self, client_key_id: &str, value: bool) -> Result<String, BackendError> {
+        let client_key = self.client_key(client_key_id)?;
+        self.key_store.reseed_thread();
+        let encrypted =
+            FheBool::try_encrypt(value, &*client_key).map_err(|e| anyhow!("Encryption failed: {}", e))?;
+        Ok(self.ciphertext_store.store_boolean(encrypted))
+    }
+
+    fn encrypt_integer(&self, client_key_id: &str, value: u8) -> Result<String, BackendError> {
+        let client_key = self.client_key(client_key_id)?;
+        self.key_store.reseed_thread();
+        let encrypted =
+            FheUint8::try_encrypt(value, &*client_key).map_err(|e| anyhow!("Encryption failed: {}", e))?;
+        Ok(self.ciphertext_store.store_integer(encrypted))
+    }
+
+    fn decrypt_boolean(&self, client_key_id: &str, id: &str) -> Result<bool, BackendError> {
+        let client_key = self.client_key(client_key_id)?;
+        match self.load(id)? {
+            Value::Boolean(ciphertext) => Ok(ciphertext.decrypt(&*client_key)),
+            other => Err(type_mismatch(CiphertextKind::Boolean, &other)),
+        }
+    }
+
+    fn decrypt_integer(&self, client_key_id: &str, id: &str) -> Result<u8, BackendError> {
+        let client_key = self.client_key(client_key_id)?;
+        match self.load(id)? {
+            Value::Integer(ciphertext) => {
+                Ok(<FheUint8 as FheDecrypt<u8>>::decrypt(&*ciphertext, &*client_key))
+            }
+            other => Err(type_mismatch(CiphertextKind::Integer, &other)),
+        }
+    }
+
+    fn evaluate(
+        &self,
+        server_key_id: &str,
+        operation: Operation,
+        operand_ids: &[&str],
+    ) -> Result<String, BackendError> {
+        check_arity(operation, operand_ids.len())?;
+        let server_key = self.server_key(server_key_id)?;
+        let operands = self.load_all(operand_ids)?;
+
+        tfhe::set_server_key((*server_key).clone());
+        let result = apply(&server_key, operation, &operands.iter().collect::<Vec<_>>())?;
+        let parents = self.ciphertext_store.parents(operand_ids.iter().copied());
+        let derivation = Derivation::new("EvaluateOperation", format!("{:?}", operation), parents);
+        let id = self.ciphertext_store.store(result);
+        self.ciphertext_store.set_derivation(&id, derivation);
+        Ok(id)
+    }
+
+    fn evaluate_circuit(
+        &self,
+        server_key_id: &str,
+        circuit: &Circuit,
+        input_ids: &[&str],
+    ) -> Result<Vec<String>, BackendError> {
+        let server_key = self.server_key(server_key_id)?;
+        let inputs = self.load_all(input_ids)?;
+
+        tfhe::set_server_key((*server_key).clone());
+        let result = circuit.evaluate(&server_key, &inputs, EvaluationOptions::default())?;
+        Ok(result
+            .outputs
+            .into_iter()
+            .zip(circuit.output_inputs())
+            .enumerate()
+            .map(|(index, (output, used))| {
+                let parents = self
+                    .ciphertext_store
+                    .parents(used.into_iter().filter_map(|index| input_ids.get(index).copied()));
+                let derivation = Derivation::new("EvaluateCircuit", format!("output {}", index), parents);
+                let id = self.ciphertext_store.store(output);
+                self.ciphertext_store.set_derivation(&id, derivation);
+                id
+            })
+            .collect())
+    }
+
+    fn remove(&self, id: &str) -> bool {
+        self.ciphertext_store.remove(id)
+    }
+}
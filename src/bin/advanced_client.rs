@@ -1,16 +1,19 @@
+use std::collections::HashMap;
 use std::error::Error;
 
 use hermetic_fhe::api::{
     fhe_service_client::FheServiceClient, DecryptBooleanRequest, DecryptIntegerRequest,
     EncryptBooleanRequest, EncryptIntegerRequest, EvaluationRequest, KeyGenerationRequest, OperationType,
 };
+use hermetic_fhe::service::listen::{self, client_endpoint};
 use tonic::Request;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    // Connect to the FHE service
-    let mut client = FheServiceClient::connect("http://[::1]:50051").await?;
-    println!("Connected to FHE service");
+    // Connect to the FHE service, at the endpoint given as the first argument if any
+    let endpoint = client_endpoint(std::env::args().skip(1));
+    let mut client = FheServiceClient::new(listen::connect(&endpoint).await?);
+    println!("Connected to FHE service at {}", endpoint);
     
     // Demo 1: Boolean operations
     println!("\n===== Boolean Circuit Evaluation =====");
@@ -31,6 +34,7 @@ async fn boolean_circuit_demo(client: &mut FheServiceClient<tonic::transport::Ch
     // Generate keys with default parameter set
     let key_gen_request = Request::new(KeyGenerationRequest {
         parameter_set: 0, // DEFAULT
+        ..Default::default()
     });
     
     let key_gen_response = client.generate_keys(key_gen_request).await?;
@@ -54,6 +58,7 @@ async fn boolean_circuit_demo(client: &mut FheServiceClient<tonic::transport::Ch
         let encrypt_request = Request::new(EncryptBooleanRequest {
             client_key_id: client_key_id.clone(),
             value: *value,
+            ..Default::default()
         });
         
         let encrypt_response = client.encrypt_boolean(encrypt_request).await?;
@@ -71,6 +76,7 @@ async fn boolean_circuit_demo(client: &mut FheServiceClient<tonic::transport::Ch
         server_key_id: server_key_id.clone(),
         operation: OperationType::Not as i32,
         operand_ids: vec![encrypted_ids[3].clone()], // D
+        ..Default::default()
     });
     
     let not_d_response = client.evaluate_operation(not_d_request).await?;
@@ -82,6 +88,7 @@ async fn boolean_circuit_demo(client: &mut FheServiceClient<tonic::transport::Ch
         server_key_id: server_key_id.clone(),
         operation: OperationType::And as i32,
         operand_ids: vec![encrypted_ids[0].clone(), encrypted_ids[1].clone()], // A, B
+        ..Default::default()
     });
     
     let a_and_b_response = client.evaluate_operation(a_and_b_request).await?;
@@ -93,6 +100,7 @@ async fn boolean_circuit_demo(client: &mut FheServiceClient<tonic::transport::Ch
         server_key_id: server_key_id.clone(),
         operation: OperationType::And as i32,
         operand_ids: vec![encrypted_ids[2].clone(), not_d_id.clone()], // C, NOT D
+        ..Default::default()
     });
     
     let c_and_not_d_response = client.evaluate_operation(c_and_not_d_request).await?;
@@ -104,6 +112,7 @@ async fn boolean_circuit_demo(client: &mut FheServiceClient<tonic::transport::Ch
         server_key_id: server_key_id.clone(),
         operation: OperationType::Or as i32,
         operand_ids: vec![a_and_b_id.clone(), c_and_not_d_id.clone()],
+        ..Default::default()
     });
     
     let final_response = client.evaluate_operation(final_request).await?;
@@ -123,7 +132,7 @@ async fn boolean_circuit_demo(client: &mut FheServiceClient<tonic::transport::Ch
     println!("Final result: (A AND B) OR (C AND NOT D) = {}", result);
     println!("Expected: (true AND false) OR (true AND NOT false) = false OR (true AND true) = false OR true = true");
     
-    if result == true {
+    if result {
         println!("✅ Result matches expected output");
     } else {
         println!("❌ Result does not match expected output");
@@ -136,6 +145,7 @@ async fn integer_arithmetic_demo(client: &mut FheServiceClient<tonic::transport:
     // Generate keys with default parameter set
     let key_gen_request = Request::new(KeyGenerationRequest {
         parameter_set: 0, // DEFAULT
+        ..Default::default()
     });
     
     let key_gen_response = client.generate_keys(key_gen_request).await?;
@@ -154,6 +164,7 @@ async fn integer_arithmetic_demo(client: &mut FheServiceClient<tonic::transport:
         client_key_id: client_key_id.clone(),
         value: a,
         num_bits: 8,
+        ..Default::default()
     });
     let encrypt_a_response = client.encrypt_integer(encrypt_a_request).await?;
     let a_id = encrypt_a_response.into_inner().encrypted_data_id;
@@ -163,6 +174,7 @@ async fn integer_arithmetic_demo(client: &mut FheServiceClient<tonic::transport:
         client_key_id: client_key_id.clone(),
         value: b,
         num_bits: 8,
+        ..Default::default()
     });
     let encrypt_b_response = client.encrypt_integer(encrypt_b_request).await?;
     let b_id = encrypt_b_response.into_inner().encrypted_data_id;
@@ -172,28 +184,22 @@ async fn integer_arithmetic_demo(client: &mut FheServiceClient<tonic::transport:
         client_key_id: client_key_id.clone(),
         value: c,
         num_bits: 8,
+        ..Default::default()
     });
     let encrypt_c_response = client.encrypt_integer(encrypt_c_request).await?;
     let c_id = encrypt_c_response.into_inner().encrypted_data_id;
     println!("Encrypted C = {} (id: {})", c, c_id);
     
-    // Compute a complex arithmetic expression: (A - B) * C
-    
-    // Step 1: Compute A - B
-    let a_minus_b_request = Request::new(EvaluationRequest {
-        server_key_id: server_key_id.clone(),
-        operation: OperationType::Subtract as i32,
-        operand_ids: vec![a_id.clone(), b_id.clone()],
-    });
-    let a_minus_b_response = client.evaluate_operation(a_minus_b_request).await?;
-    let a_minus_b_id = a_minus_b_response.into_inner().result_id;
-    println!("Computed A - B (id: {})", a_minus_b_id);
-    
-    // Step 2: Compute (A - B) * C
+    // Compute a complex arithmetic expression in one call: (A - B) * C
     let final_request = Request::new(EvaluationRequest {
         server_key_id: server_key_id.clone(),
-        operation: OperationType::Multiply as i32,
-        operand_ids: vec![a_minus_b_id.clone(), c_id.clone()],
+        expression: "(a - b) * c".to_string(),
+        variables: HashMap::from([
+            ("a".to_string(), a_id.clone()),
+            ("b".to_string(), b_id.clone()),
+            ("c".to_string(), c_id.clone()),
+        ]),
+        ..Default::default()
     });
     let final_response = client.evaluate_operation(final_request).await?;
     let final_result_id = final_response.into_inner().result_id;
@@ -204,6 +210,7 @@ async fn integer_arithmetic_demo(client: &mut FheServiceClient<tonic::transport:
         client_key_id: client_key_id.clone(),
         encrypted_data_id: final_result_id,
         serialized_data: vec![],
+        ..Default::default()
     });
     let decrypt_response = client.decrypt_integer(decrypt_request).await?;
     let result = decrypt_response.into_inner().value;
@@ -234,6 +241,7 @@ async fn parameter_sets_demo(client: &mut FheServiceClient<tonic::transport::Cha
         // Generate keys with this parameter set
         let key_gen_request = Request::new(KeyGenerationRequest {
             parameter_set: *param_set,
+            ..Default::default()
         });
         
         let key_gen_response = client.generate_keys(key_gen_request).await?;
@@ -251,6 +259,7 @@ async fn parameter_sets_demo(client: &mut FheServiceClient<tonic::transport::Cha
             client_key_id: client_key_id.clone(),
             value: a,
             num_bits: 8,
+            ..Default::default()
         });
         let encrypt_a_response = client.encrypt_integer(encrypt_a_request).await?;
         let a_id = encrypt_a_response.into_inner().encrypted_data_id;
@@ -259,6 +268,7 @@ async fn parameter_sets_demo(client: &mut FheServiceClient<tonic::transport::Cha
             client_key_id: client_key_id.clone(),
             value: b,
             num_bits: 8,
+            ..Default::default()
         });
         let encrypt_b_response = client.encrypt_integer(encrypt_b_request).await?;
         let b_id = encrypt_b_response.into_inner().encrypted_data_id;
@@ -268,6 +278,7 @@ async fn parameter_sets_demo(client: &mut FheServiceClient<tonic::transport::Cha
             server_key_id: server_key_id.clone(),
             operation: OperationType::Add as i32,
             operand_ids: vec![a_id.clone(), b_id.clone()],
+            ..Default::default()
         });
         let add_response = client.evaluate_operation(add_request).await?;
         let add_result_id = add_response.into_inner().result_id;
@@ -277,6 +288,7 @@ async fn parameter_sets_demo(client: &mut FheServiceClient<tonic::transport::Cha
             client_key_id: client_key_id.clone(),
             encrypted_data_id: add_result_id,
             serialized_data: vec![],
+            ..Default::default()
         });
         let decrypt_response = client.decrypt_integer(decrypt_request).await?;
         let result = decrypt_response.into_inner().value;
@@ -2,7 +2,8 @@ use hermetic_fhe::api::{
     EncryptBooleanRequest, EvaluationRequest,
     KeyGenerationRequest, OperationType, DecryptBooleanRequest,
 };
-use hermetic_fhe::api::hermetic_fhe::fhe_service_client::FheServiceClient;
+use hermetic_fhe::api::fhe_service_client::FheServiceClient;
+use hermetic_fhe::service::listen::{self, client_endpoint};
 use tracing::{info, Level};
 use tracing_subscriber::FmtSubscriber;
 
@@ -14,16 +15,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .finish();
     tracing::subscriber::set_global_default(subscriber)?;
 
-    info!("Connecting to FHE Service...");
+    // The server endpoint can be given as the first argument
+    let endpoint = client_endpoint(std::env::args().skip(1));
+    info!("Connecting to FHE Service at {}...", endpoint);
     
     // Connect to the server
-    let mut client = FheServiceClient::connect("http://[::1]:50051").await?;
+    let mut client = FheServiceClient::new(listen::connect(&endpoint).await?);
     
     // Generate encryption keys
     info!("Generating encryption keys...");
     let key_response = client
         .generate_keys(KeyGenerationRequest {
             parameter_set: 0, // DEFAULT
+            ..Default::default()
         })
         .await?;
     
@@ -39,6 +43,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .encrypt_boolean(EncryptBooleanRequest {
             client_key_id: client_key_id.clone(),
             value: true,
+            ..Default::default()
         })
         .await?;
     
@@ -46,6 +51,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .encrypt_boolean(EncryptBooleanRequest {
             client_key_id: client_key_id.clone(),
             value: false,
+            ..Default::default()
         })
         .await?;
     
@@ -62,6 +68,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             server_key_id: server_key_id.clone(),
             operation: OperationType::And as i32,
             operand_ids: vec![true_id.clone(), false_id.clone()],
+            ..Default::default()
         })
         .await?;
     
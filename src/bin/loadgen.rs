@@ -0,0 +1,544 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hermetic_fhe::api::fhe_service_client::FheServiceClient;
+use hermetic_fhe::api::{
+    CloseSessionRequest, CreateSessionRequest, DecryptIntegerRequest, EncryptIntegerRequest,
+    EvaluationRequest, KeyGenerationRequest, OperationType,
+};
+use hermetic_fhe::service::listen::{self, client_endpoint};
+use serde::Serialize;
+use tokio::sync::{mpsc, Semaphore};
+use tokio::time::{Instant, MissedTickBehavior};
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::interceptor::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::transport::Channel;
+use tonic::{Code, Request, Status};
+
+const USAGE: &str = "\
+Usage: loadgen [options]
+
+Sends a steady stream of requests to a running server and reports latency percentiles
+and error rates for each kind of request.
+
+Options:
+  --endpoint URL        Server to load; defaults to HERMETIC_FHE_ENDPOINT, then localhost
+  --rps N               Requests started per second, whether or not earlier ones have
+                        finished (default 10)
+  --duration SECONDS    How long to send for (default 30)
+  --concurrency N       Most requests in flight; a request due while this many are
+                        outstanding is skipped and counted (default 64)
+  --mix WEIGHTS         Relative share of each request, e.g. keygen=0,encrypt=4,evaluate=4,
+                        decrypt=2 (the default)
+  --operation NAME      Operation evaluate requests run on stored integers, e.g. ADD or
+                        MULTIPLY (default ADD)
+  --token TOKEN         Access token for servers requiring one; defaults to
+                        HERMETIC_FHE_ACCESS_TOKEN
+  --json                Print the report as JSON instead of a table";
+
+// Ciphertexts encrypted before the run for evaluate and decrypt requests to read
+const OPERANDS: usize = 16;
+
+// Integer operations evaluate requests may run, with the operands each takes
+const OPERATIONS: [(OperationType, usize); 8] = [
+    (OperationType::Add, 2),
+    (OperationType::Subtract, 2),
+    (OperationType::Multiply, 2),
+    (OperationType::AbsDiff, 2),
+    (OperationType::SaturatingAdd, 2),
+    (OperationType::SaturatingSub, 2),
+    (OperationType::IsZero, 1),
+    (OperationType::IsNonZero, 1),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    Keygen,
+    Encrypt,
+    Evaluate,
+    Decrypt,
+}
+
+impl Kind {
+    const ALL: [Kind; 4] = [Kind::Keygen, Kind::Encrypt, Kind::Evaluate, Kind::Decrypt];
+
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Keygen => "keygen",
+            Kind::Encrypt => "encrypt",
+            Kind::Evaluate => "evaluate",
+            Kind::Decrypt => "decrypt",
+        }
+    }
+}
+
+struct Options {
+    endpoint: String,
+    rps: f64,
+    duration: Duration,
+    concurrency: usize,
+    mix: Vec<(Kind, u32)>,
+    operation: (OperationType, usize),
+    token: Option<String>,
+    json: bool,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut endpoint = None;
+        let mut options = Options {
+            endpoint: String::new(),
+            rps: 10.0,
+            duration: Duration::from_secs(30),
+            concurrency: 64,
+            mix: parse_mix("keygen=0,encrypt=4,evaluate=4,decrypt=2")?,
+            operation: OPERATIONS[0],
+            token: std::env::var("HERMETIC_FHE_ACCESS_TOKEN").ok(),
+            json: false,
+        };
+        while let Some(flag) = args.next() {
+            if flag == "--json" {
+                options.json = true;
+                continue;
+            }
+            let value = args.next().ok_or_else(|| format!("{} needs a value", flag))?;
+            match flag.as_str() {
+                "--endpoint" => endpoint = Some(value),
+                "--rps" => {
+                    options.rps = value
+                        .parse()
+                        .ok()
+                        .filter(|rps: &f64| *rps > 0.0 && rps.is_finite())
+                        .ok_or("--rps must be a positive number")?
+                }
+                "--duration" => {
+                    let seconds: f64 = value
+                        .parse()
+                        .ok()
+                        .filter(|seconds: &f64| *seconds > 0.0 && seconds.is_finite())
+                        .ok_or("--duration must be a positive number of seconds")?;
+                    options.duration = Duration::from_secs_f64(seconds);
+                }
+                "--concurrency" => {
+                    options.concurrency = value
+                        .parse()
+                        .ok()
+                        .filter(|concurrency| *concurrency > 0)
+                        .ok_or("--concurrency must be a positive integer")?
+                }
+                "--mix" => options.mix = parse_mix(&value)?,
+                "--operation" => {
+                    let operation = OperationType::from_str_name(&value.to_ascii_uppercase());
+                    options.operation = OPERATIONS
+                        .into_iter()
+                        .find(|(known, _)| Some(*known) == operation)
+                        .ok_or_else(|| format!("--operation must be an integer operation, not {}", value))?;
+                }
+                "--token" => options.token = Some(value),
+                _ => return Err(format!("Unknown option {}", flag)),
+            }
+        }
+        options.endpoint = client_endpoint(endpoint);
+        Ok(options)
+    }
+
+    fn requests(&self) -> usize {
+        (self.rps * self.duration.as_secs_f64()).round() as usize
+    }
+}
+
+// "encrypt=4,evaluate=4" into weights; kinds left out get none
+fn parse_mix(mix: &str) -> Result<Vec<(Kind, u32)>, String> {
+    let mut weights = Vec::new();
+    for entry in mix.split(',').filter(|entry| !entry.is_empty()) {
+        let (name, weight) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("Mix entry '{}' must be name=weight", entry))?;
+        let kind = Kind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == name.trim())
+            .ok_or_else(|| {
+                format!(
+                    "Unknown request '{}' in mix: expected keygen, encrypt, evaluate or decrypt",
+                    name
+                )
+            })?;
+        let weight = weight
+            .trim()
+            .parse()
+            .map_err(|_| format!("Weight of {} must be a non-negative integer", name))?;
+        weights.push((kind, weight));
+    }
+    if weights.iter().all(|(_, weight)| *weight == 0) {
+        return Err("The mix must give some request a weight".to_string());
+    }
+    Ok(weights)
+}
+
+// Which request each slot of the schedule sends: every kind as often as its weight, with
+// the kinds interleaved so each sees the same load over any stretch of the run
+fn schedule(mix: &[(Kind, u32)]) -> Vec<Kind> {
+    let total: u32 = mix.iter().map(|(_, weight)| weight).sum();
+    let mut credit = vec![0i64; mix.len()];
+    (0..total)
+        .map(|_| {
+            for (credit, (_, weight)) in credit.iter_mut().zip(mix) {
+                *credit += i64::from(*weight);
+            }
+            let (next, _) = credit
+                .iter()
+                .enumerate()
+                .max_by_key(|(index, credit)| (**credit, std::cmp::Reverse(*index)))
+                .expect("mix is not empty");
+            credit[next] -= i64::from(total);
+            mix[next].0
+        })
+        .collect()
+}
+
+// Puts the access token on every call, for servers that require one
+#[derive(Clone)]
+struct AccessToken(Option<MetadataValue<Ascii>>);
+
+impl Interceptor for AccessToken {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(token) = &self.0 {
+            request.metadata_mut().insert("authorization", token.clone());
+        }
+        Ok(request)
+    }
+}
+
+type Client = FheServiceClient<InterceptedService<Channel, AccessToken>>;
+
+// Keys, session and ciphertexts set up before the run, which the timed requests use
+struct Fixture {
+    client_key_id: String,
+    server_key_id: String,
+    session_id: String,
+    operands: Vec<String>,
+}
+
+impl Fixture {
+    async fn set_up(client: &mut Client) -> Result<Self, Status> {
+        let keys = client
+            .generate_keys(KeyGenerationRequest {
+                parameter_set: 0, // DEFAULT
+                ..Default::default()
+            })
+            .await?
+            .into_inner();
+        // Everything the run stores goes in one session, closed at the end
+        let session_id = client
+            .create_session(CreateSessionRequest::default())
+            .await?
+            .into_inner()
+            .session_id;
+        let mut operands = Vec::with_capacity(OPERANDS);
+        for value in 0..OPERANDS {
+            let response = client
+                .encrypt_integer(EncryptIntegerRequest {
+                    client_key_id: keys.client_key_id.clone(),
+                    value: value as i64 * 7,
+                    session_id: session_id.clone(),
+                    ..Default::default()
+                })
+                .await?;
+            operands.push(response.into_inner().encrypted_data_id);
+        }
+        Ok(Self {
+            client_key_id: keys.client_key_id,
+            server_key_id: keys.server_key_id,
+            session_id,
+            operands,
+        })
+    }
+
+    fn operand(&self, index: usize) -> String {
+        self.operands[index % self.operands.len()].clone()
+    }
+}
+
+// The nth request of the run
+async fn send(
+    client: &mut Client,
+    fixture: &Fixture,
+    kind: Kind,
+    n: usize,
+    (operation, arity): (OperationType, usize),
+) -> Result<(), Status> {
+    match kind {
+        Kind::Keygen => {
+            client
+                .generate_keys(KeyGenerationRequest {
+                    parameter_set: 0, // DEFAULT
+                    ..Default::default()
+                })
+                .await?;
+        }
+        Kind::Encrypt => {
+            client
+                .encrypt_integer(EncryptIntegerRequest {
+                    client_key_id: fixture.client_key_id.clone(),
+                    value: (n % 256) as i64,
+                    session_id: fixture.session_id.clone(),
+                    ..Default::default()
+                })
+                .await?;
+        }
+        Kind::Evaluate => {
+            client
+                .evaluate_operation(EvaluationRequest {
+                    server_key_id: fixture.server_key_id.clone(),
+                    operation: operation as i32,
+                    operand_ids: (0..arity).map(|i| fixture.operand(n + i)).collect(),
+                    session_id: fixture.session_id.clone(),
+                    ..Default::default()
+                })
+                .await?;
+        }
+        Kind::Decrypt => {
+            client
+                .decrypt_integer(DecryptIntegerRequest {
+                    client_key_id: fixture.client_key_id.clone(),
+                    encrypted_data_id: fixture.operand(n),
+                    ..Default::default()
+                })
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+enum Outcome {
+    Succeeded(Duration),
+    Failed(Code),
+    // Due while the concurrency limit was reached, so never sent
+    Skipped,
+}
+
+// Send requests at a fixed rate until the duration is up, without waiting for earlier
+// ones, so a slow server builds a queue as it would under real clients. Latency counts
+// from when a request was due rather than when it went out, so time spent waiting behind
+// a stalled sender is not hidden.
+async fn run(client: Client, fixture: Arc<Fixture>, options: &Options) -> Vec<(Kind, Outcome)> {
+    let schedule = schedule(&options.mix);
+    let semaphore = Arc::new(Semaphore::new(options.concurrency));
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let mut ticker = tokio::time::interval(Duration::from_secs_f64(1.0 / options.rps));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Burst);
+
+    for n in 0..options.requests() {
+        let due = ticker.tick().await;
+        let kind = schedule[n % schedule.len()];
+        let Ok(permit) = semaphore.clone().try_acquire_owned() else {
+            let _ = sender.send((kind, Outcome::Skipped));
+            continue;
+        };
+        let (mut client, fixture, sender, operation) =
+            (client.clone(), fixture.clone(), sender.clone(), options.operation);
+        tokio::spawn(async move {
+            let outcome = match send(&mut client, &fixture, kind, n, operation).await {
+                Ok(()) => Outcome::Succeeded(Instant::now() - due),
+                Err(status) => Outcome::Failed(status.code()),
+            };
+            drop(permit);
+            let _ = sender.send((kind, outcome));
+        });
+    }
+    drop(sender);
+
+    let mut outcomes = Vec::with_capacity(options.requests());
+    while let Some(outcome) = receiver.recv().await {
+        outcomes.push(outcome);
+    }
+    outcomes
+}
+
+#[derive(Serialize)]
+struct Report {
+    request: &'static str,
+    sent: usize,
+    succeeded: usize,
+    failed: usize,
+    skipped: usize,
+    error_rate: f64,
+    // Successful requests per second of the run
+    throughput: f64,
+    // Latency percentiles of successful requests, in milliseconds
+    p50_ms: f64,
+    p90_ms: f64,
+    p99_ms: f64,
+    p999_ms: f64,
+    max_ms: f64,
+    errors: BTreeMap<String, usize>,
+}
+
+impl Report {
+    fn new<'a>(
+        request: &'static str,
+        outcomes: impl Iterator<Item = &'a Outcome>,
+        elapsed: Duration,
+    ) -> Self {
+        let mut latencies = Vec::new();
+        let mut errors = BTreeMap::new();
+        let mut skipped = 0;
+        for outcome in outcomes {
+            match outcome {
+                Outcome::Succeeded(latency) => latencies.push(*latency),
+                Outcome::Failed(code) => *errors.entry(format!("{:?}", code)).or_insert(0) += 1,
+                Outcome::Skipped => skipped += 1,
+            }
+        }
+        latencies.sort();
+        let failed: usize = errors.values().sum();
+        let sent = latencies.len() + failed;
+        let percentile = |p: f64| match latencies.len() {
+            0 => 0.0,
+            len => {
+                // Nearest rank
+                let rank = ((p / 100.0 * len as f64).ceil() as usize).clamp(1, len);
+                latencies[rank - 1].as_secs_f64() * 1000.0
+            }
+        };
+        Self {
+            request,
+            sent,
+            succeeded: latencies.len(),
+            failed,
+            skipped,
+            error_rate: if sent == 0 {
+                0.0
+            } else {
+                failed as f64 / sent as f64
+            },
+            throughput: latencies.len() as f64 / elapsed.as_secs_f64(),
+            p50_ms: percentile(50.0),
+            p90_ms: percentile(90.0),
+            p99_ms: percentile(99.0),
+            p999_ms: percentile(99.9),
+            max_ms: latencies
+                .last()
+                .map_or(0.0, |latency| latency.as_secs_f64() * 1000.0),
+            errors,
+        }
+    }
+}
+
+fn print_table(reports: &[Report]) {
+    println!(
+        "{:<10} {:>8} {:>8} {:>8} {:>8} {:>9} {:>9} {:>9} {:>9} {:>9} {:>9}",
+        "request",
+        "sent",
+        "failed",
+        "skipped",
+        "errors%",
+        "ok/s",
+        "p50 ms",
+        "p90 ms",
+        "p99 ms",
+        "p99.9 ms",
+        "max ms"
+    );
+    for report in reports {
+        println!(
+            "{:<10} {:>8} {:>8} {:>8} {:>8.2} {:>9.2} {:>9.1} {:>9.1} {:>9.1} {:>9.1} {:>9.1}",
+            report.request,
+            report.sent,
+            report.failed,
+            report.skipped,
+            report.error_rate * 100.0,
+            report.throughput,
+            report.p50_ms,
+            report.p90_ms,
+            report.p99_ms,
+            report.p999_ms,
+            report.max_ms
+        );
+    }
+    for report in reports.iter().filter(|report| !report.errors.is_empty()) {
+        let errors: Vec<String> = report
+            .errors
+            .iter()
+            .map(|(code, count)| format!("{} {}", count, code))
+            .collect();
+        println!("{} errors: {}", report.request, errors.join(", "));
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+    let options = match Options::parse(args.into_iter()) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let token = match &options.token {
+        Some(token) => Some(format!("Bearer {}", token).parse()?),
+        None => None,
+    };
+    let channel = listen::connect(&options.endpoint).await?;
+    let mut client = FheServiceClient::with_interceptor(channel, AccessToken(token));
+
+    eprintln!(
+        "Setting up keys and {} operands on {}...",
+        OPERANDS, options.endpoint
+    );
+    let fixture = Arc::new(Fixture::set_up(&mut client).await?);
+    eprintln!(
+        "Sending {} requests at {} per second for {:.0} s, at most {} in flight...",
+        options.requests(),
+        options.rps,
+        options.duration.as_secs_f64(),
+        options.concurrency
+    );
+    let started = Instant::now();
+    let outcomes = run(client.clone(), fixture.clone(), &options).await;
+    let elapsed = started.elapsed();
+
+    let mut reports: Vec<Report> = Kind::ALL
+        .into_iter()
+        .filter(|kind| {
+            options
+                .mix
+                .iter()
+                .any(|(mixed, weight)| mixed == kind && *weight > 0)
+        })
+        .map(|kind| {
+            let outcomes = outcomes
+                .iter()
+                .filter(|(of, _)| *of == kind)
+                .map(|(_, outcome)| outcome);
+            Report::new(kind.name(), outcomes, elapsed)
+        })
+        .collect();
+    reports.push(Report::new(
+        "all",
+        outcomes.iter().map(|(_, outcome)| outcome),
+        elapsed,
+    ));
+
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    } else {
+        print_table(&reports);
+    }
+
+    client
+        .close_session(CloseSessionRequest {
+            session_id: fixture.session_id.clone(),
+        })
+        .await?;
+    Ok(())
+}
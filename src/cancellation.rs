@@ -0,0 +1,79 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use thiserror::Error;
+
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum Cancelled {
+    #[error("Evaluation cancelled by the caller")]
+    ByCaller,
+    #[error("Evaluation deadline exceeded")]
+    DeadlineExceeded,
+}
+
+// Cooperative cancellation for long evaluations. Work checks it between gates or chunks
+// and stops once the caller has gone away or its deadline has passed.
+#[derive(Clone, Debug, Default)]
+pub struct Cancellation {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl Cancellation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_deadline(deadline: Instant) -> Self {
+        Self {
+            deadline: Some(deadline),
+            ..Self::default()
+        }
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    // Err once the work should stop
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return Err(Cancelled::ByCaller);
+        }
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => Err(Cancelled::DeadlineExceeded),
+            _ => Ok(()),
+        }
+    }
+
+    // Cancel when the returned guard is dropped, e.g. along with an abandoned request future.
+    // Call disarm on the guard once the work has finished.
+    pub fn cancel_on_drop(&self) -> CancelOnDrop {
+        CancelOnDrop {
+            cancellation: Some(self.clone()),
+        }
+    }
+}
+
+pub struct CancelOnDrop {
+    cancellation: Option<Cancellation>,
+}
+
+impl CancelOnDrop {
+    pub fn disarm(mut self) {
+        self.cancellation = None;
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(cancellation) = &self.cancellation {
+            cancellation.cancel();
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::circuit::Value;
+use crate::crypto::versioning;
+use crate::crypto::{Ciphertext, CiphertextKind};
+
+// A file a long job saves its progress to every interval, so a run cut short by a crash or
+// restart carries on from the last save instead of starting over. Each save replaces the
+// one before. Saves are tagged with the job they belong to, and another job naming the same
+// file starts afresh rather than picking up progress that isn't its own.
+#[derive(Debug)]
+pub struct Checkpoint {
+    path: PathBuf,
+    // Digest of everything that determines the job's result
+    job: String,
+    interval: Duration,
+    last_saved: Mutex<Instant>,
+}
+
+// The tag goes first, so it can be read whatever the progress is
+#[derive(Deserialize)]
+struct Saved<T> {
+    job: String,
+    progress: T,
+}
+
+// Encodes the same as Saved
+#[derive(Serialize)]
+struct Saving<'a, T> {
+    job: &'a str,
+    progress: &'a T,
+}
+
+impl Checkpoint {
+    pub fn new(path: impl Into<PathBuf>, job: impl Into<String>, interval: Duration) -> Self {
+        Self {
+            path: path.into(),
+            job: job.into(),
+            interval,
+            last_saved: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // What an earlier run of this job saved, or None if there is nothing to carry on from
+    pub fn load<T: DeserializeOwned>(&self) -> Result<Option<T>> {
+        let bytes = match fs::read(&self.path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(anyhow!("Failed to read {}: {}", self.path.display(), e)),
+        };
+        // The ciphertexts in it are only as readable as the tfhe-rs release that wrote them
+        let (bytes, _) = versioning::read(&bytes).map_err(|e| anyhow!("{} in {}", e, self.path.display()))?;
+        let job: String = bincode::deserialize(&bytes)
+            .map_err(|e| anyhow!("Invalid checkpoint {}: {}", self.path.display(), e))?;
+        if job != self.job {
+            warn!(
+                "Checkpoint {} was saved by another job; starting over",
+                self.path.display()
+            );
+            return Ok(None);
+        }
+        let saved: Saved<T> = bincode::deserialize(&bytes)
+            .map_err(|e| anyhow!("Invalid checkpoint {}: {}", self.path.display(), e))?;
+        Ok(Some(saved.progress))
+    }
+
+    // Whether the interval has passed since the last save, or since the job started
+    pub fn due(&self) -> bool {
+        self.last_saved.lock().unwrap().elapsed() >= self.interval
+    }
+
+    // Written to a temporary file and renamed, so a crash mid-save leaves the previous save.
+    // Like key bundles, saves record the tfhe-rs release that wrote them.
+    pub fn save<T: Serialize>(&self, progress: &T) -> Result<()> {
+        let saving = Saving {
+            job: &self.job,
+            progress,
+        };
+        let bytes = bincode::serialize(&saving).map_err(|e| anyhow!("Failed to encode checkpoint: {}", e))?;
+        let bytes = versioning::seal(&bytes)?;
+        let staging = self.path.with_extension("tmp");
+        fs::write(&staging, bytes)
+            .and_then(|_| fs::rename(&staging, &self.path))
+            .map_err(|e| anyhow!("Failed to write {}: {}", self.path.display(), e))?;
+        *self.last_saved.lock().unwrap() = Instant::now();
+        Ok(())
+    }
+
+    // Forget the saved progress once the job has finished
+    pub fn remove(&self) {
+        match fs::remove_file(&self.path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => warn!("Failed to remove checkpoint {}: {}", self.path.display(), e),
+        }
+    }
+}
+
+// A boolean or integer ciphertext as a checkpoint holds it
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SavedValue {
+    boolean: bool,
+    bytes: Vec<u8>,
+}
+
+impl SavedValue {
+    pub fn new(value: &Value) -> Result<Self> {
+        let (bytes, _) = Ciphertext::from(value.clone()).serialize_with_fingerprint()?;
+        Ok(Self {
+            boolean: matches!(value, Value::Boolean(_)),
+            bytes,
+        })
+    }
+
+    pub fn value(&self) -> Result<Value> {
+        let kind = if self.boolean {
+            CiphertextKind::Boolean
+        } else {
+            CiphertextKind::Integer
+        };
+        match Ciphertext::deserialize(kind, &self.bytes)? {
+            Ciphertext::Boolean(ciphertext) => Ok(Value::Boolean(ciphertext)),
+            Ciphertext::Integer(ciphertext) => Ok(Value::Integer(ciphertext)),
+            _ => unreachable!("deserialized as a boolean or integer"),
+        }
+    }
+}
+
+// How far a circuit evaluation got: the number of gates done, and the outputs of those
+// that later gates or the circuit's outputs still need
+#[derive(Serialize, Deserialize)]
+pub(crate) struct CircuitProgress {
+    pub completed: usize,
+    pub values: Vec<(usize, SavedValue)>,
+}
+
+impl CircuitProgress {
+    pub fn new(completed: usize, values: &[Option<Value>]) -> Result<Self> {
+        let values = values
+            .iter()
+            .enumerate()
+            .filter_map(|(index, value)| value.as_ref().map(|value| (index, value)))
+            .map(|(index, value)| Ok((index, SavedValue::new(value)?)))
+            .collect::<Result<_>>()?;
+        Ok(Self { completed, values })
+    }
+}
@@ -0,0 +1,335 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tfhe::prelude::FheTryEncrypt;
+use tfhe::{ClientKey, FheBool, FheUint8, ServerKey};
+
+use super::{apply, Circuit, Operation, Value, ValueType};
+use crate::crypto::{parameter_config, PARAMETER_SETS};
+
+// Deadlines suggested to clients allow this many times the expected time, for noise in the
+// estimate and in the machine's load
+const DEADLINE_MARGIN: u32 = 2;
+
+// Shortest deadline suggested, however quick the work
+const MIN_SUGGESTED_DEADLINE: Duration = Duration::from_secs(1);
+
+pub const OPERATIONS: [Operation; 12] = [
+    Operation::And,
+    Operation::Or,
+    Operation::Xor,
+    Operation::Not,
+    Operation::Add,
+    Operation::Subtract,
+    Operation::Multiply,
+    Operation::IsZero,
+    Operation::IsNonZero,
+    Operation::AbsDiff,
+    Operation::SaturatingAdd,
+    Operation::SaturatingSub,
+];
+
+// What one gate costs to run and what its ciphertexts cost to hold under one parameter set
+#[derive(Clone, Debug)]
+pub struct ParameterCosts {
+    pub gate_latency: HashMap<Operation, Duration>,
+    pub boolean_bytes: usize,
+    pub integer_bytes: usize,
+    pub server_key_bytes: usize,
+}
+
+impl ParameterCosts {
+    // Rough single-core figures for the default tfhe parameters on a recent x86 server
+    fn builtin() -> Self {
+        let gate_latency = OPERATIONS
+            .iter()
+            .map(|operation| {
+                let millis = match operation {
+                    Operation::Not => 1,
+                    Operation::And | Operation::Or | Operation::Xor => 15,
+                    Operation::Add => 60,
+                    Operation::Subtract => 70,
+                    Operation::Multiply => 200,
+                    Operation::IsZero | Operation::IsNonZero => 30,
+                    // A comparison and a selection on top of the arithmetic
+                    Operation::SaturatingAdd => 190,
+                    Operation::SaturatingSub => 200,
+                    Operation::AbsDiff => 330,
+                };
+                (*operation, Duration::from_millis(millis))
+            })
+            .collect();
+
+        Self {
+            gate_latency,
+            boolean_bytes: 16 * 1024,
+            integer_bytes: 64 * 1024,
+            server_key_bytes: 110 * 1024 * 1024,
+        }
+    }
+
+    fn ciphertext_bytes(&self, value_type: ValueType) -> usize {
+        match value_type {
+            ValueType::Boolean => self.boolean_bytes,
+            ValueType::Integer => self.integer_bytes,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CostEstimate {
+    // Gates run one after another, so this is the sum over every gate
+    pub latency: Duration,
+    // Most ciphertext memory held at once for gate outputs
+    pub memory_bytes: usize,
+    pub server_key_bytes: usize,
+}
+
+// One parameter set's costs as saved in a cost table, with latencies by operation name
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SavedCosts {
+    gate_latency_micros: BTreeMap<String, u64>,
+    boolean_bytes: usize,
+    integer_bytes: usize,
+    server_key_bytes: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CostTable {
+    parameter_sets: BTreeMap<String, SavedCosts>,
+}
+
+// Per-gate costs for every parameter set, either built in or measured on this machine
+#[derive(Clone, Debug)]
+pub struct CostModel {
+    parameter_sets: HashMap<String, ParameterCosts>,
+    // Parameter sets whose costs were measured rather than built in
+    calibrated: BTreeSet<String>,
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        Self {
+            parameter_sets: PARAMETER_SETS
+                .iter()
+                .map(|name| (name.to_string(), ParameterCosts::builtin()))
+                .collect(),
+            calibrated: BTreeSet::new(),
+        }
+    }
+}
+
+impl CostModel {
+    // Time every gate `samples` times under each parameter set and measure real ciphertext
+    // and key sizes. Generates a key pair per set, so this takes a while.
+    pub fn calibrate(parameter_sets: &[&str], samples: usize) -> Result<Self> {
+        Self::default().recalibrate(parameter_sets, samples)
+    }
+
+    // A copy with the listed parameter sets measured again and the rest kept as they are
+    pub fn recalibrate(&self, parameter_sets: &[&str], samples: usize) -> Result<Self> {
+        let mut model = self.clone();
+        for name in parameter_sets {
+            model.parameter_sets.insert(name.to_string(), measure(name, samples.max(1))?);
+            model.calibrated.insert(name.to_string());
+        }
+        Ok(model)
+    }
+
+    // HERMETIC_FHE_CALIBRATE_COSTS names how many times to time each gate at startup,
+    // saving the results to the cost table if there is one. Otherwise an existing cost
+    // table is loaded, and the built-in figures are used when there is none.
+    pub fn from_env() -> Result<Self> {
+        let table = table_path_from_env();
+        match env::var("HERMETIC_FHE_CALIBRATE_COSTS") {
+            Ok(value) => match value.trim().parse::<usize>() {
+                Ok(samples) if samples > 0 => {
+                    let model = Self::calibrate(&PARAMETER_SETS, samples)?;
+                    if let Some(path) = &table {
+                        model.save(path)?;
+                    }
+                    Ok(model)
+                }
+                _ => Err(anyhow!("HERMETIC_FHE_CALIBRATE_COSTS must be a positive integer")),
+            },
+            Err(_) => match table {
+                Some(path) if path.exists() => Self::load(&path),
+                _ => Ok(Self::default()),
+            },
+        }
+    }
+
+    // A table saved by `save`, so a restart needn't measure again. Parameter sets and
+    // operations it leaves out keep their built-in figures.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents =
+            std::fs::read(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        let table: CostTable = serde_json::from_slice(&contents)
+            .map_err(|e| anyhow!("Invalid cost table {}: {}", path.display(), e))?;
+
+        let mut model = Self::default();
+        for (name, saved) in table.parameter_sets {
+            parameter_config(&name).map_err(|_| anyhow!("Unknown parameter set '{}' in cost table", name))?;
+            let mut costs = ParameterCosts::builtin();
+            for (operation, micros) in saved.gate_latency_micros {
+                let operation = OPERATIONS
+                    .into_iter()
+                    .find(|known| format!("{:?}", known) == operation)
+                    .ok_or_else(|| anyhow!("Unknown operation '{}' in cost table", operation))?;
+                costs.gate_latency.insert(operation, Duration::from_micros(micros));
+            }
+            costs.boolean_bytes = saved.boolean_bytes;
+            costs.integer_bytes = saved.integer_bytes;
+            costs.server_key_bytes = saved.server_key_bytes;
+            model.parameter_sets.insert(name.clone(), costs);
+            model.calibrated.insert(name);
+        }
+        Ok(model)
+    }
+
+    // Write the measured parameter sets to a cost table, replacing the file whole so a
+    // reader never sees half of it
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let parameter_sets = self
+            .calibrated
+            .iter()
+            .filter_map(|name| Some((name, self.parameter_sets.get(name)?)))
+            .map(|(name, costs)| {
+                let gate_latency_micros = costs
+                    .gate_latency
+                    .iter()
+                    .map(|(operation, latency)| (format!("{:?}", operation), latency.as_micros() as u64))
+                    .collect();
+                let saved = SavedCosts {
+                    gate_latency_micros,
+                    boolean_bytes: costs.boolean_bytes,
+                    integer_bytes: costs.integer_bytes,
+                    server_key_bytes: costs.server_key_bytes,
+                };
+                (name.clone(), saved)
+            })
+            .collect();
+        let contents = serde_json::to_vec_pretty(&CostTable { parameter_sets })?;
+        let partial = path.with_extension("partial");
+        std::fs::write(&partial, contents)
+            .and_then(|_| std::fs::rename(&partial, path))
+            .map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))
+    }
+
+    // True when some parameter set's costs were measured on this machine rather than built in
+    pub fn is_calibrated(&self) -> bool {
+        !self.calibrated.is_empty()
+    }
+
+    pub fn is_measured(&self, parameter_set: &str) -> bool {
+        self.calibrated.contains(parameter_set)
+    }
+
+    pub fn parameter_costs(&self, parameter_set: &str) -> Option<&ParameterCosts> {
+        self.parameter_sets.get(parameter_set)
+    }
+
+    // Every parameter set's costs, by name
+    pub fn parameter_sets(&self) -> Vec<(&str, &ParameterCosts)> {
+        let mut parameter_sets: Vec<_> = self
+            .parameter_sets
+            .iter()
+            .map(|(name, costs)| (name.as_str(), costs))
+            .collect();
+        parameter_sets.sort_by_key(|(name, _)| *name);
+        parameter_sets
+    }
+
+    // Least time the circuit's gates take under any parameter set, for deciding that work
+    // can't meet a deadline without knowing which set its key was made with
+    pub fn fastest_latency(&self, circuit: &Circuit) -> Duration {
+        self.parameter_sets
+            .keys()
+            .filter_map(|name| self.estimate(name, circuit).ok())
+            .map(|estimate| estimate.latency)
+            .min()
+            .unwrap_or_default()
+    }
+
+    pub fn estimate(&self, parameter_set: &str, circuit: &Circuit) -> Result<CostEstimate> {
+        let costs = self
+            .parameter_costs(parameter_set)
+            .ok_or_else(|| anyhow!("Unknown parameter set '{}'", parameter_set))?;
+
+        let latency = circuit
+            .gates
+            .iter()
+            .filter_map(|gate| costs.gate_latency.get(&gate.operation))
+            .sum();
+        // Charge every live output at the size of the largest ciphertext the circuit makes
+        let largest = circuit
+            .gates
+            .iter()
+            .map(|gate| costs.ciphertext_bytes(gate.operation.value_type()))
+            .max()
+            .unwrap_or(0);
+
+        Ok(CostEstimate {
+            latency,
+            memory_bytes: circuit.peak_live_values() * largest,
+            server_key_bytes: costs.server_key_bytes,
+        })
+    }
+}
+
+// Deadline for a client to send with work expected to take `latency` once it has waited
+// `queue_wait` for a worker
+pub fn suggested_deadline(latency: Duration, queue_wait: Duration) -> Duration {
+    ((latency + queue_wait) * DEADLINE_MARGIN).max(MIN_SUGGESTED_DEADLINE)
+}
+
+// HERMETIC_FHE_COST_TABLE, the file measured costs are kept in across restarts
+pub fn table_path_from_env() -> Option<PathBuf> {
+    env::var("HERMETIC_FHE_COST_TABLE").ok().map(PathBuf::from)
+}
+
+fn measure(parameter_set: &str, samples: usize) -> Result<ParameterCosts> {
+    let client_key = ClientKey::generate(parameter_config(parameter_set)?);
+    let server_key = ServerKey::new(&client_key);
+    tfhe::set_server_key(server_key.clone());
+
+    let boolean = FheBool::try_encrypt(true, &client_key).map_err(|e| anyhow!("Encryption failed: {}", e))?;
+    let integer = FheUint8::try_encrypt(3u8, &client_key).map_err(|e| anyhow!("Encryption failed: {}", e))?;
+    let (boolean, integer) = (Arc::new(boolean), Arc::new(integer));
+    let boolean_operand = Value::Boolean(boolean.clone());
+    let integer_operand = Value::Integer(integer.clone());
+
+    let mut gate_latency = HashMap::new();
+    for operation in OPERATIONS {
+        let operand = match operation.operand_type() {
+            ValueType::Boolean => &boolean_operand,
+            ValueType::Integer => &integer_operand,
+        };
+        let operands = vec![operand; operation.arity()];
+
+        let started = Instant::now();
+        for _ in 0..samples {
+            apply(&server_key, operation, &operands)?;
+        }
+        gate_latency.insert(operation, started.elapsed() / samples as u32);
+    }
+
+    let size = |result: bincode::Result<u64>| {
+        result
+            .map(|bytes| bytes as usize)
+            .map_err(|e| anyhow!("Failed to measure serialized size: {}", e))
+    };
+    Ok(ParameterCosts {
+        gate_latency,
+        boolean_bytes: size(bincode::serialized_size(&*boolean))?,
+        integer_bytes: size(bincode::serialized_size(&*integer))?,
+        server_key_bytes: size(bincode::serialized_size(&server_key))?,
+    })
+}
@@ -0,0 +1,239 @@
+use thiserror::Error;
+
+use super::{Circuit, Gate, Issue, IssueKind, Operation, ValueType, Wire};
+
+// Longest expression accepted, which also bounds the number of gates it can compile to
+pub const MAX_EXPRESSION_LENGTH: usize = 4096;
+
+// Deepest nesting of parentheses and prefix operators, so parsing can't exhaust the stack
+pub const MAX_NESTING: usize = 64;
+
+// Binary operators from loosest to tightest binding; all are left-associative
+const LEVELS: [&[(char, Operation)]; 5] = [
+    &[('|', Operation::Or)],
+    &[('^', Operation::Xor)],
+    &[('&', Operation::And)],
+    &[('+', Operation::Add), ('-', Operation::Subtract)],
+    &[('*', Operation::Multiply)],
+];
+
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+#[error("{message} at offset {offset}")]
+pub struct ExpressionError {
+    // Byte offset into the expression
+    pub offset: usize,
+    pub message: String,
+}
+
+// An infix expression compiled to a single-output circuit. Each distinct variable is
+// one circuit input, numbered in order of first appearance.
+#[derive(Clone, Debug)]
+pub struct Expression {
+    pub circuit: Circuit,
+    pub variables: Vec<String>,
+}
+
+impl Expression {
+    // Types of the variables and of the result. Every operator takes operands of one type,
+    // so each variable's type follows from where it is used; a variable standing alone is
+    // taken to be an integer.
+    pub fn types(&self) -> Result<(Vec<ValueType>, ValueType), Issue> {
+        let mut types: Vec<Option<ValueType>> = vec![None; self.variables.len()];
+        for (index, gate) in self.circuit.gates.iter().enumerate() {
+            let expected = gate.operation.operand_type();
+            for wire in &gate.inputs {
+                let Wire::Input(variable) = *wire else {
+                    continue;
+                };
+                match types[variable] {
+                    Some(value_type) if value_type != expected => {
+                        return Err(Issue::new(
+                            IssueKind::TypeMismatch,
+                            Some(index),
+                            format!(
+                                "Variable {} is used as both a boolean and an integer",
+                                self.variables[variable]
+                            ),
+                        ))
+                    }
+                    _ => types[variable] = Some(expected),
+                }
+            }
+        }
+        let types: Vec<ValueType> = types
+            .into_iter()
+            .map(|t| t.unwrap_or(ValueType::Integer))
+            .collect();
+        let output = match self.circuit.outputs[0] {
+            Wire::Input(variable) => types[variable],
+            Wire::Gate(index) => self.circuit.gates[index].operation.value_type(),
+        };
+        Ok((types, output))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Variable(String),
+    Operator(char),
+    Open,
+    Close,
+    End,
+}
+
+// Parse e.g. "(a - b) * c" or "x & !y". Types aren't checked here; the circuit is
+// validated against its inputs like any other before it runs.
+pub fn parse(source: &str) -> Result<Expression, ExpressionError> {
+    if source.len() > MAX_EXPRESSION_LENGTH {
+        return Err(ExpressionError {
+            offset: MAX_EXPRESSION_LENGTH,
+            message: format!("Expression is longer than {} bytes", MAX_EXPRESSION_LENGTH),
+        });
+    }
+
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        position: 0,
+        depth: 0,
+        circuit: Circuit::default(),
+        variables: Vec::new(),
+    };
+    let output = parser.binary(0)?;
+    parser.expect(Token::End, "Expected an operator or end of expression")?;
+
+    parser.circuit.outputs.push(output);
+    Ok(Expression {
+        circuit: parser.circuit,
+        variables: parser.variables,
+    })
+}
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, ExpressionError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '|' | '^' | '&' | '+' | '-' | '*' | '!' => Token::Operator(c),
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut name = c.to_string();
+                while let Some(&(_, next)) = chars.peek() {
+                    if !(next.is_ascii_alphanumeric() || next == '_') {
+                        break;
+                    }
+                    name.push(next);
+                    chars.next();
+                }
+                Token::Variable(name)
+            }
+            _ => {
+                return Err(ExpressionError {
+                    offset,
+                    message: format!("Unexpected character '{}'", c),
+                })
+            }
+        };
+        tokens.push((offset, token));
+    }
+    tokens.push((source.len(), Token::End));
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+    depth: usize,
+    circuit: Circuit,
+    variables: Vec<String>,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position].1
+    }
+
+    fn error(&self, message: impl Into<String>) -> ExpressionError {
+        ExpressionError {
+            offset: self.tokens[self.position].0,
+            message: message.into(),
+        }
+    }
+
+    fn expect(&mut self, token: Token, message: &str) -> Result<(), ExpressionError> {
+        if *self.peek() != token {
+            return Err(self.error(message));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn gate(&mut self, operation: Operation, inputs: Vec<Wire>) -> Wire {
+        self.circuit.gates.push(Gate { operation, inputs });
+        Wire::Gate(self.circuit.gates.len() - 1)
+    }
+
+    fn nest(&mut self) -> Result<(), ExpressionError> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING {
+            return Err(self.error(format!("Expression is nested more than {} deep", MAX_NESTING)));
+        }
+        Ok(())
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Wire, ExpressionError> {
+        let Some(operators) = LEVELS.get(level) else {
+            return self.unary();
+        };
+
+        let mut left = self.binary(level + 1)?;
+        loop {
+            let Token::Operator(symbol) = *self.peek() else {
+                return Ok(left);
+            };
+            let Some(&(_, operation)) = operators.iter().find(|(c, _)| *c == symbol) else {
+                return Ok(left);
+            };
+            self.position += 1;
+            let right = self.binary(level + 1)?;
+            left = self.gate(operation, vec![left, right]);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Wire, ExpressionError> {
+        if *self.peek() == Token::Operator('!') {
+            self.position += 1;
+            self.nest()?;
+            let operand = self.unary()?;
+            self.depth -= 1;
+            return Ok(self.gate(Operation::Not, vec![operand]));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Wire, ExpressionError> {
+        match self.peek().clone() {
+            Token::Variable(name) => {
+                self.position += 1;
+                let index = match self.variables.iter().position(|variable| *variable == name) {
+                    Some(index) => index,
+                    None => {
+                        self.variables.push(name);
+                        self.variables.len() - 1
+                    }
+                };
+                Ok(Wire::Input(index))
+            }
+            Token::Open => {
+                self.position += 1;
+                self.nest()?;
+                let inner = self.binary(0)?;
+                self.expect(Token::Close, "Expected ')'")?;
+                self.depth -= 1;
+                Ok(inner)
+            }
+            _ => Err(self.error("Expected a variable or '('")),
+        }
+    }
+}
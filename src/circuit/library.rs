@@ -0,0 +1,248 @@
+use anyhow::{anyhow, Result};
+
+use super::{Circuit, Gate, Operation, Wire};
+use crate::crypto::vector::sorting_network;
+
+// Widest operand a library circuit takes, in bits
+pub const MAX_WIDTH: usize = 64;
+
+// A standard boolean circuit the server can build by name. Multi-bit values are lists of
+// encrypted booleans, least significant bit first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LibraryCircuit {
+    pub name: &'static str,
+    pub description: &'static str,
+}
+
+pub const CIRCUITS: [LibraryCircuit; 5] = [
+    LibraryCircuit {
+        name: "ripple_adder",
+        description: "Sum of two width-bit values a and b. Inputs: a then b. \
+                      Outputs: width sum bits, then the carry out.",
+    },
+    LibraryCircuit {
+        name: "comparator",
+        description: "Unsigned comparison of two width-bit values a and b. Inputs: a then b. \
+                      Outputs: a > b, then a == b.",
+    },
+    LibraryCircuit {
+        name: "max",
+        description: "Largest of count width-bit values. Inputs: each value in turn. \
+                      Outputs: width bits of the maximum.",
+    },
+    LibraryCircuit {
+        name: "parity",
+        description: "XOR of count bits, as a balanced tree. Output: 1 when an odd number are set.",
+    },
+    LibraryCircuit {
+        name: "majority",
+        description: "Majority vote of an odd count of bits. Output: 1 when more than half are set.",
+    },
+];
+
+// Build a library circuit. `width` is the bit width of each multi-bit value and `count`
+// the number of values or bits, depending on the circuit.
+pub fn build(name: &str, width: usize, count: usize) -> Result<Circuit> {
+    let mut builder = Builder::default();
+    let outputs = match name {
+        "ripple_adder" => {
+            check_width(width)?;
+            let (a, b) = (builder.value(width), builder.value(width));
+            let (mut sum, carry) = builder.add(&a, &b);
+            sum.push(carry);
+            sum
+        }
+        "comparator" => {
+            check_width(width)?;
+            let (a, b) = (builder.value(width), builder.value(width));
+            let (greater, equal) = builder.compare(&a, &b);
+            vec![greater, equal]
+        }
+        "max" => {
+            check_width(width)?;
+            check_count(count)?;
+            let values: Vec<Vec<Wire>> = (0..count).map(|_| builder.value(width)).collect();
+            builder.max(values)
+        }
+        "parity" => {
+            check_count(count)?;
+            let bits = builder.value(count);
+            vec![builder.reduce(bits, Operation::Xor)]
+        }
+        "majority" => {
+            check_count(count)?;
+            if count.is_multiple_of(2) {
+                return Err(anyhow!("majority needs an odd count, got {}", count));
+            }
+            let bits = builder.value(count);
+            vec![builder.majority(bits)]
+        }
+        _ => return Err(anyhow!("Unknown library circuit '{}'", name)),
+    };
+
+    Ok(Circuit {
+        gates: builder.gates,
+        outputs,
+    })
+}
+
+// Number of inputs the circuit built with these parameters reads
+pub fn input_count(name: &str, width: usize, count: usize) -> usize {
+    match name {
+        "ripple_adder" | "comparator" => 2 * width,
+        "max" => count * width,
+        _ => count,
+    }
+}
+
+fn check_width(width: usize) -> Result<()> {
+    if width == 0 || width > MAX_WIDTH {
+        return Err(anyhow!("Width must be between 1 and {} bits, got {}", MAX_WIDTH, width));
+    }
+    Ok(())
+}
+
+fn check_count(count: usize) -> Result<()> {
+    if count == 0 {
+        return Err(anyhow!("Count must be at least 1"));
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct Builder {
+    gates: Vec<Gate>,
+    inputs: usize,
+}
+
+impl Builder {
+    // The next `bits` circuit inputs
+    fn value(&mut self, bits: usize) -> Vec<Wire> {
+        let wires = (self.inputs..self.inputs + bits).map(Wire::Input).collect();
+        self.inputs += bits;
+        wires
+    }
+
+    fn gate(&mut self, operation: Operation, inputs: Vec<Wire>) -> Wire {
+        self.gates.push(Gate { operation, inputs });
+        Wire::Gate(self.gates.len() - 1)
+    }
+
+    fn and(&mut self, a: Wire, b: Wire) -> Wire {
+        self.gate(Operation::And, vec![a, b])
+    }
+
+    fn or(&mut self, a: Wire, b: Wire) -> Wire {
+        self.gate(Operation::Or, vec![a, b])
+    }
+
+    fn xor(&mut self, a: Wire, b: Wire) -> Wire {
+        self.gate(Operation::Xor, vec![a, b])
+    }
+
+    fn not(&mut self, a: Wire) -> Wire {
+        self.gate(Operation::Not, vec![a])
+    }
+
+    // Chain of full adders; returns the sum bits and the carry out
+    fn add(&mut self, a: &[Wire], b: &[Wire]) -> (Vec<Wire>, Wire) {
+        let mut sum = Vec::with_capacity(a.len());
+        let mut carry: Option<Wire> = None;
+        for (&x, &y) in a.iter().zip(b) {
+            let half = self.xor(x, y);
+            let generated = self.and(x, y);
+            let (bit, next) = match carry {
+                // The first stage has no carry in, so it is a half adder
+                None => (half, generated),
+                Some(carry) => {
+                    let bit = self.xor(half, carry);
+                    let propagated = self.and(half, carry);
+                    (bit, self.or(generated, propagated))
+                }
+            };
+            sum.push(bit);
+            carry = Some(next);
+        }
+        (sum, carry.expect("width is at least 1"))
+    }
+
+    // Unsigned a > b and a == b, resolved from the least significant bit up so the
+    // most significant differing bit decides
+    fn compare(&mut self, a: &[Wire], b: &[Wire]) -> (Wire, Wire) {
+        let mut state: Option<(Wire, Wire)> = None;
+        for (&x, &y) in a.iter().zip(b) {
+            let not_y = self.not(y);
+            let bit_greater = self.and(x, not_y);
+            let differ = self.xor(x, y);
+            let bit_equal = self.not(differ);
+            state = Some(match state {
+                None => (bit_greater, bit_equal),
+                Some((greater, equal)) => {
+                    let carried = self.and(bit_equal, greater);
+                    (self.or(bit_greater, carried), self.and(bit_equal, equal))
+                }
+            });
+        }
+        state.expect("width is at least 1")
+    }
+
+    // select ? a : b, bit by bit
+    fn mux(&mut self, select: Wire, a: &[Wire], b: &[Wire]) -> Vec<Wire> {
+        let not_select = self.not(select);
+        a.iter()
+            .zip(b)
+            .map(|(&x, &y)| {
+                let from_a = self.and(select, x);
+                let from_b = self.and(not_select, y);
+                self.or(from_a, from_b)
+            })
+            .collect()
+    }
+
+    // Tournament of compare-and-select stages, log2(count) deep
+    fn max(&mut self, mut values: Vec<Vec<Wire>>) -> Vec<Wire> {
+        while values.len() > 1 {
+            let mut winners = Vec::with_capacity(values.len().div_ceil(2));
+            let mut entrants = values.into_iter();
+            while let Some(left) = entrants.next() {
+                match entrants.next() {
+                    Some(right) => {
+                        let (greater, _) = self.compare(&left, &right);
+                        winners.push(self.mux(greater, &left, &right));
+                    }
+                    None => winners.push(left),
+                }
+            }
+            values = winners;
+        }
+        values.pop().expect("count is at least 1")
+    }
+
+    // Balanced tree of one operation over every bit
+    fn reduce(&mut self, mut bits: Vec<Wire>, operation: Operation) -> Wire {
+        while bits.len() > 1 {
+            let mut reduced = Vec::with_capacity(bits.len().div_ceil(2));
+            let mut pairs = bits.into_iter();
+            while let Some(left) = pairs.next() {
+                match pairs.next() {
+                    Some(right) => reduced.push(self.gate(operation, vec![left, right])),
+                    None => reduced.push(left),
+                }
+            }
+            bits = reduced;
+        }
+        bits.pop().expect("count is at least 1")
+    }
+
+    // Sorting bits puts the majority value at the median. On bits a comparator is just
+    // AND for the low output and OR for the high one.
+    fn majority(&mut self, mut bits: Vec<Wire>) -> Wire {
+        for (i, j) in sorting_network(bits.len()) {
+            let low = self.and(bits[i], bits[j]);
+            let high = self.or(bits[i], bits[j]);
+            bits[i] = low;
+            bits[j] = high;
+        }
+        bits[bits.len() / 2]
+    }
+}
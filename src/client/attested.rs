@@ -0,0 +1,59 @@
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use anyhow::{anyhow, Result};
+use tonic::transport::{Certificate, Channel, ClientTlsConfig};
+
+use crate::api::fhe_service_client::FheServiceClient;
+use crate::api::{attestation_response, AttestationRequest};
+use crate::crypto::attestation::{verify_quote, SignatureVerifier, TeePlatform};
+use crate::service::fhe_service::MAX_MESSAGE_BYTES;
+
+// Connect over TLS to a server in an enclave or confidential VM, and refuse it unless it
+// proves it runs the expected build and holds the certificate's key. certificate is the
+// PEM the server was given as HERMETIC_FHE_TLS_CERT, trusted for this connection only, and
+// measurement the MRENCLAVE, SNP launch measurement or MRTD of the build. The quote must
+// also pass signature, which checks it against Intel's or AMD's collateral.
+pub async fn connect_attested(
+    address: &str,
+    certificate: &[u8],
+    measurement: &[u8],
+    signature: &dyn SignatureVerifier,
+) -> Result<FheServiceClient<Channel>> {
+    let tls = ClientTlsConfig::new().ca_certificate(Certificate::from_pem(certificate));
+    let endpoint = Channel::from_shared(address.to_string())
+        .and_then(|endpoint| endpoint.tls_config(tls))
+        .map_err(|e| anyhow!("Invalid address {}: {}", address, e))?;
+    let channel = endpoint
+        .connect()
+        .await
+        .map_err(|e| anyhow!("Connection to {} failed: {}", address, e))?;
+    // Match the server's default limits rather than tonic's 4 MiB, which a few exported
+    // ciphertexts already exceed
+    let mut service = FheServiceClient::new(channel)
+        .max_decoding_message_size(MAX_MESSAGE_BYTES)
+        .max_encoding_message_size(MAX_MESSAGE_BYTES);
+
+    let mut nonce = vec![0u8; 32];
+    OsRng.fill_bytes(&mut nonce);
+    let request = AttestationRequest { nonce: nonce.clone() };
+    let attestation = service
+        .get_attestation(request)
+        .await
+        .map_err(|status| anyhow!("Attestation failed: {}", status.message()))?
+        .into_inner();
+    let platform = match attestation.platform() {
+        attestation_response::Platform::Sgx => TeePlatform::Sgx,
+        attestation_response::Platform::SevSnp => TeePlatform::SevSnp,
+        attestation_response::Platform::Tdx => TeePlatform::Tdx,
+    };
+    verify_quote(
+        platform,
+        &attestation.quote,
+        certificate,
+        &nonce,
+        measurement,
+        signature,
+    )
+    .map_err(|e| anyhow!("Attestation failed: {}", e))?;
+    Ok(service)
+}
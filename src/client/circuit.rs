@@ -0,0 +1,334 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use thiserror::Error;
+
+use crate::api::{
+    circuit_wire, CiphertextType, CircuitEvaluationRequest, CircuitGate, CircuitWire, DeclaredInput,
+    ValidateCircuitRequest,
+};
+use crate::circuit::expression::{self, ExpressionError};
+use crate::circuit::{Circuit, Gate, InputSpec, Issue, Operation, ValueType, Wire};
+use crate::service::fhe_service::{operation_type, MAX_CIRCUIT_GATES};
+
+// Tells apart the signals of different builders, which can't be mixed
+static NEXT_BUILDER: AtomicU64 = AtomicU64::new(0);
+
+// A wire of one builder's circuit; Bool and Int say which type it carries
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Signal {
+    builder: u64,
+    wire: Wire,
+}
+
+// An encrypted boolean flowing through a circuit under construction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Bool(Signal);
+
+// An encrypted 8-bit integer flowing through a circuit under construction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Int(Signal);
+
+// Either kind of signal, for outputs
+pub trait Output {
+    fn signal(&self) -> (Signal, ValueType);
+}
+
+impl Output for Bool {
+    fn signal(&self) -> (Signal, ValueType) {
+        (self.0, ValueType::Boolean)
+    }
+}
+
+impl Output for Int {
+    fn signal(&self) -> (Signal, ValueType) {
+        (self.0, ValueType::Integer)
+    }
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum CircuitBuildError {
+    #[error("Input '{0}' is declared twice")]
+    DuplicateInput(String),
+    #[error("A signal from another circuit builder was used")]
+    ForeignSignal,
+    #[error("Circuit has {0} gates, the limit is {MAX_CIRCUIT_GATES}")]
+    TooManyGates(usize),
+    #[error("No ciphertext given for input '{0}'")]
+    MissingInput(String),
+    #[error("Invalid expression: {0}")]
+    Expression(#[from] ExpressionError),
+    // Whatever else the server's own validation would refuse
+    #[error(transparent)]
+    Invalid(#[from] Issue),
+}
+
+// Compile a Rust-like expression over named inputs to a BuiltCircuit, as in
+// `fhe!((a - b) * k)` or `fhe! { x & !y }`. Identifiers are the circuit's inputs, and the
+// operators are those of EvaluateOperation expressions. Evaluates to a Result, since the
+// expression is only checked once the program runs.
+#[macro_export]
+macro_rules! fhe {
+    ($($expression:tt)+) => {
+        $crate::client::circuit::BuiltCircuit::from_expression(stringify!($($expression)+))
+    };
+}
+
+// Builds the gate list of EvaluateCircuit from named inputs and typed operations, so a
+// circuit is assembled and checked in Rust rather than written out as wires by hand.
+// Booleans and integers are separate types, so a gate given the wrong kind of operand
+// doesn't compile; what can only be found once the circuit is whole is reported by build.
+pub struct CircuitBuilder {
+    id: u64,
+    inputs: Vec<(String, ValueType)>,
+    gates: Vec<Gate>,
+    outputs: Vec<Wire>,
+    output_types: Vec<ValueType>,
+    // The first mistake made while building, returned by build
+    error: Option<CircuitBuildError>,
+}
+
+impl CircuitBuilder {
+    pub fn new() -> Self {
+        Self {
+            id: NEXT_BUILDER.fetch_add(1, Ordering::Relaxed),
+            inputs: Vec::new(),
+            gates: Vec::new(),
+            outputs: Vec::new(),
+            output_types: Vec::new(),
+            error: None,
+        }
+    }
+
+    pub fn input_bool(&mut self, name: &str) -> Bool {
+        Bool(self.input(name, ValueType::Boolean))
+    }
+
+    pub fn input_int(&mut self, name: &str) -> Int {
+        Int(self.input(name, ValueType::Integer))
+    }
+
+    pub fn and(&mut self, a: Bool, b: Bool) -> Bool {
+        Bool(self.gate(Operation::And, &[a.0, b.0]))
+    }
+
+    pub fn or(&mut self, a: Bool, b: Bool) -> Bool {
+        Bool(self.gate(Operation::Or, &[a.0, b.0]))
+    }
+
+    pub fn xor(&mut self, a: Bool, b: Bool) -> Bool {
+        Bool(self.gate(Operation::Xor, &[a.0, b.0]))
+    }
+
+    pub fn not(&mut self, a: Bool) -> Bool {
+        Bool(self.gate(Operation::Not, &[a.0]))
+    }
+
+    // Integer arithmetic wraps modulo 256, as in EvaluateOperation
+    pub fn add(&mut self, a: Int, b: Int) -> Int {
+        Int(self.gate(Operation::Add, &[a.0, b.0]))
+    }
+
+    pub fn subtract(&mut self, a: Int, b: Int) -> Int {
+        Int(self.gate(Operation::Subtract, &[a.0, b.0]))
+    }
+
+    pub fn multiply(&mut self, a: Int, b: Int) -> Int {
+        Int(self.gate(Operation::Multiply, &[a.0, b.0]))
+    }
+
+    pub fn abs_diff(&mut self, a: Int, b: Int) -> Int {
+        Int(self.gate(Operation::AbsDiff, &[a.0, b.0]))
+    }
+
+    pub fn saturating_add(&mut self, a: Int, b: Int) -> Int {
+        Int(self.gate(Operation::SaturatingAdd, &[a.0, b.0]))
+    }
+
+    pub fn saturating_sub(&mut self, a: Int, b: Int) -> Int {
+        Int(self.gate(Operation::SaturatingSub, &[a.0, b.0]))
+    }
+
+    pub fn is_zero(&mut self, a: Int) -> Bool {
+        Bool(self.gate(Operation::IsZero, &[a.0]))
+    }
+
+    pub fn is_non_zero(&mut self, a: Int) -> Bool {
+        Bool(self.gate(Operation::IsNonZero, &[a.0]))
+    }
+
+    // Make a signal an output of the circuit; outputs come back in the order added
+    pub fn output(&mut self, signal: impl Output) -> &mut Self {
+        let (signal, value_type) = signal.signal();
+        let wire = self.wire(signal);
+        self.outputs.push(wire);
+        self.output_types.push(value_type);
+        self
+    }
+
+    // The finished circuit, checked as the server would check it
+    pub fn build(self) -> Result<BuiltCircuit, CircuitBuildError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        let circuit = Circuit {
+            gates: self.gates,
+            outputs: self.outputs,
+        };
+        BuiltCircuit::new(self.inputs, circuit, self.output_types)
+    }
+
+    fn input(&mut self, name: &str, value_type: ValueType) -> Signal {
+        if self.inputs.iter().any(|(input, _)| input == name) {
+            self.fail(CircuitBuildError::DuplicateInput(name.to_string()));
+        }
+        self.inputs.push((name.to_string(), value_type));
+        Signal {
+            builder: self.id,
+            wire: Wire::Input(self.inputs.len() - 1),
+        }
+    }
+
+    fn gate(&mut self, operation: Operation, operands: &[Signal]) -> Signal {
+        let inputs = operands.iter().map(|operand| self.wire(*operand)).collect();
+        self.gates.push(Gate { operation, inputs });
+        Signal {
+            builder: self.id,
+            wire: Wire::Gate(self.gates.len() - 1),
+        }
+    }
+
+    fn wire(&mut self, signal: Signal) -> Wire {
+        if signal.builder != self.id {
+            self.fail(CircuitBuildError::ForeignSignal);
+        }
+        signal.wire
+    }
+
+    fn fail(&mut self, error: CircuitBuildError) {
+        self.error.get_or_insert(error);
+    }
+}
+
+impl Default for CircuitBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// A checked circuit in wire format, with the names its inputs were declared under
+#[derive(Clone, Debug)]
+pub struct BuiltCircuit {
+    inputs: Vec<(String, ValueType)>,
+    pub gates: Vec<CircuitGate>,
+    pub outputs: Vec<CircuitWire>,
+    output_types: Vec<ValueType>,
+}
+
+impl BuiltCircuit {
+    fn new(
+        inputs: Vec<(String, ValueType)>,
+        circuit: Circuit,
+        output_types: Vec<ValueType>,
+    ) -> Result<Self, CircuitBuildError> {
+        if circuit.gates.len() > MAX_CIRCUIT_GATES {
+            return Err(CircuitBuildError::TooManyGates(circuit.gates.len()));
+        }
+        let specs: Vec<Option<InputSpec>> = inputs
+            .iter()
+            .map(|(_, value_type)| Some(InputSpec::of(*value_type)))
+            .collect();
+        if let Some(issue) = circuit.check(&specs).issues.into_iter().next() {
+            return Err(CircuitBuildError::Invalid(issue));
+        }
+        Ok(Self {
+            inputs,
+            gates: circuit.gates.iter().map(proto_gate).collect(),
+            outputs: circuit.outputs.iter().map(proto_wire).collect(),
+            output_types,
+        })
+    }
+
+    // Compile an infix expression in the syntax EvaluateOperation accepts, usually through
+    // the fhe! macro. Each variable is an input, named as written and typed by the
+    // operators applied to it.
+    pub fn from_expression(source: &str) -> Result<Self, CircuitBuildError> {
+        let expression = expression::parse(source)?;
+        let (input_types, output_type) = expression.types()?;
+        let inputs = expression.variables.into_iter().zip(input_types).collect();
+        Self::new(inputs, expression.circuit, vec![output_type])
+    }
+
+    // Input names, in the order their ciphertext IDs are sent
+    pub fn input_names(&self) -> Vec<&str> {
+        self.inputs.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    // Whether each output is a boolean, rather than an integer, in output order
+    pub fn outputs_boolean(&self) -> Vec<bool> {
+        self.output_types
+            .iter()
+            .map(|value_type| *value_type == ValueType::Boolean)
+            .collect()
+    }
+
+    // An EvaluateCircuit request with each input bound to a stored ciphertext by name
+    pub fn request(
+        &self,
+        server_key_id: &str,
+        input_ids: &HashMap<&str, &str>,
+    ) -> Result<CircuitEvaluationRequest, CircuitBuildError> {
+        let input_ids = self
+            .inputs
+            .iter()
+            .map(|(name, _)| match input_ids.get(name.as_str()) {
+                Some(id) => Ok(id.to_string()),
+                None => Err(CircuitBuildError::MissingInput(name.clone())),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(CircuitEvaluationRequest {
+            server_key_id: server_key_id.to_string(),
+            input_ids,
+            gates: self.gates.clone(),
+            outputs: self.outputs.clone(),
+            ..Default::default()
+        })
+    }
+
+    // A ValidateCircuit request declaring the inputs' types, to have the server check the
+    // circuit and estimate its depth before anything is encrypted
+    pub fn validation_request(&self) -> ValidateCircuitRequest {
+        let declared_inputs = self
+            .inputs
+            .iter()
+            .map(|(_, value_type)| DeclaredInput {
+                ciphertext_type: match value_type {
+                    ValueType::Boolean => CiphertextType::Boolean,
+                    ValueType::Integer => CiphertextType::Integer,
+                } as i32,
+                num_bits: 0,
+            })
+            .collect();
+        ValidateCircuitRequest {
+            declared_inputs,
+            gates: self.gates.clone(),
+            outputs: self.outputs.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+fn proto_gate(gate: &Gate) -> CircuitGate {
+    CircuitGate {
+        operation: operation_type(gate.operation) as i32,
+        operands: gate.inputs.iter().map(proto_wire).collect(),
+    }
+}
+
+fn proto_wire(wire: &Wire) -> CircuitWire {
+    let source = match *wire {
+        Wire::Input(index) => circuit_wire::Source::Input(index as u32),
+        Wire::Gate(index) => circuit_wire::Source::Gate(index as u32),
+    };
+    CircuitWire { source: Some(source) }
+}
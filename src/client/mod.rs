@@ -0,0 +1,90 @@
+use anyhow::{anyhow, Result};
+use tfhe::prelude::{FheDecrypt, FheTryEncrypt};
+use tfhe::{ClientKey, ConfigBuilder, FheBool, FheUint8, ServerKey};
+use zeroize::Zeroizing;
+
+use crate::crypto::canonical::{self, Canonical};
+use crate::crypto::fingerprint::verify_fingerprint;
+use crate::crypto::parameter_config;
+
+// Typed construction of EvaluateCircuit requests, which are API types
+#[cfg(feature = "server")]
+pub mod circuit;
+// Connecting to a server only once its attestation quote checks out
+#[cfg(feature = "server")]
+pub mod attested;
+
+// Client-side half of the protocol: the client key stays in this process, and only
+// ciphertexts and the server key are handed to whoever performs the evaluation
+pub struct FheClient {
+    client_key: ClientKey,
+}
+
+impl FheClient {
+    pub fn new(client_key: ClientKey) -> Self {
+        Self { client_key }
+    }
+
+    // Generate a fresh key pair, returning the server key to send to the evaluator
+    pub fn generate() -> (Self, ServerKey) {
+        let client_key = ClientKey::generate(ConfigBuilder::default());
+        let server_key = ServerKey::new(&client_key);
+        (Self::new(client_key), server_key)
+    }
+
+    // A fresh client key under one of the parameter sets GenerateKeys accepts. Unlike
+    // generate it leaves out the server key, which takes far longer to build; server_key
+    // derives one when needed.
+    pub fn with_parameters(parameter_set: &str) -> Result<Self> {
+        Ok(Self::new(ClientKey::generate(parameter_config(parameter_set)?)))
+    }
+
+    // Restore a client saved with to_bytes
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        Ok(Self::new(canonical::decode(bytes)?))
+    }
+
+    // The serialized client key, for keeping in local storage. It is the secret half of the
+    // pair, so the buffer is wiped on drop.
+    pub fn to_bytes(&self) -> Result<Zeroizing<Vec<u8>>> {
+        Ok(Zeroizing::new(canonical::encode(&self.client_key)?))
+    }
+
+    pub fn client_key(&self) -> &ClientKey {
+        &self.client_key
+    }
+
+    pub fn server_key(&self) -> ServerKey {
+        ServerKey::new(&self.client_key)
+    }
+
+    pub fn encrypt_boolean(&self, value: bool) -> Result<FheBool> {
+        FheBool::try_encrypt(value, &self.client_key)
+            .map_err(|e| anyhow!("Encryption failed: {}", e))
+    }
+
+    pub fn encrypt_integer(&self, value: u8) -> Result<FheUint8> {
+        FheUint8::try_encrypt(value, &self.client_key)
+            .map_err(|e| anyhow!("Encryption failed: {}", e))
+    }
+
+    pub fn decrypt_boolean(&self, ciphertext: &FheBool) -> bool {
+        ciphertext.decrypt(&self.client_key)
+    }
+
+    pub fn decrypt_integer(&self, ciphertext: &FheUint8) -> u8 {
+        <FheUint8 as FheDecrypt<u8>>::decrypt(ciphertext, &self.client_key)
+    }
+}
+
+// Serialize a ciphertext in the form ImportCiphertext expects: canonical bytes plus their
+// fingerprint
+pub fn export_ciphertext<T: Canonical>(ciphertext: &T) -> Result<(Vec<u8>, String)> {
+    canonical::encode_with_fingerprint(ciphertext)
+}
+
+// Verify and deserialize bytes produced by ExportCiphertext
+pub fn import_ciphertext<T: Canonical>(bytes: &[u8], fingerprint: &str) -> Result<T> {
+    verify_fingerprint(bytes, fingerprint)?;
+    canonical::decode(bytes)
+}
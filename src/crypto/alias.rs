@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+const MAX_ALIAS_LENGTH: usize = 64;
+
+// A name a tenant gives a key pair, so configuration can refer to `prod-analytics-key`
+// rather than IDs that change whenever the keys are regenerated
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyAlias {
+    pub tenant: String,
+    pub alias: String,
+    pub client_key_id: String,
+    pub server_key_id: String,
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum AliasError {
+    #[error("Alias '{0}' already names another key pair")]
+    Taken(String),
+    #[error(
+        "Alias '{0}' must be 1 to 64 letters, digits, '-', '_', '.' or '/', start with a \
+         letter or digit, and not be a UUID"
+    )]
+    Invalid(String),
+    #[error("Key {0} not found")]
+    KeyNotFound(String),
+    #[error("{0}")]
+    Storage(String),
+}
+
+// Aliases of every tenant. Each is unique within its tenant, and different tenants may
+// use the same one for different pairs.
+#[derive(Default)]
+pub struct AliasTable {
+    aliases: RwLock<BTreeMap<(String, String), KeyAlias>>,
+}
+
+impl AliasTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Point the alias at the pair. An alias naming a different pair is only moved if
+    // replace is set; the alias it replaced is returned.
+    pub fn set(&self, alias: KeyAlias, replace: bool) -> Result<Option<KeyAlias>, AliasError> {
+        check_alias(&alias.alias)?;
+        let mut aliases = self.aliases.write().unwrap();
+        let key = (alias.tenant.clone(), alias.alias.clone());
+        if let Some(existing) = aliases.get(&key) {
+            if !replace && existing.server_key_id != alias.server_key_id {
+                return Err(AliasError::Taken(alias.alias));
+            }
+        }
+        Ok(aliases.insert(key, alias))
+    }
+
+    pub fn get(&self, tenant: &str, alias: &str) -> Option<KeyAlias> {
+        let key = (tenant.to_string(), alias.to_string());
+        self.aliases.read().unwrap().get(&key).cloned()
+    }
+
+    pub fn remove(&self, tenant: &str, alias: &str) -> Option<KeyAlias> {
+        let key = (tenant.to_string(), alias.to_string());
+        self.aliases.write().unwrap().remove(&key)
+    }
+
+    // A tenant's aliases, in alias order
+    pub fn of_tenant(&self, tenant: &str) -> Vec<KeyAlias> {
+        self.aliases
+            .read()
+            .unwrap()
+            .values()
+            .filter(|alias| alias.tenant == tenant)
+            .cloned()
+            .collect()
+    }
+
+    pub fn all(&self) -> Vec<KeyAlias> {
+        self.aliases.read().unwrap().values().cloned().collect()
+    }
+
+    // Replace every alias, as loaded from the key directory
+    pub fn restore(&self, restored: Vec<KeyAlias>) {
+        let mut aliases = self.aliases.write().unwrap();
+        aliases.clear();
+        for alias in restored {
+            aliases.insert((alias.tenant.clone(), alias.alias.clone()), alias);
+        }
+    }
+}
+
+// Aliases share fields with key IDs, which are UUIDs, so an alias may never look like one
+pub fn check_alias(alias: &str) -> Result<(), AliasError> {
+    let valid = alias.len() <= MAX_ALIAS_LENGTH
+        && alias.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && alias
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/'))
+        && Uuid::parse_str(alias).is_err();
+    match valid {
+        true => Ok(()),
+        false => Err(AliasError::Invalid(alias.to_string())),
+    }
+}
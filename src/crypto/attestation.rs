@@ -0,0 +1,152 @@
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::crypto::fingerprint::to_hex;
+
+// Most nonce bytes a client may send; the nonce is hashed into the report data either way
+pub const MAX_NONCE_BYTES: usize = 64;
+
+// Confidential-compute platforms a server can produce a quote on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TeePlatform {
+    // An SGX enclave under Gramine, whose quote carries MRENCLAVE
+    Sgx,
+    // An SEV-SNP guest, whose attestation report carries the launch measurement
+    SevSnp,
+    // A TDX guest, whose quote carries MRTD
+    Tdx,
+}
+
+impl TeePlatform {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "sgx" => Some(Self::Sgx),
+            "sev-snp" | "snp" => Some(Self::SevSnp),
+            "tdx" => Some(Self::Tdx),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Sgx => "sgx",
+            Self::SevSnp => "sev-snp",
+            Self::Tdx => "tdx",
+        }
+    }
+
+    // Where the build measurement and the 64 bytes of report data sit in a quote, and how
+    // long the measurement is. SGX and TDX quotes start with a 48-byte header before the
+    // report body; an SNP attestation report is the report body.
+    fn layout(self) -> Layout {
+        match self {
+            Self::Sgx => Layout {
+                measurement: 48 + 64,
+                measurement_len: 32,
+                report_data: 48 + 320,
+            },
+            Self::SevSnp => Layout {
+                measurement: 0x90,
+                measurement_len: 48,
+                report_data: 0x50,
+            },
+            Self::Tdx => Layout {
+                measurement: 48 + 136,
+                measurement_len: 48,
+                report_data: 48 + 520,
+            },
+        }
+    }
+}
+
+struct Layout {
+    measurement: usize,
+    measurement_len: usize,
+    report_data: usize,
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum AttestationError {
+    #[error("Quote is too short for {0}")]
+    Malformed(&'static str),
+    #[error("Quote is not bound to this certificate and nonce")]
+    NotBound,
+    #[error("Build measurement {actual} does not match the expected {expected}")]
+    MeasurementMismatch { expected: String, actual: String },
+    #[error("Quote signature was rejected: {0}")]
+    SignatureRejected(String),
+}
+
+// Checks a quote's signature back to Intel's or AMD's root of trust, e.g. by wrapping
+// Intel's DCAP Quote Verification Library or AMD's snpguest with the platform's collateral.
+// Ok only for a quote genuine hardware signed.
+pub trait SignatureVerifier {
+    fn verify(&self, platform: TeePlatform, quote: &[u8]) -> Result<(), String>;
+}
+
+impl<F> SignatureVerifier for F
+where
+    F: Fn(TeePlatform, &[u8]) -> Result<(), String>,
+{
+    fn verify(&self, platform: TeePlatform, quote: &[u8]) -> Result<(), String> {
+        self(platform, quote)
+    }
+}
+
+// The fields of a quote a client checks, besides its signature
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QuoteFields {
+    pub measurement: Vec<u8>,
+    pub report_data: [u8; 64],
+}
+
+// What a quote's report data holds: the SHA-256 of the server's TLS certificate, as PEM,
+// then the SHA-256 of the client's nonce, so a quote can't be replayed by another server
+// or to another client
+pub fn report_data(certificate: &[u8], nonce: &[u8]) -> [u8; 64] {
+    let mut data = [0u8; 64];
+    data[..32].copy_from_slice(&Sha256::digest(certificate));
+    data[32..].copy_from_slice(&Sha256::digest(nonce));
+    data
+}
+
+pub fn parse_quote(platform: TeePlatform, quote: &[u8]) -> Result<QuoteFields, AttestationError> {
+    let layout = platform.layout();
+    let measurement = quote
+        .get(layout.measurement..layout.measurement + layout.measurement_len)
+        .ok_or(AttestationError::Malformed(platform.name()))?;
+    let report_data = quote
+        .get(layout.report_data..layout.report_data + 64)
+        .ok_or(AttestationError::Malformed(platform.name()))?;
+    Ok(QuoteFields {
+        measurement: measurement.to_vec(),
+        report_data: report_data.try_into().expect("report data is 64 bytes"),
+    })
+}
+
+// Check that a quote was signed by genuine hardware, binds the certificate the client
+// connected with and the nonce it sent, and was taken in a build with the expected
+// measurement. The fields alone can be forged, so there is no way to skip the signature.
+pub fn verify_quote(
+    platform: TeePlatform,
+    quote: &[u8],
+    certificate: &[u8],
+    nonce: &[u8],
+    expected_measurement: &[u8],
+    signature: &dyn SignatureVerifier,
+) -> Result<QuoteFields, AttestationError> {
+    let fields = parse_quote(platform, quote)?;
+    signature
+        .verify(platform, quote)
+        .map_err(AttestationError::SignatureRejected)?;
+    if fields.report_data != report_data(certificate, nonce) {
+        return Err(AttestationError::NotBound);
+    }
+    if fields.measurement != expected_measurement {
+        return Err(AttestationError::MeasurementMismatch {
+            expected: to_hex(expected_measurement),
+            actual: to_hex(&fields.measurement),
+        });
+    }
+    Ok(fields)
+}
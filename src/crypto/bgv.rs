@@ -0,0 +1,206 @@
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use super::ring::{self, Modulus, Pair, Ring, DEGREE};
+pub use super::ring::{EvaluationKey, ReEncryptionKey, SecretKey, SLOTS};
+
+// BGV: exact arithmetic modulo a prime t on vectors of integers, packed into the slots
+// of one ciphertext, so a single addition or multiplication works on thousands of values
+// at once. Noise sits in multiples of t away from the message, and each multiplication
+// drops the top prime of the chain to keep it small.
+//
+// With t ≡ 1 mod 2N the plaintext ring splits into N slots, in two rows of N/2 that
+// rotations cycle separately. Both rows hold the same values, so the ciphertext behaves
+// as one cyclic vector of N/2 slots.
+
+// Plaintext modulus; slot arithmetic wraps modulo this prime
+pub const PLAINTEXT_MODULUS: u64 = 65537;
+// Slots hold integers in [-MAX_VALUE, MAX_VALUE], the centered residues modulo t
+pub const MAX_VALUE: i64 = (PLAINTEXT_MODULUS as i64 - 1) / 2;
+// Bits of each ciphertext prime, lowest first. Each prime above the first is consumed by
+// one multiplication. With the special prime they come to 218 bits, the most the HE
+// security standard allows at N = 8192 for 128-bit security.
+const MODULUS_BITS: [u32; 3] = [60, 50, 48];
+// Prime used only inside key switching, to divide the switching noise away
+const SPECIAL_BITS: u32 = 60;
+// Multiplications a fresh ciphertext can go through
+pub const MAX_DEPTH: usize = MODULUS_BITS.len() - 1;
+
+struct Context {
+    ring: Ring,
+    plaintext: Modulus,
+    // Position of each slot, and of its copy in the second row, among the evaluations of
+    // the plaintext NTT
+    slot_indices: Vec<(usize, usize)>,
+}
+
+fn context() -> &'static Context {
+    static CONTEXT: OnceLock<Context> = OnceLock::new();
+    CONTEXT.get_or_init(Context::new)
+}
+
+impl Context {
+    fn new() -> Self {
+        // Primes ≡ 1 mod t as well as 2N, so dividing by one leaves the message unchanged
+        let step = 2 * DEGREE as u64 * PLAINTEXT_MODULUS;
+        let bits: Vec<u32> = MODULUS_BITS.iter().copied().chain([SPECIAL_BITS]).collect();
+        // Evaluation k of the NTT is at ψ^(2k+1); slot j is at ψ^(5^j), its copy at ψ^(-5^j)
+        let slot_indices = ring::slot_exponents()
+            .into_iter()
+            .map(|exponent| ((exponent - 1) / 2, (2 * DEGREE - exponent - 1) / 2))
+            .collect();
+        Self {
+            ring: Ring::new(ring::find_primes(&bits, step), PLAINTEXT_MODULUS),
+            plaintext: Modulus::new(PLAINTEXT_MODULUS),
+            slot_indices,
+        }
+    }
+
+    // Coefficients of the polynomial mod t whose evaluations are the values
+    fn encode(&self, values: &[i64]) -> Vec<i64> {
+        let mut evaluations = vec![0u64; DEGREE];
+        for (value, (index, copy)) in values.iter().zip(&self.slot_indices) {
+            let residue = ring::reduce(*value, PLAINTEXT_MODULUS);
+            evaluations[*index] = residue;
+            evaluations[*copy] = residue;
+        }
+        self.plaintext.inverse(&mut evaluations);
+        evaluations.iter().map(|c| ring::center(*c, PLAINTEXT_MODULUS)).collect()
+    }
+
+    fn decode(&self, coefficients: &[i64], slots: usize) -> Vec<i64> {
+        let mut evaluations: Vec<u64> =
+            coefficients.iter().map(|c| ring::reduce(*c, PLAINTEXT_MODULUS)).collect();
+        self.plaintext.forward(&mut evaluations);
+        self.slot_indices[..slots]
+            .iter()
+            .map(|(index, _)| ring::center(evaluations[*index], PLAINTEXT_MODULUS))
+            .collect()
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BgvCiphertext {
+    // c0 + c1·s = m + t·e modulo the first level + 1 ciphertext primes
+    components: Pair,
+    // Slots holding values the client supplied; decryption returns this many
+    slots: usize,
+}
+
+impl BgvCiphertext {
+    // Multiplications still available
+    pub fn level(&self) -> usize {
+        self.components.0.len() - 1
+    }
+
+    pub fn slots(&self) -> usize {
+        self.slots
+    }
+
+    // The same value under fewer primes, which BGV allows as long as the noise fits
+    fn at_level(&self, level: usize) -> Pair {
+        let (c0, c1) = &self.components;
+        (c0[..=level].to_vec(), c1[..=level].to_vec())
+    }
+}
+
+pub fn generate_keys() -> (SecretKey, EvaluationKey) {
+    context().ring.generate_keys()
+}
+
+// Like ckks::generate_re_encryption_key, for BGV secret keys
+pub fn generate_re_encryption_key(source: &SecretKey, target: &SecretKey) -> ReEncryptionKey {
+    context().ring.re_encryption_key(source, target)
+}
+
+// Reject vectors that don't fit a ciphertext or values outside the slot range
+pub fn check_values(values: &[i64]) -> Result<()> {
+    if values.len() > SLOTS {
+        return Err(anyhow!("Vector has {} values, a ciphertext holds {}", values.len(), SLOTS));
+    }
+    if let Some(value) = values.iter().find(|v| v.abs() > MAX_VALUE) {
+        return Err(anyhow!("Value {} is outside [-{}, {}]", value, MAX_VALUE, MAX_VALUE));
+    }
+    Ok(())
+}
+
+// Reject a deserialized ciphertext that encryption and the operations here could never
+// have produced, before anything indexes into it
+pub fn check_ciphertext(ciphertext: &BgvCiphertext) -> Result<()> {
+    if !context().ring.is_well_formed(&ciphertext.components) {
+        return Err(anyhow!("Ciphertext components don't fit the ring"));
+    }
+    if ciphertext.slots > SLOTS {
+        return Err(anyhow!("Ciphertext claims {} slots, at most {} exist", ciphertext.slots, SLOTS));
+    }
+    Ok(())
+}
+
+pub fn encrypt(secret: &SecretKey, values: &[i64]) -> Result<BgvCiphertext> {
+    check_values(values)?;
+    let context = context();
+    Ok(BgvCiphertext {
+        components: context.ring.encrypt(secret, &context.encode(values)),
+        slots: values.len(),
+    })
+}
+
+pub fn decrypt(secret: &SecretKey, ciphertext: &BgvCiphertext) -> Vec<i64> {
+    let context = context();
+    let coefficients = context.ring.decrypt(secret, &ciphertext.components);
+    context.decode(&coefficients, ciphertext.slots)
+}
+
+// Slot-wise sum modulo t, at the lower of the operands' levels
+pub fn add(a: &BgvCiphertext, b: &BgvCiphertext) -> BgvCiphertext {
+    let ring = &context().ring;
+    let level = a.level().min(b.level());
+    let (mut c0, mut c1) = a.at_level(level);
+    let (b0, b1) = b.at_level(level);
+    ring.add(&mut c0, &b0);
+    ring.add(&mut c1, &b1);
+    BgvCiphertext {
+        components: (c0, c1),
+        slots: a.slots.max(b.slots),
+    }
+}
+
+// Slot-wise product modulo t, relinearized and switched one level down from the lower
+// operand
+pub fn multiply(key: &EvaluationKey, a: &BgvCiphertext, b: &BgvCiphertext) -> Result<BgvCiphertext> {
+    let level = a.level().min(b.level());
+    if level == 0 {
+        return Err(anyhow!(
+            "No multiplicative depth left; at most {} multiplications can be chained",
+            MAX_DEPTH
+        ));
+    }
+    let ring = &context().ring;
+
+    let (mut c0, mut c1) = ring.multiply_ciphertexts(key, &a.at_level(level), &b.at_level(level));
+    ring.divide_by_last(&mut c0, level);
+    ring.divide_by_last(&mut c1, level);
+    Ok(BgvCiphertext {
+        components: (c0, c1),
+        slots: a.slots.max(b.slots),
+    })
+}
+
+// Cyclic left rotation over all SLOTS slots, so a short vector shifts zeros in from the
+// right; negative steps rotate right
+pub fn rotate(key: &EvaluationKey, ciphertext: &BgvCiphertext, steps: i64) -> BgvCiphertext {
+    BgvCiphertext {
+        components: context().ring.rotate(key, &ciphertext.components, steps),
+        slots: ciphertext.slots,
+    }
+}
+
+// The same values under the key's target secret, at the same level
+pub fn re_encrypt(key: &ReEncryptionKey, ciphertext: &BgvCiphertext) -> BgvCiphertext {
+    BgvCiphertext {
+        components: context().ring.re_encrypt(key, &ciphertext.components),
+        slots: ciphertext.slots,
+    }
+}
@@ -0,0 +1,160 @@
+use anyhow::{anyhow, Result};
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use tfhe::{FheBool, ServerKey};
+
+use super::operations;
+use crate::cancellation::Cancellation;
+
+// Most bits a filter holds, each an encrypted boolean
+pub const MAX_BLOOM_BITS: usize = 8192;
+
+// Most positions an element is hashed to
+pub const MAX_BLOOM_HASHES: usize = 16;
+
+// Most elements one query looks up
+pub const MAX_BLOOM_QUERIES: usize = 1024;
+
+// Most filters one merge combines
+pub const MAX_MERGED_FILTERS: usize = 32;
+
+// Positions of an element in a filter of num_bits bits, by double hashing: with h1 and h2
+// the first two big-endian u64s of the element's SHA-256 digest, h2 made odd, position i is
+// (h1 + i * h2) mod num_bits in wrapping 64-bit arithmetic. Clients building a filter and
+// the server answering queries must agree on these, so they never change.
+pub fn positions(element: &[u8], num_bits: usize, num_hashes: usize) -> Vec<usize> {
+    let digest = Sha256::digest(element);
+    let h1 = u64::from_be_bytes(digest[..8].try_into().unwrap());
+    let h2 = u64::from_be_bytes(digest[8..16].try_into().unwrap()) | 1;
+    (0..num_hashes as u64)
+        .map(|i| (h1.wrapping_add(i.wrapping_mul(h2)) % num_bits as u64) as usize)
+        .collect()
+}
+
+// The plaintext bits of a filter holding the elements, for the client to encrypt one by one
+pub fn filter_bits<E: AsRef<[u8]>>(elements: &[E], num_bits: usize, num_hashes: usize) -> Vec<bool> {
+    let mut bits = vec![false; num_bits];
+    for element in elements {
+        for position in positions(element.as_ref(), num_bits, num_hashes) {
+            bits[position] = true;
+        }
+    }
+    bits
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Merge {
+    // Holds every element of any filter, exactly as if built from all of them
+    Union,
+    // Holds every element of all filters, with more false positives than a filter built
+    // from the intersection itself
+    Intersection,
+}
+
+// A Bloom filter whose bits are encrypted, so the server can answer membership queries
+// without learning the set or the answers. Lookups may give false positives, never false
+// negatives.
+#[derive(Clone)]
+pub struct EncryptedBloomFilter {
+    bits: Vec<FheBool>,
+    num_hashes: usize,
+}
+
+impl EncryptedBloomFilter {
+    pub fn new(bits: Vec<FheBool>, num_hashes: usize) -> Result<Self> {
+        if bits.is_empty() || bits.len() > MAX_BLOOM_BITS {
+            return Err(anyhow!(
+                "A filter has 1 to {} bits, not {}",
+                MAX_BLOOM_BITS,
+                bits.len()
+            ));
+        }
+        if num_hashes == 0 || num_hashes > MAX_BLOOM_HASHES {
+            return Err(anyhow!(
+                "A filter uses 1 to {} hashes, not {}",
+                MAX_BLOOM_HASHES,
+                num_hashes
+            ));
+        }
+        Ok(Self { bits, num_hashes })
+    }
+
+    pub fn num_bits(&self) -> usize {
+        self.bits.len()
+    }
+
+    pub fn num_hashes(&self) -> usize {
+        self.num_hashes
+    }
+
+    // Encrypted flag per element for whether the filter may hold it: the AND of the bits at
+    // its positions. Elements are looked up in parallel, each worker with its own copy of
+    // the server key.
+    pub fn contains<E: AsRef<[u8]> + Sync>(
+        &self,
+        server_key: &ServerKey,
+        elements: &[E],
+        cancellation: &Cancellation,
+    ) -> Result<Vec<FheBool>> {
+        elements
+            .par_iter()
+            .map_init(
+                || tfhe::set_server_key(server_key.clone()),
+                |_, element| -> Result<FheBool> {
+                    cancellation.check()?;
+                    let mut positions = positions(element.as_ref(), self.bits.len(), self.num_hashes);
+                    positions.sort_unstable();
+                    positions.dedup();
+                    let mut bits = positions.into_iter().map(|position| &self.bits[position]);
+                    let first = bits.next().ok_or_else(|| anyhow!("Element has no positions"))?;
+                    Ok(bits.fold(first.clone(), |all, bit| {
+                        operations::boolean_and(server_key, &all, bit)
+                    }))
+                },
+            )
+            .collect()
+    }
+
+    // The bitwise OR or AND of filters of the same size and hash count
+    pub fn merge(
+        server_key: &ServerKey,
+        filters: &[&EncryptedBloomFilter],
+        merge: Merge,
+        cancellation: &Cancellation,
+    ) -> Result<Self> {
+        let (first, rest) = filters
+            .split_first()
+            .ok_or_else(|| anyhow!("No filters to merge"))?;
+        if let Some(other) = rest
+            .iter()
+            .find(|other| (other.bits.len(), other.num_hashes) != (first.bits.len(), first.num_hashes))
+        {
+            return Err(anyhow!(
+                "Cannot merge a filter of {} bits and {} hashes with one of {} bits and {} hashes",
+                first.bits.len(),
+                first.num_hashes,
+                other.bits.len(),
+                other.num_hashes
+            ));
+        }
+
+        let bits = (0..first.bits.len())
+            .into_par_iter()
+            .map_init(
+                || tfhe::set_server_key(server_key.clone()),
+                |_, position| -> Result<FheBool> {
+                    cancellation.check()?;
+                    let merged = rest.iter().fold(first.bits[position].clone(), |merged, other| {
+                        let bit = &other.bits[position];
+                        match merge {
+                            Merge::Union => operations::boolean_or(server_key, &merged, bit),
+                            Merge::Intersection => operations::boolean_and(server_key, &merged, bit),
+                        }
+                    });
+                    Ok(merged)
+                },
+            )
+            .collect::<Result<Vec<_>>>()?;
+        Self::new(bits, first.num_hashes)
+    }
+}
@@ -0,0 +1,304 @@
+use anyhow::{anyhow, Result};
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tfhe::conformance::ParameterSetConformant;
+use tfhe::named::Named;
+use tfhe::safe_serialization::{safe_deserialize, safe_serialize};
+use tfhe::shortint::parameters::PARAM_MESSAGE_2_CARRY_2_KS_PBS;
+use tfhe::{ClientKey, FheBool, FheBoolConformanceParams, FheUint8, FheUint8ConformanceParams, ServerKey};
+
+use super::bgv::{self, BgvCiphertext};
+use super::ckks::{self, CkksCiphertext};
+use super::fingerprint::fingerprint_bytes;
+use super::matrix::EncryptedMatrix;
+use super::ring::DEGREE;
+use super::timestamp::EncryptedTimestamp;
+use super::versioning::TFHE_PARAMETERS;
+
+// The one encoding keys and ciphertexts cross the network and reach storage in: a header
+// naming the format version, the type and a hash of the parameters it was encrypted
+// under, then the payload. TFHE types go through tfhe-rs's safe serialization, which
+// bounds how much a payload may claim to hold; the crate's own types are bincode under the
+// same kind of bound. Nothing untrusted reaches a plain bincode::deserialize, so a few
+// bytes declaring a huge length can't exhaust memory. Every decoded value is then
+// checked: ciphertexts must conform to the parameters, and matrices and lattice
+// ciphertexts must have a shape the code that indexes into them expects.
+
+// Never the start of the plain bincode clients sent before, whose first word is a length
+const MAGIC: [u8; 4] = *b"\xffHFC";
+pub const FORMAT_VERSION: u8 = 1;
+const PARAMETERS_HASH_BYTES: usize = 8;
+const HEADER_BYTES: usize = MAGIC.len() + 2 + PARAMETERS_HASH_BYTES;
+
+// Largest payload each type may claim. A FheUint8 is tens of kilobytes; server keys run
+// to hundreds of megabytes.
+const MAX_CIPHERTEXT_BYTES: u64 = 1 << 20;
+const MAX_COMPOSITE_BYTES: u64 = 1 << 30;
+const MAX_KEY_BYTES: u64 = 1 << 30;
+
+// What the payload after a header is
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum TypeTag {
+    Boolean = 1,
+    Integer = 2,
+    Matrix = 3,
+    RealVector = 4,
+    IntegerBatch = 5,
+    Timestamp = 6,
+    ServerKey = 7,
+    ClientKey = 8,
+}
+
+impl TypeTag {
+    fn from_byte(byte: u8) -> Option<Self> {
+        [
+            Self::Boolean,
+            Self::Integer,
+            Self::Matrix,
+            Self::RealVector,
+            Self::IntegerBatch,
+            Self::Timestamp,
+            Self::ServerKey,
+            Self::ClientKey,
+        ]
+        .into_iter()
+        .find(|tag| *tag as u8 == byte)
+    }
+}
+
+// A key or ciphertext with a canonical encoding
+pub trait Canonical: Serialize + DeserializeOwned + Sized {
+    const TAG: TypeTag;
+    const TYPE_NAME: &'static str;
+    // Most bytes a payload of this type may claim to hold
+    const LIMIT: u64;
+
+    // Description of the parameters values of this type are encrypted under
+    fn parameters() -> String {
+        TFHE_PARAMETERS.to_string()
+    }
+
+    fn write_payload(&self, out: &mut Vec<u8>) -> Result<()> {
+        bincode_options(Self::LIMIT)
+            .serialize_into(out, self)
+            .map_err(|e| anyhow!("Serialization failed: {}", e))
+    }
+
+    fn read_payload(payload: &[u8]) -> Result<Self> {
+        read_bincode(payload, Self::LIMIT)
+    }
+
+    // Rejects a value that decodes but that nothing in this crate could have written
+    fn check(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub fn encode<T: Canonical>(value: &T) -> Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(HEADER_BYTES);
+    bytes.extend_from_slice(&MAGIC);
+    bytes.push(FORMAT_VERSION);
+    bytes.push(T::TAG as u8);
+    bytes.extend_from_slice(&parameters_hash(&T::parameters()));
+    value.write_payload(&mut bytes)?;
+    Ok(bytes)
+}
+
+// The encoding and its SHA-256 fingerprint
+pub fn encode_with_fingerprint<T: Canonical>(value: &T) -> Result<(Vec<u8>, String)> {
+    let bytes = encode(value)?;
+    let fingerprint = fingerprint_bytes(&bytes);
+    Ok((bytes, fingerprint))
+}
+
+// Bytes without a header are taken as the plain bincode of a release before this format,
+// read under the same limit. Either way the value is checked before it's returned.
+pub fn decode<T: Canonical>(bytes: &[u8]) -> Result<T> {
+    let invalid = |reason: String| anyhow!("Invalid {}: {}", T::TYPE_NAME, reason);
+    let value = match bytes.strip_prefix(&MAGIC) {
+        Some(header) => decode_canonical(header)?,
+        None => read_bincode(bytes, T::LIMIT).map_err(|e| invalid(e.to_string()))?,
+    };
+    value.check().map_err(|e| invalid(e.to_string()))?;
+    Ok(value)
+}
+
+// The value after the magic of a canonical encoding
+fn decode_canonical<T: Canonical>(header: &[u8]) -> Result<T> {
+    let invalid = |reason: String| anyhow!("Invalid {}: {}", T::TYPE_NAME, reason);
+    let [version, tag, rest @ ..] = header else {
+        return Err(invalid("truncated header".to_string()));
+    };
+    if *version != FORMAT_VERSION {
+        return Err(invalid(format!("unsupported format version {}", version)));
+    }
+    match TypeTag::from_byte(*tag) {
+        Some(tag) if tag == T::TAG => {}
+        Some(tag) => return Err(invalid(format!("the bytes hold a {:?}", tag))),
+        None => return Err(invalid(format!("unknown type tag {}", tag))),
+    }
+    if rest.len() < PARAMETERS_HASH_BYTES {
+        return Err(invalid("truncated header".to_string()));
+    }
+    let (hash, payload) = rest.split_at(PARAMETERS_HASH_BYTES);
+    if hash != parameters_hash(&T::parameters()) {
+        return Err(invalid("it was encrypted under other parameters".to_string()));
+    }
+    T::read_payload(payload).map_err(|e| invalid(e.to_string()))
+}
+
+// Whether the bytes start with a header, rather than being plain bincode from before
+pub fn is_canonical(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+fn parameters_hash(parameters: &str) -> [u8; PARAMETERS_HASH_BYTES] {
+    let digest = Sha256::digest(parameters.as_bytes());
+    let mut hash = [0; PARAMETERS_HASH_BYTES];
+    hash.copy_from_slice(&digest[..PARAMETERS_HASH_BYTES]);
+    hash
+}
+
+// The encoding of bincode::serialize, refusing to read past limit bytes
+fn bincode_options(limit: u64) -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(limit)
+}
+
+fn read_bincode<T: DeserializeOwned>(bytes: &[u8], limit: u64) -> Result<T> {
+    bincode_options(limit)
+        .deserialize(bytes)
+        .map_err(|e| anyhow!("{}", e))
+}
+
+fn write_safe<T: Serialize + Named>(value: &T, limit: u64, out: &mut Vec<u8>) -> Result<()> {
+    safe_serialize(value, out, limit).map_err(|e| anyhow!("Serialization failed: {}", e))
+}
+
+impl Canonical for FheBool {
+    const TAG: TypeTag = TypeTag::Boolean;
+    const TYPE_NAME: &'static str = "FheBool";
+    const LIMIT: u64 = MAX_CIPHERTEXT_BYTES;
+
+    fn write_payload(&self, out: &mut Vec<u8>) -> Result<()> {
+        write_safe(self, Self::LIMIT, out)
+    }
+
+    fn read_payload(payload: &[u8]) -> Result<Self> {
+        safe_deserialize(payload, Self::LIMIT).map_err(|e| anyhow!("{}", e))
+    }
+
+    fn check(&self) -> Result<()> {
+        let parameters = FheBoolConformanceParams::from(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+        match self.is_conformant(&parameters) {
+            true => Ok(()),
+            false => Err(anyhow!("it doesn't conform to the parameters")),
+        }
+    }
+}
+
+impl Canonical for FheUint8 {
+    const TAG: TypeTag = TypeTag::Integer;
+    const TYPE_NAME: &'static str = "FheUint8";
+    const LIMIT: u64 = MAX_CIPHERTEXT_BYTES;
+
+    fn write_payload(&self, out: &mut Vec<u8>) -> Result<()> {
+        write_safe(self, Self::LIMIT, out)
+    }
+
+    fn read_payload(payload: &[u8]) -> Result<Self> {
+        safe_deserialize(payload, Self::LIMIT).map_err(|e| anyhow!("{}", e))
+    }
+
+    fn check(&self) -> Result<()> {
+        let parameters = FheUint8ConformanceParams::from(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+        match self.is_conformant(&parameters) {
+            true => Ok(()),
+            false => Err(anyhow!("it doesn't conform to the parameters")),
+        }
+    }
+}
+
+impl Canonical for ServerKey {
+    const TAG: TypeTag = TypeTag::ServerKey;
+    const TYPE_NAME: &'static str = "server key";
+    const LIMIT: u64 = MAX_KEY_BYTES;
+
+    fn write_payload(&self, out: &mut Vec<u8>) -> Result<()> {
+        write_safe(self, Self::LIMIT, out)
+    }
+
+    fn read_payload(payload: &[u8]) -> Result<Self> {
+        safe_deserialize(payload, Self::LIMIT).map_err(|e| anyhow!("{}", e))
+    }
+}
+
+impl Canonical for ClientKey {
+    const TAG: TypeTag = TypeTag::ClientKey;
+    const TYPE_NAME: &'static str = "client key";
+    const LIMIT: u64 = MAX_KEY_BYTES;
+
+    fn write_payload(&self, out: &mut Vec<u8>) -> Result<()> {
+        write_safe(self, Self::LIMIT, out)
+    }
+
+    fn read_payload(payload: &[u8]) -> Result<Self> {
+        safe_deserialize(payload, Self::LIMIT).map_err(|e| anyhow!("{}", e))
+    }
+}
+
+impl Canonical for EncryptedMatrix {
+    const TAG: TypeTag = TypeTag::Matrix;
+    const TYPE_NAME: &'static str = "EncryptedMatrix";
+    const LIMIT: u64 = MAX_COMPOSITE_BYTES;
+
+    // The elements skip safe deserialization, so they're checked against the parameters
+    // here instead
+    fn check(&self) -> Result<()> {
+        self.check_shape()?;
+        let parameters = FheUint8ConformanceParams::from(PARAM_MESSAGE_2_CARRY_2_KS_PBS);
+        match self.elements().iter().position(|element| !element.is_conformant(&parameters)) {
+            Some(index) => Err(anyhow!("element {} doesn't conform to the parameters", index)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Canonical for EncryptedTimestamp {
+    const TAG: TypeTag = TypeTag::Timestamp;
+    const TYPE_NAME: &'static str = "EncryptedTimestamp";
+    const LIMIT: u64 = MAX_CIPHERTEXT_BYTES;
+}
+
+impl Canonical for CkksCiphertext {
+    const TAG: TypeTag = TypeTag::RealVector;
+    const TYPE_NAME: &'static str = "CkksCiphertext";
+    const LIMIT: u64 = MAX_COMPOSITE_BYTES;
+
+    fn parameters() -> String {
+        format!("CKKS degree {} scale 2^{} depth {}", DEGREE, ckks::LOG_SCALE, ckks::MAX_DEPTH)
+    }
+
+    fn check(&self) -> Result<()> {
+        ckks::check_ciphertext(self)
+    }
+}
+
+impl Canonical for BgvCiphertext {
+    const TAG: TypeTag = TypeTag::IntegerBatch;
+    const TYPE_NAME: &'static str = "BgvCiphertext";
+    const LIMIT: u64 = MAX_COMPOSITE_BYTES;
+
+    fn parameters() -> String {
+        format!("BGV degree {} plaintext modulus {} depth {}", DEGREE, bgv::PLAINTEXT_MODULUS, bgv::MAX_DEPTH)
+    }
+
+    fn check(&self) -> Result<()> {
+        bgv::check_ciphertext(self)
+    }
+}
@@ -0,0 +1,220 @@
+use std::f64::consts::PI;
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use super::ring::{self, Pair, Ring, DEGREE};
+pub use super::ring::{EvaluationKey, ReEncryptionKey, SecretKey, SLOTS};
+
+// CKKS: approximate arithmetic on vectors of reals, packed into the slots of one
+// ciphertext. Values are scaled up to fixed point and encoded so the slots are the
+// polynomial's evaluations; each multiplication divides the scale back down by dropping
+// the top prime of the chain.
+
+// Bits of each ciphertext prime, lowest first. The first has room for the result's
+// integer part; each of the others is consumed by one multiplication.
+const MODULUS_BITS: [u32; 3] = [60, 40, 40];
+// Prime used only inside key switching, to divide the switching noise away
+const SPECIAL_BITS: u32 = 60;
+// Values are encoded at this many bits of fixed-point precision
+pub const LOG_SCALE: i32 = 40;
+// Multiplications a fresh ciphertext can go through
+pub const MAX_DEPTH: usize = MODULUS_BITS.len() - 1;
+// Largest magnitude a slot may hold, on encryption or after any operation, before the
+// result wraps around the last prime
+pub const MAX_MAGNITUDE: f64 = (1u64 << 18) as f64;
+
+struct Context {
+    ring: Ring,
+    // cos(πt/N) for t < 2N
+    cosines: Vec<f64>,
+    // 5^j mod 2N: slot j holds the value at ψ^(5^j)
+    slot_exponents: Vec<usize>,
+}
+
+fn context() -> &'static Context {
+    static CONTEXT: OnceLock<Context> = OnceLock::new();
+    CONTEXT.get_or_init(Context::new)
+}
+
+impl Context {
+    fn new() -> Self {
+        let bits: Vec<u32> = MODULUS_BITS.iter().copied().chain([SPECIAL_BITS]).collect();
+        Self {
+            ring: Ring::new(ring::find_primes(&bits, 2 * DEGREE as u64), 1),
+            cosines: (0..2 * DEGREE).map(|t| (PI * t as f64 / DEGREE as f64).cos()).collect(),
+            slot_exponents: ring::slot_exponents(),
+        }
+    }
+
+    // The real polynomial whose evaluations at the slot roots are the values times scale:
+    // m_k = (2/N) Σ_j z_j cos(π e_j k / N) for real z
+    fn encode(&self, values: &[f64], scale: f64) -> Vec<i64> {
+        let modulus = 2 * DEGREE;
+        (0..DEGREE)
+            .map(|k| {
+                let sum: f64 = values
+                    .iter()
+                    .zip(&self.slot_exponents)
+                    .map(|(value, exponent)| value * self.cosines[exponent * k % modulus])
+                    .sum();
+                (sum * 2.0 / DEGREE as f64 * scale).round() as i64
+            })
+            .collect()
+    }
+
+    // Real parts of the first `slots` evaluations, divided by scale
+    fn decode(&self, coefficients: &[i64], scale: f64, slots: usize) -> Vec<f64> {
+        let modulus = 2 * DEGREE;
+        self.slot_exponents[..slots]
+            .iter()
+            .map(|exponent| {
+                let sum: f64 = coefficients
+                    .iter()
+                    .enumerate()
+                    .map(|(k, coefficient)| *coefficient as f64 * self.cosines[exponent * k % modulus])
+                    .sum();
+                sum / scale
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CkksCiphertext {
+    // c0 + c1·s ≈ scale·m modulo the first level + 1 ciphertext primes
+    components: Pair,
+    scale: f64,
+    // Slots holding values the client supplied; decryption returns this many
+    slots: usize,
+}
+
+impl CkksCiphertext {
+    // Multiplications still available
+    pub fn level(&self) -> usize {
+        self.components.0.len() - 1
+    }
+
+    pub fn slots(&self) -> usize {
+        self.slots
+    }
+
+    // The same value with its top limbs dropped, to line up with a lower-level operand
+    fn at_level(&self, level: usize) -> Pair {
+        let (c0, c1) = &self.components;
+        (c0[..=level].to_vec(), c1[..=level].to_vec())
+    }
+}
+
+pub fn generate_keys() -> (SecretKey, EvaluationKey) {
+    context().ring.generate_keys()
+}
+
+// Key for re_encrypt from one secret key to another; whoever holds it learns nothing
+// about either
+pub fn generate_re_encryption_key(source: &SecretKey, target: &SecretKey) -> ReEncryptionKey {
+    context().ring.re_encryption_key(source, target)
+}
+
+// Reject vectors that don't fit a ciphertext or whose values would overflow it
+pub fn check_values(values: &[f64]) -> Result<()> {
+    if values.len() > SLOTS {
+        return Err(anyhow!("Vector has {} values, a ciphertext holds {}", values.len(), SLOTS));
+    }
+    if let Some(value) = values.iter().find(|v| !v.is_finite() || v.abs() > MAX_MAGNITUDE) {
+        return Err(anyhow!("Value {} is not finite or exceeds {} in magnitude", value, MAX_MAGNITUDE));
+    }
+    Ok(())
+}
+
+// Reject a deserialized ciphertext that encryption and the operations here could never
+// have produced, before anything indexes into it
+pub fn check_ciphertext(ciphertext: &CkksCiphertext) -> Result<()> {
+    if !context().ring.is_well_formed(&ciphertext.components) {
+        return Err(anyhow!("Ciphertext components don't fit the ring"));
+    }
+    if ciphertext.slots > SLOTS {
+        return Err(anyhow!("Ciphertext claims {} slots, at most {} exist", ciphertext.slots, SLOTS));
+    }
+    if !ciphertext.scale.is_finite() || ciphertext.scale < 1.0 {
+        return Err(anyhow!("Ciphertext scale {} is not a valid scale", ciphertext.scale));
+    }
+    Ok(())
+}
+
+pub fn encrypt(secret: &SecretKey, values: &[f64]) -> Result<CkksCiphertext> {
+    check_values(values)?;
+    let context = context();
+    let scale = 2f64.powi(LOG_SCALE);
+
+    Ok(CkksCiphertext {
+        components: context.ring.encrypt(secret, &context.encode(values, scale)),
+        scale,
+        slots: values.len(),
+    })
+}
+
+pub fn decrypt(secret: &SecretKey, ciphertext: &CkksCiphertext) -> Vec<f64> {
+    let context = context();
+    let coefficients = context.ring.decrypt(secret, &ciphertext.components);
+    context.decode(&coefficients, ciphertext.scale, ciphertext.slots)
+}
+
+// Slot-wise sum. Operands at different levels meet at the lower one; their scales agree
+// to about one part in a million, below the precision of the scheme itself.
+pub fn add(a: &CkksCiphertext, b: &CkksCiphertext) -> CkksCiphertext {
+    let ring = &context().ring;
+    let level = a.level().min(b.level());
+    let (mut c0, mut c1) = a.at_level(level);
+    let (b0, b1) = b.at_level(level);
+    ring.add(&mut c0, &b0);
+    ring.add(&mut c1, &b1);
+    CkksCiphertext {
+        components: (c0, c1),
+        scale: a.scale,
+        slots: a.slots.max(b.slots),
+    }
+}
+
+// Slot-wise product, relinearized and rescaled, one level down from the lower operand
+pub fn multiply(key: &EvaluationKey, a: &CkksCiphertext, b: &CkksCiphertext) -> Result<CkksCiphertext> {
+    let level = a.level().min(b.level());
+    if level == 0 {
+        return Err(anyhow!(
+            "No multiplicative depth left; at most {} multiplications can be chained",
+            MAX_DEPTH
+        ));
+    }
+    let ring = &context().ring;
+
+    let (mut c0, mut c1) = ring.multiply_ciphertexts(key, &a.at_level(level), &b.at_level(level));
+    let last_q = ring.moduli[level].q as f64;
+    ring.divide_by_last(&mut c0, level);
+    ring.divide_by_last(&mut c1, level);
+    Ok(CkksCiphertext {
+        components: (c0, c1),
+        scale: a.scale * b.scale / last_q,
+        slots: a.slots.max(b.slots),
+    })
+}
+
+// Cyclic left rotation over all SLOTS slots, so a short vector shifts zeros in from the
+// right; negative steps rotate right
+pub fn rotate(key: &EvaluationKey, ciphertext: &CkksCiphertext, steps: i64) -> CkksCiphertext {
+    CkksCiphertext {
+        components: context().ring.rotate(key, &ciphertext.components, steps),
+        scale: ciphertext.scale,
+        slots: ciphertext.slots,
+    }
+}
+
+// The same values under the key's target secret, at the same level and scale. A
+// ciphertext under any secret but the key's source comes out as noise.
+pub fn re_encrypt(key: &ReEncryptionKey, ciphertext: &CkksCiphertext) -> CkksCiphertext {
+    CkksCiphertext {
+        components: context().ring.re_encrypt(key, &ciphertext.components),
+        scale: ciphertext.scale,
+        slots: ciphertext.slots,
+    }
+}
@@ -0,0 +1,63 @@
+use std::env;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+
+pub const DEFAULT_LEVEL: i32 = 3;
+
+// When stored ciphertexts count as cold, and how hard to compress them once they are
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompressionConfig {
+    pub level: i32,
+    pub cold_after: Duration,
+}
+
+impl CompressionConfig {
+    // HERMETIC_FHE_COMPRESS_COLD_AFTER_SECONDS turns compression on, with the zstd level in
+    // HERMETIC_FHE_COMPRESSION_LEVEL (3 by default); unset means ciphertexts stay as they are
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(seconds) = env::var("HERMETIC_FHE_COMPRESS_COLD_AFTER_SECONDS") else {
+            return Ok(None);
+        };
+        let seconds: u64 = seconds
+            .trim()
+            .parse()
+            .ok()
+            .filter(|seconds| *seconds > 0)
+            .ok_or_else(|| anyhow!("HERMETIC_FHE_COMPRESS_COLD_AFTER_SECONDS must be a positive integer"))?;
+        let level = match env::var("HERMETIC_FHE_COMPRESSION_LEVEL") {
+            Ok(level) => level
+                .trim()
+                .parse()
+                .ok()
+                .filter(|level| (1..=22).contains(level))
+                .ok_or_else(|| anyhow!("HERMETIC_FHE_COMPRESSION_LEVEL must be between 1 and 22"))?,
+            Err(_) => DEFAULT_LEVEL,
+        };
+        Ok(Some(Self {
+            level,
+            cold_after: Duration::from_secs(seconds),
+        }))
+    }
+}
+
+// zstd links a C library that isn't built for the browser, where nothing is stored anyway
+#[cfg(not(target_arch = "wasm32"))]
+pub fn compress(bytes: &[u8], level: i32) -> Result<Vec<u8>> {
+    zstd::encode_all(bytes, level).map_err(|e| anyhow!("Compression failed: {}", e))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn decompress(bytes: &[u8]) -> Result<Vec<u8>> {
+    zstd::decode_all(bytes).map_err(|e| anyhow!("Decompression failed: {}", e))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn compress(_bytes: &[u8], _level: i32) -> Result<Vec<u8>> {
+    Err(anyhow!("Compression is not available on wasm32"))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn decompress(_bytes: &[u8]) -> Result<Vec<u8>> {
+    Err(anyhow!("Compression is not available on wasm32"))
+}
@@ -0,0 +1,66 @@
+use std::env;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{anyhow, Result};
+use tfhe::core_crypto::commons::generators::DeterministicSeeder;
+use tfhe::core_crypto::prelude::ActivatedRandomGenerator;
+use tfhe::shortint::engine::ShortintEngine;
+use tfhe::Seed;
+use uuid::Uuid;
+
+// Randomness drawn from one seed instead of the OS, so a run can be replayed down to the
+// exact keys, ciphertexts and IDs. Draws are numbered in call order, so a replay has to
+// make the same calls in the same order. Anyone who knows the seed can rebuild every
+// key, so this is for tests and bug reports only.
+#[derive(Debug)]
+pub struct Determinism {
+    seed: u64,
+    draws: AtomicU64,
+}
+
+impl Determinism {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            draws: AtomicU64::new(0),
+        }
+    }
+
+    // HERMETIC_FHE_DETERMINISTIC_SEED turns the mode on; unset means real randomness
+    pub fn from_env() -> Result<Option<Self>> {
+        match env::var("HERMETIC_FHE_DETERMINISTIC_SEED") {
+            Ok(value) => value
+                .trim()
+                .parse()
+                .map(|seed| Some(Self::new(seed)))
+                .map_err(|_| anyhow!("HERMETIC_FHE_DETERMINISTIC_SEED must be a non-negative integer")),
+            Err(_) => Ok(None),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // How many draws have been made, to check a replay is in step with the original
+    pub fn draws(&self) -> u64 {
+        self.draws.load(Ordering::Relaxed)
+    }
+
+    fn next(&self) -> u128 {
+        let draw = self.draws.fetch_add(1, Ordering::Relaxed);
+        ((self.seed as u128) << 64) | draw as u128
+    }
+
+    // Reseed this thread's tfhe engine, which key generation and encryption draw their
+    // noise from, so whatever it does next is reproducible
+    pub fn reseed_thread(&self) {
+        let mut seeder = DeterministicSeeder::<ActivatedRandomGenerator>::new(Seed(self.next()));
+        ShortintEngine::replace_thread_local(ShortintEngine::new_from_seeder(&mut seeder));
+    }
+
+    // A UUID-shaped ID taken from the next draw
+    pub fn next_id(&self) -> String {
+        Uuid::from_u128(self.next()).to_string()
+    }
+}
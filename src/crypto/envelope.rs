@@ -0,0 +1,208 @@
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::{anyhow, Result};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+// 256-bit key-encryption key; wiped from memory when dropped
+pub struct MasterKey(Zeroizing<[u8; 32]>);
+
+impl MasterKey {
+    // Random master key that only lives for the lifetime of the process
+    pub fn generate() -> Self {
+        Self(random_key())
+    }
+
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(Zeroizing::new(bytes))
+    }
+
+    // Parse a 64-character hex string, as supplied through configuration
+    pub fn from_hex(hex: &str) -> Result<Self> {
+        let hex = hex.trim();
+        if hex.len() != 64 {
+            return Err(anyhow!("Master key must be 64 hex characters"));
+        }
+        // Checked up front: slicing multi-byte characters would panic, and from_str_radix
+        // would take a sign such as "+f"
+        if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(anyhow!("Master key is not valid hex"));
+        }
+
+        let mut bytes = Zeroizing::new([0u8; 32]);
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+                .map_err(|_| anyhow!("Master key is not valid hex"))?;
+        }
+        Ok(Self(bytes))
+    }
+
+    // HMAC-SHA256 signature, used to authenticate exported key bundles
+    pub fn sign(&self, data: &[u8]) -> Vec<u8> {
+        let mut signer = self.signer();
+        signer.update(data);
+        signer.finish()
+    }
+
+    pub fn verify_signature(&self, data: &[u8], signature: &[u8]) -> Result<()> {
+        let mut signer = self.signer();
+        signer.update(data);
+        signer.verify(signature)
+    }
+
+    // The same signature over data that arrives in pieces, such as a backup archive
+    pub fn signer(&self) -> Signer {
+        Signer(self.signing_mac())
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.0[..]))
+    }
+
+    // Signatures use a derived subkey so the encryption key is never used for MACs directly
+    fn signing_mac(&self) -> Hmac<Sha256> {
+        let mut derive = <Hmac<Sha256> as Mac>::new_from_slice(&self.0[..])
+            .expect("HMAC accepts any key length");
+        derive.update(b"hermetic-fhe key bundle signing v1");
+
+        let mut subkey = Zeroizing::new([0u8; 32]);
+        subkey.copy_from_slice(&derive.finalize().into_bytes());
+        <Hmac<Sha256> as Mac>::new_from_slice(&subkey[..]).expect("HMAC accepts any key length")
+    }
+}
+
+// Signature being computed over data fed to it piece by piece
+pub struct Signer(Hmac<Sha256>);
+
+impl Signer {
+    pub fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.0.finalize().into_bytes().to_vec()
+    }
+
+    pub fn verify(self, signature: &[u8]) -> Result<()> {
+        self.0
+            .verify_slice(signature)
+            .map_err(|_| anyhow!("Signature verification failed"))
+    }
+}
+
+// 256-bit key for bulk data rather than key material, such as a data subject's
+// ciphertexts in a backup; wiped from memory when dropped
+pub struct DataKey(Zeroizing<[u8; 32]>);
+
+impl DataKey {
+    pub fn generate() -> Self {
+        Self(random_key())
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> Result<Self> {
+        let bytes: [u8; 32] = bytes.try_into().map_err(|_| anyhow!("Data key has the wrong length"))?;
+        Ok(Self(Zeroizing::new(bytes)))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0[..]
+    }
+
+    // The nonce followed by the AES-256-GCM ciphertext, bound to `aad`
+    pub fn encrypt(&self, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        let nonce = Aes256Gcm::generate_nonce(OsRng);
+        let ciphertext = self
+            .cipher()
+            .encrypt(&nonce, Payload { msg: plaintext, aad })
+            .map_err(|_| anyhow!("Failed to encrypt data"))?;
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    pub fn decrypt(&self, aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>> {
+        if sealed.len() < 12 {
+            return Err(anyhow!("Sealed data is too short"));
+        }
+        let (nonce, ciphertext) = sealed.split_at(12);
+        self.cipher()
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+            .map_err(|_| anyhow!("Failed to decrypt data"))
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.0[..]))
+    }
+}
+
+fn random_key() -> Zeroizing<[u8; 32]> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    OsRng.fill_bytes(&mut key[..]);
+    key
+}
+
+// Secret material encrypted under a one-off data key, which is itself wrapped by the master key
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SealedKey {
+    wrapped_data_key: Vec<u8>,
+    data_key_nonce: Vec<u8>,
+    ciphertext: Vec<u8>,
+    nonce: Vec<u8>,
+}
+
+// Encrypt secret bytes, binding them to `key_id` so sealed blobs can't be swapped between IDs
+pub fn seal(master_key: &MasterKey, key_id: &str, plaintext: &[u8]) -> Result<SealedKey> {
+    let data_key = random_key();
+    let data_cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&data_key[..]));
+
+    let nonce = Aes256Gcm::generate_nonce(OsRng);
+    let ciphertext = data_cipher
+        .encrypt(&nonce, Payload { msg: plaintext, aad: key_id.as_bytes() })
+        .map_err(|_| anyhow!("Failed to encrypt key material"))?;
+
+    let data_key_nonce = Aes256Gcm::generate_nonce(OsRng);
+    let wrapped_data_key = master_key
+        .cipher()
+        .encrypt(&data_key_nonce, Payload { msg: &data_key[..], aad: key_id.as_bytes() })
+        .map_err(|_| anyhow!("Failed to wrap data key"))?;
+
+    Ok(SealedKey {
+        wrapped_data_key,
+        data_key_nonce: data_key_nonce.to_vec(),
+        ciphertext,
+        nonce: nonce.to_vec(),
+    })
+}
+
+// Decrypt sealed bytes; the returned buffer is zeroized when dropped
+pub fn open(master_key: &MasterKey, key_id: &str, sealed: &SealedKey) -> Result<Zeroizing<Vec<u8>>> {
+    if sealed.nonce.len() != 12 || sealed.data_key_nonce.len() != 12 {
+        return Err(anyhow!("Sealed key has a malformed nonce"));
+    }
+
+    let data_key = Zeroizing::new(
+        master_key
+            .cipher()
+            .decrypt(
+                Nonce::from_slice(&sealed.data_key_nonce),
+                Payload { msg: &sealed.wrapped_data_key, aad: key_id.as_bytes() },
+            )
+            .map_err(|_| anyhow!("Failed to unwrap data key"))?,
+    );
+    if data_key.len() != 32 {
+        return Err(anyhow!("Unwrapped data key has the wrong length"));
+    }
+
+    let data_cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&data_key[..]));
+    let plaintext = data_cipher
+        .decrypt(
+            Nonce::from_slice(&sealed.nonce),
+            Payload { msg: &sealed.ciphertext, aad: key_id.as_bytes() },
+        )
+        .map_err(|_| anyhow!("Failed to decrypt key material"))?;
+
+    Ok(Zeroizing::new(plaintext))
+}
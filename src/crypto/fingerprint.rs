@@ -0,0 +1,33 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+// SHA-256 fingerprint of serialized bytes, rendered as lowercase hex
+pub fn fingerprint_bytes(bytes: &[u8]) -> String {
+    to_hex(&Sha256::digest(bytes))
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Serialize a key or ciphertext and fingerprint the resulting bytes
+pub fn serialize_with_fingerprint<T: Serialize>(value: &T) -> Result<(Vec<u8>, String)> {
+    let bytes = bincode::serialize(value)
+        .map_err(|e| anyhow!("Serialization failed: {}", e))?;
+    let fingerprint = fingerprint_bytes(&bytes);
+    Ok((bytes, fingerprint))
+}
+
+// Check serialized bytes against an expected fingerprint
+pub fn verify_fingerprint(bytes: &[u8], expected: &str) -> Result<()> {
+    let actual = fingerprint_bytes(bytes);
+    if actual != expected.to_ascii_lowercase() {
+        return Err(anyhow!(
+            "Fingerprint mismatch: expected {}, computed {}",
+            expected,
+            actual
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,143 @@
+use std::borrow::Borrow;
+
+use anyhow::{anyhow, Result};
+use rayon::prelude::*;
+use tfhe::{FheUint8, ServerKey};
+
+use super::operations;
+use crate::cancellation::Cancellation;
+
+// Entries in an activation lookup table: one per FheUint8 value
+pub const LOOKUP_TABLE_SIZE: usize = 256;
+
+// Fully connected layer with plaintext weights, applied to an encrypted vector as
+// activation(weights * x + bias). The activation is a lookup table evaluated with
+// programmable bootstrapping, so any function of one 8-bit value costs the same.
+pub struct Layer {
+    outputs: usize,
+    inputs: usize,
+    weights: Vec<u8>,
+    bias: Vec<u8>,
+    activation: Option<Vec<u8>>,
+}
+
+impl Layer {
+    // weights is row-major, outputs x inputs; an empty bias means zero
+    pub fn new(outputs: usize, weights: Vec<u8>, bias: Vec<u8>, activation: Option<Vec<u8>>) -> Result<Self> {
+        if outputs == 0 || weights.is_empty() || !weights.len().is_multiple_of(outputs) {
+            return Err(anyhow!(
+                "{} weights cannot be split into {} output rows",
+                weights.len(),
+                outputs
+            ));
+        }
+        if !bias.is_empty() && bias.len() != outputs {
+            return Err(anyhow!("Layer has {} outputs but {} biases", outputs, bias.len()));
+        }
+        if let Some(table) = &activation {
+            if table.len() != LOOKUP_TABLE_SIZE {
+                return Err(anyhow!(
+                    "Activation lookup table needs {} entries, got {}",
+                    LOOKUP_TABLE_SIZE,
+                    table.len()
+                ));
+            }
+        }
+
+        Ok(Self {
+            outputs,
+            inputs: weights.len() / outputs,
+            weights,
+            bias,
+            activation,
+        })
+    }
+
+    pub fn inputs(&self) -> usize {
+        self.inputs
+    }
+
+    pub fn outputs(&self) -> usize {
+        self.outputs
+    }
+
+    // One output neuron per worker; each worker installs the thread-local server key
+    fn apply<X: Borrow<FheUint8> + Sync>(
+        &self,
+        server_key: &ServerKey,
+        x: &[X],
+        cancellation: &Cancellation,
+    ) -> Result<Vec<FheUint8>> {
+        (0..self.outputs)
+            .into_par_iter()
+            .map_init(
+                || tfhe::set_server_key(server_key.clone()),
+                |_, row| -> Result<FheUint8> {
+                    cancellation.check()?;
+                    let weights = &self.weights[row * self.inputs..(row + 1) * self.inputs];
+                    let mut sum = operations::integer_multiply_scalar(x[0].borrow(), weights[0]);
+                    for (xi, wi) in x.iter().zip(weights).skip(1) {
+                        sum = operations::integer_add(&sum, &operations::integer_multiply_scalar(xi.borrow(), *wi));
+                    }
+                    if let Some(bias) = self.bias.get(row) {
+                        sum = operations::integer_add_scalar(&sum, *bias);
+                    }
+                    Ok(match &self.activation {
+                        Some(table) => operations::integer_lookup(&sum, table),
+                        None => sum,
+                    })
+                },
+            )
+            .collect()
+    }
+}
+
+// A feed-forward model: layers applied in order, each reading the previous layer's outputs
+pub struct Model {
+    layers: Vec<Layer>,
+}
+
+impl Model {
+    pub fn new(layers: Vec<Layer>) -> Result<Self> {
+        if layers.is_empty() {
+            return Err(anyhow!("Model has no layers"));
+        }
+        for (index, pair) in layers.windows(2).enumerate() {
+            if pair[0].outputs != pair[1].inputs {
+                return Err(anyhow!(
+                    "Layer {} produces {} values but layer {} expects {}",
+                    index,
+                    pair[0].outputs,
+                    index + 1,
+                    pair[1].inputs
+                ));
+            }
+        }
+        Ok(Self { layers })
+    }
+
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    // Encrypted predictions for an encrypted input vector, which is only borrowed
+    pub fn run<X: Borrow<FheUint8> + Sync>(
+        &self,
+        server_key: &ServerKey,
+        inputs: &[X],
+        cancellation: &Cancellation,
+    ) -> Result<Vec<FheUint8>> {
+        if inputs.len() != self.layers[0].inputs {
+            return Err(anyhow!(
+                "Model expects {} inputs, got {}",
+                self.layers[0].inputs,
+                inputs.len()
+            ));
+        }
+
+        let first = self.layers[0].apply(server_key, inputs, cancellation)?;
+        self.layers[1..]
+            .iter()
+            .try_fold(first, |x, layer| layer.apply(server_key, &x, cancellation))
+    }
+}
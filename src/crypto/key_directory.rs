@@ -0,0 +1,223 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use uuid::Uuid;
+
+use super::alias::KeyAlias;
+use super::envelope::SealedKey;
+use super::fingerprint::to_hex;
+use super::versioning;
+use super::KeyBundle;
+
+const BUNDLE_EXTENSION: &str = "bundle";
+const SUBJECT_KEY_DIRECTORY: &str = "subjects";
+const ALIAS_FILE: &str = "aliases";
+
+// Persistent home for key pairs: one signed KeyBundle per file, named after its server
+// key ID, in an envelope recording the tfhe-rs release that wrote it. Client keys stay
+// sealed under the master key on disk, as they are in memory.
+pub struct KeyDirectory {
+    path: PathBuf,
+}
+
+impl KeyDirectory {
+    // Use the directory at path, creating it if needed
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        fs::create_dir_all(&path)
+            .map_err(|e| anyhow!("Failed to create key directory {}: {}", path.display(), e))?;
+        Ok(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Written to a temporary file and renamed, so a crash never leaves half a bundle behind
+    pub fn save(&self, bundle: &KeyBundle) -> Result<()> {
+        let target = self.bundle_path(&bundle.server_key_id)?;
+        let bytes = bincode::serialize(bundle).map_err(|e| anyhow!("Failed to encode key bundle: {}", e))?;
+        let bytes = versioning::seal(&bytes)?;
+
+        let staging = target.with_extension("tmp");
+        fs::write(&staging, bytes)
+            .and_then(|_| fs::rename(&staging, &target))
+            .map_err(|e| anyhow!("Failed to write {}: {}", target.display(), e))
+    }
+
+    // A bundle written by an older tfhe-rs release, or before envelopes, is migrated and
+    // written back, so each file is only migrated once
+    pub fn load(&self, server_key_id: &str) -> Result<KeyBundle> {
+        let path = self.bundle_path(server_key_id)?;
+        let bytes = fs::read(&path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        let (payload, stale) = versioning::read(&bytes).map_err(|e| anyhow!("{} in {}", e, path.display()))?;
+        let bundle = KeyBundle::decode(&payload).map_err(|e| anyhow!("{} in {}", e, path.display()))?;
+        if stale && bundle.server_key_id == server_key_id {
+            self.save(&bundle)?;
+        }
+        Ok(bundle)
+    }
+
+    // Delete a stored bundle; false if there was none
+    pub fn remove(&self, server_key_id: &str) -> Result<bool> {
+        let path = self.bundle_path(server_key_id)?;
+        match fs::remove_file(&path) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(anyhow!("Failed to delete {}: {}", path.display(), e)),
+        }
+    }
+
+    // Server key IDs of every stored bundle, sorted so loading order is stable
+    pub fn server_key_ids(&self) -> Result<Vec<String>> {
+        let entries = fs::read_dir(&self.path)
+            .map_err(|e| anyhow!("Failed to list key directory {}: {}", self.path.display(), e))?;
+
+        let mut ids = Vec::new();
+        for entry in entries {
+            let path = entry.map_err(|e| anyhow!("Failed to list key directory: {}", e))?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(BUNDLE_EXTENSION) {
+                continue;
+            }
+            if let Some(id) = path.file_stem().and_then(|stem| stem.to_str()) {
+                ids.push(id.to_string());
+            }
+        }
+        ids.sort();
+        Ok(ids)
+    }
+
+    // Subject keys sit apart from the bundles, sealed under the master key like client keys
+    pub fn save_subject_key(&self, subject_id: &str, sealed: &SealedKey) -> Result<()> {
+        let target = self.subject_key_path(subject_id);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| anyhow!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let bytes = bincode::serialize(sealed).map_err(|e| anyhow!("Failed to encode subject key: {}", e))?;
+
+        let staging = target.with_extension("tmp");
+        fs::write(&staging, bytes)
+            .and_then(|_| fs::rename(&staging, &target))
+            .map_err(|e| anyhow!("Failed to write {}: {}", target.display(), e))
+    }
+
+    // None if the subject has no stored key, never having had one or having been shredded
+    pub fn load_subject_key(&self, subject_id: &str) -> Result<Option<SealedKey>> {
+        let path = self.subject_key_path(subject_id);
+        match fs::read(&path) {
+            Ok(bytes) => bincode::deserialize(&bytes)
+                .map(Some)
+                .map_err(|e| anyhow!("Invalid subject key in {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(anyhow!("Failed to read {}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn remove_subject_key(&self, subject_id: &str) -> Result<bool> {
+        let path = self.subject_key_path(subject_id);
+        match fs::remove_file(&path) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(anyhow!("Failed to delete {}: {}", path.display(), e)),
+        }
+    }
+
+    // Every tenant's aliases in one file, rewritten whole on each change
+    pub fn save_aliases(&self, aliases: &[KeyAlias]) -> Result<()> {
+        let target = self.path.join(ALIAS_FILE);
+        let bytes = bincode::serialize(aliases).map_err(|e| anyhow!("Failed to encode key aliases: {}", e))?;
+
+        let staging = target.with_extension("tmp");
+        fs::write(&staging, bytes)
+            .and_then(|_| fs::rename(&staging, &target))
+            .map_err(|e| anyhow!("Failed to write {}: {}", target.display(), e))
+    }
+
+    // Empty if no alias was ever saved
+    pub fn load_aliases(&self) -> Result<Vec<KeyAlias>> {
+        let path = self.path.join(ALIAS_FILE);
+        match fs::read(&path) {
+            Ok(bytes) => bincode::deserialize(&bytes)
+                .map_err(|e| anyhow!("Invalid key aliases in {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(anyhow!("Failed to read {}: {}", path.display(), e)),
+        }
+    }
+
+    // Subject IDs are chosen by clients, so files are named by their hex instead
+    fn subject_key_path(&self, subject_id: &str) -> PathBuf {
+        self.path
+            .join(SUBJECT_KEY_DIRECTORY)
+            .join(format!("{}.key", to_hex(subject_id.as_bytes())))
+    }
+
+    // Key IDs are UUIDs; anything else could point outside the directory
+    fn bundle_path(&self, server_key_id: &str) -> Result<PathBuf> {
+        Uuid::parse_str(server_key_id).map_err(|_| anyhow!("Invalid server key ID '{}'", server_key_id))?;
+        Ok(self.path.join(format!("{}.{}", server_key_id, BUNDLE_EXTENSION)))
+    }
+}
+
+// Which stored key pairs to load into memory at startup
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeyPreload {
+    None,
+    All,
+    Only(Vec<String>),
+}
+
+impl KeyPreload {
+    // "all", "none", or a comma-separated list of server key IDs
+    pub fn parse(value: &str) -> Self {
+        match value.trim() {
+            "all" => KeyPreload::All,
+            "" | "none" => KeyPreload::None,
+            ids => KeyPreload::Only(
+                ids.split(',')
+                    .map(str::trim)
+                    .filter(|id| !id.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            ),
+        }
+    }
+
+    // Read HERMETIC_FHE_PRELOAD_KEYS, loading everything when it is unset
+    pub fn from_env() -> Self {
+        env::var("HERMETIC_FHE_PRELOAD_KEYS")
+            .map(|value| Self::parse(&value))
+            .unwrap_or(KeyPreload::All)
+    }
+}
+
+// How long a server key may go unused before it is dropped from memory, to be reloaded
+// from the key directory on its next use
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyUnloading {
+    pub idle_after: Duration,
+}
+
+impl KeyUnloading {
+    // HERMETIC_FHE_UNLOAD_IDLE_KEYS_AFTER_SECONDS turns unloading on; unset means every
+    // key stays in memory
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(seconds) = env::var("HERMETIC_FHE_UNLOAD_IDLE_KEYS_AFTER_SECONDS") else {
+            return Ok(None);
+        };
+        let seconds: u64 = seconds
+            .trim()
+            .parse()
+            .ok()
+            .filter(|seconds| *seconds > 0)
+            .ok_or_else(|| {
+                anyhow!("HERMETIC_FHE_UNLOAD_IDLE_KEYS_AFTER_SECONDS must be a positive integer")
+            })?;
+        Ok(Some(Self {
+            idle_after: Duration::from_secs(seconds),
+        }))
+    }
+}
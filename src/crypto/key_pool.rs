@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+
+use super::{parameter_config, GeneratedKeys};
+
+// Key pairs generated ahead of time, so GenerateKeys can hand one out at once instead of
+// making its caller wait seconds for tfhe. Each pair already has its IDs, and its
+// client key is sealed like any other; taking one only registers it with the store.
+pub struct KeyPool {
+    config: KeyPoolConfig,
+    pairs: Mutex<HashMap<String, Vec<GeneratedKeys>>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyPoolConfig {
+    // Pairs kept ready for each parameter set
+    pub size: usize,
+    pub parameter_sets: Vec<String>,
+}
+
+impl KeyPoolConfig {
+    // HERMETIC_FHE_KEY_POOL_SIZE turns the pool on; unset means every pair is generated
+    // on demand. HERMETIC_FHE_KEY_POOL_PARAMETER_SETS is a comma-separated list of the
+    // parameter sets pooled, DEFAULT if unset.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(size) = env::var("HERMETIC_FHE_KEY_POOL_SIZE") else {
+            return Ok(None);
+        };
+        let size: usize = size
+            .trim()
+            .parse()
+            .ok()
+            .filter(|size| *size > 0)
+            .ok_or_else(|| anyhow!("HERMETIC_FHE_KEY_POOL_SIZE must be a positive integer"))?;
+        let parameter_sets = env::var("HERMETIC_FHE_KEY_POOL_PARAMETER_SETS")
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|set| !set.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_else(|_| vec!["DEFAULT".to_string()]);
+        Self::new(size, parameter_sets).map(Some)
+    }
+
+    pub fn new(size: usize, parameter_sets: Vec<String>) -> Result<Self> {
+        for parameter_set in &parameter_sets {
+            parameter_config(parameter_set)
+                .map_err(|_| anyhow!("Unknown parameter set '{}' for the key pool", parameter_set))?;
+        }
+        Ok(Self { size, parameter_sets })
+    }
+}
+
+impl KeyPool {
+    pub fn new(config: KeyPoolConfig) -> Self {
+        Self {
+            config,
+            pairs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn config(&self) -> &KeyPoolConfig {
+        &self.config
+    }
+
+    // Pairs ready for the parameter set
+    pub fn available(&self, parameter_set: &str) -> usize {
+        self.pairs.lock().unwrap().get(parameter_set).map_or(0, Vec::len)
+    }
+
+    // A parameter set short of pairs, if any, most depleted first
+    pub fn next_to_fill(&self) -> Option<String> {
+        self.config
+            .parameter_sets
+            .iter()
+            .map(|parameter_set| (self.available(parameter_set), parameter_set))
+            .filter(|(available, _)| *available < self.config.size)
+            .min_by_key(|(available, _)| *available)
+            .map(|(_, parameter_set)| parameter_set.clone())
+    }
+
+    pub(super) fn take(&self, parameter_set: &str) -> Option<GeneratedKeys> {
+        self.pairs.lock().unwrap().get_mut(parameter_set)?.pop()
+    }
+
+    pub(super) fn put(&self, parameter_set: &str, keys: GeneratedKeys) {
+        self.pairs
+            .lock()
+            .unwrap()
+            .entry(parameter_set.to_string())
+            .or_default()
+            .push(keys);
+    }
+}
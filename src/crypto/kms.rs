@@ -0,0 +1,299 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use tracing::warn;
+use zeroize::Zeroizing;
+
+use super::envelope::MasterKey;
+
+// Source of the master key that seals client keys and signs exported key bundles
+pub trait MasterKeyProvider: Send + Sync {
+    // Short description for logs; must never include key material
+    fn describe(&self) -> String;
+
+    fn master_key(&self) -> Result<MasterKey>;
+}
+
+// Master key held in an environment variable as 64 hex characters
+pub struct EnvMasterKeyProvider {
+    variable: String,
+}
+
+impl EnvMasterKeyProvider {
+    pub fn new(variable: impl Into<String>) -> Self {
+        Self { variable: variable.into() }
+    }
+}
+
+impl MasterKeyProvider for EnvMasterKeyProvider {
+    fn describe(&self) -> String {
+        format!("environment variable {}", self.variable)
+    }
+
+    fn master_key(&self) -> Result<MasterKey> {
+        let hex = Zeroizing::new(
+            env::var(&self.variable).map_err(|_| anyhow!("{} is not set", self.variable))?,
+        );
+        MasterKey::from_hex(&hex)
+    }
+}
+
+// Master key stored in a file, either as 32 raw bytes or 64 hex characters
+pub struct FileMasterKeyProvider {
+    path: PathBuf,
+}
+
+impl FileMasterKeyProvider {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl MasterKeyProvider for FileMasterKeyProvider {
+    fn describe(&self) -> String {
+        format!("file {}", self.path.display())
+    }
+
+    fn master_key(&self) -> Result<MasterKey> {
+        let contents = Zeroizing::new(
+            fs::read(&self.path)
+                .map_err(|e| anyhow!("Failed to read {}: {}", self.path.display(), e))?,
+        );
+
+        if let Ok(raw) = <[u8; 32]>::try_from(contents.as_slice()) {
+            return Ok(MasterKey::from_bytes(raw));
+        }
+
+        let text = std::str::from_utf8(&contents)
+            .map_err(|_| anyhow!("{} is neither 32 raw bytes nor hex", self.path.display()))?;
+        MasterKey::from_hex(text)
+    }
+}
+
+// Random master key for development; sealed keys are unrecoverable after restart
+pub struct EphemeralMasterKeyProvider;
+
+impl MasterKeyProvider for EphemeralMasterKeyProvider {
+    fn describe(&self) -> String {
+        "ephemeral in-memory key".to_string()
+    }
+
+    fn master_key(&self) -> Result<MasterKey> {
+        warn!("Using an ephemeral master key; sealed client keys will not survive a restart");
+        Ok(MasterKey::generate())
+    }
+}
+
+// Key management service able to unwrap a master key it encrypted earlier
+pub trait KmsClient: Send + Sync {
+    fn describe(&self) -> String;
+
+    // `wrapped_key` is the ciphertext in the KMS's native text form
+    fn decrypt(&self, wrapped_key: &str) -> Result<Zeroizing<Vec<u8>>>;
+}
+
+// Master key kept wrapped by an external KMS; only the wrapped form lives in configuration
+pub struct KmsMasterKeyProvider<C: KmsClient> {
+    client: C,
+    wrapped_key: String,
+}
+
+impl<C: KmsClient> KmsMasterKeyProvider<C> {
+    pub fn new(client: C, wrapped_key: impl Into<String>) -> Self {
+        Self {
+            client,
+            wrapped_key: wrapped_key.into(),
+        }
+    }
+}
+
+impl<C: KmsClient> MasterKeyProvider for KmsMasterKeyProvider<C> {
+    fn describe(&self) -> String {
+        self.client.describe()
+    }
+
+    fn master_key(&self) -> Result<MasterKey> {
+        let plaintext = self.client.decrypt(&self.wrapped_key)?;
+        let raw = <[u8; 32]>::try_from(plaintext.as_slice())
+            .map_err(|_| anyhow!("KMS returned a {}-byte key, expected 32", plaintext.len()))?;
+        Ok(MasterKey::from_bytes(raw))
+    }
+}
+
+// Build the provider selected by HERMETIC_FHE_MASTER_KEY_PROVIDER
+// (`env`, `file`, `aws-kms`, `gcp-kms`, `vault` or `ephemeral`).
+// Without a selection, `env` is used when HERMETIC_FHE_MASTER_KEY is set.
+pub fn provider_from_env() -> Result<Box<dyn MasterKeyProvider>> {
+    let selected = env::var("HERMETIC_FHE_MASTER_KEY_PROVIDER").ok();
+    let selected = selected.as_deref().unwrap_or_else(|| {
+        if env::var_os("HERMETIC_FHE_MASTER_KEY").is_some() {
+            "env"
+        } else {
+            "ephemeral"
+        }
+    });
+
+    match selected {
+        "env" => Ok(Box::new(EnvMasterKeyProvider::new("HERMETIC_FHE_MASTER_KEY"))),
+        "file" => Ok(Box::new(FileMasterKeyProvider::new(required_env("HERMETIC_FHE_MASTER_KEY_FILE")?))),
+        "ephemeral" => Ok(Box::new(EphemeralMasterKeyProvider)),
+        #[cfg(feature = "cloud-kms")]
+        "aws-kms" => Ok(Box::new(KmsMasterKeyProvider::new(
+            cloud::AwsKmsClient::from_env()?,
+            required_env("HERMETIC_FHE_WRAPPED_MASTER_KEY")?,
+        ))),
+        #[cfg(feature = "cloud-kms")]
+        "gcp-kms" => Ok(Box::new(KmsMasterKeyProvider::new(
+            cloud::GcpKmsClient::from_env()?,
+            required_env("HERMETIC_FHE_WRAPPED_MASTER_KEY")?,
+        ))),
+        #[cfg(feature = "cloud-kms")]
+        "vault" => Ok(Box::new(KmsMasterKeyProvider::new(
+            cloud::VaultTransitClient::from_env()?,
+            required_env("HERMETIC_FHE_WRAPPED_MASTER_KEY")?,
+        ))),
+        #[cfg(not(feature = "cloud-kms"))]
+        "aws-kms" | "gcp-kms" | "vault" => Err(anyhow!(
+            "Master key provider '{}' requires the cloud-kms feature",
+            selected
+        )),
+        other => Err(anyhow!("Unknown master key provider '{}'", other)),
+    }
+}
+
+fn required_env(name: &str) -> Result<String> {
+    env::var(name).map_err(|_| anyhow!("{} is not set", name))
+}
+
+// HTTPS clients for hosted key management services
+#[cfg(feature = "cloud-kms")]
+pub mod cloud {
+    use anyhow::{anyhow, Result};
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use base64::Engine;
+    use serde_json::{json, Value};
+    use zeroize::Zeroizing;
+
+    use super::{required_env, KmsClient};
+    use crate::crypto::sigv4::AwsCredentials;
+
+    fn decode_plaintext(response: &Value, field: &str) -> Result<Zeroizing<Vec<u8>>> {
+        let encoded = response
+            .pointer(field)
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("KMS response is missing {}", field))?;
+        BASE64
+            .decode(encoded)
+            .map(Zeroizing::new)
+            .map_err(|e| anyhow!("KMS returned invalid base64: {}", e))
+    }
+
+    // AWS KMS `Decrypt`, signed with SigV4 using the standard AWS_* credentials
+    pub struct AwsKmsClient {
+        credentials: AwsCredentials,
+    }
+
+    impl AwsKmsClient {
+        pub fn from_env() -> Result<Self> {
+            Ok(Self {
+                credentials: AwsCredentials::from_env()?,
+            })
+        }
+    }
+
+    impl KmsClient for AwsKmsClient {
+        fn describe(&self) -> String {
+            format!("AWS KMS ({})", self.credentials.region)
+        }
+
+        fn decrypt(&self, wrapped_key: &str) -> Result<Zeroizing<Vec<u8>>> {
+            let host = format!("kms.{}.amazonaws.com", self.credentials.region);
+            let body = json!({ "CiphertextBlob": wrapped_key }).to_string();
+
+            let headers = vec![
+                ("content-type", "application/x-amz-json-1.1".to_string()),
+                ("host", host.clone()),
+                ("x-amz-target", "TrentService.Decrypt".to_string()),
+            ];
+            let mut request = ureq::post(&format!("https://{}/", host));
+            for (name, value) in self.credentials.sign("kms", "POST", "/", headers, body.as_bytes()) {
+                if name != "host" {
+                    request = request.set(name, &value);
+                }
+            }
+
+            let response: Value = request
+                .send_string(&body)
+                .map_err(|e| anyhow!("AWS KMS decrypt failed: {}", e))?
+                .into_json()?;
+            decode_plaintext(&response, "/Plaintext")
+        }
+    }
+
+    // Google Cloud KMS `decrypt` using an OAuth access token
+    pub struct GcpKmsClient {
+        key_name: String,
+        access_token: Zeroizing<String>,
+    }
+
+    impl GcpKmsClient {
+        // HERMETIC_FHE_GCP_KMS_KEY is the full `projects/.../cryptoKeys/...` resource name
+        pub fn from_env() -> Result<Self> {
+            Ok(Self {
+                key_name: required_env("HERMETIC_FHE_GCP_KMS_KEY")?,
+                access_token: Zeroizing::new(required_env("GOOGLE_OAUTH_ACCESS_TOKEN")?),
+            })
+        }
+    }
+
+    impl KmsClient for GcpKmsClient {
+        fn describe(&self) -> String {
+            format!("GCP KMS ({})", self.key_name)
+        }
+
+        fn decrypt(&self, wrapped_key: &str) -> Result<Zeroizing<Vec<u8>>> {
+            let url = format!("https://cloudkms.googleapis.com/v1/{}:decrypt", self.key_name);
+            let response: Value = ureq::post(&url)
+                .set("Authorization", &format!("Bearer {}", self.access_token.as_str()))
+                .send_json(json!({ "ciphertext": wrapped_key }))
+                .map_err(|e| anyhow!("GCP KMS decrypt failed: {}", e))?
+                .into_json()?;
+            decode_plaintext(&response, "/plaintext")
+        }
+    }
+
+    // HashiCorp Vault transit secrets engine
+    pub struct VaultTransitClient {
+        address: String,
+        token: Zeroizing<String>,
+        key_name: String,
+    }
+
+    impl VaultTransitClient {
+        pub fn from_env() -> Result<Self> {
+            Ok(Self {
+                address: required_env("VAULT_ADDR")?.trim_end_matches('/').to_string(),
+                token: Zeroizing::new(required_env("VAULT_TOKEN")?),
+                key_name: required_env("HERMETIC_FHE_VAULT_TRANSIT_KEY")?,
+            })
+        }
+    }
+
+    impl KmsClient for VaultTransitClient {
+        fn describe(&self) -> String {
+            format!("Vault transit key {} at {}", self.key_name, self.address)
+        }
+
+        fn decrypt(&self, wrapped_key: &str) -> Result<Zeroizing<Vec<u8>>> {
+            let url = format!("{}/v1/transit/decrypt/{}", self.address, self.key_name);
+            let response: Value = ureq::post(&url)
+                .set("X-Vault-Token", self.token.as_str())
+                .send_json(json!({ "ciphertext": wrapped_key }))
+                .map_err(|e| anyhow!("Vault decrypt failed: {}", e))?
+                .into_json()?;
+            decode_plaintext(&response, "/data/plaintext")
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::borrow::Borrow;
+
+use anyhow::{anyhow, Result};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use tfhe::{FheUint8, ServerKey};
+
+use super::operations;
+use crate::cancellation::Cancellation;
+
+// Row-major matrix of encrypted integers. Arithmetic wraps modulo 2^8 like the scalar
+// operations; fixed-point values are integers scaled by a power of two chosen by the client.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EncryptedMatrix {
+    rows: usize,
+    cols: usize,
+    elements: Vec<FheUint8>,
+}
+
+impl EncryptedMatrix {
+    pub fn new(rows: usize, cols: usize, elements: Vec<FheUint8>) -> Result<Self> {
+        let matrix = Self { rows, cols, elements };
+        matrix.check_shape()?;
+        Ok(matrix)
+    }
+
+    // Deserialization fills the fields directly, so a matrix read from outside is checked
+    // again before row() slices into it
+    pub fn check_shape(&self) -> Result<()> {
+        if self.rows == 0 || self.cols == 0 {
+            return Err(anyhow!("Matrix must have at least one row and column"));
+        }
+        if self.rows.checked_mul(self.cols) != Some(self.elements.len()) {
+            return Err(anyhow!(
+                "A {}x{} matrix needs {} elements, got {}",
+                self.rows,
+                self.cols,
+                self.rows.saturating_mul(self.cols),
+                self.elements.len()
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    // Elements in row-major order
+    pub fn elements(&self) -> &[FheUint8] {
+        &self.elements
+    }
+
+    pub fn row(&self, row: usize) -> &[FheUint8] {
+        &self.elements[row * self.cols..(row + 1) * self.cols]
+    }
+
+    // Product with an encrypted column vector, one row per worker. The vector is only
+    // borrowed, so elements shared with the ciphertext store are read in place.
+    pub fn multiply_vector<V: Borrow<FheUint8> + Sync>(
+        &self,
+        server_key: &ServerKey,
+        vector: &[V],
+        cancellation: &Cancellation,
+    ) -> Result<Vec<FheUint8>> {
+        self.check_vector_length(vector.len())?;
+        self.map_rows(server_key, cancellation, |row| {
+            dot(row.iter().zip(vector).map(|(a, b)| operations::integer_multiply(a, b.borrow())))
+        })
+    }
+
+    // Product with a plaintext column vector, e.g. the weights of a linear model
+    pub fn multiply_plaintext_vector(
+        &self,
+        server_key: &ServerKey,
+        vector: &[u8],
+        cancellation: &Cancellation,
+    ) -> Result<Vec<FheUint8>> {
+        self.check_vector_length(vector.len())?;
+        self.map_rows(server_key, cancellation, |row| {
+            dot(row.iter().zip(vector).map(|(a, b)| operations::integer_multiply_scalar(a, *b)))
+        })
+    }
+
+    pub fn add(&self, server_key: &ServerKey, other: &EncryptedMatrix) -> Result<EncryptedMatrix> {
+        if (self.rows, self.cols) != (other.rows, other.cols) {
+            return Err(anyhow!(
+                "Cannot add a {}x{} matrix to a {}x{} matrix",
+                other.rows,
+                other.cols,
+                self.rows,
+                self.cols
+            ));
+        }
+
+        let elements = self
+            .elements
+            .par_iter()
+            .zip(&other.elements)
+            .map_init(
+                || tfhe::set_server_key(server_key.clone()),
+                |_, (a, b)| operations::integer_add(a, b),
+            )
+            .collect();
+        Ok(Self { elements, ..*self })
+    }
+
+    pub fn scale(&self, server_key: &ServerKey, scalar: u8) -> EncryptedMatrix {
+        let elements = self
+            .elements
+            .par_iter()
+            .map_init(
+                || tfhe::set_server_key(server_key.clone()),
+                |_, a| operations::integer_multiply_scalar(a, scalar),
+            )
+            .collect();
+        Self { elements, ..*self }
+    }
+
+    fn check_vector_length(&self, len: usize) -> Result<()> {
+        if len != self.cols {
+            return Err(anyhow!(
+                "Cannot multiply a {}x{} matrix by a vector of length {}",
+                self.rows,
+                self.cols,
+                len
+            ));
+        }
+        Ok(())
+    }
+
+    // Apply f to every row on the rayon pool. The tfhe server key is thread-local, so each
+    // worker installs its own copy before evaluating. Rows not yet started when the
+    // cancellation fires are skipped.
+    fn map_rows<F>(
+        &self,
+        server_key: &ServerKey,
+        cancellation: &Cancellation,
+        f: F,
+    ) -> Result<Vec<FheUint8>>
+    where
+        F: Fn(&[FheUint8]) -> FheUint8 + Sync,
+    {
+        (0..self.rows)
+            .into_par_iter()
+            .map_init(
+                || tfhe::set_server_key(server_key.clone()),
+                |_, row| -> Result<FheUint8> {
+                    cancellation.check()?;
+                    Ok(f(self.row(row)))
+                },
+            )
+            .collect()
+    }
+}
+
+// Sum of products; callers guarantee at least one term since matrices are never empty
+fn dot(mut products: impl Iterator<Item = FheUint8>) -> FheUint8 {
+    let first = products.next().expect("matrix row is not empty");
+    products.fold(first, |sum, product| operations::integer_add(&sum, &product))
+}
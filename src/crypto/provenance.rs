@@ -0,0 +1,222 @@
+use std::borrow::Cow;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::sync::Arc;
+
+use sha2::{Digest, Sha256};
+
+// Most distinct inputs a provenance records. Past this it only remembers that there were
+// at least this many, which is as high as a minimum-input policy can ask for.
+pub const MAX_TRACKED_INPUTS: usize = 1024;
+
+// The fresh ciphertexts a stored value was computed from, each named by a hash of its ID
+// so a large aggregate costs eight bytes per input rather than a UUID
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Provenance {
+    inputs: BTreeSet<u64>,
+    saturated: bool,
+}
+
+impl Provenance {
+    // A value encrypted, imported or ingested as it is, which is its own only input
+    pub fn fresh(id: &str) -> Self {
+        let digest = Sha256::digest(id.as_bytes());
+        let token = u64::from_le_bytes(digest[..8].try_into().expect("SHA-256 digests are 32 bytes"));
+        Self {
+            inputs: BTreeSet::from([token]),
+            saturated: false,
+        }
+    }
+
+    // Provenance of a value computed from values with these provenances. An input used
+    // twice, as in a + a, still counts once.
+    pub fn merge<'a>(parts: impl IntoIterator<Item = &'a Provenance>) -> Self {
+        let mut merged = Self::default();
+        for part in parts {
+            if part.saturated {
+                return Self::saturated();
+            }
+            merged.inputs.extend(&part.inputs);
+            if merged.inputs.len() >= MAX_TRACKED_INPUTS {
+                return Self::saturated();
+            }
+        }
+        merged
+    }
+
+    fn saturated() -> Self {
+        Self {
+            inputs: BTreeSet::new(),
+            saturated: true,
+        }
+    }
+
+    // Distinct inputs, or MAX_TRACKED_INPUTS if there were at least that many
+    pub fn inputs(&self) -> usize {
+        if self.saturated {
+            MAX_TRACKED_INPUTS
+        } else {
+            self.inputs.len()
+        }
+    }
+}
+
+// Longest chain of derivations a value keeps. A parent deeper than this keeps its own
+// operation and inputs but not its history, so a value overwritten in place again and
+// again doesn't hold every version it replaced.
+pub const MAX_LINEAGE_DEPTH: usize = 256;
+
+// How a stored value was computed: the call that made it and the values it read. Each
+// parent keeps its own derivation, so the history survives its intermediates being deleted.
+#[derive(Clone, Debug)]
+pub struct Derivation {
+    // The call, e.g. "EvaluateOperation"
+    pub operation: String,
+    // What the call did to make this value, e.g. "ADD" or "output 2"
+    pub detail: String,
+    pub parents: Vec<Parent>,
+    pub provenance: Provenance,
+    // The parents' own history was dropped at MAX_LINEAGE_DEPTH
+    pub truncated: bool,
+    depth: usize,
+}
+
+// A value a derivation read, as it was when it was read
+#[derive(Clone, Debug)]
+pub struct Parent {
+    pub id: String,
+    // None for a value encrypted, imported or ingested as it is
+    pub derivation: Option<Arc<Derivation>>,
+}
+
+impl Parent {
+    pub fn new(id: &str, derivation: Option<Arc<Derivation>>) -> Self {
+        let derivation = derivation.map(|derivation| {
+            if derivation.depth < MAX_LINEAGE_DEPTH {
+                return derivation;
+            }
+            Arc::new(Derivation {
+                parents: vec![],
+                truncated: true,
+                depth: 1,
+                ..(*derivation).clone()
+            })
+        });
+        Self {
+            id: id.to_string(),
+            derivation,
+        }
+    }
+
+    pub fn provenance(&self) -> Cow<'_, Provenance> {
+        match &self.derivation {
+            Some(derivation) => Cow::Borrowed(&derivation.provenance),
+            None => Cow::Owned(Provenance::fresh(&self.id)),
+        }
+    }
+}
+
+impl Derivation {
+    // Computed from the parents, whose inputs it inherits
+    pub fn new(operation: &str, detail: impl Into<String>, parents: Vec<Parent>) -> Self {
+        let provenances: Vec<_> = parents.iter().map(Parent::provenance).collect();
+        let provenance = Provenance::merge(provenances.iter().map(|provenance| provenance.as_ref()));
+        let depth = parents
+            .iter()
+            .filter_map(|parent| parent.derivation.as_ref())
+            .map(|derivation| derivation.depth)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        Self {
+            operation: operation.to_string(),
+            detail: detail.into(),
+            parents,
+            provenance,
+            truncated: false,
+            depth,
+        }
+    }
+
+    // The same computation's other outputs, e.g. each position of a sorted vector
+    pub fn with_detail(&self, detail: impl Into<String>) -> Self {
+        Self {
+            detail: detail.into(),
+            ..self.clone()
+        }
+    }
+
+    // For a value whose inputs aren't all parents still held, like a counter or a tally
+    pub fn with_provenance(mut self, provenance: Provenance) -> Self {
+        self.provenance = provenance;
+        self
+    }
+}
+
+// One value in a lineage. Parents are positions in the lineage's node list.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LineageNode {
+    pub id: String,
+    // Empty for a value encrypted, imported or ingested as it is
+    pub operation: String,
+    pub detail: String,
+    pub parents: Vec<usize>,
+    pub truncated: bool,
+}
+
+// The ancestry of a value as a DAG, breadth first from the value itself. A derivation
+// shared by several descendants appears once, as does a source value read more than once.
+// Returns the nodes and whether max_nodes cut it short.
+pub fn lineage(id: &str, derivation: Option<Arc<Derivation>>, max_nodes: usize) -> (Vec<LineageNode>, bool) {
+    // Derived values are told apart by derivation, since a value overwritten in place
+    // shares its ID with what it replaced, and source values by ID
+    let key = |parent: &Parent| match &parent.derivation {
+        Some(derivation) => NodeKey::Derived(Arc::as_ptr(derivation)),
+        None => NodeKey::Source(parent.id.clone()),
+    };
+    let root = Parent {
+        id: id.to_string(),
+        derivation,
+    };
+    let mut index: HashMap<NodeKey, usize> = HashMap::from([(key(&root), 0)]);
+    let mut queue = VecDeque::from([root]);
+    let mut nodes = Vec::new();
+    let mut truncated = false;
+    while let Some(parent) = queue.pop_front() {
+        let mut node = LineageNode {
+            id: parent.id.clone(),
+            operation: String::new(),
+            detail: String::new(),
+            parents: vec![],
+            truncated: false,
+        };
+        if let Some(derivation) = &parent.derivation {
+            node.operation = derivation.operation.clone();
+            node.detail = derivation.detail.clone();
+            node.truncated = derivation.truncated;
+            for grandparent in &derivation.parents {
+                let next = index.len();
+                let position = match index.entry(key(grandparent)) {
+                    Entry::Occupied(entry) => *entry.get(),
+                    Entry::Vacant(_) if next >= max_nodes => {
+                        truncated = true;
+                        continue;
+                    }
+                    Entry::Vacant(entry) => {
+                        queue.push_back(grandparent.clone());
+                        *entry.insert(next)
+                    }
+                };
+                node.parents.push(position);
+            }
+        }
+        nodes.push(node);
+    }
+    (nodes, truncated)
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum NodeKey {
+    Derived(*const Derivation),
+    Source(String),
+}
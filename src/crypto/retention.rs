@@ -0,0 +1,35 @@
+use std::env;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+
+// How long deleted ciphertexts stay restorable when the operator doesn't say
+pub const DEFAULT_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+// How long a deleted ciphertext is kept, hidden, before it is purged for good
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetentionConfig {
+    pub retention: Duration,
+}
+
+impl RetentionConfig {
+    // HERMETIC_FHE_DELETE_RETENTION_SECONDS sets the window, a day by default; 0 frees
+    // deleted ciphertexts at once, leaving nothing to restore
+    pub fn from_env() -> Result<Self> {
+        let retention = match env::var("HERMETIC_FHE_DELETE_RETENTION_SECONDS") {
+            Ok(seconds) => seconds.trim().parse().map(Duration::from_secs).map_err(|_| {
+                anyhow!("HERMETIC_FHE_DELETE_RETENTION_SECONDS must be a whole number of seconds")
+            })?,
+            Err(_) => DEFAULT_RETENTION,
+        };
+        Ok(Self { retention })
+    }
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            retention: DEFAULT_RETENTION,
+        }
+    }
+}
@@ -0,0 +1,601 @@
+use std::ops::Range;
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroize;
+
+// Arithmetic in Z_Q[X]/(X^N + 1) shared by the lattice schemes, CKKS and BGV. Q is a
+// chain of word-sized primes and every polynomial is held as one residue vector per prime
+// ("limb") in NTT form, so products are pointwise. Key switching, which both schemes use
+// for relinearization and rotation, lives here too.
+
+// Ring degree N
+pub const LOG_DEGREE: u32 = 13;
+pub const DEGREE: usize = 1 << LOG_DEGREE;
+// Slots a rotation cycles through: the powers of 5 modulo 2N
+pub const SLOTS: usize = DEGREE / 2;
+// Encryption noise is a centered binomial over this many coin pairs, standard deviation ~3.2
+const NOISE_WIDTH: u32 = 21;
+
+// Polynomial as one residue vector per prime, in NTT form
+pub(crate) type Limbs = Vec<Vec<u64>>;
+
+pub(crate) fn mul_mod(a: u64, b: u64, q: u64) -> u64 {
+    ((a as u128 * b as u128) % q as u128) as u64
+}
+
+// Primes stay below 2^61, so sums of two residues never overflow
+pub(crate) fn add_mod(a: u64, b: u64, q: u64) -> u64 {
+    let sum = a + b;
+    if sum >= q {
+        sum - q
+    } else {
+        sum
+    }
+}
+
+pub(crate) fn sub_mod(a: u64, b: u64, q: u64) -> u64 {
+    if a >= b {
+        a - b
+    } else {
+        a + q - b
+    }
+}
+
+fn pow_mod(mut base: u64, mut exponent: u64, q: u64) -> u64 {
+    let mut result = 1;
+    base %= q;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = mul_mod(result, base, q);
+        }
+        base = mul_mod(base, base, q);
+        exponent >>= 1;
+    }
+    result
+}
+
+fn inverse_mod(a: u64, q: u64) -> u64 {
+    pow_mod(a, q - 2, q)
+}
+
+pub(crate) fn reduce(value: i64, q: u64) -> u64 {
+    value.rem_euclid(q as i64) as u64
+}
+
+// Representative of x mod q in (-q/2, q/2]
+pub(crate) fn center(x: u64, q: u64) -> i64 {
+    if x > q / 2 {
+        x as i64 - q as i64
+    } else {
+        x as i64
+    }
+}
+
+// Deterministic Miller-Rabin; these bases are enough for every 64-bit n
+fn is_prime(n: u64) -> bool {
+    const BASES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+    if n < 2 {
+        return false;
+    }
+    for p in BASES {
+        if n.is_multiple_of(p) {
+            return n == p;
+        }
+    }
+
+    let mut d = n - 1;
+    let mut r = 0;
+    while d.is_multiple_of(2) {
+        d /= 2;
+        r += 1;
+    }
+    'witness: for a in BASES {
+        let mut x = pow_mod(a, d, n);
+        if x == 1 || x == n - 1 {
+            continue;
+        }
+        for _ in 1..r {
+            x = mul_mod(x, x, n);
+            if x == n - 1 {
+                continue 'witness;
+            }
+        }
+        return false;
+    }
+    true
+}
+
+// Distinct primes just below 2^bits for each entry, all ≡ 1 mod step. The step must be a
+// multiple of 2N so the NTT exists.
+pub(crate) fn find_primes(bits: &[u32], step: u64) -> Vec<u64> {
+    let mut primes: Vec<u64> = Vec::with_capacity(bits.len());
+    for &bits in bits {
+        let mut candidate = ((1u64 << bits) - 1) / step * step + 1;
+        while !is_prime(candidate) || primes.contains(&candidate) {
+            candidate -= step;
+        }
+        primes.push(candidate);
+    }
+    primes
+}
+
+// In-place cyclic NTT over Z_q, with roots[i] = ω^i for i < n/2
+fn transform(a: &mut [u64], roots: &[u64], q: u64) {
+    let n = a.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            a.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let half = len / 2;
+        let stride = n / len;
+        for start in (0..n).step_by(len) {
+            for k in 0..half {
+                let u = a[start + k];
+                let v = mul_mod(a[start + k + half], roots[k * stride], q);
+                a[start + k] = add_mod(u, v, q);
+                a[start + k + half] = sub_mod(u, v, q);
+            }
+        }
+        len *= 2;
+    }
+}
+
+// One prime with its negacyclic NTT tables
+pub(crate) struct Modulus {
+    pub(crate) q: u64,
+    // Powers of ψ, a primitive 2N-th root of unity, and of its inverse
+    psi: Vec<u64>,
+    psi_inverse: Vec<u64>,
+    // The first N/2 powers of ω = ψ² and of its inverse
+    omega: Vec<u64>,
+    omega_inverse: Vec<u64>,
+    degree_inverse: u64,
+}
+
+impl Modulus {
+    pub(crate) fn new(q: u64) -> Self {
+        let order = 2 * DEGREE as u64;
+        let psi = (2..)
+            .map(|g| pow_mod(g, (q - 1) / order, q))
+            .find(|&root| pow_mod(root, order / 2, q) == q - 1)
+            .expect("a prime ≡ 1 mod 2N has a primitive 2N-th root");
+        let powers = |root: u64, count: usize| {
+            let mut powers = Vec::with_capacity(count);
+            let mut power = 1;
+            for _ in 0..count {
+                powers.push(power);
+                power = mul_mod(power, root, q);
+            }
+            powers
+        };
+        let psi_inverse = inverse_mod(psi, q);
+        let omega = mul_mod(psi, psi, q);
+
+        Self {
+            q,
+            psi: powers(psi, DEGREE),
+            psi_inverse: powers(psi_inverse, DEGREE),
+            omega: powers(omega, DEGREE / 2),
+            omega_inverse: powers(inverse_mod(omega, q), DEGREE / 2),
+            degree_inverse: inverse_mod(DEGREE as u64, q),
+        }
+    }
+
+    // Coefficients to evaluations: index k ends up holding the value at ψ^(2k+1)
+    pub(crate) fn forward(&self, a: &mut [u64]) {
+        for (x, psi) in a.iter_mut().zip(&self.psi) {
+            *x = mul_mod(*x, *psi, self.q);
+        }
+        transform(a, &self.omega, self.q);
+    }
+
+    pub(crate) fn inverse(&self, a: &mut [u64]) {
+        transform(a, &self.omega_inverse, self.q);
+        for (x, psi_inverse) in a.iter_mut().zip(&self.psi_inverse) {
+            *x = mul_mod(mul_mod(*x, self.degree_inverse, self.q), *psi_inverse, self.q);
+        }
+    }
+
+    fn uniform(&self) -> Vec<u64> {
+        let mask = u64::MAX >> self.q.leading_zeros();
+        (0..DEGREE)
+            .map(|_| loop {
+                let x = OsRng.next_u64() & mask;
+                if x < self.q {
+                    break x;
+                }
+            })
+            .collect()
+    }
+}
+
+// Ternary secret s, as coefficients; wiped on drop
+#[derive(Serialize, Deserialize)]
+pub struct SecretKey {
+    coefficients: Vec<i64>,
+}
+
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        self.coefficients.zeroize();
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct SwitchingKey {
+    // (b_j, a_j) per ciphertext prime q_j, over every prime including the special one
+    digits: Vec<(Limbs, Limbs)>,
+}
+
+// Public keys for multiplication and rotation
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EvaluationKey {
+    relinearization: SwitchingKey,
+    // Rotation left by 2^i slots at index i; other amounts are composed from these
+    rotations: Vec<SwitchingKey>,
+}
+
+// Public key that moves ciphertexts from one secret key to another without decrypting
+// them: a switching key encrypting the source secret under the target one
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ReEncryptionKey(SwitchingKey);
+
+// A ciphertext (c0, c1) with c0 + c1·s ≈ m modulo the limbs it has
+pub(crate) type Pair = (Limbs, Limbs);
+
+// A chain of ciphertext primes q_0..q_L, then the special prime P used only inside key
+// switching. Every bit of noise a scheme adds, and every rounding error, is a multiple of
+// `plaintext`: 1 for CKKS, whose messages absorb the noise, and t for BGV, whose
+// messages live modulo t and must come out exact. For BGV every prime is 1 mod t, so
+// dropping or dividing by one leaves the message alone.
+pub(crate) struct Ring {
+    pub(crate) moduli: Vec<Modulus>,
+    plaintext: u64,
+}
+
+impl Ring {
+    pub(crate) fn new(primes: Vec<u64>, plaintext: u64) -> Self {
+        Self {
+            moduli: primes.into_iter().map(Modulus::new).collect(),
+            plaintext,
+        }
+    }
+
+    fn special(&self) -> usize {
+        self.moduli.len() - 1
+    }
+
+    pub(crate) fn ciphertext_limbs(&self) -> Range<usize> {
+        0..self.special()
+    }
+
+    // Whether a pair read from outside has the shape of a ciphertext: both halves with
+    // the same 1 to L + 1 limbs, each of N residues reduced modulo its prime. Arithmetic
+    // on anything else indexes out of bounds or overflows.
+    pub(crate) fn is_well_formed(&self, (c0, c1): &Pair) -> bool {
+        let limbs = c0.len();
+        limbs > 0
+            && limbs == c1.len()
+            && limbs <= self.ciphertext_limbs().len()
+            && c0.iter().chain(c1).zip((0..limbs).cycle()).all(|(limb, i)| {
+                let q = self.moduli[i].q;
+                limb.len() == DEGREE && limb.iter().all(|x| *x < q)
+            })
+    }
+
+    // Small signed coefficients into NTT form under each of the given primes
+    fn to_limbs(&self, coefficients: &[i64], limbs: Range<usize>) -> Limbs {
+        limbs
+            .map(|i| {
+                let modulus = &self.moduli[i];
+                let mut limb: Vec<u64> = coefficients.iter().map(|c| reduce(*c, modulus.q)).collect();
+                modulus.forward(&mut limb);
+                limb
+            })
+            .collect()
+    }
+
+    // Fresh noise, scaled by the plaintext modulus
+    fn noise(&self) -> Vec<i64> {
+        let mask = (1u64 << NOISE_WIDTH) - 1;
+        let scale = self.plaintext as i64;
+        (0..DEGREE)
+            .map(|_| {
+                let bits = OsRng.next_u64();
+                let sample = (bits & mask).count_ones() as i64 - ((bits >> 32) & mask).count_ones() as i64;
+                sample * scale
+            })
+            .collect()
+    }
+
+    pub(crate) fn add(&self, a: &mut Limbs, b: &Limbs) {
+        for (i, (x, y)) in a.iter_mut().zip(b).enumerate() {
+            let q = self.moduli[i].q;
+            for (x, y) in x.iter_mut().zip(y) {
+                *x = add_mod(*x, *y, q);
+            }
+        }
+    }
+
+    fn multiply(&self, a: &Limbs, b: &Limbs) -> Limbs {
+        a.iter()
+            .zip(b)
+            .enumerate()
+            .map(|(i, (x, y))| x.iter().zip(y).map(|(x, y)| mul_mod(*x, *y, self.moduli[i].q)).collect())
+            .collect()
+    }
+
+    pub(crate) fn generate_keys(&self) -> (SecretKey, EvaluationKey) {
+        let secret = SecretKey {
+            coefficients: ternary(),
+        };
+        let all_limbs = 0..self.moduli.len();
+        let s = self.to_limbs(&secret.coefficients, all_limbs.clone());
+
+        let relinearization = self.switching_key(&s, &self.multiply(&s, &s));
+        let rotations = (0..LOG_DEGREE - 1)
+            .map(|i| {
+                let rotated = small_automorphism(&secret.coefficients, galois_element(1 << i));
+                self.switching_key(&s, &self.to_limbs(&rotated, all_limbs.clone()))
+            })
+            .collect();
+
+        (
+            secret,
+            EvaluationKey {
+                relinearization,
+                rotations,
+            },
+        )
+    }
+
+    // (m + noise - a·s, a) over every ciphertext prime, for a message given by its
+    // coefficients
+    pub(crate) fn encrypt(&self, secret: &SecretKey, message: &[i64]) -> Pair {
+        let limbs = self.ciphertext_limbs();
+        let mut c0 = self.to_limbs(message, limbs.clone());
+        self.add(&mut c0, &self.to_limbs(&self.noise(), limbs.clone()));
+        let s = self.to_limbs(&secret.coefficients, limbs.clone());
+        let c1: Limbs = limbs.map(|i| self.moduli[i].uniform()).collect();
+        for (i, (limb, a_s)) in c0.iter_mut().zip(self.multiply(&c1, &s)).enumerate() {
+            let q = self.moduli[i].q;
+            for (x, y) in limb.iter_mut().zip(a_s) {
+                *x = sub_mod(*x, y, q);
+            }
+        }
+        (c0, c1)
+    }
+
+    // Coefficients of c0 + c1·s under the lowest prime alone, centered. Any message
+    // plus noise smaller than q_0/2 comes out exactly.
+    pub(crate) fn decrypt(&self, secret: &SecretKey, ciphertext: &Pair) -> Vec<i64> {
+        let modulus = &self.moduli[0];
+        let s = self.to_limbs(&secret.coefficients, 0..1);
+        let (c0, c1) = ciphertext;
+        let mut m: Vec<u64> = c0[0]
+            .iter()
+            .zip(&c1[0])
+            .zip(&s[0])
+            .map(|((c0, c1), s)| add_mod(*c0, mul_mod(*c1, *s, modulus.q), modulus.q))
+            .collect();
+        modulus.inverse(&mut m);
+        m.iter().map(|x| center(*x, modulus.q)).collect()
+    }
+
+    // Product of two ciphertexts at the same level, relinearized back to two components
+    pub(crate) fn multiply_ciphertexts(&self, key: &EvaluationKey, a: &Pair, b: &Pair) -> Pair {
+        let mut c0 = self.multiply(&a.0, &b.0);
+        let mut c1 = self.multiply(&a.0, &b.1);
+        self.add(&mut c1, &self.multiply(&a.1, &b.0));
+        let d2 = self.multiply(&a.1, &b.1);
+
+        let (r0, r1) = self.switch_key(&d2, &key.relinearization);
+        self.add(&mut c0, &r0);
+        self.add(&mut c1, &r1);
+        (c0, c1)
+    }
+
+    pub(crate) fn re_encryption_key(&self, source: &SecretKey, target: &SecretKey) -> ReEncryptionKey {
+        let all_limbs = 0..self.moduli.len();
+        let target = self.to_limbs(&target.coefficients, all_limbs.clone());
+        ReEncryptionKey(self.switching_key(&target, &self.to_limbs(&source.coefficients, all_limbs)))
+    }
+
+    // The same message under the key's target secret, at the same level: c1·source is
+    // switched to (r0, r1) with r0 + r1·target ≈ c1·source
+    pub(crate) fn re_encrypt(&self, key: &ReEncryptionKey, ciphertext: &Pair) -> Pair {
+        let (c0, c1) = ciphertext;
+        let mut c0 = c0.clone();
+        let (r0, r1) = self.switch_key(c1, &key.0);
+        self.add(&mut c0, &r0);
+        (c0, r1)
+    }
+
+    // Cyclic left rotation over all SLOTS slots; negative steps rotate right
+    pub(crate) fn rotate(&self, key: &EvaluationKey, ciphertext: &Pair, steps: i64) -> Pair {
+        let steps = steps.rem_euclid(SLOTS as i64) as usize;
+        let mut result = ciphertext.clone();
+        for (i, rotation_key) in key.rotations.iter().enumerate() {
+            if (steps >> i) & 1 == 0 {
+                continue;
+            }
+            let galois = galois_element(1 << i);
+            let mut c0 = self.automorphism(&result.0, galois);
+            let (r0, r1) = self.switch_key(&self.automorphism(&result.1, galois), rotation_key);
+            self.add(&mut c0, &r0);
+            result = (c0, r1);
+        }
+        result
+    }
+
+    // Divide by the prime of the last limb, rounding to a nearby value that agrees with
+    // the original modulo the plaintext modulus, and drop that limb
+    pub(crate) fn divide_by_last(&self, poly: &mut Limbs, last_modulus: usize) {
+        let last_q = self.moduli[last_modulus].q;
+        let mut last = poly.pop().expect("polynomial has limbs");
+        self.moduli[last_modulus].inverse(&mut last);
+        // The correction is plaintext·[x·plaintext^-1] mod last_q, so x - correction is
+        // divisible by last_q and unchanged modulo the plaintext modulus
+        let plaintext_inverse = inverse_mod(self.plaintext % last_q, last_q);
+        let steps: Vec<i64> = last
+            .iter()
+            .map(|x| center(mul_mod(*x, plaintext_inverse, last_q), last_q))
+            .collect();
+
+        for (i, limb) in poly.iter_mut().enumerate() {
+            let modulus = &self.moduli[i];
+            let plaintext = self.plaintext % modulus.q;
+            let mut correction: Vec<u64> = steps
+                .iter()
+                .map(|step| mul_mod(reduce(*step, modulus.q), plaintext, modulus.q))
+                .collect();
+            modulus.forward(&mut correction);
+            let last_inverse = inverse_mod(last_q % modulus.q, modulus.q);
+            for (x, c) in limb.iter_mut().zip(&correction) {
+                *x = mul_mod(sub_mod(*x, *c, modulus.q), last_inverse, modulus.q);
+            }
+        }
+    }
+
+    // Key for turning a ciphertext component under `source` into one under s. Digit j
+    // encrypts P·g_j·source, where g_j is 1 mod q_j and 0 mod every other ciphertext
+    // prime, so the digits of any lower level work with the same key.
+    fn switching_key(&self, secret: &Limbs, source: &Limbs) -> SwitchingKey {
+        let special = self.special();
+        let p = self.moduli[special].q;
+        let digits = self
+            .ciphertext_limbs()
+            .map(|j| {
+                let a: Limbs = self.moduli.iter().map(Modulus::uniform).collect();
+                let mut b = self.to_limbs(&self.noise(), 0..special + 1);
+                for (i, modulus) in self.moduli.iter().enumerate() {
+                    let q = modulus.q;
+                    for ((x, a_k), s_k) in b[i].iter_mut().zip(&a[i]).zip(&secret[i]) {
+                        *x = sub_mod(*x, mul_mod(*a_k, *s_k, q), q);
+                    }
+                    if i == j {
+                        let gadget = p % q;
+                        for (x, source) in b[i].iter_mut().zip(&source[i]) {
+                            *x = add_mod(*x, mul_mod(gadget, *source, q), q);
+                        }
+                    }
+                }
+                (b, a)
+            })
+            .collect();
+        SwitchingKey { digits }
+    }
+
+    // (r0, r1) with r0 + r1·s ≈ d·source at d's level
+    fn switch_key(&self, d: &Limbs, key: &SwitchingKey) -> Pair {
+        let special = self.special();
+        let limbs: Vec<usize> = (0..d.len()).chain([special]).collect();
+        let mut acc0 = vec![vec![0u64; DEGREE]; limbs.len()];
+        let mut acc1 = vec![vec![0u64; DEGREE]; limbs.len()];
+
+        for (j, limb) in d.iter().enumerate() {
+            let mut digit = limb.clone();
+            self.moduli[j].inverse(&mut digit);
+            let (b, a) = &key.digits[j];
+
+            for (slot, &i) in limbs.iter().enumerate() {
+                let modulus = &self.moduli[i];
+                let q = modulus.q;
+                let lifted = if i == j {
+                    limb.clone()
+                } else {
+                    let mut lifted: Vec<u64> = digit.iter().map(|x| x % q).collect();
+                    modulus.forward(&mut lifted);
+                    lifted
+                };
+                for ((x, d_k), b_k) in acc0[slot].iter_mut().zip(&lifted).zip(&b[i]) {
+                    *x = add_mod(*x, mul_mod(*d_k, *b_k, q), q);
+                }
+                for ((x, d_k), a_k) in acc1[slot].iter_mut().zip(&lifted).zip(&a[i]) {
+                    *x = add_mod(*x, mul_mod(*d_k, *a_k, q), q);
+                }
+            }
+        }
+
+        self.divide_by_last(&mut acc0, special);
+        self.divide_by_last(&mut acc1, special);
+        (acc0, acc1)
+    }
+
+    // Apply X -> X^galois to every limb
+    fn automorphism(&self, poly: &Limbs, galois: usize) -> Limbs {
+        poly.iter()
+            .enumerate()
+            .map(|(i, limb)| {
+                let modulus = &self.moduli[i];
+                let mut coefficients = limb.clone();
+                modulus.inverse(&mut coefficients);
+                let mut permuted = vec![0u64; DEGREE];
+                for (k, c) in coefficients.iter().enumerate() {
+                    let target = k * galois % (2 * DEGREE);
+                    if target < DEGREE {
+                        permuted[target] = *c;
+                    } else {
+                        permuted[target - DEGREE] = sub_mod(0, *c, modulus.q);
+                    }
+                }
+                modulus.forward(&mut permuted);
+                permuted
+            })
+            .collect()
+    }
+}
+
+// 5^j mod 2N for each slot j; rotating left by one slot maps the root ψ^(5^j) to
+// ψ^(5^(j+1)), in either scheme
+pub(crate) fn slot_exponents() -> Vec<usize> {
+    let mut exponents = Vec::with_capacity(SLOTS);
+    let mut exponent = 1;
+    for _ in 0..SLOTS {
+        exponents.push(exponent);
+        exponent = exponent * 5 % (2 * DEGREE);
+    }
+    exponents
+}
+
+// Galois element rotating the slots left by `steps`
+fn galois_element(steps: usize) -> usize {
+    pow_mod(5, (steps % SLOTS) as u64, 2 * DEGREE as u64) as usize
+}
+
+fn small_automorphism(coefficients: &[i64], galois: usize) -> Vec<i64> {
+    let mut permuted = vec![0; coefficients.len()];
+    for (k, c) in coefficients.iter().enumerate() {
+        let target = k * galois % (2 * DEGREE);
+        if target < DEGREE {
+            permuted[target] = *c;
+        } else {
+            permuted[target - DEGREE] = -*c;
+        }
+    }
+    permuted
+}
+
+fn ternary() -> Vec<i64> {
+    let mut coefficients = Vec::with_capacity(DEGREE);
+    while coefficients.len() < DEGREE {
+        let byte = OsRng.next_u32() & 0xff;
+        if byte < 255 {
+            coefficients.push((byte % 3) as i64 - 1);
+        }
+    }
+    coefficients
+}
@@ -0,0 +1,133 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::iter::Sum;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError};
+
+// Shards per map. Enough that parallel evaluations rarely land on the same lock,
+// few enough that walking every shard for len() stays cheap.
+pub const SHARD_COUNT: usize = 16;
+
+// How often a map's locks were taken, and how often a caller had to wait for one
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LockMetrics {
+    pub acquisitions: u64,
+    pub contended: u64,
+}
+
+impl Sum for LockMetrics {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |total, metrics| Self {
+            acquisitions: total.acquisitions + metrics.acquisitions,
+            contended: total.contended + metrics.contended,
+        })
+    }
+}
+
+// String-keyed map split across independently locked shards. Readers of one shard
+// never block each other, and writers only block the shard their key hashes to.
+pub struct ShardedMap<V> {
+    shards: Vec<RwLock<HashMap<String, V>>>,
+    acquisitions: AtomicU64,
+    contended: AtomicU64,
+}
+
+impl<V: Clone> ShardedMap<V> {
+    pub fn new() -> Self {
+        Self {
+            shards: (0..SHARD_COUNT).map(|_| RwLock::new(HashMap::new())).collect(),
+            acquisitions: AtomicU64::new(0),
+            contended: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<V> {
+        self.read(self.shard(key)).get(key).cloned()
+    }
+
+    pub fn insert(&self, key: String, value: V) -> Option<V> {
+        self.write(self.shard(&key)).insert(key, value)
+    }
+
+    // Change the value under an existing key while holding its shard's write lock.
+    // False if the key is absent or the closure declined the change.
+    pub fn update(&self, key: &str, update: impl FnOnce(&mut V) -> bool) -> bool {
+        self.write(self.shard(key)).get_mut(key).is_some_and(update)
+    }
+
+    pub fn remove(&self, key: &str) -> Option<V> {
+        self.write(self.shard(key)).remove(key)
+    }
+
+    // Snapshot of the keys; entries may come and go while it is taken
+    pub fn keys(&self) -> Vec<String> {
+        self.shards
+            .iter()
+            .flat_map(|shard| self.read(shard).keys().cloned().collect::<Vec<_>>())
+            .collect()
+    }
+
+    // Every entry as of one instant: all shards are read-locked together while it is
+    // taken, so no write lands partway through. Shards are always locked in the same
+    // order and writers hold only one, so this can't deadlock.
+    pub fn snapshot(&self) -> Vec<(String, V)> {
+        let shards: Vec<_> = self.shards.iter().map(|shard| self.read(shard)).collect();
+        shards
+            .iter()
+            .flat_map(|shard| shard.iter().map(|(key, value)| (key.clone(), value.clone())))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| self.read(shard).len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn metrics(&self) -> LockMetrics {
+        LockMetrics {
+            acquisitions: self.acquisitions.load(Ordering::Relaxed),
+            contended: self.contended.load(Ordering::Relaxed),
+        }
+    }
+
+    fn shard(&self, key: &str) -> &RwLock<HashMap<String, V>> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % self.shards.len()]
+    }
+
+    // Try the lock first so waits can be counted, then block like a plain read()
+    fn read<'a>(&self, shard: &'a RwLock<HashMap<String, V>>) -> RwLockReadGuard<'a, HashMap<String, V>> {
+        self.acquisitions.fetch_add(1, Ordering::Relaxed);
+        match shard.try_read() {
+            Ok(guard) => guard,
+            Err(TryLockError::WouldBlock) => {
+                self.contended.fetch_add(1, Ordering::Relaxed);
+                shard.read().unwrap()
+            }
+            Err(TryLockError::Poisoned(e)) => panic!("shard lock poisoned: {}", e),
+        }
+    }
+
+    fn write<'a>(&self, shard: &'a RwLock<HashMap<String, V>>) -> RwLockWriteGuard<'a, HashMap<String, V>> {
+        self.acquisitions.fetch_add(1, Ordering::Relaxed);
+        match shard.try_write() {
+            Ok(guard) => guard,
+            Err(TryLockError::WouldBlock) => {
+                self.contended.fetch_add(1, Ordering::Relaxed);
+                shard.write().unwrap()
+            }
+            Err(TryLockError::Poisoned(e)) => panic!("shard lock poisoned: {}", e),
+        }
+    }
+}
+
+impl<V: Clone> Default for ShardedMap<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,116 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use super::fingerprint::to_hex;
+
+// The standard AWS_* credentials, for requests signed with SigV4
+pub struct AwsCredentials {
+    pub region: String,
+    access_key_id: String,
+    secret_access_key: Zeroizing<String>,
+    session_token: Option<String>,
+}
+
+impl AwsCredentials {
+    pub fn from_env() -> Result<Self> {
+        let required = |name: &str| std::env::var(name).map_err(|_| anyhow!("{} is not set", name));
+        Ok(Self {
+            region: required("AWS_REGION")?,
+            access_key_id: required("AWS_ACCESS_KEY_ID")?,
+            secret_access_key: Zeroizing::new(required("AWS_SECRET_ACCESS_KEY")?),
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+        })
+    }
+
+    // Sign a request to an AWS service, returning the headers to send with it. The
+    // headers given must have lowercase names and include host; the returned ones add
+    // x-amz-date, the session token if any, and authorization.
+    pub fn sign(
+        &self,
+        service: &str,
+        method: &str,
+        path: &str,
+        mut headers: Vec<(&'static str, String)>,
+        body: &[u8],
+    ) -> Vec<(&'static str, String)> {
+        let (amz_date, date) = aws_timestamp(SystemTime::now());
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, service);
+
+        headers.push(("x-amz-date", amz_date.clone()));
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        headers.sort_by_key(|(name, _)| *name);
+
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value))
+            .collect();
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_request = format!(
+            "{}\n{}\n\n{}\n{}\n{}",
+            method,
+            path,
+            canonical_headers,
+            signed_headers,
+            to_hex(&Sha256::digest(body))
+        );
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            to_hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let secret = Zeroizing::new(format!("AWS4{}", self.secret_access_key.as_str()));
+        let signing_key = [service, "aws4_request"].iter().fold(
+            hmac(&hmac(secret.as_bytes(), &date), &self.region),
+            |key, part| hmac(&key, part),
+        );
+        let signature = to_hex(&hmac(&signing_key, &string_to_sign));
+
+        headers.push((
+            "authorization",
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                self.access_key_id, scope, signed_headers, signature
+            ),
+        ));
+        headers
+    }
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+// SigV4 wants `YYYYMMDDTHHMMSSZ` and `YYYYMMDD` in UTC
+fn aws_timestamp(now: SystemTime) -> (String, String) {
+    let secs = now.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, rem) = ((secs / 86_400) as i64, secs % 86_400);
+
+    // Civil-from-days conversion (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    let date = format!("{:04}{:02}{:02}", year, month, day);
+    let time = format!("{:02}{:02}{:02}", rem / 3_600, (rem % 3_600) / 60, rem % 60);
+    (format!("{}T{}Z", date, time), date)
+}
@@ -0,0 +1,160 @@
+use anyhow::{anyhow, Result};
+use rayon::prelude::*;
+use tfhe::prelude::FheTryTrivialEncrypt;
+use tfhe::{FheBool, FheUint8, ServerKey};
+
+use super::operations;
+use crate::cancellation::Cancellation;
+
+// Longest encrypted string or plaintext candidate MatchString takes, in bytes
+pub const MAX_STRING_LENGTH: usize = 64;
+
+// Most candidates one MatchString call compares against
+pub const MAX_CANDIDATES: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StringMatch {
+    Equals,
+    // Levenshtein distance at most 1: one byte inserted, deleted or changed
+    WithinOneEdit,
+}
+
+// Encrypted flag per candidate for whether it matches the encrypted string, one encrypted
+// byte per element. Every candidate is compared in full, so the work done depends only on
+// the lengths involved, never on the bytes or on which candidate matches. Unless
+// case_sensitive is set, ASCII letters are compared without regard to case.
+pub fn match_candidates(
+    bytes: &[FheUint8],
+    candidates: &[Vec<u8>],
+    kind: StringMatch,
+    case_sensitive: bool,
+    server_key: &ServerKey,
+    cancellation: &Cancellation,
+) -> Result<Vec<FheBool>> {
+    if bytes.len() > MAX_STRING_LENGTH {
+        return Err(anyhow!(
+            "String is {} bytes, the limit is {}",
+            bytes.len(),
+            MAX_STRING_LENGTH
+        ));
+    }
+    if candidates.len() > MAX_CANDIDATES {
+        return Err(anyhow!(
+            "{} candidates given, the limit is {}",
+            candidates.len(),
+            MAX_CANDIDATES
+        ));
+    }
+    if let Some(candidate) = candidates
+        .iter()
+        .find(|candidate| candidate.len() > MAX_STRING_LENGTH)
+    {
+        return Err(anyhow!(
+            "A candidate is {} bytes, the limit is {}",
+            candidate.len(),
+            MAX_STRING_LENGTH
+        ));
+    }
+
+    let with_key = || tfhe::set_server_key(server_key.clone());
+    let lowered: Vec<FheUint8>;
+    let (bytes, candidates) = match case_sensitive {
+        true => (bytes, candidates.to_vec()),
+        false => {
+            // One lookup bootstrap per byte lowers the string; the candidates are in the clear
+            let table = lowercase_table();
+            lowered = bytes
+                .par_iter()
+                .map_init(with_key, |_, byte| operations::integer_lookup(byte, &table))
+                .collect();
+            let candidates = candidates
+                .iter()
+                .map(|candidate| candidate.to_ascii_lowercase())
+                .collect();
+            (lowered.as_slice(), candidates)
+        }
+    };
+    candidates
+        .par_iter()
+        .map_init(with_key, |_, candidate| {
+            cancellation.check()?;
+            match kind {
+                StringMatch::Equals => equals(bytes, candidate),
+                StringMatch::WithinOneEdit => within_one_edit(bytes, candidate),
+            }
+        })
+        .collect()
+}
+
+fn lowercase_table() -> [u8; 256] {
+    let mut table = [0; 256];
+    for (byte, entry) in table.iter_mut().enumerate() {
+        *entry = (byte as u8).to_ascii_lowercase();
+    }
+    table
+}
+
+fn trivial(value: bool) -> Result<FheBool> {
+    FheBool::try_encrypt_trivial(value).map_err(|e| anyhow!("Failed to encode result: {}", e))
+}
+
+fn equals(bytes: &[FheUint8], candidate: &[u8]) -> Result<FheBool> {
+    // Lengths are public, so strings of different lengths are different without any work
+    if bytes.len() != candidate.len() {
+        return trivial(false);
+    }
+    let mut equal = trivial(true)?;
+    for (byte, expected) in bytes.iter().zip(candidate) {
+        equal = equal & operations::integer_equal_scalar(byte, *expected);
+    }
+    Ok(equal)
+}
+
+// The strings are within one edit when, for some position i, they agree byte for byte
+// before i and, past the edit, the rest of one lines up with the rest of the other: shifted
+// by one if a byte was inserted or deleted there, or after skipping position i in both if
+// it was changed.
+fn within_one_edit(bytes: &[FheUint8], candidate: &[u8]) -> Result<FheBool> {
+    if bytes.len().abs_diff(candidate.len()) > 1 {
+        return trivial(false);
+    }
+    let aligned: Vec<FheBool> = bytes
+        .iter()
+        .zip(candidate)
+        .map(|(byte, expected)| operations::integer_equal_scalar(byte, *expected))
+        .collect();
+    // Agreement of the bytes after the edit, rest[i] pairing the bytes on either side that
+    // follow an edit at position i
+    let rest: Vec<FheBool> = match bytes.len().cmp(&candidate.len()) {
+        std::cmp::Ordering::Equal => aligned.iter().skip(1).cloned().collect(),
+        std::cmp::Ordering::Greater => bytes[1..]
+            .iter()
+            .zip(candidate)
+            .map(|(byte, expected)| operations::integer_equal_scalar(byte, *expected))
+            .collect(),
+        std::cmp::Ordering::Less => bytes
+            .iter()
+            .zip(&candidate[1..])
+            .map(|(byte, expected)| operations::integer_equal_scalar(byte, *expected))
+            .collect(),
+    };
+
+    // prefix[i]: every aligned byte before i agrees; suffix[i]: every rest byte from i on does
+    let mut prefix = vec![trivial(true)?];
+    for agrees in &aligned[..rest.len()] {
+        let before = prefix.last().unwrap();
+        prefix.push(before & agrees);
+    }
+    let mut suffix = vec![trivial(true)?];
+    for agrees in rest.iter().rev() {
+        let after = suffix.last().unwrap();
+        suffix.push(agrees & after);
+    }
+    suffix.reverse();
+
+    let mut within = trivial(false)?;
+    for (before, after) in prefix.iter().zip(&suffix) {
+        within = within | (before & after);
+    }
+    Ok(within)
+}
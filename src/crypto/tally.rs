@@ -0,0 +1,50 @@
+use std::borrow::Borrow;
+
+use anyhow::{anyhow, Result};
+use tfhe::prelude::FheTryTrivialEncrypt;
+use tfhe::FheUint8;
+
+use super::operations;
+use crate::cancellation::Cancellation;
+
+// Most options a ballot can have, so the sum of its choices can't wrap past 2^8
+pub const MAX_OPTIONS: usize = 255;
+
+// Most ballots an election accepts, so no 8-bit tally can wrap
+pub const MAX_BALLOTS: u64 = 255;
+
+// What a ballot adds to each option's tally: its choices if they form a one-hot vote,
+// all zeros otherwise. The check runs on ciphertexts, so a malformed ballot is dropped
+// without the server learning that it was malformed or what it said.
+// The caller must have installed the server key for the current thread.
+pub fn contribution<C: Borrow<FheUint8>>(
+    choices: &[C],
+    cancellation: &Cancellation,
+) -> Result<Vec<FheUint8>> {
+    if choices.is_empty() || choices.len() > MAX_OPTIONS {
+        return Err(anyhow!("A ballot needs between 1 and {} choices", MAX_OPTIONS));
+    }
+
+    // 1 where a choice is 0 or 1, 0 where it is anything else
+    let mut is_bit = [0u8; 256];
+    is_bit[..2].fill(1);
+
+    let zero = FheUint8::try_encrypt_trivial(0u8).map_err(|e| anyhow!("Failed to encode zero: {}", e))?;
+    let (mut votes, mut bits) = (zero.clone(), zero.clone());
+    for choice in choices {
+        cancellation.check()?;
+        votes = operations::integer_add(&votes, choice.borrow());
+        bits = operations::integer_add(&bits, &operations::integer_lookup(choice.borrow(), &is_bit));
+    }
+    // Every choice is a bit and exactly one is set
+    let valid = operations::integer_equal_scalar(&votes, 1)
+        & operations::integer_equal_scalar(&bits, choices.len() as u8);
+
+    choices
+        .iter()
+        .map(|choice| {
+            cancellation.check()?;
+            Ok(operations::integer_select(&valid, choice.borrow(), &zero))
+        })
+        .collect()
+}
@@ -0,0 +1,124 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tfhe::prelude::{
+    FheDecrypt, FheEq, FheMax, FheMin, FheOrd, FheTryEncrypt, FheTryTrivialEncrypt, IfThenElse,
+};
+use tfhe::{ClientKey, FheBool, FheUint32, FheUint8};
+
+// Most boundaries BucketTimestamp takes; the bucket index has to fit a FheUint8
+pub const MAX_BUCKET_BOUNDARIES: usize = 255;
+
+// What a timestamp counts since 1970-01-01T00:00:00Z
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TimeUnit {
+    Days,
+    Seconds,
+}
+
+// How a timestamp relates to the one it is compared with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Before,
+    AtOrBefore,
+    At,
+    AtOrAfter,
+    After,
+}
+
+// A date or instant as a count of units since the Unix epoch, or a duration in those
+// units. Seconds reach 2106-02-07; days outlast any date of interest. Timestamps only
+// meet others in the same unit, so a count of days is never read as seconds.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EncryptedTimestamp {
+    unit: TimeUnit,
+    value: FheUint32,
+}
+
+impl EncryptedTimestamp {
+    pub fn encrypt(client_key: &ClientKey, unit: TimeUnit, value: u32) -> Result<Self> {
+        let value =
+            FheUint32::try_encrypt(value, client_key).map_err(|e| anyhow!("Encryption failed: {}", e))?;
+        Ok(Self { unit, value })
+    }
+
+    pub fn decrypt(&self, client_key: &ClientKey) -> u32 {
+        self.value.decrypt(client_key)
+    }
+
+    pub fn unit(&self) -> TimeUnit {
+        self.unit
+    }
+
+    // Against another timestamp in the same unit. This and the operations below evaluate
+    // against the thread-local server key.
+    pub fn compare(&self, other: &Self, comparison: Comparison) -> Result<FheBool> {
+        self.check_unit(other)?;
+        let (a, b) = (&self.value, &other.value);
+        Ok(match comparison {
+            Comparison::Before => a.lt(b),
+            Comparison::AtOrBefore => a.le(b),
+            Comparison::At => FheEq::eq(a, b),
+            Comparison::AtOrAfter => a.ge(b),
+            Comparison::After => a.gt(b),
+        })
+    }
+
+    // Against a plaintext timestamp in this one's unit, such as the start of a retention window
+    pub fn compare_plaintext(&self, other: u32, comparison: Comparison) -> FheBool {
+        let a = &self.value;
+        match comparison {
+            Comparison::Before => a.lt(other),
+            Comparison::AtOrBefore => a.le(other),
+            Comparison::At => FheEq::eq(a, other),
+            Comparison::AtOrAfter => a.ge(other),
+            Comparison::After => a.gt(other),
+        }
+    }
+
+    // Time between the two, whichever comes first, as a duration in their unit
+    pub fn difference(&self, other: &Self) -> Result<Self> {
+        self.check_unit(other)?;
+        let later = FheMax::max(&self.value, &other.value);
+        let earlier = FheMin::min(&self.value, &other.value);
+        Ok(Self {
+            unit: self.unit,
+            value: later - earlier,
+        })
+    }
+
+    // Index of the bucket the timestamp falls in: how many of the ascending plaintext
+    // boundaries it is at or after. Each boundary costs a comparison, a selection and an
+    // addition, so the table stays short.
+    pub fn bucket(&self, boundaries: &[u32]) -> Result<FheUint8> {
+        if boundaries.len() > MAX_BUCKET_BOUNDARIES {
+            return Err(anyhow!(
+                "{} boundaries given, the limit is {}",
+                boundaries.len(),
+                MAX_BUCKET_BOUNDARIES
+            ));
+        }
+        if boundaries.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(anyhow!("Boundaries must be strictly ascending"));
+        }
+
+        let trivial =
+            |value: u8| FheUint8::try_encrypt_trivial(value).map_err(|e| anyhow!("Encoding failed: {}", e));
+        let (one, zero) = (trivial(1)?, trivial(0)?);
+        let mut index = trivial(0)?;
+        for boundary in boundaries {
+            index = index + self.value.ge(*boundary).if_then_else(&one, &zero);
+        }
+        Ok(index)
+    }
+
+    fn check_unit(&self, other: &Self) -> Result<()> {
+        if self.unit != other.unit {
+            return Err(anyhow!(
+                "Timestamps are in different units: {:?} and {:?}",
+                self.unit,
+                other.unit
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,278 @@
+use std::borrow::Borrow;
+
+use anyhow::{anyhow, Result};
+use rayon::prelude::*;
+use tfhe::prelude::FheTryTrivialEncrypt;
+use tfhe::{FheBool, FheUint8, ServerKey};
+
+use super::operations;
+use crate::cancellation::Cancellation;
+
+// Longest vector whose positions fit in the FheUint8 used for encrypted indices
+pub const MAX_INDEXED_LENGTH: usize = 256;
+
+// An encrypted vector element together with its encrypted position
+#[derive(Clone)]
+pub struct Ranked {
+    pub value: FheUint8,
+    pub index: FheUint8,
+}
+
+// Comparator pairs of Batcher's odd-even merge sort over n elements, in execution order.
+// Each pair (i, j) has i < j and leaves the smaller value at i. The schedule depends only
+// on n, so running it reveals nothing about the encrypted values.
+pub fn sorting_network(n: usize) -> Vec<(usize, usize)> {
+    let mut comparators = Vec::new();
+    let mut p = 1;
+    while p < n {
+        let mut k = p;
+        while k >= 1 {
+            let mut j = k % p;
+            while j + k < n {
+                for i in 0..k.min(n - j - k) {
+                    if (i + j) / (2 * p) == (i + j + k) / (2 * p) {
+                        comparators.push((i + j, i + j + k));
+                    }
+                }
+                j += 2 * k;
+            }
+            k /= 2;
+        }
+        p *= 2;
+    }
+    comparators
+}
+
+// Sort encrypted integers ascending with an oblivious sorting network.
+// The caller must have installed the server key for the current thread.
+pub fn sort(mut values: Vec<FheUint8>, cancellation: &Cancellation) -> Result<Vec<FheUint8>> {
+    for (i, j) in sorting_network(values.len()) {
+        cancellation.check()?;
+        let low = operations::integer_min(&values[i], &values[j]);
+        let high = operations::integer_max(&values[i], &values[j]);
+        values[i] = low;
+        values[j] = high;
+    }
+    Ok(values)
+}
+
+// Encrypted maximum and its index, found with a single-elimination tournament.
+// Ties go to the lowest index. The caller must have installed the server key for the current thread.
+pub fn argmax(values: Vec<FheUint8>, cancellation: &Cancellation) -> Result<Ranked> {
+    let mut round = with_indices(values)?;
+    while round.len() > 1 {
+        cancellation.check()?;
+        let mut winners = Vec::with_capacity(round.len().div_ceil(2));
+        let mut entrants = round.into_iter();
+        while let Some(left) = entrants.next() {
+            match entrants.next() {
+                Some(right) => winners.push(larger(left, right)),
+                None => winners.push(left),
+            }
+        }
+        round = winners;
+    }
+    round.pop().ok_or_else(|| anyhow!("Vector is empty"))
+}
+
+// The k largest elements with their indices, largest first. Runs the sorting network
+// descending over the whole vector, so the cost does not depend on k beyond k = 1.
+pub fn top_k(values: Vec<FheUint8>, k: usize, cancellation: &Cancellation) -> Result<Vec<Ranked>> {
+    if k == 1 {
+        return argmax(values, cancellation).map(|winner| vec![winner]);
+    }
+
+    let mut ranked = with_indices(values)?;
+    for (i, j) in sorting_network(ranked.len()) {
+        cancellation.check()?;
+        let i_wins = operations::integer_greater_or_equal(&ranked[i].value, &ranked[j].value);
+        let first = select(&i_wins, &ranked[i], &ranked[j]);
+        let second = select(&i_wins, &ranked[j], &ranked[i]);
+        ranked[i] = first;
+        ranked[j] = second;
+    }
+    ranked.truncate(k);
+    Ok(ranked)
+}
+
+fn with_indices(values: Vec<FheUint8>) -> Result<Vec<Ranked>> {
+    if values.is_empty() {
+        return Err(anyhow!("Vector is empty"));
+    }
+    if values.len() > MAX_INDEXED_LENGTH {
+        return Err(anyhow!(
+            "Vector has {} elements, at most {} can be indexed",
+            values.len(),
+            MAX_INDEXED_LENGTH
+        ));
+    }
+
+    values
+        .into_iter()
+        .enumerate()
+        .map(|(index, value)| {
+            // Positions are public, so a trivial encryption is enough
+            let index = FheUint8::try_encrypt_trivial(index as u8)
+                .map_err(|e| anyhow!("Failed to encode index: {}", e))?;
+            Ok(Ranked { value, index })
+        })
+        .collect()
+}
+
+// The left entrant wins ties, keeping the lower index
+fn larger(left: Ranked, right: Ranked) -> Ranked {
+    let left_wins = operations::integer_greater_or_equal(&left.value, &right.value);
+    select(&left_wins, &left, &right)
+}
+
+fn select(condition: &FheBool, a: &Ranked, b: &Ranked) -> Ranked {
+    Ranked {
+        value: operations::integer_select(condition, &a.value, &b.value),
+        index: operations::integer_select(condition, &a.index, &b.index),
+    }
+}
+
+// Combine values pairwise in a balanced tree, n values in log2(n) rounds instead of n - 1
+// steps. The pairs of each round run in parallel on the rayon pool, each worker with its
+// own copy of the server key. `combine` must be associative; the order is kept, so it
+// need not commute.
+pub fn reduce_tree<T, F>(
+    values: Vec<T>,
+    server_key: &ServerKey,
+    cancellation: &Cancellation,
+    combine: F,
+) -> Result<T>
+where
+    T: Clone + Send + Sync,
+    F: Fn(&T, &T) -> T + Sync,
+{
+    let mut round = values;
+    while round.len() > 1 {
+        cancellation.check()?;
+        round = round
+            .par_chunks(2)
+            .map_init(
+                || tfhe::set_server_key(server_key.clone()),
+                |_, pair| match pair {
+                    [left, right] => combine(left, right),
+                    // The odd one out goes through to the next round
+                    [left] => left.clone(),
+                    _ => unreachable!(),
+                },
+            )
+            .collect();
+    }
+    round.pop().ok_or_else(|| anyhow!("Nothing to reduce"))
+}
+
+// `combine` applied to each element and the one after it, values[i] and values[i + 1], all
+// pairs in parallel; one shorter than values
+pub fn pairwise<T, F>(
+    values: &[T],
+    server_key: &ServerKey,
+    cancellation: &Cancellation,
+    combine: F,
+) -> Result<Vec<T>>
+where
+    T: Send + Sync,
+    F: Fn(&T, &T) -> T + Sync,
+{
+    values
+        .par_windows(2)
+        .map_init(
+            || tfhe::set_server_key(server_key.clone()),
+            |_, pair| {
+                cancellation.check()?;
+                Ok(combine(&pair[0], &pair[1]))
+            },
+        )
+        .collect()
+}
+
+// Running combination of values, element i combining values[0] through values[i]. A
+// Hillis-Steele scan: in round r every element is combined with the one 2^r places before
+// it, in parallel, so n values take log2(n) rounds instead of n - 1 steps. Like
+// reduce_tree, `combine` must be associative and the order is kept.
+pub fn scan<T, F>(
+    values: Vec<T>,
+    server_key: &ServerKey,
+    cancellation: &Cancellation,
+    combine: F,
+) -> Result<Vec<T>>
+where
+    T: Clone + Send + Sync,
+    F: Fn(&T, &T) -> T + Sync,
+{
+    let mut round = values;
+    let mut distance = 1;
+    while distance < round.len() {
+        cancellation.check()?;
+        round = (0..round.len())
+            .into_par_iter()
+            .map_init(
+                || tfhe::set_server_key(server_key.clone()),
+                |_, i| match i.checked_sub(distance) {
+                    Some(before) => combine(&round[before], &round[i]),
+                    None => round[i].clone(),
+                },
+            )
+            .collect();
+        distance *= 2;
+    }
+    Ok(round)
+}
+
+// Encrypted flag for whether the value equals any of the encrypted or plaintext elements.
+// Every element is compared and the results are OR-reduced pairwise, so the work done
+// does not depend on whether or where a match occurs.
+pub fn contains<E: Borrow<FheUint8>>(
+    value: &FheUint8,
+    elements: &[E],
+    plaintext_elements: &[u8],
+) -> Result<FheBool> {
+    let mut matches: Vec<FheBool> = elements
+        .iter()
+        .map(|element| operations::integer_equal(value, element.borrow()))
+        .chain(
+            plaintext_elements
+                .iter()
+                .map(|element| operations::integer_equal_scalar(value, *element)),
+        )
+        .collect();
+
+    if matches.is_empty() {
+        return FheBool::try_encrypt_trivial(false).map_err(|e| anyhow!("Failed to encode result: {}", e));
+    }
+
+    // Balanced OR tree: depth grows with the log of the set size
+    while matches.len() > 1 {
+        let mut pairs = matches.into_iter();
+        let mut reduced = Vec::with_capacity(pairs.len().div_ceil(2));
+        while let Some(left) = pairs.next() {
+            match pairs.next() {
+                Some(right) => reduced.push(left | right),
+                None => reduced.push(left),
+            }
+        }
+        matches = reduced;
+    }
+    matches.pop().ok_or_else(|| anyhow!("Set is empty"))
+}
+
+// Encrypted element of a plaintext table at an encrypted index, for private lookups.
+// The whole table is folded into a single programmable bootstrap, so the work done is the
+// same for every index; indices past the end of the table select 0.
+// The caller must have installed the server key for the current thread.
+pub fn select_plaintext(index: &FheUint8, table: &[u8]) -> Result<FheUint8> {
+    if table.len() > MAX_INDEXED_LENGTH {
+        return Err(anyhow!(
+            "Table has {} elements, at most {} can be indexed",
+            table.len(),
+            MAX_INDEXED_LENGTH
+        ));
+    }
+
+    let mut padded = [0u8; MAX_INDEXED_LENGTH];
+    padded[..table.len()].copy_from_slice(table);
+    Ok(operations::integer_lookup(index, &padded))
+}
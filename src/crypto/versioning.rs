@@ -0,0 +1,132 @@
+use std::borrow::Cow;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+// The tfhe-rs series this build serializes keys and ciphertexts with, pinned in build.rs
+// alongside the tfhe requirement
+pub const TFHE_SERIES: &str = env!("HERMETIC_FHE_TFHE_SERIES");
+
+// The release of that series in Cargo.lock, or the series alone when there is no lock file
+pub const TFHE_VERSION: &str = env!("HERMETIC_FHE_TFHE_VERSION");
+
+// The parameters ConfigBuilder::default picks in tfhe-rs 0.5, which every parameter set
+// uses for now
+pub const TFHE_PARAMETERS: &str = "PARAM_MESSAGE_2_CARRY_2_KS_PBS";
+
+// The series every key and ciphertext written before envelopes came from
+const LEGACY_SERIES: &str = "0.5";
+
+// Starts every envelope. Read as the length bincode puts in front of a key bundle's or
+// checkpoint's first field, it is far larger than any file, so it can't be mistaken for
+// data from before envelopes.
+const MAGIC: [u8; 8] = *b"HFVE\xff\xff\xff\xff";
+
+// Converts a payload written by an older series into what this build reads. tfhe-rs only
+// versions its data from 0.7 on; until then an upgrade across a series registers here how
+// the payloads of the series before it are read, decoding them with the old release's
+// forward-compatibility conversions, before the dependency moves.
+type Migration = fn(&[u8]) -> Result<Vec<u8>>;
+
+// Series this build can read besides its own, with how. Empty while every stored key and
+// ciphertext was written by the series in use.
+const MIGRATIONS: &[(&str, Migration)] = &[];
+
+// Which tfhe-rs release and parameters wrote some persisted data
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DataVersion {
+    pub tfhe: String,
+    pub parameters: String,
+}
+
+impl DataVersion {
+    pub fn current() -> Self {
+        Self {
+            tfhe: TFHE_VERSION.to_string(),
+            parameters: TFHE_PARAMETERS.to_string(),
+        }
+    }
+
+    // Data that predates envelopes, or a backup header without a version
+    pub fn legacy() -> Self {
+        Self {
+            tfhe: LEGACY_SERIES.to_string(),
+            parameters: TFHE_PARAMETERS.to_string(),
+        }
+    }
+
+    // tfhe-rs keeps its serialization stable within a 0.x series, so patch releases of the
+    // series in use read each other's data as it is
+    pub fn is_current(&self) -> bool {
+        series(&self.tfhe) == TFHE_SERIES
+    }
+
+    // Whether this build can read the data at all, directly or through a migration
+    pub fn check(&self) -> Result<()> {
+        if self.is_current() || migration(&self.tfhe).is_some() {
+            return Ok(());
+        }
+        Err(anyhow!(
+            "Data written by tfhe-rs {} can't be read by this build, which uses tfhe-rs {} and \
+             migrates nothing from that release",
+            self.tfhe,
+            TFHE_VERSION
+        ))
+    }
+}
+
+// Major and minor version, the part that changes when the serialization may
+fn series(version: &str) -> &str {
+    match version.match_indices('.').nth(1) {
+        Some((end, _)) => &version[..end],
+        None => version,
+    }
+}
+
+fn migration(version: &str) -> Option<Migration> {
+    MIGRATIONS
+        .iter()
+        .find(|(from, _)| *from == series(version))
+        .map(|(_, migrate)| *migrate)
+}
+
+// The payload with the current version in front
+pub fn seal(payload: &[u8]) -> Result<Vec<u8>> {
+    let version =
+        bincode::serialize(&DataVersion::current()).map_err(|e| anyhow!("Failed to encode data version: {}", e))?;
+    let mut bytes = Vec::with_capacity(MAGIC.len() + version.len() + payload.len());
+    bytes.extend_from_slice(&MAGIC);
+    bytes.extend_from_slice(&version);
+    bytes.extend_from_slice(payload);
+    Ok(bytes)
+}
+
+// The version an envelope records and the payload after it. Bytes without an envelope
+// predate them and are returned whole as legacy data.
+pub fn open(bytes: &[u8]) -> Result<(DataVersion, &[u8])> {
+    let Some(rest) = bytes.strip_prefix(&MAGIC) else {
+        return Ok((DataVersion::legacy(), bytes));
+    };
+    let version: DataVersion =
+        bincode::deserialize(rest).map_err(|e| anyhow!("Invalid data version: {}", e))?;
+    let length = bincode::serialized_size(&version).map_err(|e| anyhow!("Invalid data version: {}", e))?;
+    Ok((version, &rest[length as usize..]))
+}
+
+// A payload written by the given version, as this build reads it
+pub fn migrate<'a>(version: &DataVersion, payload: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+    if version.is_current() {
+        return Ok(Cow::Borrowed(payload));
+    }
+    version.check()?;
+    let migrate = migration(&version.tfhe).expect("checked above");
+    Ok(Cow::Owned(migrate(payload)?))
+}
+
+// open and migrate in one, also saying whether the data was written by an older series
+// and is worth writing back in the current one
+pub fn read(bytes: &[u8]) -> Result<(Cow<'_, [u8]>, bool)> {
+    let (version, payload) = open(bytes)?;
+    let stale = !version.is_current() || !bytes.starts_with(&MAGIC);
+    Ok((migrate(&version, payload)?, stale))
+}
@@ -1,3 +1,16 @@
+#[cfg(feature = "server")]
 pub mod api;
+#[cfg(feature = "circuit")]
+pub mod backend;
+pub mod cancellation;
+#[cfg(feature = "circuit")]
+pub mod circuit;
+#[cfg(feature = "client")]
+pub mod client;
 pub mod crypto;
-pub mod service; 
\ No newline at end of file
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "server")]
+pub mod service;
+#[cfg(feature = "wasm")]
+pub mod wasm;
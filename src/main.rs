@@ -1,41 +1,498 @@
+use std::path::Path;
 use std::sync::Arc;
-use tonic::transport::Server;
-use tracing::{info, Level};
-use tracing_subscriber::FmtSubscriber;
+use std::time::Duration;
+use tonic::service::interceptor::InterceptedService;
+use tonic_web::GrpcWebLayer;
+use tracing::{error, info, warn};
+use tracing_subscriber::{EnvFilter, FmtSubscriber};
 
-mod api;
-mod crypto;
-mod service;
-
-use api::FheServiceServer;
-use crypto::{KeyStore, CiphertextStore};
-use service::FheServiceImpl;
+use hermetic_fhe::api::FheAdminServiceServer;
+use hermetic_fhe::backend::self_test::{self, SelfTestConfig};
+use hermetic_fhe::circuit::cost::{self, CostModel};
+use hermetic_fhe::crypto::{KeyStore, CiphertextStore};
+use hermetic_fhe::crypto::compression::CompressionConfig;
+use hermetic_fhe::crypto::deterministic::Determinism;
+use hermetic_fhe::crypto::fingerprint::to_hex;
+use hermetic_fhe::crypto::key_directory::{KeyDirectory, KeyPreload, KeyUnloading};
+use hermetic_fhe::crypto::key_pool::KeyPoolConfig;
+use hermetic_fhe::crypto::kms;
+use hermetic_fhe::crypto::retention::RetentionConfig;
+use hermetic_fhe::service::admin::{AdminAuth, FheAdminServiceImpl};
+use hermetic_fhe::service::admission::{AdmissionConfig, AdmissionControl, FairShareConfig};
+use hermetic_fhe::service::attestation::Attestor;
+use hermetic_fhe::service::authorization;
+use hermetic_fhe::service::checkpoint::CheckpointPolicy;
+use hermetic_fhe::service::events::{self, NatsConfig};
+use hermetic_fhe::service::journal::JobJournal;
+use hermetic_fhe::service::lease::ResultLeases;
+use hermetic_fhe::service::FheServiceImpl;
+use hermetic_fhe::service::interceptors::{fhe_service_server, Interceptors};
+use hermetic_fhe::service::legacy::LegacyService;
+use hermetic_fhe::service::listen::{self, ListenFlags};
+use hermetic_fhe::service::logging::CallLogLayer;
+use hermetic_fhe::service::memory::MemoryLimit;
+use hermetic_fhe::service::oidc::{OidcAuth, OidcConfig, OidcVerifier};
+use hermetic_fhe::service::privacy::{PrivacyConfig, PrivacyLedger, LEDGER_FILE};
+use hermetic_fhe::service::reload::{self, LogLevelSetter, RuntimeConfigFile, DEFAULT_LOG_LEVEL};
+use hermetic_fhe::service::sink::SinkPolicy;
+use hermetic_fhe::service::transport::{TlsIdentity, TransportConfig};
+use hermetic_fhe::service::usage::UsageExport;
+use hermetic_fhe::service::webhook::WebhookPolicy;
+use hermetic_fhe::service::web;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize tracing
-    let subscriber = FmtSubscriber::builder()
-        .with_max_level(Level::INFO)
-        .finish();
-    tracing::subscriber::set_global_default(subscriber)?;
-
-    // Initialize FHE service stores
-    let key_store = Arc::new(KeyStore::new());
-    let ciphertext_store = Arc::new(CiphertextStore::new());
+    // Initialize tracing, with a filter the runtime config can change later
+    let builder = FmtSubscriber::builder()
+        .with_env_filter(EnvFilter::new(DEFAULT_LOG_LEVEL))
+        .with_filter_reloading();
+    let log_filter = builder.reload_handle();
+    tracing::subscriber::set_global_default(builder.finish())?;
+    let set_log_level: LogLevelSetter = Box::new(move |level: &str| {
+        log_filter.reload(EnvFilter::try_new(level)?)?;
+        Ok(())
+    });
+
+    // Checked before anything slow, so a mistyped flag fails straight away
+    let listeners = ListenFlags::parse(std::env::args().skip(1))?.listeners()?;
+    let transport = TransportConfig::from_env()?;
+    let tls = TlsIdentity::from_env()?;
+
+    // Optionally prove each backend decrypts a sum correctly on this machine before serving,
+    // so a broken build or CPU path never sees user data
+    if let Some(config) = SelfTestConfig::from_env()? {
+        for check in self_test::run(&config)? {
+            info!(
+                "Self-test passed for {}: key generation took {:?}, encrypt, add and decrypt {:?}",
+                check.name(),
+                check.key_generation,
+                check.round_trip
+            );
+        }
+    }
+
+    // Initialize FHE service stores; client keys are sealed under the master key
+    let master_key_provider = kms::provider_from_env()?;
+    info!("Loading master key from {}", master_key_provider.describe());
+    let mut key_store = KeyStore::from_provider(master_key_provider.as_ref())?;
+
+    // Test-only: every key, ciphertext and ID is derived from a seed, so a reported
+    // failure can be reproduced exactly. Keys made this way are not secret.
+    let determinism = Determinism::from_env()?.map(Arc::new);
+    if let Some(determinism) = &determinism {
+        if std::env::var("HERMETIC_FHE_KEY_DIR").is_ok() {
+            return Err("HERMETIC_FHE_DETERMINISTIC_SEED cannot be used with HERMETIC_FHE_KEY_DIR".into());
+        }
+        warn!(
+            "Deterministic mode with seed {}: keys are reproducible by anyone who knows it",
+            determinism.seed()
+        );
+        key_store = key_store.with_determinism(determinism.clone());
+    }
+
+    // Pairs generated ahead of requests would break the replay order
+    let key_pool = KeyPoolConfig::from_env()?;
+    if let Some(pool) = &key_pool {
+        if determinism.is_some() {
+            return Err("HERMETIC_FHE_KEY_POOL_SIZE cannot be used with HERMETIC_FHE_DETERMINISTIC_SEED"
+                .into());
+        }
+        key_store = key_store.with_key_pool(pool.clone());
+    }
+
+    // Unloaded keys are reloaded from the key directory, so there has to be one
+    let key_unloading = KeyUnloading::from_env()?;
+    if key_unloading.is_some() && std::env::var("HERMETIC_FHE_KEY_DIR").is_err() {
+        return Err("HERMETIC_FHE_UNLOAD_IDLE_KEYS_AFTER_SECONDS requires HERMETIC_FHE_KEY_DIR".into());
+    }
+
+    // Persist key pairs and load the selected ones up front, so the first requests after a
+    // deploy don't pay to deserialize server keys or spin up the worker pool
+    if let Ok(key_dir) = std::env::var("HERMETIC_FHE_KEY_DIR") {
+        key_store = key_store.with_key_directory(KeyDirectory::open(key_dir)?);
+        let preloaded = key_store.preload(&KeyPreload::from_env())?;
+        let aliases = key_store.load_aliases()?;
+        if aliases > 0 {
+            info!("Loaded {} key aliases", aliases);
+        }
+        let workers = key_store.warm(&preloaded)?;
+        info!("Warmed {} server keys on {} workers", preloaded.len(), workers);
+    }
+    let key_store = Arc::new(key_store);
+
+    // Top the key pool back up as pairs are handed out, in the background so a request
+    // never waits on the refill
+    if let Some(pool) = key_pool {
+        info!(
+            "Keeping {} key pairs ready for each of {}",
+            pool.size,
+            pool.parameter_sets.join(", ")
+        );
+        let store = key_store.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(1));
+            loop {
+                interval.tick().await;
+                let store = store.clone();
+                match tokio::task::spawn_blocking(move || store.fill_key_pool()).await {
+                    Ok(Ok(generated)) if generated > 0 => info!("Generated {} pooled key pairs", generated),
+                    Ok(Ok(_)) => {}
+                    Ok(Err(e)) => error!("Filling the key pool failed: {}", e),
+                    Err(e) => error!("Filling the key pool failed: {}", e),
+                }
+            }
+        });
+    }
+
+    // Drop server keys nobody has used for a while; they are most of a tenant's memory
+    if let Some(unloading) = key_unloading {
+        info!("Unloading server keys unused for {:?}", unloading.idle_after);
+        let store = key_store.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(unloading.idle_after);
+            loop {
+                interval.tick().await;
+                let store = store.clone();
+                match tokio::task::spawn_blocking(move || store.unload_idle()).await {
+                    Ok(unloaded) if unloaded > 0 => info!("Unloaded {} idle server keys", unloaded),
+                    Ok(_) => {}
+                    Err(e) => error!("Server key unloading failed: {}", e),
+                }
+            }
+        });
+    }
+    let mut ciphertext_store = CiphertextStore::new();
+    if let Some(determinism) = &determinism {
+        ciphertext_store = ciphertext_store.with_determinism(determinism.clone());
+    }
+    let compression = CompressionConfig::from_env()?;
+    if let Some(compression) = compression {
+        ciphertext_store = ciphertext_store.with_compression(compression);
+    }
+    // Deleted ciphertexts stay restorable for a while before they are freed
+    let retention = RetentionConfig::from_env()?;
+    ciphertext_store = ciphertext_store.with_retention(retention);
+    let ciphertext_store = Arc::new(ciphertext_store);
+
+    // Compress ciphertexts nobody has read for a while, since they dominate storage
+    if let Some(compression) = compression {
+        info!(
+            "Compressing ciphertexts unread for {:?} at zstd level {}",
+            compression.cold_after, compression.level
+        );
+        let store = ciphertext_store.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(compression.cold_after);
+            loop {
+                interval.tick().await;
+                let store = store.clone();
+                match tokio::task::spawn_blocking(move || store.compress_cold()).await {
+                    Ok(compressed) if compressed > 0 => info!("Compressed {} cold ciphertexts", compressed),
+                    Ok(_) => {}
+                    Err(e) => error!("Ciphertext compression failed: {}", e),
+                }
+            }
+        });
+    }
+    
+    // Free deleted ciphertexts once their retention window has passed
+    if !retention.retention.is_zero() {
+        info!("Keeping deleted ciphertexts restorable for {:?}", retention.retention);
+        let store = ciphertext_store.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(60));
+            loop {
+                interval.tick().await;
+                let purged = store.purge_deleted();
+                if purged > 0 {
+                    info!("Purged {} deleted ciphertexts", purged);
+                }
+            }
+        });
+    }
+
+    // Create service implementation, bounding how much evaluation work can pile up
+    let admission_config = match determinism {
+        Some(_) => AdmissionConfig::deterministic(),
+        None => AdmissionConfig::from_env()?,
+    };
+    info!(
+        "Running {} evaluations at once with a queue of {}",
+        admission_config.workers, admission_config.queue_depth
+    );
+    // EstimateCost works from built-in gate timings unless asked to measure this machine's,
+    // or given a table of timings measured before
+    let cost_model = CostModel::from_env()?;
+    if cost_model.is_calibrated() {
+        info!("Using gate costs measured on this machine");
+    }
+    // Tenants competing for workers share them by weight; a replayed run keeps arrival order
+    let mut admission = AdmissionControl::new(admission_config);
+    if determinism.is_none() {
+        let fair_share = FairShareConfig::from_env()?;
+        if !fair_share.weights.is_empty() {
+            info!("Sharing workers between tenants with weights {:?}", fair_share.weights);
+        }
+        admission = admission.with_fair_share(fair_share);
+    }
+    let mut service = FheServiceImpl::with_admission_control(key_store, ciphertext_store, admission)
+        .with_cost_model(cost_model)
+        .with_max_message_bytes(transport.max_request_bytes);
+    if let Some(path) = cost::table_path_from_env() {
+        info!("Keeping measured gate costs in {}", path.display());
+        service = service.with_cost_table(path);
+    }
+    // Without a limit the stores grow until the OOM killer steps in
+    if let Some(limit) = MemoryLimit::from_env()? {
+        info!("Limiting stored keys and ciphertexts to {} bytes ({:?} when full)", limit.max_bytes, limit.policy);
+        service = service.with_memory_limit(limit);
+    }
+    // Results not yet fetched outlive their session's timeout or eviction
+    let leases = ResultLeases::from_env()?;
+    info!("Pinning evaluation results in sessions for {:?} until fetched", leases.duration());
+    service = service.with_result_leases(leases);
+    // Map jobs only write results outside the store where the operator allows
+    let sinks = SinkPolicy::from_env()?;
+    if let Some(root) = sinks.root() {
+        info!("Map jobs may write results under {}", root.display());
+    }
+    if !sinks.buckets().is_empty() {
+        info!("Map jobs may write results to S3 buckets {}", sinks.buckets().join(", "));
+    }
+    service = service.with_sink_policy(sinks);
+    // Jobs only notify callbacks under the operator's prefixes, signed with its secret
+    let webhooks = WebhookPolicy::from_env()?;
+    if webhooks.enabled() {
+        info!("Jobs may notify callbacks under {}", webhooks.allowed().join(", "));
+    }
+    service = service.with_webhook_policy(webhooks);
+    let checkpoints = CheckpointPolicy::from_env()?;
+    if let Some(dir) = checkpoints.dir() {
+        info!(
+            "Long jobs may checkpoint to {}, every {:?} by default",
+            dir.display(),
+            checkpoints.default_interval()
+        );
+    }
+    service = service.with_checkpoint_policy(checkpoints);
+    // Jobs given a dedup token run once; with a journal directory, across restarts too
+    let journal = JobJournal::from_env()?;
+    if let Some(dir) = journal.dir() {
+        info!(
+            "Journaling map jobs with dedup tokens in {}, keeping finished jobs for {:?}",
+            dir.display(),
+            journal.retention()
+        );
+    }
+    service = service.with_job_journal(journal);
+    let replayed = service.replay_job_journal()?;
+    if replayed > 0 {
+        info!("Replayed {} journaled map jobs", replayed);
+    }
+    // Noised decryptions spend a per-key budget, kept in the key directory with the keys
+    // if there is one, and otherwise reset when the server restarts
+    let privacy = PrivacyConfig::from_env()?;
+    info!(
+        "Each client key may spend epsilon {} and delta {} on noised decryptions{}",
+        privacy.epsilon,
+        privacy.delta,
+        if privacy.require_noise { "; exact decryptions are refused" } else { "" }
+    );
+    service = match std::env::var("HERMETIC_FHE_KEY_DIR") {
+        Ok(key_dir) => {
+            let ledger = PrivacyLedger::open(privacy, Path::new(&key_dir).join(LEDGER_FILE))?;
+            service.with_privacy_ledger(ledger)
+        }
+        Err(_) => service.with_privacy_config(privacy),
+    };
+    // Inside an enclave or confidential VM, clients can ask for a quote binding the TLS
+    // certificate. One is taken now, so a server that can't produce them fails at startup.
+    if let Some(attestor) = Attestor::from_env(tls.as_ref().map(|tls| tls.certificate.as_slice()))? {
+        let attestation = attestor.attest(b"startup")?;
+        info!(
+            "Attesting on {} with build measurement {}",
+            attestor.platform().name(),
+            to_hex(&attestation.measurement)
+        );
+        service = service.with_attestor(attestor);
+    }
+    // Every call can be put to a policy engine, so operators can express their own rules
+    if let Some(policy) = authorization::policy_from_env()? {
+        info!("Authorizing each call against the configured policy");
+        service = service.with_policy(policy);
+    }
+    // Quotas, allow-lists, parameter defaults, the log level and policy rules can change
+    // without a restart. Both files are applied now, so one that is broken at startup stops
+    // the server.
+    if let Some(runtime_config) = RuntimeConfigFile::from_env(Some(set_log_level)) {
+        info!("Applying runtime settings from {}", runtime_config.path().display());
+        service = service.with_runtime_config(runtime_config);
+    }
+    if let Some(e) = service.reload_config(true).errors.into_iter().next() {
+        return Err(e.into());
+    }
+    if let Some(every) = reload::reload_interval_from_env()? {
+        let reloader = service.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(every);
+            loop {
+                interval.tick().await;
+                let outcome = reloader.reload_config(false);
+                if !outcome.reloaded.is_empty() {
+                    info!("Reloaded the {}", outcome.reloaded.join(" and "));
+                }
+                for e in outcome.errors {
+                    error!("Keeping the previous settings: {}", e);
+                }
+            }
+        });
+    }
+
+    // Periodically free ciphertexts belonging to idle sessions
+    let reaper = service.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            let freed = reaper.reap_expired_sessions();
+            if freed > 0 {
+                info!("Freed {} ciphertexts from expired sessions and ended leases", freed);
+            }
+        }
+    });
     
-    // Create service implementation
-    let service = FheServiceImpl::new(key_store, ciphertext_store);
+    // Periodically write usage totals for billing
+    if let Some(export) = UsageExport::from_env()? {
+        info!("Exporting usage to {} every {:?}", export.path.display(), export.interval);
+        let usage = service.usage();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(export.interval);
+            loop {
+                interval.tick().await;
+                if let Err(e) = export.write(&usage) {
+                    error!("Usage export failed: {}", e);
+                }
+            }
+        });
+    }
+
+    // Evaluation requests can also arrive over NATS, answered by the same service
+    if let Some(config) = NatsConfig::from_env()? {
+        let service = service.clone();
+        tokio::spawn(async move {
+            if let Err(e) = events::serve_nats(service, config).await {
+                error!("NATS frontend stopped: {}", e);
+            }
+        });
+    }
+
+    // With an issuer configured, every data-plane call needs one of its access tokens, and
+    // is billed and authorized as the token's subject and tenant
+    let oidc = match OidcConfig::from_env()? {
+        Some(config) => {
+            let verifier = Arc::new(OidcVerifier::connect(config)?);
+            info!(
+                "Requiring access tokens from {} for audience {}",
+                verifier.config().issuer,
+                verifier.config().audience
+            );
+            let refresher = verifier.clone();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(refresher.config().refresh_interval);
+                loop {
+                    interval.tick().await;
+                    let verifier = refresher.clone();
+                    match tokio::task::spawn_blocking(move || verifier.refresh()).await {
+                        Ok(Ok(_)) => {}
+                        Ok(Err(e)) => warn!("Keeping the old OIDC signing keys: {}", e),
+                        Err(e) => error!("OIDC key refresh failed: {}", e),
+                    }
+                }
+            });
+            Some(OidcAuth::new(verifier))
+        }
+        None => None,
+    };
+    let mut interceptors = Interceptors::new();
+    if let Some(oidc) = oidc {
+        interceptors = interceptors.with(oidc);
+    }
+
+    // Both servers present the same certificate when TLS ends here rather than at a proxy
+    let server = || match &tls {
+        Some(tls) => transport.server().tls_config(tls.server_config()),
+        None => Ok(transport.server()),
+    };
+    if tls.is_some() {
+        info!("Serving TLS with the certificate in HERMETIC_FHE_TLS_CERT");
+    }
+
+    // The admin service needs its own token, and can be kept off the public port entirely
+    let mut admin_on_main_port = None;
+    match std::env::var("HERMETIC_FHE_ADMIN_TOKEN") {
+        Ok(token) => {
+            let admin = FheAdminServiceServer::new(FheAdminServiceImpl::new(service.clone()))
+                .max_decoding_message_size(transport.max_request_bytes)
+                .max_encoding_message_size(transport.max_response_bytes);
+            let admin = InterceptedService::new(admin, AdminAuth::new(&token)?);
+            match std::env::var("HERMETIC_FHE_ADMIN_ADDR") {
+                Ok(admin_addr) => {
+                    let admin_addr = admin_addr.parse()?;
+                    info!("FHE admin service listening on {}", admin_addr);
+                    tokio::spawn(server()?.layer(CallLogLayer).add_service(admin).serve(admin_addr));
+                }
+                Err(_) => admin_on_main_port = Some(admin),
+            }
+        }
+        Err(_) => info!("HERMETIC_FHE_ADMIN_TOKEN is not set; the admin service is disabled"),
+    }
     
-    // Define server address
-    let addr = "[::1]:50051".parse()?;
+    if let Some(addr) = listeners.tcp {
+        info!("FHE Service listening on {}", addr);
+    }
+    // Bound before serving, so a path that can't be used fails at startup
+    let unix_socket = match &listeners.unix_socket {
+        Some(path) => {
+            info!("FHE Service listening on Unix socket {}", path.display());
+            Some(listen::bind_unix(path)?)
+        }
+        None => None,
+    };
+    info!(
+        "Accepting requests up to {} bytes and sending responses up to {} bytes",
+        transport.max_request_bytes, transport.max_response_bytes
+    );
     
-    info!("FHE Service listening on {}", addr);
+    // Browsers can't speak plain gRPC, so the main port also accepts gRPC-Web over HTTP/1.1
+    let cors = web::cors_from_env()?;
     
-    // Start gRPC server
-    Server::builder()
-        .add_service(FheServiceServer::new(service))
-        .serve(addr)
-        .await?;
+    // Start gRPC server, logging each call's metadata and sizes but never its messages
+    let fhe_service = |service: FheServiceImpl| fhe_service_server(service, &transport, interceptors.clone());
+    let router = || -> Result<_, tonic::transport::Error> {
+        Ok(server()?
+            .accept_http1(true)
+            .layer(cors.clone())
+            .layer(GrpcWebLayer::new())
+            .layer(CallLogLayer)
+            .add_service(fhe_service(service.clone()))
+            .add_optional_service(admin_on_main_port.clone())
+            // Clients built against the unversioned package keep working
+            .add_service(LegacyService::new(fhe_service(service.clone()))))
+    };
+    // The same services on every listener; each runs until the process stops
+    let tcp = async {
+        if let Some(addr) = listeners.tcp {
+            router()?.serve(addr).await?;
+        }
+        Ok::<_, Box<dyn std::error::Error>>(())
+    };
+    let unix = async {
+        if let Some(incoming) = unix_socket {
+            router()?.serve_with_incoming(incoming).await?;
+        }
+        Ok::<_, Box<dyn std::error::Error>>(())
+    };
+    tokio::try_join!(tcp, unix)?;
     
     Ok(())
 }
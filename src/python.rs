@@ -0,0 +1,401 @@
+use std::collections::HashMap;
+use std::future::Future;
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyBytes};
+use tfhe::{FheBool, FheUint8};
+use tokio::runtime::Runtime;
+use tonic::transport::Channel;
+use tonic::{Response, Status};
+
+use crate::api::fhe_service_client::FheServiceClient;
+use crate::api::v1::key_generation_request::ParameterSet;
+use crate::api::{
+    circuit_wire, CiphertextType, CircuitEvaluationRequest, CircuitGate, CircuitWire, DecryptBooleanRequest,
+    DecryptIntegerRequest, EncryptBooleanRequest, EncryptIntegerRequest, EvaluationRequest,
+    ExportCiphertextRequest, ImportCiphertextRequest, KeyGenerationRequest, OperationType,
+};
+use crate::client::{attested, export_ciphertext, import_ciphertext, FheClient};
+use crate::crypto::attestation::TeePlatform;
+use crate::crypto::canonical::Canonical;
+use crate::service::errors::ErrorReason;
+use crate::service::fhe_service::MAX_MESSAGE_BYTES;
+
+// Python bindings, built with maturin from python/pyproject.toml as the hermetic_fhe_py
+// module. Calls block on a runtime owned by the client, with the GIL released, so Python
+// users get a plain synchronous API and never touch stubs or serialization.
+
+create_exception!(hermetic_fhe_py, FheError, PyException);
+
+// A connection to the service
+#[pyclass]
+pub struct Client {
+    runtime: Runtime,
+    service: FheServiceClient<Channel>,
+}
+
+#[pymethods]
+impl Client {
+    #[staticmethod]
+    fn connect(address: &str) -> PyResult<Self> {
+        let runtime = Runtime::new()?;
+        let service = runtime
+            .block_on(FheServiceClient::connect(address.to_string()))
+            .map_err(|e| FheError::new_err(format!("Connection to {} failed: {}", address, e)))?
+            // Match the server's default limits rather than tonic's 4 MiB, which a few
+            // exported ciphertexts already exceed
+            .max_decoding_message_size(MAX_MESSAGE_BYTES)
+            .max_encoding_message_size(MAX_MESSAGE_BYTES);
+        Ok(Self { runtime, service })
+    }
+
+    // Connect over TLS to a server in an enclave or confidential VM, and refuse it unless
+    // it proves it runs the expected build and holds the certificate's key. certificate
+    // is the PEM the server was given as HERMETIC_FHE_TLS_CERT, trusted for this connection
+    // only, and measurement the MRENCLAVE, SNP launch measurement or MRTD of the build.
+    // verify_signature is required: called with the platform name and the quote, it must
+    // check the quote against Intel's or AMD's collateral and return True. Anything else,
+    // including a truthy value that isn't True or an exception, refuses the server.
+    #[staticmethod]
+    fn connect_attested(
+        py: Python<'_>,
+        address: &str,
+        certificate: &[u8],
+        measurement: &[u8],
+        verify_signature: PyObject,
+    ) -> PyResult<Self> {
+        let signature = |platform: TeePlatform, quote: &[u8]| -> Result<(), String> {
+            check_signature(py, &verify_signature, platform.name(), quote)
+        };
+        let runtime = Runtime::new()?;
+        let service = runtime
+            .block_on(attested::connect_attested(
+                address,
+                certificate,
+                measurement,
+                &signature,
+            ))
+            .map_err(|e| FheError::new_err(e.to_string()))?;
+        Ok(Self { runtime, service })
+    }
+
+    // A key pair held by the server, as (client_key_id, server_key_id)
+    #[pyo3(signature = (parameter_set = "DEFAULT"))]
+    fn generate_keys(&self, py: Python<'_>, parameter_set: &str) -> PyResult<(String, String)> {
+        let parameter_set = ParameterSet::from_str_name(parameter_set)
+            .ok_or_else(|| PyValueError::new_err(format!("Unknown parameter set {}", parameter_set)))?;
+        let request = KeyGenerationRequest {
+            parameter_set: parameter_set as i32,
+            ..Default::default()
+        };
+        let keys = self.call(
+            py,
+            |mut service| async move { service.generate_keys(request).await },
+        )?;
+        Ok((keys.client_key_id, keys.server_key_id))
+    }
+
+    fn encrypt_boolean(&self, py: Python<'_>, client_key_id: String, value: bool) -> PyResult<String> {
+        let request = EncryptBooleanRequest {
+            client_key_id,
+            value,
+            ..Default::default()
+        };
+        let response = self.call(
+            py,
+            |mut service| async move { service.encrypt_boolean(request).await },
+        )?;
+        Ok(response.encrypted_data_id)
+    }
+
+    fn encrypt_integer(&self, py: Python<'_>, client_key_id: String, value: i64) -> PyResult<String> {
+        let request = EncryptIntegerRequest {
+            client_key_id,
+            value,
+            num_bits: 8,
+            ..Default::default()
+        };
+        let response = self.call(
+            py,
+            |mut service| async move { service.encrypt_integer(request).await },
+        )?;
+        Ok(response.encrypted_data_id)
+    }
+
+    // One operation by name, e.g. "ADD" or "AND", returning the result's ID
+    fn evaluate(
+        &self,
+        py: Python<'_>,
+        server_key_id: String,
+        operation: &str,
+        operand_ids: Vec<String>,
+    ) -> PyResult<String> {
+        let request = EvaluationRequest {
+            server_key_id,
+            operation: operation_type(operation)? as i32,
+            operand_ids,
+            ..Default::default()
+        };
+        let response = self.call(py, |mut service| async move {
+            service.evaluate_operation(request).await
+        })?;
+        Ok(response.result_id)
+    }
+
+    // An infix expression such as "(a + b) * c", with the ciphertext ID of each variable
+    fn evaluate_expression(
+        &self,
+        py: Python<'_>,
+        server_key_id: String,
+        expression: String,
+        variables: HashMap<String, String>,
+    ) -> PyResult<String> {
+        let request = EvaluationRequest {
+            server_key_id,
+            expression,
+            variables,
+            ..Default::default()
+        };
+        let response = self.call(py, |mut service| async move {
+            service.evaluate_operation(request).await
+        })?;
+        Ok(response.result_id)
+    }
+
+    // A circuit in one call. Gates are (operation, operands) in topological order, and each
+    // operand or output is ("input", index) or ("gate", index). Returns the output IDs.
+    fn evaluate_circuit(
+        &self,
+        py: Python<'_>,
+        server_key_id: String,
+        input_ids: Vec<String>,
+        gates: Vec<(String, Vec<(String, u32)>)>,
+        outputs: Vec<(String, u32)>,
+    ) -> PyResult<Vec<String>> {
+        let gates = gates
+            .into_iter()
+            .map(|(operation, operands)| {
+                Ok(CircuitGate {
+                    operation: operation_type(&operation)? as i32,
+                    operands: operands.into_iter().map(circuit_wire).collect::<PyResult<_>>()?,
+                })
+            })
+            .collect::<PyResult<_>>()?;
+        let request = CircuitEvaluationRequest {
+            server_key_id,
+            input_ids,
+            gates,
+            outputs: outputs.into_iter().map(circuit_wire).collect::<PyResult<_>>()?,
+            ..Default::default()
+        };
+        let response = self.call(py, |mut service| async move {
+            service.evaluate_circuit(request).await
+        })?;
+        Ok(response.output_ids)
+    }
+
+    fn decrypt_boolean(
+        &self,
+        py: Python<'_>,
+        client_key_id: String,
+        encrypted_data_id: String,
+    ) -> PyResult<bool> {
+        let request = DecryptBooleanRequest {
+            client_key_id,
+            encrypted_data_id,
+            ..Default::default()
+        };
+        let response = self.call(
+            py,
+            |mut service| async move { service.decrypt_boolean(request).await },
+        )?;
+        Ok(response.value)
+    }
+
+    fn decrypt_integer(
+        &self,
+        py: Python<'_>,
+        client_key_id: String,
+        encrypted_data_id: String,
+    ) -> PyResult<i64> {
+        let request = DecryptIntegerRequest {
+            client_key_id,
+            encrypted_data_id,
+            ..Default::default()
+        };
+        let response = self.call(
+            py,
+            |mut service| async move { service.decrypt_integer(request).await },
+        )?;
+        Ok(response.value)
+    }
+
+    // Encrypt under a local key and store the ciphertext on the server, returning its ID
+    fn upload_boolean(&self, py: Python<'_>, key: &LocalKey, value: bool) -> PyResult<String> {
+        let ciphertext = key.client.encrypt_boolean(value).map_err(local_error)?;
+        self.upload(py, CiphertextType::Boolean, &ciphertext)
+    }
+
+    fn upload_integer(&self, py: Python<'_>, key: &LocalKey, value: u8) -> PyResult<String> {
+        let ciphertext = key.client.encrypt_integer(value).map_err(local_error)?;
+        self.upload(py, CiphertextType::Integer, &ciphertext)
+    }
+
+    // Fetch a stored ciphertext and decrypt it under a local key
+    fn download_boolean(&self, py: Python<'_>, key: &LocalKey, encrypted_data_id: String) -> PyResult<bool> {
+        let (bytes, fingerprint) = self.download(py, encrypted_data_id)?;
+        let ciphertext: FheBool = import_ciphertext(&bytes, &fingerprint).map_err(local_error)?;
+        Ok(key.client.decrypt_boolean(&ciphertext))
+    }
+
+    fn download_integer(&self, py: Python<'_>, key: &LocalKey, encrypted_data_id: String) -> PyResult<u8> {
+        let (bytes, fingerprint) = self.download(py, encrypted_data_id)?;
+        let ciphertext: FheUint8 = import_ciphertext(&bytes, &fingerprint).map_err(local_error)?;
+        Ok(key.client.decrypt_integer(&ciphertext))
+    }
+}
+
+impl Client {
+    // Run one call on the client's runtime with the GIL released
+    fn call<T, F, Fut>(&self, py: Python<'_>, call: F) -> PyResult<T>
+    where
+        T: Send,
+        F: FnOnce(FheServiceClient<Channel>) -> Fut + Send,
+        Fut: Future<Output = Result<Response<T>, Status>>,
+    {
+        let service = self.service.clone();
+        py.allow_threads(|| self.runtime.block_on(call(service)))
+            .map(Response::into_inner)
+            .map_err(service_error)
+    }
+
+    fn upload<T: Canonical>(
+        &self,
+        py: Python<'_>,
+        ciphertext_type: CiphertextType,
+        ciphertext: &T,
+    ) -> PyResult<String> {
+        let (serialized_data, fingerprint) = export_ciphertext(ciphertext).map_err(local_error)?;
+        let request = ImportCiphertextRequest {
+            ciphertext_type: ciphertext_type as i32,
+            serialized_data,
+            fingerprint,
+            ..Default::default()
+        };
+        let response = self.call(py, |mut service| async move {
+            service.import_ciphertext(request).await
+        })?;
+        Ok(response.encrypted_data_id)
+    }
+
+    fn download(&self, py: Python<'_>, encrypted_data_id: String) -> PyResult<(Vec<u8>, String)> {
+        let request = ExportCiphertextRequest { encrypted_data_id };
+        let response = self.call(py, |mut service| async move {
+            service.export_ciphertext(request).await
+        })?;
+        Ok((response.serialized_data, response.fingerprint))
+    }
+}
+
+// A client key that stays in the Python process. Ciphertexts made with it reach the server
+// through Client.upload_* and come back through Client.download_*.
+#[pyclass]
+pub struct LocalKey {
+    client: FheClient,
+}
+
+#[pymethods]
+impl LocalKey {
+    #[staticmethod]
+    #[pyo3(signature = (parameter_set = "DEFAULT"))]
+    fn generate(parameter_set: &str) -> PyResult<Self> {
+        Ok(Self {
+            client: FheClient::with_parameters(parameter_set).map_err(local_error)?,
+        })
+    }
+
+    // Restore a key saved with to_bytes
+    #[staticmethod]
+    fn from_bytes(bytes: &[u8]) -> PyResult<Self> {
+        Ok(Self {
+            client: FheClient::from_bytes(bytes).map_err(local_error)?,
+        })
+    }
+
+    // The serialized client key. Anyone holding it can decrypt.
+    fn to_bytes<'py>(&self, py: Python<'py>) -> PyResult<&'py PyBytes> {
+        Ok(PyBytes::new(py, &self.client.to_bytes().map_err(local_error)?))
+    }
+}
+
+// Whether a verify_signature callback accepted the quote. Only the bool True does: an
+// error string, a non-empty dict or 1 are all truthy, and would let a forged quote through.
+fn check_signature(
+    py: Python<'_>,
+    verify_signature: &PyObject,
+    platform: &str,
+    quote: &[u8],
+) -> Result<(), String> {
+    let returned = verify_signature
+        .call1(py, (platform, PyBytes::new(py, quote)))
+        .map_err(|e| format!("verify_signature raised {}", e))?
+        .into_ref(py);
+    match returned.downcast::<PyBool>() {
+        Ok(accepted) if accepted.is_true() => Ok(()),
+        _ => Err(format!("verify_signature returned {} rather than True", returned)),
+    }
+}
+
+// check_signature as the module exposes it, so the package's tests can put callbacks to it
+// without a server in an enclave
+#[pyfunction]
+#[pyo3(name = "_check_signature")]
+fn check_signature_for_tests(
+    py: Python<'_>,
+    verify_signature: PyObject,
+    platform: &str,
+    quote: &[u8],
+) -> PyResult<()> {
+    check_signature(py, &verify_signature, platform, quote)
+        .map_err(|e| FheError::new_err(format!("Attestation failed: {}", e)))
+}
+
+fn operation_type(name: &str) -> PyResult<OperationType> {
+    OperationType::from_str_name(name)
+        .ok_or_else(|| PyValueError::new_err(format!("Unknown operation {}", name)))
+}
+
+fn circuit_wire((source, index): (String, u32)) -> PyResult<CircuitWire> {
+    let source = match source.as_str() {
+        "input" => circuit_wire::Source::Input(index),
+        "gate" => circuit_wire::Source::Gate(index),
+        _ => {
+            return Err(PyValueError::new_err(format!(
+                "Wire source must be input or gate, not {}",
+                source
+            )))
+        }
+    };
+    Ok(CircuitWire { source: Some(source) })
+}
+
+// FheError with the status's error reason in front, e.g. "KEY_NOT_FOUND: Client key not found"
+fn service_error(status: Status) -> PyErr {
+    let reason = ErrorReason::of(&status).map_or("UNKNOWN", ErrorReason::as_str);
+    FheError::new_err(format!("{}: {}", reason, status.message()))
+}
+
+fn local_error(error: anyhow::Error) -> PyErr {
+    FheError::new_err(error.to_string())
+}
+
+#[pymodule]
+fn hermetic_fhe_py(py: Python<'_>, module: &PyModule) -> PyResult<()> {
+    module.add_class::<Client>()?;
+    module.add_class::<LocalKey>()?;
+    module.add("FheError", py.get_type::<FheError>())?;
+    module.add_function(wrap_pyfunction!(check_signature_for_tests, module)?)?;
+    Ok(())
+}
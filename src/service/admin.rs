@@ -0,0 +1,613 @@
+// Handlers return tonic::Status, which is large by design
+#![allow(clippy::result_large_err)]
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::metadata::MetadataValue;
+use tonic::service::Interceptor;
+use tonic::{Request, Response, Status, Streaming};
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::api::v1::key_generation_request::ParameterSet;
+use crate::api::{
+    BackupChunk, CalibrateCostsRequest, CalibrateCostsResponse, CloseSessionResponse,
+    CreateBackupRequest, DeleteKeyPairRequest, DeleteKeyPairResponse, DeletedCiphertextInfo,
+    EvictSessionRequest, FheAdminService, GateLatency, GetMigrationRequest, KeyPairInfo,
+    ListDeletedCiphertextsRequest, ListDeletedCiphertextsResponse, ListKeysRequest,
+    ListKeysResponse, ListSessionsRequest, ListSessionsResponse, ListSubjectsRequest,
+    ListSubjectsResponse, MigratedCiphertext, MigrationStatus, ParameterSetCosts,
+    ReloadConfigRequest, ReloadConfigResponse, RestoreBackupResponse,
+    RestoreDeletedCiphertextsRequest, RestoreDeletedCiphertextsResponse, SessionInfo,
+    SetKeyOperationsRequest, SetKeyOperationsResponse, ShredSubjectRequest, ShredSubjectResponse,
+    StartMigrationRequest, StatsRequest, StatsResponse, SubjectInfo, UsageRecord, UsageRequest,
+    UsageResponse,
+};
+use crate::crypto::PARAMETER_SETS;
+use crate::service::backup::{self, ArchiveReader, BackupLedger, BACKUP_ID_HEADER};
+use crate::service::errors::ErrorReason;
+use crate::service::fhe_service::{mask_operations, operation_mask, operation_type, parameter_set_name};
+use crate::service::migration::{MigrationProgress, MigrationStore};
+use crate::service::webhook::JobEvent;
+use crate::service::FheServiceImpl;
+
+// Shortest admin token accepted, so a placeholder value can't end up guarding production
+pub const MIN_ADMIN_TOKEN_LENGTH: usize = 32;
+// Times each gate is run by CalibrateCosts when the request doesn't say, and at most
+const DEFAULT_CALIBRATION_SAMPLES: usize = 3;
+const MAX_CALIBRATION_SAMPLES: usize = 100;
+// Backup chunks written ahead of the client reading them
+const BACKUP_CHUNKS_IN_FLIGHT: usize = 4;
+
+// Operator RPCs over the same stores as the data-plane service it wraps
+#[derive(Clone)]
+pub struct FheAdminServiceImpl {
+    service: FheServiceImpl,
+    migrations: Arc<MigrationStore>,
+    backups: Arc<BackupLedger>,
+}
+
+impl FheAdminServiceImpl {
+    pub fn new(service: FheServiceImpl) -> Self {
+        Self {
+            service,
+            migrations: Arc::new(MigrationStore::new()),
+            backups: Arc::new(BackupLedger::new()),
+        }
+    }
+
+    // What RestoreBackup does with its request stream, for archive chunks from any source.
+    // Nothing is applied until the whole archive has arrived and its signature checks out.
+    pub async fn restore(
+        &self,
+        mut chunks: impl Stream<Item = Result<BackupChunk, Status>> + Unpin,
+    ) -> Result<RestoreBackupResponse, Status> {
+        let mut reader = ArchiveReader::new(self.service.key_store().signer());
+        while let Some(chunk) = chunks.next().await {
+            reader.push(&chunk?.data)?;
+        }
+        let archive = reader.finish()?;
+
+        let base = match archive.base_backup_id() {
+            "" => None,
+            base_backup_id => Some(self.backups.get(base_backup_id).ok_or_else(|| {
+                ErrorReason::BackupNotFound.status(format!(
+                    "Restore base backup {} before this incremental one",
+                    base_backup_id
+                ))
+            })?),
+        };
+        info!("Restoring backup {}", archive.backup_id());
+
+        // Deserializing keys and ciphertexts is CPU-bound
+        let service = self.service.clone();
+        let restore = move || backup::restore_backup(&service, archive, base.as_deref());
+        let (restored, manifest) = tokio::task::spawn_blocking(restore)
+            .await
+            .map_err(|e| ErrorReason::Internal.status(format!("Restore failed: {}", e)))??;
+        self.backups.record(&restored.backup_id, manifest);
+        info!(
+            "Restored backup {}: {} key pairs, {} ciphertexts, {} sessions, {} deleted, {} shredded",
+            restored.backup_id,
+            restored.key_pairs,
+            restored.ciphertexts,
+            restored.sessions,
+            restored.deleted,
+            restored.shredded
+        );
+
+        Ok(restored)
+    }
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn migration_status(migration_id: &str, progress: &MigrationProgress) -> MigrationStatus {
+    let failed = progress.failed();
+    MigrationStatus {
+        migration_id: migration_id.to_string(),
+        done: progress.done,
+        source_scheme: progress.source_scheme as i32,
+        target_scheme: progress.target_scheme as i32,
+        total: progress.total as u32,
+        migrated: (progress.ciphertexts.len() - failed) as u32,
+        failed: failed as u32,
+        ciphertexts: progress
+            .ciphertexts
+            .iter()
+            .map(|ciphertext| {
+                let (target_id, error) = match &ciphertext.result {
+                    Ok(target_id) => (target_id.clone(), String::new()),
+                    Err(error) => (String::new(), error.clone()),
+                };
+                MigratedCiphertext {
+                    source_id: ciphertext.source_id.clone(),
+                    target_id,
+                    error,
+                }
+            })
+            .collect(),
+    }
+}
+
+#[tonic::async_trait]
+impl FheAdminService for FheAdminServiceImpl {
+    async fn list_keys(
+        &self,
+        _request: Request<ListKeysRequest>,
+    ) -> Result<Response<ListKeysResponse>, Status> {
+        let key_store = self.service.key_store();
+        let key_pairs = key_store
+            .key_pairs()
+            .into_iter()
+            .map(|(client_key_id, server_key_id)| KeyPairInfo {
+                client_key_fingerprint: key_store.get_fingerprint(&client_key_id).unwrap_or_default(),
+                server_key_fingerprint: key_store.get_fingerprint(&server_key_id).unwrap_or_default(),
+                allowed_operations: mask_operations(key_store.policy(&server_key_id).allowed_operations),
+                client_key_id,
+                server_key_id,
+            })
+            .collect();
+
+        Ok(Response::new(ListKeysResponse { key_pairs }))
+    }
+
+    async fn delete_key_pair(
+        &self,
+        request: Request<DeleteKeyPairRequest>,
+    ) -> Result<Response<DeleteKeyPairResponse>, Status> {
+        let req = request.into_inner();
+
+        let (client_key_id, server_key_id) = self
+            .service
+            .key_store()
+            .delete_key_pair(&req.key_id)
+            .map_err(|e| ErrorReason::Internal.status(format!("Failed to delete key pair: {}", e)))?
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Key not found"))?;
+
+        info!("Deleted key pair {} / {}", client_key_id, server_key_id);
+
+        Ok(Response::new(DeleteKeyPairResponse {
+            client_key_id,
+            server_key_id,
+        }))
+    }
+
+    async fn set_key_operations(
+        &self,
+        request: Request<SetKeyOperationsRequest>,
+    ) -> Result<Response<SetKeyOperationsResponse>, Status> {
+        let req = request.into_inner();
+        let allowed_operations = operation_mask(&req.allowed_operations)?;
+
+        let key_store = self.service.key_store();
+        // Only TFHE pairs evaluate OperationTypes
+        if key_store.is_loaded(&req.server_key_id).is_none() {
+            return Err(ErrorReason::KeyNotFound.status("Server key not found"));
+        }
+        let policy = key_store
+            .set_allowed_operations(&req.server_key_id, allowed_operations)
+            .map_err(|e| ErrorReason::Internal.status(format!("Failed to update key policy: {}", e)))?;
+
+        info!(
+            "Set the operations allowed under {} to {:?}",
+            req.server_key_id, req.allowed_operations
+        );
+
+        Ok(Response::new(SetKeyOperationsResponse {
+            allowed_operations: mask_operations(policy.allowed_operations),
+        }))
+    }
+
+    async fn list_sessions(
+        &self,
+        _request: Request<ListSessionsRequest>,
+    ) -> Result<Response<ListSessionsResponse>, Status> {
+        let sessions = self
+            .service
+            .sessions()
+            .list()
+            .into_iter()
+            .map(|session| SessionInfo {
+                session_id: session.session_id,
+                ciphertexts: session.ciphertexts as u32,
+                idle_seconds: session.idle.as_secs(),
+                idle_timeout_seconds: session.idle_timeout.as_secs(),
+            })
+            .collect();
+
+        Ok(Response::new(ListSessionsResponse { sessions }))
+    }
+
+    async fn evict_session(
+        &self,
+        request: Request<EvictSessionRequest>,
+    ) -> Result<Response<CloseSessionResponse>, Status> {
+        let req = request.into_inner();
+
+        let freed = self
+            .service
+            .evict_session(&req.session_id)
+            .ok_or_else(|| ErrorReason::SessionNotFound.status("Session not found"))?;
+        info!("Evicted session {}, freed {} ciphertexts", req.session_id, freed);
+
+        Ok(Response::new(CloseSessionResponse {
+            freed_ciphertexts: freed as u32,
+        }))
+    }
+
+    async fn get_stats(
+        &self,
+        _request: Request<StatsRequest>,
+    ) -> Result<Response<StatsResponse>, Status> {
+        Ok(Response::new(StatsResponse {
+            metrics: Some(self.service.metrics()),
+            key_pairs: self.service.key_store().key_pairs().len() as u32,
+            sessions: self.service.sessions().list().len() as u32,
+        }))
+    }
+
+    async fn get_usage(
+        &self,
+        request: Request<UsageRequest>,
+    ) -> Result<Response<UsageResponse>, Status> {
+        let req = request.into_inner();
+
+        let records = self
+            .service
+            .usage()
+            .records(&req.tenant)
+            .into_iter()
+            .map(|record| UsageRecord {
+                tenant: record.tenant,
+                key_id: record.key_id,
+                operation: record.operation,
+                count: record.count,
+                compute_seconds: record.compute_seconds,
+            })
+            .collect();
+
+        Ok(Response::new(UsageResponse { records }))
+    }
+
+    async fn start_migration(
+        &self,
+        request: Request<StartMigrationRequest>,
+    ) -> Result<Response<MigrationStatus>, Status> {
+        let req = request.into_inner();
+
+        if req.encrypted_data_ids.is_empty() {
+            return Err(ErrorReason::InvalidRequest.status("No encrypted data to migrate"));
+        }
+        if req.source_client_key_id == req.target_client_key_id {
+            return Err(ErrorReason::InvalidRequest.status("Source and target keys are the same"));
+        }
+        let migrator = self.service.migrator();
+        let source_scheme = migrator
+            .scheme(&req.source_client_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Source client key not found"))?;
+        let target_scheme = migrator
+            .scheme(&req.target_client_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Target client key not found"))?;
+
+        let callback = self.service.callback_url(req.callback)?;
+
+        let (migration_id, migration) =
+            self.migrations.create(source_scheme, target_scheme, req.encrypted_data_ids.len());
+        info!(
+            "Migration {}: {} ciphertexts from {:?} key {} to {:?} key {}",
+            migration_id,
+            req.encrypted_data_ids.len(),
+            source_scheme,
+            req.source_client_key_id,
+            target_scheme,
+            req.target_client_key_id
+        );
+        let status = migration_status(&migration_id, &migration.progress());
+
+        // Decryption and encryption are CPU-bound, so the job runs on the blocking pool
+        let webhooks = self.service.webhooks();
+        let id = migration_id.clone();
+        tokio::task::spawn_blocking(move || {
+            migrator.run(
+                &migration,
+                &req.source_client_key_id,
+                &req.target_client_key_id,
+                &req.encrypted_data_ids,
+                req.delete_source,
+            );
+            if let Some(url) = callback {
+                let progress = migration.progress();
+                webhooks.deliver(&url, &JobEvent::new(&id, "migration", progress.total, progress.failed()));
+            }
+        });
+
+        Ok(Response::new(status))
+    }
+
+    async fn get_migration(
+        &self,
+        request: Request<GetMigrationRequest>,
+    ) -> Result<Response<MigrationStatus>, Status> {
+        let req = request.into_inner();
+
+        let migration = self
+            .migrations
+            .get(&req.migration_id)
+            .ok_or_else(|| ErrorReason::MigrationNotFound.status("Migration not found"))?;
+
+        Ok(Response::new(migration_status(&req.migration_id, &migration.progress())))
+    }
+
+    type CreateBackupStream = Pin<Box<dyn Stream<Item = Result<BackupChunk, Status>> + Send>>;
+
+    async fn create_backup(
+        &self,
+        request: Request<CreateBackupRequest>,
+    ) -> Result<Response<Self::CreateBackupStream>, Status> {
+        let req = request.into_inner();
+
+        let base = match req.base_backup_id.as_str() {
+            "" => None,
+            base_backup_id => Some(
+                self.backups
+                    .get(base_backup_id)
+                    .ok_or_else(|| ErrorReason::BackupNotFound.status("Base backup not found"))?,
+            ),
+        };
+        let backup_id = Uuid::new_v4().to_string();
+        info!("Backup {} started", backup_id);
+
+        // Serializing keys and ciphertexts is CPU-bound, so the archive is written on the
+        // blocking pool, a few chunks ahead of the client
+        let (sender, receiver) = mpsc::channel(BACKUP_CHUNKS_IN_FLIGHT);
+        let service = self.service.clone();
+        let backups = self.backups.clone();
+        let id = backup_id.clone();
+        tokio::task::spawn_blocking(move || {
+            let base = base.as_deref().map(|manifest| (req.base_backup_id.as_str(), manifest));
+            let written = backup::write_backup(&service, &id, base, |data| {
+                sender
+                    .blocking_send(Ok(BackupChunk { data }))
+                    .map_err(|_| anyhow!("the client went away"))
+            });
+            match written {
+                // Only a backup that was sent in full can be the base of another
+                Ok(manifest) => {
+                    info!("Backup {} finished with {} entries", id, manifest.len());
+                    backups.record(&id, manifest);
+                }
+                Err(e) => {
+                    error!("Backup {} failed: {}", id, e);
+                    let status = ErrorReason::Internal.status(format!("Backup failed: {}", e));
+                    let _ = sender.blocking_send(Err(status));
+                }
+            }
+        });
+
+        let mut response = Response::new(Box::pin(ReceiverStream::new(receiver)) as Self::CreateBackupStream);
+        if let Ok(value) = MetadataValue::try_from(backup_id.as_str()) {
+            response.metadata_mut().insert(BACKUP_ID_HEADER, value);
+        }
+        Ok(response)
+    }
+
+    async fn restore_backup(
+        &self,
+        request: Request<Streaming<BackupChunk>>,
+    ) -> Result<Response<RestoreBackupResponse>, Status> {
+        self.restore(request.into_inner()).await.map(Response::new)
+    }
+
+    async fn list_deleted_ciphertexts(
+        &self,
+        _request: Request<ListDeletedCiphertextsRequest>,
+    ) -> Result<Response<ListDeletedCiphertextsResponse>, Status> {
+        let ciphertexts = self
+            .service
+            .ciphertext_store()
+            .list_deleted()
+            .into_iter()
+            .map(|deleted| DeletedCiphertextInfo {
+                encrypted_data_id: deleted.id,
+                kind: backup::kind_to_proto(deleted.kind) as i32,
+                deleted_unix_seconds: unix_seconds(deleted.deleted_at),
+                purge_unix_seconds: unix_seconds(deleted.purge_at),
+            })
+            .collect();
+
+        Ok(Response::new(ListDeletedCiphertextsResponse { ciphertexts }))
+    }
+
+    async fn restore_deleted_ciphertexts(
+        &self,
+        request: Request<RestoreDeletedCiphertextsRequest>,
+    ) -> Result<Response<RestoreDeletedCiphertextsResponse>, Status> {
+        let req = request.into_inner();
+
+        let store = self.service.ciphertext_store();
+        let (restored_ids, missing_ids): (Vec<String>, Vec<String>) =
+            req.encrypted_data_ids.into_iter().partition(|id| store.undelete(id));
+        info!(
+            "Restored {} deleted ciphertexts, {} not found",
+            restored_ids.len(),
+            missing_ids.len()
+        );
+
+        Ok(Response::new(RestoreDeletedCiphertextsResponse {
+            restored_ids,
+            missing_ids,
+        }))
+    }
+
+    async fn list_subjects(
+        &self,
+        _request: Request<ListSubjectsRequest>,
+    ) -> Result<Response<ListSubjectsResponse>, Status> {
+        let subjects = self
+            .service
+            .ciphertext_store()
+            .subjects()
+            .into_iter()
+            .map(|(subject_id, ciphertexts)| SubjectInfo {
+                subject_id,
+                ciphertexts: ciphertexts as u32,
+            })
+            .collect();
+
+        Ok(Response::new(ListSubjectsResponse { subjects }))
+    }
+
+    async fn shred_subject(
+        &self,
+        request: Request<ShredSubjectRequest>,
+    ) -> Result<Response<ShredSubjectResponse>, Status> {
+        let req = request.into_inner();
+        if req.subject_id.is_empty() {
+            return Err(ErrorReason::InvalidRequest.status("No subject to shred"));
+        }
+
+        // The key goes first: once it is gone, every backup copy is unreadable even if
+        // freeing the live values is interrupted
+        let key_destroyed = self
+            .service
+            .key_store()
+            .shred_subject(&req.subject_id)
+            .map_err(|e| ErrorReason::Internal.status(format!("Failed to destroy subject key: {}", e)))?;
+        let freed = self.service.ciphertext_store().remove_subject(&req.subject_id);
+        info!(
+            "Shredded subject {}: key destroyed {}, freed {} ciphertexts",
+            req.subject_id, key_destroyed, freed
+        );
+
+        Ok(Response::new(ShredSubjectResponse {
+            key_destroyed,
+            freed_ciphertexts: freed as u32,
+        }))
+    }
+
+    async fn reload_config(
+        &self,
+        _request: Request<ReloadConfigRequest>,
+    ) -> Result<Response<ReloadConfigResponse>, Status> {
+        let outcome = self.service.reload_config(true);
+        info!("Reloaded {:?} on request", outcome.reloaded);
+        if !outcome.errors.is_empty() {
+            return Err(ErrorReason::InvalidRequest.status(format!(
+                "Reloaded {:?}, but not: {}",
+                outcome.reloaded,
+                outcome.errors.join("; ")
+            )));
+        }
+
+        Ok(Response::new(ReloadConfigResponse {
+            reloaded: outcome.reloaded.into_iter().map(str::to_string).collect(),
+        }))
+    }
+
+    async fn calibrate_costs(
+        &self,
+        request: Request<CalibrateCostsRequest>,
+    ) -> Result<Response<CalibrateCostsResponse>, Status> {
+        let req = request.into_inner();
+        let parameter_sets = if req.parameter_sets.is_empty() {
+            PARAMETER_SETS.to_vec()
+        } else {
+            req.parameter_sets
+                .iter()
+                .map(|parameter_set| parameter_set_name(*parameter_set))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        let samples = match req.samples as usize {
+            0 => DEFAULT_CALIBRATION_SAMPLES,
+            samples if samples > MAX_CALIBRATION_SAMPLES => {
+                return Err(ErrorReason::LimitExceeded.status(format!(
+                    "At most {} samples per gate, not {}",
+                    MAX_CALIBRATION_SAMPLES, samples
+                )))
+            }
+            samples => samples,
+        };
+
+        // Generating keys and timing gates is CPU-bound
+        let service = self.service.clone();
+        let measured = parameter_sets.clone();
+        let cost_model = tokio::task::spawn_blocking(move || service.calibrate_costs(&measured, samples))
+            .await
+            .map_err(|e| ErrorReason::Internal.status(format!("Calibration failed: {}", e)))?
+            .map_err(|e| ErrorReason::Internal.status(format!("Calibration failed: {}", e)))?;
+        info!("Calibrated gate costs for {} with {} samples", parameter_sets.join(", "), samples);
+
+        let parameter_sets = cost_model
+            .parameter_sets()
+            .into_iter()
+            .map(|(name, costs)| {
+                let mut gate_latencies: Vec<GateLatency> = costs
+                    .gate_latency
+                    .iter()
+                    .map(|(operation, latency)| GateLatency {
+                        operation: operation_type(*operation) as i32,
+                        latency_seconds: latency.as_secs_f64(),
+                    })
+                    .collect();
+                gate_latencies.sort_by_key(|gate| gate.operation);
+                ParameterSetCosts {
+                    parameter_set: ParameterSet::from_str_name(name).map_or(0, |set| set as i32),
+                    gate_latencies,
+                    boolean_bytes: costs.boolean_bytes as u64,
+                    integer_bytes: costs.integer_bytes as u64,
+                    server_key_bytes: costs.server_key_bytes as u64,
+                    calibrated: cost_model.is_measured(name),
+                }
+            })
+            .collect();
+        Ok(Response::new(CalibrateCostsResponse { parameter_sets }))
+    }
+}
+
+// Requires `authorization: Bearer <token>` on every admin call. The data-plane service
+// is not behind it, so end users never need the token.
+#[derive(Clone)]
+pub struct AdminAuth {
+    token: Arc<str>,
+}
+
+impl AdminAuth {
+    pub fn new(token: &str) -> Result<Self> {
+        if token.len() < MIN_ADMIN_TOKEN_LENGTH {
+            return Err(anyhow!(
+                "Admin token must be at least {} characters",
+                MIN_ADMIN_TOKEN_LENGTH
+            ));
+        }
+        Ok(Self { token: token.into() })
+    }
+}
+
+impl Interceptor for AdminAuth {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let presented = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+
+        match presented {
+            Some(token) if constant_time_eq(token.as_bytes(), self.token.as_bytes()) => Ok(request),
+            _ => Err(ErrorReason::Unauthenticated.status("Admin token required")),
+        }
+    }
+}
+
+// Compare without an early exit, so response timing doesn't reveal how much of the token matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
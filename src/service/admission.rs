@@ -0,0 +1,520 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use tokio::sync::oneshot;
+
+pub const DEFAULT_QUEUE_DEPTH: usize = 64;
+
+// How many evaluations may run at once and how many may wait for a worker
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AdmissionConfig {
+    pub workers: usize,
+    pub queue_depth: usize,
+}
+
+impl Default for AdmissionConfig {
+    // One worker per core, since each evaluation keeps a core busy
+    fn default() -> Self {
+        Self {
+            workers: std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            queue_depth: DEFAULT_QUEUE_DEPTH,
+        }
+    }
+}
+
+impl AdmissionConfig {
+    // Read HERMETIC_FHE_WORKERS and HERMETIC_FHE_QUEUE_DEPTH, falling back to the defaults
+    pub fn from_env() -> Result<Self> {
+        let mut config = Self::default();
+        if let Some(workers) = env_usize("HERMETIC_FHE_WORKERS")? {
+            config.workers = workers;
+        }
+        if let Some(queue_depth) = env_usize("HERMETIC_FHE_QUEUE_DEPTH")? {
+            config.queue_depth = queue_depth;
+        }
+        if config.workers == 0 {
+            return Err(anyhow!("HERMETIC_FHE_WORKERS must be at least 1"));
+        }
+        Ok(config)
+    }
+
+    // One evaluation at a time, admitted in the order they arrive, so a replayed run
+    // schedules its work exactly as the original did
+    pub fn deterministic() -> Self {
+        Self {
+            workers: 1,
+            ..Self::default()
+        }
+    }
+}
+
+// How workers are shared between tenants when they compete for them. Each tenant is charged
+// the compute time its evaluations use, divided by its weight, and a free worker goes to the
+// waiting tenant that has been charged least, so a tenant running long circuits doesn't hold
+// up another's single additions.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FairShareConfig {
+    // Tenants not listed have weight 1
+    pub weights: HashMap<String, u32>,
+    // Most workers one tenant may hold at once
+    pub tenant_max_workers: Option<usize>,
+    // Most evaluations one tenant may have waiting
+    pub tenant_queue_depth: Option<usize>,
+    // Most workers each listed RPC may hold at once, e.g. RunInference
+    pub rpc_max_workers: HashMap<String, usize>,
+}
+
+impl FairShareConfig {
+    // HERMETIC_FHE_TENANT_WEIGHTS (e.g. "acme=4,globex=2"), HERMETIC_FHE_TENANT_MAX_WORKERS,
+    // HERMETIC_FHE_TENANT_QUEUE_DEPTH and HERMETIC_FHE_RPC_MAX_WORKERS (e.g.
+    // "RunInference=2"); every tenant has weight 1 and no caps by default
+    pub fn from_env() -> Result<Self> {
+        let weights = env_map("HERMETIC_FHE_TENANT_WEIGHTS")?
+            .into_iter()
+            .map(|(tenant, weight)| {
+                u32::try_from(weight)
+                    .ok()
+                    .filter(|weight| *weight > 0)
+                    .map(|weight| (tenant.clone(), weight))
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "HERMETIC_FHE_TENANT_WEIGHTS: weight of {} must be 1 or more",
+                            tenant
+                        )
+                    })
+            })
+            .collect::<Result<_>>()?;
+        let config = Self {
+            weights,
+            tenant_max_workers: env_usize("HERMETIC_FHE_TENANT_MAX_WORKERS")?,
+            tenant_queue_depth: env_usize("HERMETIC_FHE_TENANT_QUEUE_DEPTH")?,
+            rpc_max_workers: env_map("HERMETIC_FHE_RPC_MAX_WORKERS")?,
+        };
+        if config.tenant_max_workers == Some(0) || config.rpc_max_workers.values().any(|max| *max == 0) {
+            return Err(anyhow!("Worker caps must be at least 1"));
+        }
+        Ok(config)
+    }
+
+    pub fn weight(&self, tenant: &str) -> u32 {
+        self.weights.get(tenant).copied().unwrap_or(1)
+    }
+}
+
+// name=count pairs, comma-separated
+fn env_map(name: &str) -> Result<HashMap<String, usize>> {
+    let Ok(value) = std::env::var(name) else {
+        return Ok(HashMap::new());
+    };
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, count) = pair
+                .split_once('=')
+                .ok_or_else(|| anyhow!("{} entries must look like name=count, got '{}'", name, pair))?;
+            let count = count
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("{}: '{}' is not a non-negative integer", name, count.trim()))?;
+            Ok((key.trim().to_string(), count))
+        })
+        .collect()
+}
+
+fn env_usize(name: &str) -> Result<Option<usize>> {
+    match std::env::var(name) {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| anyhow!("{} must be a non-negative integer", name)),
+        Err(_) => Ok(None),
+    }
+}
+
+#[derive(Debug)]
+pub struct QueueFull;
+
+// Point-in-time view of the worker pool
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AdmissionMetrics {
+    pub workers: usize,
+    pub running: usize,
+    pub queued: usize,
+    pub queue_depth: usize,
+    pub rejected: u64,
+}
+
+// One tenant's share of the worker pool, since startup where it's a total
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TenantMetrics {
+    pub tenant: String,
+    pub weight: u32,
+    pub running: usize,
+    pub queued: usize,
+    pub admitted: u64,
+    pub rejected: u64,
+    pub compute: Duration,
+}
+
+// Bounded queue in front of the evaluation workers. Work beyond the running and queued
+// limits is turned away immediately instead of piling up in memory. Waiting work is
+// admitted in arrival order, or by fair share between tenants when that is configured.
+pub struct AdmissionControl {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    config: AdmissionConfig,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    // Kept with the queues, so weights and caps can change while calls wait
+    fair_share: Option<FairShareConfig>,
+    idle: usize,
+    queued: usize,
+    rejected: u64,
+    next_ticket: u64,
+    // The charge of the tenant most recently admitted. A tenant that had nothing running or
+    // waiting starts here, so it can't bank the time it spent idle.
+    clock: u128,
+    tenants: HashMap<String, TenantState>,
+    rpc_running: HashMap<&'static str, usize>,
+    // Moving average of how long evaluations hold a worker, over the last few dozen
+    mean_evaluation: Option<Duration>,
+}
+
+#[derive(Default)]
+struct TenantState {
+    running: usize,
+    waiting: VecDeque<Waiter>,
+    // Compute nanoseconds used, divided by the tenant's weight
+    charged: u128,
+    admitted: u64,
+    rejected: u64,
+    compute: Duration,
+}
+
+struct Waiter {
+    ticket: u64,
+    operation: &'static str,
+    grant: oneshot::Sender<Permit>,
+}
+
+impl Shared {
+    fn can_run(&self, state: &State, tenant: &TenantState, operation: &str) -> bool {
+        let Some(fair_share) = &state.fair_share else {
+            return true;
+        };
+        let tenant_ok = fair_share
+            .tenant_max_workers
+            .is_none_or(|max| tenant.running < max);
+        let rpc_ok = fair_share
+            .rpc_max_workers
+            .get(operation)
+            .is_none_or(|max| state.rpc_running.get(operation).copied().unwrap_or(0) < *max);
+        tenant_ok && rpc_ok
+    }
+
+    fn start(&self, state: &mut State, tenant: &str, operation: &'static str) {
+        state.idle -= 1;
+        *state.rpc_running.entry(operation).or_default() += 1;
+        let tenant = state
+            .tenants
+            .get_mut(tenant)
+            .expect("a tenant is added before it is admitted");
+        tenant.running += 1;
+        tenant.admitted += 1;
+        state.clock = state.clock.max(tenant.charged);
+    }
+
+    fn finish(&self, state: &mut State, tenant: &str, operation: &'static str, elapsed: Duration) {
+        state.idle += 1;
+        if let Some(running) = state.rpc_running.get_mut(operation) {
+            *running -= 1;
+        }
+        let weight = state
+            .fair_share
+            .as_ref()
+            .map_or(1, |fair_share| fair_share.weight(tenant));
+        let tenant = state
+            .tenants
+            .get_mut(tenant)
+            .expect("a running tenant is never removed");
+        tenant.running -= 1;
+        tenant.compute += elapsed;
+        tenant.charged += elapsed.as_nanos() / weight as u128;
+        state.mean_evaluation = Some(match state.mean_evaluation {
+            Some(mean) => (mean * 7 + elapsed) / 8,
+            None => elapsed,
+        });
+    }
+
+    // The waiter to admit next: the earliest, or with fair share the earliest of the
+    // least-charged tenant, among those the caps allow to run
+    fn next(&self, state: &State) -> Option<(String, usize)> {
+        let mut best: Option<((u128, u64), &str, usize)> = None;
+        for (name, tenant) in &state.tenants {
+            let Some(index) = tenant
+                .waiting
+                .iter()
+                .position(|waiter| self.can_run(state, tenant, waiter.operation))
+            else {
+                continue;
+            };
+            let ticket = tenant.waiting[index].ticket;
+            let rank = match state.fair_share {
+                Some(_) => (tenant.charged, ticket),
+                None => (0, ticket),
+            };
+            if best.as_ref().is_none_or(|(best, _, _)| rank < *best) {
+                best = Some((rank, name, index));
+            }
+        }
+        best.map(|(_, name, index)| (name.to_string(), index))
+    }
+
+    // Hand free workers to waiters until one or the other runs out
+    fn dispatch(self: &Arc<Self>, state: &mut State) {
+        while state.idle > 0 {
+            let Some((tenant, index)) = self.next(state) else {
+                break;
+            };
+            let waiter = state
+                .tenants
+                .get_mut(&tenant)
+                .and_then(|queue| queue.waiting.remove(index))
+                .expect("next returns a queued waiter");
+            state.queued -= 1;
+            self.start(state, &tenant, waiter.operation);
+            let permit = Permit::new(self.clone(), tenant.clone(), waiter.operation);
+            // The caller gave up after being chosen; the worker goes to the next one
+            if let Err(mut permit) = waiter.grant.send(permit) {
+                permit.armed = false;
+                self.finish(state, &tenant, waiter.operation, Duration::ZERO);
+                if let Some(queue) = state.tenants.get_mut(&tenant) {
+                    queue.admitted -= 1;
+                }
+            }
+        }
+    }
+}
+
+impl AdmissionControl {
+    pub fn new(config: AdmissionConfig) -> Self {
+        Self::build(config, None)
+    }
+
+    fn build(config: AdmissionConfig, fair_share: Option<FairShareConfig>) -> Self {
+        let state = State {
+            idle: config.workers,
+            fair_share,
+            ..State::default()
+        };
+        Self {
+            shared: Arc::new(Shared {
+                config,
+                state: Mutex::new(state),
+            }),
+        }
+    }
+
+    // Share workers between tenants by weight and cap what each tenant and RPC may hold,
+    // rather than admitting strictly in arrival order
+    pub fn with_fair_share(self, fair_share: FairShareConfig) -> Self {
+        Self::build(self.shared.config, Some(fair_share))
+    }
+
+    // Replace the weights and caps while the server runs. Calls already running keep their
+    // workers; waiting ones are admitted under the new caps, and compute is charged at the
+    // new weights from now on. False, changing nothing, when fair share is off.
+    pub fn set_fair_share(&self, fair_share: FairShareConfig) -> bool {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.fair_share.is_none() {
+            return false;
+        }
+        state.fair_share = Some(fair_share);
+        self.shared.dispatch(&mut state);
+        true
+    }
+
+    pub fn fair_share(&self) -> Option<FairShareConfig> {
+        self.shared.state.lock().unwrap().fair_share.clone()
+    }
+
+    // Wait for a worker slot, or fail straight away if the queue is already full.
+    // The slot is held until the returned permit is dropped.
+    pub async fn admit(&self) -> Result<Permit, QueueFull> {
+        self.admit_as("", "").await
+    }
+
+    // Wait for a worker slot for one of the tenant's calls to the RPC
+    pub async fn admit_as(&self, tenant: &str, operation: &'static str) -> Result<Permit, QueueFull> {
+        let shared = &self.shared;
+        let (ticket, granted) = {
+            let mut state = shared.state.lock().unwrap();
+            let clock = state.clock;
+            let queue = state.tenants.entry(tenant.to_string()).or_default();
+            if queue.running == 0 && queue.waiting.is_empty() {
+                queue.charged = queue.charged.max(clock);
+            }
+
+            // Workers are only ever idle when nobody waiting is allowed to run
+            let state = &mut *state;
+            let queue = &state.tenants[tenant];
+            if state.idle > 0 && shared.can_run(state, queue, operation) {
+                shared.start(state, tenant, operation);
+                return Ok(Permit::new(shared.clone(), tenant.to_string(), operation));
+            }
+
+            let tenant_depth = state
+                .fair_share
+                .as_ref()
+                .and_then(|fair_share| fair_share.tenant_queue_depth);
+            let tenant_full = tenant_depth.is_some_and(|depth| queue.waiting.len() >= depth);
+            if state.queued >= shared.config.queue_depth || tenant_full {
+                state.rejected += 1;
+                if let Some(queue) = state.tenants.get_mut(tenant) {
+                    queue.rejected += 1;
+                }
+                return Err(QueueFull);
+            }
+
+            let (grant, granted) = oneshot::channel();
+            let ticket = state.next_ticket;
+            state.next_ticket += 1;
+            state.queued += 1;
+            let queue = state.tenants.get_mut(tenant).expect("added above");
+            queue.waiting.push_back(Waiter {
+                ticket,
+                operation,
+                grant,
+            });
+            (ticket, granted)
+        };
+
+        // Leave the queue whether we get a worker or the caller gives up waiting
+        let _queued = QueueSlot {
+            shared,
+            tenant,
+            ticket,
+        };
+        Ok(granted
+            .await
+            .expect("a waiter is granted a worker or removed from the queue first"))
+    }
+
+    pub fn metrics(&self) -> AdmissionMetrics {
+        let state = self.shared.state.lock().unwrap();
+        AdmissionMetrics {
+            workers: self.shared.config.workers,
+            running: self.shared.config.workers - state.idle,
+            queued: state.queued,
+            queue_depth: self.shared.config.queue_depth,
+            rejected: state.rejected,
+        }
+    }
+
+    // Roughly how long a call arriving now would wait for a worker: the rounds of work
+    // queued ahead of it, at the recent mean evaluation time. Zero while a worker is free,
+    // and before any evaluation has finished to go by.
+    pub fn estimated_wait(&self) -> Duration {
+        let state = self.shared.state.lock().unwrap();
+        if state.idle > 0 && state.queued == 0 {
+            return Duration::ZERO;
+        }
+        let rounds = state.queued / self.shared.config.workers + 1;
+        state.mean_evaluation.unwrap_or_default() * rounds as u32
+    }
+
+    // Every tenant that has asked for a worker since startup, by name
+    pub fn tenant_metrics(&self) -> Vec<TenantMetrics> {
+        let state = self.shared.state.lock().unwrap();
+        let mut tenants: Vec<TenantMetrics> = state
+            .tenants
+            .iter()
+            .map(|(name, tenant)| TenantMetrics {
+                tenant: name.clone(),
+                weight: state
+                    .fair_share
+                    .as_ref()
+                    .map_or(1, |fair_share| fair_share.weight(name)),
+                running: tenant.running,
+                queued: tenant.waiting.len(),
+                admitted: tenant.admitted,
+                rejected: tenant.rejected,
+                compute: tenant.compute,
+            })
+            .collect();
+        tenants.sort_by(|a, b| a.tenant.cmp(&b.tenant));
+        tenants
+    }
+}
+
+impl Default for AdmissionControl {
+    fn default() -> Self {
+        Self::new(AdmissionConfig::default())
+    }
+}
+
+// A worker slot, given back when dropped. The time it was held is charged to its tenant.
+pub struct Permit {
+    shared: Arc<Shared>,
+    tenant: String,
+    operation: &'static str,
+    started: Instant,
+    armed: bool,
+}
+
+impl Permit {
+    fn new(shared: Arc<Shared>, tenant: String, operation: &'static str) -> Self {
+        Self {
+            shared,
+            tenant,
+            operation,
+            started: Instant::now(),
+            armed: true,
+        }
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        let mut state = self.shared.state.lock().unwrap();
+        self.shared
+            .finish(&mut state, &self.tenant, self.operation, self.started.elapsed());
+        self.shared.dispatch(&mut state);
+    }
+}
+
+struct QueueSlot<'a> {
+    shared: &'a Shared,
+    tenant: &'a str,
+    ticket: u64,
+}
+
+impl Drop for QueueSlot<'_> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        let Some(queue) = state.tenants.get_mut(self.tenant) else {
+            return;
+        };
+        if let Some(index) = queue
+            .waiting
+            .iter()
+            .position(|waiter| waiter.ticket == self.ticket)
+        {
+            queue.waiting.remove(index);
+            state.queued -= 1;
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use tfhe::FheUint8;
+use uuid::Uuid;
+
+use crate::crypto::provenance::Provenance;
+use crate::crypto::sharded::ShardedMap;
+use crate::service::counter::{Counter, CounterValue};
+
+// Most metrics one aggregation keeps, per window if it has windows
+pub const MAX_METRICS: usize = 256;
+
+// Longest metric name, in bytes
+pub const MAX_METRIC_NAME_LENGTH: usize = 128;
+
+// Most windows one contribution falls in, so a sliding window costs at most this many
+// additions per contribution
+pub const MAX_OVERLAPPING_WINDOWS: u64 = 24;
+
+// Most windows kept per aggregation, a week of hourly windows. The oldest are dropped first.
+pub const MAX_WINDOWS_KEPT: usize = 168;
+
+// Fixed-size windows of arrival time, in seconds. Windows start at multiples of the slide
+// since the Unix epoch and are `size` long, so with slide equal to size they tumble, and
+// with a smaller slide they overlap and each contribution falls in size / slide of them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Windowing {
+    pub size: u64,
+    pub slide: u64,
+}
+
+impl Windowing {
+    // Starts of the windows holding time `at`, oldest first
+    pub fn starts(&self, at: u64) -> Vec<u64> {
+        let latest = at - at % self.slide;
+        (0..self.size / self.slide)
+            .rev()
+            .filter_map(|i| latest.checked_sub(i * self.slide))
+            .collect()
+    }
+
+    pub fn end(&self, start: u64) -> u64 {
+        start + self.size
+    }
+
+    // Start of the newest window that has ended by time `at`
+    pub fn latest_closed(&self, at: u64) -> Option<u64> {
+        let ended = at.checked_sub(self.size)?;
+        Some(ended - ended % self.slide)
+    }
+}
+
+type Metrics = BTreeMap<String, Arc<Counter>>;
+
+struct Windows {
+    // Metrics by window start; an aggregation without windows keeps everything under 0
+    windows: BTreeMap<u64, Metrics>,
+    // Start of the newest window dropped to stay within MAX_WINDOWS_KEPT
+    dropped_through: Option<u64>,
+}
+
+// Encrypted running sums by metric name, under the server key the aggregation was created
+// with, kept either once for all time or once per window. Each sum is a counter, so
+// contributions from any number of clients are added one at a time and none is lost.
+pub struct Aggregation {
+    server_key_id: String,
+    windowing: Option<Windowing>,
+    state: Mutex<Windows>,
+}
+
+impl Aggregation {
+    pub fn server_key_id(&self) -> &str {
+        &self.server_key_id
+    }
+
+    pub fn windowing(&self) -> Option<Windowing> {
+        self.windowing
+    }
+
+    // Windows a contribution arriving at `at` is added to
+    pub fn windows_at(&self, at: u64) -> Vec<u64> {
+        match self.windowing {
+            Some(windowing) => windowing.starts(at),
+            None => vec![0],
+        }
+    }
+
+    pub fn metric(&self, window: u64, name: &str) -> Option<Arc<Counter>> {
+        let state = self.state.lock().unwrap();
+        state.windows.get(&window)?.get(name).cloned()
+    }
+
+    // Start a metric's sum in a window from zero, or return the metric if another
+    // contribution started it first. None when the window already has MAX_METRICS metrics.
+    pub fn add_metric(&self, window: u64, name: &str, zero: FheUint8) -> Option<Arc<Counter>> {
+        let mut state = self.state.lock().unwrap();
+        if !state.windows.contains_key(&window) {
+            while state.windows.len() >= MAX_WINDOWS_KEPT {
+                let (oldest, _) = state.windows.pop_first().unwrap();
+                state.dropped_through = Some(oldest);
+            }
+        }
+        let metrics = state.windows.entry(window).or_default();
+        if let Some(counter) = metrics.get(name) {
+            return Some(counter.clone());
+        }
+        if metrics.len() >= MAX_METRICS {
+            return None;
+        }
+        let zero = Arc::new(zero);
+        let counter = Arc::new(Counter::new(&self.server_key_id, zero, Provenance::default()));
+        metrics.insert(name.to_string(), counter.clone());
+        Some(counter)
+    }
+
+    // Each metric's current sum in a window, by name; a window nothing arrived in has none.
+    // None if the window was dropped to make room for newer ones.
+    pub fn snapshot(&self, window: u64) -> Option<Vec<(String, CounterValue)>> {
+        let state = self.state.lock().unwrap();
+        if state.dropped_through.is_some_and(|dropped| window <= dropped) {
+            return None;
+        }
+        let metrics = state.windows.get(&window).map(|metrics| {
+            metrics
+                .iter()
+                .map(|(name, counter)| (name.clone(), counter.read()))
+                .collect()
+        });
+        Some(metrics.unwrap_or_default())
+    }
+
+    // Starts of the windows kept, oldest first
+    pub fn windows(&self) -> Vec<u64> {
+        self.state.lock().unwrap().windows.keys().copied().collect()
+    }
+
+    // Contributions added to a window so far, over all metrics
+    pub fn window_contributions(&self, window: u64) -> u64 {
+        let state = self.state.lock().unwrap();
+        state
+            .windows
+            .get(&window)
+            .map(|metrics| metrics.values().map(|counter| counter.read().increments).sum())
+            .unwrap_or(0)
+    }
+
+    // Contributions added so far, over all metrics. With overlapping windows, one
+    // contribution counts once per window it fell in.
+    pub fn contributions(&self) -> u64 {
+        let windows = self.windows();
+        windows
+            .into_iter()
+            .map(|window| self.window_contributions(window))
+            .sum()
+    }
+}
+
+// Aggregations by ID, so many contributors can feed the same sums without coordinating
+pub struct AggregationStore {
+    aggregations: ShardedMap<Arc<Aggregation>>,
+}
+
+impl AggregationStore {
+    pub fn new() -> Self {
+        Self {
+            aggregations: ShardedMap::new(),
+        }
+    }
+
+    pub fn create(&self, server_key_id: &str, windowing: Option<Windowing>) -> String {
+        let id = Uuid::new_v4().to_string();
+        let aggregation = Aggregation {
+            server_key_id: server_key_id.to_string(),
+            windowing,
+            state: Mutex::new(Windows {
+                windows: BTreeMap::new(),
+                dropped_through: None,
+            }),
+        };
+        self.aggregations.insert(id.clone(), Arc::new(aggregation));
+        id
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<Aggregation>> {
+        self.aggregations.get(id)
+    }
+
+    // Contributions already holding the aggregation finish, but it can no longer be found
+    pub fn remove(&self, id: &str) -> Option<Arc<Aggregation>> {
+        self.aggregations.remove(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.aggregations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for AggregationStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
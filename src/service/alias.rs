@@ -0,0 +1,101 @@
+use crate::api::{
+    ArgMaxRequest, BucketTimestampRequest, CircuitEvaluationRequest, CompareTimestampRequest,
+    CreateAggregationRequest, CreateBloomFilterRequest, CreateCounterRequest, CreateElectionRequest,
+    DecryptBooleanRequest, DecryptIntegerBatchRequest, DecryptIntegerRequest, DecryptMatrixRequest,
+    DecryptRealVectorRequest, DecryptTimestampRequest, EncryptAndEvaluateRequest, EncryptBooleanRequest,
+    EncryptIntegerBatchRequest, EncryptIntegerRequest, EncryptMatrixRequest, EncryptRealVectorRequest,
+    EncryptTimestampRequest, EvaluateAndDecryptRequest, EvaluationRequest, InferenceRequest,
+    IntegerBatchEvaluationRequest, InvokeCustomOperationRequest, JoinOperationRequest, LibraryCircuitRequest,
+    MapOperationRequest, MatchStringRequest, MatrixAddRequest, MatrixScaleRequest, MatrixVectorProductRequest,
+    PirQueryRequest, ReEncryptionKeyRequest, RealVectorEvaluationRequest, ReduceOperationRequest,
+    SetMembershipRequest, SortVectorRequest, TimestampDifferenceRequest, TransformSeriesRequest,
+    WarmServerKeysRequest,
+};
+use crate::crypto::alias::KeyAlias;
+
+// Which key of a pair a field names
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyRole {
+    Client,
+    Server,
+}
+
+impl KeyRole {
+    pub fn key_id(self, alias: &KeyAlias) -> &str {
+        match self {
+            KeyRole::Client => &alias.client_key_id,
+            KeyRole::Server => &alias.server_key_id,
+        }
+    }
+}
+
+// A request naming keys, whose fields may hold aliases for handlers to swap for IDs
+// before anything reads them
+pub trait KeyReferences {
+    fn key_fields(&mut self) -> Vec<(KeyRole, &mut String)>;
+}
+
+macro_rules! key_references {
+    ($($message:ident { $($role:ident $field:ident),+ })*) => {$(
+        impl KeyReferences for $message {
+            fn key_fields(&mut self) -> Vec<(KeyRole, &mut String)> {
+                vec![$((KeyRole::$role, &mut self.$field)),+]
+            }
+        }
+    )*};
+}
+
+// Every request with a client_key_id or server_key_id field, or one for the source and
+// target of a re-encryption key
+key_references! {
+    EncryptBooleanRequest { Client client_key_id }
+    EncryptIntegerRequest { Client client_key_id }
+    EvaluationRequest { Server server_key_id }
+    CircuitEvaluationRequest { Server server_key_id }
+    LibraryCircuitRequest { Server server_key_id }
+    InvokeCustomOperationRequest { Server server_key_id }
+    EvaluateAndDecryptRequest { Client client_key_id, Server server_key_id }
+    EncryptAndEvaluateRequest { Client client_key_id, Server server_key_id }
+    SortVectorRequest { Server server_key_id }
+    ArgMaxRequest { Server server_key_id }
+    SetMembershipRequest { Server server_key_id }
+    PirQueryRequest { Server server_key_id }
+    MatchStringRequest { Server server_key_id }
+    CreateCounterRequest { Server server_key_id }
+    CreateBloomFilterRequest { Server server_key_id }
+    CreateAggregationRequest { Server server_key_id }
+    CreateElectionRequest { Server server_key_id }
+    EncryptMatrixRequest { Client client_key_id }
+    DecryptMatrixRequest { Client client_key_id }
+    MatrixVectorProductRequest { Server server_key_id }
+    MatrixAddRequest { Server server_key_id }
+    MatrixScaleRequest { Server server_key_id }
+    EncryptTimestampRequest { Client client_key_id }
+    DecryptTimestampRequest { Client client_key_id }
+    CompareTimestampRequest { Server server_key_id }
+    TimestampDifferenceRequest { Server server_key_id }
+    BucketTimestampRequest { Server server_key_id }
+    EncryptRealVectorRequest { Client client_key_id }
+    DecryptRealVectorRequest { Client client_key_id }
+    RealVectorEvaluationRequest { Server server_key_id }
+    EncryptIntegerBatchRequest { Client client_key_id }
+    DecryptIntegerBatchRequest { Client client_key_id }
+    IntegerBatchEvaluationRequest { Server server_key_id }
+    ReEncryptionKeyRequest { Client source_client_key_id, Client target_client_key_id }
+    InferenceRequest { Server server_key_id }
+    DecryptBooleanRequest { Client client_key_id }
+    DecryptIntegerRequest { Client client_key_id }
+    MapOperationRequest { Server server_key_id }
+    JoinOperationRequest { Server server_key_id }
+    ReduceOperationRequest { Server server_key_id }
+    TransformSeriesRequest { Server server_key_id }
+}
+
+impl KeyReferences for WarmServerKeysRequest {
+    fn key_fields(&mut self) -> Vec<(KeyRole, &mut String)> {
+        self.server_key_ids
+            .iter_mut()
+            .map(|id| (KeyRole::Server, id))
+            .collect()
+    }
+}
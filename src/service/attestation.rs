@@ -0,0 +1,157 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+use uuid::Uuid;
+
+use crate::crypto::attestation::{parse_quote, report_data, TeePlatform};
+
+// Produces quotes over 64 bytes of report data from the platform the server runs on
+pub trait QuoteProvider: Send + Sync {
+    fn quote(&self, report_data: &[u8; 64]) -> Result<Vec<u8>>;
+}
+
+// SGX quotes through Gramine's pseudo-files. Writing the report data and reading the
+// quote are two steps on one device, so concurrent quotes take turns.
+pub struct GramineQuotes {
+    lock: Mutex<()>,
+}
+
+impl GramineQuotes {
+    pub fn new() -> Self {
+        Self { lock: Mutex::new(()) }
+    }
+}
+
+impl Default for GramineQuotes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl QuoteProvider for GramineQuotes {
+    fn quote(&self, report_data: &[u8; 64]) -> Result<Vec<u8>> {
+        let _turn = self.lock.lock().unwrap();
+        fs::write("/dev/attestation/user_report_data", report_data)
+            .map_err(|e| anyhow!("Failed to write SGX report data: {}", e))?;
+        fs::read("/dev/attestation/quote").map_err(|e| anyhow!("Failed to read SGX quote: {}", e))
+    }
+}
+
+// SEV-SNP and TDX quotes through the kernel's configfs-tsm interface (Linux 6.7 and
+// later), one report directory per quote so concurrent quotes don't share one
+pub struct ConfigfsQuotes {
+    root: PathBuf,
+    // The provider the kernel must report, so a TDX server can't hand out an SNP report
+    provider: &'static str,
+}
+
+impl ConfigfsQuotes {
+    pub fn new(platform: TeePlatform) -> Result<Self> {
+        let provider = match platform {
+            TeePlatform::SevSnp => "sev_guest",
+            TeePlatform::Tdx => "tdx_guest",
+            TeePlatform::Sgx => return Err(anyhow!("SGX quotes come from Gramine, not configfs-tsm")),
+        };
+        Ok(Self {
+            root: PathBuf::from("/sys/kernel/config/tsm/report"),
+            provider,
+        })
+    }
+}
+
+impl QuoteProvider for ConfigfsQuotes {
+    fn quote(&self, report_data: &[u8; 64]) -> Result<Vec<u8>> {
+        let report = self.root.join(Uuid::new_v4().to_string());
+        fs::create_dir(&report).map_err(|e| anyhow!("Failed to create {}: {}", report.display(), e))?;
+        let result = (|| {
+            fs::write(report.join("inblob"), report_data)?;
+            let quote = fs::read(report.join("outblob"))?;
+            let provider = fs::read_to_string(report.join("provider"))?;
+            Ok::<_, std::io::Error>((quote, provider))
+        })();
+        let _ = fs::remove_dir(&report);
+        let (quote, provider) = result.map_err(|e| anyhow!("Failed to take a quote: {}", e))?;
+        if provider.trim() != self.provider {
+            return Err(anyhow!(
+                "Quote came from {}, expected {}",
+                provider.trim(),
+                self.provider
+            ));
+        }
+        Ok(quote)
+    }
+}
+
+// A quote for one client, and the build measurement it carries
+pub struct Attestation {
+    pub quote: Vec<u8>,
+    pub measurement: Vec<u8>,
+}
+
+// Quotes binding the server's TLS certificate, for clients to check before trusting the
+// server with their data
+pub struct Attestor {
+    platform: TeePlatform,
+    // PEM, exactly as served, so clients can hash the certificate they pinned
+    certificate: Vec<u8>,
+    provider: Box<dyn QuoteProvider>,
+}
+
+impl Attestor {
+    pub fn new(platform: TeePlatform, certificate: Vec<u8>, provider: Box<dyn QuoteProvider>) -> Self {
+        Self {
+            platform,
+            certificate,
+            provider,
+        }
+    }
+
+    // HERMETIC_FHE_ATTESTATION names the platform: sgx, sev-snp or tdx. Unset turns
+    // attestation off. A quote has to bind a TLS certificate, so it needs one.
+    pub fn from_env(certificate: Option<&[u8]>) -> Result<Option<Self>> {
+        let Ok(name) = std::env::var("HERMETIC_FHE_ATTESTATION") else {
+            return Ok(None);
+        };
+        let platform = TeePlatform::parse(&name).ok_or_else(|| {
+            anyhow!(
+                "HERMETIC_FHE_ATTESTATION must be sgx, sev-snp or tdx, got '{}'",
+                name
+            )
+        })?;
+        let certificate =
+            certificate.ok_or_else(|| anyhow!("HERMETIC_FHE_ATTESTATION requires HERMETIC_FHE_TLS_CERT"))?;
+        let provider: Box<dyn QuoteProvider> = match platform {
+            TeePlatform::Sgx => Box::new(GramineQuotes::new()),
+            TeePlatform::SevSnp | TeePlatform::Tdx => Box::new(ConfigfsQuotes::new(platform)?),
+        };
+        Ok(Some(Self::new(platform, certificate.to_vec(), provider)))
+    }
+
+    pub fn platform(&self) -> TeePlatform {
+        self.platform
+    }
+
+    pub fn certificate(&self) -> &[u8] {
+        &self.certificate
+    }
+
+    // A quote over the certificate and the client's nonce. Blocks on the platform's
+    // quoting interface.
+    pub fn attest(&self, nonce: &[u8]) -> Result<Attestation> {
+        let data = report_data(&self.certificate, nonce);
+        let quote = self.provider.quote(&data)?;
+        let fields = parse_quote(self.platform, &quote)?;
+        if fields.report_data != data {
+            return Err(anyhow!(
+                "The {} quote does not carry the report data",
+                self.platform.name()
+            ));
+        }
+        Ok(Attestation {
+            quote,
+            measurement: fields.measurement,
+        })
+    }
+}
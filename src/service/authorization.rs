@@ -0,0 +1,222 @@
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+// Header naming who is calling, set by the authenticating proxy in front of the server.
+// The server does not authenticate callers itself, so the proxy must overwrite whatever
+// value a client sends.
+pub const PRINCIPAL_HEADER: &str = "x-principal-id";
+
+// What a policy decides on: who is calling, for which tenant, which RPC, and the key the
+// request names. Empty strings where the call has none.
+#[derive(Clone, Debug, Default, Serialize, PartialEq, Eq)]
+pub struct AuthorizationInput {
+    pub principal: String,
+    pub tenant: String,
+    // The RPC's name, e.g. "EvaluateOperation"
+    pub method: String,
+    pub key_id: String,
+}
+
+// Decides whether a call may go ahead. An error means no decision could be made, and the
+// call is refused.
+pub trait PolicyEngine: Send + Sync {
+    fn allows(&self, input: &AuthorizationInput) -> Result<bool>;
+
+    // Pick up changes to the policy's source if it has changed, or whether or not it has when
+    // forced, returning whether it was reloaded. On an error the policy in force stays.
+    fn reload(&self, _force: bool) -> Result<bool> {
+        Ok(false)
+    }
+}
+
+// Asks an Open Policy Agent decision endpoint, e.g. http://localhost:8181/v1/data/hermetic/allow,
+// POSTing {"input": ...} and expecting {"result": true}. A missing or non-boolean result is
+// a denial, as OPA returns no result for an undefined rule.
+pub struct OpaPolicy {
+    url: String,
+    timeout: Duration,
+}
+
+impl OpaPolicy {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            timeout: Duration::from_secs(2),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct OpaQuery<'a> {
+    input: &'a AuthorizationInput,
+}
+
+#[derive(Deserialize)]
+struct OpaDecision {
+    #[serde(default)]
+    result: serde_json::Value,
+}
+
+impl PolicyEngine for OpaPolicy {
+    fn allows(&self, input: &AuthorizationInput) -> Result<bool> {
+        let body = serde_json::to_vec(&OpaQuery { input })?;
+        let decision: OpaDecision = serde_json::from_slice(&post(&self.url, self.timeout, &body)?)
+            .map_err(|e| anyhow!("Unreadable decision from {}: {}", self.url, e))?;
+        Ok(decision.result == serde_json::Value::Bool(true))
+    }
+}
+
+#[cfg(feature = "opa")]
+fn post(url: &str, timeout: Duration, body: &[u8]) -> Result<Vec<u8>> {
+    let response = ureq::post(url)
+        .timeout(timeout)
+        .set("Content-Type", "application/json")
+        .send_bytes(body)
+        .map_err(|e| anyhow!("Policy query to {} failed: {}", url, e))?;
+    Ok(response.into_string()?.into_bytes())
+}
+
+#[cfg(not(feature = "opa"))]
+fn post(_url: &str, _timeout: Duration, _body: &[u8]) -> Result<Vec<u8>> {
+    Err(anyhow!("Built without the opa feature"))
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Effect {
+    Allow,
+    Deny,
+}
+
+// One rule of a policy file. Each list matches any value when empty or when it holds "*",
+// and a value ending in "*" matches by prefix, so "Decrypt*" covers every decryption.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PolicyRule {
+    pub effect: Effect,
+    #[serde(default)]
+    pub principals: Vec<String>,
+    #[serde(default)]
+    pub tenants: Vec<String>,
+    #[serde(default)]
+    pub methods: Vec<String>,
+    #[serde(default)]
+    pub keys: Vec<String>,
+}
+
+impl PolicyRule {
+    fn matches(&self, input: &AuthorizationInput) -> bool {
+        let any = |patterns: &[String], value: &str| {
+            patterns.is_empty()
+                || patterns.iter().any(|pattern| match pattern.strip_suffix('*') {
+                    Some(prefix) => value.starts_with(prefix),
+                    None => pattern == value,
+                })
+        };
+        any(&self.principals, &input.principal)
+            && any(&self.tenants, &input.tenant)
+            && any(&self.methods, &input.method)
+            && any(&self.keys, &input.key_id)
+    }
+}
+
+// Rules read from a JSON file, {"rules": [...]}, for deployments without a policy server.
+// A call goes ahead if some rule allows it and none denies it.
+#[derive(Clone, Debug, Deserialize)]
+pub struct RulePolicy {
+    pub rules: Vec<PolicyRule>,
+}
+
+impl RulePolicy {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents =
+            std::fs::read(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        serde_json::from_slice(&contents)
+            .map_err(|e| anyhow!("Invalid policy file {}: {}", path.display(), e))
+    }
+}
+
+impl PolicyEngine for RulePolicy {
+    fn allows(&self, input: &AuthorizationInput) -> Result<bool> {
+        let matching = self.rules.iter().filter(|rule| rule.matches(input));
+        let mut allowed = false;
+        for rule in matching {
+            match rule.effect {
+                Effect::Deny => return Ok(false),
+                Effect::Allow => allowed = true,
+            }
+        }
+        Ok(allowed)
+    }
+}
+
+// A rules file that can be edited while the server runs: reloading reads it again if it
+// was modified since the last attempt. A version that doesn't parse is reported and
+// skipped, and calls go on being decided by the last one that did.
+pub struct PolicyFile {
+    path: PathBuf,
+    policy: RwLock<RulePolicy>,
+    // Modification time of the version last read, whether or not it parsed
+    read: RwLock<Option<SystemTime>>,
+}
+
+impl PolicyFile {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let read = modified(&path);
+        let policy = RulePolicy::load(&path)?;
+        Ok(Self {
+            path,
+            policy: RwLock::new(policy),
+            read: RwLock::new(read),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl PolicyEngine for PolicyFile {
+    fn allows(&self, input: &AuthorizationInput) -> Result<bool> {
+        self.policy.read().unwrap().allows(input)
+    }
+
+    fn reload(&self, force: bool) -> Result<bool> {
+        let current = modified(&self.path);
+        if !force && current == *self.read.read().unwrap() {
+            return Ok(false);
+        }
+        *self.read.write().unwrap() = current;
+        let policy = RulePolicy::load(&self.path)?;
+        *self.policy.write().unwrap() = policy;
+        Ok(true)
+    }
+}
+
+// When a file was last modified, if that can be told
+pub(crate) fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+// HERMETIC_FHE_OPA_URL names an OPA decision endpoint (with the opa feature), and
+// HERMETIC_FHE_POLICY_FILE a file of rules; at most one may be set. Neither leaves every
+// call allowed.
+pub fn policy_from_env() -> Result<Option<Box<dyn PolicyEngine>>> {
+    let url = std::env::var("HERMETIC_FHE_OPA_URL").ok();
+    let file = std::env::var("HERMETIC_FHE_POLICY_FILE").ok();
+    match (url, file) {
+        (Some(_), Some(_)) => Err(anyhow!(
+            "Set HERMETIC_FHE_OPA_URL or HERMETIC_FHE_POLICY_FILE, not both"
+        )),
+        (Some(_), None) if !cfg!(feature = "opa") => {
+            Err(anyhow!("HERMETIC_FHE_OPA_URL requires the opa feature"))
+        }
+        (Some(url), None) => Ok(Some(Box::new(OpaPolicy::new(url)))),
+        (None, Some(file)) => Ok(Some(Box::new(PolicyFile::open(file)?))),
+        (None, None) => Ok(None),
+    }
+}
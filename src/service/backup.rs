@@ -0,0 +1,584 @@
+// Handlers return tonic::Status, which is large by design
+#![allow(clippy::result_large_err)]
+
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use prost::Message;
+use tonic::Status;
+
+use crate::api::v1::key_generation_request::Scheme;
+use crate::api::{
+    backup_ciphertext, backup_record, BackupCiphertext, BackupFooter, BackupHeader, BackupKeyPair,
+    BackupManifest, BackupManifestEntry, BackupReEncryptionKey, BackupRecord, BackupSession,
+    RestoreBackupResponse,
+};
+use crate::crypto::envelope::Signer;
+use crate::crypto::fingerprint::{fingerprint_bytes, verify_fingerprint};
+use crate::crypto::versioning::{self, DataVersion};
+use crate::crypto::{CiphertextKind, KeyBundle, KeyScheme, ReEncryption};
+use crate::service::errors::ErrorReason;
+use crate::service::session::SessionRecord;
+use crate::service::FheServiceImpl;
+
+// Written into every header; restore refuses archives of any other version
+pub const BACKUP_FORMAT_VERSION: u32 = 1;
+// Archive bytes per BackupChunk, well under the default 4 MiB message limit
+pub const BACKUP_CHUNK_BYTES: usize = 1024 * 1024;
+// Response metadata naming the backup CreateBackup is streaming
+pub const BACKUP_ID_HEADER: &str = "x-backup-id";
+// Manifests kept for incremental backups to build on; older ones are forgotten
+pub const MAX_BACKUP_MANIFESTS: usize = 8;
+
+// Largest record a restore will buffer. Server keys are the biggest by far.
+const MAX_RECORD_BYTES: usize = 1 << 30;
+// Fed to the signature ahead of the records, so an archive signature can never pass for
+// a key bundle's
+const SIGNATURE_CONTEXT: &[u8] = b"hermetic-fhe backup v1";
+
+// Every key pair (by server key), re-encryption key, ciphertext and session in a backup,
+// with its fingerprint
+pub type Manifest = HashMap<String, String>;
+
+// Manifests of the most recent backups this process took or restored, the bases an
+// incremental backup or restore can build on. They are not persisted, so the first backup
+// after a restart has to be a full one.
+pub struct BackupLedger {
+    manifests: Mutex<VecDeque<(String, Arc<Manifest>)>>,
+}
+
+impl BackupLedger {
+    pub fn new() -> Self {
+        Self {
+            manifests: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn get(&self, backup_id: &str) -> Option<Arc<Manifest>> {
+        self.manifests
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(id, _)| id == backup_id)
+            .map(|(_, manifest)| manifest.clone())
+    }
+
+    pub fn record(&self, backup_id: &str, manifest: Manifest) {
+        let mut manifests = self.manifests.lock().unwrap();
+        manifests.retain(|(id, _)| id != backup_id);
+        if manifests.len() == MAX_BACKUP_MANIFESTS {
+            manifests.pop_front();
+        }
+        manifests.push_back((backup_id.to_string(), Arc::new(manifest)));
+    }
+}
+
+impl Default for BackupLedger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Write an archive of the service's stores to `send` a chunk at a time, returning its
+// manifest. With a base manifest, only what the base lacks or held differently is written.
+// Ciphertexts are captured in one pass before the keys, so the key pairs any of them
+// were made under are in the archive unless deleted in the meantime.
+pub fn write_backup(
+    service: &FheServiceImpl,
+    backup_id: &str,
+    base: Option<(&str, &Manifest)>,
+    send: impl FnMut(Vec<u8>) -> Result<()>,
+) -> Result<Manifest> {
+    let key_store = service.key_store();
+    let ciphertexts = service.ciphertext_store().snapshot();
+    let sessions = service.sessions().records();
+    let re_encryption_keys = key_store.re_encryption_keys();
+    let key_pairs = key_store.key_pairs();
+
+    let unchanged = |id: &str, fingerprint: &str| {
+        base.is_some_and(|(_, manifest)| manifest.get(id).is_some_and(|held| held == fingerprint))
+    };
+    let mut archive = ArchiveWriter::new(key_store.signer(), send);
+    let mut manifest = Manifest::new();
+
+    archive.write(backup_record::Record::Header(BackupHeader {
+        format_version: BACKUP_FORMAT_VERSION,
+        backup_id: backup_id.to_string(),
+        base_backup_id: base.map(|(id, _)| id.to_string()).unwrap_or_default(),
+        created_unix_seconds: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default(),
+        tfhe_version: versioning::TFHE_VERSION.to_string(),
+        tfhe_parameters: versioning::TFHE_PARAMETERS.to_string(),
+    }))?;
+
+    for (_, server_key_id) in key_pairs {
+        let Some(fingerprint) = key_store.get_fingerprint(&server_key_id) else {
+            continue; // Deleted since it was listed
+        };
+        if !unchanged(&server_key_id, &fingerprint) {
+            let (scheme, bundle) = key_store.export_key_pair(&server_key_id)?;
+            let bundle =
+                bincode::serialize(&bundle).map_err(|e| anyhow!("Failed to encode key bundle: {}", e))?;
+            archive.write(backup_record::Record::KeyPair(BackupKeyPair {
+                scheme: scheme_to_proto(scheme) as i32,
+                bundle,
+            }))?;
+        }
+        manifest.insert(server_key_id, fingerprint);
+    }
+
+    for (id, re_encryption) in re_encryption_keys {
+        // Re-encryption keys never change under an ID, so the ID alone says whether the
+        // base has one
+        if !unchanged(&id, "") {
+            let key = bincode::serialize(&re_encryption.key)
+                .map_err(|e| anyhow!("Failed to encode re-encryption key: {}", e))?;
+            archive.write(backup_record::Record::ReEncryptionKey(BackupReEncryptionKey {
+                re_encryption_key_id: id.clone(),
+                scheme: scheme_to_proto(re_encryption.scheme) as i32,
+                source_client_key_id: re_encryption.source_client_key_id.clone(),
+                target_client_key_id: re_encryption.target_client_key_id.clone(),
+                key,
+            }))?;
+        }
+        manifest.insert(id, String::new());
+    }
+
+    for ciphertext in ciphertexts {
+        if !unchanged(&ciphertext.id, &ciphertext.fingerprint) {
+            // A subject's values are sealed under its key, so shredding the subject later
+            // reaches this archive too
+            let serialized_data = match &ciphertext.subject {
+                Some(subject_id) => key_store.seal_for_subject(subject_id, &ciphertext.serialize()?)?,
+                None => ciphertext.serialize()?,
+            };
+            archive.write(backup_record::Record::Ciphertext(BackupCiphertext {
+                encrypted_data_id: ciphertext.id.clone(),
+                kind: kind_to_proto(ciphertext.kind) as i32,
+                serialized_data,
+                fingerprint: ciphertext.fingerprint.clone(),
+                subject_id: ciphertext.subject.clone().unwrap_or_default(),
+            }))?;
+        }
+        manifest.insert(ciphertext.id, ciphertext.fingerprint);
+    }
+
+    // Sessions are small and their contents change in place, so every backup has them all
+    for session in sessions {
+        manifest.insert(session.session_id.clone(), String::new());
+        archive.write(backup_record::Record::Session(BackupSession {
+            session_id: session.session_id,
+            idle_timeout_seconds: session.idle_timeout.as_secs(),
+            ciphertext_ids: session.ciphertext_ids,
+        }))?;
+    }
+
+    let mut entries: Vec<BackupManifestEntry> = manifest
+        .iter()
+        .map(|(id, fingerprint)| BackupManifestEntry {
+            id: id.clone(),
+            fingerprint: fingerprint.clone(),
+        })
+        .collect();
+    entries.sort_by(|a, b| a.id.cmp(&b.id));
+    archive.write(backup_record::Record::Manifest(BackupManifest { entries }))?;
+    archive.finish()?;
+
+    Ok(manifest)
+}
+
+// Splits length-delimited records into chunks, signing every byte on the way
+struct ArchiveWriter<F> {
+    buffer: Vec<u8>,
+    signer: Signer,
+    send: F,
+}
+
+impl<F: FnMut(Vec<u8>) -> Result<()>> ArchiveWriter<F> {
+    fn new(mut signer: Signer, send: F) -> Self {
+        signer.update(SIGNATURE_CONTEXT);
+        Self {
+            buffer: Vec::new(),
+            signer,
+            send,
+        }
+    }
+
+    fn write(&mut self, record: backup_record::Record) -> Result<()> {
+        let bytes = BackupRecord { record: Some(record) }.encode_length_delimited_to_vec();
+        self.signer.update(&bytes);
+        self.buffer.extend_from_slice(&bytes);
+        send_chunks(&mut self.buffer, &mut self.send, false)
+    }
+
+    // The footer signs everything before it
+    fn finish(self) -> Result<()> {
+        let Self {
+            mut buffer,
+            signer,
+            mut send,
+        } = self;
+        let footer = BackupRecord {
+            record: Some(backup_record::Record::Footer(BackupFooter {
+                signature: signer.finish(),
+            })),
+        };
+        buffer.extend_from_slice(&footer.encode_length_delimited_to_vec());
+        send_chunks(&mut buffer, &mut send, true)
+    }
+}
+
+// Send every full chunk buffered, and the partial one at the end too when finishing
+fn send_chunks(
+    buffer: &mut Vec<u8>,
+    send: &mut impl FnMut(Vec<u8>) -> Result<()>,
+    finishing: bool,
+) -> Result<()> {
+    let ready = match finishing {
+        true => buffer.len(),
+        false => buffer.len() - buffer.len() % BACKUP_CHUNK_BYTES,
+    };
+    for chunk in buffer[..ready].chunks(BACKUP_CHUNK_BYTES) {
+        send(chunk.to_vec())?;
+    }
+    buffer.drain(..ready);
+    Ok(())
+}
+
+// Everything in an archive, checked against its signature and ready to apply
+pub struct Archive {
+    header: BackupHeader,
+    key_pairs: Vec<BackupKeyPair>,
+    re_encryption_keys: Vec<BackupReEncryptionKey>,
+    ciphertexts: Vec<BackupCiphertext>,
+    sessions: Vec<BackupSession>,
+    manifest: Manifest,
+}
+
+impl Archive {
+    pub fn backup_id(&self) -> &str {
+        &self.header.backup_id
+    }
+
+    // Empty for a full backup
+    pub fn base_backup_id(&self) -> &str {
+        &self.header.base_backup_id
+    }
+}
+
+// Reassembles records from chunks as they arrive, checking the signature as it goes
+pub struct ArchiveReader {
+    buffer: Vec<u8>,
+    signer: Option<Signer>,
+    header: Option<BackupHeader>,
+    key_pairs: Vec<BackupKeyPair>,
+    re_encryption_keys: Vec<BackupReEncryptionKey>,
+    ciphertexts: Vec<BackupCiphertext>,
+    sessions: Vec<BackupSession>,
+    manifest: Option<Manifest>,
+    verified: bool,
+}
+
+impl ArchiveReader {
+    // Checks signatures with the signer of the key store being restored into, so an
+    // archive from a server with another master key is refused
+    pub fn new(mut signer: Signer) -> Self {
+        signer.update(SIGNATURE_CONTEXT);
+        Self {
+            buffer: Vec::new(),
+            signer: Some(signer),
+            header: None,
+            key_pairs: Vec::new(),
+            re_encryption_keys: Vec::new(),
+            ciphertexts: Vec::new(),
+            sessions: Vec::new(),
+            manifest: None,
+            verified: false,
+        }
+    }
+
+    pub fn push(&mut self, data: &[u8]) -> Result<(), Status> {
+        self.buffer.extend_from_slice(data);
+        while let Some(length) = self.next_record()? {
+            let record = BackupRecord::decode_length_delimited(&self.buffer[..length])
+                .map_err(|e| malformed(format!("undecodable record: {}", e)))?
+                .record
+                .ok_or_else(|| malformed("empty record"))?;
+            if !matches!(record, backup_record::Record::Footer(_)) {
+                if let Some(signer) = &mut self.signer {
+                    signer.update(&self.buffer[..length]);
+                }
+            }
+            self.buffer.drain(..length);
+            self.accept(record)?;
+        }
+        Ok(())
+    }
+
+    // The whole archive, once the footer has arrived with nothing after it
+    pub fn finish(self) -> Result<Archive, Status> {
+        if !self.verified {
+            return Err(malformed("it ends before its footer"));
+        }
+        if !self.buffer.is_empty() {
+            return Err(malformed("bytes follow its footer"));
+        }
+        Ok(Archive {
+            header: self.header.ok_or_else(|| malformed("no header"))?,
+            key_pairs: self.key_pairs,
+            re_encryption_keys: self.re_encryption_keys,
+            ciphertexts: self.ciphertexts,
+            sessions: self.sessions,
+            manifest: self.manifest.ok_or_else(|| malformed("no manifest"))?,
+        })
+    }
+
+    // Length of the buffered record, prefix included, once all of it has arrived
+    fn next_record(&self) -> Result<Option<usize>, Status> {
+        if self.buffer.is_empty() {
+            return Ok(None);
+        }
+        let mut rest = &self.buffer[..];
+        let length = match prost::encoding::decode_varint(&mut rest) {
+            Ok(length) => length as usize,
+            // A length prefix is at most ten bytes; a shorter one may still be arriving
+            Err(_) if self.buffer.len() < 10 => return Ok(None),
+            Err(_) => return Err(malformed("bad record length")),
+        };
+        if length > MAX_RECORD_BYTES {
+            return Err(malformed(format!("a record of {} bytes is too large", length)));
+        }
+        let total = self.buffer.len() - rest.len() + length;
+        Ok((self.buffer.len() >= total).then_some(total))
+    }
+
+    fn accept(&mut self, record: backup_record::Record) -> Result<(), Status> {
+        if self.verified {
+            return Err(malformed("records follow its footer"));
+        }
+        if self.header.is_none() && !matches!(record, backup_record::Record::Header(_)) {
+            return Err(malformed("it does not start with a header"));
+        }
+        match record {
+            backup_record::Record::Header(header) => {
+                if self.header.is_some() {
+                    return Err(malformed("it has two headers"));
+                }
+                if header.format_version != BACKUP_FORMAT_VERSION {
+                    return Err(ErrorReason::Unsupported.status(format!(
+                        "Backup format version {} is not supported",
+                        header.format_version
+                    )));
+                }
+                data_version(&header)
+                    .check()
+                    .map_err(|e| ErrorReason::Unsupported.status(e.to_string()))?;
+                self.header = Some(header);
+            }
+            backup_record::Record::KeyPair(key_pair) => self.key_pairs.push(key_pair),
+            backup_record::Record::ReEncryptionKey(key) => self.re_encryption_keys.push(key),
+            backup_record::Record::Ciphertext(ciphertext) => self.ciphertexts.push(ciphertext),
+            backup_record::Record::Session(session) => self.sessions.push(session),
+            backup_record::Record::Manifest(manifest) => {
+                if self.manifest.is_some() {
+                    return Err(malformed("it has two manifests"));
+                }
+                let entries = manifest
+                    .entries
+                    .into_iter()
+                    .map(|entry| (entry.id, entry.fingerprint));
+                self.manifest = Some(entries.collect());
+            }
+            backup_record::Record::Footer(footer) => {
+                let signer = self
+                    .signer
+                    .take()
+                    .ok_or_else(|| malformed("it has two footers"))?;
+                // Altered in transit or at rest, or written under another master key
+                signer.verify(&footer.signature).map_err(|_| {
+                    ErrorReason::FingerprintMismatch.status("Backup signature does not verify")
+                })?;
+                self.verified = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn malformed(detail: impl std::fmt::Display) -> Status {
+    ErrorReason::InvalidRequest.status(format!("Malformed backup archive: {}", detail))
+}
+
+// Apply a verified archive to the service's stores, overwriting entries with the same IDs
+// and leaving others alone. For an incremental archive, entries of its base manifest
+// that it no longer lists are deleted. Returns what was restored, and the manifest to
+// record for the next incremental restore.
+pub fn restore_backup(
+    service: &FheServiceImpl,
+    archive: Archive,
+    base: Option<&Manifest>,
+) -> Result<(RestoreBackupResponse, Manifest), Status> {
+    let key_store = service.key_store();
+    let restore_failed = |what: &str, e: anyhow::Error| {
+        ErrorReason::InvalidRequest.status(format!("Failed to restore {}: {}", what, e))
+    };
+
+    // Keys and ciphertexts from an older tfhe-rs release are migrated as they're restored
+    let version = data_version(&archive.header);
+    for key_pair in &archive.key_pairs {
+        let bundle = versioning::migrate(&version, &key_pair.bundle)
+            .and_then(|bundle| KeyBundle::decode(&bundle))
+            .map_err(|e| restore_failed("key pair", e))?;
+        let scheme = scheme_from_proto(key_pair.scheme());
+        key_store
+            .restore_key_pair(scheme, bundle)
+            .map_err(|e| restore_failed("key pair", e))?;
+    }
+
+    for key in &archive.re_encryption_keys {
+        let re_encryption = ReEncryption {
+            scheme: scheme_from_proto(key.scheme()),
+            source_client_key_id: key.source_client_key_id.clone(),
+            target_client_key_id: key.target_client_key_id.clone(),
+            key: bincode::deserialize(&key.key)
+                .map_err(|e| restore_failed("re-encryption key", anyhow!("invalid key: {}", e)))?,
+        };
+        key_store.restore_re_encryption_key(&key.re_encryption_key_id, re_encryption);
+    }
+
+    let ciphertext_store = service.ciphertext_store();
+    let mut shredded = 0;
+    for ciphertext in &archive.ciphertexts {
+        let (serialized_data, subject) = match ciphertext.subject_id.as_str() {
+            "" => (Cow::Borrowed(&ciphertext.serialized_data[..]), None),
+            subject_id => match key_store.open_for_subject(subject_id, &ciphertext.serialized_data) {
+                Ok(opened) => (Cow::Owned(opened), Some(subject_id)),
+                // The subject was shredded after the backup was taken
+                Err(_) => {
+                    shredded += 1;
+                    continue;
+                }
+            },
+        };
+        let (serialized_data, fingerprint) =
+            migrate_ciphertext(&version, &serialized_data, &ciphertext.fingerprint)
+                .map_err(|e| restore_failed("ciphertext", e))?;
+        ciphertext_store
+            .restore(
+                &ciphertext.encrypted_data_id,
+                kind_from_proto(ciphertext.kind()),
+                &serialized_data,
+                &fingerprint,
+                subject,
+            )
+            .map_err(|e| restore_failed("ciphertext", e))?;
+    }
+
+    for session in &archive.sessions {
+        service.sessions().restore(SessionRecord {
+            session_id: session.session_id.clone(),
+            idle_timeout: Duration::from_secs(session.idle_timeout_seconds),
+            ciphertext_ids: session.ciphertext_ids.clone(),
+        });
+    }
+
+    // Whatever kind of entry a dropped ID was, only one of these finds it
+    let mut deleted = 0;
+    for id in base.into_iter().flat_map(|base| base.keys()) {
+        if archive.manifest.contains_key(id) {
+            continue;
+        }
+        let found = key_store
+            .delete_key_pair(id)
+            .map_err(|e| restore_failed("deletions", e))?
+            .is_some()
+            || key_store.delete_re_encryption_key(id)
+            || ciphertext_store.remove(id)
+            || service.sessions().close(id).is_some();
+        if found {
+            deleted += 1;
+        }
+    }
+
+    let response = RestoreBackupResponse {
+        backup_id: archive.header.backup_id,
+        key_pairs: archive.key_pairs.len() as u32,
+        re_encryption_keys: archive.re_encryption_keys.len() as u32,
+        ciphertexts: archive.ciphertexts.len() as u32 - shredded,
+        sessions: archive.sessions.len() as u32,
+        deleted,
+        shredded,
+    };
+    Ok((response, archive.manifest))
+}
+
+fn scheme_to_proto(scheme: KeyScheme) -> Scheme {
+    match scheme {
+        KeyScheme::Tfhe => Scheme::Tfhe,
+        KeyScheme::Ckks => Scheme::Ckks,
+        KeyScheme::Bgv => Scheme::Bgv,
+    }
+}
+
+fn scheme_from_proto(scheme: Scheme) -> KeyScheme {
+    match scheme {
+        Scheme::Tfhe => KeyScheme::Tfhe,
+        Scheme::Ckks => KeyScheme::Ckks,
+        Scheme::Bgv => KeyScheme::Bgv,
+    }
+}
+
+pub(crate) fn kind_to_proto(kind: CiphertextKind) -> backup_ciphertext::Kind {
+    match kind {
+        CiphertextKind::Boolean => backup_ciphertext::Kind::Boolean,
+        CiphertextKind::Integer => backup_ciphertext::Kind::Integer,
+        CiphertextKind::Matrix => backup_ciphertext::Kind::Matrix,
+        CiphertextKind::RealVector => backup_ciphertext::Kind::RealVector,
+        CiphertextKind::IntegerBatch => backup_ciphertext::Kind::IntegerBatch,
+        CiphertextKind::Timestamp => backup_ciphertext::Kind::Timestamp,
+    }
+}
+
+// Archives from before headers recorded it were written by tfhe-rs 0.5
+fn data_version(header: &BackupHeader) -> DataVersion {
+    if header.tfhe_version.is_empty() {
+        return DataVersion::legacy();
+    }
+    DataVersion {
+        tfhe: header.tfhe_version.clone(),
+        parameters: header.tfhe_parameters.clone(),
+    }
+}
+
+// A ciphertext as this build reads it, with its fingerprint. One from an older release is
+// checked against the fingerprint it was backed up with before it is migrated.
+fn migrate_ciphertext<'a>(
+    version: &DataVersion,
+    bytes: &'a [u8],
+    fingerprint: &'a str,
+) -> Result<(Cow<'a, [u8]>, Cow<'a, str>)> {
+    match versioning::migrate(version, bytes)? {
+        Cow::Borrowed(bytes) => Ok((Cow::Borrowed(bytes), Cow::Borrowed(fingerprint))),
+        Cow::Owned(migrated) => {
+            verify_fingerprint(bytes, fingerprint)?;
+            let fingerprint = fingerprint_bytes(&migrated);
+            Ok((Cow::Owned(migrated), Cow::Owned(fingerprint)))
+        }
+    }
+}
+
+fn kind_from_proto(kind: backup_ciphertext::Kind) -> CiphertextKind {
+    match kind {
+        backup_ciphertext::Kind::Boolean => CiphertextKind::Boolean,
+        backup_ciphertext::Kind::Integer => CiphertextKind::Integer,
+        backup_ciphertext::Kind::Matrix => CiphertextKind::Matrix,
+        backup_ciphertext::Kind::RealVector => CiphertextKind::RealVector,
+        backup_ciphertext::Kind::IntegerBatch => CiphertextKind::IntegerBatch,
+        backup_ciphertext::Kind::Timestamp => CiphertextKind::Timestamp,
+    }
+}
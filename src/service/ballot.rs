@@ -0,0 +1,164 @@
+use std::sync::{Arc, Mutex};
+
+use thiserror::Error;
+use tfhe::FheUint8;
+use uuid::Uuid;
+
+use crate::crypto::operations;
+use crate::crypto::provenance::Provenance;
+use crate::crypto::sharded::ShardedMap;
+use crate::crypto::tally::MAX_BALLOTS;
+
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum ElectionError {
+    #[error("Election is closed")]
+    Closed,
+    #[error("Election is still open")]
+    Open,
+    #[error("Election has taken its limit of {} ballots", MAX_BALLOTS)]
+    Full,
+}
+
+struct ElectionState {
+    tallies: Vec<Arc<FheUint8>>,
+    ballots: u64,
+    closed: bool,
+    // Every ballot ciphertext counted so far, since each feeds every tally
+    provenance: Provenance,
+}
+
+impl ElectionState {
+    fn status(&self) -> ElectionStatus {
+        ElectionStatus {
+            options: self.tallies.len(),
+            ballots: self.ballots,
+            closed: self.closed,
+        }
+    }
+}
+
+// Public facts about an election; the tallies themselves stay encrypted
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ElectionStatus {
+    pub options: usize,
+    pub ballots: u64,
+    pub closed: bool,
+}
+
+// Encrypted per-option tallies under one server key. Ballots are accepted until the
+// election is closed, and the tallies are only released after that.
+pub struct Election {
+    server_key_id: String,
+    state: Mutex<ElectionState>,
+}
+
+impl Election {
+    pub fn server_key_id(&self) -> &str {
+        &self.server_key_id
+    }
+
+    pub fn status(&self) -> ElectionStatus {
+        self.state.lock().unwrap().status()
+    }
+
+    // Fail early, before a ballot is checked, if it could not be counted anyway
+    pub fn check_open(&self) -> Result<(), ElectionError> {
+        let state = self.state.lock().unwrap();
+        if state.closed {
+            return Err(ElectionError::Closed);
+        }
+        if state.ballots >= MAX_BALLOTS {
+            return Err(ElectionError::Full);
+        }
+        Ok(())
+    }
+
+    // Add a ballot's contribution to the tallies. The lock is held for the additions,
+    // so concurrent ballots are counted one after another.
+    pub fn cast(
+        &self,
+        contribution: &[FheUint8],
+        ballot: &Provenance,
+    ) -> Result<ElectionStatus, ElectionError> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Err(ElectionError::Closed);
+        }
+        if state.ballots >= MAX_BALLOTS {
+            return Err(ElectionError::Full);
+        }
+        for (tally, added) in state.tallies.iter_mut().zip(contribution) {
+            *tally = Arc::new(operations::integer_add(&**tally, added));
+        }
+        state.ballots += 1;
+        state.provenance = Provenance::merge([&state.provenance, ballot]);
+        Ok(state.status())
+    }
+
+    // Stop accepting ballots. Closing twice is harmless.
+    pub fn close(&self) -> ElectionStatus {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        state.status()
+    }
+
+    // The encrypted totals, in option order, once the election is closed
+    pub fn tallies(&self) -> Result<(Vec<Arc<FheUint8>>, u64), ElectionError> {
+        let state = self.state.lock().unwrap();
+        if !state.closed {
+            return Err(ElectionError::Open);
+        }
+        Ok((state.tallies.clone(), state.ballots))
+    }
+
+    // The ballot ciphertexts the tallies were computed from
+    pub fn provenance(&self) -> Provenance {
+        self.state.lock().unwrap().provenance.clone()
+    }
+}
+
+pub struct ElectionStore {
+    elections: ShardedMap<Arc<Election>>,
+}
+
+impl ElectionStore {
+    pub fn new() -> Self {
+        Self {
+            elections: ShardedMap::new(),
+        }
+    }
+
+    // Start an election whose tallies begin at the given encrypted zeros, one per option
+    pub fn create(&self, server_key_id: &str, tallies: Vec<FheUint8>) -> String {
+        let id = Uuid::new_v4().to_string();
+        let election = Election {
+            server_key_id: server_key_id.to_string(),
+            state: Mutex::new(ElectionState {
+                tallies: tallies.into_iter().map(Arc::new).collect(),
+                ballots: 0,
+                closed: false,
+                provenance: Provenance::default(),
+            }),
+        };
+        self.elections.insert(id.clone(), Arc::new(election));
+        id
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<Election>> {
+        self.elections.get(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.elections.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for ElectionStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,68 @@
+use std::sync::Arc;
+
+use uuid::Uuid;
+
+use crate::crypto::bloom::EncryptedBloomFilter;
+use crate::crypto::provenance::Provenance;
+use crate::crypto::sharded::ShardedMap;
+
+// An encrypted Bloom filter under the server key it was created with. Filters never
+// change once made; merging makes a new one.
+pub struct BloomFilter {
+    pub server_key_id: String,
+    pub filter: EncryptedBloomFilter,
+    // Stored ciphertexts the bits were computed from
+    pub provenance: Provenance,
+}
+
+// Bloom filters by ID, so a set encrypted once can answer any number of queries
+pub struct BloomFilterStore {
+    filters: ShardedMap<Arc<BloomFilter>>,
+}
+
+impl BloomFilterStore {
+    pub fn new() -> Self {
+        Self {
+            filters: ShardedMap::new(),
+        }
+    }
+
+    pub fn create(
+        &self,
+        server_key_id: &str,
+        filter: EncryptedBloomFilter,
+        provenance: Provenance,
+    ) -> String {
+        let id = Uuid::new_v4().to_string();
+        let filter = BloomFilter {
+            server_key_id: server_key_id.to_string(),
+            filter,
+            provenance,
+        };
+        self.filters.insert(id.clone(), Arc::new(filter));
+        id
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<BloomFilter>> {
+        self.filters.get(id)
+    }
+
+    // Queries already holding the filter finish, but it can no longer be found
+    pub fn remove(&self, id: &str) -> Option<Arc<BloomFilter>> {
+        self.filters.remove(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.filters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for BloomFilterStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
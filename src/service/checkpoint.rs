@@ -0,0 +1,117 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use sha2::{Digest, Sha256};
+
+use crate::api::CheckpointOptions;
+use crate::circuit::checkpoint::Checkpoint;
+use crate::crypto::fingerprint::to_hex;
+
+// How often a job saves when it doesn't choose
+pub const DEFAULT_CHECKPOINT_INTERVAL: Duration = Duration::from_secs(300);
+
+const MAX_CHECKPOINT_NAME_LENGTH: usize = 128;
+
+// Where long jobs save their progress, chosen by the operator. Without a directory, jobs
+// asking for checkpoints are refused.
+#[derive(Clone, Debug)]
+pub struct CheckpointPolicy {
+    dir: Option<PathBuf>,
+    default_interval: Duration,
+}
+
+impl Default for CheckpointPolicy {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            default_interval: DEFAULT_CHECKPOINT_INTERVAL,
+        }
+    }
+}
+
+impl CheckpointPolicy {
+    // HERMETIC_FHE_CHECKPOINT_DIR is the directory checkpoints are kept in, which should
+    // outlive the process, and HERMETIC_FHE_CHECKPOINT_INTERVAL_SECONDS how often jobs that
+    // don't choose save, 300 by default
+    pub fn from_env() -> Result<Self> {
+        let mut policy = Self::default();
+        if let Ok(value) = std::env::var("HERMETIC_FHE_CHECKPOINT_INTERVAL_SECONDS") {
+            let seconds = value
+                .parse::<u64>()
+                .ok()
+                .filter(|seconds| *seconds > 0)
+                .ok_or_else(|| {
+                    anyhow!("HERMETIC_FHE_CHECKPOINT_INTERVAL_SECONDS must be a positive integer")
+                })?;
+            policy.default_interval = Duration::from_secs(seconds);
+        }
+        match std::env::var("HERMETIC_FHE_CHECKPOINT_DIR") {
+            Ok(dir) => policy.with_dir(dir),
+            Err(_) => Ok(policy),
+        }
+    }
+
+    // Keep checkpoints in dir, creating it if needed
+    pub fn with_dir(mut self, dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)
+            .map_err(|e| anyhow!("Failed to create checkpoint directory {}: {}", dir.display(), e))?;
+        self.dir = Some(dir);
+        Ok(self)
+    }
+
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    pub fn default_interval(&self) -> Duration {
+        self.default_interval
+    }
+
+    // The checkpoint a job's options name, for a job with the given digest
+    pub fn open(&self, options: &CheckpointOptions, job: String) -> Result<Checkpoint, String> {
+        let dir = self
+            .dir
+            .as_ref()
+            .ok_or("Checkpoints are not enabled on this server")?;
+        let name = &options.name;
+        let valid = !name.is_empty()
+            && name.len() <= MAX_CHECKPOINT_NAME_LENGTH
+            && !name.starts_with('.')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid {
+            return Err(format!(
+                "Checkpoint name '{}' must be 1 to {} letters, digits, '-', '_' or '.', and not start \
+                 with '.'",
+                name, MAX_CHECKPOINT_NAME_LENGTH
+            ));
+        }
+        let interval = match options.interval_seconds {
+            0 => self.default_interval,
+            seconds => Duration::from_secs(seconds.into()),
+        };
+        Ok(Checkpoint::new(
+            dir.join(format!("{}.checkpoint", name)),
+            job,
+            interval,
+        ))
+    }
+}
+
+// Digest identifying a job by its request, less the fields that don't change its result,
+// and the fingerprints of the ciphertexts it reads. A rerun of the same request over the
+// same data has the same digest, even after the data has been restored into a new process.
+pub fn job_digest(request: &[u8], fingerprints: impl IntoIterator<Item = String>) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update((request.len() as u64).to_be_bytes());
+    hasher.update(request);
+    for fingerprint in fingerprints {
+        hasher.update((fingerprint.len() as u64).to_be_bytes());
+        hasher.update(fingerprint.as_bytes());
+    }
+    to_hex(&hasher.finalize())
+}
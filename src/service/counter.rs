@@ -0,0 +1,114 @@
+use std::sync::{Arc, Mutex};
+
+use tfhe::FheUint8;
+use uuid::Uuid;
+
+use crate::crypto::provenance::Provenance;
+use crate::crypto::sharded::ShardedMap;
+
+struct CounterState {
+    value: Arc<FheUint8>,
+    increments: u64,
+    provenance: Provenance,
+}
+
+impl CounterState {
+    fn value(&self) -> CounterValue {
+        CounterValue {
+            value: self.value.clone(),
+            increments: self.increments,
+            provenance: self.provenance.clone(),
+        }
+    }
+}
+
+// What a counter held at one moment
+#[derive(Clone)]
+pub struct CounterValue {
+    pub value: Arc<FheUint8>,
+    pub increments: u64,
+    // Stored ciphertexts the value was computed from
+    pub provenance: Provenance,
+}
+
+// Encrypted running total, updated under the server key it was created with. An
+// increment holds the counter's lock for the whole homomorphic addition, so concurrent
+// increments are applied one after another and none is lost.
+pub struct Counter {
+    server_key_id: String,
+    state: Mutex<CounterState>,
+}
+
+impl Counter {
+    pub fn new(server_key_id: &str, initial: Arc<FheUint8>, provenance: Provenance) -> Self {
+        Self {
+            server_key_id: server_key_id.to_string(),
+            state: Mutex::new(CounterState {
+                value: initial,
+                increments: 0,
+                provenance,
+            }),
+        }
+    }
+
+    pub fn server_key_id(&self) -> &str {
+        &self.server_key_id
+    }
+
+    pub fn read(&self) -> CounterValue {
+        self.state.lock().unwrap().value()
+    }
+
+    // Replace the value with `add` applied to it, as one step. `added` is the provenance
+    // of what was added, which is empty for a plaintext amount.
+    pub fn increment(&self, add: impl FnOnce(&FheUint8) -> FheUint8, added: &Provenance) -> CounterValue {
+        let mut state = self.state.lock().unwrap();
+        state.value = Arc::new(add(&state.value));
+        state.increments += 1;
+        state.provenance = Provenance::merge([&state.provenance, added]);
+        state.value()
+    }
+}
+
+// Counters by ID, so clients can aggregate without their own read-add-store round trips
+pub struct CounterStore {
+    counters: ShardedMap<Arc<Counter>>,
+}
+
+impl CounterStore {
+    pub fn new() -> Self {
+        Self {
+            counters: ShardedMap::new(),
+        }
+    }
+
+    pub fn create(&self, server_key_id: &str, initial: Arc<FheUint8>, provenance: Provenance) -> String {
+        let id = Uuid::new_v4().to_string();
+        let counter = Counter::new(server_key_id, initial, provenance);
+        self.counters.insert(id.clone(), Arc::new(counter));
+        id
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<Counter>> {
+        self.counters.get(id)
+    }
+
+    // Increments already holding the counter finish, but it can no longer be found
+    pub fn remove(&self, id: &str) -> Option<Arc<Counter>> {
+        self.counters.remove(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.counters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for CounterStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, RwLock};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::circuit::checkpoint::SavedValue;
+use crate::crypto::sharded::ShardedMap;
+
+// Labels naming stored ciphertexts, such as the rows of an ingested dataset. Kept in
+// order, so the records under a prefix like "salaries/" sit next to each other.
+pub struct LabelIndex {
+    labels: RwLock<BTreeMap<String, String>>,
+}
+
+impl LabelIndex {
+    pub fn new() -> Self {
+        Self {
+            labels: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    // Point a label at a ciphertext, replacing whatever it named before
+    pub fn set(&self, label: &str, ciphertext_id: &str) {
+        self.labels
+            .write()
+            .unwrap()
+            .insert(label.to_string(), ciphertext_id.to_string());
+    }
+
+    pub fn remove(&self, label: &str) -> bool {
+        self.labels.write().unwrap().remove(label).is_some()
+    }
+
+    // Labels starting with the prefix and the ciphertexts they name, in label order
+    pub fn with_prefix(&self, prefix: &str) -> Vec<(String, String)> {
+        self.labels
+            .read()
+            .unwrap()
+            .range(prefix.to_string()..)
+            .take_while(|(label, _)| label.starts_with(prefix))
+            .map(|(label, id)| (label.clone(), id.clone()))
+            .collect()
+    }
+}
+
+impl Default for LabelIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Where a record's result went
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MapOutput {
+    // Stored under this ciphertext ID and labeled
+    Stored(String),
+    // Written to the job's sink, at this file path or object URL
+    Written(String),
+}
+
+// Outcome for one labeled record: where its result went, or why there is none
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MappedRecord {
+    pub label: String,
+    pub result_label: String,
+    pub result: Result<MapOutput, String>,
+}
+
+// A record's result as a map job's checkpoint holds it. Only records that succeeded are
+// saved, so a rerun tries the failed ones again.
+#[derive(Serialize, Deserialize)]
+pub struct SavedRecord {
+    pub label: String,
+    pub result_label: String,
+    pub output: SavedOutput,
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum SavedOutput {
+    // The result itself, since the store it was kept in may be gone
+    Stored(SavedValue),
+    // Already in the sink, at this file path or object URL
+    Written(String),
+}
+
+// What a map job has done so far
+#[derive(Clone, Debug)]
+pub struct MapProgress {
+    pub total: usize,
+    // In the order they finished, one per record processed
+    pub records: Vec<MappedRecord>,
+    // Records an earlier run finished, taken from its checkpoint
+    pub resumed: usize,
+    pub done: bool,
+}
+
+impl MapProgress {
+    pub fn failed(&self) -> usize {
+        self.records.iter().filter(|record| record.result.is_err()).count()
+    }
+}
+
+// One operation applied across a labeled record set
+pub struct MapJob {
+    progress: Mutex<MapProgress>,
+}
+
+impl MapJob {
+    pub fn progress(&self) -> MapProgress {
+        self.progress.lock().unwrap().clone()
+    }
+
+    pub(crate) fn record(&self, record: MappedRecord) {
+        self.progress.lock().unwrap().records.push(record);
+    }
+
+    pub(crate) fn resume(&self, record: MappedRecord) {
+        let mut progress = self.progress.lock().unwrap();
+        progress.records.push(record);
+        progress.resumed += 1;
+    }
+
+    pub(crate) fn finish(&self) {
+        self.progress.lock().unwrap().done = true;
+    }
+}
+
+// Map jobs by ID, kept after they finish so their results can still be read
+pub struct MapJobStore {
+    jobs: ShardedMap<Arc<MapJob>>,
+}
+
+impl MapJobStore {
+    pub fn new() -> Self {
+        Self {
+            jobs: ShardedMap::new(),
+        }
+    }
+
+    pub fn create(&self, total: usize) -> (String, Arc<MapJob>) {
+        let id = Uuid::new_v4().to_string();
+        let job = self.create_as(&id, total);
+        (id, job)
+    }
+
+    // A job under an ID chosen ahead, as a journaled job's is, replacing any job already
+    // there
+    pub fn create_as(&self, id: &str, total: usize) -> Arc<MapJob> {
+        let job = Arc::new(MapJob {
+            progress: Mutex::new(MapProgress {
+                total,
+                records: Vec::with_capacity(total),
+                resumed: 0,
+                done: false,
+            }),
+        });
+        self.jobs.insert(id.to_string(), job.clone());
+        job
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<MapJob>> {
+        self.jobs.get(id)
+    }
+}
+
+impl Default for MapJobStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
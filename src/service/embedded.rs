@@ -0,0 +1,29 @@
+use std::sync::Arc;
+
+use crate::api::fhe_service_client::FheServiceClient;
+use crate::api::FheServiceServer;
+use crate::crypto::{CiphertextStore, KeyStore};
+use crate::service::FheServiceImpl;
+
+// The generated client with the service itself in place of a channel. Calls are encoded,
+// size-checked and decoded exactly as over the network, and fail with the same statuses and
+// error reasons, but never leave the process.
+pub type EmbeddedClient = FheServiceClient<FheServiceServer<FheServiceImpl>>;
+
+// A client for a service over these stores, with every other setting at its default
+pub fn new(key_store: Arc<KeyStore>, ciphertext_store: Arc<CiphertextStore>) -> EmbeddedClient {
+    client(FheServiceImpl::new(key_store, ciphertext_store))
+}
+
+// A client for a service already configured with its builders. The service is shared, not
+// copied, so other clients and servers made from clones of it see the same keys, ciphertexts,
+// sessions and jobs.
+pub fn client(service: FheServiceImpl) -> EmbeddedClient {
+    let max_message_bytes = service.max_message_bytes();
+    let server = FheServiceServer::new(service)
+        .max_decoding_message_size(max_message_bytes)
+        .max_encoding_message_size(max_message_bytes);
+    FheServiceClient::new(server)
+        .max_decoding_message_size(max_message_bytes)
+        .max_encoding_message_size(max_message_bytes)
+}
@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+
+use tonic::{Code, Status};
+use tonic_types::{ErrorDetails, StatusExt};
+
+// Domain of every ErrorInfo the service attaches, so clients can tell these reasons
+// apart from ones added by proxies or other services
+pub const ERROR_DOMAIN: &str = "hermetic-fhe.v1";
+
+// Why a request failed, in a form clients can branch on. Every error status carries
+// its reason as a google.rpc.ErrorInfo detail; the message is for humans only.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorReason {
+    KeyNotFound,
+    CiphertextNotFound,
+    SessionNotFound,
+    CounterNotFound,
+    BloomFilterNotFound,
+    AggregationNotFound,
+    ElectionNotFound,
+    MigrationNotFound,
+    BackupNotFound,
+    JobNotFound,
+    TypeMismatch,
+    WidthMismatch,
+    ArityMismatch,
+    ShapeMismatch,
+    InvalidCircuit,
+    InvalidRequest,
+    ValueOutOfRange,
+    OffsetOutOfRange,
+    LimitExceeded,
+    Overloaded,
+    Unsupported,
+    FingerprintMismatch,
+    PolicyViolation,
+    PermissionDenied,
+    PolicyUnavailable,
+    PrivacyBudgetExhausted,
+    ElectionClosed,
+    ElectionOpen,
+    AliasTaken,
+    DedupConflict,
+    Cancelled,
+    DeadlineExceeded,
+    Unauthenticated,
+    Internal,
+}
+
+const REASONS: [ErrorReason; 34] = [
+    ErrorReason::KeyNotFound,
+    ErrorReason::CiphertextNotFound,
+    ErrorReason::SessionNotFound,
+    ErrorReason::CounterNotFound,
+    ErrorReason::BloomFilterNotFound,
+    ErrorReason::AggregationNotFound,
+    ErrorReason::ElectionNotFound,
+    ErrorReason::MigrationNotFound,
+    ErrorReason::BackupNotFound,
+    ErrorReason::JobNotFound,
+    ErrorReason::TypeMismatch,
+    ErrorReason::WidthMismatch,
+    ErrorReason::ArityMismatch,
+    ErrorReason::ShapeMismatch,
+    ErrorReason::InvalidCircuit,
+    ErrorReason::InvalidRequest,
+    ErrorReason::ValueOutOfRange,
+    ErrorReason::OffsetOutOfRange,
+    ErrorReason::LimitExceeded,
+    ErrorReason::Overloaded,
+    ErrorReason::Unsupported,
+    ErrorReason::FingerprintMismatch,
+    ErrorReason::PolicyViolation,
+    ErrorReason::PermissionDenied,
+    ErrorReason::PolicyUnavailable,
+    ErrorReason::PrivacyBudgetExhausted,
+    ErrorReason::ElectionClosed,
+    ErrorReason::ElectionOpen,
+    ErrorReason::AliasTaken,
+    ErrorReason::DedupConflict,
+    ErrorReason::Cancelled,
+    ErrorReason::DeadlineExceeded,
+    ErrorReason::Unauthenticated,
+    ErrorReason::Internal,
+];
+
+impl ErrorReason {
+    // The ErrorInfo reason, stable across releases
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorReason::KeyNotFound => "KEY_NOT_FOUND",
+            ErrorReason::CiphertextNotFound => "CIPHERTEXT_NOT_FOUND",
+            ErrorReason::SessionNotFound => "SESSION_NOT_FOUND",
+            ErrorReason::CounterNotFound => "COUNTER_NOT_FOUND",
+            ErrorReason::BloomFilterNotFound => "BLOOM_FILTER_NOT_FOUND",
+            ErrorReason::AggregationNotFound => "AGGREGATION_NOT_FOUND",
+            ErrorReason::ElectionNotFound => "ELECTION_NOT_FOUND",
+            ErrorReason::MigrationNotFound => "MIGRATION_NOT_FOUND",
+            ErrorReason::BackupNotFound => "BACKUP_NOT_FOUND",
+            ErrorReason::JobNotFound => "JOB_NOT_FOUND",
+            ErrorReason::TypeMismatch => "TYPE_MISMATCH",
+            ErrorReason::WidthMismatch => "WIDTH_MISMATCH",
+            ErrorReason::ArityMismatch => "ARITY_MISMATCH",
+            ErrorReason::ShapeMismatch => "SHAPE_MISMATCH",
+            ErrorReason::InvalidCircuit => "INVALID_CIRCUIT",
+            ErrorReason::InvalidRequest => "INVALID_REQUEST",
+            ErrorReason::ValueOutOfRange => "VALUE_OUT_OF_RANGE",
+            ErrorReason::OffsetOutOfRange => "OFFSET_OUT_OF_RANGE",
+            ErrorReason::LimitExceeded => "LIMIT_EXCEEDED",
+            ErrorReason::Overloaded => "OVERLOADED",
+            ErrorReason::Unsupported => "UNSUPPORTED",
+            ErrorReason::FingerprintMismatch => "FINGERPRINT_MISMATCH",
+            ErrorReason::PolicyViolation => "POLICY_VIOLATION",
+            ErrorReason::PermissionDenied => "PERMISSION_DENIED",
+            ErrorReason::PolicyUnavailable => "POLICY_UNAVAILABLE",
+            ErrorReason::PrivacyBudgetExhausted => "PRIVACY_BUDGET_EXHAUSTED",
+            ErrorReason::ElectionClosed => "ELECTION_CLOSED",
+            ErrorReason::ElectionOpen => "ELECTION_OPEN",
+            ErrorReason::AliasTaken => "ALIAS_TAKEN",
+            ErrorReason::DedupConflict => "DEDUP_CONFLICT",
+            ErrorReason::Cancelled => "CANCELLED",
+            ErrorReason::DeadlineExceeded => "DEADLINE_EXCEEDED",
+            ErrorReason::Unauthenticated => "UNAUTHENTICATED",
+            ErrorReason::Internal => "INTERNAL",
+        }
+    }
+
+    pub fn code(self) -> Code {
+        match self {
+            ErrorReason::KeyNotFound
+            | ErrorReason::CiphertextNotFound
+            | ErrorReason::SessionNotFound
+            | ErrorReason::CounterNotFound
+            | ErrorReason::BloomFilterNotFound
+            | ErrorReason::AggregationNotFound
+            | ErrorReason::ElectionNotFound
+            | ErrorReason::MigrationNotFound
+            | ErrorReason::BackupNotFound
+            | ErrorReason::JobNotFound => Code::NotFound,
+            ErrorReason::TypeMismatch | ErrorReason::ElectionClosed | ErrorReason::ElectionOpen => {
+                Code::FailedPrecondition
+            }
+            ErrorReason::WidthMismatch
+            | ErrorReason::ArityMismatch
+            | ErrorReason::ShapeMismatch
+            | ErrorReason::InvalidCircuit
+            | ErrorReason::InvalidRequest
+            | ErrorReason::ValueOutOfRange => Code::InvalidArgument,
+            ErrorReason::OffsetOutOfRange => Code::OutOfRange,
+            ErrorReason::LimitExceeded | ErrorReason::Overloaded | ErrorReason::PrivacyBudgetExhausted => {
+                Code::ResourceExhausted
+            }
+            ErrorReason::Unsupported => Code::Unimplemented,
+            ErrorReason::FingerprintMismatch => Code::DataLoss,
+            ErrorReason::AliasTaken | ErrorReason::DedupConflict => Code::AlreadyExists,
+            ErrorReason::PolicyViolation | ErrorReason::PermissionDenied => Code::PermissionDenied,
+            ErrorReason::PolicyUnavailable => Code::Unavailable,
+            ErrorReason::Cancelled => Code::Cancelled,
+            ErrorReason::DeadlineExceeded => Code::DeadlineExceeded,
+            ErrorReason::Unauthenticated => Code::Unauthenticated,
+            ErrorReason::Internal => Code::Internal,
+        }
+    }
+
+    // A status with this reason's code and an ErrorInfo detail naming it
+    pub fn status(self, message: impl Into<String>) -> Status {
+        Status::with_error_details(
+            self.code(),
+            message,
+            ErrorDetails::with_error_info(self.as_str(), ERROR_DOMAIN, HashMap::new()),
+        )
+    }
+
+    // The reason a status from this service carries, if any
+    pub fn of(status: &Status) -> Option<Self> {
+        let info = status.get_details_error_info()?;
+        if info.domain != ERROR_DOMAIN {
+            return None;
+        }
+        REASONS.into_iter().find(|reason| reason.as_str() == info.reason)
+    }
+}
@@ -0,0 +1,162 @@
+// Evaluation requests taken from a message bus and answered through the same service
+// as the gRPC handlers, which return tonic::Status, large by design
+#![allow(clippy::result_large_err)]
+
+use anyhow::{anyhow, Result};
+use prost::Message;
+#[cfg(feature = "nats")]
+use tokio_stream::StreamExt;
+use tonic::metadata::MetadataValue;
+use tonic::{Request, Status};
+#[cfg(feature = "nats")]
+use tracing::{error, info};
+
+use crate::api::{event_request, event_response, EventError, EventRequest, EventResponse, FheService};
+use crate::service::errors::ErrorReason;
+use crate::service::usage::TENANT_HEADER;
+use crate::service::FheServiceImpl;
+
+// Largest timeout_ms that fits grpc-timeout's eight digits
+const MAX_TIMEOUT_MS: u32 = 99_999_999;
+
+// Run one encoded EventRequest and encode its EventResponse. Anything a bus can carry
+// bytes over can sit in front of this.
+pub async fn handle(service: &FheServiceImpl, payload: &[u8]) -> Vec<u8> {
+    let response = match EventRequest::decode(payload) {
+        Ok(request) => {
+            let request_id = request.request_id.clone();
+            EventResponse {
+                request_id,
+                result: Some(
+                    run(service, request)
+                        .await
+                        .unwrap_or_else(|status| event_response::Result::Error(event_error(&status))),
+                ),
+            }
+        }
+        Err(e) => EventResponse {
+            request_id: String::new(),
+            result: Some(event_response::Result::Error(event_error(
+                &ErrorReason::InvalidRequest.status(format!("Malformed EventRequest: {}", e)),
+            ))),
+        },
+    };
+    response.encode_to_vec()
+}
+
+async fn run(service: &FheServiceImpl, request: EventRequest) -> Result<event_response::Result, Status> {
+    let tenant = request.tenant;
+    let timeout_ms = request.timeout_ms;
+    match request.request {
+        Some(event_request::Request::Evaluate(req)) => {
+            let response = service
+                .evaluate_operation(rpc_request(req, &tenant, timeout_ms)?)
+                .await?;
+            Ok(event_response::Result::Evaluation(response.into_inner()))
+        }
+        Some(event_request::Request::Circuit(req)) => {
+            let response = service
+                .evaluate_circuit(rpc_request(req, &tenant, timeout_ms)?)
+                .await?;
+            Ok(event_response::Result::Circuit(response.into_inner()))
+        }
+        None => Err(ErrorReason::InvalidRequest.status("EventRequest has no request")),
+    }
+}
+
+// The request as the RPC handler would see it, with the event's tenant and deadline
+// carried as the metadata a gRPC client would send
+fn rpc_request<T>(message: T, tenant: &str, timeout_ms: u32) -> Result<Request<T>, Status> {
+    let mut request = Request::new(message);
+    if !tenant.is_empty() {
+        let value = MetadataValue::try_from(tenant)
+            .map_err(|_| ErrorReason::InvalidRequest.status("tenant is not a valid metadata value"))?;
+        request.metadata_mut().insert(TENANT_HEADER, value);
+    }
+    if timeout_ms > 0 {
+        let timeout = format!("{}m", timeout_ms.min(MAX_TIMEOUT_MS));
+        let value = MetadataValue::try_from(timeout.as_str()).expect("digits are valid metadata");
+        request.metadata_mut().insert("grpc-timeout", value);
+    }
+    Ok(request)
+}
+
+fn event_error(status: &Status) -> EventError {
+    EventError {
+        code: status.code() as i32,
+        reason: ErrorReason::of(status)
+            .map(|reason| reason.as_str().to_string())
+            .unwrap_or_default(),
+        message: status.message().to_string(),
+    }
+}
+
+// Where the NATS frontend reads requests and writes responses
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NatsConfig {
+    pub url: String,
+    pub request_subject: String,
+    // Responses go to a request's reply subject when it has one, and here otherwise
+    pub result_subject: String,
+    // Servers in the same queue group share the requests between them
+    pub queue_group: String,
+}
+
+impl NatsConfig {
+    // HERMETIC_FHE_NATS_URL enables the frontend; HERMETIC_FHE_NATS_REQUEST_SUBJECT,
+    // HERMETIC_FHE_NATS_RESULT_SUBJECT and HERMETIC_FHE_NATS_QUEUE_GROUP override the
+    // defaults. None when no URL is set.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(url) = std::env::var("HERMETIC_FHE_NATS_URL") else {
+            return Ok(None);
+        };
+        if !cfg!(feature = "nats") {
+            return Err(anyhow!("HERMETIC_FHE_NATS_URL requires the nats feature"));
+        }
+        let setting = |name: &str, default: &str| std::env::var(name).unwrap_or_else(|_| default.to_string());
+        Ok(Some(Self {
+            url,
+            request_subject: setting("HERMETIC_FHE_NATS_REQUEST_SUBJECT", "hermetic-fhe.requests"),
+            result_subject: setting("HERMETIC_FHE_NATS_RESULT_SUBJECT", "hermetic-fhe.results"),
+            queue_group: setting("HERMETIC_FHE_NATS_QUEUE_GROUP", "hermetic-fhe"),
+        }))
+    }
+}
+
+// Answer requests from the subject until the connection closes, each in its own task so
+// a slow evaluation doesn't hold up the rest; admission control still bounds the work
+#[cfg(feature = "nats")]
+pub async fn serve_nats(service: FheServiceImpl, config: NatsConfig) -> Result<()> {
+    let client = async_nats::connect(config.url.as_str())
+        .await
+        .map_err(|e| anyhow!("Failed to connect to NATS at {}: {}", config.url, e))?;
+    let mut requests = client
+        .queue_subscribe(config.request_subject.clone(), config.queue_group.clone())
+        .await
+        .map_err(|e| anyhow!("Failed to subscribe to {}: {}", config.request_subject, e))?;
+    info!(
+        "Taking evaluation requests from NATS subject {} (queue group {})",
+        config.request_subject, config.queue_group
+    );
+
+    while let Some(message) = requests.next().await {
+        let service = service.clone();
+        let client = client.clone();
+        let subject = message
+            .reply
+            .clone()
+            .unwrap_or_else(|| config.result_subject.clone().into());
+        tokio::spawn(async move {
+            let response = handle(&service, &message.payload).await;
+            if let Err(e) = client.publish(subject.clone(), response.into()).await {
+                error!("Failed to publish to {}: {}", subject, e);
+            }
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "nats"))]
+pub async fn serve_nats(_service: FheServiceImpl, _config: NatsConfig) -> Result<()> {
+    Err(anyhow!("Built without the nats feature"))
+}
@@ -0,0 +1,97 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+
+use super::sink::ResultSink;
+
+// Failures injected into storage, so tests can check the service copes with a slow or
+// failing sink rather than assuming it never fails. Each write draws from a seeded
+// generator, so a run with the same seed and the same writes fails the same way.
+#[derive(Clone, Debug, Default)]
+pub struct FaultConfig {
+    // Pause before a write, taken with latency_probability
+    latency: Duration,
+    latency_probability: f64,
+    // Chance a write fails without touching storage
+    error_probability: f64,
+    // Chance a write stores only a prefix of the bytes, then fails
+    partial_failure_probability: f64,
+    seed: u64,
+}
+
+impl FaultConfig {
+    pub fn with_latency(mut self, latency: Duration, probability: f64) -> Self {
+        self.latency = latency;
+        self.latency_probability = probability;
+        self
+    }
+
+    pub fn with_errors(mut self, probability: f64) -> Self {
+        self.error_probability = probability;
+        self
+    }
+
+    pub fn with_partial_failures(mut self, probability: f64) -> Self {
+        self.partial_failure_probability = probability;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+}
+
+// A sink that fails as configured and otherwise passes writes on
+pub struct FaultySink {
+    inner: Box<dyn ResultSink>,
+    config: FaultConfig,
+    state: Mutex<u64>,
+}
+
+impl FaultySink {
+    pub fn new(inner: Box<dyn ResultSink>, config: FaultConfig) -> Self {
+        let state = Mutex::new(config.seed);
+        Self { inner, config, state }
+    }
+
+    // Whether an event of the given probability happens this time
+    fn happens(&self, probability: f64) -> bool {
+        if probability <= 0.0 {
+            return false;
+        }
+        let mut state = self.state.lock().unwrap();
+        let draw = (splitmix64(&mut state) >> 11) as f64 / (1u64 << 53) as f64;
+        draw < probability
+    }
+}
+
+impl ResultSink for FaultySink {
+    fn describe(&self) -> String {
+        format!("{} with injected faults", self.inner.describe())
+    }
+
+    fn write(&self, name: &str, bytes: &[u8]) -> Result<String> {
+        if self.happens(self.config.latency_probability) {
+            std::thread::sleep(self.config.latency);
+        }
+        if self.happens(self.config.error_probability) {
+            return Err(anyhow!("Injected failure writing {}", name));
+        }
+        if self.happens(self.config.partial_failure_probability) {
+            self.inner.write(name, &bytes[..bytes.len() / 2])?;
+            return Err(anyhow!("Injected failure after writing half of {}", name));
+        }
+        self.inner.write(name, bytes)
+    }
+}
+
+// SplitMix64: tiny and good enough to pick failures
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
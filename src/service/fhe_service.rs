@@ -1,275 +1,5823 @@
-use std::sync::Arc;
-use tonic::{Request, Response, Status};
-use tracing::info;
-use tfhe::{FheBool, FheUint8, prelude::FheTryEncrypt, prelude::FheDecrypt};
+// Handlers and their helpers return tonic::Status, which is large by design
+#![allow(clippy::result_large_err)]
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Request, Response, Status, Streaming};
+use tracing::{error, info, warn};
+use tfhe::{ClientKey, FheBool, FheUint8, ServerKey, prelude::FheTryEncrypt, prelude::FheDecrypt};
+use tfhe::prelude::FheTryTrivialEncrypt;
+use rayon::prelude::*;
+use prost::Message;
 
 use crate::api::{
-    BooleanResponse, DecryptBooleanRequest, DecryptIntegerRequest, EncryptBooleanRequest,
-    EncryptIntegerRequest, EncryptedDataResponse, EvaluationRequest, EvaluationResponse,
-    FheService, IntegerResponse, KeyGenerationRequest, KeyGenerationResponse, OperationType,
+    attestation_response, circuit_wire, contribution, increment_counter_request, plaintext_value,
+    result_sink, AggregationResponse, AggregationSnapshot, AggregationWindow, ArgMaxRequest,
+    ArgMaxResponse, AttestationRequest, AttestationResponse, BloomFilterMerge, BloomFilterResponse,
+    BooleanResponse, BucketTimestampRequest, CastBallotRequest, CheckpointOptions, CiphertextChunk,
+    CiphertextType, CircuitEvaluationRequest, CircuitEvaluationResponse, CircuitGate,
+    CircuitIntermediate, CircuitIssue, CircuitIssueKind, CircuitWire, CloseElectionRequest,
+    CloseSessionRequest, CloseSessionResponse, CompareTimestampRequest, Contribution,
+    ContributionSummary, CounterResponse, CreateAggregationRequest, CreateBloomFilterRequest,
+    CreateCounterRequest, CreateElectionRequest, CreateSessionRequest, CreateSessionResponse,
+    CreationOrder, CustomOperationInfo, DeclaredInput, DecryptBooleanRequest,
+    DecryptIntegerBatchRequest, DecryptIntegerRequest, DecryptMatrixRequest, DecryptMatrixResponse,
+    DecryptRealVectorRequest, DecryptTimestampRequest, DeleteAggregationRequest,
+    DeleteBloomFilterRequest, DeleteCiphertextsRequest, DeleteCiphertextsResponse,
+    DeleteCounterRequest, DeleteKeyAliasRequest, DeleteKeyAliasResponse, ElectionResponse,
+    EncryptAndEvaluateRequest, EncryptBooleanRequest, EncryptIntegerBatchRequest,
+    EncryptIntegerRequest, EncryptMatrixRequest, EncryptRealVectorRequest, EncryptTimestampRequest,
+    EncryptedDataResponse, EncryptedRecord, EstimateCostRequest, EstimateCostResponse,
+    EvaluateAndDecryptRequest, EvaluateAndDecryptResponse, EvaluationRequest, EvaluationResponse,
+    ExportCiphertextRequest, ExportCiphertextResponse, FheService, GetLineageRequest,
+    GetMapJobRequest, GetTallyRequest, ImportCiphertextRequest, IncrementCounterRequest,
+    InferenceRequest, InferenceResponse, IngestSummary, IngestedRecord,
+    IntegerBatchEvaluationRequest, IntegerBatchOperation, IntegerBatchResponse, IntegerResponse,
+    InvokeCustomOperationRequest, InvokeCustomOperationResponse, JobCallback, JoinOperationRequest,
+    KeyAlias, KeyGenerationRequest, KeyGenerationResponse, LibraryCircuitInfo,
+    LibraryCircuitRequest, LineageNode, LineageResponse, ListAggregationWindowsRequest,
+    ListAggregationWindowsResponse, ListCustomOperationsRequest, ListCustomOperationsResponse,
+    ListKeyAliasesRequest, ListKeyAliasesResponse, ListLibraryCircuitsRequest,
+    ListLibraryCircuitsResponse, MapJobStatus, MapOperationRequest, MappedRecord,
+    MatchStringRequest, MatchStringResponse, MatrixAddRequest, MatrixResponse, MatrixScaleRequest,
+    MatrixVectorProductRequest, MatrixVectorProductResponse, MemoryMetrics,
+    MergeBloomFiltersRequest, MetricSnapshot, MetricsRequest, MetricsResponse, ModelLayer,
+    OperationCount, OperationType, PirQueryRequest, PlaintextValue, PrivacyBudget, PrivacyNoise,
+    QueryBloomFilterRequest, QueryBloomFilterResponse, QueryCiphertextsRequest,
+    QueryCiphertextsResponse, RankedElement, ReEncryptRequest, ReEncryptionKeyRequest,
+    ReEncryptionKeyResponse, ReadAggregationRequest, ReadCounterRequest, ReadCounterResponse,
+    RealVectorEvaluationRequest, RealVectorOperation, RealVectorResponse, ReduceOperationRequest,
+    Reduction, ReleaseResultsRequest, ReleaseResultsResponse, ResourceLimits, ResultSink,
+    SeriesPoint, SeriesTransform, ServerFeatures, ServerInfoRequest, ServerInfoResponse,
+    SetKeyAliasRequest, SetMembershipRequest, SortVectorRequest, SortVectorResponse, StoreMetrics,
+    StreamCiphertextsRequest, StringMatch, TagCiphertextRequest, TagCiphertextResponse,
+    TaggedCiphertext, TallyResponse, TenantQueueMetrics, TimeUnit, TimestampComparison,
+    TimestampDifferenceRequest, TimestampResponse, TransformSeriesRequest, TransformSeriesResponse,
+    ValidateCircuitRequest, ValidateCircuitResponse, WarmServerKeysRequest, WarmServerKeysResponse,
+    WorkerPoolMetrics, API_VERSIONS,
+};
+use crate::api::v1::compare_timestamp_request::Other;
+use crate::api::v1::evaluation_request::OverflowBehavior;
+use crate::api::v1::key_generation_request::{ParameterSet, Scheme, TypePolicy};
+use crate::api::v1::privacy_noise::Mechanism as NoiseMechanism;
+use crate::backend::{
+    self, BackendError, BgvBackend, CkksBackend, FheBackend, OperationRegistry, PluginContext, TfheBackend,
+};
+use crate::cancellation::{Cancellation, Cancelled};
+use crate::circuit::checkpoint::{Checkpoint, SavedValue};
+use crate::circuit::cost::{self, CostModel};
+use crate::circuit::{expression, library};
+use crate::circuit::{
+    Circuit, EvaluationOptions, EvaluationResult, Gate, InputSpec, Issue, IssueKind, Operation,
+    Value, ValueType, Wire,
+};
+use crate::crypto::{bgv, check_subject_id, ckks, KeyPolicy, KeyScheme};
+use crate::crypto::alias::{check_alias, AliasError};
+use crate::crypto::attestation::{TeePlatform, MAX_NONCE_BYTES};
+use crate::crypto::bloom::{self, EncryptedBloomFilter};
+use crate::crypto::canonical;
+use crate::crypto::fingerprint::verify_fingerprint;
+use crate::crypto::inference::{Layer, Model};
+use crate::crypto::matrix::EncryptedMatrix;
+use crate::crypto::provenance::{self, Derivation, Parent, Provenance};
+use crate::crypto::sharded::LockMetrics;
+use crate::crypto::tally::{self, MAX_OPTIONS};
+use crate::crypto::timestamp::{self, Comparison, EncryptedTimestamp, MAX_BUCKET_BOUNDARIES};
+use crate::crypto::{KeyStore, Ciphertext, CiphertextKind, CiphertextStore, operations, strings, vector};
+use crate::service::admission::{AdmissionControl, FairShareConfig};
+use crate::service::aggregation::{self, Aggregation, AggregationStore, Windowing};
+use crate::service::alias::KeyReferences;
+use crate::service::attestation::Attestor;
+use crate::service::authorization::{AuthorizationInput, PolicyEngine, PRINCIPAL_HEADER};
+use crate::service::ballot::{Election, ElectionError, ElectionStatus, ElectionStore};
+use crate::service::bloom::{BloomFilter, BloomFilterStore};
+use crate::service::checkpoint::{self, CheckpointPolicy};
+use crate::service::counter::{Counter, CounterStore};
+use crate::service::dataset::{
+    self, LabelIndex, MapJob, MapJobStore, MapOutput, MapProgress, SavedOutput, SavedRecord,
 };
-use crate::crypto::{KeyStore, CiphertextStore, operations};
+use crate::service::errors::ErrorReason;
+use crate::service::journal::{
+    Claim, CommittedOutput, CommittedRecord, JobJournal, JobLog, JournalEntry, JournalError,
+};
+use crate::service::lease::ResultLeases;
+use crate::service::memory::{MemoryGuard, MemoryLimit, MemoryPolicy};
+use crate::service::migration::Migrator;
+use crate::service::privacy::{self, Noise, PrivacyConfig, PrivacyError, PrivacyLedger};
+use crate::service::reload::{ReloadOutcome, RuntimeConfig, RuntimeConfigFile};
+use crate::service::session::{SessionStore, DEFAULT_IDLE_TIMEOUT, MAX_IDLE_TIMEOUT};
+use crate::service::sink::{self, SinkError, SinkPolicy};
+use crate::service::tags::{self, TagIndex};
+use crate::service::usage::{UsageLedger, UsageTag, TENANT_HEADER};
+use crate::service::webhook::{JobEvent, WebhookPolicy};
 
+#[derive(Clone)]
 pub struct FheServiceImpl {
     key_store: Arc<KeyStore>,
     ciphertext_store: Arc<CiphertextStore>,
+    // Key generation, encryption and decryption go through the backend; the other
+    // handlers still work on the stores directly
+    backend: TfheBackend,
+    // Real-vector arithmetic, under CKKS key pairs kept in the same stores
+    ckks: CkksBackend,
+    // Batched integer arithmetic, under BGV key pairs kept in the same stores
+    bgv: BgvBackend,
+    sessions: Arc<SessionStore>,
+    // Evaluation results kept past an evicted session until the client reads them
+    leases: Arc<ResultLeases>,
+    counters: Arc<CounterStore>,
+    elections: Arc<ElectionStore>,
+    bloom_filters: Arc<BloomFilterStore>,
+    aggregations: Arc<AggregationStore>,
+    labels: Arc<LabelIndex>,
+    tags: Arc<TagIndex>,
+    map_jobs: Arc<MapJobStore>,
+    // Sinks, webhooks and parameter defaults as the server was started with them, and as
+    // the runtime config last overrode them; replaced whole on each reload
+    configured: RuntimePolicies,
+    runtime: Arc<RwLock<Arc<RuntimePolicies>>>,
+    // Where circuits and map jobs may save progress to resume from
+    checkpoints: Arc<CheckpointPolicy>,
+    // Map jobs by dedup token, journaled to disk if the operator chose a directory
+    journal: Arc<JobJournal>,
+    admission: Arc<AdmissionControl>,
+    memory: Arc<MemoryGuard>,
+    usage: Arc<UsageLedger>,
+    // Privacy spent by noised decryptions, per client key
+    privacy: Arc<PrivacyLedger>,
+    // Replaced whole when the costs are measured again, so readers never see a mix
+    cost_model: Arc<RwLock<Arc<CostModel>>>,
+    // File measured costs are saved to, so a restart needn't measure them again
+    cost_table: Option<PathBuf>,
+    // Quotes from the enclave or confidential VM the server runs in, if it runs in one
+    attestor: Option<Arc<Attestor>>,
+    // Decides each call from its principal, tenant, method and key; None allows every call
+    policy: Option<Arc<dyn PolicyEngine>>,
+    // Operations added by the application embedding the service, invoked by name
+    operations: Arc<OperationRegistry>,
+    // Settings re-read from a file while the server runs
+    runtime_config: Option<Arc<RuntimeConfigFile>>,
+    // Request size limit the server was started with, reported by GetServerInfo
+    max_message_bytes: usize,
+}
+
+// What the runtime config can override of the sink and webhook policies, and the parameter
+// set DEFAULT stands for
+#[derive(Clone, Default)]
+struct RuntimePolicies {
+    // Directories and buckets map jobs may write their results to
+    sinks: Arc<SinkPolicy>,
+    // Callback URLs jobs may notify, and the secret signing the notifications
+    webhooks: Arc<WebhookPolicy>,
+    default_parameter_set: Option<&'static str>,
 }
 
 impl FheServiceImpl {
     pub fn new(key_store: Arc<KeyStore>, ciphertext_store: Arc<CiphertextStore>) -> Self {
+        Self::with_admission_control(key_store, ciphertext_store, AdmissionControl::default())
+    }
+
+    pub fn with_admission_control(
+        key_store: Arc<KeyStore>,
+        ciphertext_store: Arc<CiphertextStore>,
+        admission: AdmissionControl,
+    ) -> Self {
         Self {
+            backend: TfheBackend::new(key_store.clone(), ciphertext_store.clone()),
+            ckks: CkksBackend::new(key_store.clone(), ciphertext_store.clone()),
+            bgv: BgvBackend::new(key_store.clone(), ciphertext_store.clone()),
             key_store,
             ciphertext_store,
+            sessions: Arc::new(SessionStore::new()),
+            leases: Arc::new(ResultLeases::default()),
+            counters: Arc::new(CounterStore::new()),
+            elections: Arc::new(ElectionStore::new()),
+            bloom_filters: Arc::new(BloomFilterStore::new()),
+            aggregations: Arc::new(AggregationStore::new()),
+            labels: Arc::new(LabelIndex::new()),
+            tags: Arc::new(TagIndex::new()),
+            map_jobs: Arc::new(MapJobStore::new()),
+            configured: RuntimePolicies::default(),
+            runtime: Arc::new(RwLock::new(Arc::new(RuntimePolicies::default()))),
+            checkpoints: Arc::new(CheckpointPolicy::default()),
+            journal: Arc::new(JobJournal::default()),
+            admission: Arc::new(admission),
+            memory: Arc::new(MemoryGuard::default()),
+            usage: Arc::new(UsageLedger::new()),
+            privacy: Arc::new(PrivacyLedger::default()),
+            cost_model: Arc::new(RwLock::new(Arc::new(CostModel::default()))),
+            cost_table: None,
+            attestor: None,
+            policy: None,
+            operations: Arc::new(OperationRegistry::new()),
+            runtime_config: None,
+            max_message_bytes: MAX_MESSAGE_BYTES,
+        }
+    }
+
+    // Replace the built-in per-gate costs EstimateCost works from, e.g. with calibrated ones
+    pub fn with_cost_model(mut self, cost_model: CostModel) -> Self {
+        self.cost_model = Arc::new(RwLock::new(Arc::new(cost_model)));
+        self
+    }
+
+    // Save costs measured later, through calibrate_costs, to this file
+    pub fn with_cost_table(mut self, path: impl Into<PathBuf>) -> Self {
+        self.cost_table = Some(path.into());
+        self
+    }
+
+    // Cap the memory the key and ciphertext stores may hold between them
+    pub fn with_memory_limit(mut self, limit: MemoryLimit) -> Self {
+        self.memory = Arc::new(MemoryGuard::new(Some(limit)));
+        self
+    }
+
+    // Pin evaluation results for this lease instead of the default hour
+    pub fn with_result_leases(mut self, leases: ResultLeases) -> Self {
+        self.leases = Arc::new(leases);
+        self
+    }
+
+    // Let map jobs write results to the directories and buckets the policy allows, rather
+    // than refusing every sink
+    pub fn with_sink_policy(mut self, sinks: SinkPolicy) -> Self {
+        self.configured.sinks = Arc::new(sinks);
+        self.runtime = Arc::new(RwLock::new(Arc::new(self.configured.clone())));
+        self
+    }
+
+    // Give each client key this privacy budget for noised decryptions, spent in memory
+    pub fn with_privacy_config(mut self, config: PrivacyConfig) -> Self {
+        self.privacy = Arc::new(PrivacyLedger::new(config));
+        self
+    }
+
+    // Charge noised decryptions to the ledger, which should be durable when the key store
+    // keeps its keys in a key directory
+    pub fn with_privacy_ledger(mut self, ledger: PrivacyLedger) -> Self {
+        self.privacy = Arc::new(ledger);
+        self
+    }
+
+    // Let jobs notify the callback URLs the policy allows when they finish
+    pub fn with_webhook_policy(mut self, webhooks: WebhookPolicy) -> Self {
+        self.configured.webhooks = Arc::new(webhooks);
+        self.runtime = Arc::new(RwLock::new(Arc::new(self.configured.clone())));
+        self
+    }
+
+    // Let circuits and map jobs that ask for it save their progress under the policy's
+    // directory, rather than refusing checkpoints
+    pub fn with_checkpoint_policy(mut self, checkpoints: CheckpointPolicy) -> Self {
+        self.checkpoints = Arc::new(checkpoints);
+        self
+    }
+
+    // Journal map jobs given a dedup token under the journal's directory, so they run once
+    // even across restarts, rather than deduplicating them in memory only
+    pub fn with_job_journal(mut self, journal: JobJournal) -> Self {
+        self.journal = Arc::new(journal);
+        self
+    }
+
+    // Read back the job journal after a restart: store the results of jobs cut short again,
+    // under the IDs and labels they had, and list the jobs for GetMapJob. A job cut short
+    // carries on when its request is sent again with the same token. A finished job's results
+    // aren't stored again, since its client may have fetched or deleted them already. Returns
+    // how many jobs the journal held.
+    pub fn replay_job_journal(&self) -> anyhow::Result<usize> {
+        let jobs = self.journal.replay()?;
+        for replayed in &jobs {
+            let job = self.map_jobs.create_as(&replayed.job_id, replayed.total);
+            for record in &replayed.committed {
+                let stored = match &record.output {
+                    CommittedOutput::Stored { id, value } if !replayed.finished => Some((id, value)),
+                    _ => None,
+                };
+                if let Some((id, value)) = stored {
+                    match value.value() {
+                        Ok(value) => {
+                            self.ciphertext_store.store_as(id, Ciphertext::from(value));
+                            self.labels.set(&record.result_label, id);
+                        }
+                        Err(e) => warn!("Journaled result {} of job {} lost: {}", id, replayed.job_id, e),
+                    }
+                }
+                job.record(committed_record(record));
+            }
+            if replayed.finished {
+                job.finish();
+            }
+        }
+        Ok(jobs.len())
+    }
+
+    // Answer GetAttestation with quotes binding the TLS certificate the attestor was given
+    pub fn with_attestor(mut self, attestor: Attestor) -> Self {
+        self.attestor = Some(Arc::new(attestor));
+        self
+    }
+
+    // Ask the policy engine before serving each call
+    pub fn with_policy(mut self, policy: Box<dyn PolicyEngine>) -> Self {
+        self.policy = Some(Arc::from(policy));
+        self
+    }
+
+    // Apply the file's settings on each reload_config
+    pub fn with_runtime_config(mut self, runtime_config: RuntimeConfigFile) -> Self {
+        self.runtime_config = Some(Arc::new(runtime_config));
+        self
+    }
+
+    // Serve the registry's operations through InvokeCustomOperation
+    pub fn with_operations(mut self, operations: OperationRegistry) -> Self {
+        self.operations = Arc::new(operations);
+        self
+    }
+
+    // Record the request size limit applied in front of this service, so clients can
+    // discover it; the limit itself is enforced by the generated server
+    pub fn with_max_message_bytes(mut self, max_message_bytes: usize) -> Self {
+        self.max_message_bytes = max_message_bytes;
+        self
+    }
+
+    // Operation counts and compute time since startup, for billing
+    pub fn usage(&self) -> Arc<UsageLedger> {
+        self.usage.clone()
+    }
+
+    // The fair-share weights and caps in force, None when fair share is off
+    pub fn fair_share(&self) -> Option<FairShareConfig> {
+        self.admission.fair_share()
+    }
+
+    // The per-gate costs that EstimateCost and deadline checks work from
+    pub fn cost_model(&self) -> Arc<CostModel> {
+        self.cost_model.read().unwrap().clone()
+    }
+
+    // Measure the listed parameter sets' gate costs again, keeping the others, save them to
+    // the cost table if there is one, and work from them from now on. Blocks for as long as
+    // the measurements take; other work running meanwhile makes them slower than they are.
+    pub fn calibrate_costs(&self, parameter_sets: &[&str], samples: usize) -> anyhow::Result<Arc<CostModel>> {
+        let calibrated = Arc::new(self.cost_model().recalibrate(parameter_sets, samples)?);
+        if let Some(path) = &self.cost_table {
+            calibrated.save(path)?;
+        }
+        *self.cost_model.write().unwrap() = calibrated.clone();
+        Ok(calibrated)
+    }
+
+    // Re-read the runtime config and the policy if they changed since they were last read,
+    // or whether or not they did when forced. A file that fails keeps its settings as they
+    // were and doesn't hold up the other.
+    pub fn reload_config(&self, force: bool) -> ReloadOutcome {
+        let mut outcome = ReloadOutcome::default();
+        if let Some(runtime_config) = &self.runtime_config {
+            let applied = runtime_config.read(force).and_then(|config| match config {
+                Some(config) => self.apply_runtime_config(runtime_config, &config).map(|()| true),
+                None => Ok(false),
+            });
+            match applied {
+                Ok(true) => outcome.reloaded.push("runtime config"),
+                Ok(false) => {}
+                Err(e) => outcome.errors.push(e.to_string()),
+            }
+        }
+        if let Some(policy) = &self.policy {
+            match policy.reload(force) {
+                Ok(true) => outcome.reloaded.push("policy"),
+                Ok(false) => {}
+                Err(e) => outcome.errors.push(e.to_string()),
+            }
+        }
+        outcome
+    }
+
+    // Apply a version of the runtime config. Whatever can refuse it comes first, so a refused
+    // version changes nothing.
+    fn apply_runtime_config(&self, file: &RuntimeConfigFile, config: &RuntimeConfig) -> anyhow::Result<()> {
+        let mut sinks = (*self.configured.sinks).clone();
+        if let Some(dir) = &config.sink_dir {
+            sinks = sinks.with_root(dir)?;
+        }
+        if let Some(buckets) = &config.sink_buckets {
+            sinks = sinks.with_buckets(buckets.clone())?;
+        }
+        let webhooks = match &config.webhook_urls {
+            Some(urls) => self.configured.webhooks.with_allowed(urls.clone())?,
+            None => (*self.configured.webhooks).clone(),
+        };
+        // Checked when the file was parsed
+        let default_parameter_set = config
+            .default_parameter_set
+            .as_deref()
+            .and_then(ParameterSet::from_str_name)
+            .map(|set| set.as_str_name());
+
+        // The log level is the only setting that can still be refused, so it goes first
+        file.apply_log_level(config)?;
+        self.admission.set_fair_share(config.fair_share());
+        *self.runtime.write().unwrap() = Arc::new(RuntimePolicies {
+            sinks: Arc::new(sinks),
+            webhooks: Arc::new(webhooks),
+            default_parameter_set,
+        });
+        Ok(())
+    }
+
+    fn runtime(&self) -> Arc<RuntimePolicies> {
+        self.runtime.read().unwrap().clone()
+    }
+
+    // The parameter set a request names, DEFAULT standing for the runtime config's default
+    // if it sets one
+    fn parameter_set(&self, parameter_set: i32) -> Result<&'static str, Status> {
+        let name = parameter_set_name(parameter_set)?;
+        match self.runtime().default_parameter_set {
+            Some(default) if name == "DEFAULT" => Ok(default),
+            _ => Ok(name),
+        }
+    }
+
+    // Free the ciphertexts of every session that has sat idle past its timeout, except
+    // results still pinned
+    pub fn reap_expired_sessions(&self) -> usize {
+        let expired = self.sessions.take_expired();
+        self.free_evicted(expired)
+    }
+
+    // Close a session and free its ciphertexts; None if it is unknown
+    pub(crate) fn evict_session(&self, session_id: &str) -> Option<usize> {
+        let ciphertext_ids = self.sessions.close(session_id)?;
+        Some(self.free_ciphertexts(&ciphertext_ids))
+    }
+
+    pub(crate) fn key_store(&self) -> &KeyStore {
+        &self.key_store
+    }
+
+    pub(crate) fn ciphertext_store(&self) -> &CiphertextStore {
+        &self.ciphertext_store
+    }
+
+    pub(crate) fn sessions(&self) -> &SessionStore {
+        &self.sessions
+    }
+
+    pub(crate) fn max_message_bytes(&self) -> usize {
+        self.max_message_bytes
+    }
+
+    pub(crate) fn webhooks(&self) -> Arc<WebhookPolicy> {
+        self.runtime().webhooks.clone()
+    }
+
+    pub(crate) fn migrator(&self) -> Migrator {
+        Migrator::new(self.key_store.clone(), self.ciphertext_store.clone())
+    }
+
+    pub(crate) fn metrics(&self) -> MetricsResponse {
+        let admission = self.admission.metrics();
+        let memory = self.memory.usage(self.memory_used());
+
+        MetricsResponse {
+            worker_pool: Some(WorkerPoolMetrics {
+                workers: admission.workers as u32,
+                running: admission.running as u32,
+                queued: admission.queued as u32,
+                queue_depth: admission.queue_depth as u32,
+                rejected_total: admission.rejected,
+                tenants: self
+                    .admission
+                    .tenant_metrics()
+                    .into_iter()
+                    .map(|tenant| TenantQueueMetrics {
+                        tenant: tenant.tenant,
+                        weight: tenant.weight,
+                        running: tenant.running as u32,
+                        queued: tenant.queued as u32,
+                        admitted_total: tenant.admitted,
+                        rejected_total: tenant.rejected,
+                        compute_seconds_total: tenant.compute.as_secs_f64(),
+                    })
+                    .collect(),
+            }),
+            key_store: Some(store_metrics(
+                self.key_store.len(),
+                self.key_store.memory_bytes(),
+                self.key_store.lock_metrics(),
+            )),
+            ciphertext_store: Some(store_metrics(
+                self.ciphertext_store.len(),
+                self.ciphertext_store.memory_bytes(),
+                self.ciphertext_store.lock_metrics(),
+            )),
+            memory: Some(MemoryMetrics {
+                used_bytes: memory.used_bytes,
+                limit_bytes: memory.limit_bytes.unwrap_or(0),
+                rejected_total: memory.rejected,
+                evicted_sessions_total: memory.evicted_sessions,
+            }),
+        }
+    }
+
+    // What IngestEncryptedRecords does with its request stream, for records from any
+    // source. If a record is rejected the stream fails and the records already stored
+    // from it are removed, so a retry doesn't leave duplicates behind.
+    pub async fn ingest(
+        &self,
+        records: impl Stream<Item = Result<EncryptedRecord, Status>> + Unpin,
+    ) -> Result<IngestSummary, Status> {
+        let mut ingested = Vec::new();
+        if let Err(status) = self.ingest_records(records, &mut ingested).await {
+            let ids: Vec<String> = ingested.into_iter().map(|(record, _)| record.encrypted_data_id).collect();
+            self.free_ciphertexts(&ids);
+            return Err(status);
+        }
+
+        // Sessions and labels only learn of the records once all of them are in. A record
+        // whose session closed meanwhile fails the stream like any other rejected record.
+        for (record, session_id) in &ingested {
+            if let Err(status) = self.track_in_session(session_id, &record.encrypted_data_id) {
+                let ids: Vec<String> =
+                    ingested.iter().map(|(record, _)| record.encrypted_data_id.clone()).collect();
+                self.free_ciphertexts(&ids);
+                return Err(status);
+            }
+        }
+        for (record, _) in &ingested {
+            if !record.label.is_empty() {
+                self.labels.set(&record.label, &record.encrypted_data_id);
+            }
+        }
+        info!("Ingested {} records", ingested.len());
+
+        Ok(IngestSummary {
+            records: ingested.into_iter().map(|(record, _)| record).collect(),
+        })
+    }
+
+    // Store records a batch at a time, adding each stored record and its session to `ingested`
+    async fn ingest_records(
+        &self,
+        mut records: impl Stream<Item = Result<EncryptedRecord, Status>> + Unpin,
+        ingested: &mut Vec<(IngestedRecord, String)>,
+    ) -> Result<(), Status> {
+        let mut batch = Vec::with_capacity(INGEST_BATCH_SIZE);
+        loop {
+            let record = records.next().await.transpose()?;
+            let done = record.is_none();
+            if let Some(record) = record {
+                if ingested.len() + batch.len() == MAX_INGEST_RECORDS {
+                    return Err(ErrorReason::LimitExceeded.status(format!(
+                        "A stream may carry at most {} records",
+                        MAX_INGEST_RECORDS
+                    )));
+                }
+                batch.push(record);
+            }
+            if batch.len() == INGEST_BATCH_SIZE || (done && !batch.is_empty()) {
+                let batch = std::mem::replace(&mut batch, Vec::with_capacity(INGEST_BATCH_SIZE));
+                ingested.extend(self.ingest_batch(batch, ingested.len()).await?);
+            }
+            if done {
+                return Ok(());
+            }
+        }
+    }
+
+    // Deserializing is CPU-bound, so a batch is stored on a blocking thread. `first` is the
+    // position of the batch's first record in the stream, for error messages.
+    async fn ingest_batch(
+        &self,
+        batch: Vec<EncryptedRecord>,
+        first: usize,
+    ) -> Result<Vec<(IngestedRecord, String)>, Status> {
+        let session_ids: HashSet<&str> = batch.iter().map(|record| record.session_id.as_str()).collect();
+        for session_id in session_ids {
+            self.check_session(session_id)?;
+        }
+
+        for (index, record) in batch.iter().enumerate() {
+            check_subject_id(&record.subject_id).map_err(|e| {
+                ErrorReason::InvalidRequest.status(format!("Record {}: {}", first + index, e))
+            })?;
+        }
+
+        let store = self.ciphertext_store.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut stored = Vec::with_capacity(batch.len());
+            for (index, record) in batch.into_iter().enumerate() {
+                match import_serialized(
+                    &store,
+                    record.ciphertext_type(),
+                    &record.serialized_data,
+                    &record.fingerprint,
+                ) {
+                    Ok(encrypted_data_id) => {
+                        if !record.subject_id.is_empty() {
+                            store.set_subject(&encrypted_data_id, &record.subject_id);
+                        }
+                        stored.push((
+                            IngestedRecord {
+                                label: record.label,
+                                encrypted_data_id,
+                            },
+                            record.session_id,
+                        ))
+                    }
+                    Err(status) => {
+                        for (record, _) in &stored {
+                            store.remove(&record.encrypted_data_id);
+                        }
+                        let reason = ErrorReason::of(&status).unwrap_or(ErrorReason::Internal);
+                        return Err(reason.status(format!("Record {}: {}", first + index, status.message())));
+                    }
+                }
+            }
+            Ok(stored)
+        })
+        .await
+        .map_err(|e| ErrorReason::Internal.status(format!("Ingestion failed: {}", e)))?
+    }
+
+    // Add each contribution to its metric as it arrives, so a long-lived stream feeds the
+    // sums continuously. A contribution that fails ends the stream; earlier ones stay added.
+    pub async fn contribute(
+        &self,
+        tenant: &str,
+        mut contributions: impl Stream<Item = Result<Contribution, Status>> + Unpin,
+    ) -> Result<ContributionSummary, Status> {
+        let mut accepted = 0;
+        while let Some(contribution) = contributions.next().await.transpose()? {
+            self.add_contribution(tenant, contribution).map_err(|status| {
+                let reason = ErrorReason::of(&status).unwrap_or(ErrorReason::Internal);
+                reason.status(format!("Contribution {}: {}", accepted, status.message()))
+            })?;
+            accepted += 1;
+        }
+        info!("Accepted {} contributions", accepted);
+
+        Ok(ContributionSummary { accepted })
+    }
+
+    fn add_contribution(&self, tenant: &str, contribution: Contribution) -> Result<(), Status> {
+        let aggregation = self.load_aggregation(&contribution.aggregation_id)?;
+        let metric = &contribution.metric;
+        if metric.is_empty() || metric.len() > aggregation::MAX_METRIC_NAME_LENGTH {
+            return Err(ErrorReason::InvalidRequest.status(format!(
+                "Metric names are 1 to {} bytes",
+                aggregation::MAX_METRIC_NAME_LENGTH
+            )));
+        }
+        let (value, added) = match contribution.value {
+            Some(contribution::Value::ValueId(value_id)) => {
+                let added = self.ciphertext_store.derive([value_id.as_str()]);
+                (self.load_integer(&value_id, "Contribution")?, added)
+            }
+            Some(contribution::Value::SerializedValue(bytes)) => {
+                if contribution.fingerprint.is_empty() {
+                    let message = "Fingerprint is required for a serialized value";
+                    return Err(ErrorReason::InvalidRequest.status(message));
+                }
+                verify_fingerprint(&bytes, &contribution.fingerprint)
+                    .map_err(|e| ErrorReason::FingerprintMismatch.status(e.to_string()))?;
+                let value: FheUint8 = canonical::decode(&bytes)
+                    .map_err(|e| ErrorReason::InvalidRequest.status(e.to_string()))?;
+                // Never stored, so there is nothing to trace it back to
+                (Arc::new(value), Provenance::default())
+            }
+            None => return Err(ErrorReason::InvalidRequest.status("No value provided")),
+        };
+        let server_key = self
+            .key_store
+            .get_server_key(aggregation.server_key_id())
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Server key not found"))?;
+
+        // The high-level tfhe API evaluates against a thread-local server key
+        tfhe::set_server_key((*server_key).clone());
+
+        // Windows go by arrival time, so a window never takes contributions after it closes
+        let counters = aggregation
+            .windows_at(unix_now())
+            .into_iter()
+            .map(|window| match aggregation.metric(window, metric) {
+                Some(counter) => Ok(counter),
+                None => {
+                    // Zero is public, so a trivial encryption is enough
+                    let zero = FheUint8::try_encrypt_trivial(0u8)
+                        .map_err(|e| ErrorReason::Internal.status(format!("Failed to encode zero: {}", e)))?;
+                    aggregation.add_metric(window, metric, zero).ok_or_else(|| {
+                        ErrorReason::LimitExceeded.status(format!(
+                            "An aggregation has at most {} metrics per window",
+                            aggregation::MAX_METRICS
+                        ))
+                    })
+                }
+            })
+            .collect::<Result<Vec<_>, Status>>()?;
+        let usage = UsageTag::new(tenant, aggregation.server_key_id(), "SubmitContributions");
+        self.metered(usage, || {
+            for counter in &counters {
+                counter.increment(|sum| operations::integer_add(sum, &value), &added);
+            }
+        });
+        Ok(())
+    }
+
+    // Apply the circuit to each record on the rayon pool, recording every outcome on the
+    // job as it finishes. The record's ciphertexts are the first inputs and the shared
+    // operands follow them. A record that fails gets no result and the rest still run.
+    // With a checkpoint, records an earlier run finished are taken from it, and the ones
+    // finished since are saved to it every interval until all have succeeded.
+    fn run_map_job(
+        &self,
+        job: &MapJob,
+        map: MapCircuit,
+        server_key: &ServerKey,
+        mut records: Vec<MapInput>,
+        checkpoint: Option<Arc<Checkpoint>>,
+    ) {
+        let result_inputs = map.circuit.output_inputs().into_iter().next().unwrap_or_default();
+        if let Some(committed) = map.journal.as_ref().and_then(|run| run.committed.as_ref()) {
+            resume_journaled(job, committed, &mut records);
+        }
+        let saved = match &checkpoint {
+            Some(checkpoint) => self.resume_map_job(job, &map, checkpoint, &result_inputs, &mut records),
+            None => vec![],
+        };
+        let saved = Mutex::new(saved);
+        records.into_par_iter().for_each_init(
+            || tfhe::set_server_key(server_key.clone()),
+            |_, record| {
+                let result = record
+                    .ids
+                    .iter()
+                    .map(|id| {
+                        self.load_value(id)
+                            .ok_or_else(|| format!("Ciphertext {} not found or not a boolean or integer", id))
+                    })
+                    .chain(map.operands.iter().cloned().map(Ok))
+                    .collect::<Result<Vec<Value>, String>>()
+                    .and_then(|inputs| {
+                        map.circuit
+                            .evaluate(server_key, &inputs, EvaluationOptions::default())
+                            .map_err(|e| e.to_string())
+                    })
+                    .and_then(|mut result| {
+                        let value = result.outputs.remove(0);
+                        match &map.sink {
+                            Some(sink) => Ciphertext::from(value)
+                                .serialize_with_fingerprint()
+                                .and_then(|(bytes, _)| {
+                                    sink::write_with_retries(sink.as_ref(), &record.result_label, &bytes)
+                                })
+                                .and_then(|location| journal_written(&map, &record, location))
+                                .map(MapOutput::Written)
+                                .map_err(|e| e.to_string()),
+                            None => {
+                                let derivation = self.map_derivation(&map, &record, &result_inputs);
+                                let session_id = &map.session_id;
+                                match &map.journal {
+                                    Some(run) => self
+                                        .commit_map_result(&run.log, session_id, &record, value, derivation)
+                                        .map(MapOutput::Stored)
+                                        .map_err(|e| e.to_string()),
+                                    None => self
+                                        .store_derived(value, session_id, derivation)
+                                        .map(|result_id| {
+                                            self.labels.set(&record.result_label, &result_id);
+                                            MapOutput::Stored(result_id)
+                                        })
+                                        .map_err(|status| status.message().to_string()),
+                                }
+                            }
+                        }
+                    });
+                if let (Some(checkpoint), Ok(output)) = (&checkpoint, &result) {
+                    let output = match output {
+                        MapOutput::Stored(result_id) => self.load_value(result_id).map(|value| {
+                            SavedValue::new(&value).map(SavedOutput::Stored)
+                        }),
+                        MapOutput::Written(location) => Some(Ok(SavedOutput::Written(location.clone()))),
+                    };
+                    let mut saved = saved.lock().unwrap();
+                    match output {
+                        Some(Ok(output)) => saved.push(SavedRecord {
+                            label: record.label.clone(),
+                            result_label: record.result_label.clone(),
+                            output,
+                        }),
+                        Some(Err(e)) => warn!("Record {} left out of the checkpoint: {}", record.label, e),
+                        None => {}
+                    }
+                    // A failed save only costs the records finished since the last one
+                    if checkpoint.due() {
+                        if let Err(e) = checkpoint.save(&*saved) {
+                            warn!("Map job checkpoint failed: {}", e);
+                        }
+                    }
+                }
+                job.record(dataset::MappedRecord {
+                    label: record.label,
+                    result_label: record.result_label,
+                    result,
+                });
+            },
+        );
+
+        // A rerun after failures picks up every record that succeeded
+        if let Some(checkpoint) = checkpoint {
+            if job.progress().failed() == 0 {
+                checkpoint.remove();
+            } else if let Err(e) = checkpoint.save(&*saved.lock().unwrap()) {
+                warn!("Map job checkpoint failed: {}", e);
+            }
+        }
+        if let Some(run) = &map.journal {
+            if let Err(e) = run.log.append(&JournalEntry::Finished) {
+                warn!("Map job {} finished but could not journal it: {}", run.job_id, e);
+            }
+        }
+        job.finish();
+    }
+
+    // Journal a map result under an ID chosen now, then store it there. A crash leaves
+    // either no trace of the result, and the record runs again, or a journal entry the
+    // replay stores it again from, so it is never computed or stored twice.
+    fn commit_map_result(
+        &self,
+        log: &JobLog,
+        session_id: &str,
+        record: &MapInput,
+        value: Value,
+        derivation: Derivation,
+    ) -> anyhow::Result<String> {
+        let id = self.ciphertext_store.new_id();
+        let entry = JournalEntry::Committed(CommittedRecord {
+            label: record.label.clone(),
+            result_label: record.result_label.clone(),
+            output: CommittedOutput::Stored {
+                id: id.clone(),
+                value: SavedValue::new(&value)?,
+            },
+        });
+        log.append(&entry)?;
+        self.ciphertext_store.store_as(&id, Ciphertext::from(value));
+        self.track_in_session(session_id, &id)?;
+        self.ciphertext_store.set_derivation(&id, derivation);
+        self.labels.set(&record.result_label, &id);
+        Ok(id)
+    }
+
+    // Claim a map request's dedup token: the job the token already started, or the run to
+    // journal for a new job or one a restart cut short. No token, no journal.
+    fn claim_map_job(
+        &self,
+        tenant: &str,
+        token: &str,
+        digest: Option<String>,
+        total: usize,
+    ) -> Result<MapJobStart, Status> {
+        if token.is_empty() {
+            return Ok(MapJobStart::Run(None));
+        }
+        let claim = self
+            .journal
+            .claim(tenant, token, digest.unwrap_or_default(), total)
+            .map_err(|e| match e {
+                JournalError::InvalidToken(_) => ErrorReason::InvalidRequest.status(e.to_string()),
+                JournalError::Conflict(_) => ErrorReason::DedupConflict.status(e.to_string()),
+                JournalError::Storage(_) => ErrorReason::Internal.status(e.to_string()),
+            })?;
+        let (job_id, log, committed) = match claim {
+            Claim::Existing { job_id, total } => {
+                let status = match self.map_jobs.get(&job_id) {
+                    Some(job) => map_job_status(&job_id, &job.progress()),
+                    // Still waiting for an evaluation slot
+                    None => MapJobStatus {
+                        job_id,
+                        total: total as u32,
+                        ..Default::default()
+                    },
+                };
+                return Ok(MapJobStart::Started(status));
+            }
+            Claim::Resume { job_id, log, committed } => (job_id, log, Some(committed)),
+            Claim::New { job_id, log } => (job_id, log, None),
+        };
+        Ok(MapJobStart::Run(Some(JournaledRun {
+            tenant: tenant.to_string(),
+            token: token.to_string(),
+            job_id,
+            log,
+            committed,
+        })))
+    }
+
+    // Record the results an earlier run of the job saved to its checkpoint, storing them
+    // again where they were stored, and take those records out of the ones left to run.
+    // Returns what was saved, to carry forward into the next save.
+    fn resume_map_job(
+        &self,
+        job: &MapJob,
+        map: &MapCircuit,
+        checkpoint: &Checkpoint,
+        result_inputs: &BTreeSet<usize>,
+        records: &mut Vec<MapInput>,
+    ) -> Vec<SavedRecord> {
+        let saved = match checkpoint.load::<Vec<SavedRecord>>() {
+            Ok(saved) => saved.unwrap_or_default(),
+            Err(e) => {
+                warn!("Ignoring map job checkpoint: {}", e);
+                return vec![];
+            }
+        };
+        let mut saved: HashMap<String, SavedRecord> =
+            saved.into_iter().map(|record| (record.label.clone(), record)).collect();
+        let mut resumed = Vec::with_capacity(saved.len());
+        records.retain(|record| {
+            let Some(saved_record) = saved
+                .remove(&record.label)
+                .filter(|saved_record| saved_record.result_label == record.result_label)
+            else {
+                return true;
+            };
+            let output = match &saved_record.output {
+                SavedOutput::Stored(saved_value) => match saved_value.value() {
+                    Ok(value) => {
+                        let derivation = self.map_derivation(map, record, result_inputs);
+                        let result_id = match self.store_derived(value, &map.session_id, derivation) {
+                            Ok(result_id) => result_id,
+                            Err(status) => {
+                                warn!("Running record {} again: {}", record.label, status.message());
+                                return true;
+                            }
+                        };
+                        self.labels.set(&record.result_label, &result_id);
+                        MapOutput::Stored(result_id)
+                    }
+                    Err(e) => {
+                        warn!("Running record {} again: {}", record.label, e);
+                        return true;
+                    }
+                },
+                SavedOutput::Written(location) => MapOutput::Written(location.clone()),
+            };
+            job.resume(dataset::MappedRecord {
+                label: record.label.clone(),
+                result_label: record.result_label.clone(),
+                result: Ok(output),
+            });
+            resumed.push(saved_record);
+            false
+        });
+        resumed
+    }
+
+    // Digest of a map job for its checkpoint: the request with the fields that don't change
+    // its results cleared, the shared operands and every record it selected
+    fn map_job_digest(&self, request: &[u8], operand_ids: &[String], records: &[MapInput]) -> String {
+        let operands = operand_ids.iter().map(|id| self.ciphertext_fingerprint(id));
+        let records = records.iter().flat_map(|record| {
+            let fingerprints = record.ids.iter().map(|id| self.ciphertext_fingerprint(id));
+            std::iter::once(record.label.clone()).chain(fingerprints)
+        });
+        checkpoint::job_digest(request, operands.chain(records))
+    }
+
+    // A map result is derived from the circuit inputs it depends on, the record's
+    // ciphertexts first and then the shared operands
+    fn map_derivation(&self, map: &MapCircuit, record: &MapInput, inputs: &BTreeSet<usize>) -> Derivation {
+        let parents = inputs
+            .iter()
+            .filter_map(|&index| match record.ids.get(index) {
+                Some(id) => Some(self.ciphertext_store.parent(id)),
+                None => map.operand_parents.get(index - record.ids.len()).cloned(),
+            })
+            .collect();
+        Derivation::new(map.operation, record.label.clone(), parents)
+    }
+
+    // Stored values matching every tag filter, or all of them with none, with the time each
+    // was first stored, oldest first. Tags of values freed for good are dropped as they are
+    // found; those of deleted values are kept, since the values may yet be restored.
+    fn queried_ciphertexts(&self, filters: &[(String, String)]) -> Vec<(Duration, String)> {
+        let ids: Vec<String> = match filters.is_empty() {
+            true => self.ciphertext_store.list().into_iter().map(|(id, _)| id).collect(),
+            false => self.tags.query(filters).into_iter().collect(),
+        };
+        let mut found: Vec<_> = ids
+            .into_iter()
+            .filter_map(|id| {
+                let Some(created_at) = self.ciphertext_store.created_at(&id) else {
+                    if !self.ciphertext_store.is_deleted(&id) {
+                        self.tags.forget(&id);
+                    }
+                    return None;
+                };
+                Some((created_at.duration_since(UNIX_EPOCH).unwrap_or_default(), id))
+            })
+            .collect();
+        found.sort();
+        found
+    }
+
+    // Labels under a prefix and the ciphertexts they name, for MapOperation, JoinOperation
+    // and ReduceOperation. Labels whose ciphertext has since been freed are dropped as they
+    // are found.
+    fn labeled_records(&self, prefix: &str) -> Result<Vec<(String, String)>, Status> {
+        let mut records = self.labels.with_prefix(prefix);
+        records.retain(|(label, id)| {
+            let live = self.ciphertext_store.kind(id).is_some();
+            if !live {
+                self.labels.remove(label);
+            }
+            live
+        });
+        if records.is_empty() {
+            return Err(ErrorReason::CiphertextNotFound
+                .status(format!("No labeled ciphertexts under prefix '{}'", prefix)));
+        }
+        if records.len() > MAX_MAP_RECORDS {
+            return Err(ErrorReason::LimitExceeded.status(format!(
+                "Prefix selects {} records, the limit is {}",
+                records.len(),
+                MAX_MAP_RECORDS
+            )));
+        }
+        Ok(records)
+    }
+
+    // The sink a map job writes to instead of the store, if the client named one the
+    // server allows
+    fn open_sink(&self, sink: Option<ResultSink>) -> Result<Option<Box<dyn sink::ResultSink>>, Status> {
+        let Some(sink) = sink else {
+            return Ok(None);
+        };
+        let sinks = self.runtime().sinks.clone();
+        let opened = match sink.target {
+            Some(result_sink::Target::Path(path)) => {
+                sinks.directory(&path).map(|sink| Box::new(sink) as Box<dyn sink::ResultSink>)
+            }
+            Some(result_sink::Target::S3(location)) => sinks.s3(&location.bucket, &location.prefix),
+            None => return Err(ErrorReason::InvalidRequest.status("sink needs a path or an S3 location")),
+        };
+        opened.map(|sink| Some(sinks.wrap(sink))).map_err(|e| match e {
+            SinkError::NotAllowed(message) => ErrorReason::PolicyViolation.status(message),
+            SinkError::Invalid(message) => ErrorReason::InvalidRequest.status(message),
+        })
+    }
+
+    // Check the circuit against the first record's inputs, so a malformed one fails here
+    // rather than once per record
+    fn validate_map_circuit(
+        &self,
+        server_key_id: &str,
+        map: &MapCircuit,
+        first: &MapInput,
+    ) -> Result<(), Status> {
+        self.check_operations_allowed(server_key_id, map.circuit.gates.iter().map(|gate| gate.operation))?;
+        let values = first.ids.iter().filter_map(|id| self.load_value(id)).collect::<Vec<_>>();
+        if values.len() < first.ids.len() {
+            return Ok(());
+        }
+        let input_types: Vec<_> = values.iter().chain(&map.operands).map(Value::value_type).collect();
+        map.circuit.validate(&input_types).map_err(circuit_status)?;
+        self.check_booleans_allowed(server_key_id, map.circuit.uses_booleans(&input_types))
+    }
+
+    // Start a map job in the background and return its status before any record is done.
+    // The job holds one evaluation slot, and runs its records in parallel within it; the
+    // callback, if any, is notified once the slot is released.
+    async fn start_map_job(
+        &self,
+        usage: UsageTag,
+        server_key: Arc<ServerKey>,
+        map: MapCircuit,
+        records: Vec<MapInput>,
+        callback: Option<String>,
+        checkpoint: Option<Arc<Checkpoint>>,
+    ) -> Result<(String, MapJobStatus), Status> {
+        let Ok(permit) = self.admission.admit_as(&usage.tenant, usage.operation).await else {
+            if let Some(run) = map.journal {
+                self.journal.release(&run.tenant, &run.token, run.committed);
+            }
+            return Err(ErrorReason::Overloaded.status("Evaluation queue is full, retry later"));
+        };
+        let (job_id, job) = match &map.journal {
+            Some(run) => (run.job_id.clone(), self.map_jobs.create_as(&run.job_id, records.len())),
+            None => self.map_jobs.create(records.len()),
+        };
+        let status = map_job_status(&job_id, &job.progress());
+        if let Some(sink) = &map.sink {
+            info!("Map job {} writes its results to {}", job_id, sink.describe());
+        }
+
+        let service = self.clone();
+        let id = job_id.clone();
+        tokio::task::spawn_blocking(move || {
+            service.metered(usage, || service.run_map_job(&job, map, &server_key, records, checkpoint));
+            drop(permit);
+            if let Some(url) = callback {
+                let progress = job.progress();
+                let event = JobEvent::new(&id, "map", progress.total, progress.failed());
+                service.webhooks().deliver(&url, &event);
+            }
+        });
+        Ok((job_id, status))
+    }
+
+    // The checkpoint a job asked to save its progress to, if any. The digest identifies the
+    // job, and is only worked out when checkpoints are in use.
+    fn open_checkpoint(
+        &self,
+        options: Option<CheckpointOptions>,
+        digest: impl FnOnce() -> String,
+    ) -> Result<Option<Arc<Checkpoint>>, Status> {
+        let Some(options) = options else {
+            return Ok(None);
+        };
+        if self.checkpoints.dir().is_none() {
+            return Err(ErrorReason::Unsupported.status("Checkpoints are not enabled on this server"));
+        }
+        self.checkpoints
+            .open(&options, digest())
+            .map(|checkpoint| Some(Arc::new(checkpoint)))
+            .map_err(|message| ErrorReason::InvalidRequest.status(message))
+    }
+
+    // The URL a job notifies when it finishes, if the client gave one the server allows
+    pub(crate) fn callback_url(&self, callback: Option<JobCallback>) -> Result<Option<String>, Status> {
+        let Some(callback) = callback else {
+            return Ok(None);
+        };
+        self.webhooks()
+            .check(&callback.url)
+            .map_err(|message| ErrorReason::PolicyViolation.status(message))?;
+        Ok(Some(callback.url))
+    }
+
+    fn free_ciphertexts(&self, ids: &[String]) -> usize {
+        ids.iter().filter(|id| self.ciphertext_store.remove(id)).count()
+    }
+
+    // Free the ciphertexts of a session closed by timeout or memory pressure. Results not
+    // yet fetched outlive it until their lease ends; those kept from earlier sessions whose
+    // lease has ended since are freed along with the rest.
+    fn free_evicted(&self, ids: Vec<String>) -> usize {
+        let mut unpinned = self.leases.evict(ids);
+        unpinned.extend(self.leases.take_unpinned());
+        self.free_ciphertexts(&unpinned)
+    }
+
+    // Pin results stored in a session until the client fetches or releases them,
+    // returning when the lease ends in seconds since the epoch; 0 for results in no
+    // session, which nothing evicts, or with pinning off
+    fn lease_results(&self, session_id: &str, ids: &[&str]) -> u64 {
+        if session_id.is_empty() {
+            return 0;
+        }
+        let expires = self.leases.pin(ids);
+        expires.map_or(0, |expires| {
+            expires.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+        })
+    }
+
+    // Refuse the call unless the policy engine allows it. Policies may query a remote
+    // service, so the decision is made on a blocking thread, and a failed query refuses the
+    // call rather than letting it through. The returned future doesn't borrow the request,
+    // which a streaming call's body can't be shared across threads for.
+    fn authorize<T>(
+        &self,
+        request: &Request<T>,
+        method: &str,
+        key_id: &str,
+    ) -> impl Future<Output = Result<(), Status>> {
+        let policy = self.policy.clone();
+        let input = AuthorizationInput {
+            principal: request_principal(request),
+            tenant: request_tenant(request),
+            method: method.to_string(),
+            key_id: key_id.to_string(),
+        };
+        async move {
+            let Some(policy) = policy else {
+                return Ok(());
+            };
+            let query = input.clone();
+            let decision = tokio::task::spawn_blocking(move || policy.allows(&query))
+                .await
+                .map_err(|e| ErrorReason::Internal.status(format!("Authorization worker failed: {}", e)))?;
+            match decision {
+                Ok(true) => Ok(()),
+                Ok(false) => {
+                    warn!(
+                        "Policy denied {} to principal '{}' of tenant '{}' on key '{}'",
+                        input.method, input.principal, input.tenant, input.key_id
+                    );
+                    let message = format!("{} is not allowed by policy", input.method);
+                    Err(ErrorReason::PermissionDenied.status(message))
+                }
+                Err(e) => {
+                    error!("Authorization policy failed for {}: {}", input.method, e);
+                    Err(ErrorReason::PolicyUnavailable.status("Authorization policy could not be evaluated"))
+                }
+            }
+        }
+    }
+
+    // Swap aliases in the request's key fields for the IDs they name in the caller's
+    // tenant. Runs before authorization, so policies only ever see key IDs.
+    fn resolve_aliases<T: KeyReferences>(&self, request: &mut Request<T>) {
+        let tenant = request_tenant(request);
+        for (role, field) in request.get_mut().key_fields() {
+            if let Some(alias) = self.key_store.alias(&tenant, field) {
+                *field = role.key_id(&alias).to_string();
+            }
+        }
+    }
+
+    // Reject requests naming a session that is closed or has timed out. Every handler
+    // that stores a ciphertext calls this first, so it also enforces the memory limit.
+    fn check_session(&self, session_id: &str) -> Result<(), Status> {
+        self.reap_expired_sessions();
+        if !session_id.is_empty() && !self.sessions.touch(session_id) {
+            return Err(ErrorReason::SessionNotFound.status("Session not found"));
+        }
+        self.check_memory()
+    }
+
+    // Reject operations the key pair's policy doesn't list
+    fn check_operations_allowed(
+        &self,
+        key_id: &str,
+        operations: impl IntoIterator<Item = Operation>,
+    ) -> Result<(), Status> {
+        let policy = self.key_store.policy(key_id);
+        for operation in operations.into_iter().map(operation_type) {
+            if !policy.allows_operation(operation as i32) {
+                return Err(ErrorReason::PolicyViolation.status(format!(
+                    "{} is not permitted under this key",
+                    operation.as_str_name()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    // Reject work touching a boolean under a key pair whose policy denies them
+    fn check_booleans_allowed(&self, key_id: &str, uses_booleans: bool) -> Result<(), Status> {
+        if uses_booleans && self.key_store.policy(key_id).deny_booleans {
+            return Err(ErrorReason::PolicyViolation.status("Booleans are not permitted under this key"));
+        }
+        Ok(())
+    }
+
+    // Refuse to reveal a value computed from fewer distinct inputs than the server's
+    // minimum. An unknown ID passes, since decrypting it fails anyway.
+    fn check_aggregation(&self, provenance: Option<&Provenance>) -> Result<(), Status> {
+        let min_inputs = self.privacy.config().min_inputs;
+        match provenance {
+            // A value computed from no stored ciphertexts at all, like an empty counter, is public
+            Some(provenance) if provenance.inputs() > 0 && provenance.inputs() < min_inputs => {
+                Err(ErrorReason::PolicyViolation.status(format!(
+                    "Value was computed from {} distinct inputs; at least {} are needed to decrypt it",
+                    provenance.inputs(),
+                    min_inputs
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Refuse a call that returns decrypted values without noise when the server requires
+    // it, since they would let an exact integer out, if need be a bit at a time
+    fn check_exact_decryption(&self, method: &str) -> Result<(), Status> {
+        if self.privacy.config().require_noise {
+            return Err(ErrorReason::PolicyViolation.status(format!(
+                "{} returns exact values, and this server only decrypts integers with privacy noise",
+                method
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_stored_aggregation(&self, id: &str) -> Result<(), Status> {
+        self.check_aggregation(self.ciphertext_store.provenance(id).as_ref())
+    }
+
+    // Derivation of each circuit output, from the stored inputs it depends on
+    fn circuit_derivations(
+        &self,
+        operation: &str,
+        circuit: &Circuit,
+        input_ids: &[String],
+    ) -> Vec<Derivation> {
+        circuit
+            .output_inputs()
+            .iter()
+            .enumerate()
+            .map(|(output, inputs)| {
+                self.inputs_derivation(operation, format!("output {}", output), inputs, input_ids)
+            })
+            .collect()
+    }
+
+    fn inputs_derivation(
+        &self,
+        operation: &str,
+        detail: String,
+        inputs: &BTreeSet<usize>,
+        input_ids: &[String],
+    ) -> Derivation {
+        let ids = inputs.iter().filter_map(|&index| input_ids.get(index).map(String::as_str));
+        Derivation::new(operation, detail, self.ciphertext_store.parents(ids))
+    }
+
+    fn memory_used(&self) -> u64 {
+        self.key_store.memory_bytes() + self.ciphertext_store.memory_bytes()
+    }
+
+    // Turn away work that would store more once the stores reach the memory limit. Under
+    // the evict-sessions policy the longest-idle sessions are closed first; the caller's
+    // own session was just touched, so it goes last. Results not yet fetched survive
+    // their session, so they are the last thing freed.
+    fn check_memory(&self) -> Result<(), Status> {
+        let Some(limit) = self.memory.limit() else {
+            return Ok(());
+        };
+        while self.memory_used() >= limit.max_bytes {
+            if limit.policy == MemoryPolicy::EvictSessions {
+                if let Some((session_id, ciphertext_ids)) = self.sessions.take_least_recently_used() {
+                    let freed = self.free_evicted(ciphertext_ids);
+                    self.memory.record_eviction();
+                    warn!(
+                        "Memory limit reached; closed session {} and freed {} ciphertexts",
+                        session_id, freed
+                    );
+                    continue;
+                }
+            }
+            self.memory.record_rejection();
+            return Err(ErrorReason::Overloaded.status(format!(
+                "Server memory limit of {} bytes reached, retry later",
+                limit.max_bytes
+            )));
+        }
+        Ok(())
+    }
+
+    // A session closed, reaped or evicted while the work ran would never free the
+    // ciphertext, so it is freed here and the request fails as if the session were gone
+    fn track_in_session(&self, session_id: &str, ciphertext_id: &str) -> Result<(), Status> {
+        if !session_id.is_empty() && !self.sessions.track(session_id, ciphertext_id) {
+            self.ciphertext_store.remove(ciphertext_id);
+            return Err(ErrorReason::SessionNotFound.status("Session not found"));
+        }
+        Ok(())
+    }
+
+    // Checked before anything is stored, so a subject that can't be tagged fails the call
+    fn check_subject(&self, subject_id: &str) -> Result<(), Status> {
+        check_subject_id(subject_id).map_err(|e| ErrorReason::InvalidRequest.status(e.to_string()))
+    }
+
+    fn tag_subject(&self, subject_id: &str, ciphertext_id: &str) {
+        if !subject_id.is_empty() {
+            self.ciphertext_store.set_subject(ciphertext_id, subject_id);
+        }
+    }
+
+    fn ciphertext_fingerprint(&self, id: &str) -> String {
+        self.ciphertext_store.get_fingerprint(id).unwrap_or_default()
+    }
+
+    fn load_value(&self, id: &str) -> Option<Value> {
+        match self.ciphertext_store.get(id)? {
+            Ciphertext::Boolean(ciphertext) => Some(Value::Boolean(ciphertext)),
+            Ciphertext::Integer(ciphertext) => Some(Value::Integer(ciphertext)),
+            Ciphertext::Matrix(_)
+            | Ciphertext::RealVector(_)
+            | Ciphertext::IntegerBatch(_)
+            | Ciphertext::Timestamp(_) => None,
+        }
+    }
+
+    fn load_inputs(&self, ids: &[String]) -> Result<Vec<Value>, Status> {
+        ids.iter()
+            .map(|id| {
+                self.load_value(id).ok_or_else(|| {
+                    ErrorReason::CiphertextNotFound.status(format!("Circuit input {} not found", id))
+                })
+            })
+            .collect()
+    }
+
+    // Elements stay shared with the store; callers that rewrite them in place make copies
+    fn load_integer_vector(&self, ids: &[String]) -> Result<Vec<Arc<FheUint8>>, Status> {
+        if ids.len() > MAX_VECTOR_LENGTH {
+            return Err(ErrorReason::LimitExceeded.status(format!(
+                "Vector has {} elements, the limit is {}",
+                ids.len(),
+                MAX_VECTOR_LENGTH
+            )));
+        }
+
+        ids.iter()
+            .map(|id| self.load_integer(id, &format!("Vector element {}", id)))
+            .collect()
+    }
+
+    fn load_matrix(&self, id: &str) -> Result<Arc<EncryptedMatrix>, Status> {
+        self.ciphertext_store
+            .get_matrix(id)
+            .ok_or_else(|| self.lookup_error(id, &format!("Matrix {}", id), CiphertextKind::Matrix))
+    }
+
+    fn load_timestamp(&self, id: &str) -> Result<Arc<EncryptedTimestamp>, Status> {
+        self.ciphertext_store
+            .get_timestamp(id)
+            .ok_or_else(|| self.lookup_error(id, &format!("Timestamp {}", id), CiphertextKind::Timestamp))
+    }
+
+    fn load_boolean(&self, id: &str, description: &str) -> Result<Arc<FheBool>, Status> {
+        self.ciphertext_store
+            .get_boolean(id)
+            .ok_or_else(|| self.lookup_error(id, description, CiphertextKind::Boolean))
+    }
+
+    fn load_integer(&self, id: &str, description: &str) -> Result<Arc<FheUint8>, Status> {
+        self.ciphertext_store
+            .get_integer(id)
+            .ok_or_else(|| self.lookup_error(id, description, CiphertextKind::Integer))
+    }
+
+    // Why a typed lookup missed: the ID is unknown, or it holds a different kind of value
+    fn lookup_error(&self, id: &str, description: &str, expected: CiphertextKind) -> Status {
+        match self.ciphertext_store.kind(id) {
+            Some(found) => ErrorReason::TypeMismatch.status(format!(
+                "{}: type mismatch: expected {}, found {}",
+                description,
+                expected.type_name(),
+                found.type_name()
+            )),
+            None => ErrorReason::CiphertextNotFound.status(format!("{} not found", description)),
+        }
+    }
+
+    fn load_counter(&self, id: &str) -> Result<Arc<Counter>, Status> {
+        self.counters
+            .get(id)
+            .ok_or_else(|| ErrorReason::CounterNotFound.status("Counter not found"))
+    }
+
+    fn load_aggregation(&self, id: &str) -> Result<Arc<Aggregation>, Status> {
+        self.aggregations
+            .get(id)
+            .ok_or_else(|| ErrorReason::AggregationNotFound.status("Aggregation not found"))
+    }
+
+    fn load_bloom_filter(&self, id: &str) -> Result<Arc<BloomFilter>, Status> {
+        self.bloom_filters
+            .get(id)
+            .ok_or_else(|| ErrorReason::BloomFilterNotFound.status("Bloom filter not found"))
+    }
+
+    fn load_election(&self, id: &str) -> Result<Arc<Election>, Status> {
+        self.elections
+            .get(id)
+            .ok_or_else(|| ErrorReason::ElectionNotFound.status("Election not found"))
+    }
+
+    // A derivation is given for a value computed from others, and None for one encrypted as it is
+    fn store_matrix(
+        &self,
+        matrix: EncryptedMatrix,
+        session_id: &str,
+        derivation: Option<Derivation>,
+    ) -> Result<MatrixResponse, Status> {
+        let (rows, cols) = (matrix.rows() as u32, matrix.cols() as u32);
+        let matrix_id = self.ciphertext_store.store_matrix(matrix);
+        if let Some(derivation) = derivation {
+            self.ciphertext_store.set_derivation(&matrix_id, derivation);
+        }
+        self.track_in_session(session_id, &matrix_id)?;
+        Ok(MatrixResponse {
+            fingerprint: self.ciphertext_fingerprint(&matrix_id),
+            matrix_id,
+            rows,
+            cols,
+        })
+    }
+
+    fn store_timestamp(
+        &self,
+        timestamp: EncryptedTimestamp,
+        session_id: &str,
+        derivation: Option<Derivation>,
+    ) -> Result<EncryptedDataResponse, Status> {
+        let encrypted_data_id = self.ciphertext_store.store(timestamp);
+        if let Some(derivation) = derivation {
+            self.ciphertext_store.set_derivation(&encrypted_data_id, derivation);
+        }
+        self.track_in_session(session_id, &encrypted_data_id)?;
+        Ok(EncryptedDataResponse {
+            fingerprint: self.ciphertext_fingerprint(&encrypted_data_id),
+            encrypted_data_id,
+            serialized_data: vec![],
+        })
+    }
+
+    // Run homomorphic work on a blocking thread so it doesn't stall the async runtime.
+    // The work first waits for a worker slot, failing with RESOURCE_EXHAUSTED when the
+    // queue is full, and keeps the slot until it returns. If the request future is dropped
+    // (client disconnect or server-side timeout) the cancellation fires, and the work stops
+    // at its next check instead of running to completion.
+    // Compute time is billed to the usage tag whether or not the work succeeds
+    async fn run_blocking<T, F>(
+        &self,
+        usage: UsageTag,
+        cancellation: &Cancellation,
+        work: F,
+    ) -> Result<T, Status>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T, Status> + Send + 'static,
+    {
+        let permit = self.admission.admit_as(&usage.tenant, usage.operation).await.map_err(|_| {
+            ErrorReason::Overloaded.status("Evaluation queue is full, retry later")
+        })?;
+
+        let guard = cancellation.cancel_on_drop();
+        let ledger = self.usage.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            let started = Instant::now();
+            let result = work();
+            ledger.record(&usage, started.elapsed());
+            result
+        })
+        .await
+        .map_err(|e| ErrorReason::Internal.status(format!("Evaluation worker failed: {}", e)))?;
+        guard.disarm();
+        result
+    }
+
+    // Run inline work, billing its compute time to the usage tag
+    fn metered<T>(&self, usage: UsageTag, work: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let result = work();
+        self.usage.record(&usage, started.elapsed());
+        result
+    }
+
+    async fn run_circuit(
+        &self,
+        circuit: Circuit,
+        server_key: Arc<ServerKey>,
+        inputs: Vec<Value>,
+        options: EvaluationOptions,
+        usage: UsageTag,
+    ) -> Result<EvaluationResult, Status> {
+        // Reject malformed circuits before doing any homomorphic work
+        let input_types: Vec<_> = inputs.iter().map(Value::value_type).collect();
+        circuit
+            .validate(&input_types)
+            .map_err(circuit_status)?;
+        // Usage is always tagged with the server key the circuit runs under
+        self.check_operations_allowed(&usage.key_id, circuit.gates.iter().map(|gate| gate.operation))?;
+        self.check_booleans_allowed(&usage.key_id, circuit.uses_booleans(&input_types))?;
+
+        // With measured costs, refuse work that can't finish before its deadline even under
+        // the cheapest parameter set, rather than queueing it only for it to time out
+        let cancellation = options.cancellation.clone();
+        let cost_model = self.cost_model();
+        if let (Some(deadline), true) = (cancellation.deadline(), cost_model.is_calibrated()) {
+            let queue_wait = self.admission.estimated_wait();
+            let latency = cost_model.fastest_latency(&circuit);
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if queue_wait + latency > remaining {
+                return Err(ErrorReason::DeadlineExceeded.status(format!(
+                    "Expected to take {:.2}s, {:.2}s of it waiting for a worker, but the deadline is {:.2}s \
+                     away; retry with a deadline of at least {:.0}s",
+                    (queue_wait + latency).as_secs_f64(),
+                    queue_wait.as_secs_f64(),
+                    remaining.as_secs_f64(),
+                    cost::suggested_deadline(latency, queue_wait).as_secs_f64().ceil()
+                )));
+            }
+        }
+
+        self.run_blocking(usage, &cancellation, move || {
+            // The high-level tfhe API evaluates against a thread-local server key
+            tfhe::set_server_key((*server_key).clone());
+
+            circuit.evaluate(&server_key, &inputs, options).map_err(|e| {
+                evaluation_status(e, |e| {
+                    ErrorReason::Internal.status(format!("Circuit evaluation failed: {}", e))
+                })
+            })
+        })
+        .await
+    }
+
+    // Compile an EvaluationRequest expression to a circuit and run it as EvaluateCircuit would
+    async fn evaluate_expression(
+        &self,
+        req: &EvaluationRequest,
+        server_key: Arc<ServerKey>,
+        cancellation: Cancellation,
+        usage: UsageTag,
+    ) -> Result<(Ciphertext, Derivation), Status> {
+        let mut expression = expression::parse(&req.expression)
+            .map_err(|e| ErrorReason::InvalidRequest.status(format!("Invalid expression: {}", e)))?;
+        for gate in &mut expression.circuit.gates {
+            gate.operation = with_overflow(gate.operation, req.overflow())?;
+        }
+        let ids = expression
+            .variables
+            .iter()
+            .map(|name| {
+                req.variables.get(name).cloned().ok_or_else(|| {
+                    ErrorReason::InvalidRequest.status(format!("Variable {} has no ciphertext ID", name))
+                })
+            })
+            .collect::<Result<Vec<_>, Status>>()?;
+        let inputs = expression
+            .variables
+            .iter()
+            .zip(&ids)
+            .map(|(name, id)| {
+                self.load_value(id).ok_or_else(|| {
+                    ErrorReason::CiphertextNotFound.status(format!("Variable {} ({}) not found", name, id))
+                })
+            })
+            .collect::<Result<Vec<_>, Status>>()?;
+        let parents = self.ciphertext_store.parents(ids.iter().map(String::as_str));
+        let derivation = Derivation::new("EvaluateOperation", req.expression.clone(), parents);
+
+        let options = EvaluationOptions {
+            keep_intermediates: false,
+            cancellation,
+            checkpoint: None,
+        };
+        let mut result = self.run_circuit(expression.circuit, server_key, inputs, options, usage).await?;
+        let result = result
+            .outputs
+            .pop()
+            .map(Ciphertext::from)
+            .ok_or_else(|| ErrorReason::Internal.status("Expression produced no result"))?;
+        Ok((result, derivation))
+    }
+
+    // Store an EvaluateOperation result under a new ID, or over the operand the client named,
+    // and its overflow bit if one was asked for. Both were computed from the same inputs.
+    fn evaluation_response(
+        &self,
+        req: EvaluationRequest,
+        result: Ciphertext,
+        overflowed: Option<FheBool>,
+        derivation: Derivation,
+    ) -> Result<Response<EvaluationResponse>, Status> {
+        let result_id = if req.overwrite_id.is_empty() {
+            let result_id = self.ciphertext_store.store(result);
+            self.track_in_session(&req.session_id, &result_id)?;
+            result_id
+        } else {
+            if let Some(kind) = self.ciphertext_store.kind(&req.overwrite_id) {
+                if kind != result.kind() {
+                    return Err(ErrorReason::TypeMismatch.status(format!(
+                        "Result is {} but overwrite_id holds {}",
+                        result.kind().type_name(),
+                        kind.type_name()
+                    )));
+                }
+            }
+            // The overwritten ID keeps its owner; the operand was loaded above, so this
+            // only fails if it was deleted while the operation ran
+            if !self.ciphertext_store.replace(&req.overwrite_id, result) {
+                return Err(ErrorReason::CiphertextNotFound
+                    .status(format!("Operand {} was removed during evaluation", req.overwrite_id)));
+            }
+            req.overwrite_id
+        };
+        let overflow_id = match overflowed {
+            Some(overflowed) => {
+                let overflow_id = self.ciphertext_store.store_boolean(overflowed);
+                let overflow = derivation.with_detail(format!("{} overflow", derivation.detail));
+                self.ciphertext_store.set_derivation(&overflow_id, overflow);
+                self.track_in_session(&req.session_id, &overflow_id)?;
+                overflow_id
+            }
+            None => String::new(),
+        };
+        self.ciphertext_store.set_derivation(&result_id, derivation);
+
+        let mut leased = vec![result_id.as_str()];
+        if !overflow_id.is_empty() {
+            leased.push(&overflow_id);
+        }
+        let lease_expires_unix_seconds = self.lease_results(&req.session_id, &leased);
+
+        Ok(Response::new(EvaluationResponse {
+            result_fingerprint: self.ciphertext_fingerprint(&result_id),
+            result_id,
+            serialized_result: vec![],
+            overflow_id,
+            lease_expires_unix_seconds,
+        }))
+    }
+
+    fn store_value(&self, value: Value, session_id: &str) -> Result<String, Status> {
+        let id = match value {
+            Value::Boolean(ct) => self.ciphertext_store.store_boolean(ct),
+            Value::Integer(ct) => self.ciphertext_store.store_integer(ct),
+        };
+        self.track_in_session(session_id, &id)?;
+        Ok(id)
+    }
+
+    // Store a value computed from others, recording what it was computed from
+    fn store_derived(
+        &self,
+        value: Value,
+        session_id: &str,
+        derivation: Derivation,
+    ) -> Result<String, Status> {
+        let id = self.store_value(value, session_id)?;
+        self.ciphertext_store.set_derivation(&id, derivation);
+        Ok(id)
+    }
+}
+
+// Operations with a working implementation; comparisons are still missing
+const SUPPORTED_OPERATIONS: [OperationType; 12] = [
+    OperationType::And,
+    OperationType::Or,
+    OperationType::Xor,
+    OperationType::Not,
+    OperationType::Add,
+    OperationType::Subtract,
+    OperationType::Multiply,
+    OperationType::IsZero,
+    OperationType::IsNonZero,
+    OperationType::AbsDiff,
+    OperationType::SaturatingAdd,
+    OperationType::SaturatingSub,
+];
+
+// Every integer is currently encrypted as a FheUint8
+const INTEGER_WIDTHS: [u32; 1] = [8];
+
+// Largest circuit EvaluateCircuit will accept
+pub const MAX_CIRCUIT_GATES: usize = 10_000;
+
+// Longest vector the vector operations will accept
+pub const MAX_VECTOR_LENGTH: usize = 1024;
+
+// Most elements an encrypted matrix may hold
+pub const MAX_MATRIX_ELEMENTS: usize = 64 * 64;
+
+// Most values an encrypted real vector may hold: one per CKKS slot
+pub const MAX_REAL_VECTOR_LENGTH: usize = ckks::SLOTS;
+
+// Most values an encrypted integer batch may hold: one per BGV slot
+pub const MAX_INTEGER_BATCH_LENGTH: usize = bgv::SLOTS;
+
+// Largest message the server decodes or sends unless configured otherwise (see
+// service::transport). Well above tonic's 4 MiB default, which serialized ciphertexts
+// outgrow quickly.
+pub const MAX_MESSAGE_BYTES: usize = 64 * 1024 * 1024;
+
+// Most records one IngestEncryptedRecords stream may carry, which keeps its summary of
+// labels and IDs well inside the response size limit
+pub const MAX_INGEST_RECORDS: usize = 1_000_000;
+
+// Records ingested are verified and stored this many at a time on a blocking thread
+pub const INGEST_BATCH_SIZE: usize = 256;
+
+// Most records one prefix may select in MapOperation, JoinOperation or ReduceOperation,
+// which keeps a map job's status inside the response size limit like an ingest summary
+pub const MAX_MAP_RECORDS: usize = MAX_INGEST_RECORDS;
+
+// Nodes GetLineage returns when the request doesn't say, and the most it ever returns
+pub const DEFAULT_LINEAGE_NODES: usize = 1000;
+pub const MAX_LINEAGE_NODES: usize = 10_000;
+
+// Values a QueryCiphertexts page holds when the request doesn't say, and the most it holds
+pub const DEFAULT_QUERY_PAGE_SIZE: usize = 100;
+pub const MAX_QUERY_PAGE_SIZE: usize = 1000;
+
+// What a map job applies to each record, and where its results go: a sink if the
+// client named one, otherwise the store under the session
+struct MapCircuit {
+    circuit: Circuit,
+    operands: Vec<Value>,
+    // Taken when the job starts, like the operands themselves
+    operand_parents: Vec<Parent>,
+    // The call that started the job, which its results name as their operation
+    operation: &'static str,
+    session_id: String,
+    sink: Option<Box<dyn sink::ResultSink>>,
+    // Set for a job given a dedup token, which commits each result to its journal
+    journal: Option<JournaledRun>,
+}
+
+// A map job started with a dedup token: where its steps are journaled, and for one carried
+// on after a restart, the records it committed before
+struct JournaledRun {
+    tenant: String,
+    token: String,
+    job_id: String,
+    log: Arc<JobLog>,
+    committed: Option<Vec<CommittedRecord>>,
+}
+
+// What a map request's dedup token leads to
+enum MapJobStart {
+    // The job the token already started, as it stands
+    Started(MapJobStatus),
+    Run(Option<JournaledRun>),
+}
+
+// One record of a map job: the ciphertexts it feeds the circuit, one for MapOperation
+// and a matched pair for JoinOperation, and the label its result gets
+struct MapInput {
+    label: String,
+    result_label: String,
+    ids: Vec<String>,
+}
+
+// Takes the raw field, since prost's getter reads a value it doesn't know as AND
+fn circuit_operation(operation: i32) -> Result<Operation, Status> {
+    let operation = OperationType::try_from(operation)
+        .map_err(|_| ErrorReason::InvalidRequest.status(format!("Unknown operation {}", operation)))?;
+    match operation {
+        OperationType::And => Ok(Operation::And),
+        OperationType::Or => Ok(Operation::Or),
+        OperationType::Xor => Ok(Operation::Xor),
+        OperationType::Not => Ok(Operation::Not),
+        OperationType::Add => Ok(Operation::Add),
+        OperationType::Subtract => Ok(Operation::Subtract),
+        OperationType::Multiply => Ok(Operation::Multiply),
+        OperationType::IsZero => Ok(Operation::IsZero),
+        OperationType::IsNonZero => Ok(Operation::IsNonZero),
+        OperationType::AbsDiff => Ok(Operation::AbsDiff),
+        OperationType::SaturatingAdd => Ok(Operation::SaturatingAdd),
+        OperationType::SaturatingSub => Ok(Operation::SaturatingSub),
+        OperationType::GreaterThan | OperationType::LessThan | OperationType::Equal => {
+            Err(ErrorReason::Unsupported.status("Comparison operations not implemented in this demo"))
+        }
+    }
+}
+
+// Only the 8-bit unsigned integers the service has are allowed
+fn key_policy(policy: Option<&TypePolicy>) -> Result<KeyPolicy, Status> {
+    let Some(policy) = policy else {
+        return Ok(KeyPolicy::default());
+    };
+    if policy.allow_signed {
+        return Err(ErrorReason::Unsupported.status("Signed integers are not supported"));
+    }
+    if policy.integer_bits != 0 && !INTEGER_WIDTHS.contains(&policy.integer_bits) {
+        return Err(ErrorReason::Unsupported.status(format!(
+            "{}-bit integers are not supported; supported widths are {:?}",
+            policy.integer_bits, INTEGER_WIDTHS
+        )));
+    }
+    Ok(KeyPolicy {
+        integer_bits: policy.integer_bits,
+        deny_booleans: policy.deny_booleans,
+        allowed_operations: operation_mask(&policy.allowed_operations)?,
+    })
+}
+
+fn privacy_noise(noise: &PrivacyNoise) -> Result<Noise, Status> {
+    let noise = Noise {
+        mechanism: match noise.mechanism() {
+            NoiseMechanism::Laplace => privacy::Mechanism::Laplace,
+            NoiseMechanism::Gaussian => privacy::Mechanism::Gaussian,
+        },
+        epsilon: noise.epsilon,
+        delta: noise.delta,
+        sensitivity: noise.sensitivity,
+    };
+    noise.validate().map_err(privacy_status)?;
+    Ok(noise)
+}
+
+fn privacy_status(error: PrivacyError) -> Status {
+    match error {
+        PrivacyError::Invalid(message) => ErrorReason::InvalidRequest.status(message),
+        PrivacyError::Exhausted { .. } => ErrorReason::PrivacyBudgetExhausted.status(error.to_string()),
+        PrivacyError::Storage(_) => {
+            error!("{}", error);
+            ErrorReason::Internal.status("Privacy budget could not be charged")
+        }
+    }
+}
+
+// KeyPolicy's bitmask for a list of OperationType values; an empty list allows all
+pub(crate) fn operation_mask(operations: &[i32]) -> Result<u64, Status> {
+    operations.iter().try_fold(0u64, |mask, &operation| {
+        OperationType::try_from(operation)
+            .map(|_| mask | 1 << operation)
+            .map_err(|_| ErrorReason::InvalidRequest.status(format!("Unknown operation {}", operation)))
+    })
+}
+
+// The OperationType values a KeyPolicy bitmask allows, empty when it allows them all
+pub(crate) fn mask_operations(mask: u64) -> Vec<i32> {
+    (0..64).filter(|operation| mask & (1u64 << operation) != 0).collect()
+}
+
+pub(crate) fn parameter_set_name(parameter_set: i32) -> Result<&'static str, Status> {
+    match parameter_set {
+        0 => Ok("DEFAULT"),
+        1 => Ok("FAST"),
+        2 => Ok("SECURE"),
+        _ => Err(ErrorReason::InvalidRequest.status("Invalid parameter set")),
+    }
+}
+
+fn real_vector_operation(operation: RealVectorOperation, rotation: i64) -> backend::SlotOperation {
+    match operation {
+        RealVectorOperation::RealAdd => backend::SlotOperation::Add,
+        RealVectorOperation::RealMultiply => backend::SlotOperation::Multiply,
+        RealVectorOperation::RealRotate => backend::SlotOperation::Rotate(rotation),
+    }
+}
+
+fn integer_batch_operation(operation: IntegerBatchOperation, rotation: i64) -> backend::SlotOperation {
+    match operation {
+        IntegerBatchOperation::BatchAdd => backend::SlotOperation::Add,
+        IntegerBatchOperation::BatchMultiply => backend::SlotOperation::Multiply,
+        IntegerBatchOperation::BatchRotate => backend::SlotOperation::Rotate(rotation),
+    }
+}
+
+pub(crate) fn operation_type(operation: Operation) -> OperationType {
+    match operation {
+        Operation::And => OperationType::And,
+        Operation::Or => OperationType::Or,
+        Operation::Xor => OperationType::Xor,
+        Operation::Not => OperationType::Not,
+        Operation::Add => OperationType::Add,
+        Operation::Subtract => OperationType::Subtract,
+        Operation::Multiply => OperationType::Multiply,
+        Operation::IsZero => OperationType::IsZero,
+        Operation::IsNonZero => OperationType::IsNonZero,
+        Operation::AbsDiff => OperationType::AbsDiff,
+        Operation::SaturatingAdd => OperationType::SaturatingAdd,
+        Operation::SaturatingSub => OperationType::SaturatingSub,
+    }
+}
+
+// The operation to run in place of one requested under the given overflow behavior.
+// Saturating multiplication would need the full 16-bit product, which isn't offered.
+fn with_overflow(operation: Operation, overflow: OverflowBehavior) -> Result<Operation, Status> {
+    match (overflow, operation) {
+        (OverflowBehavior::Saturate, Operation::Add) => Ok(Operation::SaturatingAdd),
+        (OverflowBehavior::Saturate, Operation::Subtract) => Ok(Operation::SaturatingSub),
+        (OverflowBehavior::Saturate, Operation::Multiply) => {
+            Err(ErrorReason::Unsupported.status("Saturating multiplication is not supported"))
+        }
+        (_, operation) => Ok(operation),
+    }
+}
+
+fn issue_kind(kind: IssueKind) -> CircuitIssueKind {
+    match kind {
+        IssueKind::NoOutputs => CircuitIssueKind::NoOutputs,
+        IssueKind::MissingInput => CircuitIssueKind::MissingInput,
+        IssueKind::InvalidWire => CircuitIssueKind::InvalidWire,
+        IssueKind::ArityMismatch => CircuitIssueKind::ArityMismatch,
+        IssueKind::TypeMismatch => CircuitIssueKind::TypeMismatch,
+        IssueKind::WidthMismatch => CircuitIssueKind::WidthMismatch,
+    }
+}
+
+// A zero width means the default, as for EncryptInteger
+fn declared_input(input: &DeclaredInput) -> InputSpec {
+    match input.ciphertext_type() {
+        CiphertextType::Boolean => InputSpec::of(ValueType::Boolean),
+        CiphertextType::Integer => InputSpec {
+            value_type: ValueType::Integer,
+            bits: if input.num_bits == 0 { 8 } else { input.num_bits },
+        },
+        // Too wide for any circuit, which validation reports
+        CiphertextType::Timestamp => InputSpec {
+            value_type: ValueType::Integer,
+            bits: 32,
+        },
+    }
+}
+
+fn circuit_wire(wire: &CircuitWire) -> Result<Wire, Status> {
+    match wire.source {
+        Some(circuit_wire::Source::Input(index)) => Ok(Wire::Input(index as usize)),
+        Some(circuit_wire::Source::Gate(index)) => Ok(Wire::Gate(index as usize)),
+        None => Err(ErrorReason::InvalidCircuit.status("Circuit wire has no source")),
+    }
+}
+
+fn build_circuit(gates: &[CircuitGate], outputs: &[CircuitWire]) -> Result<Circuit, Status> {
+    if gates.len() > MAX_CIRCUIT_GATES {
+        return Err(ErrorReason::LimitExceeded.status(format!(
+            "Circuit has {} gates, the limit is {}",
+            gates.len(),
+            MAX_CIRCUIT_GATES
+        )));
+    }
+
+    let gates = gates
+        .iter()
+        .map(|gate| {
+            Ok(Gate {
+                operation: circuit_operation(gate.operation)?,
+                inputs: gate.operands.iter().map(circuit_wire).collect::<Result<_, Status>>()?,
+            })
+        })
+        .collect::<Result<Vec<_>, Status>>()?;
+    Ok(Circuit {
+        gates,
+        outputs: outputs.iter().map(circuit_wire).collect::<Result<_, Status>>()?,
+    })
+}
+
+// Every integer is encrypted as a FheUint8, so plaintext operands must fit in one
+fn plaintext_integer(value: i64) -> Result<u8, Status> {
+    u8::try_from(value).map_err(|_| ErrorReason::ValueOutOfRange.status("Value out of range for uint8"))
+}
+
+fn plaintext_timestamp(value: i64) -> Result<u32, Status> {
+    u32::try_from(value).map_err(|_| {
+        ErrorReason::ValueOutOfRange.status("Timestamp must be from 0 to 2^32 - 1 units since the epoch")
+    })
+}
+
+fn map_job_status(job_id: &str, progress: &MapProgress) -> MapJobStatus {
+    let failed = progress.failed();
+    MapJobStatus {
+        job_id: job_id.to_string(),
+        done: progress.done,
+        total: progress.total as u32,
+        mapped: (progress.records.len() - failed) as u32,
+        failed: failed as u32,
+        resumed: progress.resumed as u32,
+        records: progress
+            .records
+            .iter()
+            .map(|record| {
+                let mut mapped = MappedRecord {
+                    label: record.label.clone(),
+                    result_label: record.result_label.clone(),
+                    ..Default::default()
+                };
+                match &record.result {
+                    Ok(MapOutput::Stored(result_id)) => mapped.result_id = result_id.clone(),
+                    Ok(MapOutput::Written(location)) => mapped.sink_object = location.clone(),
+                    Err(error) => mapped.error = error.clone(),
+                }
+                mapped
+            })
+            .collect(),
+    }
+}
+
+// A record a journaled job committed, as its job reports it
+fn committed_record(record: &CommittedRecord) -> dataset::MappedRecord {
+    let output = match &record.output {
+        CommittedOutput::Stored { id, .. } => MapOutput::Stored(id.clone()),
+        CommittedOutput::Written(location) => MapOutput::Written(location.clone()),
+    };
+    dataset::MappedRecord {
+        label: record.label.clone(),
+        result_label: record.result_label.clone(),
+        result: Ok(output),
+    }
+}
+
+// Record the results a job cut short by a restart committed before it, which the replay
+// stored again, and take those records out of the ones left to run
+fn resume_journaled(job: &MapJob, committed: &[CommittedRecord], records: &mut Vec<MapInput>) {
+    let committed: HashMap<&str, &CommittedRecord> =
+        committed.iter().map(|record| (record.label.as_str(), record)).collect();
+    records.retain(|record| {
+        let Some(done) = committed
+            .get(record.label.as_str())
+            .filter(|done| done.result_label == record.result_label)
+        else {
+            return true;
+        };
+        job.resume(committed_record(done));
+        false
+    });
+}
+
+// Journal where a journaled job wrote a result. The write itself can't be undone, but a
+// rerun writes the same name, so a crash before this only costs writing it again.
+fn journal_written(map: &MapCircuit, record: &MapInput, location: String) -> anyhow::Result<String> {
+    if let Some(run) = &map.journal {
+        run.log.append(&JournalEntry::Committed(CommittedRecord {
+            label: record.label.clone(),
+            result_label: record.result_label.clone(),
+            output: CommittedOutput::Written(location.clone()),
+        }))?;
+    }
+    Ok(location)
+}
+
+// Check an uploaded ciphertext against its fingerprint and store it under a new ID
+fn import_serialized(
+    store: &CiphertextStore,
+    ciphertext_type: CiphertextType,
+    bytes: &[u8],
+    fingerprint: &str,
+) -> Result<String, Status> {
+    if fingerprint.is_empty() {
+        return Err(ErrorReason::InvalidRequest.status("Fingerprint is required for import"));
+    }
+
+    // Detect corruption in transit before touching the bytes
+    verify_fingerprint(bytes, fingerprint)
+        .map_err(|e| ErrorReason::FingerprintMismatch.status(e.to_string()))?;
+
+    match ciphertext_type {
+        CiphertextType::Boolean => store.import_boolean(bytes),
+        CiphertextType::Integer => store.import_integer(bytes),
+        CiphertextType::Timestamp => store.import_timestamp(bytes),
+    }
+    .map_err(|e| ErrorReason::InvalidRequest.status(e.to_string()))
+}
+
+fn time_unit(unit: TimeUnit) -> timestamp::TimeUnit {
+    match unit {
+        TimeUnit::Days => timestamp::TimeUnit::Days,
+        TimeUnit::Seconds => timestamp::TimeUnit::Seconds,
+    }
+}
+
+fn time_unit_to_proto(unit: timestamp::TimeUnit) -> TimeUnit {
+    match unit {
+        timestamp::TimeUnit::Days => TimeUnit::Days,
+        timestamp::TimeUnit::Seconds => TimeUnit::Seconds,
+    }
+}
+
+fn timestamp_comparison(comparison: TimestampComparison) -> Comparison {
+    match comparison {
+        TimestampComparison::Before => Comparison::Before,
+        TimestampComparison::AtOrBefore => Comparison::AtOrBefore,
+        TimestampComparison::At => Comparison::At,
+        TimestampComparison::AtOrAfter => Comparison::AtOrAfter,
+        TimestampComparison::After => Comparison::After,
+    }
+}
+
+fn check_same_unit(a: &EncryptedTimestamp, b: &EncryptedTimestamp) -> Result<(), Status> {
+    if a.unit() != b.unit() {
+        return Err(ErrorReason::TypeMismatch.status(format!(
+            "Timestamps are in different units: {:?} and {:?}",
+            a.unit(),
+            b.unit()
+        )));
+    }
+    Ok(())
+}
+
+fn model_layer(layer: &ModelLayer) -> Result<Layer, Status> {
+    if layer.weights.len() > MAX_MATRIX_ELEMENTS {
+        return Err(ErrorReason::LimitExceeded.status(format!(
+            "Layer has {} weights, the limit is {}",
+            layer.weights.len(),
+            MAX_MATRIX_ELEMENTS
+        )));
+    }
+
+    let plaintexts = |values: &[i64]| {
+        values
+            .iter()
+            .copied()
+            .map(plaintext_integer)
+            .collect::<Result<Vec<_>, Status>>()
+    };
+    let activation = if layer.activation.is_empty() {
+        None
+    } else {
+        Some(plaintexts(&layer.activation)?)
+    };
+
+    Layer::new(
+        layer.outputs as usize,
+        plaintexts(&layer.weights)?,
+        plaintexts(&layer.bias)?,
+        activation,
+    )
+    .map_err(|e| ErrorReason::ShapeMismatch.status(e.to_string()))
+}
+
+// The status for a backend failure; description names the ciphertext for the client
+fn backend_status(error: BackendError, description: &str) -> Status {
+    match error {
+        BackendError::ClientKeyNotFound
+        | BackendError::ServerKeyNotFound
+        | BackendError::ReEncryptionKeyNotFound => {
+            ErrorReason::KeyNotFound.status(error.to_string())
+        }
+        BackendError::CiphertextNotFound(_) => {
+            ErrorReason::CiphertextNotFound.status(format!("{} not found", description))
+        }
+        BackendError::TypeMismatch { .. } | BackendError::KeyPairMismatch(_) => {
+            ErrorReason::TypeMismatch.status(format!("{}: {}", description, error))
+        }
+        BackendError::DepthExhausted => {
+            ErrorReason::LimitExceeded.status(format!("{}: {}", description, error))
+        }
+        BackendError::Other(e) => ErrorReason::Internal.status(e.to_string()),
+    }
+}
+
+fn store_metrics(entries: usize, memory_bytes: u64, locks: LockMetrics) -> StoreMetrics {
+    StoreMetrics {
+        entries: entries as u64,
+        lock_acquisitions_total: locks.acquisitions,
+        lock_contended_total: locks.contended,
+        memory_bytes,
+    }
+}
+
+fn owned_elements(elements: &[Arc<FheUint8>]) -> Vec<FheUint8> {
+    elements.iter().map(|element| FheUint8::clone(element)).collect()
+}
+
+fn encrypt_plaintext(client_key: &ClientKey, plaintext: &PlaintextValue) -> Result<Value, Status> {
+    match plaintext.value {
+        Some(plaintext_value::Value::Boolean(value)) => FheBool::try_encrypt(value, client_key)
+            .map(|ct| Value::Boolean(Arc::new(ct)))
+            .map_err(|e| ErrorReason::Internal.status(format!("Encryption failed: {}", e))),
+        Some(plaintext_value::Value::Integer(value)) => {
+            FheUint8::try_encrypt(plaintext_integer(value)?, client_key)
+                .map(|ct| Value::Integer(Arc::new(ct)))
+                .map_err(|e| ErrorReason::Internal.status(format!("Encryption failed: {}", e)))
+        }
+        None => Err(ErrorReason::InvalidRequest.status("Plaintext input has no value")),
+    }
+}
+
+// Tenant the call is billed to, from the tenant header; empty when the client sent none
+fn alias_status(error: AliasError) -> Status {
+    match error {
+        AliasError::Taken(_) => ErrorReason::AliasTaken.status(error.to_string()),
+        AliasError::Invalid(_) => ErrorReason::InvalidRequest.status(error.to_string()),
+        AliasError::KeyNotFound(_) => ErrorReason::KeyNotFound.status(error.to_string()),
+        AliasError::Storage(_) => ErrorReason::Internal.status(error.to_string()),
+    }
+}
+
+// Seconds since the Unix epoch, by the server's clock
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+fn request_tenant<T>(request: &Request<T>) -> String {
+    request
+        .metadata()
+        .get(TENANT_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string()
+}
+
+// Caller named by the principal header; empty when there is none
+fn request_principal<T>(request: &Request<T>) -> String {
+    request
+        .metadata()
+        .get(PRINCIPAL_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string()
+}
+
+// Deadline of the gRPC call, from the grpc-timeout header the client sends
+fn request_cancellation<T>(request: &Request<T>) -> Cancellation {
+    let timeout = request
+        .metadata()
+        .get("grpc-timeout")
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_grpc_timeout);
+    match timeout {
+        Some(timeout) => Cancellation::with_deadline(Instant::now() + timeout),
+        None => Cancellation::new(),
+    }
+}
+
+// grpc-timeout is an integer of at most 8 digits followed by a unit: H, M, S, m, u or n
+fn parse_grpc_timeout(value: &str) -> Option<Duration> {
+    let (digits, unit) = value.split_at(value.len().checked_sub(1)?);
+    if digits.is_empty() || digits.len() > 8 {
+        return None;
+    }
+    let amount: u64 = digits.parse().ok()?;
+    match unit {
+        "H" => Some(Duration::from_secs(amount * 60 * 60)),
+        "M" => Some(Duration::from_secs(amount * 60)),
+        "S" => Some(Duration::from_secs(amount)),
+        "m" => Some(Duration::from_millis(amount)),
+        "u" => Some(Duration::from_micros(amount)),
+        "n" => Some(Duration::from_nanos(amount)),
+        _ => None,
+    }
+}
+
+// Report a circuit rejected by validation with the reason for its first issue
+fn circuit_status(error: anyhow::Error) -> Status {
+    let reason = match error.downcast_ref::<Issue>().map(|issue| issue.kind) {
+        Some(IssueKind::TypeMismatch) => ErrorReason::TypeMismatch,
+        Some(IssueKind::WidthMismatch) => ErrorReason::WidthMismatch,
+        Some(IssueKind::ArityMismatch) => ErrorReason::ArityMismatch,
+        Some(IssueKind::NoOutputs | IssueKind::MissingInput | IssueKind::InvalidWire) => {
+            ErrorReason::InvalidCircuit
+        }
+        None => ErrorReason::InvalidRequest,
+    };
+    reason.status(error.to_string())
+}
+
+// Map an evaluation error, reporting cancellation with its own status code
+fn evaluation_status(error: anyhow::Error, otherwise: impl FnOnce(anyhow::Error) -> Status) -> Status {
+    match error.downcast_ref::<Cancelled>() {
+        Some(Cancelled::ByCaller) => ErrorReason::Cancelled.status(error.to_string()),
+        Some(Cancelled::DeadlineExceeded) => ErrorReason::DeadlineExceeded.status(error.to_string()),
+        None => otherwise(error),
+    }
+}
+
+fn election_error(error: ElectionError) -> Status {
+    let reason = match error {
+        ElectionError::Closed => ErrorReason::ElectionClosed,
+        ElectionError::Open => ErrorReason::ElectionOpen,
+        ElectionError::Full => ErrorReason::LimitExceeded,
+    };
+    reason.status(error.to_string())
+}
+
+fn election_response(election_id: String, status: ElectionStatus) -> ElectionResponse {
+    ElectionResponse {
+        election_id,
+        num_options: status.options as u32,
+        ballots: status.ballots,
+        closed: status.closed,
+    }
+}
+
+// Serialize whichever kind of ciphertext is stored under the ID, checking it against
+// the fingerprint recorded when it was stored
+fn export_stored(store: &CiphertextStore, id: &str) -> Result<(CiphertextType, Vec<u8>, String), Status> {
+    let ciphertext = store
+        .get(id)
+        .ok_or_else(|| ErrorReason::CiphertextNotFound.status("Encrypted data not found"))?;
+    let ciphertext_type = match ciphertext.kind() {
+        CiphertextKind::Boolean => CiphertextType::Boolean,
+        CiphertextKind::Integer => CiphertextType::Integer,
+        CiphertextKind::Timestamp => CiphertextType::Timestamp,
+        CiphertextKind::Matrix => {
+            return Err(ErrorReason::TypeMismatch
+                .status("Encrypted data is a matrix; stream its elements instead"))
+        }
+        CiphertextKind::RealVector => {
+            return Err(ErrorReason::TypeMismatch.status("Encrypted data is a CKKS real vector"))
+        }
+        CiphertextKind::IntegerBatch => {
+            return Err(ErrorReason::TypeMismatch.status("Encrypted data is a BGV integer batch"))
+        }
+    };
+
+    let (serialized_data, fingerprint) = ciphertext
+        .serialize_with_fingerprint()
+        .map_err(|e| ErrorReason::Internal.status(format!("Failed to serialize ciphertext: {}", e)))?;
+
+    // Refuse to hand out bytes that no longer match what was stored
+    if store.get_fingerprint(id).as_deref() != Some(fingerprint.as_str()) {
+        return Err(ErrorReason::FingerprintMismatch
+            .status("Ciphertext does not match its recorded fingerprint"));
+    }
+
+    Ok((ciphertext_type, serialized_data, fingerprint))
+}
+
+// Elements streamed by StreamCiphertexts: stored ciphertexts looked up one at a time,
+// or the elements of a stored matrix
+enum StreamSource {
+    Ids(Vec<String>),
+    Matrix(Arc<EncryptedMatrix>),
+}
+
+impl StreamSource {
+    fn len(&self) -> usize {
+        match self {
+            StreamSource::Ids(ids) => ids.len(),
+            StreamSource::Matrix(matrix) => matrix.elements().len(),
+        }
+    }
+
+    fn chunk(&self, store: &CiphertextStore, index: usize) -> Result<CiphertextChunk, Status> {
+        let (ciphertext_type, serialized_data, fingerprint) = match self {
+            StreamSource::Ids(ids) => export_stored(store, &ids[index])?,
+            StreamSource::Matrix(matrix) => {
+                let element = &matrix.elements()[index];
+                let (serialized_data, fingerprint) =
+                    canonical::encode_with_fingerprint(element).map_err(|e| {
+                        ErrorReason::Internal.status(format!("Failed to serialize ciphertext: {}", e))
+                    })?;
+                (CiphertextType::Integer, serialized_data, fingerprint)
+            }
+        };
+
+        Ok(CiphertextChunk {
+            index: index as u32,
+            ciphertext_type: ciphertext_type as i32,
+            serialized_data,
+            fingerprint,
+            total: self.len() as u32,
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl FheService for FheServiceImpl {
+    async fn get_server_info(
+        &self,
+        request: Request<ServerInfoRequest>,
+    ) -> Result<Response<ServerInfoResponse>, Status> {
+        self.authorize(&request, "GetServerInfo", "").await?;
+        let admission = self.admission.metrics();
+        let runtime = self.runtime();
+        Ok(Response::new(ServerInfoResponse {
+            api_versions: API_VERSIONS.iter().map(|version| version.to_string()).collect(),
+            operations: SUPPORTED_OPERATIONS.iter().map(|op| *op as i32).collect(),
+            integer_widths: INTEGER_WIDTHS.to_vec(),
+            parameter_sets: [ParameterSet::Default, ParameterSet::Fast, ParameterSet::Secure]
+                .iter()
+                .map(|set| *set as i32)
+                .collect(),
+            tfhe_version: env!("HERMETIC_FHE_TFHE_VERSION").to_string(),
+            max_integer_width: INTEGER_WIDTHS.iter().copied().max().unwrap_or_default(),
+            features: Some(ServerFeatures {
+                gpu: false,
+                compression: false,
+                comparisons: false,
+                ckks: true,
+                bgv: true,
+                re_encryption: true,
+                file_sinks: runtime.sinks.root().is_some(),
+                s3_sinks: !runtime.sinks.buckets().is_empty(),
+                webhooks: runtime.webhooks.enabled(),
+                attestation: self.attestor.is_some(),
+                checkpoints: self.checkpoints.dir().is_some(),
+                job_journal: self.journal.dir().is_some(),
+            }),
+            limits: Some(ResourceLimits {
+                max_circuit_gates: MAX_CIRCUIT_GATES as u32,
+                max_message_bytes: u32::try_from(self.max_message_bytes).unwrap_or(u32::MAX),
+                max_session_idle_timeout_seconds: MAX_IDLE_TIMEOUT.as_secs() as u32,
+                max_vector_length: MAX_VECTOR_LENGTH as u32,
+                max_matrix_elements: MAX_MATRIX_ELEMENTS as u32,
+                max_concurrent_evaluations: admission.workers as u32,
+                max_queued_evaluations: admission.queue_depth as u32,
+                max_real_vector_length: MAX_REAL_VECTOR_LENGTH as u32,
+                max_integer_batch_length: MAX_INTEGER_BATCH_LENGTH as u32,
+                max_timestamp_boundaries: MAX_BUCKET_BOUNDARIES as u32,
+                max_ingest_records: MAX_INGEST_RECORDS as u32,
+                max_map_records: MAX_MAP_RECORDS as u32,
+                result_lease_seconds: self.leases.duration().as_secs() as u32,
+            }),
+        }))
+    }
+
+    async fn get_metrics(
+        &self,
+        request: Request<MetricsRequest>,
+    ) -> Result<Response<MetricsResponse>, Status> {
+        self.authorize(&request, "GetMetrics", "").await?;
+        Ok(Response::new(self.metrics()))
+    }
+
+    async fn get_attestation(
+        &self,
+        request: Request<AttestationRequest>,
+    ) -> Result<Response<AttestationResponse>, Status> {
+        self.authorize(&request, "GetAttestation", "").await?;
+        let req = request.into_inner();
+
+        let Some(attestor) = self.attestor.clone() else {
+            return Err(ErrorReason::Unsupported.status("Server is not running in an attested environment"));
+        };
+        if req.nonce.is_empty() || req.nonce.len() > MAX_NONCE_BYTES {
+            return Err(ErrorReason::InvalidRequest
+                .status(format!("nonce must be 1 to {} bytes", MAX_NONCE_BYTES)));
+        }
+        let platform = match attestor.platform() {
+            TeePlatform::Sgx => attestation_response::Platform::Sgx,
+            TeePlatform::SevSnp => attestation_response::Platform::SevSnp,
+            TeePlatform::Tdx => attestation_response::Platform::Tdx,
+        };
+        // Quoting goes through the platform's device files, which can block
+        let quoting = attestor.clone();
+        let attestation = tokio::task::spawn_blocking(move || quoting.attest(&req.nonce))
+            .await
+            .map_err(|e| ErrorReason::Internal.status(format!("Attestation worker failed: {}", e)))?
+            .map_err(|e| ErrorReason::Internal.status(format!("Attestation failed: {}", e)))?;
+
+        Ok(Response::new(AttestationResponse {
+            platform: platform as i32,
+            quote: attestation.quote,
+            measurement: attestation.measurement,
+            certificate: attestor.certificate().to_vec(),
+        }))
+    }
+
+    async fn generate_keys(
+        &self,
+        request: Request<KeyGenerationRequest>,
+    ) -> Result<Response<KeyGenerationResponse>, Status> {
+        self.authorize(&request, "GenerateKeys", "").await?;
+        let tenant = request_tenant(&request);
+        let alias = request.get_ref().alias.clone();
+        // Checked up front, so a taken alias doesn't cost a key generation
+        if !alias.is_empty() {
+            check_alias(&alias).map_err(alias_status)?;
+            if self.key_store.alias(&tenant, &alias).is_some() {
+                return Err(alias_status(AliasError::Taken(alias)));
+            }
+        }
+        let parameter_set = self.parameter_set(request.get_ref().parameter_set)?;
+        let policy = key_policy(request.get_ref().policy.as_ref())?;
+        if policy != KeyPolicy::default() && request.get_ref().scheme() != Scheme::Tfhe {
+            return Err(ErrorReason::InvalidRequest.status("A type policy only applies to TFHE keys"));
+        }
+        self.check_memory()?;
+
+        let generated = match request.get_ref().scheme() {
+            Scheme::Tfhe => {
+                info!("Generating keys with parameter set: {}", parameter_set);
+                self.backend.generate_keys_with_policy(parameter_set, policy)
+            }
+            Scheme::Ckks => {
+                info!("Generating {} keys", self.ckks.scheme());
+                self.ckks.generate_keys()
+            }
+            Scheme::Bgv => {
+                info!("Generating {} keys", self.bgv.scheme());
+                self.bgv.generate_keys()
+            }
+        };
+        let (client_key_id, server_key_id) = generated
+            .map_err(|e| ErrorReason::Internal.status(format!("Failed to generate keys: {}", e)))?;
+        if !alias.is_empty() {
+            self.key_store
+                .set_alias(&tenant, &alias, &server_key_id, false)
+                .map_err(alias_status)?;
+        }
+
+        let client_key_fingerprint = self.key_store.get_fingerprint(&client_key_id).unwrap_or_default();
+        let server_key_fingerprint = self.key_store.get_fingerprint(&server_key_id).unwrap_or_default();
+
+        Ok(Response::new(KeyGenerationResponse {
+            client_key_id,
+            server_key_id,
+            client_key_fingerprint,
+            server_key_fingerprint,
+        }))
+    }
+
+    async fn warm_server_keys(
+        &self,
+        mut request: Request<WarmServerKeysRequest>,
+    ) -> Result<Response<WarmServerKeysResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "WarmServerKeys", "").await?;
+        let tenant = request_tenant(&request);
+        let req = request.into_inner();
+
+        for id in &req.server_key_ids {
+            if self.key_store.get_server_key(id).is_none() {
+                return Err(ErrorReason::KeyNotFound.status(format!("Server key {} not found", id)));
+            }
+        }
+
+        let key_count = req.server_key_ids.len();
+        let key_store = self.key_store.clone();
+        let server_key_ids = req.server_key_ids;
+        // Warming is not tied to one key, so it is billed to the tenant alone
+        let usage = UsageTag::new(tenant, "", "WarmServerKeys");
+        let workers = self
+            .run_blocking(usage, &Cancellation::new(), move || {
+                key_store
+                    .warm(&server_key_ids)
+                    .map_err(|e| ErrorReason::Internal.status(format!("Failed to warm server keys: {}", e)))
+            })
+            .await?;
+
+        info!("Warmed {} server keys on {} workers", key_count, workers);
+
+        Ok(Response::new(WarmServerKeysResponse { workers: workers as u32 }))
+    }
+
+    async fn set_key_alias(
+        &self,
+        request: Request<SetKeyAliasRequest>,
+    ) -> Result<Response<KeyAlias>, Status> {
+        let tenant = request_tenant(&request);
+        // The key may itself be given by an alias, such as to copy one
+        let key_id = match self.key_store.alias(&tenant, &request.get_ref().key_id) {
+            Some(alias) => alias.server_key_id,
+            None => request.get_ref().key_id.clone(),
+        };
+        self.authorize(&request, "SetKeyAlias", &key_id).await?;
+        let req = request.into_inner();
+
+        let alias = self
+            .key_store
+            .set_alias(&tenant, &req.alias, &key_id, req.replace)
+            .map_err(alias_status)?;
+        info!("Alias '{}' of tenant '{}' names server key {}", alias.alias, tenant, alias.server_key_id);
+
+        Ok(Response::new(KeyAlias {
+            alias: alias.alias,
+            client_key_id: alias.client_key_id,
+            server_key_id: alias.server_key_id,
+        }))
+    }
+
+    async fn delete_key_alias(
+        &self,
+        request: Request<DeleteKeyAliasRequest>,
+    ) -> Result<Response<DeleteKeyAliasResponse>, Status> {
+        self.authorize(&request, "DeleteKeyAlias", "").await?;
+        let tenant = request_tenant(&request);
+        let deleted = self
+            .key_store
+            .remove_alias(&tenant, &request.get_ref().alias)
+            .map_err(alias_status)?;
+        Ok(Response::new(DeleteKeyAliasResponse { deleted }))
+    }
+
+    async fn list_key_aliases(
+        &self,
+        request: Request<ListKeyAliasesRequest>,
+    ) -> Result<Response<ListKeyAliasesResponse>, Status> {
+        self.authorize(&request, "ListKeyAliases", "").await?;
+        let aliases = self
+            .key_store
+            .aliases(&request_tenant(&request))
+            .into_iter()
+            .map(|alias| KeyAlias {
+                alias: alias.alias,
+                client_key_id: alias.client_key_id,
+                server_key_id: alias.server_key_id,
+            })
+            .collect();
+        Ok(Response::new(ListKeyAliasesResponse { aliases }))
+    }
+
+    async fn encrypt_boolean(
+        &self,
+        mut request: Request<EncryptBooleanRequest>,
+    ) -> Result<Response<EncryptedDataResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "EncryptBoolean", &request.get_ref().client_key_id).await?;
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+        self.check_subject(&req.subject_id)?;
+        self.check_booleans_allowed(&req.client_key_id, true)?;
+        
+        // Encrypt and store the boolean value
+        let encrypted_data_id = self
+            .backend
+            .encrypt_boolean(&req.client_key_id, req.value)
+            .map_err(|e| backend_status(e, "Encrypted data"))?;
+        self.track_in_session(&req.session_id, &encrypted_data_id)?;
+        self.tag_subject(&req.subject_id, &encrypted_data_id);
+        
+        Ok(Response::new(EncryptedDataResponse {
+            fingerprint: self.ciphertext_fingerprint(&encrypted_data_id),
+            encrypted_data_id,
+            serialized_data: vec![], // For simplicity, not serializing the data
+        }))
+    }
+
+    async fn encrypt_integer(
+        &self,
+        mut request: Request<EncryptIntegerRequest>,
+    ) -> Result<Response<EncryptedDataResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "EncryptInteger", &request.get_ref().client_key_id).await?;
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+        self.check_subject(&req.subject_id)?;
+        
+        // Simplifying to always use uint8 for the example
+        // In a real implementation, you'd choose the integer type based on the num_bits
+        if req.value < 0 || req.value > 255 {
+            return Err(ErrorReason::ValueOutOfRange.status("Value out of range for uint8"));
+        }
+        let policy = self.key_store.policy(&req.client_key_id);
+        if policy.integer_bits != 0 && req.num_bits != 0 && req.num_bits != policy.integer_bits {
+            return Err(ErrorReason::WidthMismatch.status(format!(
+                "Integers under this key are {}-bit, not {}-bit",
+                policy.integer_bits, req.num_bits
+            )));
+        }
+
+        // Encrypt and store the integer value
+        let encrypted_data_id = self
+            .backend
+            .encrypt_integer(&req.client_key_id, req.value as u8)
+            .map_err(|e| backend_status(e, "Encrypted data"))?;
+        self.track_in_session(&req.session_id, &encrypted_data_id)?;
+        self.tag_subject(&req.subject_id, &encrypted_data_id);
+        
+        Ok(Response::new(EncryptedDataResponse {
+            fingerprint: self.ciphertext_fingerprint(&encrypted_data_id),
+            encrypted_data_id,
+            serialized_data: vec![], // For simplicity, not serializing the data
+        }))
+    }
+
+    async fn evaluate_operation(
+        &self,
+        mut request: Request<EvaluationRequest>,
+    ) -> Result<Response<EvaluationResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "EvaluateOperation", &request.get_ref().server_key_id).await?;
+        let cancellation = request_cancellation(&request);
+        let tenant = request_tenant(&request);
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+        
+        // Get the server key
+        let server_key = self
+            .key_store
+            .get_server_key(&req.server_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Server key not found"))?;
+
+        // The high-level tfhe API evaluates against a thread-local server key
+        tfhe::set_server_key((*server_key).clone());
+
+        let usage = UsageTag::new(tenant, &req.server_key_id, "EvaluateOperation");
+        if !req.expression.is_empty() {
+            if !req.overwrite_id.is_empty() && !req.variables.values().any(|id| *id == req.overwrite_id) {
+                return Err(ErrorReason::InvalidRequest.status("overwrite_id must name one of the variables"));
+            }
+            if req.detect_overflow {
+                return Err(ErrorReason::InvalidRequest
+                    .status("detect_overflow does not apply to expressions"));
+            }
+            let (result, derivation) = self.evaluate_expression(&req, server_key, cancellation, usage).await?;
+            return self.evaluation_response(req, result, None, derivation);
+        }
+
+        // Validate the operands
+        if req.operand_ids.is_empty() {
+            return Err(ErrorReason::ArityMismatch.status("No operands provided"));
+        }
+        if !req.overwrite_id.is_empty() && !req.operand_ids.contains(&req.overwrite_id) {
+            return Err(ErrorReason::InvalidRequest.status("overwrite_id must name one of the operands"));
+        }
+
+        // Comparisons are refused here, as they are in circuits
+        let operation = with_overflow(circuit_operation(req.operation)?, req.overflow())?;
+        self.check_operations_allowed(&req.server_key_id, [operation])?;
+        self.check_booleans_allowed(&req.server_key_id, operation.uses_booleans() || req.detect_overflow)?;
+        let operation = operation_type(operation);
+        let detectable = matches!(
+            operation,
+            OperationType::Add | OperationType::Subtract | OperationType::Multiply
+        );
+        if req.detect_overflow && !detectable {
+            return Err(ErrorReason::InvalidRequest.status(
+                "detect_overflow only applies to ADD, SUBTRACT and MULTIPLY with overflow WRAP",
+            ));
+        }
+
+        let parents = self.ciphertext_store.parents(req.operand_ids.iter().map(String::as_str));
+        let derivation = Derivation::new("EvaluateOperation", operation.as_str_name(), parents);
+        let result: Ciphertext = match operation {
+            // Boolean operations
+            OperationType::And | OperationType::Or | OperationType::Xor => {
+                if req.operand_ids.len() != 2 {
+                    return Err(ErrorReason::ArityMismatch.status("Binary operation requires 2 operands"));
+                }
+
+                let a = self.load_boolean(&req.operand_ids[0], "First operand")?;
+
+                let b = self.load_boolean(&req.operand_ids[1], "Second operand")?;
+
+                self.metered(usage, || match operation {
+                    OperationType::And => operations::boolean_and(&server_key, &a, &b),
+                    OperationType::Or => operations::boolean_or(&server_key, &a, &b),
+                    OperationType::Xor => operations::boolean_xor(&server_key, &a, &b),
+                    _ => unreachable!(),
+                })
+                .into()
+            }
+            
+            // Unary boolean operation
+            OperationType::Not => {
+                if req.operand_ids.len() != 1 {
+                    return Err(ErrorReason::ArityMismatch.status("Unary operation requires 1 operand"));
+                }
+
+                let a = self.load_boolean(&req.operand_ids[0], "Operand")?;
+
+                self.metered(usage, || operations::boolean_not(&server_key, &a)).into()
+            }
+            
+            // Integer operations
+            OperationType::Add
+            | OperationType::Subtract
+            | OperationType::Multiply
+            | OperationType::AbsDiff
+            | OperationType::SaturatingAdd
+            | OperationType::SaturatingSub => {
+                if req.operand_ids.len() != 2 {
+                    return Err(ErrorReason::ArityMismatch.status("Binary operation requires 2 operands"));
+                }
+
+                let a = self.load_integer(&req.operand_ids[0], "First operand")?;
+
+                let b = self.load_integer(&req.operand_ids[1], "Second operand")?;
+
+                if req.detect_overflow {
+                    let (result, overflowed) = self.metered(usage, || match operation {
+                        OperationType::Add => operations::integer_overflowing_add(&a, &b),
+                        OperationType::Subtract => operations::integer_overflowing_sub(&a, &b),
+                        OperationType::Multiply => operations::integer_overflowing_mul(&a, &b),
+                        _ => unreachable!(),
+                    });
+                    return self.evaluation_response(req, result.into(), Some(overflowed), derivation);
+                }
+
+                self.metered(usage, || match operation {
+                    OperationType::Add => operations::integer_add(&a, &b),
+                    OperationType::Subtract => operations::integer_subtract(&a, &b),
+                    OperationType::Multiply => operations::integer_multiply(&a, &b),
+                    OperationType::AbsDiff => operations::integer_abs_diff(&a, &b),
+                    OperationType::SaturatingAdd => operations::integer_saturating_add(&a, &b),
+                    OperationType::SaturatingSub => operations::integer_saturating_sub(&a, &b),
+                    _ => unreachable!(),
+                })
+                .into()
+            }
+            
+            // Integer predicates, giving a boolean
+            OperationType::IsZero | OperationType::IsNonZero => {
+                if req.operand_ids.len() != 1 {
+                    return Err(ErrorReason::ArityMismatch.status("Unary operation requires 1 operand"));
+                }
+
+                let a = self.load_integer(&req.operand_ids[0], "Operand")?;
+
+                self.metered(usage, || match operation {
+                    OperationType::IsZero => operations::integer_is_zero(&a),
+                    OperationType::IsNonZero => operations::integer_is_non_zero(&a),
+                    _ => unreachable!(),
+                })
+                .into()
+            }
+            
+            // Already refused by circuit_operation above
+            OperationType::GreaterThan | OperationType::LessThan | OperationType::Equal => unreachable!(),
+        };
+
+        self.evaluation_response(req, result, None, derivation)
+    }
+
+    async fn evaluate_circuit(
+        &self,
+        mut request: Request<CircuitEvaluationRequest>,
+    ) -> Result<Response<CircuitEvaluationResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "EvaluateCircuit", &request.get_ref().server_key_id).await?;
+        let cancellation = request_cancellation(&request);
+        let tenant = request_tenant(&request);
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+
+        // Get the server key
+        let server_key = self
+            .key_store
+            .get_server_key(&req.server_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Server key not found"))?;
+
+        let circuit = build_circuit(&req.gates, &req.outputs)?;
+        let inputs = self.load_inputs(&req.input_ids)?;
+        let output_derivations = self.circuit_derivations("EvaluateCircuit", &circuit, &req.input_ids);
+        let gate_inputs = circuit.gate_inputs();
+        let checkpoint = self.open_checkpoint(req.checkpoint.clone(), || {
+            // The session that owns the outputs doesn't change them
+            let job = CircuitEvaluationRequest {
+                session_id: String::new(),
+                checkpoint: None,
+                ..req.clone()
+            };
+            let fingerprints = req.input_ids.iter().map(|id| self.ciphertext_fingerprint(id));
+            checkpoint::job_digest(&job.encode_to_vec(), fingerprints)
+        })?;
+
+        let options = EvaluationOptions {
+            keep_intermediates: req.keep_intermediates,
+            cancellation,
+            checkpoint,
+        };
+        let usage = UsageTag::new(tenant, &req.server_key_id, "EvaluateCircuit");
+        let result = self.run_circuit(circuit, server_key, inputs, options, usage).await?;
+
+        info!(
+            "Evaluated circuit of {} gates, peak {} live intermediates",
+            req.gates.len(),
+            result.peak_live_values
+        );
+        if result.resumed_gates > 0 {
+            info!("Resumed circuit from a checkpoint after {} gates", result.resumed_gates);
+        }
+
+        let output_ids: Vec<String> = result
+            .outputs
+            .into_iter()
+            .zip(output_derivations)
+            .map(|(value, derivation)| self.store_derived(value, &req.session_id, derivation))
+            .collect::<Result<Vec<_>, Status>>()?;
+        let output_fingerprints = output_ids.iter().map(|id| self.ciphertext_fingerprint(id)).collect();
+        let intermediates = result
+            .intermediates
+            .into_iter()
+            .map(|(gate, value)| {
+                let detail = format!("gate {}", gate);
+                let derivation =
+                    self.inputs_derivation("EvaluateCircuit", detail, &gate_inputs[gate], &req.input_ids);
+                Ok(CircuitIntermediate {
+                    gate: gate as u32,
+                    encrypted_data_id: self.store_derived(value, &req.session_id, derivation)?,
+                })
+            })
+            .collect::<Result<Vec<_>, Status>>()?;
+
+        Ok(Response::new(CircuitEvaluationResponse {
+            output_ids,
+            output_fingerprints,
+            intermediates,
+            peak_live_ciphertexts: result.peak_live_values as u32,
+            resumed_gates: result.resumed_gates as u32,
+        }))
+    }
+
+    async fn list_library_circuits(
+        &self,
+        request: Request<ListLibraryCircuitsRequest>,
+    ) -> Result<Response<ListLibraryCircuitsResponse>, Status> {
+        self.authorize(&request, "ListLibraryCircuits", "").await?;
+        let circuits = library::CIRCUITS
+            .iter()
+            .map(|circuit| LibraryCircuitInfo {
+                name: circuit.name.to_string(),
+                description: circuit.description.to_string(),
+            })
+            .collect();
+
+        Ok(Response::new(ListLibraryCircuitsResponse { circuits }))
+    }
+
+    async fn evaluate_library_circuit(
+        &self,
+        mut request: Request<LibraryCircuitRequest>,
+    ) -> Result<Response<CircuitEvaluationResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "EvaluateLibraryCircuit", &request.get_ref().server_key_id).await?;
+        let cancellation = request_cancellation(&request);
+        let tenant = request_tenant(&request);
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+
+        // Get the server key
+        let server_key = self
+            .key_store
+            .get_server_key(&req.server_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Server key not found"))?;
+
+        let (width, count) = (req.width as usize, req.count as usize);
+        let expected_inputs = library::input_count(&req.name, width, count);
+        if expected_inputs > MAX_VECTOR_LENGTH {
+            return Err(ErrorReason::LimitExceeded.status(format!(
+                "Circuit would have {} inputs, the limit is {}",
+                expected_inputs, MAX_VECTOR_LENGTH
+            )));
+        }
+        let circuit = library::build(&req.name, width, count)
+            .map_err(|e| ErrorReason::InvalidRequest.status(e.to_string()))?;
+        if circuit.gates.len() > MAX_CIRCUIT_GATES {
+            return Err(ErrorReason::LimitExceeded.status(format!(
+                "Circuit has {} gates, the limit is {}",
+                circuit.gates.len(),
+                MAX_CIRCUIT_GATES
+            )));
+        }
+        if req.input_ids.len() != expected_inputs {
+            return Err(ErrorReason::ArityMismatch.status(format!(
+                "{} takes {} inputs, got {}",
+                req.name,
+                expected_inputs,
+                req.input_ids.len()
+            )));
+        }
+        let inputs = self.load_inputs(&req.input_ids)?;
+        let output_derivations: Vec<Derivation> = self
+            .circuit_derivations("EvaluateLibraryCircuit", &circuit, &req.input_ids)
+            .into_iter()
+            .map(|derivation| derivation.with_detail(format!("{} {}", req.name, derivation.detail)))
+            .collect();
+
+        let options = EvaluationOptions {
+            keep_intermediates: false,
+            cancellation,
+            checkpoint: None,
+        };
+        let usage = UsageTag::new(tenant, &req.server_key_id, "EvaluateLibraryCircuit");
+        let gates = circuit.gates.len();
+        let result = self.run_circuit(circuit, server_key, inputs, options, usage).await?;
+        info!("Evaluated library circuit {} of {} gates", req.name, gates);
+
+        let output_ids: Vec<String> = result
+            .outputs
+            .into_iter()
+            .zip(output_derivations)
+            .map(|(value, derivation)| self.store_derived(value, &req.session_id, derivation))
+            .collect::<Result<Vec<_>, Status>>()?;
+        let output_fingerprints = output_ids.iter().map(|id| self.ciphertext_fingerprint(id)).collect();
+
+        Ok(Response::new(CircuitEvaluationResponse {
+            output_ids,
+            output_fingerprints,
+            intermediates: vec![],
+            peak_live_ciphertexts: result.peak_live_values as u32,
+            resumed_gates: 0,
+        }))
+    }
+
+    async fn list_custom_operations(
+        &self,
+        request: Request<ListCustomOperationsRequest>,
+    ) -> Result<Response<ListCustomOperationsResponse>, Status> {
+        self.authorize(&request, "ListCustomOperations", "").await?;
+        let operations = self
+            .operations
+            .plugins()
+            .map(|plugin| CustomOperationInfo {
+                name: plugin.name().to_string(),
+                description: plugin.description().to_string(),
+                arity: plugin.arity().unwrap_or(0) as u32,
+            })
+            .collect();
+
+        Ok(Response::new(ListCustomOperationsResponse { operations }))
+    }
+
+    async fn invoke_custom_operation(
+        &self,
+        mut request: Request<InvokeCustomOperationRequest>,
+    ) -> Result<Response<InvokeCustomOperationResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "InvokeCustomOperation", &request.get_ref().server_key_id).await?;
+        let cancellation = request_cancellation(&request);
+        let tenant = request_tenant(&request);
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+
+        // Get the server key
+        let server_key = self
+            .key_store
+            .get_server_key(&req.server_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Server key not found"))?;
+
+        let plugin = self.operations.get(&req.name).ok_or_else(|| {
+            ErrorReason::Unsupported.status(format!("No custom operation named '{}'", req.name))
+        })?;
+        // What a plugin computes is opaque, so a key limited to some operations can't vouch for it
+        if self.key_store.policy(&req.server_key_id).allowed_operations != 0 {
+            return Err(ErrorReason::PolicyViolation.status(format!(
+                "Custom operation {} is not permitted under a key limited to some operations",
+                req.name
+            )));
+        }
+        if req.input_ids.len() > MAX_VECTOR_LENGTH {
+            return Err(ErrorReason::LimitExceeded.status(format!(
+                "{} inputs given, the limit is {}",
+                req.input_ids.len(),
+                MAX_VECTOR_LENGTH
+            )));
+        }
+        if let Some(arity) = plugin.arity().filter(|arity| *arity != req.input_ids.len()) {
+            return Err(ErrorReason::ArityMismatch.status(format!(
+                "{} takes {} inputs, got {}",
+                req.name,
+                arity,
+                req.input_ids.len()
+            )));
+        }
+        let inputs = self.load_inputs(&req.input_ids)?;
+        let uses_booleans = |values: &[Value]| values.iter().any(|value| matches!(value, Value::Boolean(_)));
+        self.check_booleans_allowed(&req.server_key_id, uses_booleans(&inputs))?;
+
+        let backend = self.backend.clone();
+        let server_key_id = req.server_key_id.clone();
+        let name = req.name.clone();
+        let check = cancellation.clone();
+        let usage = UsageTag::new(tenant, &req.server_key_id, "InvokeCustomOperation");
+        let outputs = self
+            .run_blocking(usage, &cancellation, move || {
+                // The high-level tfhe API evaluates against a thread-local server key
+                tfhe::set_server_key((*server_key).clone());
+
+                let context = PluginContext {
+                    server_key_id: &server_key_id,
+                    server_key: &server_key,
+                    backend: &backend,
+                    cancellation: &check,
+                };
+                plugin.evaluate(&context, &inputs).map_err(|e| match e {
+                    BackendError::Other(e) => evaluation_status(e, |e| {
+                        ErrorReason::Internal.status(format!("{} failed: {}", name, e))
+                    }),
+                    e => backend_status(e, "Input"),
+                })
+            })
+            .await?;
+        if outputs.len() > MAX_VECTOR_LENGTH {
+            return Err(ErrorReason::Internal.status(format!(
+                "{} returned {} outputs, the limit is {}",
+                req.name,
+                outputs.len(),
+                MAX_VECTOR_LENGTH
+            )));
+        }
+        self.check_booleans_allowed(&req.server_key_id, uses_booleans(&outputs))?;
+        info!("Invoked custom operation {} on {} inputs", req.name, req.input_ids.len());
+
+        let parents = self.ciphertext_store.parents(req.input_ids.iter().map(String::as_str));
+        let derivation = Derivation::new("InvokeCustomOperation", req.name.clone(), parents);
+        let output_ids: Vec<String> = outputs
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let derivation = derivation.with_detail(format!("{} output {}", req.name, i));
+                self.store_derived(value, &req.session_id, derivation)
+            })
+            .collect::<Result<Vec<_>, Status>>()?;
+        let output_fingerprints = output_ids.iter().map(|id| self.ciphertext_fingerprint(id)).collect();
+
+        Ok(Response::new(InvokeCustomOperationResponse {
+            output_ids,
+            output_fingerprints,
+        }))
+    }
+
+    async fn validate_circuit(
+        &self,
+        request: Request<ValidateCircuitRequest>,
+    ) -> Result<Response<ValidateCircuitResponse>, Status> {
+        self.authorize(&request, "ValidateCircuit", "").await?;
+        let req = request.into_inner();
+
+        // A circuit that can't even be built gets a one-line report rather than an error,
+        // so clients handle every validation failure the same way
+        let circuit = match build_circuit(&req.gates, &req.outputs) {
+            Ok(circuit) => circuit,
+            Err(status) if status.code() == tonic::Code::ResourceExhausted => return Err(status),
+            Err(status) => {
+                return Ok(Response::new(ValidateCircuitResponse {
+                    valid: false,
+                    issues: vec![CircuitIssue {
+                        kind: CircuitIssueKind::Malformed as i32,
+                        gate: -1,
+                        message: status.message().to_string(),
+                    }],
+                    ..Default::default()
+                }))
+            }
+        };
+
+        let inputs: Vec<Option<InputSpec>> = req
+            .input_ids
+            .iter()
+            .map(|id| self.load_value(id).map(|value| InputSpec::of(value.value_type())))
+            .chain(req.declared_inputs.iter().map(|input| Some(declared_input(input))))
+            .collect();
+        let report = circuit.check(&inputs);
+
+        let mut issues: Vec<CircuitIssue> = Vec::new();
+        for (index, input) in inputs.iter().enumerate() {
+            if let Some(spec) = input.filter(|spec| spec.value_type == ValueType::Integer) {
+                if !INTEGER_WIDTHS.contains(&spec.bits) {
+                    issues.push(CircuitIssue {
+                        kind: CircuitIssueKind::WidthMismatch as i32,
+                        gate: -1,
+                        message: format!(
+                            "Input {} is {}-bit; supported widths are {:?}",
+                            index, spec.bits, INTEGER_WIDTHS
+                        ),
+                    });
+                }
+            }
+        }
+        issues.extend(report.issues.iter().map(|issue| CircuitIssue {
+            kind: issue_kind(issue.kind) as i32,
+            gate: issue.gate.map_or(-1, |gate| gate as i32),
+            message: issue.message.clone(),
+        }));
+
+        Ok(Response::new(ValidateCircuitResponse {
+            valid: issues.is_empty(),
+            issues,
+            depth: report.depth as u32,
+            multiplicative_depth: report.multiplicative_depth as u32,
+            peak_live_ciphertexts: report.peak_live_values as u32,
+            operation_counts: report
+                .operation_counts
+                .iter()
+                .map(|(operation, count)| OperationCount {
+                    operation: operation_type(*operation) as i32,
+                    count: *count as u32,
+                })
+                .collect(),
+        }))
+    }
+
+    async fn estimate_cost(
+        &self,
+        request: Request<EstimateCostRequest>,
+    ) -> Result<Response<EstimateCostResponse>, Status> {
+        self.authorize(&request, "EstimateCost", "").await?;
+        let req = request.into_inner();
+        let parameter_set = self.parameter_set(req.parameter_set)?;
+
+        // A single operation is a one-gate circuit over as many inputs as it takes
+        let circuit = if req.gates.is_empty() {
+            let operation = circuit_operation(req.operation)?;
+            Circuit {
+                gates: vec![Gate {
+                    operation,
+                    inputs: (0..operation.arity()).map(Wire::Input).collect(),
+                }],
+                outputs: vec![Wire::Gate(0)],
+            }
+        } else {
+            build_circuit(&req.gates, &req.outputs)?
+        };
+
+        let cost_model = self.cost_model();
+        let estimate = cost_model
+            .estimate(parameter_set, &circuit)
+            .map_err(|e| ErrorReason::InvalidRequest.status(e.to_string()))?;
+        let queue_wait = self.admission.estimated_wait();
+
+        Ok(Response::new(EstimateCostResponse {
+            latency_seconds: estimate.latency.as_secs_f64(),
+            memory_bytes: estimate.memory_bytes as u64,
+            server_key_bytes: estimate.server_key_bytes as u64,
+            calibrated: cost_model.is_measured(parameter_set),
+            queue_wait_seconds: queue_wait.as_secs_f64(),
+            suggested_deadline_seconds: cost::suggested_deadline(estimate.latency, queue_wait).as_secs_f64(),
+        }))
+    }
+
+    async fn evaluate_and_decrypt(
+        &self,
+        mut request: Request<EvaluateAndDecryptRequest>,
+    ) -> Result<Response<EvaluateAndDecryptResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "EvaluateAndDecrypt", &request.get_ref().server_key_id).await?;
+        let cancellation = request_cancellation(&request);
+        let tenant = request_tenant(&request);
+        let req = request.into_inner();
+        self.check_exact_decryption("EvaluateAndDecrypt")?;
+
+        // Decryption is authorized by the client key, so check it before evaluating anything
+        let client_key = self
+            .key_store
+            .get_client_key(&req.client_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Client key not found"))?;
+
+        // Get the server key
+        let server_key = self
+            .key_store
+            .get_server_key(&req.server_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Server key not found"))?;
+
+        let circuit = build_circuit(&req.gates, &req.outputs)?;
+        let inputs = self.load_inputs(&req.input_ids)?;
+        // Checked up front, so a circuit whose outputs can't all be revealed isn't run
+        for derivation in self.circuit_derivations("EvaluateAndDecrypt", &circuit, &req.input_ids) {
+            self.check_aggregation(Some(&derivation.provenance))?;
+        }
+        let options = EvaluationOptions {
+            cancellation,
+            ..Default::default()
+        };
+        let usage = UsageTag::new(tenant, &req.server_key_id, "EvaluateAndDecrypt");
+        let result = self.run_circuit(circuit, server_key, inputs, options, usage).await?;
+
+        // Outputs are decrypted in place and never stored
+        let values = result
+            .outputs
+            .iter()
+            .map(|output| {
+                let value = match output {
+                    Value::Boolean(ct) => plaintext_value::Value::Boolean(ct.decrypt(&*client_key)),
+                    Value::Integer(ct) => plaintext_value::Value::Integer(
+                        <FheUint8 as FheDecrypt<u8>>::decrypt(ct, &*client_key) as i64,
+                    ),
+                };
+                PlaintextValue { value: Some(value) }
+            })
+            .collect();
+
+        info!("Evaluated and decrypted circuit of {} gates", req.gates.len());
+
+        Ok(Response::new(EvaluateAndDecryptResponse { values }))
+    }
+
+    async fn encrypt_and_evaluate(
+        &self,
+        mut request: Request<EncryptAndEvaluateRequest>,
+    ) -> Result<Response<CircuitEvaluationResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "EncryptAndEvaluate", &request.get_ref().server_key_id).await?;
+        let cancellation = request_cancellation(&request);
+        let tenant = request_tenant(&request);
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+
+        // Get the client key
+        let client_key = self
+            .key_store
+            .get_client_key(&req.client_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Client key not found"))?;
+
+        // Get the server key
+        let server_key = self
+            .key_store
+            .get_server_key(&req.server_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Server key not found"))?;
+
+        let circuit = build_circuit(&req.gates, &req.outputs)?;
+
+        // The encrypted inputs only live for the duration of this call
+        self.key_store.reseed_thread();
+        let inputs = req
+            .inputs
+            .iter()
+            .map(|plaintext| encrypt_plaintext(&client_key, plaintext))
+            .collect::<Result<Vec<_>, Status>>()?;
+        let options = EvaluationOptions {
+            cancellation,
+            ..Default::default()
+        };
+        let usage = UsageTag::new(tenant, &req.server_key_id, "EncryptAndEvaluate");
+        let result = self.run_circuit(circuit, server_key, inputs, options, usage).await?;
+
+        info!(
+            "Encrypted {} inputs and evaluated circuit of {} gates",
+            req.inputs.len(),
+            req.gates.len()
+        );
+
+        let output_ids: Vec<String> = result
+            .outputs
+            .into_iter()
+            .map(|value| self.store_value(value, &req.session_id))
+            .collect::<Result<Vec<_>, Status>>()?;
+        let output_fingerprints = output_ids.iter().map(|id| self.ciphertext_fingerprint(id)).collect();
+
+        Ok(Response::new(CircuitEvaluationResponse {
+            output_ids,
+            output_fingerprints,
+            intermediates: vec![],
+            peak_live_ciphertexts: result.peak_live_values as u32,
+            resumed_gates: 0,
+        }))
+    }
+
+    async fn sort_vector(
+        &self,
+        mut request: Request<SortVectorRequest>,
+    ) -> Result<Response<SortVectorResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "SortVector", &request.get_ref().server_key_id).await?;
+        let cancellation = request_cancellation(&request);
+        let tenant = request_tenant(&request);
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+
+        // Get the server key
+        let server_key = self
+            .key_store
+            .get_server_key(&req.server_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Server key not found"))?;
+
+        // The sorting network overwrites elements as it goes, so it works on copies
+        let elements = owned_elements(&self.load_integer_vector(&req.element_ids)?);
+
+        let worker_cancellation = cancellation.clone();
+        let usage = UsageTag::new(tenant, &req.server_key_id, "SortVector");
+        let sorted = self.run_blocking(usage, &cancellation, move || {
+            // The high-level tfhe API evaluates against a thread-local server key
+            tfhe::set_server_key((*server_key).clone());
+
+            vector::sort(elements, &worker_cancellation)
+                .map_err(|e| {
+                    evaluation_status(e, |e| ErrorReason::Internal.status(format!("Sort failed: {}", e)))
+                })
+        })
+        .await?;
+
+        // Every position of a sorted vector depends on every element
+        let parents = self.ciphertext_store.parents(req.element_ids.iter().map(String::as_str));
+        let derivation = Derivation::new("SortVector", "", parents);
+        let sorted_ids: Vec<String> = sorted
+            .into_iter()
+            .enumerate()
+            .map(|(position, value)| {
+                let derivation = derivation.with_detail(format!("position {}", position));
+                self.store_derived(Value::Integer(Arc::new(value)), &req.session_id, derivation)
+            })
+            .collect::<Result<Vec<_>, Status>>()?;
+        let sorted_fingerprints = sorted_ids.iter().map(|id| self.ciphertext_fingerprint(id)).collect();
+
+        info!("Sorted encrypted vector of {} elements", sorted_ids.len());
+
+        Ok(Response::new(SortVectorResponse {
+            sorted_ids,
+            sorted_fingerprints,
+        }))
+    }
+
+    async fn arg_max(
+        &self,
+        mut request: Request<ArgMaxRequest>,
+    ) -> Result<Response<ArgMaxResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "ArgMax", &request.get_ref().server_key_id).await?;
+        let cancellation = request_cancellation(&request);
+        let tenant = request_tenant(&request);
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+
+        // Get the server key
+        let server_key = self
+            .key_store
+            .get_server_key(&req.server_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Server key not found"))?;
+
+        let elements = owned_elements(&self.load_integer_vector(&req.element_ids)?);
+
+        let k = req.k.max(1) as usize;
+        let worker_cancellation = cancellation.clone();
+        let usage = UsageTag::new(tenant, &req.server_key_id, "ArgMax");
+        let top = self.run_blocking(usage, &cancellation, move || {
+            // The high-level tfhe API evaluates against a thread-local server key
+            tfhe::set_server_key((*server_key).clone());
+
+            vector::top_k(elements, k, &worker_cancellation)
+                .map_err(|e| evaluation_status(e, |e| ErrorReason::InvalidRequest.status(e.to_string())))
+        })
+        .await?;
+
+        let parents = self.ciphertext_store.parents(req.element_ids.iter().map(String::as_str));
+        let derivation = Derivation::new("ArgMax", "", parents);
+        let store = |value, detail: String| {
+            let derivation = derivation.with_detail(detail);
+            self.store_derived(Value::Integer(Arc::new(value)), &req.session_id, derivation)
+        };
+        let elements: Vec<RankedElement> = top
+            .into_iter()
+            .enumerate()
+            .map(|(rank, ranked)| {
+                Ok(RankedElement {
+                    value_id: store(ranked.value, format!("rank {} value", rank))?,
+                    index_id: store(ranked.index, format!("rank {} index", rank))?,
+                })
+            })
+            .collect::<Result<Vec<_>, Status>>()?;
+
+        info!("Selected top {} of {} encrypted elements", elements.len(), req.element_ids.len());
+
+        Ok(Response::new(ArgMaxResponse { elements }))
+    }
+
+    async fn set_membership(
+        &self,
+        mut request: Request<SetMembershipRequest>,
+    ) -> Result<Response<EvaluationResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "SetMembership", &request.get_ref().server_key_id).await?;
+        let tenant = request_tenant(&request);
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+
+        // Get the server key
+        let server_key = self
+            .key_store
+            .get_server_key(&req.server_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Server key not found"))?;
+
+        let value = self.load_integer(&req.value_id, "Value")?;
+
+        if req.element_ids.len() + req.plaintext_elements.len() > MAX_VECTOR_LENGTH {
+            return Err(ErrorReason::LimitExceeded.status(format!(
+                "Set has {} elements, the limit is {}",
+                req.element_ids.len() + req.plaintext_elements.len(),
+                MAX_VECTOR_LENGTH
+            )));
+        }
+        let elements = self.load_integer_vector(&req.element_ids)?;
+        let plaintext_elements = req
+            .plaintext_elements
+            .iter()
+            .copied()
+            .map(plaintext_integer)
+            .collect::<Result<Vec<_>, Status>>()?;
+
+        // The high-level tfhe API evaluates against a thread-local server key
+        tfhe::set_server_key((*server_key).clone());
+
+        self.check_booleans_allowed(&req.server_key_id, true)?;
+        let usage = UsageTag::new(tenant, &req.server_key_id, "SetMembership");
+        let result = self
+            .metered(usage, || vector::contains(&value, &elements, &plaintext_elements))
+            .map_err(|e| ErrorReason::Internal.status(format!("Set membership failed: {}", e)))?;
+
+        let ids = std::iter::once(&req.value_id).chain(&req.element_ids);
+        let detail = format!("{} plaintext elements", plaintext_elements.len());
+        let parents = self.ciphertext_store.parents(ids.map(String::as_str));
+        let derivation = Derivation::new("SetMembership", detail, parents);
+        let result_id = self.store_derived(Value::Boolean(Arc::new(result)), &req.session_id, derivation)?;
+        info!(
+            "Checked membership against {} encrypted and {} plaintext elements",
+            elements.len(),
+            plaintext_elements.len()
+        );
+
+        Ok(Response::new(EvaluationResponse {
+            result_fingerprint: self.ciphertext_fingerprint(&result_id),
+            lease_expires_unix_seconds: self.lease_results(&req.session_id, &[&result_id]),
+            result_id,
+            serialized_result: vec![],
+            overflow_id: String::new(),
+        }))
+    }
+
+    async fn pir_query(
+        &self,
+        mut request: Request<PirQueryRequest>,
+    ) -> Result<Response<EvaluationResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "PirQuery", &request.get_ref().server_key_id).await?;
+        let tenant = request_tenant(&request);
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+
+        // Get the server key
+        let server_key = self
+            .key_store
+            .get_server_key(&req.server_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Server key not found"))?;
+
+        let index = self.load_integer(&req.index_id, "Index")?;
+
+        if req.table.len() > vector::MAX_INDEXED_LENGTH {
+            return Err(ErrorReason::LimitExceeded.status(format!(
+                "Table has {} elements, the limit is {}",
+                req.table.len(),
+                vector::MAX_INDEXED_LENGTH
+            )));
+        }
+        let table = req
+            .table
+            .iter()
+            .copied()
+            .map(plaintext_integer)
+            .collect::<Result<Vec<_>, Status>>()?;
+
+        // The high-level tfhe API evaluates against a thread-local server key
+        tfhe::set_server_key((*server_key).clone());
+
+        let usage = UsageTag::new(tenant, &req.server_key_id, "PirQuery");
+        let result = self
+            .metered(usage, || vector::select_plaintext(&index, &table))
+            .map_err(|e| ErrorReason::Internal.status(format!("PIR query failed: {}", e)))?;
+
+        // The table is plaintext, so the index is the only input
+        let detail = format!("table of {} elements", table.len());
+        let parents = vec![self.ciphertext_store.parent(&req.index_id)];
+        let derivation = Derivation::new("PirQuery", detail, parents);
+        let result_id = self.store_derived(Value::Integer(Arc::new(result)), &req.session_id, derivation)?;
+        info!("Answered PIR query over {} elements", table.len());
+
+        Ok(Response::new(EvaluationResponse {
+            result_fingerprint: self.ciphertext_fingerprint(&result_id),
+            lease_expires_unix_seconds: self.lease_results(&req.session_id, &[&result_id]),
+            result_id,
+            serialized_result: vec![],
+            overflow_id: String::new(),
+        }))
+    }
+
+    async fn match_string(
+        &self,
+        mut request: Request<MatchStringRequest>,
+    ) -> Result<Response<MatchStringResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "MatchString", &request.get_ref().server_key_id).await?;
+        let cancellation = request_cancellation(&request);
+        let tenant = request_tenant(&request);
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+
+        // Get the server key
+        let server_key = self
+            .key_store
+            .get_server_key(&req.server_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Server key not found"))?;
+
+        if req.byte_ids.len() > strings::MAX_STRING_LENGTH {
+            return Err(ErrorReason::LimitExceeded.status(format!(
+                "String has {} bytes, the limit is {}",
+                req.byte_ids.len(),
+                strings::MAX_STRING_LENGTH
+            )));
+        }
+        if req.candidates.is_empty() {
+            return Err(ErrorReason::InvalidRequest.status("No candidates to match against"));
+        }
+        if req.candidates.len() > strings::MAX_CANDIDATES
+            || req.candidates.iter().any(|candidate| candidate.len() > strings::MAX_STRING_LENGTH)
+        {
+            return Err(ErrorReason::LimitExceeded.status(format!(
+                "At most {} candidates of up to {} bytes are compared at once",
+                strings::MAX_CANDIDATES,
+                strings::MAX_STRING_LENGTH
+            )));
+        }
+        self.check_booleans_allowed(&req.server_key_id, true)?;
+        let bytes = owned_elements(&self.load_integer_vector(&req.byte_ids)?);
+        let candidates: Vec<Vec<u8>> = req
+            .candidates
+            .iter()
+            .map(|candidate| candidate.as_bytes().to_vec())
+            .collect();
+        let kind = match req.mode() {
+            StringMatch::Equals => strings::StringMatch::Equals,
+            StringMatch::WithinOneEdit => strings::StringMatch::WithinOneEdit,
+        };
+
+        let case_sensitive = req.case_sensitive;
+        let worker_cancellation = cancellation.clone();
+        let usage = UsageTag::new(tenant, &req.server_key_id, "MatchString");
+        let (matches, any) = self
+            .run_blocking(usage, &cancellation, move || {
+                let failed = |e: anyhow::Error| {
+                    evaluation_status(e, |e| {
+                        ErrorReason::Internal.status(format!("String matching failed: {}", e))
+                    })
+                };
+                let matches = strings::match_candidates(
+                    &bytes,
+                    &candidates,
+                    kind,
+                    case_sensitive,
+                    &server_key,
+                    &worker_cancellation,
+                )
+                .map_err(failed)?;
+                let any = vector::reduce_tree(matches.clone(), &server_key, &worker_cancellation, |a, b| {
+                    a | b
+                })
+                .map_err(failed)?;
+                Ok((matches, any))
+            })
+            .await?;
+
+        // The candidates are plaintext, so the string's bytes are the only inputs
+        let parents = self.ciphertext_store.parents(req.byte_ids.iter().map(String::as_str));
+        let derivation = Derivation::new("MatchString", "", parents);
+        let store = |value, detail: String| {
+            let derivation = derivation.with_detail(detail);
+            self.store_derived(Value::Boolean(Arc::new(value)), &req.session_id, derivation)
+        };
+        let match_ids: Vec<String> = matches
+            .into_iter()
+            .enumerate()
+            .map(|(i, matched)| store(matched, format!("candidate {}", i)))
+            .collect::<Result<Vec<_>, Status>>()?;
+        let any_match_id = store(any, "any candidate".to_string())?;
+        info!(
+            "Matched a {}-byte string against {} candidates",
+            req.byte_ids.len(),
+            match_ids.len()
+        );
+
+        Ok(Response::new(MatchStringResponse { match_ids, any_match_id }))
+    }
+
+    async fn create_counter(
+        &self,
+        mut request: Request<CreateCounterRequest>,
+    ) -> Result<Response<CounterResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "CreateCounter", &request.get_ref().server_key_id).await?;
+        let req = request.into_inner();
+
+        let server_key = self
+            .key_store
+            .get_server_key(&req.server_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Server key not found"))?;
+
+        let (initial, provenance) = if req.initial_value_id.is_empty() {
+            // Zero is public, so a trivial encryption is enough
+            tfhe::set_server_key((*server_key).clone());
+            let zero = FheUint8::try_encrypt_trivial(0u8)
+                .map_err(|e| ErrorReason::Internal.status(format!("Failed to encode zero: {}", e)))?;
+            (Arc::new(zero), Provenance::default())
+        } else {
+            let initial = self.load_integer(&req.initial_value_id, "Initial value")?;
+            (initial, self.ciphertext_store.derive([req.initial_value_id.as_str()]))
+        };
+
+        let counter_id = self.counters.create(&req.server_key_id, initial, provenance);
+        info!("Created counter {}", counter_id);
+
+        Ok(Response::new(CounterResponse {
+            counter_id,
+            increments: 0,
+        }))
+    }
+
+    async fn increment_counter(
+        &self,
+        request: Request<IncrementCounterRequest>,
+    ) -> Result<Response<CounterResponse>, Status> {
+        self.authorize(&request, "IncrementCounter", "").await?;
+        let tenant = request_tenant(&request);
+        let req = request.into_inner();
+
+        let counter = self.load_counter(&req.counter_id)?;
+        let server_key = self
+            .key_store
+            .get_server_key(counter.server_key_id())
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Server key not found"))?;
+
+        // The high-level tfhe API evaluates against a thread-local server key
+        tfhe::set_server_key((*server_key).clone());
+
+        let usage = UsageTag::new(tenant, counter.server_key_id(), "IncrementCounter");
+        let updated = match req.delta {
+            Some(increment_counter_request::Delta::Amount(amount)) => {
+                let amount = plaintext_integer(amount)?;
+                // A plaintext amount adds no inputs
+                let add = |value: &FheUint8| operations::integer_add_scalar(value, amount);
+                self.metered(usage, || counter.increment(add, &Provenance::default()))
+            }
+            Some(increment_counter_request::Delta::DeltaId(delta_id)) => {
+                let delta = self.load_integer(&delta_id, "Delta")?;
+                let added = self.ciphertext_store.derive([delta_id.as_str()]);
+                self.metered(usage, || {
+                    counter.increment(|value| operations::integer_add(value, &delta), &added)
+                })
+            }
+            None => return Err(ErrorReason::InvalidRequest.status("No delta provided")),
+        };
+
+        Ok(Response::new(CounterResponse {
+            counter_id: req.counter_id,
+            increments: updated.increments,
+        }))
+    }
+
+    async fn read_counter(
+        &self,
+        request: Request<ReadCounterRequest>,
+    ) -> Result<Response<ReadCounterResponse>, Status> {
+        self.authorize(&request, "ReadCounter", "").await?;
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+
+        let current = self.load_counter(&req.counter_id)?.read();
+        // The deltas aren't held, so the counter stands in for them
+        let detail = format!("counter {} after {} increments", req.counter_id, current.increments);
+        let derivation = Derivation::new("ReadCounter", detail, vec![]).with_provenance(current.provenance);
+        let value_id = self.store_derived(Value::Integer(current.value), &req.session_id, derivation)?;
+
+        Ok(Response::new(ReadCounterResponse {
+            counter_id: req.counter_id,
+            increments: current.increments,
+            value_fingerprint: self.ciphertext_fingerprint(&value_id),
+            value_id,
+        }))
+    }
+
+    async fn delete_counter(
+        &self,
+        request: Request<DeleteCounterRequest>,
+    ) -> Result<Response<CounterResponse>, Status> {
+        self.authorize(&request, "DeleteCounter", "").await?;
+        let req = request.into_inner();
+
+        let counter = self
+            .counters
+            .remove(&req.counter_id)
+            .ok_or_else(|| ErrorReason::CounterNotFound.status("Counter not found"))?;
+        info!("Deleted counter {}", req.counter_id);
+
+        Ok(Response::new(CounterResponse {
+            counter_id: req.counter_id,
+            increments: counter.read().increments,
+        }))
+    }
+
+    async fn create_bloom_filter(
+        &self,
+        mut request: Request<CreateBloomFilterRequest>,
+    ) -> Result<Response<BloomFilterResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "CreateBloomFilter", &request.get_ref().server_key_id).await?;
+        let req = request.into_inner();
+
+        if self.key_store.get_server_key(&req.server_key_id).is_none() {
+            return Err(ErrorReason::KeyNotFound.status("Server key not found"));
+        }
+        if req.bit_ids.is_empty() {
+            return Err(ErrorReason::InvalidRequest.status("A filter needs at least one bit"));
+        }
+        if req.bit_ids.len() > bloom::MAX_BLOOM_BITS {
+            return Err(ErrorReason::LimitExceeded.status(format!(
+                "Filter has {} bits, the limit is {}",
+                req.bit_ids.len(),
+                bloom::MAX_BLOOM_BITS
+            )));
+        }
+        let num_hashes = req.num_hashes as usize;
+        if num_hashes == 0 || num_hashes > bloom::MAX_BLOOM_HASHES {
+            return Err(ErrorReason::InvalidRequest.status(format!(
+                "num_hashes must be between 1 and {}",
+                bloom::MAX_BLOOM_HASHES
+            )));
+        }
+        self.check_booleans_allowed(&req.server_key_id, true)?;
+
+        let bits = req
+            .bit_ids
+            .iter()
+            .map(|id| self.load_boolean(id, "Bit").map(|bit| FheBool::clone(&bit)))
+            .collect::<Result<Vec<_>, Status>>()?;
+        let filter = EncryptedBloomFilter::new(bits, num_hashes)
+            .map_err(|e| ErrorReason::InvalidRequest.status(e.to_string()))?;
+        let provenance = self.ciphertext_store.derive(req.bit_ids.iter().map(String::as_str));
+        let filter_id = self.bloom_filters.create(&req.server_key_id, filter, provenance);
+        info!("Created Bloom filter {} of {} bits", filter_id, req.bit_ids.len());
+
+        Ok(Response::new(BloomFilterResponse {
+            filter_id,
+            num_bits: req.bit_ids.len() as u32,
+            num_hashes: req.num_hashes,
+        }))
+    }
+
+    async fn query_bloom_filter(
+        &self,
+        request: Request<QueryBloomFilterRequest>,
+    ) -> Result<Response<QueryBloomFilterResponse>, Status> {
+        self.authorize(&request, "QueryBloomFilter", "").await?;
+        let cancellation = request_cancellation(&request);
+        let tenant = request_tenant(&request);
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+
+        if req.elements.len() > bloom::MAX_BLOOM_QUERIES {
+            return Err(ErrorReason::LimitExceeded.status(format!(
+                "{} elements queried, the limit is {}",
+                req.elements.len(),
+                bloom::MAX_BLOOM_QUERIES
+            )));
+        }
+        let filter = self.load_bloom_filter(&req.filter_id)?;
+        let server_key = self
+            .key_store
+            .get_server_key(&filter.server_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Server key not found"))?;
+
+        let queried = filter.clone();
+        let elements = req.elements;
+        let worker_cancellation = cancellation.clone();
+        let usage = UsageTag::new(tenant, &filter.server_key_id, "QueryBloomFilter");
+        let results = self
+            .run_blocking(usage, &cancellation, move || {
+                queried
+                    .filter
+                    .contains(&server_key, &elements, &worker_cancellation)
+                    .map_err(|e| {
+                        evaluation_status(e, |e| {
+                            ErrorReason::Internal.status(format!("Bloom filter query failed: {}", e))
+                        })
+                    })
+            })
+            .await?;
+
+        // The elements are plaintext, so the filter's bits are the only inputs
+        let derivation =
+            Derivation::new("QueryBloomFilter", "", vec![]).with_provenance(filter.provenance.clone());
+        let result_ids: Vec<String> = results
+            .into_iter()
+            .enumerate()
+            .map(|(i, result)| {
+                let derivation = derivation.with_detail(format!("element {} in filter {}", i, req.filter_id));
+                self.store_derived(Value::Boolean(Arc::new(result)), &req.session_id, derivation)
+            })
+            .collect::<Result<Vec<_>, Status>>()?;
+        info!("Queried Bloom filter {} for {} elements", req.filter_id, result_ids.len());
+
+        Ok(Response::new(QueryBloomFilterResponse { result_ids }))
+    }
+
+    async fn merge_bloom_filters(
+        &self,
+        request: Request<MergeBloomFiltersRequest>,
+    ) -> Result<Response<BloomFilterResponse>, Status> {
+        self.authorize(&request, "MergeBloomFilters", "").await?;
+        let cancellation = request_cancellation(&request);
+        let tenant = request_tenant(&request);
+        let req = request.into_inner();
+
+        if req.filter_ids.len() < 2 {
+            return Err(ErrorReason::InvalidRequest.status("At least two filters are needed to merge"));
+        }
+        if req.filter_ids.len() > bloom::MAX_MERGED_FILTERS {
+            return Err(ErrorReason::LimitExceeded.status(format!(
+                "{} filters given, the limit is {}",
+                req.filter_ids.len(),
+                bloom::MAX_MERGED_FILTERS
+            )));
+        }
+        let filters = req
+            .filter_ids
+            .iter()
+            .map(|id| self.load_bloom_filter(id))
+            .collect::<Result<Vec<_>, Status>>()?;
+        let first = filters[0].clone();
+        if filters.iter().any(|filter| filter.server_key_id != first.server_key_id) {
+            return Err(
+                ErrorReason::InvalidRequest.status("Filters under different server keys cannot be merged")
+            );
+        }
+        let shape = |filter: &BloomFilter| (filter.filter.num_bits(), filter.filter.num_hashes());
+        if let Some(other) = filters.iter().find(|filter| shape(filter) != shape(&first)) {
+            return Err(ErrorReason::ShapeMismatch.status(format!(
+                "Cannot merge a filter of {} bits and {} hashes with one of {} bits and {} hashes",
+                first.filter.num_bits(),
+                first.filter.num_hashes(),
+                other.filter.num_bits(),
+                other.filter.num_hashes()
+            )));
+        }
+        let server_key = self
+            .key_store
+            .get_server_key(&first.server_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Server key not found"))?;
+        let merge = match req.operation() {
+            BloomFilterMerge::Union => bloom::Merge::Union,
+            BloomFilterMerge::Intersection => bloom::Merge::Intersection,
+        };
+
+        let provenance = Provenance::merge(filters.iter().map(|filter| &filter.provenance));
+        let worker_cancellation = cancellation.clone();
+        let usage = UsageTag::new(tenant, &first.server_key_id, "MergeBloomFilters");
+        let merged = self
+            .run_blocking(usage, &cancellation, move || {
+                let filters: Vec<&EncryptedBloomFilter> =
+                    filters.iter().map(|filter| &filter.filter).collect();
+                EncryptedBloomFilter::merge(&server_key, &filters, merge, &worker_cancellation).map_err(|e| {
+                    evaluation_status(e, |e| {
+                        ErrorReason::Internal.status(format!("Bloom filter merge failed: {}", e))
+                    })
+                })
+            })
+            .await?;
+
+        let (num_bits, num_hashes) = (merged.num_bits() as u32, merged.num_hashes() as u32);
+        let filter_id = self.bloom_filters.create(&first.server_key_id, merged, provenance);
+        info!("Merged {} Bloom filters into {}", req.filter_ids.len(), filter_id);
+
+        Ok(Response::new(BloomFilterResponse {
+            filter_id,
+            num_bits,
+            num_hashes,
+        }))
+    }
+
+    async fn delete_bloom_filter(
+        &self,
+        request: Request<DeleteBloomFilterRequest>,
+    ) -> Result<Response<BloomFilterResponse>, Status> {
+        self.authorize(&request, "DeleteBloomFilter", "").await?;
+        let req = request.into_inner();
+
+        let filter = self
+            .bloom_filters
+            .remove(&req.filter_id)
+            .ok_or_else(|| ErrorReason::BloomFilterNotFound.status("Bloom filter not found"))?;
+        info!("Deleted Bloom filter {}", req.filter_id);
+
+        Ok(Response::new(BloomFilterResponse {
+            filter_id: req.filter_id,
+            num_bits: filter.filter.num_bits() as u32,
+            num_hashes: filter.filter.num_hashes() as u32,
+        }))
+    }
+
+    async fn create_aggregation(
+        &self,
+        mut request: Request<CreateAggregationRequest>,
+    ) -> Result<Response<AggregationResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "CreateAggregation", &request.get_ref().server_key_id).await?;
+        let req = request.into_inner();
+
+        if self.key_store.get_server_key(&req.server_key_id).is_none() {
+            return Err(ErrorReason::KeyNotFound.status("Server key not found"));
+        }
+        let windowing = match (req.window_seconds, req.slide_seconds) {
+            (0, 0) => None,
+            (0, _) => return Err(ErrorReason::InvalidRequest.status("slide_seconds needs window_seconds")),
+            (size, 0) => Some(Windowing { size, slide: size }),
+            (size, slide) if size.is_multiple_of(slide) => Some(Windowing { size, slide }),
+            _ => {
+                let message = "slide_seconds must divide window_seconds";
+                return Err(ErrorReason::InvalidRequest.status(message));
+            }
+        };
+        let overlapping = windowing.map_or(1, |windowing| windowing.size / windowing.slide);
+        if overlapping > aggregation::MAX_OVERLAPPING_WINDOWS {
+            return Err(ErrorReason::LimitExceeded.status(format!(
+                "A contribution may fall in at most {} windows",
+                aggregation::MAX_OVERLAPPING_WINDOWS
+            )));
+        }
+        let aggregation_id = self.aggregations.create(&req.server_key_id, windowing);
+        info!("Created aggregation {}", aggregation_id);
+
+        Ok(Response::new(AggregationResponse {
+            aggregation_id,
+            contributions: 0,
+        }))
+    }
+
+    async fn submit_contributions(
+        &self,
+        request: Request<Streaming<Contribution>>,
+    ) -> Result<Response<ContributionSummary>, Status> {
+        self.authorize(&request, "SubmitContributions", "").await?;
+        let tenant = request_tenant(&request);
+        self.contribute(&tenant, request.into_inner()).await.map(Response::new)
+    }
+
+    async fn read_aggregation(
+        &self,
+        request: Request<ReadAggregationRequest>,
+    ) -> Result<Response<AggregationSnapshot>, Status> {
+        self.authorize(&request, "ReadAggregation", "").await?;
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+
+        let aggregation = self.load_aggregation(&req.aggregation_id)?;
+        let now = unix_now();
+        let (window, end, closed) = match aggregation.windowing() {
+            None if req.window_start_unix_seconds != 0 => {
+                return Err(ErrorReason::InvalidRequest.status("The aggregation has no windows"));
+            }
+            None => (0, 0, false),
+            Some(windowing) => {
+                let start = match req.window_start_unix_seconds {
+                    0 => windowing
+                        .latest_closed(now)
+                        .ok_or_else(|| ErrorReason::InvalidRequest.status("No window has closed yet"))?,
+                    start if start.is_multiple_of(windowing.slide) => start,
+                    _ => {
+                        return Err(ErrorReason::InvalidRequest.status(format!(
+                            "Windows start at multiples of {} seconds",
+                            windowing.slide
+                        )));
+                    }
+                };
+                let end = windowing.end(start);
+                (start, end, end <= now)
+            }
+        };
+        let snapshot = aggregation.snapshot(window).ok_or_else(|| {
+            ErrorReason::AggregationNotFound.status(format!("Window {} is no longer kept", window))
+        })?;
+        let source = match aggregation.windowing() {
+            Some(_) => format!("aggregation {} window {}", req.aggregation_id, window),
+            None => format!("aggregation {}", req.aggregation_id),
+        };
+        let metrics = snapshot
+            .into_iter()
+            .map(|(metric, current)| {
+                // The contributions aren't held, so the aggregation stands in for them
+                let detail =
+                    format!("metric {} of {} after {} contributions", metric, source, current.increments);
+                let derivation =
+                    Derivation::new("ReadAggregation", detail, vec![]).with_provenance(current.provenance);
+                let sum_id = self.store_derived(Value::Integer(current.value), &req.session_id, derivation)?;
+                Ok(MetricSnapshot {
+                    metric,
+                    contributions: current.increments,
+                    sum_fingerprint: self.ciphertext_fingerprint(&sum_id),
+                    sum_id,
+                })
+            })
+            .collect::<Result<Vec<_>, Status>>()?;
+
+        Ok(Response::new(AggregationSnapshot {
+            aggregation_id: req.aggregation_id,
+            metrics,
+            window_start_unix_seconds: window,
+            window_end_unix_seconds: end,
+            closed,
+        }))
+    }
+
+    async fn list_aggregation_windows(
+        &self,
+        request: Request<ListAggregationWindowsRequest>,
+    ) -> Result<Response<ListAggregationWindowsResponse>, Status> {
+        self.authorize(&request, "ListAggregationWindows", "").await?;
+        let req = request.into_inner();
+
+        let aggregation = self.load_aggregation(&req.aggregation_id)?;
+        let windowing = aggregation
+            .windowing()
+            .ok_or_else(|| ErrorReason::InvalidRequest.status("The aggregation has no windows"))?;
+        let now = unix_now();
+        let windows = aggregation
+            .windows()
+            .into_iter()
+            .map(|start| {
+                let end = windowing.end(start);
+                AggregationWindow {
+                    start_unix_seconds: start,
+                    end_unix_seconds: end,
+                    contributions: aggregation.window_contributions(start),
+                    closed: end <= now,
+                }
+            })
+            .collect();
+
+        Ok(Response::new(ListAggregationWindowsResponse { windows }))
+    }
+
+    async fn delete_aggregation(
+        &self,
+        request: Request<DeleteAggregationRequest>,
+    ) -> Result<Response<AggregationResponse>, Status> {
+        self.authorize(&request, "DeleteAggregation", "").await?;
+        let req = request.into_inner();
+
+        let aggregation = self
+            .aggregations
+            .remove(&req.aggregation_id)
+            .ok_or_else(|| ErrorReason::AggregationNotFound.status("Aggregation not found"))?;
+        info!("Deleted aggregation {}", req.aggregation_id);
+
+        Ok(Response::new(AggregationResponse {
+            aggregation_id: req.aggregation_id,
+            contributions: aggregation.contributions(),
+        }))
+    }
+
+    async fn create_election(
+        &self,
+        mut request: Request<CreateElectionRequest>,
+    ) -> Result<Response<ElectionResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "CreateElection", &request.get_ref().server_key_id).await?;
+        let req = request.into_inner();
+
+        let options = req.num_options as usize;
+        if options == 0 {
+            return Err(ErrorReason::InvalidRequest.status("An election needs at least one option"));
+        }
+        if options > MAX_OPTIONS {
+            return Err(ErrorReason::LimitExceeded.status(format!(
+                "Election has {} options, the limit is {}",
+                options, MAX_OPTIONS
+            )));
+        }
+
+        let server_key = self
+            .key_store
+            .get_server_key(&req.server_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Server key not found"))?;
+
+        // Zero is public, so trivial encryptions are enough to start the tallies from
+        tfhe::set_server_key((*server_key).clone());
+        let tallies = (0..options)
+            .map(|_| FheUint8::try_encrypt_trivial(0u8))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| ErrorReason::Internal.status(format!("Failed to encode zero: {}", e)))?;
+
+        let election_id = self.elections.create(&req.server_key_id, tallies);
+        info!("Created election {} with {} options", election_id, options);
+
+        let status = self.load_election(&election_id)?.status();
+        Ok(Response::new(election_response(election_id, status)))
+    }
+
+    async fn cast_ballot(
+        &self,
+        request: Request<CastBallotRequest>,
+    ) -> Result<Response<ElectionResponse>, Status> {
+        self.authorize(&request, "CastBallot", "").await?;
+        let cancellation = request_cancellation(&request);
+        let tenant = request_tenant(&request);
+        let req = request.into_inner();
+
+        let election = self.load_election(&req.election_id)?;
+        election.check_open().map_err(election_error)?;
+        let options = election.status().options;
+        if req.choice_ids.len() != options {
+            return Err(ErrorReason::ShapeMismatch.status(format!(
+                "Ballot has {} choices, the election has {} options",
+                req.choice_ids.len(),
+                options
+            )));
+        }
+
+        let server_key = self
+            .key_store
+            .get_server_key(election.server_key_id())
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Server key not found"))?;
+        let choices = self.load_integer_vector(&req.choice_ids)?;
+        let ballot = self.ciphertext_store.derive(req.choice_ids.iter().map(String::as_str));
+
+        let worker_cancellation = cancellation.clone();
+        let usage = UsageTag::new(tenant, election.server_key_id(), "CastBallot");
+        let worker_election = election.clone();
+        let status = self.run_blocking(usage, &cancellation, move || {
+            // The high-level tfhe API evaluates against a thread-local server key
+            tfhe::set_server_key((*server_key).clone());
+
+            let contribution = tally::contribution(&choices, &worker_cancellation).map_err(|e| {
+                evaluation_status(e, |e| ErrorReason::Internal.status(format!("Ballot check failed: {}", e)))
+            })?;
+            worker_election.cast(&contribution, &ballot).map_err(election_error)
+        })
+        .await?;
+
+        Ok(Response::new(election_response(req.election_id, status)))
+    }
+
+    async fn close_election(
+        &self,
+        request: Request<CloseElectionRequest>,
+    ) -> Result<Response<ElectionResponse>, Status> {
+        self.authorize(&request, "CloseElection", "").await?;
+        let req = request.into_inner();
+
+        let status = self.load_election(&req.election_id)?.close();
+        info!("Closed election {} after {} ballots", req.election_id, status.ballots);
+
+        Ok(Response::new(election_response(req.election_id, status)))
+    }
+
+    async fn get_tally(
+        &self,
+        request: Request<GetTallyRequest>,
+    ) -> Result<Response<TallyResponse>, Status> {
+        self.authorize(&request, "GetTally", "").await?;
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+
+        let election = self.load_election(&req.election_id)?;
+        let (tallies, ballots) = election.tallies().map_err(election_error)?;
+        let provenance = election.provenance();
+
+        // Nor are the ballots, so the election stands in for them
+        let tally_ids: Vec<String> = tallies
+            .into_iter()
+            .enumerate()
+            .map(|(option, tally)| {
+                let detail = format!("election {} option {}", req.election_id, option);
+                let derivation =
+                    Derivation::new("GetTally", detail, vec![]).with_provenance(provenance.clone());
+                self.store_derived(Value::Integer(tally), &req.session_id, derivation)
+            })
+            .collect::<Result<Vec<_>, Status>>()?;
+        let tally_fingerprints = tally_ids.iter().map(|id| self.ciphertext_fingerprint(id)).collect();
+
+        Ok(Response::new(TallyResponse {
+            election_id: req.election_id,
+            ballots,
+            tally_ids,
+            tally_fingerprints,
+        }))
+    }
+
+    async fn encrypt_matrix(
+        &self,
+        mut request: Request<EncryptMatrixRequest>,
+    ) -> Result<Response<MatrixResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "EncryptMatrix", &request.get_ref().client_key_id).await?;
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+
+        // Get the client key
+        let client_key = self
+            .key_store
+            .get_client_key(&req.client_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Client key not found"))?;
+
+        if req.values.len() > MAX_MATRIX_ELEMENTS {
+            return Err(ErrorReason::LimitExceeded.status(format!(
+                "Matrix has {} elements, the limit is {}",
+                req.values.len(),
+                MAX_MATRIX_ELEMENTS
+            )));
+        }
+
+        self.key_store.reseed_thread();
+        let elements = req
+            .values
+            .iter()
+            .map(|value| {
+                FheUint8::try_encrypt(plaintext_integer(*value)?, &*client_key)
+                    .map_err(|e| ErrorReason::Internal.status(format!("Encryption failed: {}", e)))
+            })
+            .collect::<Result<Vec<_>, Status>>()?;
+        let matrix = EncryptedMatrix::new(req.rows as usize, req.cols as usize, elements)
+            .map_err(|e| ErrorReason::ShapeMismatch.status(e.to_string()))?;
+
+        Ok(Response::new(self.store_matrix(matrix, &req.session_id, None)?))
+    }
+
+    async fn decrypt_matrix(
+        &self,
+        mut request: Request<DecryptMatrixRequest>,
+    ) -> Result<Response<DecryptMatrixResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "DecryptMatrix", &request.get_ref().client_key_id).await?;
+        let req = request.into_inner();
+        self.check_exact_decryption("DecryptMatrix")?;
+
+        // Get the client key
+        let client_key = self
+            .key_store
+            .get_client_key(&req.client_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Client key not found"))?;
+
+        self.check_stored_aggregation(&req.matrix_id)?;
+        let matrix = self.load_matrix(&req.matrix_id)?;
+        let values = matrix
+            .elements()
+            .iter()
+            .map(|element| <FheUint8 as FheDecrypt<u8>>::decrypt(element, &*client_key) as i64)
+            .collect();
+
+        Ok(Response::new(DecryptMatrixResponse {
+            rows: matrix.rows() as u32,
+            cols: matrix.cols() as u32,
+            values,
+        }))
+    }
+
+    async fn matrix_vector_product(
+        &self,
+        mut request: Request<MatrixVectorProductRequest>,
+    ) -> Result<Response<MatrixVectorProductResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "MatrixVectorProduct", &request.get_ref().server_key_id).await?;
+        let cancellation = request_cancellation(&request);
+        let tenant = request_tenant(&request);
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+
+        // Get the server key
+        let server_key = self
+            .key_store
+            .get_server_key(&req.server_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Server key not found"))?;
+
+        let matrix = self.load_matrix(&req.matrix_id)?;
+        let (rows, cols) = (matrix.rows(), matrix.cols());
+
+        // Rows are evaluated on the rayon pool, whose workers install the server key themselves
+        let worker_cancellation = cancellation.clone();
+        let usage = UsageTag::new(tenant, &req.server_key_id, "MatrixVectorProduct");
+        let product = match (req.vector_ids.is_empty(), req.plaintext_vector.is_empty()) {
+            (false, true) => {
+                let vector = self.load_integer_vector(&req.vector_ids)?;
+                self.run_blocking(usage, &cancellation, move || {
+                    matrix
+                        .multiply_vector(&server_key, &vector, &worker_cancellation)
+                        .map_err(|e| {
+                            evaluation_status(e, |e| ErrorReason::ShapeMismatch.status(e.to_string()))
+                        })
+                })
+                .await?
+            }
+            (true, false) => {
+                let vector = req
+                    .plaintext_vector
+                    .iter()
+                    .copied()
+                    .map(plaintext_integer)
+                    .collect::<Result<Vec<_>, Status>>()?;
+                self.run_blocking(usage, &cancellation, move || {
+                    matrix
+                        .multiply_plaintext_vector(&server_key, &vector, &worker_cancellation)
+                        .map_err(|e| {
+                            evaluation_status(e, |e| ErrorReason::ShapeMismatch.status(e.to_string()))
+                        })
+                })
+                .await?
+            }
+            _ => {
+                return Err(ErrorReason::InvalidRequest.status(
+                    "Exactly one of vector_ids and plaintext_vector must be set",
+                ))
+            }
+        };
+
+        let parents = self
+            .ciphertext_store
+            .parents(std::iter::once(&req.matrix_id).chain(&req.vector_ids).map(String::as_str));
+        let derivation = Derivation::new("MatrixVectorProduct", "", parents);
+        let result_ids: Vec<String> = product
+            .into_iter()
+            .enumerate()
+            .map(|(row, value)| {
+                let derivation = derivation.with_detail(format!("row {}", row));
+                self.store_derived(Value::Integer(Arc::new(value)), &req.session_id, derivation)
+            })
+            .collect::<Result<Vec<_>, Status>>()?;
+        let result_fingerprints = result_ids.iter().map(|id| self.ciphertext_fingerprint(id)).collect();
+
+        info!("Multiplied {}x{} matrix by a vector", rows, cols);
+
+        Ok(Response::new(MatrixVectorProductResponse {
+            result_ids,
+            result_fingerprints,
+        }))
+    }
+
+    async fn matrix_add(
+        &self,
+        mut request: Request<MatrixAddRequest>,
+    ) -> Result<Response<MatrixResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "MatrixAdd", &request.get_ref().server_key_id).await?;
+        let tenant = request_tenant(&request);
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+
+        // Get the server key
+        let server_key = self
+            .key_store
+            .get_server_key(&req.server_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Server key not found"))?;
+
+        let a = self.load_matrix(&req.a_id)?;
+        let b = self.load_matrix(&req.b_id)?;
+        let usage = UsageTag::new(tenant, &req.server_key_id, "MatrixAdd");
+        let sum = self
+            .metered(usage, || a.add(&server_key, &b))
+            .map_err(|e| ErrorReason::ShapeMismatch.status(e.to_string()))?;
+
+        let parents = self.ciphertext_store.parents([req.a_id.as_str(), req.b_id.as_str()]);
+        let derivation = Derivation::new("MatrixAdd", "", parents);
+        Ok(Response::new(self.store_matrix(sum, &req.session_id, Some(derivation))?))
+    }
+
+    async fn matrix_scale(
+        &self,
+        mut request: Request<MatrixScaleRequest>,
+    ) -> Result<Response<MatrixResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "MatrixScale", &request.get_ref().server_key_id).await?;
+        let tenant = request_tenant(&request);
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+
+        // Get the server key
+        let server_key = self
+            .key_store
+            .get_server_key(&req.server_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Server key not found"))?;
+
+        let matrix = self.load_matrix(&req.matrix_id)?;
+        let scalar = plaintext_integer(req.scalar)?;
+        let usage = UsageTag::new(tenant, &req.server_key_id, "MatrixScale");
+        let scaled = self.metered(usage, || matrix.scale(&server_key, scalar));
+
+        let parents = vec![self.ciphertext_store.parent(&req.matrix_id)];
+        let derivation = Derivation::new("MatrixScale", "", parents);
+        Ok(Response::new(self.store_matrix(scaled, &req.session_id, Some(derivation))?))
+    }
+
+    async fn encrypt_timestamp(
+        &self,
+        mut request: Request<EncryptTimestampRequest>,
+    ) -> Result<Response<EncryptedDataResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "EncryptTimestamp", &request.get_ref().client_key_id).await?;
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+
+        // Get the client key
+        let client_key = self
+            .key_store
+            .get_client_key(&req.client_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Client key not found"))?;
+
+        // A key held to narrow integers is held to their cost too
+        let policy = self.key_store.policy(&req.client_key_id);
+        if policy.integer_bits != 0 {
+            return Err(ErrorReason::PolicyViolation.status(format!(
+                "Integers under this key are {}-bit; timestamps are 32-bit",
+                policy.integer_bits
+            )));
+        }
+
+        let value = plaintext_timestamp(req.value)?;
+        self.key_store.reseed_thread();
+        let timestamp = EncryptedTimestamp::encrypt(&client_key, time_unit(req.unit()), value)
+            .map_err(|e| ErrorReason::Internal.status(e.to_string()))?;
+
+        Ok(Response::new(self.store_timestamp(timestamp, &req.session_id, None)?))
+    }
+
+    async fn decrypt_timestamp(
+        &self,
+        mut request: Request<DecryptTimestampRequest>,
+    ) -> Result<Response<TimestampResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "DecryptTimestamp", &request.get_ref().client_key_id).await?;
+        let req = request.into_inner();
+        self.check_exact_decryption("DecryptTimestamp")?;
+
+        // Get the client key
+        let client_key = self
+            .key_store
+            .get_client_key(&req.client_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Client key not found"))?;
+
+        self.check_stored_aggregation(&req.timestamp_id)?;
+        let timestamp = self.load_timestamp(&req.timestamp_id)?;
+        self.leases.release(&req.timestamp_id);
+
+        Ok(Response::new(TimestampResponse {
+            value: timestamp.decrypt(&client_key) as i64,
+            unit: time_unit_to_proto(timestamp.unit()) as i32,
+        }))
+    }
+
+    async fn compare_timestamp(
+        &self,
+        mut request: Request<CompareTimestampRequest>,
+    ) -> Result<Response<EvaluationResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "CompareTimestamp", &request.get_ref().server_key_id).await?;
+        let cancellation = request_cancellation(&request);
+        let tenant = request_tenant(&request);
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+
+        // Get the server key
+        let server_key = self
+            .key_store
+            .get_server_key(&req.server_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Server key not found"))?;
+        self.check_booleans_allowed(&req.server_key_id, true)?;
+
+        let a = self.load_timestamp(&req.timestamp_id)?;
+        let comparison = timestamp_comparison(req.comparison());
+        let usage = UsageTag::new(tenant, &req.server_key_id, "CompareTimestamp");
+        let result = match &req.other {
+            Some(Other::OtherId(other_id)) => {
+                let b = self.load_timestamp(other_id)?;
+                check_same_unit(&a, &b)?;
+                self.run_blocking(usage, &cancellation, move || {
+                    // The high-level tfhe API evaluates against a thread-local server key
+                    tfhe::set_server_key((*server_key).clone());
+                    a.compare(&b, comparison)
+                        .map_err(|e| ErrorReason::Internal.status(e.to_string()))
+                })
+                .await?
+            }
+            Some(Other::OtherValue(value)) => {
+                let b = plaintext_timestamp(*value)?;
+                self.run_blocking(usage, &cancellation, move || {
+                    tfhe::set_server_key((*server_key).clone());
+                    Ok(a.compare_plaintext(b, comparison))
+                })
+                .await?
+            }
+            None => {
+                return Err(ErrorReason::InvalidRequest.status("One of other_id and other_value must be set"))
+            }
+        };
+
+        let mut ids = vec![req.timestamp_id.as_str()];
+        if let Some(Other::OtherId(other_id)) = &req.other {
+            ids.push(other_id);
+        }
+        let parents = self.ciphertext_store.parents(ids);
+        let derivation = Derivation::new("CompareTimestamp", req.comparison().as_str_name(), parents);
+        let result_id = self.store_derived(Value::Boolean(Arc::new(result)), &req.session_id, derivation)?;
+
+        Ok(Response::new(EvaluationResponse {
+            result_fingerprint: self.ciphertext_fingerprint(&result_id),
+            lease_expires_unix_seconds: self.lease_results(&req.session_id, &[&result_id]),
+            result_id,
+            serialized_result: vec![],
+            overflow_id: String::new(),
+        }))
+    }
+
+    async fn timestamp_difference(
+        &self,
+        mut request: Request<TimestampDifferenceRequest>,
+    ) -> Result<Response<EncryptedDataResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "TimestampDifference", &request.get_ref().server_key_id).await?;
+        let cancellation = request_cancellation(&request);
+        let tenant = request_tenant(&request);
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+
+        // Get the server key
+        let server_key = self
+            .key_store
+            .get_server_key(&req.server_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Server key not found"))?;
+
+        let a = self.load_timestamp(&req.a_id)?;
+        let b = self.load_timestamp(&req.b_id)?;
+        check_same_unit(&a, &b)?;
+        let usage = UsageTag::new(tenant, &req.server_key_id, "TimestampDifference");
+        let difference = self
+            .run_blocking(usage, &cancellation, move || {
+                tfhe::set_server_key((*server_key).clone());
+                a.difference(&b).map_err(|e| ErrorReason::Internal.status(e.to_string()))
+            })
+            .await?;
+
+        let parents = self.ciphertext_store.parents([req.a_id.as_str(), req.b_id.as_str()]);
+        let derivation = Derivation::new("TimestampDifference", "", parents);
+        Ok(Response::new(self.store_timestamp(difference, &req.session_id, Some(derivation))?))
+    }
+
+    async fn bucket_timestamp(
+        &self,
+        mut request: Request<BucketTimestampRequest>,
+    ) -> Result<Response<EvaluationResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "BucketTimestamp", &request.get_ref().server_key_id).await?;
+        let cancellation = request_cancellation(&request);
+        let tenant = request_tenant(&request);
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+
+        // Get the server key
+        let server_key = self
+            .key_store
+            .get_server_key(&req.server_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Server key not found"))?;
+
+        if req.boundaries.len() > MAX_BUCKET_BOUNDARIES {
+            return Err(ErrorReason::LimitExceeded.status(format!(
+                "{} boundaries given, the limit is {}",
+                req.boundaries.len(),
+                MAX_BUCKET_BOUNDARIES
+            )));
+        }
+        let boundaries = req
+            .boundaries
+            .iter()
+            .copied()
+            .map(plaintext_timestamp)
+            .collect::<Result<Vec<_>, Status>>()?;
+        if boundaries.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(ErrorReason::InvalidRequest.status("Boundaries must be strictly ascending"));
+        }
+
+        let timestamp = self.load_timestamp(&req.timestamp_id)?;
+        let usage = UsageTag::new(tenant, &req.server_key_id, "BucketTimestamp");
+        let bucket = self
+            .run_blocking(usage, &cancellation, move || {
+                tfhe::set_server_key((*server_key).clone());
+                timestamp
+                    .bucket(&boundaries)
+                    .map_err(|e| ErrorReason::Internal.status(e.to_string()))
+            })
+            .await?;
+
+        let detail = format!("{} boundaries", req.boundaries.len());
+        let parents = vec![self.ciphertext_store.parent(&req.timestamp_id)];
+        let derivation = Derivation::new("BucketTimestamp", detail, parents);
+        let result_id = self.store_derived(Value::Integer(Arc::new(bucket)), &req.session_id, derivation)?;
+
+        Ok(Response::new(EvaluationResponse {
+            result_fingerprint: self.ciphertext_fingerprint(&result_id),
+            lease_expires_unix_seconds: self.lease_results(&req.session_id, &[&result_id]),
+            result_id,
+            serialized_result: vec![],
+            overflow_id: String::new(),
+        }))
+    }
+
+    async fn encrypt_real_vector(
+        &self,
+        mut request: Request<EncryptRealVectorRequest>,
+    ) -> Result<Response<EncryptedDataResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "EncryptRealVector", &request.get_ref().client_key_id).await?;
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+
+        if req.values.len() > MAX_REAL_VECTOR_LENGTH {
+            return Err(ErrorReason::LimitExceeded.status(format!(
+                "Vector has {} values, the limit is {}",
+                req.values.len(),
+                MAX_REAL_VECTOR_LENGTH
+            )));
+        }
+        ckks::check_values(&req.values).map_err(|e| ErrorReason::ValueOutOfRange.status(e.to_string()))?;
+
+        let encrypted_data_id = self
+            .ckks
+            .encrypt_real_vector(&req.client_key_id, &req.values)
+            .map_err(|e| backend_status(e, "Encrypted data"))?;
+        self.track_in_session(&req.session_id, &encrypted_data_id)?;
+
+        Ok(Response::new(EncryptedDataResponse {
+            fingerprint: self.ciphertext_fingerprint(&encrypted_data_id),
+            encrypted_data_id,
+            serialized_data: vec![],
+        }))
+    }
+
+    async fn decrypt_real_vector(
+        &self,
+        mut request: Request<DecryptRealVectorRequest>,
+    ) -> Result<Response<RealVectorResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "DecryptRealVector", &request.get_ref().client_key_id).await?;
+        let req = request.into_inner();
+        self.check_exact_decryption("DecryptRealVector")?;
+        self.check_stored_aggregation(&req.encrypted_data_id)?;
+
+        let values = self
+            .ckks
+            .decrypt_real_vector(&req.client_key_id, &req.encrypted_data_id)
+            .map_err(|e| backend_status(e, "Encrypted data"))?;
+        self.leases.release(&req.encrypted_data_id);
+
+        Ok(Response::new(RealVectorResponse { values }))
+    }
+
+    async fn evaluate_real_vector(
+        &self,
+        mut request: Request<RealVectorEvaluationRequest>,
+    ) -> Result<Response<EvaluationResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "EvaluateRealVector", &request.get_ref().server_key_id).await?;
+        let cancellation = request_cancellation(&request);
+        let tenant = request_tenant(&request);
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+
+        let operation = real_vector_operation(req.operation(), req.rotation);
+        if req.operand_ids.len() != operation.arity() {
+            return Err(ErrorReason::ArityMismatch.status(format!(
+                "{:?} takes {} operands, got {}",
+                req.operation(),
+                operation.arity(),
+                req.operand_ids.len()
+            )));
+        }
+
+        // Key switching for multiplication and rotation is heavy, so it runs off the runtime
+        let ckks = self.ckks.clone();
+        let server_key_id = req.server_key_id.clone();
+        let operand_ids = req.operand_ids.clone();
+        let usage = UsageTag::new(tenant, &req.server_key_id, "EvaluateRealVector");
+        let result_id = self
+            .run_blocking(usage, &cancellation, move || {
+                let operand_ids: Vec<&str> = operand_ids.iter().map(String::as_str).collect();
+                ckks
+                    .evaluate(&server_key_id, operation, &operand_ids)
+                    .map_err(|e| backend_status(e, "Operand"))
+            })
+            .await?;
+        let parents = self.ciphertext_store.parents(req.operand_ids.iter().map(String::as_str));
+        let derivation = Derivation::new("EvaluateRealVector", req.operation().as_str_name(), parents);
+        self.ciphertext_store.set_derivation(&result_id, derivation);
+        self.track_in_session(&req.session_id, &result_id)?;
+
+        Ok(Response::new(EvaluationResponse {
+            result_fingerprint: self.ciphertext_fingerprint(&result_id),
+            lease_expires_unix_seconds: self.lease_results(&req.session_id, &[&result_id]),
+            result_id,
+            serialized_result: vec![],
+            overflow_id: String::new(),
+        }))
+    }
+
+    async fn encrypt_integer_batch(
+        &self,
+        mut request: Request<EncryptIntegerBatchRequest>,
+    ) -> Result<Response<EncryptedDataResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "EncryptIntegerBatch", &request.get_ref().client_key_id).await?;
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+
+        if req.values.len() > MAX_INTEGER_BATCH_LENGTH {
+            return Err(ErrorReason::LimitExceeded.status(format!(
+                "Batch has {} values, the limit is {}",
+                req.values.len(),
+                MAX_INTEGER_BATCH_LENGTH
+            )));
+        }
+        bgv::check_values(&req.values).map_err(|e| ErrorReason::ValueOutOfRange.status(e.to_string()))?;
+
+        let encrypted_data_id = self
+            .bgv
+            .encrypt_integer_batch(&req.client_key_id, &req.values)
+            .map_err(|e| backend_status(e, "Encrypted data"))?;
+        self.track_in_session(&req.session_id, &encrypted_data_id)?;
+
+        Ok(Response::new(EncryptedDataResponse {
+            fingerprint: self.ciphertext_fingerprint(&encrypted_data_id),
+            encrypted_data_id,
+            serialized_data: vec![],
+        }))
+    }
+
+    async fn decrypt_integer_batch(
+        &self,
+        mut request: Request<DecryptIntegerBatchRequest>,
+    ) -> Result<Response<IntegerBatchResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "DecryptIntegerBatch", &request.get_ref().client_key_id).await?;
+        let req = request.into_inner();
+        self.check_exact_decryption("DecryptIntegerBatch")?;
+        self.check_stored_aggregation(&req.encrypted_data_id)?;
+
+        let values = self
+            .bgv
+            .decrypt_integer_batch(&req.client_key_id, &req.encrypted_data_id)
+            .map_err(|e| backend_status(e, "Encrypted data"))?;
+        self.leases.release(&req.encrypted_data_id);
+
+        Ok(Response::new(IntegerBatchResponse { values }))
+    }
+
+    async fn evaluate_integer_batch(
+        &self,
+        mut request: Request<IntegerBatchEvaluationRequest>,
+    ) -> Result<Response<EvaluationResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "EvaluateIntegerBatch", &request.get_ref().server_key_id).await?;
+        let cancellation = request_cancellation(&request);
+        let tenant = request_tenant(&request);
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+
+        let operation = integer_batch_operation(req.operation(), req.rotation);
+        if req.operand_ids.len() != operation.arity() {
+            return Err(ErrorReason::ArityMismatch.status(format!(
+                "{:?} takes {} operands, got {}",
+                req.operation(),
+                operation.arity(),
+                req.operand_ids.len()
+            )));
+        }
+
+        let bgv = self.bgv.clone();
+        let server_key_id = req.server_key_id.clone();
+        let operand_ids = req.operand_ids.clone();
+        let usage = UsageTag::new(tenant, &req.server_key_id, "EvaluateIntegerBatch");
+        let result_id = self
+            .run_blocking(usage, &cancellation, move || {
+                let operand_ids: Vec<&str> = operand_ids.iter().map(String::as_str).collect();
+                bgv.evaluate(&server_key_id, operation, &operand_ids)
+                    .map_err(|e| backend_status(e, "Operand"))
+            })
+            .await?;
+        let parents = self.ciphertext_store.parents(req.operand_ids.iter().map(String::as_str));
+        let derivation = Derivation::new("EvaluateIntegerBatch", req.operation().as_str_name(), parents);
+        self.ciphertext_store.set_derivation(&result_id, derivation);
+        self.track_in_session(&req.session_id, &result_id)?;
+
+        Ok(Response::new(EvaluationResponse {
+            result_fingerprint: self.ciphertext_fingerprint(&result_id),
+            lease_expires_unix_seconds: self.lease_results(&req.session_id, &[&result_id]),
+            result_id,
+            serialized_result: vec![],
+            overflow_id: String::new(),
+        }))
+    }
+
+    async fn generate_re_encryption_key(
+        &self,
+        mut request: Request<ReEncryptionKeyRequest>,
+    ) -> Result<Response<ReEncryptionKeyResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "GenerateReEncryptionKey", &request.get_ref().source_client_key_id).await?;
+        let req = request.into_inner();
+
+        let source_scheme = self
+            .key_store
+            .client_key_scheme(&req.source_client_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Source client key not found"))?;
+        let target_scheme = self
+            .key_store
+            .client_key_scheme(&req.target_client_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Target client key not found"))?;
+        if req.source_client_key_id == req.target_client_key_id {
+            return Err(ErrorReason::InvalidRequest.status("Source and target client keys are the same"));
+        }
+        if source_scheme != target_scheme {
+            return Err(ErrorReason::InvalidRequest.status(format!(
+                "Can't re-encrypt from a {:?} key to a {:?} key",
+                source_scheme, target_scheme
+            )));
+        }
+        self.check_memory()?;
+
+        let re_encryption_key_id = match source_scheme {
+            KeyScheme::Tfhe => {
+                return Err(
+                    ErrorReason::Unsupported.status("Re-encryption is only available for CKKS and BGV keys")
+                )
+            }
+            KeyScheme::Ckks => self
+                .ckks
+                .generate_re_encryption_key(&req.source_client_key_id, &req.target_client_key_id),
+            KeyScheme::Bgv => self
+                .bgv
+                .generate_re_encryption_key(&req.source_client_key_id, &req.target_client_key_id),
         }
+        .map_err(|e| backend_status(e, "Re-encryption key"))?;
+
+        Ok(Response::new(ReEncryptionKeyResponse { re_encryption_key_id }))
     }
-}
 
-#[tonic::async_trait]
-impl FheService for FheServiceImpl {
-    async fn generate_keys(
+    async fn re_encrypt(
         &self,
-        request: Request<KeyGenerationRequest>,
-    ) -> Result<Response<KeyGenerationResponse>, Status> {
-        let parameter_set = match request.get_ref().parameter_set {
-            0 => "DEFAULT",
-            1 => "FAST",
-            2 => "SECURE",
-            _ => return Err(Status::invalid_argument("Invalid parameter set")),
-        };
+        request: Request<ReEncryptRequest>,
+    ) -> Result<Response<EncryptedDataResponse>, Status> {
+        self.authorize(&request, "ReEncrypt", &request.get_ref().re_encryption_key_id).await?;
+        let cancellation = request_cancellation(&request);
+        let tenant = request_tenant(&request);
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
 
-        info!("Generating keys with parameter set: {}", parameter_set);
-        
-        let (client_key_id, server_key_id) = self
+        let scheme = self
             .key_store
-            .generate_keys(parameter_set)
-            .map_err(|e| Status::internal(format!("Failed to generate keys: {}", e)))?;
+            .get_re_encryption_key(&req.re_encryption_key_id)
+            .map(|re_encryption| re_encryption.scheme)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Re-encryption key not found"))?;
 
-        Ok(Response::new(KeyGenerationResponse {
-            client_key_id,
-            server_key_id,
+        let ckks = self.ckks.clone();
+        let bgv = self.bgv.clone();
+        let re_encryption_key_id = req.re_encryption_key_id.clone();
+        let encrypted_data_id = req.encrypted_data_id.clone();
+        let usage = UsageTag::new(tenant, &req.re_encryption_key_id, "ReEncrypt");
+        let result_id = self
+            .run_blocking(usage, &cancellation, move || {
+                match scheme {
+                    KeyScheme::Ckks => ckks.re_encrypt(&re_encryption_key_id, &encrypted_data_id),
+                    KeyScheme::Bgv => bgv.re_encrypt(&re_encryption_key_id, &encrypted_data_id),
+                    KeyScheme::Tfhe => Err(BackendError::ReEncryptionKeyNotFound),
+                }
+                .map_err(|e| backend_status(e, "Encrypted data"))
+            })
+            .await?;
+        // The same values under another key, from the same inputs
+        let parents = vec![self.ciphertext_store.parent(&req.encrypted_data_id)];
+        let derivation = Derivation::new("ReEncrypt", "", parents);
+        self.ciphertext_store.set_derivation(&result_id, derivation);
+        self.track_in_session(&req.session_id, &result_id)?;
+
+        Ok(Response::new(EncryptedDataResponse {
+            fingerprint: self.ciphertext_fingerprint(&result_id),
+            encrypted_data_id: result_id,
+            serialized_data: vec![],
         }))
     }
 
-    async fn encrypt_boolean(
+    async fn run_inference(
         &self,
-        request: Request<EncryptBooleanRequest>,
-    ) -> Result<Response<EncryptedDataResponse>, Status> {
+        mut request: Request<InferenceRequest>,
+    ) -> Result<Response<InferenceResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "RunInference", &request.get_ref().server_key_id).await?;
+        let cancellation = request_cancellation(&request);
+        let tenant = request_tenant(&request);
         let req = request.into_inner();
-        
-        // Get the client key
-        let client_key = self
+        self.check_session(&req.session_id)?;
+
+        // Get the server key
+        let server_key = self
             .key_store
-            .get_client_key(&req.client_key_id)
-            .ok_or_else(|| Status::not_found("Client key not found"))?;
+            .get_server_key(&req.server_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Server key not found"))?;
+
+        let layers = req.layers.iter().map(model_layer).collect::<Result<Vec<_>, Status>>()?;
+        let model = Model::new(layers).map_err(|e| ErrorReason::ShapeMismatch.status(e.to_string()))?;
+        let inputs = self.load_integer_vector(&req.input_ids)?;
+
+        let layer_count = model.layers().len();
+
+        // Neurons are evaluated on the rayon pool, whose workers install the server key themselves
+        let worker_cancellation = cancellation.clone();
+        let usage = UsageTag::new(tenant, &req.server_key_id, "RunInference");
+        let predictions = self.run_blocking(usage, &cancellation, move || {
+            model
+                .run(&server_key, &inputs, &worker_cancellation)
+                .map_err(|e| evaluation_status(e, |e| ErrorReason::ShapeMismatch.status(e.to_string())))
+        })
+        .await?;
+
+        // Dense layers feed every input into every prediction
+        let parents = self.ciphertext_store.parents(req.input_ids.iter().map(String::as_str));
+        let derivation = Derivation::new("RunInference", "", parents);
+        let prediction_ids: Vec<String> = predictions
+            .into_iter()
+            .enumerate()
+            .map(|(output, value)| {
+                let derivation = derivation.with_detail(format!("prediction {}", output));
+                self.store_derived(Value::Integer(Arc::new(value)), &req.session_id, derivation)
+            })
+            .collect::<Result<Vec<_>, Status>>()?;
+        let prediction_fingerprints = prediction_ids.iter().map(|id| self.ciphertext_fingerprint(id)).collect();
+
+        info!(
+            "Ran {}-layer model over {} encrypted inputs",
+            layer_count,
+            req.input_ids.len()
+        );
 
-        // Need to dereference Arc to get the ClientKey reference
-        let client_key_ref = &*client_key;
+        Ok(Response::new(InferenceResponse {
+            prediction_ids,
+            prediction_fingerprints,
+        }))
+    }
+
+    async fn decrypt_boolean(
+        &self,
+        mut request: Request<DecryptBooleanRequest>,
+    ) -> Result<Response<BooleanResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "DecryptBoolean", &request.get_ref().client_key_id).await?;
+        let req = request.into_inner();
+        self.check_exact_decryption("DecryptBoolean")?;
+        self.check_stored_aggregation(&req.encrypted_data_id)?;
+        
+        let value = self
+            .backend
+            .decrypt_boolean(&req.client_key_id, &req.encrypted_data_id)
+            .map_err(|e| backend_status(e, "Encrypted data"))?;
+        // Once fetched, a result is evicted with its session like any other ciphertext
+        self.leases.release(&req.encrypted_data_id);
         
-        // Encrypt the boolean value
-        let encrypted = FheBool::try_encrypt(req.value, client_key_ref)
-            .map_err(|e| Status::internal(format!("Encryption failed: {}", e)))?;
+        Ok(Response::new(BooleanResponse { value }))
+    }
+
+    async fn decrypt_integer(
+        &self,
+        mut request: Request<DecryptIntegerRequest>,
+    ) -> Result<Response<IntegerResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "DecryptInteger", &request.get_ref().client_key_id).await?;
+        let req = request.into_inner();
+        let noise = req.noise.as_ref().map(privacy_noise).transpose()?;
+        if noise.is_none() && self.privacy.config().require_noise {
+            return Err(ErrorReason::PolicyViolation
+                .status("Integers may only be decrypted with privacy noise on this server"));
+        }
+        // A key that outlives a restart can't have a budget that doesn't
+        if noise.is_some() && self.key_store.key_directory_path().is_some() && !self.privacy.is_durable() {
+            return Err(ErrorReason::Unsupported
+                .status("Noised decryptions under persisted keys need a durable privacy ledger"));
+        }
+        self.check_stored_aggregation(&req.encrypted_data_id)?;
         
-        // Store the encrypted value
-        let encrypted_data_id = self.ciphertext_store.store_boolean(encrypted);
+        let value = self
+            .backend
+            .decrypt_integer(&req.client_key_id, &req.encrypted_data_id)
+            .map_err(|e| backend_status(e, "Encrypted data"))?;
+        self.leases.release(&req.encrypted_data_id);
+        let Some(noise) = noise else {
+            return Ok(Response::new(IntegerResponse {
+                value: value as i64,
+                budget: None,
+            }));
+        };
         
-        Ok(Response::new(EncryptedDataResponse {
-            encrypted_data_id,
-            serialized_data: vec![], // For simplicity, not serializing the data
+        // Charged only once the decryption has succeeded, and before anything is returned
+        let remaining = self
+            .privacy
+            .charge(&req.client_key_id, noise.cost())
+            .map_err(privacy_status)?;
+        Ok(Response::new(IntegerResponse {
+            value: noise.apply(value as i64),
+            budget: Some(PrivacyBudget {
+                epsilon_remaining: remaining.epsilon,
+                delta_remaining: remaining.delta,
+            }),
         }))
     }
 
-    async fn encrypt_integer(
+    async fn export_ciphertext(
         &self,
-        request: Request<EncryptIntegerRequest>,
-    ) -> Result<Response<EncryptedDataResponse>, Status> {
+        request: Request<ExportCiphertextRequest>,
+    ) -> Result<Response<ExportCiphertextResponse>, Status> {
+        self.authorize(&request, "ExportCiphertext", "").await?;
         let req = request.into_inner();
-        
-        // Get the client key
-        let client_key = self
-            .key_store
-            .get_client_key(&req.client_key_id)
-            .ok_or_else(|| Status::not_found("Client key not found"))?;
+        let (ciphertext_type, serialized_data, fingerprint) =
+            export_stored(&self.ciphertext_store, &req.encrypted_data_id)?;
+        self.leases.release(&req.encrypted_data_id);
 
-        // Need to dereference Arc to get the ClientKey reference
-        let client_key_ref = &*client_key;
-        
-        // Simplifying to always use uint8 for the example
-        // In a real implementation, you'd choose the integer type based on the num_bits
-        if req.value < 0 || req.value > 255 {
-            return Err(Status::invalid_argument("Value out of range for uint8"));
+        Ok(Response::new(ExportCiphertextResponse {
+            ciphertext_type: ciphertext_type as i32,
+            serialized_data,
+            fingerprint,
+        }))
+    }
+
+    type StreamCiphertextsStream = Pin<Box<dyn Stream<Item = Result<CiphertextChunk, Status>> + Send>>;
+
+    async fn stream_ciphertexts(
+        &self,
+        request: Request<StreamCiphertextsRequest>,
+    ) -> Result<Response<Self::StreamCiphertextsStream>, Status> {
+        self.authorize(&request, "StreamCiphertexts", "").await?;
+        let req = request.into_inner();
+
+        let source = match (req.encrypted_data_ids.is_empty(), req.matrix_id.is_empty()) {
+            (false, true) => StreamSource::Ids(req.encrypted_data_ids),
+            (true, false) => StreamSource::Matrix(self.load_matrix(&req.matrix_id)?),
+            _ => {
+                return Err(ErrorReason::InvalidRequest.status(
+                    "Exactly one of encrypted_data_ids and matrix_id must be set",
+                ))
+            }
+        };
+
+        let start = req.offset as usize;
+        if start > source.len() {
+            return Err(ErrorReason::OffsetOutOfRange.status(format!(
+                "Offset {} is past the end of {} elements",
+                start,
+                source.len()
+            )));
         }
+        let end = match req.limit {
+            0 => source.len(),
+            limit => source.len().min(start + limit as usize),
+        };
+
+        // Serialize each element only when the client is ready for it, so a large
+        // vector never has to fit in memory or in a single message
+        let store = self.ciphertext_store.clone();
+        let chunks = tokio_stream::iter(start..end).map(move |index| source.chunk(&store, index));
+
+        Ok(Response::new(Box::pin(chunks)))
+    }
+
+    async fn import_ciphertext(
+        &self,
+        request: Request<ImportCiphertextRequest>,
+    ) -> Result<Response<EncryptedDataResponse>, Status> {
+        self.authorize(&request, "ImportCiphertext", "").await?;
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+        self.check_subject(&req.subject_id)?;
+
+        let encrypted_data_id = import_serialized(
+            &self.ciphertext_store,
+            req.ciphertext_type(),
+            &req.serialized_data,
+            &req.fingerprint,
+        )?;
+
+        self.track_in_session(&req.session_id, &encrypted_data_id)?;
+        self.tag_subject(&req.subject_id, &encrypted_data_id);
+        info!("Imported ciphertext {}", encrypted_data_id);
 
-        // Encrypt the integer value
-        let encrypted = FheUint8::try_encrypt(req.value as u8, client_key_ref)
-            .map_err(|e| Status::internal(format!("Encryption failed: {}", e)))?;
-        
-        // Store the encrypted value
-        let encrypted_data_id = self.ciphertext_store.store_integer(encrypted);
-        
         Ok(Response::new(EncryptedDataResponse {
+            fingerprint: self.ciphertext_fingerprint(&encrypted_data_id),
             encrypted_data_id,
-            serialized_data: vec![], // For simplicity, not serializing the data
+            serialized_data: vec![],
         }))
     }
 
-    async fn evaluate_operation(
+    async fn create_session(
         &self,
-        request: Request<EvaluationRequest>,
-    ) -> Result<Response<EvaluationResponse>, Status> {
+        request: Request<CreateSessionRequest>,
+    ) -> Result<Response<CreateSessionResponse>, Status> {
+        self.authorize(&request, "CreateSession", "").await?;
         let req = request.into_inner();
-        
+
+        let idle_timeout = match req.idle_timeout_seconds {
+            0 => DEFAULT_IDLE_TIMEOUT,
+            seconds => Duration::from_secs(seconds.into()).min(MAX_IDLE_TIMEOUT),
+        };
+
+        let session_id = self.sessions.create(idle_timeout);
+        info!("Created session {} with idle timeout {:?}", session_id, idle_timeout);
+
+        Ok(Response::new(CreateSessionResponse {
+            session_id,
+            idle_timeout_seconds: idle_timeout.as_secs() as u32,
+        }))
+    }
+
+    async fn close_session(
+        &self,
+        request: Request<CloseSessionRequest>,
+    ) -> Result<Response<CloseSessionResponse>, Status> {
+        self.authorize(&request, "CloseSession", "").await?;
+        let req = request.into_inner();
+
+        let freed = self
+            .evict_session(&req.session_id)
+            .ok_or_else(|| ErrorReason::SessionNotFound.status("Session not found"))?;
+        info!("Closed session {}, freed {} ciphertexts", req.session_id, freed);
+
+        Ok(Response::new(CloseSessionResponse {
+            freed_ciphertexts: freed as u32,
+        }))
+    }
+
+    async fn release_results(
+        &self,
+        request: Request<ReleaseResultsRequest>,
+    ) -> Result<Response<ReleaseResultsResponse>, Status> {
+        self.authorize(&request, "ReleaseResults", "").await?;
+        let req = request.into_inner();
+
+        let released = req.result_ids.iter().filter(|id| self.leases.release(id)).count();
+
+        Ok(Response::new(ReleaseResultsResponse {
+            released: released as u32,
+        }))
+    }
+
+    async fn delete_ciphertexts(
+        &self,
+        request: Request<DeleteCiphertextsRequest>,
+    ) -> Result<Response<DeleteCiphertextsResponse>, Status> {
+        self.authorize(&request, "DeleteCiphertexts", "").await?;
+        let req = request.into_inner();
+
+        let deleted = req
+            .encrypted_data_ids
+            .iter()
+            .filter(|id| self.ciphertext_store.soft_delete(id))
+            .count();
+        let retention = self.ciphertext_store.retention();
+        info!("Deleted {} ciphertexts, restorable for {:?}", deleted, retention);
+
+        Ok(Response::new(DeleteCiphertextsResponse {
+            deleted: deleted as u32,
+            retention_seconds: retention.as_secs(),
+        }))
+    }
+
+    async fn tag_ciphertext(
+        &self,
+        request: Request<TagCiphertextRequest>,
+    ) -> Result<Response<TagCiphertextResponse>, Status> {
+        self.authorize(&request, "TagCiphertext", "").await?;
+        let req = request.into_inner();
+
+        if self.ciphertext_store.kind(&req.encrypted_data_id).is_none() {
+            return Err(ErrorReason::CiphertextNotFound.status("Ciphertext not found"));
+        }
+        let set = req.tags.into_iter().collect();
+        let tags = self
+            .tags
+            .update(&req.encrypted_data_id, set, &req.remove, req.replace)
+            .map_err(|e| ErrorReason::InvalidRequest.status(e.to_string()))?;
+
+        Ok(Response::new(TagCiphertextResponse {
+            tags: tags.into_iter().collect(),
+        }))
+    }
+
+    async fn query_ciphertexts(
+        &self,
+        request: Request<QueryCiphertextsRequest>,
+    ) -> Result<Response<QueryCiphertextsResponse>, Status> {
+        self.authorize(&request, "QueryCiphertexts", "").await?;
+        let req = request.into_inner();
+
+        let page_size = match req.page_size as usize {
+            0 => DEFAULT_QUERY_PAGE_SIZE,
+            requested => requested.min(MAX_QUERY_PAGE_SIZE),
+        };
+        let after = match req.page_token.as_str() {
+            "" => None,
+            token => Some(
+                tags::parse_page_token(token)
+                    .map_err(|e| ErrorReason::InvalidRequest.status(e.to_string()))?,
+            ),
+        };
+        let filters: Vec<_> = req
+            .filters
+            .into_iter()
+            .map(|filter| (filter.key, filter.value))
+            .collect();
+        let mut found = self.queried_ciphertexts(&filters);
+        let newest_first = req.order == CreationOrder::NewestFirst as i32;
+        if newest_first {
+            found.reverse();
+        }
+        if let Some(after) = after {
+            found.retain(|position| match newest_first {
+                true => *position < after,
+                false => *position > after,
+            });
+        }
+        let next_page_token = match found.len() > page_size {
+            true => tags::page_token(&found[page_size - 1]),
+            false => String::new(),
+        };
+        found.truncate(page_size);
+
+        Ok(Response::new(QueryCiphertextsResponse {
+            ciphertexts: found
+                .into_iter()
+                .map(|(created, id)| TaggedCiphertext {
+                    tags: self.tags.tags(&id).into_iter().collect(),
+                    created_unix_seconds: created.as_secs(),
+                    encrypted_data_id: id,
+                })
+                .collect(),
+            next_page_token,
+        }))
+    }
+
+    async fn get_lineage(
+        &self,
+        request: Request<GetLineageRequest>,
+    ) -> Result<Response<LineageResponse>, Status> {
+        self.authorize(&request, "GetLineage", "").await?;
+        let req = request.into_inner();
+
+        if self.ciphertext_store.kind(&req.encrypted_data_id).is_none() {
+            return Err(ErrorReason::CiphertextNotFound.status("Ciphertext not found"));
+        }
+        let max_nodes = match req.max_nodes as usize {
+            0 => DEFAULT_LINEAGE_NODES,
+            requested => requested.min(MAX_LINEAGE_NODES),
+        };
+        let derivation = self.ciphertext_store.derivation(&req.encrypted_data_id);
+        let (nodes, truncated) = provenance::lineage(&req.encrypted_data_id, derivation, max_nodes);
+
+        Ok(Response::new(LineageResponse {
+            nodes: nodes
+                .into_iter()
+                .map(|node| LineageNode {
+                    encrypted_data_id: node.id,
+                    operation: node.operation,
+                    detail: node.detail,
+                    parent_indices: node.parents.into_iter().map(|index| index as u32).collect(),
+                    history_truncated: node.truncated,
+                })
+                .collect(),
+            truncated,
+        }))
+    }
+
+    async fn ingest_encrypted_records(
+        &self,
+        request: Request<Streaming<EncryptedRecord>>,
+    ) -> Result<Response<IngestSummary>, Status> {
+        self.authorize(&request, "IngestEncryptedRecords", "").await?;
+        self.ingest(request.into_inner()).await.map(Response::new)
+    }
+
+    async fn map_operation(
+        &self,
+        mut request: Request<MapOperationRequest>,
+    ) -> Result<Response<MapJobStatus>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "MapOperation", &request.get_ref().server_key_id).await?;
+        let tenant = request_tenant(&request);
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+
         // Get the server key
         let server_key = self
             .key_store
             .get_server_key(&req.server_key_id)
-            .ok_or_else(|| Status::not_found("Server key not found"))?;
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Server key not found"))?;
 
-        // Validate the operands
-        if req.operand_ids.is_empty() {
-            return Err(Status::invalid_argument("No operands provided"));
+        if req.result_prefix == req.label_prefix {
+            return Err(ErrorReason::InvalidRequest.status("result_prefix must differ from label_prefix"));
         }
+        // Taken before the request is picked apart, for the digest identifying the job
+        let job = (req.checkpoint.is_some() || !req.dedup_token.is_empty()).then(|| {
+            let job = MapOperationRequest {
+                session_id: String::new(),
+                callback: None,
+                checkpoint: None,
+                dedup_token: String::new(),
+                ..req.clone()
+            };
+            job.encode_to_vec()
+        });
 
-        match req.operation() {
-            // Boolean operations
-            OperationType::And | OperationType::Or | OperationType::Xor => {
-                if req.operand_ids.len() != 2 {
-                    return Err(Status::invalid_argument("Binary operation requires 2 operands"));
-                }
+        // A lone operation becomes a one-gate circuit over the record and the operands
+        let mut circuit = if req.gates.is_empty() {
+            Circuit {
+                gates: vec![Gate {
+                    operation: circuit_operation(req.operation)?,
+                    inputs: (0..=req.operand_ids.len()).map(Wire::Input).collect(),
+                }],
+                outputs: vec![],
+            }
+        } else {
+            build_circuit(&req.gates, &[])?
+        };
+        circuit.outputs = vec![Wire::Gate(circuit.gates.len() - 1)];
+        let operands = self.load_inputs(&req.operand_ids)?;
+        let operand_parents = self.ciphertext_store.parents(req.operand_ids.iter().map(String::as_str));
 
-                let a = self
-                    .ciphertext_store
-                    .get_boolean(&req.operand_ids[0])
-                    .ok_or_else(|| Status::not_found("First operand not found"))?;
+        let mut map = MapCircuit {
+            circuit,
+            operands,
+            operand_parents,
+            operation: "MapOperation",
+            session_id: req.session_id,
+            sink: self.open_sink(req.sink)?,
+            journal: None,
+        };
+        let records: Vec<MapInput> = self
+            .labeled_records(&req.label_prefix)?
+            .into_iter()
+            .map(|(label, id)| MapInput {
+                result_label: format!("{}{}", req.result_prefix, &label[req.label_prefix.len()..]),
+                label,
+                ids: vec![id],
+            })
+            .collect();
+        self.validate_map_circuit(&req.server_key_id, &map, &records[0])?;
+        let digest = job.map(|job| self.map_job_digest(&job, &req.operand_ids, &records));
+        let checkpoint = self.open_checkpoint(req.checkpoint, || digest.clone().unwrap_or_default())?;
 
-                let b = self
-                    .ciphertext_store
-                    .get_boolean(&req.operand_ids[1])
-                    .ok_or_else(|| Status::not_found("Second operand not found"))?;
+        let count = records.len();
+        let callback = self.callback_url(req.callback)?;
+        map.journal = match self.claim_map_job(&tenant, &req.dedup_token, digest, count)? {
+            MapJobStart::Started(status) => return Ok(Response::new(status)),
+            MapJobStart::Run(journal) => journal,
+        };
+        let usage = UsageTag::new(tenant, &req.server_key_id, "MapOperation");
+        let (job_id, status) = self
+            .start_map_job(usage, server_key, map, records, callback, checkpoint)
+            .await?;
+        info!(
+            "Map job {}: {} records under '{}' to '{}'",
+            job_id, count, req.label_prefix, req.result_prefix
+        );
 
-                let result = match req.operation() {
-                    OperationType::And => operations::boolean_and(&server_key, &a, &b),
-                    OperationType::Or => operations::boolean_or(&server_key, &a, &b),
-                    OperationType::Xor => operations::boolean_xor(&server_key, &a, &b),
-                    _ => unreachable!(),
-                };
+        Ok(Response::new(status))
+    }
 
-                let result_id = self.ciphertext_store.store_boolean(result);
-                
-                Ok(Response::new(EvaluationResponse {
-                    result_id,
-                    serialized_result: vec![],
-                }))
-            }
-            
-            // Unary boolean operation
-            OperationType::Not => {
-                if req.operand_ids.len() != 1 {
-                    return Err(Status::invalid_argument("Unary operation requires 1 operand"));
-                }
+    async fn join_operation(
+        &self,
+        mut request: Request<JoinOperationRequest>,
+    ) -> Result<Response<MapJobStatus>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "JoinOperation", &request.get_ref().server_key_id).await?;
+        let tenant = request_tenant(&request);
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
 
-                let a = self
-                    .ciphertext_store
-                    .get_boolean(&req.operand_ids[0])
-                    .ok_or_else(|| Status::not_found("Operand not found"))?;
-
-                let result = operations::boolean_not(&server_key, &a);
-                let result_id = self.ciphertext_store.store_boolean(result);
-                
-                Ok(Response::new(EvaluationResponse {
-                    result_id,
-                    serialized_result: vec![],
-                }))
-            }
-            
-            // Integer operations
-            OperationType::Add | OperationType::Subtract | OperationType::Multiply => {
-                if req.operand_ids.len() != 2 {
-                    return Err(Status::invalid_argument("Binary operation requires 2 operands"));
-                }
+        // Get the server key
+        let server_key = self
+            .key_store
+            .get_server_key(&req.server_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Server key not found"))?;
 
-                let a = self
-                    .ciphertext_store
-                    .get_integer(&req.operand_ids[0])
-                    .ok_or_else(|| Status::not_found("First operand not found"))?;
+        if req.result_prefix == req.left_prefix || req.result_prefix == req.right_prefix {
+            let message = "result_prefix must differ from left_prefix and right_prefix";
+            return Err(ErrorReason::InvalidRequest.status(message));
+        }
+        // Taken before the request is picked apart, for the digest identifying the job
+        let job = (req.checkpoint.is_some() || !req.dedup_token.is_empty()).then(|| {
+            let job = JoinOperationRequest {
+                session_id: String::new(),
+                callback: None,
+                checkpoint: None,
+                dedup_token: String::new(),
+                ..req.clone()
+            };
+            job.encode_to_vec()
+        });
 
-                let b = self
-                    .ciphertext_store
-                    .get_integer(&req.operand_ids[1])
-                    .ok_or_else(|| Status::not_found("Second operand not found"))?;
+        // The left record is input 0 and its partner input 1
+        let mut map = MapCircuit {
+            circuit: Circuit {
+                gates: vec![Gate {
+                    operation: circuit_operation(req.operation)?,
+                    inputs: vec![Wire::Input(0), Wire::Input(1)],
+                }],
+                outputs: vec![Wire::Gate(0)],
+            },
+            operands: vec![],
+            operand_parents: vec![],
+            operation: "JoinOperation",
+            session_id: req.session_id,
+            sink: self.open_sink(req.sink)?,
+            journal: None,
+        };
 
-                let result = match req.operation() {
-                    OperationType::Add => operations::integer_add(&a, &b),
-                    OperationType::Subtract => operations::integer_subtract(&a, &b),
-                    OperationType::Multiply => operations::integer_multiply(&a, &b),
-                    _ => unreachable!(),
-                };
+        // Records pair up by key, the part of the label after the prefix; a key found on
+        // only one side is left out
+        let right: HashMap<String, String> = self
+            .labeled_records(&req.right_prefix)?
+            .into_iter()
+            .map(|(label, id)| (label[req.right_prefix.len()..].to_string(), id))
+            .collect();
+        let records: Vec<MapInput> = self
+            .labeled_records(&req.left_prefix)?
+            .into_iter()
+            .filter_map(|(label, id)| {
+                let key = &label[req.left_prefix.len()..];
+                let partner = right.get(key)?.clone();
+                Some(MapInput {
+                    result_label: format!("{}{}", req.result_prefix, key),
+                    label,
+                    ids: vec![id, partner],
+                })
+            })
+            .collect();
+        let first = records.first().ok_or_else(|| {
+            ErrorReason::CiphertextNotFound.status(format!(
+                "No keys appear under both '{}' and '{}'",
+                req.left_prefix, req.right_prefix
+            ))
+        })?;
+        self.validate_map_circuit(&req.server_key_id, &map, first)?;
+        let digest = job.map(|job| self.map_job_digest(&job, &[], &records));
+        let checkpoint = self.open_checkpoint(req.checkpoint, || digest.clone().unwrap_or_default())?;
 
-                let result_id = self.ciphertext_store.store_integer(result);
-                
-                Ok(Response::new(EvaluationResponse {
-                    result_id,
-                    serialized_result: vec![],
-                }))
-            }
-            
-            // Comparison operations - simplified for demo
-            OperationType::GreaterThan | OperationType::LessThan | OperationType::Equal => {
-                Err(Status::unimplemented("Comparison operations not implemented in this demo"))
-            }
-        }
+        let count = records.len();
+        let callback = self.callback_url(req.callback)?;
+        map.journal = match self.claim_map_job(&tenant, &req.dedup_token, digest, count)? {
+            MapJobStart::Started(status) => return Ok(Response::new(status)),
+            MapJobStart::Run(journal) => journal,
+        };
+        let usage = UsageTag::new(tenant, &req.server_key_id, "JoinOperation");
+        let (job_id, status) = self
+            .start_map_job(usage, server_key, map, records, callback, checkpoint)
+            .await?;
+        info!(
+            "Join job {}: {} keys under '{}' and '{}' to '{}'",
+            job_id, count, req.left_prefix, req.right_prefix, req.result_prefix
+        );
+
+        Ok(Response::new(status))
     }
 
-    async fn decrypt_boolean(
+    async fn get_map_job(
         &self,
-        request: Request<DecryptBooleanRequest>,
-    ) -> Result<Response<BooleanResponse>, Status> {
+        request: Request<GetMapJobRequest>,
+    ) -> Result<Response<MapJobStatus>, Status> {
+        self.authorize(&request, "GetMapJob", "").await?;
         let req = request.into_inner();
-        
-        // Get the client key
-        let client_key = self
+
+        let job = self
+            .map_jobs
+            .get(&req.job_id)
+            .ok_or_else(|| ErrorReason::JobNotFound.status("Map job not found"))?;
+
+        Ok(Response::new(map_job_status(&req.job_id, &job.progress())))
+    }
+
+    async fn reduce_operation(
+        &self,
+        mut request: Request<ReduceOperationRequest>,
+    ) -> Result<Response<EvaluationResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "ReduceOperation", &request.get_ref().server_key_id).await?;
+        let cancellation = request_cancellation(&request);
+        let tenant = request_tenant(&request);
+        let req = request.into_inner();
+        self.check_session(&req.session_id)?;
+
+        // Get the server key
+        let server_key = self
             .key_store
-            .get_client_key(&req.client_key_id)
-            .ok_or_else(|| Status::not_found("Client key not found"))?;
+            .get_server_key(&req.server_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Server key not found"))?;
 
-        // Need to dereference Arc to get the ClientKey reference
-        let client_key_ref = &*client_key;
-        
-        // Get the encrypted value
-        let encrypted = self
-            .ciphertext_store
-            .get_boolean(&req.encrypted_data_id)
-            .ok_or_else(|| Status::not_found("Encrypted data not found"))?;
+        let reduction = req.reduction();
+        let saturate = req.overflow() == OverflowBehavior::Saturate;
+        if saturate && reduction != Reduction::Sum {
+            return Err(ErrorReason::InvalidRequest.status("overflow only applies to SUM"));
+        }
 
-        // Decrypt the value
-        let value = encrypted.decrypt(client_key_ref);
-        
-        Ok(Response::new(BooleanResponse { value }))
+        let records = self.labeled_records(&req.label_prefix)?;
+        let parents = self.ciphertext_store.parents(records.iter().map(|(_, id)| id.as_str()));
+        let detail = format!("{} of '{}'", reduction.as_str_name(), req.label_prefix);
+        let derivation = Derivation::new("ReduceOperation", detail, parents);
+        let usage = UsageTag::new(tenant, &req.server_key_id, "ReduceOperation");
+        let check = cancellation.clone();
+        let failed = |e: anyhow::Error| {
+            evaluation_status(e, |e| ErrorReason::Internal.status(format!("Reduction failed: {}", e)))
+        };
+        let result: Ciphertext = match reduction {
+            Reduction::Sum | Reduction::Min | Reduction::Max => {
+                let values = records
+                    .iter()
+                    .map(|(label, id)| self.load_integer(id, &format!("Record {}", label)))
+                    .collect::<Result<Vec<_>, Status>>()?;
+                let combine: fn(&FheUint8, &FheUint8) -> FheUint8 = match (reduction, saturate) {
+                    (Reduction::Sum, false) => operations::integer_add,
+                    (Reduction::Sum, true) => operations::integer_saturating_add,
+                    (Reduction::Min, _) => operations::integer_min,
+                    _ => operations::integer_max,
+                };
+                let reduced = self
+                    .run_blocking(usage, &cancellation, move || {
+                        vector::reduce_tree(values, &server_key, &check, |a, b| Arc::new(combine(a, b)))
+                            .map_err(failed)
+                    })
+                    .await?;
+                Ciphertext::Integer(reduced)
+            }
+            Reduction::Any | Reduction::All => {
+                self.check_booleans_allowed(&req.server_key_id, true)?;
+                let values = records
+                    .iter()
+                    .map(|(label, id)| self.load_boolean(id, &format!("Record {}", label)))
+                    .collect::<Result<Vec<_>, Status>>()?;
+                let flag = self
+                    .run_blocking(usage, &cancellation, move || {
+                        vector::reduce_tree(values, &server_key, &check, |a, b| {
+                            Arc::new(match reduction {
+                                Reduction::Any => operations::boolean_or(&server_key, a, b),
+                                _ => operations::boolean_and(&server_key, a, b),
+                            })
+                        })
+                        .map_err(failed)
+                    })
+                    .await?;
+                Ciphertext::Boolean(flag)
+            }
+        };
+        info!("Reduced {} records under '{}'", records.len(), req.label_prefix);
+
+        let result_id = self.ciphertext_store.store(result);
+        self.ciphertext_store.set_derivation(&result_id, derivation);
+        self.track_in_session(&req.session_id, &result_id)?;
+        if !req.result_label.is_empty() {
+            self.labels.set(&req.result_label, &result_id);
+        }
+
+        Ok(Response::new(EvaluationResponse {
+            result_fingerprint: self.ciphertext_fingerprint(&result_id),
+            lease_expires_unix_seconds: self.lease_results(&req.session_id, &[&result_id]),
+            result_id,
+            serialized_result: vec![],
+            overflow_id: String::new(),
+        }))
     }
 
-    async fn decrypt_integer(
+    async fn transform_series(
         &self,
-        request: Request<DecryptIntegerRequest>,
-    ) -> Result<Response<IntegerResponse>, Status> {
+        mut request: Request<TransformSeriesRequest>,
+    ) -> Result<Response<TransformSeriesResponse>, Status> {
+        self.resolve_aliases(&mut request);
+        self.authorize(&request, "TransformSeries", &request.get_ref().server_key_id).await?;
+        let cancellation = request_cancellation(&request);
+        let tenant = request_tenant(&request);
         let req = request.into_inner();
-        
-        // Get the client key
-        let client_key = self
+        self.check_session(&req.session_id)?;
+
+        // Get the server key
+        let server_key = self
             .key_store
-            .get_client_key(&req.client_key_id)
-            .ok_or_else(|| Status::not_found("Client key not found"))?;
+            .get_server_key(&req.server_key_id)
+            .ok_or_else(|| ErrorReason::KeyNotFound.status("Server key not found"))?;
 
-        // Need to dereference Arc to get the ClientKey reference
-        let client_key_ref = &*client_key;
-        
-        // Get the encrypted value
-        let encrypted = self
-            .ciphertext_store
-            .get_integer(&req.encrypted_data_id)
-            .ok_or_else(|| Status::not_found("Encrypted data not found"))?;
+        if req.result_prefix == req.label_prefix {
+            return Err(ErrorReason::InvalidRequest.status("result_prefix must differ from label_prefix"));
+        }
+        let transform = req.transform();
+        let saturate = req.overflow() == OverflowBehavior::Saturate;
+        if saturate && transform == SeriesTransform::AbsoluteDeltas {
+            return Err(ErrorReason::InvalidRequest.status("ABSOLUTE_DELTAS never overflows"));
+        }
+        let records = self.labeled_records(&req.label_prefix)?;
+        if records.len() > MAX_VECTOR_LENGTH {
+            return Err(ErrorReason::LimitExceeded.status(format!(
+                "Series has {} points, the limit is {}",
+                records.len(),
+                MAX_VECTOR_LENGTH
+            )));
+        }
+        let cumulative = transform == SeriesTransform::CumulativeSum;
+        if !cumulative && records.len() < 2 {
+            return Err(ErrorReason::InvalidRequest.status("Deltas need at least two points"));
+        }
+        let values = records
+            .iter()
+            .map(|(label, id)| self.load_integer(id, &format!("Record {}", label)))
+            .collect::<Result<Vec<_>, Status>>()?;
 
-        // Decrypt the value - explicitly specify u8 as the type
-        let value = <FheUint8 as FheDecrypt<u8>>::decrypt(&encrypted, client_key_ref) as i64;
-        
-        Ok(Response::new(IntegerResponse { value }))
+        let combine: fn(&FheUint8, &FheUint8) -> FheUint8 = match (transform, saturate) {
+            (SeriesTransform::Deltas, false) => operations::integer_subtract,
+            (SeriesTransform::Deltas, true) => operations::integer_saturating_sub,
+            (SeriesTransform::AbsoluteDeltas, _) => operations::integer_abs_diff,
+            (SeriesTransform::CumulativeSum, false) => operations::integer_add,
+            (SeriesTransform::CumulativeSum, true) => operations::integer_saturating_add,
+        };
+        let check = cancellation.clone();
+        let usage = UsageTag::new(tenant, &req.server_key_id, "TransformSeries");
+        let results = self
+            .run_blocking(usage, &cancellation, move || {
+                let results = match cumulative {
+                    true => vector::scan(values, &server_key, &check, |a, b| Arc::new(combine(a, b))),
+                    // Each delta is the later point against the earlier one
+                    false => vector::pairwise(&values, &server_key, &check, |earlier, later| {
+                        Arc::new(combine(later, earlier))
+                    }),
+                };
+                results.map_err(|e| {
+                    evaluation_status(e, |e| {
+                        ErrorReason::Internal.status(format!("Series transform failed: {}", e))
+                    })
+                })
+            })
+            .await?;
+
+        // A delta takes the label of its later point, so the first point has none
+        let labeled = &records[records.len() - results.len()..];
+        let mut points: Vec<SeriesPoint> = Vec::with_capacity(results.len());
+        for (i, ((label, id), result)) in labeled.iter().zip(results).enumerate() {
+            // A running sum comes from the one before it and its own point
+            let parent_ids = match (cumulative, points.last()) {
+                (true, Some(previous)) => vec![previous.encrypted_data_id.as_str(), id.as_str()],
+                (true, None) => vec![id.as_str()],
+                (false, _) => vec![records[i].1.as_str(), id.as_str()],
+            };
+            let parents = self.ciphertext_store.parents(parent_ids);
+            let detail = format!("{} at '{}'", transform.as_str_name(), label);
+            let derivation = Derivation::new("TransformSeries", detail, parents);
+            let encrypted_data_id = self.store_derived(Value::Integer(result), &req.session_id, derivation)?;
+            let result_label = format!("{}{}", req.result_prefix, &label[req.label_prefix.len()..]);
+            self.labels.set(&result_label, &encrypted_data_id);
+            points.push(SeriesPoint {
+                label: result_label,
+                encrypted_data_id,
+            });
+        }
+        info!(
+            "Transformed {} points under '{}' with {}",
+            records.len(),
+            req.label_prefix,
+            transform.as_str_name()
+        );
+
+        Ok(Response::new(TransformSeriesResponse { points }))
     }
-} 
\ No newline at end of file
+}
@@ -0,0 +1,70 @@
+use std::sync::Arc;
+
+use tonic::service::interceptor::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+
+use crate::api::FheServiceServer;
+use crate::service::transport::TransportConfig;
+use crate::service::FheServiceImpl;
+
+type BoxedInterceptor = Arc<dyn Fn(Request<()>) -> Result<Request<()>, Status> + Send + Sync>;
+
+// Interceptors every data-plane call passes through before reaching the service, in the
+// order they were added. Each sees the call's metadata and extensions and may change them,
+// or refuse the call with a status, in which case the rest never run. The server binary
+// puts OIDC authentication first; applications embedding the server add their own, such
+// as another authentication scheme, audit logging or propagating tracing headers.
+#[derive(Clone, Default)]
+pub struct Interceptors {
+    chain: Vec<BoxedInterceptor>,
+}
+
+impl Interceptors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Run the interceptor after those already added. Any `FnMut(Request<()>) ->
+    // Result<Request<()>, Status>` closure is one; it is cloned for each call.
+    pub fn with<I>(mut self, interceptor: I) -> Self
+    where
+        I: Interceptor + Clone + Send + Sync + 'static,
+    {
+        self.chain
+            .push(Arc::new(move |request| interceptor.clone().call(request)));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.chain.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chain.is_empty()
+    }
+}
+
+impl Interceptor for Interceptors {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        self.chain
+            .iter()
+            .try_fold(request, |request, interceptor| interceptor(request))
+    }
+}
+
+pub type InterceptedFheService = InterceptedService<FheServiceServer<FheServiceImpl>, Interceptors>;
+
+// The main service as the server binary mounts it, with the transport's message size
+// limits, behind the interceptors. Add it to a `TransportConfig::server()`, after any tower
+// layers of your own, to serve the same API from your own binary.
+pub fn fhe_service_server(
+    service: FheServiceImpl,
+    transport: &TransportConfig,
+    interceptors: Interceptors,
+) -> InterceptedFheService {
+    let server = FheServiceServer::new(service)
+        .max_decoding_message_size(transport.max_request_bytes)
+        .max_encoding_message_size(transport.max_response_bytes);
+    InterceptedService::new(server, interceptors)
+}
@@ -0,0 +1,455 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::circuit::checkpoint::SavedValue;
+use crate::crypto::fingerprint::to_hex;
+
+const MAX_DEDUP_TOKEN_LENGTH: usize = 128;
+
+// How long a finished job's token is remembered when the operator doesn't say
+pub const DEFAULT_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+// A step of a journaled job, appended to its journal before the server acts on it
+#[derive(Serialize, Deserialize)]
+pub enum JournalEntry {
+    Started {
+        tenant: String,
+        token: String,
+        // Identifies the request and the data it reads, as for checkpoints
+        digest: String,
+        job_id: String,
+        total: usize,
+    },
+    // A record's result now exists, or is about to under the ID the entry gives it
+    Committed(CommittedRecord),
+    Finished,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CommittedRecord {
+    pub label: String,
+    pub result_label: String,
+    pub output: CommittedOutput,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum CommittedOutput {
+    // Stored under this ID. The value is kept too, so a restart can store it again.
+    Stored { id: String, value: SavedValue },
+    // Written to the job's sink, at this file path or object URL
+    Written(String),
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum JournalError {
+    #[error("Dedup token '{0}' must be 1 to 128 letters, digits, '-', '_' or '.'")]
+    InvalidToken(String),
+    #[error("Dedup token '{0}' already names a different job")]
+    Conflict(String),
+    #[error("{0}")]
+    Storage(String),
+}
+
+// The journal of one job: a file of length-prefixed entries, each synced to disk before
+// the step it records is taken. A crash mid-append leaves a torn last entry, which replay
+// drops, so a step is either journaled whole or not at all. Without a journal directory
+// entries go nowhere, and jobs are only deduplicated until the server restarts.
+pub struct JobLog {
+    file: Option<Mutex<(File, u64)>>,
+    // When the job finished; for a replayed job, when its journal was last written
+    finished: Mutex<Option<SystemTime>>,
+}
+
+impl JobLog {
+    fn new(file: Option<(File, u64)>, finished: Option<SystemTime>) -> Self {
+        Self {
+            file: file.map(Mutex::new),
+            finished: Mutex::new(finished),
+        }
+    }
+
+    pub fn append(&self, entry: &JournalEntry) -> Result<()> {
+        let Some(file) = &self.file else {
+            self.record_finish(entry);
+            return Ok(());
+        };
+        let bytes =
+            bincode::serialize(entry).map_err(|e| anyhow!("Failed to encode journal entry: {}", e))?;
+        let mut framed = Vec::with_capacity(bytes.len() + 8);
+        framed.extend_from_slice(&(bytes.len() as u64).to_be_bytes());
+        framed.extend_from_slice(&bytes);
+        let mut file = file.lock().unwrap();
+        let (handle, length) = &mut *file;
+        match handle.write_all(&framed).and_then(|_| handle.sync_data()) {
+            Ok(()) => {
+                *length += framed.len() as u64;
+                self.record_finish(entry);
+                Ok(())
+            }
+            Err(e) => {
+                // Cut off whatever part was written, so later entries don't follow a torn one
+                let _ = handle.set_len(*length);
+                Err(anyhow!("Failed to append to job journal: {}", e))
+            }
+        }
+    }
+
+    fn record_finish(&self, entry: &JournalEntry) {
+        if let JournalEntry::Finished = entry {
+            *self.finished.lock().unwrap() = Some(SystemTime::now());
+        }
+    }
+
+    // Whether the job finished at least retention ago
+    fn expired(&self, now: SystemTime, retention: Duration) -> bool {
+        self.finished
+            .lock()
+            .unwrap()
+            .is_some_and(|finished| now.duration_since(finished).is_ok_and(|age| age >= retention))
+    }
+}
+
+// A job read back from the journal directory at startup
+pub struct ReplayedJob {
+    pub job_id: String,
+    pub total: usize,
+    pub committed: Vec<CommittedRecord>,
+    pub finished: bool,
+}
+
+// What a dedup token names, for the caller to act on
+pub enum Claim {
+    // A job already started under the token, running or done; its ID and record count
+    Existing {
+        job_id: String,
+        total: usize,
+    },
+    // A job a restart cut short, to carry on under its ID, skipping the committed records
+    Resume {
+        job_id: String,
+        log: Arc<JobLog>,
+        committed: Vec<CommittedRecord>,
+    },
+    // A new job, whose start is already journaled
+    New {
+        job_id: String,
+        log: Arc<JobLog>,
+    },
+}
+
+struct Claimed {
+    digest: String,
+    job_id: String,
+    total: usize,
+    log: Arc<JobLog>,
+    // Set for a job replayed unfinished, until a retry carries it on
+    interrupted: Option<Vec<CommittedRecord>>,
+}
+
+// Map and join jobs by the dedup token their client gave them. The same token with the
+// same request gets the job already started instead of a second one, so a client can
+// retry a call whose response it never saw. With a directory, every job's steps are
+// journaled there, so that holds across restarts too, and results committed before a
+// crash are stored again rather than computed twice. A finished job's token, and its
+// journal, are kept for the retention, after which the token starts a new job.
+pub struct JobJournal {
+    dir: Option<PathBuf>,
+    retention: Duration,
+    jobs: Mutex<HashMap<(String, String), Claimed>>,
+}
+
+impl Default for JobJournal {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            retention: DEFAULT_RETENTION,
+            jobs: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl JobJournal {
+    // HERMETIC_FHE_JOB_JOURNAL_DIR is the directory journals are kept in, which should
+    // outlive the process. Unset, tokens are remembered in memory only.
+    // HERMETIC_FHE_JOB_JOURNAL_RETENTION_SECONDS is how long finished jobs are kept, a day
+    // by default.
+    pub fn from_env() -> Result<Self> {
+        let journal = match std::env::var("HERMETIC_FHE_JOB_JOURNAL_DIR") {
+            Ok(dir) => Self::with_dir(dir)?,
+            Err(_) => Self::default(),
+        };
+        let retention = match std::env::var("HERMETIC_FHE_JOB_JOURNAL_RETENTION_SECONDS") {
+            Ok(seconds) => seconds.trim().parse().map(Duration::from_secs).map_err(|_| {
+                anyhow!("HERMETIC_FHE_JOB_JOURNAL_RETENTION_SECONDS must be a whole number of seconds")
+            })?,
+            Err(_) => DEFAULT_RETENTION,
+        };
+        Ok(journal.with_retention(retention))
+    }
+
+    // Journal jobs under dir, creating it if needed
+    pub fn with_dir(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)
+            .map_err(|e| anyhow!("Failed to create job journal directory {}: {}", dir.display(), e))?;
+        Ok(Self {
+            dir: Some(dir),
+            ..Self::default()
+        })
+    }
+
+    // Forget finished jobs, and delete their journals, this long after they finish
+    pub fn with_retention(mut self, retention: Duration) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    pub fn retention(&self) -> Duration {
+        self.retention
+    }
+
+    // The job a tenant's token names, or a new one for a job of total records with the
+    // given digest
+    pub fn claim(
+        &self,
+        tenant: &str,
+        token: &str,
+        digest: String,
+        total: usize,
+    ) -> Result<Claim, JournalError> {
+        check_token(token)?;
+        let mut jobs = self.jobs.lock().unwrap();
+        self.prune(&mut jobs);
+        let key = (tenant.to_string(), token.to_string());
+        if let Some(claimed) = jobs.get_mut(&key) {
+            if claimed.digest != digest {
+                return Err(JournalError::Conflict(token.to_string()));
+            }
+            return Ok(match claimed.interrupted.take() {
+                Some(committed) => Claim::Resume {
+                    job_id: claimed.job_id.clone(),
+                    log: claimed.log.clone(),
+                    committed,
+                },
+                None => Claim::Existing {
+                    job_id: claimed.job_id.clone(),
+                    total: claimed.total,
+                },
+            });
+        }
+
+        let job_id = Uuid::new_v4().to_string();
+        let log = match &self.dir {
+            Some(dir) => {
+                let path = journal_path(dir, tenant, token);
+                // The directory is synced too, or the new file may not survive a crash
+                let file = File::create(&path)
+                    .and_then(|file| sync_dir(dir).map(|_| file))
+                    .map_err(|e| {
+                        JournalError::Storage(format!("Failed to create {}: {}", path.display(), e))
+                    })?;
+                JobLog::new(Some((file, 0)), None)
+            }
+            None => JobLog::new(None, None),
+        };
+        let started = JournalEntry::Started {
+            tenant: tenant.to_string(),
+            token: token.to_string(),
+            digest: digest.clone(),
+            job_id: job_id.clone(),
+            total,
+        };
+        log.append(&started)
+            .map_err(|e| JournalError::Storage(e.to_string()))?;
+        let log = Arc::new(log);
+        jobs.insert(
+            key,
+            Claimed {
+                digest,
+                job_id: job_id.clone(),
+                total,
+                log: log.clone(),
+                interrupted: None,
+            },
+        );
+        Ok(Claim::New { job_id, log })
+    }
+
+    // Give up a claim whose job never started. A new job's token is forgotten, so a retry
+    // starts it afresh; an interrupted job's is left for a retry to carry it on.
+    pub fn release(&self, tenant: &str, token: &str, committed: Option<Vec<CommittedRecord>>) {
+        let key = (tenant.to_string(), token.to_string());
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some(committed) = committed {
+            if let Some(claimed) = jobs.get_mut(&key) {
+                claimed.interrupted = Some(committed);
+            }
+            return;
+        }
+        if jobs.remove(&key).is_none() {
+            return;
+        }
+        if let Some(dir) = &self.dir {
+            let path = journal_path(dir, tenant, token);
+            if let Err(e) = fs::remove_file(&path) {
+                warn!("Failed to remove job journal {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    // Forget the jobs that finished more than the retention ago, deleting their journals
+    fn prune(&self, jobs: &mut HashMap<(String, String), Claimed>) {
+        let now = SystemTime::now();
+        jobs.retain(|(tenant, token), claimed| {
+            if !claimed.log.expired(now, self.retention) {
+                return true;
+            }
+            if let Some(dir) = &self.dir {
+                let path = journal_path(dir, tenant, token);
+                if let Err(e) = fs::remove_file(&path) {
+                    warn!("Failed to remove job journal {}: {}", path.display(), e);
+                }
+            }
+            false
+        });
+    }
+
+    // Read back every journal in the directory and remember the tokens they were started
+    // under. Jobs that hadn't finished are carried on by the next request with their token.
+    // Journals of jobs finished more than the retention ago are deleted instead.
+    pub fn replay(&self) -> Result<Vec<ReplayedJob>> {
+        let Some(dir) = &self.dir else {
+            return Ok(vec![]);
+        };
+        let mut replayed = Vec::new();
+        let entries = fs::read_dir(dir).map_err(|e| anyhow!("Failed to read {}: {}", dir.display(), e))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("journal") {
+                continue;
+            }
+            match self.replay_file(&path) {
+                Ok(Some(job)) => replayed.push(job),
+                Ok(None) => {}
+                Err(e) => warn!("Skipping job journal {}: {}", path.display(), e),
+            }
+        }
+        Ok(replayed)
+    }
+
+    fn replay_file(&self, path: &Path) -> Result<Option<ReplayedJob>> {
+        let bytes = fs::read(path)?;
+        let (entries, valid) = read_entries(&bytes);
+        let mut entries = entries.into_iter();
+        let Some(JournalEntry::Started {
+            tenant,
+            token,
+            digest,
+            job_id,
+            total,
+        }) = entries.next()
+        else {
+            return Err(anyhow!("No start entry"));
+        };
+        let mut job = ReplayedJob {
+            job_id: job_id.clone(),
+            total,
+            committed: vec![],
+            finished: false,
+        };
+        for entry in entries {
+            match entry {
+                JournalEntry::Committed(record) => job.committed.push(record),
+                JournalEntry::Finished => job.finished = true,
+                JournalEntry::Started { .. } => return Err(anyhow!("Second start entry")),
+            }
+        }
+
+        let log = match job.finished {
+            // Nothing is appended after the Finished entry, which was the last one written,
+            // so the file is as old as the finish
+            true => {
+                let log = JobLog::new(None, Some(fs::metadata(path)?.modified()?));
+                if log.expired(SystemTime::now(), self.retention) {
+                    fs::remove_file(path)?;
+                    return Ok(None);
+                }
+                log
+            }
+            // Appends carry on after the last whole entry
+            false => {
+                let file = OpenOptions::new().append(true).open(path)?;
+                file.set_len(valid)?;
+                JobLog::new(Some((file, valid)), None)
+            }
+        };
+        let claimed = Claimed {
+            digest,
+            job_id,
+            total,
+            log: Arc::new(log),
+            interrupted: (!job.finished).then(|| job.committed.clone()),
+        };
+        self.jobs.lock().unwrap().insert((tenant, token), claimed);
+        Ok(Some(job))
+    }
+}
+
+// The whole entries at the start of a journal, and the length they take up
+fn read_entries(bytes: &[u8]) -> (Vec<JournalEntry>, u64) {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while let Some(header) = bytes.get(offset..offset + 8) {
+        let length = u64::from_be_bytes(header.try_into().unwrap()) as usize;
+        let Some(body) = bytes.get(offset + 8..).and_then(|rest| rest.get(..length)) else {
+            break;
+        };
+        let Ok(entry) = bincode::deserialize(body) else {
+            break;
+        };
+        entries.push(entry);
+        offset += 8 + length;
+    }
+    (entries, offset as u64)
+}
+
+// Sync a directory, so the entries created in it are durable
+fn sync_dir(dir: &Path) -> std::io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+// Named after a digest of the tenant and token, which may hold anything a file name can't
+fn journal_path(dir: &Path, tenant: &str, token: &str) -> PathBuf {
+    let mut hasher = Sha256::new();
+    hasher.update((tenant.len() as u64).to_be_bytes());
+    hasher.update(tenant.as_bytes());
+    hasher.update(token.as_bytes());
+    dir.join(format!("{}.journal", to_hex(&hasher.finalize())))
+}
+
+fn check_token(token: &str) -> Result<(), JournalError> {
+    let valid = !token.is_empty()
+        && token.len() <= MAX_DEDUP_TOKEN_LENGTH
+        && token
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    match valid {
+        true => Ok(()),
+        false => Err(JournalError::InvalidToken(token.to_string())),
+    }
+}
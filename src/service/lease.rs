@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Result};
+
+pub const DEFAULT_RESULT_LEASE: Duration = Duration::from_secs(60 * 60);
+
+struct Lease {
+    expires: SystemTime,
+    // Its session was closed by timeout or memory pressure, so the result is freed once
+    // the lease ends
+    orphaned: bool,
+}
+
+// Pins on evaluation results not yet fetched. Sessions closed by their idle timeout or
+// under memory pressure leave pinned results in the store until the client decrypts or
+// exports them, releases them, or the lease runs out, so a result is never evicted
+// between the response announcing it and the client reading it.
+pub struct ResultLeases {
+    duration: Duration,
+    leases: Mutex<HashMap<String, Lease>>,
+}
+
+impl ResultLeases {
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            duration,
+            leases: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // HERMETIC_FHE_RESULT_LEASE_SECONDS sets how long a result stays pinned, an hour by
+    // default; 0 turns pinning off
+    pub fn from_env() -> Result<Self> {
+        let duration = match std::env::var("HERMETIC_FHE_RESULT_LEASE_SECONDS") {
+            Ok(seconds) => Duration::from_secs(seconds.trim().parse().map_err(|_| {
+                anyhow!("HERMETIC_FHE_RESULT_LEASE_SECONDS must be a whole number of seconds")
+            })?),
+            Err(_) => DEFAULT_RESULT_LEASE,
+        };
+        Ok(Self::new(duration))
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    // Pin results, returning when their lease ends; None with pinning off
+    pub fn pin(&self, ids: &[&str]) -> Option<SystemTime> {
+        if self.duration.is_zero() {
+            return None;
+        }
+        let expires = SystemTime::now() + self.duration;
+        let mut leases = self.leases.lock().unwrap();
+        for id in ids {
+            leases.insert(
+                id.to_string(),
+                Lease {
+                    expires,
+                    orphaned: false,
+                },
+            );
+        }
+        Some(expires)
+    }
+
+    // Unpin a result; true if it was pinned
+    pub fn release(&self, id: &str) -> bool {
+        let now = SystemTime::now();
+        let mut leases = self.leases.lock().unwrap();
+        let Some(lease) = leases.get_mut(id) else {
+            return false;
+        };
+        let pinned = lease.expires > now;
+        match lease.orphaned {
+            // Left for take_unpinned to free
+            true => lease.expires = SystemTime::UNIX_EPOCH,
+            false => {
+                leases.remove(id);
+            }
+        }
+        pinned
+    }
+
+    // Of the ciphertexts an evicted session owned, keep those still pinned past the
+    // session, returning the rest to be freed
+    pub fn evict(&self, ids: Vec<String>) -> Vec<String> {
+        let now = SystemTime::now();
+        let mut leases = self.leases.lock().unwrap();
+        ids.into_iter()
+            .filter(|id| match leases.get_mut(id) {
+                Some(lease) if lease.expires > now => {
+                    lease.orphaned = true;
+                    false
+                }
+                _ => true,
+            })
+            .collect()
+    }
+
+    // Results kept past their session whose lease has since been released or run out, to
+    // be freed now. Ended leases of results still in a session are dropped.
+    pub fn take_unpinned(&self) -> Vec<String> {
+        let now = SystemTime::now();
+        let mut leases = self.leases.lock().unwrap();
+        let ended: Vec<String> = leases
+            .iter()
+            .filter(|(_, lease)| lease.expires <= now)
+            .map(|(id, _)| id.clone())
+            .collect();
+        ended
+            .into_iter()
+            .filter(|id| leases.remove(id).is_some_and(|lease| lease.orphaned))
+            .collect()
+    }
+}
+
+impl Default for ResultLeases {
+    fn default() -> Self {
+        Self::new(DEFAULT_RESULT_LEASE)
+    }
+}
@@ -0,0 +1,59 @@
+use std::convert::Infallible;
+
+use tonic::body::BoxBody;
+use tonic::codegen::{http, Context, Poll, Service};
+use tonic::server::NamedService;
+use tonic::transport::Body;
+
+// Service name used before the proto package was versioned
+pub const LEGACY_SERVICE_NAME: &str = "hermetic_fhe.FheService";
+
+// Serves the unversioned `hermetic_fhe.FheService` path by forwarding to the v1 service.
+// v1 kept the original messages unchanged, so only the request path needs rewriting.
+#[derive(Clone)]
+pub struct LegacyService<S> {
+    inner: S,
+}
+
+impl<S> LegacyService<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S> Service<http::Request<Body>> for LegacyService<S>
+where
+    S: Service<http::Request<Body>, Response = http::Response<BoxBody>, Error = Infallible> + NamedService,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: http::Request<Body>) -> Self::Future {
+        let method = request
+            .uri()
+            .path()
+            .strip_prefix("/")
+            .and_then(|path| path.strip_prefix(LEGACY_SERVICE_NAME))
+            .map(str::to_string);
+
+        if let Some(method) = method {
+            let path = format!("/{}{}", S::NAME, method);
+            let mut parts = request.uri().clone().into_parts();
+            parts.path_and_query = path.parse().ok();
+            if let Ok(uri) = http::Uri::from_parts(parts) {
+                *request.uri_mut() = uri;
+            }
+        }
+
+        self.inner.call(request)
+    }
+}
+
+impl<S> NamedService for LegacyService<S> {
+    const NAME: &'static str = LEGACY_SERVICE_NAME;
+}
@@ -0,0 +1,175 @@
+use std::env;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use tokio::net::{UnixListener, UnixStream};
+use tokio_stream::wrappers::UnixListenerStream;
+use tonic::transport::{Channel, Endpoint, Uri};
+use tower::service_fn;
+
+pub const DEFAULT_PORT: u16 = 50051;
+// IPv4 loopback, which exists everywhere, unlike ::1 in many containers. Reaching the
+// server from outside its host or container takes --bind-all or an explicit --host.
+pub const DEFAULT_HOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+// Where the example clients connect unless given an endpoint
+pub const DEFAULT_ENDPOINT: &str = "http://127.0.0.1:50051";
+
+// Owner and group may connect, so an application in the same group can share the socket
+const UNIX_SOCKET_MODE: u32 = 0o660;
+
+const USAGE: &str =
+    "Usage: hermetic-fhe [--host <ip>] [--port <port>] [--bind-all] [--unix-socket <path>] [--no-tcp]";
+
+// Listening flags from the server's command line. Each one overrides its environment
+// variable: HERMETIC_FHE_HOST, HERMETIC_FHE_PORT (or PORT, as container platforms set it),
+// HERMETIC_FHE_BIND_ALL, HERMETIC_FHE_UNIX_SOCKET and HERMETIC_FHE_NO_TCP.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ListenFlags {
+    pub host: Option<String>,
+    pub port: Option<String>,
+    pub bind_all: bool,
+    // Also serve on a Unix domain socket at this path
+    pub unix_socket: Option<String>,
+    // Serve on the socket alone, opening no network port
+    pub no_tcp: bool,
+}
+
+// Where the server listens: a TCP address, a Unix domain socket, or both
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Listeners {
+    pub tcp: Option<SocketAddr>,
+    pub unix_socket: Option<PathBuf>,
+}
+
+impl ListenFlags {
+    // Flags may be given as `--port 8080` or `--port=8080`; anything else is an error
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut flags = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (name, inline) = match arg.split_once('=') {
+                Some((name, value)) => (name.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            match name.as_str() {
+                "--host" | "--port" | "--unix-socket" => {
+                    let value = inline
+                        .or_else(|| args.next())
+                        .ok_or_else(|| anyhow!("{} needs a value\n{}", name, USAGE))?;
+                    match name.as_str() {
+                        "--host" => flags.host = Some(value),
+                        "--port" => flags.port = Some(value),
+                        _ => flags.unix_socket = Some(value),
+                    }
+                }
+                "--bind-all" if inline.is_none() => flags.bind_all = true,
+                "--no-tcp" if inline.is_none() => flags.no_tcp = true,
+                _ => return Err(anyhow!("Unknown argument {}\n{}", name, USAGE)),
+            }
+        }
+        Ok(flags)
+    }
+
+    // Address to listen on: flags first, then the environment, then 127.0.0.1:50051
+    pub fn address(&self) -> Result<SocketAddr> {
+        let bind_all = self.bind_all
+            || env::var("HERMETIC_FHE_BIND_ALL").is_ok_and(|value| matches!(value.trim(), "1" | "true"));
+        let host = self.host.clone().or_else(|| env::var("HERMETIC_FHE_HOST").ok());
+        let port = self
+            .port
+            .clone()
+            .or_else(|| env::var("HERMETIC_FHE_PORT").ok())
+            .or_else(|| env::var("PORT").ok());
+        resolve(host.as_deref(), port.as_deref(), bind_all)
+    }
+
+    // The TCP address, unless turned off, and the Unix domain socket, if one is given.
+    // Turning TCP off without a socket would leave nothing to serve on.
+    pub fn listeners(&self) -> Result<Listeners> {
+        let unix_socket = self
+            .unix_socket
+            .clone()
+            .or_else(|| env::var("HERMETIC_FHE_UNIX_SOCKET").ok())
+            .filter(|path| !path.trim().is_empty())
+            .map(PathBuf::from);
+        let no_tcp = self.no_tcp
+            || env::var("HERMETIC_FHE_NO_TCP").is_ok_and(|value| matches!(value.trim(), "1" | "true"));
+        let tcp = match (no_tcp, &unix_socket) {
+            (true, None) => return Err(anyhow!("Turning TCP off needs a Unix socket to serve on")),
+            (true, Some(_)) => None,
+            (false, _) => Some(self.address()?),
+        };
+        Ok(Listeners { tcp, unix_socket })
+    }
+}
+
+// Listen on a Unix domain socket, replacing one left behind by an earlier run. Any other
+// kind of file at the path is left alone and the bind fails.
+pub fn bind_unix(path: &Path) -> Result<UnixListenerStream> {
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(anyhow!("{} exists and is not a socket", path.display()));
+        }
+        fs::remove_file(path)
+            .map_err(|e| anyhow!("Failed to remove stale socket {}: {}", path.display(), e))?;
+    }
+    let listener =
+        UnixListener::bind(path).map_err(|e| anyhow!("Failed to listen on {}: {}", path.display(), e))?;
+    fs::set_permissions(path, fs::Permissions::from_mode(UNIX_SOCKET_MODE))?;
+    Ok(UnixListenerStream::new(listener))
+}
+
+// A host, if given, must be an IP address; bind-all stands for 0.0.0.0 and can't be
+// combined with one
+pub fn resolve(host: Option<&str>, port: Option<&str>, bind_all: bool) -> Result<SocketAddr> {
+    let host = match (host, bind_all) {
+        (Some(_), true) => return Err(anyhow!("Pass either a host or bind-all, not both")),
+        (Some(host), false) => {
+            // Accept the bracketed form too, as in [::1]:50051
+            let host = host.trim().trim_start_matches('[').trim_end_matches(']');
+            host.parse()
+                .map_err(|_| anyhow!("Host must be an IPv4 or IPv6 address, not '{}'", host))?
+        }
+        (None, true) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        (None, false) => DEFAULT_HOST,
+    };
+    let port = match port {
+        Some(port) => port
+            .trim()
+            .parse()
+            .map_err(|_| anyhow!("Port must be a number from 0 to 65535, not '{}'", port))?,
+        None => DEFAULT_PORT,
+    };
+    Ok(SocketAddr::new(host, port))
+}
+
+// Server URL for the example clients: the first argument, then HERMETIC_FHE_ENDPOINT, then
+// the default. A bare host:port gets http:// put in front; unix:<path> names a socket.
+pub fn client_endpoint(args: impl IntoIterator<Item = String>) -> String {
+    let endpoint = args
+        .into_iter()
+        .next()
+        .or_else(|| env::var("HERMETIC_FHE_ENDPOINT").ok())
+        .unwrap_or_else(|| DEFAULT_ENDPOINT.to_string());
+    if endpoint.contains("://") || endpoint.starts_with("unix:") {
+        endpoint
+    } else {
+        format!("http://{}", endpoint)
+    }
+}
+
+// Connect to an endpoint from client_endpoint, over a Unix domain socket for unix:<path>
+pub async fn connect(endpoint: &str) -> Result<Channel> {
+    let Some(path) = endpoint.strip_prefix("unix:") else {
+        return Ok(Endpoint::from_shared(endpoint.to_string())?.connect().await?);
+    };
+    // The URI only fills in the :authority header; every connection goes to the socket
+    let path = PathBuf::from(path.trim_start_matches("//"));
+    let channel = Endpoint::from_static("http://localhost")
+        .connect_with_connector(service_fn(move |_: Uri| UnixStream::connect(path.clone())))
+        .await?;
+    Ok(channel)
+}
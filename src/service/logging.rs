@@ -0,0 +1,249 @@
+use std::fmt;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use tokio_stream::StreamExt;
+use tonic::body::BoxBody;
+use tonic::codegen::http::{HeaderMap, Request, Response};
+use tonic::codegen::{Body as HttpBody, BoxFuture, Bytes};
+use tonic::transport::Body;
+use tonic::{Code, Status};
+use tower::{Layer, Service};
+use tracing::info;
+
+use crate::api::{
+    BooleanResponse, BucketTimestampRequest, CompareTimestampRequest, DecryptMatrixResponse,
+    EncryptAndEvaluateRequest, EncryptBooleanRequest, EncryptIntegerBatchRequest, EncryptIntegerRequest,
+    EncryptMatrixRequest, EncryptRealVectorRequest, EncryptTimestampRequest, EvaluateAndDecryptResponse,
+    IncrementCounterRequest, IntegerBatchResponse, IntegerResponse, MatchStringRequest, MatrixScaleRequest,
+    MatrixVectorProductRequest, ModelLayer, PirQueryRequest, PlaintextValue, QueryBloomFilterRequest,
+    RealVectorResponse, SetMembershipRequest, TimestampResponse,
+};
+use crate::service::authorization::PRINCIPAL_HEADER;
+use crate::service::usage::TENANT_HEADER;
+
+// Metadata whose values are logged. Every other header is logged by name only, so tokens
+// and binary metadata stay out of the logs whatever a client sends.
+const LOGGED_HEADERS: [&str; 8] = [
+    "content-type",
+    "user-agent",
+    "x-user-agent",
+    "grpc-timeout",
+    "grpc-encoding",
+    "grpc-accept-encoding",
+    TENANT_HEADER,
+    PRINCIPAL_HEADER,
+];
+
+// A value that formats as <redacted>, with {} and {:?} alike
+pub struct Redacted<T>(pub T);
+
+impl<T> fmt::Debug for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+impl<T> fmt::Display for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+// Debug for a message build.rs generated without one, showing the fields before `redact`
+// and hiding those after it. Every field is named with no `..`, so a field added to the
+// proto stops the build here until it is sorted into one list or the other.
+macro_rules! redacted_debug {
+    ($($message:ident { $($field:ident),* ; redact $($secret:ident),+ })*) => {$(
+        impl fmt::Debug for $message {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                let $message { $($field,)* $($secret,)+ } = self;
+                f.debug_struct(stringify!($message))
+                    $(.field(stringify!($field), $field))*
+                    $(.field(stringify!($secret), &Redacted($secret)))+
+                    .finish()
+            }
+        }
+    )*};
+}
+
+// Messages with plaintext values or model parameters, the same list as REDACTED_MESSAGES
+// in build.rs. Listing one there without an impl here, or the reverse, fails to compile.
+redacted_debug! {
+    EncryptBooleanRequest { client_key_id, session_id, subject_id; redact value }
+    EncryptIntegerRequest { client_key_id, num_bits, session_id, subject_id; redact value }
+    BooleanResponse { ; redact value }
+    IntegerResponse { budget; redact value }
+    EncryptAndEvaluateRequest { client_key_id, server_key_id, gates, outputs, session_id; redact inputs }
+    EvaluateAndDecryptResponse { ; redact values }
+    PlaintextValue { ; redact value }
+    SetMembershipRequest { server_key_id, value_id, element_ids, session_id; redact plaintext_elements }
+    PirQueryRequest { server_key_id, index_id, session_id; redact table }
+    MatchStringRequest { server_key_id, byte_ids, mode, case_sensitive, session_id; redact candidates }
+    IncrementCounterRequest { counter_id; redact delta }
+    QueryBloomFilterRequest { filter_id, session_id; redact elements }
+    EncryptMatrixRequest { client_key_id, rows, cols, session_id; redact values }
+    DecryptMatrixResponse { rows, cols; redact values }
+    MatrixVectorProductRequest { server_key_id, matrix_id, vector_ids, session_id; redact plaintext_vector }
+    MatrixScaleRequest { server_key_id, matrix_id, session_id; redact scalar }
+    EncryptRealVectorRequest { client_key_id, session_id; redact values }
+    RealVectorResponse { ; redact values }
+    EncryptIntegerBatchRequest { client_key_id, session_id; redact values }
+    IntegerBatchResponse { ; redact values }
+    ModelLayer { outputs; redact weights, bias, activation }
+    EncryptTimestampRequest { client_key_id, unit, session_id; redact value }
+    TimestampResponse { unit; redact value }
+    CompareTimestampRequest { server_key_id, timestamp_id, comparison, session_id; redact other }
+    BucketTimestampRequest { server_key_id, timestamp_id, session_id; redact boundaries }
+}
+
+// Request metadata as name=value pairs, with the value of every header outside
+// LOGGED_HEADERS replaced by <redacted>
+pub struct LoggedMetadata<'a>(pub &'a HeaderMap);
+
+impl fmt::Display for LoggedMetadata<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (name, value)) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            match value.to_str() {
+                Ok(value) if LOGGED_HEADERS.contains(&name.as_str()) => write!(f, "{}={}", name, value)?,
+                _ => write!(f, "{}={}", name, Redacted(value))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// Logs a line per call with its method, metadata, bytes each way, gRPC status and duration.
+// It works on the HTTP bodies below gRPC-Web, counting bytes without ever decoding a
+// message, so no field of a request or response can reach the log through it.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CallLogLayer;
+
+impl<S> Layer<S> for CallLogLayer {
+    type Service = CallLog<S>;
+
+    fn layer(&self, inner: S) -> CallLog<S> {
+        CallLog { inner }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CallLog<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for CallLog<S>
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    type Future = BoxFuture<Response<BoxBody>, S::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let received = Arc::new(AtomicU64::new(0));
+        let mut call = CallRecord {
+            method: request.uri().path().to_string(),
+            metadata: LoggedMetadata(request.headers()).to_string(),
+            received: received.clone(),
+            sent: 0,
+            status: None,
+            started: Instant::now(),
+        };
+        let request = request.map(|body| {
+            Body::wrap_stream(body.map(move |chunk| {
+                if let Ok(chunk) = &chunk {
+                    received.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+                }
+                chunk
+            }))
+        });
+        let response = self.inner.call(request);
+        Box::pin(async move {
+            let response = response.await?;
+            // Errors raised before any message are sent in the headers alone
+            call.status = grpc_status(response.headers());
+            Ok(response.map(|body| tonic::body::boxed(LoggedBody { inner: body, call })))
+        })
+    }
+}
+
+// What is known about a call so far, logged when the response is finished or dropped
+struct CallRecord {
+    method: String,
+    metadata: String,
+    received: Arc<AtomicU64>,
+    sent: u64,
+    status: Option<Code>,
+    started: Instant,
+}
+
+impl Drop for CallRecord {
+    fn drop(&mut self) {
+        // No status means the response never finished, e.g. the client went away
+        let status = self
+            .status
+            .map_or_else(|| "unfinished".to_string(), |code| format!("{:?}", code));
+        info!(
+            method = %self.method,
+            status = %status,
+            request_bytes = self.received.load(Ordering::Relaxed),
+            response_bytes = self.sent,
+            elapsed_ms = self.started.elapsed().as_millis() as u64,
+            metadata = %self.metadata,
+            "call"
+        );
+    }
+}
+
+// A response body that counts its bytes and reads the status from its trailers
+struct LoggedBody {
+    inner: BoxBody,
+    call: CallRecord,
+}
+
+impl HttpBody for LoggedBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_data(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, Status>>> {
+        let polled = Pin::new(&mut self.inner).poll_data(cx);
+        if let Poll::Ready(Some(Ok(data))) = &polled {
+            self.call.sent += data.len() as u64;
+        }
+        polled
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Status>> {
+        let polled = Pin::new(&mut self.inner).poll_trailers(cx);
+        if let Poll::Ready(Ok(Some(trailers))) = &polled {
+            if let Some(status) = grpc_status(trailers) {
+                self.call.status = Some(status);
+            }
+        }
+        polled
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+}
+
+fn grpc_status(headers: &HeaderMap) -> Option<Code> {
+    let code = headers.get("grpc-status")?.to_str().ok()?.parse::<i32>().ok()?;
+    Some(Code::from(code))
+}
@@ -0,0 +1,99 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{anyhow, Result};
+
+const BYTES_PER_MB: u64 = 1024 * 1024;
+
+// What happens to requests that would store more data once the stores are over the limit
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryPolicy {
+    // Turn them away with OVERLOADED until enough is freed
+    Reject,
+    // Close the longest-idle sessions first, freeing their ciphertexts, and only turn
+    // requests away once no session is left to close
+    EvictSessions,
+}
+
+// Ceiling on the approximate bytes held by the key and ciphertext stores together
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryLimit {
+    pub max_bytes: u64,
+    pub policy: MemoryPolicy,
+}
+
+impl MemoryLimit {
+    // Read HERMETIC_FHE_MEMORY_LIMIT_MB and HERMETIC_FHE_MEMORY_POLICY (reject, the default,
+    // or evict-sessions); None when no limit is set
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(megabytes) = std::env::var("HERMETIC_FHE_MEMORY_LIMIT_MB") else {
+            return Ok(None);
+        };
+        let megabytes: u64 = megabytes
+            .trim()
+            .parse()
+            .ok()
+            .filter(|megabytes| *megabytes > 0)
+            .ok_or_else(|| anyhow!("HERMETIC_FHE_MEMORY_LIMIT_MB must be a positive integer"))?;
+        let policy = match std::env::var("HERMETIC_FHE_MEMORY_POLICY").as_deref() {
+            Err(_) | Ok("reject") => MemoryPolicy::Reject,
+            Ok("evict-sessions") => MemoryPolicy::EvictSessions,
+            Ok(other) => {
+                return Err(anyhow!(
+                    "HERMETIC_FHE_MEMORY_POLICY must be reject or evict-sessions, not {}",
+                    other
+                ))
+            }
+        };
+        Ok(Some(Self {
+            max_bytes: megabytes.saturating_mul(BYTES_PER_MB),
+            policy,
+        }))
+    }
+}
+
+// Point-in-time view of store memory against the limit
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub used_bytes: u64,
+    pub limit_bytes: Option<u64>,
+    pub rejected: u64,
+    pub evicted_sessions: u64,
+}
+
+// The memory limit, if any, and what enforcing it has done so far
+#[derive(Debug, Default)]
+pub struct MemoryGuard {
+    limit: Option<MemoryLimit>,
+    rejected: AtomicU64,
+    evicted_sessions: AtomicU64,
+}
+
+impl MemoryGuard {
+    pub fn new(limit: Option<MemoryLimit>) -> Self {
+        Self {
+            limit,
+            ..Self::default()
+        }
+    }
+
+    pub fn limit(&self) -> Option<MemoryLimit> {
+        self.limit
+    }
+
+    pub fn record_rejection(&self) {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_eviction(&self) {
+        self.evicted_sessions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn usage(&self, used_bytes: u64) -> MemoryUsage {
+        MemoryUsage {
+            used_bytes,
+            limit_bytes: self.limit.map(|limit| limit.max_bytes),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            evicted_sessions: self.evicted_sessions.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use tfhe::prelude::{FheDecrypt, FheTryEncrypt};
+use tfhe::FheUint8;
+use uuid::Uuid;
+
+use crate::api::v1::key_generation_request::Scheme;
+use crate::backend::{BackendError, BgvBackend, CkksBackend, FheBackend, TfheBackend};
+use crate::crypto::matrix::EncryptedMatrix;
+use crate::crypto::provenance::Derivation;
+use crate::crypto::sharded::ShardedMap;
+use crate::crypto::timestamp::{EncryptedTimestamp, TimeUnit};
+use crate::crypto::{Ciphertext, CiphertextKind, CiphertextStore, KeyScheme, KeyStore};
+
+// Decrypted contents of one ciphertext on its way from the source key to the target key.
+// It never leaves the job.
+enum Plaintext {
+    Boolean(bool),
+    Integer(u8),
+    Matrix {
+        rows: usize,
+        cols: usize,
+        elements: Vec<u8>,
+    },
+    Reals(Vec<f64>),
+    Integers(Vec<i64>),
+    Timestamp {
+        unit: TimeUnit,
+        value: u32,
+    },
+}
+
+impl Plaintext {
+    // The values as a batch for the slot schemes, which have no booleans or shapes.
+    // Reals are refused: rounding them would change the data.
+    fn into_integers(self) -> Result<Vec<i64>> {
+        match self {
+            Plaintext::Boolean(value) => Ok(vec![value as i64]),
+            Plaintext::Integer(value) => Ok(vec![value as i64]),
+            Plaintext::Matrix { elements, .. } => Ok(elements.into_iter().map(i64::from).collect()),
+            Plaintext::Integers(values) => Ok(values),
+            Plaintext::Timestamp { value, .. } => Ok(vec![value as i64]),
+            Plaintext::Reals(_) => Err(anyhow!(
+                "CKKS values are approximate and can't move to an exact scheme"
+            )),
+        }
+    }
+}
+
+// Outcome for one source ciphertext: the ID of its re-encryption, or why there is none
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MigratedCiphertext {
+    pub source_id: String,
+    pub result: Result<String, String>,
+}
+
+// What a migration has done so far
+#[derive(Clone, Debug)]
+pub struct MigrationProgress {
+    pub source_scheme: Scheme,
+    pub target_scheme: Scheme,
+    pub total: usize,
+    // In request order, one per ciphertext processed
+    pub ciphertexts: Vec<MigratedCiphertext>,
+    pub done: bool,
+}
+
+impl MigrationProgress {
+    pub fn failed(&self) -> usize {
+        self.ciphertexts
+            .iter()
+            .filter(|ciphertext| ciphertext.result.is_err())
+            .count()
+    }
+}
+
+// One bulk re-encryption job
+pub struct Migration {
+    progress: Mutex<MigrationProgress>,
+}
+
+impl Migration {
+    pub fn progress(&self) -> MigrationProgress {
+        self.progress.lock().unwrap().clone()
+    }
+
+    fn record(&self, ciphertext: MigratedCiphertext) {
+        self.progress.lock().unwrap().ciphertexts.push(ciphertext);
+    }
+
+    fn finish(&self) {
+        self.progress.lock().unwrap().done = true;
+    }
+}
+
+// Migrations by ID, kept after they finish so their results can still be read
+pub struct MigrationStore {
+    migrations: ShardedMap<Arc<Migration>>,
+}
+
+impl MigrationStore {
+    pub fn new() -> Self {
+        Self {
+            migrations: ShardedMap::new(),
+        }
+    }
+
+    pub fn create(
+        &self,
+        source_scheme: Scheme,
+        target_scheme: Scheme,
+        total: usize,
+    ) -> (String, Arc<Migration>) {
+        let id = Uuid::new_v4().to_string();
+        let migration = Arc::new(Migration {
+            progress: Mutex::new(MigrationProgress {
+                source_scheme,
+                target_scheme,
+                total,
+                ciphertexts: Vec::with_capacity(total),
+                done: false,
+            }),
+        });
+        self.migrations.insert(id.clone(), migration.clone());
+        (id, migration)
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<Migration>> {
+        self.migrations.get(id)
+    }
+}
+
+impl Default for MigrationStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Moves stored data from one client key to another by decrypting with the first and
+// encrypting with the second, possibly across schemes. Only conversions that keep every
+// value exact are allowed: TFHE booleans, integers and matrices become one-slot or
+// row-major batches under BGV or CKKS, and BGV batches become CKKS vectors, but CKKS
+// vectors can only go to another CKKS key and batches can't go to TFHE. Re-encrypting
+// also resets a BGV or CKKS ciphertext's multiplicative depth.
+#[derive(Clone)]
+pub struct Migrator {
+    key_store: Arc<KeyStore>,
+    ciphertext_store: Arc<CiphertextStore>,
+    tfhe: TfheBackend,
+    ckks: CkksBackend,
+    bgv: BgvBackend,
+}
+
+impl Migrator {
+    pub fn new(key_store: Arc<KeyStore>, ciphertext_store: Arc<CiphertextStore>) -> Self {
+        Self {
+            tfhe: TfheBackend::new(key_store.clone(), ciphertext_store.clone()),
+            ckks: CkksBackend::new(key_store.clone(), ciphertext_store.clone()),
+            bgv: BgvBackend::new(key_store.clone(), ciphertext_store.clone()),
+            key_store,
+            ciphertext_store,
+        }
+    }
+
+    // Scheme of a client key; None if no scheme has it
+    pub fn scheme(&self, client_key_id: &str) -> Option<Scheme> {
+        Some(match self.key_store.client_key_scheme(client_key_id)? {
+            KeyScheme::Tfhe => Scheme::Tfhe,
+            KeyScheme::Ckks => Scheme::Ckks,
+            KeyScheme::Bgv => Scheme::Bgv,
+        })
+    }
+
+    // Migrate each ciphertext in turn, recording every outcome on the job as it goes. A
+    // ciphertext that fails is left as it was and the rest still run.
+    pub fn run(
+        &self,
+        migration: &Migration,
+        source_client_key_id: &str,
+        target_client_key_id: &str,
+        ids: &[String],
+        delete_source: bool,
+    ) {
+        let progress = migration.progress();
+        for id in ids {
+            let result = self
+                .decrypt(progress.source_scheme, source_client_key_id, id)
+                .and_then(|plaintext| self.encrypt(progress.target_scheme, target_client_key_id, plaintext))
+                .map_err(|e| e.to_string());
+            // The migrated copy belongs to the same data subject as the original, and is
+            // recorded as derived from it
+            if let Ok(target_id) = &result {
+                if let Some(subject_id) = self.ciphertext_store.subject(id) {
+                    self.ciphertext_store.set_subject(target_id, &subject_id);
+                }
+                let derivation = Derivation::new("Migration", "", vec![self.ciphertext_store.parent(id)]);
+                self.ciphertext_store.set_derivation(target_id, derivation);
+            }
+            if delete_source && result.is_ok() {
+                self.ciphertext_store.remove(id);
+            }
+            migration.record(MigratedCiphertext {
+                source_id: id.clone(),
+                result,
+            });
+        }
+        migration.finish();
+    }
+
+    fn decrypt(&self, scheme: Scheme, client_key_id: &str, id: &str) -> Result<Plaintext, BackendError> {
+        let kind = self
+            .ciphertext_store
+            .kind(id)
+            .ok_or_else(|| BackendError::CiphertextNotFound(id.to_string()))?;
+        match (scheme, kind) {
+            (Scheme::Tfhe, CiphertextKind::Boolean) => {
+                Ok(Plaintext::Boolean(self.tfhe.decrypt_boolean(client_key_id, id)?))
+            }
+            (Scheme::Tfhe, CiphertextKind::Integer) => {
+                Ok(Plaintext::Integer(self.tfhe.decrypt_integer(client_key_id, id)?))
+            }
+            (Scheme::Tfhe, CiphertextKind::Matrix) => self.decrypt_matrix(client_key_id, id),
+            (Scheme::Tfhe, CiphertextKind::Timestamp) => self.decrypt_timestamp(client_key_id, id),
+            (Scheme::Ckks, CiphertextKind::RealVector) => Ok(Plaintext::Reals(
+                self.ckks.decrypt_real_vector(client_key_id, id)?,
+            )),
+            (Scheme::Bgv, CiphertextKind::IntegerBatch) => Ok(Plaintext::Integers(
+                self.bgv.decrypt_integer_batch(client_key_id, id)?,
+            )),
+            (_, found) => Err(BackendError::TypeMismatch {
+                expected: match scheme {
+                    Scheme::Tfhe => "FheBool, FheUint8, EncryptedMatrix or EncryptedTimestamp",
+                    Scheme::Ckks => CiphertextKind::RealVector.type_name(),
+                    Scheme::Bgv => CiphertextKind::IntegerBatch.type_name(),
+                },
+                found: found.type_name(),
+            }),
+        }
+    }
+
+    fn encrypt(
+        &self,
+        scheme: Scheme,
+        client_key_id: &str,
+        plaintext: Plaintext,
+    ) -> Result<String, BackendError> {
+        match (scheme, plaintext) {
+            (Scheme::Tfhe, Plaintext::Boolean(value)) => self.tfhe.encrypt_boolean(client_key_id, value),
+            (Scheme::Tfhe, Plaintext::Integer(value)) => self.tfhe.encrypt_integer(client_key_id, value),
+            (Scheme::Tfhe, Plaintext::Matrix { rows, cols, elements }) => {
+                self.encrypt_matrix(client_key_id, rows, cols, &elements)
+            }
+            (Scheme::Tfhe, Plaintext::Timestamp { unit, value }) => {
+                self.encrypt_timestamp(client_key_id, unit, value)
+            }
+            (Scheme::Tfhe, _) => Err(anyhow!("Slot vectors have no TFHE equivalent").into()),
+            (Scheme::Ckks, Plaintext::Reals(values)) => self.ckks.encrypt_real_vector(client_key_id, &values),
+            (Scheme::Ckks, plaintext) => {
+                let values: Vec<f64> = plaintext.into_integers()?.into_iter().map(|v| v as f64).collect();
+                self.ckks.encrypt_real_vector(client_key_id, &values)
+            }
+            (Scheme::Bgv, plaintext) => self
+                .bgv
+                .encrypt_integer_batch(client_key_id, &plaintext.into_integers()?),
+        }
+    }
+
+    fn decrypt_matrix(&self, client_key_id: &str, id: &str) -> Result<Plaintext, BackendError> {
+        let client_key = self
+            .key_store
+            .get_client_key(client_key_id)
+            .ok_or(BackendError::ClientKeyNotFound)?;
+        let matrix = match self.ciphertext_store.get(id) {
+            Some(Ciphertext::Matrix(matrix)) => matrix,
+            _ => return Err(BackendError::CiphertextNotFound(id.to_string())),
+        };
+        Ok(Plaintext::Matrix {
+            rows: matrix.rows(),
+            cols: matrix.cols(),
+            elements: matrix
+                .elements()
+                .iter()
+                .map(|element| <FheUint8 as FheDecrypt<u8>>::decrypt(element, &*client_key))
+                .collect(),
+        })
+    }
+
+    fn encrypt_matrix(
+        &self,
+        client_key_id: &str,
+        rows: usize,
+        cols: usize,
+        values: &[u8],
+    ) -> Result<String, BackendError> {
+        let client_key = self
+            .key_store
+            .get_client_key(client_key_id)
+            .ok_or(BackendError::ClientKeyNotFound)?;
+        self.key_store.reseed_thread();
+        let elements = values
+            .iter()
+            .map(|value| {
+                FheUint8::try_encrypt(*value, &*client_key).map_err(|e| anyhow!("Encryption failed: {}", e))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(self
+            .ciphertext_store
+            .store(EncryptedMatrix::new(rows, cols, elements)?))
+    }
+    fn decrypt_timestamp(&self, client_key_id: &str, id: &str) -> Result<Plaintext, BackendError> {
+        let client_key = self
+            .key_store
+            .get_client_key(client_key_id)
+            .ok_or(BackendError::ClientKeyNotFound)?;
+        let timestamp = self
+            .ciphertext_store
+            .get_timestamp(id)
+            .ok_or_else(|| BackendError::CiphertextNotFound(id.to_string()))?;
+        Ok(Plaintext::Timestamp {
+            unit: timestamp.unit(),
+            value: timestamp.decrypt(&client_key),
+        })
+    }
+
+    fn encrypt_timestamp(
+        &self,
+        client_key_id: &str,
+        unit: TimeUnit,
+        value: u32,
+    ) -> Result<String, BackendError> {
+        let client_key = self
+            .key_store
+            .get_client_key(client_key_id)
+            .ok_or(BackendError::ClientKeyNotFound)?;
+        self.key_store.reseed_thread();
+        let timestamp = EncryptedTimestamp::encrypt(&client_key, unit, value)?;
+        Ok(self.ciphertext_store.store(timestamp))
+    }
+}
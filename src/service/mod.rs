@@ -1,2 +1,37 @@
+pub mod admin;
+pub mod admission;
+pub mod aggregation;
+pub mod alias;
+pub mod attestation;
+pub mod authorization;
+pub mod backup;
+pub mod checkpoint;
+pub mod ballot;
+pub mod bloom;
+pub mod counter;
+pub mod dataset;
+pub mod embedded;
+pub mod errors;
+pub mod events;
+#[cfg(feature = "fault-injection")]
+pub mod faults;
 pub mod fhe_service;
+pub mod interceptors;
+pub mod journal;
+pub mod lease;
+pub mod legacy;
+pub mod listen;
+pub mod logging;
+pub mod memory;
+pub mod migration;
+pub mod oidc;
+pub mod privacy;
+pub mod reload;
+pub mod session;
+pub mod sink;
+pub mod tags;
+pub mod transport;
+pub mod usage;
+pub mod web;
+pub mod webhook;
 pub use fhe_service::FheServiceImpl; 
\ No newline at end of file
@@ -0,0 +1,266 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use serde_json::{Map, Value};
+use tonic::metadata::MetadataValue;
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+
+use crate::service::authorization::PRINCIPAL_HEADER;
+use crate::service::errors::ErrorReason;
+use crate::service::usage::TENANT_HEADER;
+
+// A token's claims, as the issuer signed them
+pub type Claims = Map<String, Value>;
+
+// The identity provider whose access tokens the service accepts
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OidcConfig {
+    // Must equal the tokens' iss claim exactly
+    pub issuer: String,
+    // Must be among the tokens' aud claim
+    pub audience: String,
+    // Where the signing keys are published; found through the issuer's discovery document
+    // when unset
+    pub jwks_url: Option<String>,
+    // Scopes every token must carry, from its scope or scp claim
+    pub required_scopes: Vec<String>,
+    // Claim naming the tenant a call is billed to; a token without it bills its subject
+    pub tenant_claim: String,
+    // How often the signing keys are fetched again, to pick up rotated ones
+    pub refresh_interval: Duration,
+}
+
+impl OidcConfig {
+    // HERMETIC_FHE_OIDC_ISSUER turns authentication on and HERMETIC_FHE_OIDC_AUDIENCE is
+    // required with it. HERMETIC_FHE_OIDC_JWKS_URL, HERMETIC_FHE_OIDC_SCOPES (space or
+    // comma separated), HERMETIC_FHE_OIDC_TENANT_CLAIM and
+    // HERMETIC_FHE_OIDC_REFRESH_SECONDS override the defaults. None when no issuer is set.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(issuer) = std::env::var("HERMETIC_FHE_OIDC_ISSUER") else {
+            return Ok(None);
+        };
+        if !cfg!(feature = "oidc") {
+            return Err(anyhow!("HERMETIC_FHE_OIDC_ISSUER requires the oidc feature"));
+        }
+        let audience = std::env::var("HERMETIC_FHE_OIDC_AUDIENCE")
+            .map_err(|_| anyhow!("HERMETIC_FHE_OIDC_ISSUER requires HERMETIC_FHE_OIDC_AUDIENCE"))?;
+        let refresh_seconds = match std::env::var("HERMETIC_FHE_OIDC_REFRESH_SECONDS") {
+            Ok(value) => value
+                .parse::<u64>()
+                .ok()
+                .filter(|seconds| *seconds > 0)
+                .ok_or_else(|| anyhow!("HERMETIC_FHE_OIDC_REFRESH_SECONDS must be a positive integer"))?,
+            Err(_) => 3600,
+        };
+        Ok(Some(Self {
+            issuer,
+            audience,
+            jwks_url: std::env::var("HERMETIC_FHE_OIDC_JWKS_URL").ok(),
+            required_scopes: std::env::var("HERMETIC_FHE_OIDC_SCOPES")
+                .map(|value| {
+                    value
+                        .split([' ', ','])
+                        .filter(|scope| !scope.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            tenant_claim: std::env::var("HERMETIC_FHE_OIDC_TENANT_CLAIM")
+                .unwrap_or_else(|_| "tenant".to_string()),
+            refresh_interval: Duration::from_secs(refresh_seconds),
+        }))
+    }
+}
+
+// Who a verified token says is calling
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Identity {
+    // The token's sub claim
+    pub principal: String,
+    pub tenant: String,
+}
+
+// Checks access tokens against the issuer's published signing keys
+pub struct OidcVerifier {
+    config: OidcConfig,
+    // The "keys" array of the issuer's JWKS
+    keys: RwLock<Vec<Value>>,
+}
+
+impl OidcVerifier {
+    // A verifier over a JWKS document already in hand
+    pub fn new(config: OidcConfig, jwks: &Value) -> Result<Self> {
+        Ok(Self {
+            keys: RwLock::new(jwks_keys(jwks)?),
+            config,
+        })
+    }
+
+    // Fetch the issuer's signing keys. Blocks on the network.
+    pub fn connect(config: OidcConfig) -> Result<Self> {
+        let jwks = fetch_jwks(&config)?;
+        Self::new(config, &jwks)
+    }
+
+    pub fn config(&self) -> &OidcConfig {
+        &self.config
+    }
+
+    // Fetch the signing keys again, keeping the old ones if that fails. Returns how many
+    // keys the issuer publishes. Blocks on the network.
+    pub fn refresh(&self) -> Result<usize> {
+        let keys = jwks_keys(&fetch_jwks(&self.config)?)?;
+        let count = keys.len();
+        *self.keys.write().unwrap() = keys;
+        Ok(count)
+    }
+
+    // Check the token's signature, issuer, audience, expiry and scopes, and map it to the
+    // caller's identity
+    pub fn verify(&self, token: &str) -> Result<Identity, String> {
+        let claims = {
+            let keys = self.keys.read().unwrap();
+            decode(token, &keys, &self.config)?
+        };
+        let principal = match claims.get("sub") {
+            Some(Value::String(sub)) if !sub.is_empty() => sub.clone(),
+            _ => return Err("Token has no subject".to_string()),
+        };
+        let scopes = token_scopes(&claims);
+        if let Some(missing) = self
+            .config
+            .required_scopes
+            .iter()
+            .find(|scope| !scopes.contains(&scope.as_str()))
+        {
+            return Err(format!("Token lacks the {} scope", missing));
+        }
+        let tenant = match claims.get(&self.config.tenant_claim) {
+            Some(Value::String(tenant)) if !tenant.is_empty() => tenant.clone(),
+            _ => principal.clone(),
+        };
+        Ok(Identity { principal, tenant })
+    }
+}
+
+// The scope claim is a space-separated string; some issuers send scp, as a string or a list
+fn token_scopes(claims: &Claims) -> Vec<&str> {
+    match claims.get("scope").or_else(|| claims.get("scp")) {
+        Some(Value::String(scopes)) => scopes.split(' ').filter(|scope| !scope.is_empty()).collect(),
+        Some(Value::Array(scopes)) => scopes.iter().filter_map(Value::as_str).collect(),
+        _ => vec![],
+    }
+}
+
+fn jwks_keys(jwks: &Value) -> Result<Vec<Value>> {
+    match jwks.get("keys") {
+        Some(Value::Array(keys)) if !keys.is_empty() => Ok(keys.clone()),
+        _ => Err(anyhow!("The issuer's JWKS has no keys")),
+    }
+}
+
+fn fetch_jwks(config: &OidcConfig) -> Result<Value> {
+    let url = match &config.jwks_url {
+        Some(url) => url.clone(),
+        None => {
+            let discovery = format!(
+                "{}/.well-known/openid-configuration",
+                config.issuer.trim_end_matches('/')
+            );
+            get_json(&discovery)?
+                .get("jwks_uri")
+                .and_then(Value::as_str)
+                .map(str::to_string)
+                .ok_or_else(|| anyhow!("{} has no jwks_uri", discovery))?
+        }
+    };
+    get_json(&url)
+}
+
+#[cfg(feature = "oidc")]
+fn get_json(url: &str) -> Result<Value> {
+    ureq::get(url)
+        .timeout(Duration::from_secs(10))
+        .call()
+        .map_err(|e| anyhow!("Failed to fetch {}: {}", url, e))?
+        .into_json()
+        .map_err(|e| anyhow!("{} is not JSON: {}", url, e))
+}
+
+#[cfg(not(feature = "oidc"))]
+fn get_json(_url: &str) -> Result<Value> {
+    Err(anyhow!("Built without the oidc feature"))
+}
+
+// Verify the signature with the published key the token names, then its issuer, audience
+// and expiry. Only public-key algorithms are accepted, so a token can't be signed with
+// an HMAC keyed by a public key.
+#[cfg(feature = "oidc")]
+fn decode(token: &str, keys: &[Value], config: &OidcConfig) -> Result<Claims, String> {
+    use jsonwebtoken::jwk::Jwk;
+    use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+
+    let header = jsonwebtoken::decode_header(token).map_err(|e| format!("Malformed token: {}", e))?;
+    if matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+        return Err("Token is not signed with a public-key algorithm".to_string());
+    }
+    let jwk = keys
+        .iter()
+        .filter_map(|key| serde_json::from_value::<Jwk>(key.clone()).ok())
+        .find(|jwk| jwk.common.key_id == header.kid)
+        .ok_or("Token is signed with a key the issuer does not publish")?;
+    let key = DecodingKey::from_jwk(&jwk).map_err(|e| format!("Unusable signing key: {}", e))?;
+    let mut validation = Validation::new(header.alg);
+    validation.set_issuer(&[&config.issuer]);
+    validation.set_audience(&[&config.audience]);
+    validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+    jsonwebtoken::decode::<Claims>(token, &key, &validation)
+        .map(|data| data.claims)
+        .map_err(|e| format!("Invalid token: {}", e))
+}
+
+#[cfg(not(feature = "oidc"))]
+fn decode(_token: &str, _keys: &[Value], _config: &OidcConfig) -> Result<Claims, String> {
+    Err("Built without the oidc feature".to_string())
+}
+
+// Requires `authorization: Bearer <token>` from the issuer on every call, and replaces the
+// principal and tenant headers with the token's, so neither can be claimed without one
+#[derive(Clone)]
+pub struct OidcAuth {
+    verifier: Arc<OidcVerifier>,
+}
+
+impl OidcAuth {
+    pub fn new(verifier: Arc<OidcVerifier>) -> Self {
+        Self { verifier }
+    }
+}
+
+impl Interceptor for OidcAuth {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| ErrorReason::Unauthenticated.status("Bearer token required"))?;
+        let identity = self
+            .verifier
+            .verify(token)
+            .map_err(|e| ErrorReason::Unauthenticated.status(e))?;
+
+        let metadata = |value: &str| {
+            MetadataValue::try_from(value).map_err(|_| {
+                ErrorReason::Unauthenticated.status("Token identity is not a valid metadata value")
+            })
+        };
+        let principal = metadata(&identity.principal)?;
+        let tenant = metadata(&identity.tenant)?;
+        request.metadata_mut().insert(PRINCIPAL_HEADER, principal);
+        request.metadata_mut().insert(TENANT_HEADER, tenant);
+        Ok(request)
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::crypto::provenance::MAX_TRACKED_INPUTS;
+
+// File in the key directory the spent budgets are kept in
+pub const LEDGER_FILE: &str = "privacy-ledger.json";
+
+// Budget each client key starts with unless the operator sets another
+pub const DEFAULT_EPSILON_BUDGET: f64 = 10.0;
+pub const DEFAULT_DELTA_BUDGET: f64 = 1e-5;
+
+// How much noised decryptions under one client key may reveal in total, whether exact
+// integer decryptions are allowed at all, and how many inputs a decrypted value needs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PrivacyConfig {
+    pub epsilon: f64,
+    pub delta: f64,
+    // Refuse DecryptInteger without noise, and every other call that returns decrypted
+    // values, so every plaintext leaves through a mechanism
+    pub require_noise: bool,
+    // Refuse to decrypt a value computed from fewer distinct stored ciphertexts than this,
+    // so an aggregate can't be narrowed down to one record. 0 or 1 allows everything.
+    pub min_inputs: usize,
+}
+
+impl Default for PrivacyConfig {
+    fn default() -> Self {
+        Self {
+            epsilon: DEFAULT_EPSILON_BUDGET,
+            delta: DEFAULT_DELTA_BUDGET,
+            require_noise: false,
+            min_inputs: 0,
+        }
+    }
+}
+
+impl PrivacyConfig {
+    // HERMETIC_FHE_PRIVACY_EPSILON and HERMETIC_FHE_PRIVACY_DELTA set each key's budget,
+    // HERMETIC_FHE_PRIVACY_REQUIRED=1 refuses exact decryptions and
+    // HERMETIC_FHE_PRIVACY_MIN_INPUTS sets the fewest inputs a decrypted value may have
+    pub fn from_env() -> Result<Self> {
+        let budget = |name: &str, default: f64| -> Result<f64> {
+            let Ok(value) = std::env::var(name) else {
+                return Ok(default);
+            };
+            match value.parse::<f64>() {
+                Ok(parsed) if parsed.is_finite() && parsed >= 0.0 => Ok(parsed),
+                _ => Err(anyhow!("{} must be a non-negative number, got '{}'", name, value)),
+            }
+        };
+        let min_inputs = match std::env::var("HERMETIC_FHE_PRIVACY_MIN_INPUTS") {
+            Ok(value) => match value.parse::<usize>() {
+                Ok(parsed) if parsed <= MAX_TRACKED_INPUTS => parsed,
+                _ => {
+                    return Err(anyhow!(
+                        "HERMETIC_FHE_PRIVACY_MIN_INPUTS must be a whole number up to {}, got '{}'",
+                        MAX_TRACKED_INPUTS,
+                        value
+                    ))
+                }
+            },
+            Err(_) => 0,
+        };
+        Ok(Self {
+            epsilon: budget("HERMETIC_FHE_PRIVACY_EPSILON", DEFAULT_EPSILON_BUDGET)?,
+            delta: budget("HERMETIC_FHE_PRIVACY_DELTA", DEFAULT_DELTA_BUDGET)?,
+            require_noise: std::env::var("HERMETIC_FHE_PRIVACY_REQUIRED")
+                .is_ok_and(|value| matches!(value.trim(), "1" | "true")),
+            min_inputs,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mechanism {
+    // Pure epsilon-DP
+    Laplace,
+    // (epsilon, delta)-DP with the classic calibration, which needs epsilon below 1
+    Gaussian,
+}
+
+// Noise a client asked for on one decryption
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Noise {
+    pub mechanism: Mechanism,
+    pub epsilon: f64,
+    pub delta: f64,
+    // Most one individual can change the true value by, at least 1
+    pub sensitivity: f64,
+}
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum PrivacyError {
+    #[error("{0}")]
+    Invalid(String),
+    #[error(
+        "Privacy budget exhausted: epsilon {} and delta {} left under this key",
+        .remaining.epsilon,
+        .remaining.delta
+    )]
+    Exhausted { remaining: Budget },
+    #[error("Privacy ledger could not be saved: {0}")]
+    Storage(String),
+}
+
+impl Noise {
+    pub fn validate(&self) -> Result<(), PrivacyError> {
+        let positive = |value: f64| value.is_finite() && value > 0.0;
+        if !positive(self.epsilon) {
+            return Err(PrivacyError::Invalid("epsilon must be positive".to_string()));
+        }
+        // Decrypted values are integers, so one individual changes them by at least 1. A
+        // smaller sensitivity would shrink the noise below rounding and reveal the exact
+        // value for next to no epsilon.
+        if !self.sensitivity.is_finite() || self.sensitivity < 1.0 {
+            return Err(PrivacyError::Invalid("sensitivity must be at least 1".to_string()));
+        }
+        if self.mechanism == Mechanism::Gaussian {
+            if self.epsilon >= 1.0 {
+                return Err(PrivacyError::Invalid(
+                    "Gaussian noise needs epsilon below 1".to_string(),
+                ));
+            }
+            if !positive(self.delta) || self.delta >= 1.0 {
+                return Err(PrivacyError::Invalid(
+                    "Gaussian noise needs delta between 0 and 1".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // What the decryption costs; Laplace noise spends no delta
+    pub fn cost(&self) -> Budget {
+        Budget {
+            epsilon: self.epsilon,
+            delta: match self.mechanism {
+                Mechanism::Laplace => 0.0,
+                Mechanism::Gaussian => self.delta,
+            },
+        }
+    }
+
+    // Add noise to a decrypted value. Rounding to the nearest integer is post-processing,
+    // so it costs no privacy.
+    pub fn apply(&self, value: i64) -> i64 {
+        let noise = match self.mechanism {
+            Mechanism::Laplace => {
+                // The difference of two exponential samples is Laplace distributed
+                let scale = self.sensitivity / self.epsilon;
+                scale * (uniform().ln() - uniform().ln())
+            }
+            Mechanism::Gaussian => {
+                let sigma = self.sensitivity * (2.0 * (1.25 / self.delta).ln()).sqrt() / self.epsilon;
+                sigma * (-2.0 * uniform().ln()).sqrt() * (std::f64::consts::TAU * uniform()).cos()
+            }
+        };
+        value.saturating_add(noise.round() as i64)
+    }
+}
+
+// Uniform on the open interval (0, 1), from the OS generator so the noise can't be predicted
+fn uniform() -> f64 {
+    ((OsRng.next_u64() >> 11) as f64 + 0.5) / (1u64 << 53) as f64
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Budget {
+    pub epsilon: f64,
+    pub delta: f64,
+}
+
+// Privacy spent by noised decryptions under each client key. With a file, every charge is
+// saved to it before the decryption is returned, so budgets outlive restarts as the keys in
+// a key directory do; without one they start over at startup.
+pub struct PrivacyLedger {
+    config: PrivacyConfig,
+    path: Option<PathBuf>,
+    spent: Mutex<HashMap<String, Budget>>,
+}
+
+impl Default for PrivacyLedger {
+    fn default() -> Self {
+        Self::new(PrivacyConfig::default())
+    }
+}
+
+impl PrivacyLedger {
+    pub fn new(config: PrivacyConfig) -> Self {
+        Self {
+            config,
+            path: None,
+            spent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // A ledger kept in the file, reading back what was spent before if it exists
+    pub fn open(config: PrivacyConfig, path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let spent = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| anyhow!("Invalid privacy ledger {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(anyhow!("Failed to read {}: {}", path.display(), e)),
+        };
+        Ok(Self {
+            config,
+            path: Some(path),
+            spent: Mutex::new(spent),
+        })
+    }
+
+    pub fn config(&self) -> PrivacyConfig {
+        self.config
+    }
+
+    // Whether spent budgets survive a restart
+    pub fn is_durable(&self) -> bool {
+        self.path.is_some()
+    }
+
+    // Pay for a decryption under the key, or refuse it if the key can't afford it.
+    // Returns the budget left.
+    pub fn charge(&self, key_id: &str, cost: Budget) -> Result<Budget, PrivacyError> {
+        let mut spent = self.spent.lock().unwrap();
+        let remaining = self.left(spent.get(key_id));
+        if cost.epsilon > remaining.epsilon || cost.delta > remaining.delta {
+            return Err(PrivacyError::Exhausted { remaining });
+        }
+        let before = spent.get(key_id).copied();
+        let entry = spent.entry(key_id.to_string()).or_default();
+        entry.epsilon += cost.epsilon;
+        entry.delta += cost.delta;
+        let after = *entry;
+
+        // A charge that can't be saved is undone, and the decryption refused
+        if let Some(path) = &self.path {
+            if let Err(e) = save(path, &spent) {
+                match before {
+                    Some(before) => spent.insert(key_id.to_string(), before),
+                    None => spent.remove(key_id),
+                };
+                return Err(PrivacyError::Storage(e.to_string()));
+            }
+        }
+        Ok(self.left(Some(&after)))
+    }
+
+    pub fn remaining(&self, key_id: &str) -> Budget {
+        self.left(self.spent.lock().unwrap().get(key_id))
+    }
+
+    fn left(&self, spent: Option<&Budget>) -> Budget {
+        let spent = spent.copied().unwrap_or_default();
+        Budget {
+            epsilon: (self.config.epsilon - spent.epsilon).max(0.0),
+            delta: (self.config.delta - spent.delta).max(0.0),
+        }
+    }
+}
+
+// Write the ledger to a staging file, sync it and move it into place
+fn save(path: &Path, spent: &HashMap<String, Budget>) -> Result<()> {
+    let bytes = serde_json::to_vec(spent)?;
+    let staging = path.with_extension("tmp");
+    let mut file = fs::File::create(&staging)?;
+    file.write_all(&bytes)?;
+    file.sync_all()?;
+    fs::rename(&staging, path)?;
+    Ok(())
+}
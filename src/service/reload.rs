@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+use crate::api::v1::key_generation_request::ParameterSet;
+use crate::service::admission::FairShareConfig;
+use crate::service::authorization::modified;
+
+// How often the runtime config and policy files are checked for changes by default
+pub const DEFAULT_RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+// Log level used when the runtime config doesn't set one
+pub const DEFAULT_LOG_LEVEL: &str = "info";
+
+// Settings that can change while the server runs, read from a JSON file. Everything else
+// still comes from the environment and needs a restart.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct RuntimeConfig {
+    // A tracing filter, e.g. "info" or "info,hermetic_fhe=debug"
+    #[serde(default)]
+    pub log_level: Option<String>,
+    // The fair-share settings of HERMETIC_FHE_TENANT_WEIGHTS and its neighbours, which the
+    // file replaces
+    #[serde(default)]
+    pub tenant_weights: HashMap<String, u32>,
+    #[serde(default)]
+    pub tenant_max_workers: Option<usize>,
+    #[serde(default)]
+    pub tenant_queue_depth: Option<usize>,
+    #[serde(default)]
+    pub rpc_max_workers: HashMap<String, usize>,
+    // Replace HERMETIC_FHE_WEBHOOK_URLS, HERMETIC_FHE_SINK_DIR and HERMETIC_FHE_SINK_BUCKETS
+    // while set; left out, the environment's value applies again
+    #[serde(default)]
+    pub webhook_urls: Option<Vec<String>>,
+    #[serde(default)]
+    pub sink_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub sink_buckets: Option<Vec<String>>,
+    // The parameter set, e.g. "FAST", used by key generation and cost estimates that ask for
+    // DEFAULT
+    #[serde(default)]
+    pub default_parameter_set: Option<String>,
+}
+
+impl RuntimeConfig {
+    pub fn parse(contents: &[u8]) -> Result<Self> {
+        let config: Self = serde_json::from_slice(contents)?;
+        if config.tenant_weights.values().any(|weight| *weight == 0) {
+            return Err(anyhow!("Tenant weights must be 1 or more"));
+        }
+        if config.tenant_max_workers == Some(0) || config.rpc_max_workers.values().any(|max| *max == 0) {
+            return Err(anyhow!("Worker caps must be at least 1"));
+        }
+        if let Some(name) = &config.default_parameter_set {
+            if ParameterSet::from_str_name(name).is_none() {
+                return Err(anyhow!("Unknown parameter set '{}'", name));
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn fair_share(&self) -> FairShareConfig {
+        FairShareConfig {
+            weights: self.tenant_weights.clone(),
+            tenant_max_workers: self.tenant_max_workers,
+            tenant_queue_depth: self.tenant_queue_depth,
+            rpc_max_workers: self.rpc_max_workers.clone(),
+        }
+    }
+}
+
+// Changes the process's log filter, e.g. through a tracing-subscriber reload handle
+pub type LogLevelSetter = Box<dyn Fn(&str) -> Result<()> + Send + Sync>;
+
+// The runtime config file and what its settings are applied to. Like a policy file, it is
+// read again when modified since the last attempt; a version that doesn't parse, or whose
+// log level is not a valid filter, changes nothing.
+pub struct RuntimeConfigFile {
+    path: PathBuf,
+    log_level: Option<LogLevelSetter>,
+    // Modification time of the version last read, whether or not it applied
+    read: Mutex<Option<Option<SystemTime>>>,
+}
+
+impl RuntimeConfigFile {
+    pub fn new(path: impl Into<PathBuf>, log_level: Option<LogLevelSetter>) -> Self {
+        Self {
+            path: path.into(),
+            log_level,
+            read: Mutex::new(None),
+        }
+    }
+
+    // HERMETIC_FHE_RUNTIME_CONFIG is the path of the file, if there is one
+    pub fn from_env(log_level: Option<LogLevelSetter>) -> Option<Self> {
+        std::env::var("HERMETIC_FHE_RUNTIME_CONFIG")
+            .ok()
+            .map(|path| Self::new(path, log_level))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // The file's settings if it changed since it was last read, or whether or not it did
+    // when forced. The first call always reads it.
+    pub fn read(&self, force: bool) -> Result<Option<RuntimeConfig>> {
+        let current = modified(&self.path);
+        {
+            let mut read = self.read.lock().unwrap();
+            if !force && *read == Some(current) {
+                return Ok(None);
+            }
+            *read = Some(current);
+        }
+        let contents = std::fs::read(&self.path)
+            .map_err(|e| anyhow!("Failed to read {}: {}", self.path.display(), e))?;
+        RuntimeConfig::parse(&contents)
+            .map(Some)
+            .map_err(|e| anyhow!("Invalid runtime config {}: {}", self.path.display(), e))
+    }
+
+    pub fn apply_log_level(&self, config: &RuntimeConfig) -> Result<()> {
+        if let Some(set_log_level) = &self.log_level {
+            let level = config.log_level.as_deref().unwrap_or(DEFAULT_LOG_LEVEL);
+            set_log_level(level).map_err(|e| anyhow!("Invalid log level '{}': {}", level, e))?;
+        }
+        Ok(())
+    }
+}
+
+// HERMETIC_FHE_CONFIG_RELOAD_SECONDS, 0 to only reload through the admin service
+pub fn reload_interval_from_env() -> Result<Option<Duration>> {
+    match std::env::var("HERMETIC_FHE_CONFIG_RELOAD_SECONDS") {
+        Ok(value) => {
+            let seconds: u64 = value
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("HERMETIC_FHE_CONFIG_RELOAD_SECONDS must be a non-negative integer"))?;
+            Ok((seconds > 0).then(|| Duration::from_secs(seconds)))
+        }
+        Err(_) => Ok(Some(DEFAULT_RELOAD_INTERVAL)),
+    }
+}
+
+// What one reload did: the files applied, and why any others weren't
+#[derive(Debug, Default)]
+pub struct ReloadOutcome {
+    pub reloaded: Vec<&'static str>,
+    pub errors: Vec<String>,
+}
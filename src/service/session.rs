@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use uuid::Uuid;
+
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+pub const MAX_IDLE_TIMEOUT: Duration = Duration::from_secs(24 * 60 * 60);
+
+struct Session {
+    idle_timeout: Duration,
+    last_used: Instant,
+    ciphertext_ids: Vec<String>,
+}
+
+impl Session {
+    fn is_expired(&self, now: Instant) -> bool {
+        now.duration_since(self.last_used) > self.idle_timeout
+    }
+}
+
+// Operator view of an open session
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SessionSummary {
+    pub session_id: String,
+    pub ciphertexts: usize,
+    pub idle: Duration,
+    pub idle_timeout: Duration,
+}
+
+// A session's settings and ciphertexts, as kept in a backup
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SessionRecord {
+    pub session_id: String,
+    pub idle_timeout: Duration,
+    pub ciphertext_ids: Vec<String>,
+}
+
+// Groups the ciphertexts a client creates so they can be freed together
+pub struct SessionStore {
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl SessionStore {
+    pub fn new() -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn create(&self, idle_timeout: Duration) -> String {
+        let id = Uuid::new_v4().to_string();
+        self.sessions.lock().unwrap().insert(
+            id.clone(),
+            Session {
+                idle_timeout,
+                last_used: Instant::now(),
+                ciphertext_ids: Vec::new(),
+            },
+        );
+        id
+    }
+
+    // Refresh a session's idle timer; false if it is unknown or already expired
+    pub fn touch(&self, session_id: &str) -> bool {
+        let now = Instant::now();
+        match self.sessions.lock().unwrap().get_mut(session_id) {
+            Some(session) if !session.is_expired(now) => {
+                session.last_used = now;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Attach a ciphertext to a session; false if the session has already closed
+    pub fn track(&self, session_id: &str, ciphertext_id: &str) -> bool {
+        match self.sessions.lock().unwrap().get_mut(session_id) {
+            Some(session) => {
+                session.ciphertext_ids.push(ciphertext_id.to_string());
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Close a session, returning the ciphertext IDs it owned
+    pub fn close(&self, session_id: &str) -> Option<Vec<String>> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .remove(session_id)
+            .map(|session| session.ciphertext_ids)
+    }
+
+    // Every session not yet reaped, sorted by ID
+    pub fn list(&self) -> Vec<SessionSummary> {
+        let now = Instant::now();
+        let mut summaries: Vec<SessionSummary> = self
+            .sessions
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, session)| SessionSummary {
+                session_id: id.clone(),
+                ciphertexts: session.ciphertext_ids.len(),
+                idle: now.duration_since(session.last_used),
+                idle_timeout: session.idle_timeout,
+            })
+            .collect();
+        summaries.sort_by(|a, b| a.session_id.cmp(&b.session_id));
+        summaries
+    }
+
+    // Every session not yet reaped, with the ciphertexts it owns
+    pub fn records(&self) -> Vec<SessionRecord> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, session)| SessionRecord {
+                session_id: id.clone(),
+                idle_timeout: session.idle_timeout,
+                ciphertext_ids: session.ciphertext_ids.clone(),
+            })
+            .collect()
+    }
+
+    // Open a session from a record, replacing any of the same ID, with its idle timer
+    // starting now
+    pub fn restore(&self, record: SessionRecord) {
+        self.sessions.lock().unwrap().insert(
+            record.session_id,
+            Session {
+                idle_timeout: record.idle_timeout,
+                last_used: Instant::now(),
+                ciphertext_ids: record.ciphertext_ids,
+            },
+        );
+    }
+
+    // Close the session idle the longest, returning its ID and the ciphertext IDs it owned;
+    // None if there are no sessions
+    pub fn take_least_recently_used(&self) -> Option<(String, Vec<String>)> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let id = sessions
+            .iter()
+            .min_by_key(|(_, session)| session.last_used)
+            .map(|(id, _)| id.clone())?;
+        let session = sessions.remove(&id)?;
+        Some((id, session.ciphertext_ids))
+    }
+
+    // Drop every expired session, returning the ciphertext IDs they owned
+    pub fn take_expired(&self) -> Vec<String> {
+        let now = Instant::now();
+        let mut sessions = self.sessions.lock().unwrap();
+        let expired: Vec<String> = sessions
+            .iter()
+            .filter(|(_, session)| session.is_expired(now))
+            .map(|(id, _)| id.clone())
+            .collect();
+
+        expired
+            .iter()
+            .filter_map(|id| sessions.remove(id))
+            .flat_map(|session| session.ciphertext_ids)
+            .collect()
+    }
+}
+
+impl Default for SessionStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
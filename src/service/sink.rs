@@ -0,0 +1,300 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+#[cfg(feature = "s3-sink")]
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+#[cfg(feature = "s3-sink")]
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tracing::warn;
+use uuid::Uuid;
+
+#[cfg(feature = "s3-sink")]
+use crate::crypto::fingerprint::to_hex;
+#[cfg(feature = "s3-sink")]
+use crate::crypto::sigv4::AwsCredentials;
+#[cfg(feature = "fault-injection")]
+use crate::service::faults::{FaultConfig, FaultySink};
+
+// Attempts per result; the pause before a retry doubles each time. Writes replace
+// whatever is under the name, so a retry after a write that half happened is safe.
+const WRITE_ATTEMPTS: u32 = 3;
+const FIRST_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+// Where a map job writes its serialized results instead of the ciphertext store, so
+// downstream pipelines can read them without paging them back through gRPC
+pub trait ResultSink: Send + Sync {
+    // Short description for logs
+    fn describe(&self) -> String;
+
+    // Write one result under a relative name such as its label, returning where it went
+    fn write(&self, name: &str, bytes: &[u8]) -> Result<String>;
+}
+
+// Write one result, retrying failed attempts. Blocks through the pauses, as map jobs
+// write from their worker threads.
+pub fn write_with_retries(sink: &dyn ResultSink, name: &str, bytes: &[u8]) -> Result<String> {
+    let mut delay = FIRST_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        match sink.write(name, bytes) {
+            Ok(location) => return Ok(location),
+            Err(e) if attempt < WRITE_ATTEMPTS => {
+                warn!("Writing {} to {} failed, retrying: {}", name, sink.describe(), e);
+                std::thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(e) => {
+                return Err(anyhow!(
+                    "Gave up writing {} after {} attempts: {}",
+                    name,
+                    attempt,
+                    e
+                ))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum SinkError {
+    // The operator has not allowed the sink the client named
+    #[error("{0}")]
+    NotAllowed(String),
+    #[error("{0}")]
+    Invalid(String),
+}
+
+// The sinks clients may name, chosen by the operator
+#[derive(Clone, Default)]
+pub struct SinkPolicy {
+    // Directory file sinks are confined to, typically a mounted volume; None refuses them
+    root: Option<PathBuf>,
+    // Buckets S3 sinks may write to; empty refuses them
+    buckets: Vec<String>,
+    #[cfg(feature = "s3-sink")]
+    credentials: Option<Arc<AwsCredentials>>,
+    #[cfg(feature = "fault-injection")]
+    faults: Option<FaultConfig>,
+}
+
+impl SinkPolicy {
+    // HERMETIC_FHE_SINK_DIR is the directory file sinks resolve their paths under, and
+    // HERMETIC_FHE_SINK_BUCKETS a comma-separated list of buckets S3 sinks may name,
+    // written with the standard AWS_* credentials. Both unset refuses every sink.
+    pub fn from_env() -> Result<Self> {
+        let root = std::env::var("HERMETIC_FHE_SINK_DIR").ok().map(PathBuf::from);
+        let buckets: Vec<String> = std::env::var("HERMETIC_FHE_SINK_BUCKETS")
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|bucket| !bucket.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        #[cfg(feature = "s3-sink")]
+        let credentials = if buckets.is_empty() {
+            None
+        } else {
+            Some(Arc::new(AwsCredentials::from_env()?))
+        };
+        #[cfg(not(feature = "s3-sink"))]
+        if !buckets.is_empty() {
+            return Err(anyhow!("HERMETIC_FHE_SINK_BUCKETS requires the s3-sink feature"));
+        }
+
+        Ok(Self {
+            root,
+            buckets,
+            #[cfg(feature = "s3-sink")]
+            credentials,
+            #[cfg(feature = "fault-injection")]
+            faults: None,
+        })
+    }
+
+    // Allow file sinks under root, creating it if needed
+    pub fn with_root(mut self, root: impl Into<PathBuf>) -> Result<Self> {
+        let root = root.into();
+        fs::create_dir_all(&root)
+            .map_err(|e| anyhow!("Failed to create sink directory {}: {}", root.display(), e))?;
+        self.root = Some(root);
+        Ok(self)
+    }
+
+    // Let S3 sinks name these buckets instead, reading the AWS_* credentials if no bucket
+    // needed them before
+    pub fn with_buckets(mut self, buckets: Vec<String>) -> Result<Self> {
+        #[cfg(feature = "s3-sink")]
+        if !buckets.is_empty() && self.credentials.is_none() {
+            self.credentials = Some(Arc::new(AwsCredentials::from_env()?));
+        }
+        #[cfg(not(feature = "s3-sink"))]
+        if !buckets.is_empty() {
+            return Err(anyhow!("S3 sink buckets require the s3-sink feature"));
+        }
+        self.buckets = buckets;
+        Ok(self)
+    }
+
+    // Inject the given failures into every sink opened under this policy
+    #[cfg(feature = "fault-injection")]
+    pub fn with_faults(mut self, faults: FaultConfig) -> Self {
+        self.faults = Some(faults);
+        self
+    }
+
+    pub fn root(&self) -> Option<&Path> {
+        self.root.as_deref()
+    }
+
+    pub fn buckets(&self) -> &[String] {
+        &self.buckets
+    }
+
+    // A sink writing files into path, a directory relative to the root
+    pub fn directory(&self, path: &str) -> Result<DirectorySink, SinkError> {
+        let root = self
+            .root
+            .as_ref()
+            .ok_or_else(|| SinkError::NotAllowed("File sinks are not enabled on this server".to_string()))?;
+        Ok(DirectorySink {
+            path: root.join(relative_path(path)?),
+        })
+    }
+
+    // The sink as jobs see it, behind injected failures if any are configured
+    pub fn wrap(&self, sink: Box<dyn ResultSink>) -> Box<dyn ResultSink> {
+        #[cfg(feature = "fault-injection")]
+        if let Some(faults) = &self.faults {
+            return Box::new(FaultySink::new(sink, faults.clone()));
+        }
+        sink
+    }
+
+    // A sink writing objects to the bucket, their keys starting with prefix
+    pub fn s3(&self, bucket: &str, prefix: &str) -> Result<Box<dyn ResultSink>, SinkError> {
+        if !self.buckets.iter().any(|allowed| allowed == bucket) {
+            return Err(SinkError::NotAllowed(format!(
+                "Bucket '{}' is not an allowed sink",
+                bucket
+            )));
+        }
+        relative_path(prefix)?;
+
+        #[cfg(feature = "s3-sink")]
+        if let Some(credentials) = &self.credentials {
+            return Ok(Box::new(S3Sink {
+                credentials: credentials.clone(),
+                bucket: bucket.to_string(),
+                prefix: prefix.to_string(),
+            }));
+        }
+        Err(SinkError::NotAllowed(
+            "S3 sinks are not enabled on this server".to_string(),
+        ))
+    }
+}
+
+// Names come from clients and labels, so they may not climb out of where they are
+// resolved: relative, with no `..` or root components
+fn relative_path(path: &str) -> Result<&Path, SinkError> {
+    let relative = Path::new(path);
+    if relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        Ok(relative)
+    } else {
+        Err(SinkError::Invalid(format!(
+            "Sink path '{}' must be relative and stay inside the sink",
+            path
+        )))
+    }
+}
+
+// Files in a directory, one per result, named after it
+pub struct DirectorySink {
+    path: PathBuf,
+}
+
+impl ResultSink for DirectorySink {
+    fn describe(&self) -> String {
+        format!("directory {}", self.path.display())
+    }
+
+    // Written to a temporary file and renamed, so readers never see half a ciphertext;
+    // the temporary name is unique since records are written in parallel
+    fn write(&self, name: &str, bytes: &[u8]) -> Result<String> {
+        let target = self.path.join(relative_path(name)?);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| anyhow!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let staging = target.with_file_name(format!(".{}.tmp", Uuid::new_v4()));
+        fs::write(&staging, bytes)
+            .and_then(|_| fs::rename(&staging, &target))
+            .map_err(|e| anyhow!("Failed to write {}: {}", target.display(), e))?;
+        Ok(target.display().to_string())
+    }
+}
+
+// Objects in an S3 bucket, put one per result with SigV4
+#[cfg(feature = "s3-sink")]
+pub struct S3Sink {
+    credentials: Arc<AwsCredentials>,
+    bucket: String,
+    prefix: String,
+}
+
+#[cfg(feature = "s3-sink")]
+impl ResultSink for S3Sink {
+    fn describe(&self) -> String {
+        format!("s3://{}/{}", self.bucket, self.prefix)
+    }
+
+    fn write(&self, name: &str, bytes: &[u8]) -> Result<String> {
+        relative_path(name)?;
+        let key = if self.prefix.is_empty() || self.prefix.ends_with('/') {
+            format!("{}{}", self.prefix, name)
+        } else {
+            format!("{}/{}", self.prefix, name)
+        };
+        let host = format!("{}.s3.{}.amazonaws.com", self.bucket, self.credentials.region);
+        let path = format!("/{}", uri_encode(&key));
+
+        let headers = vec![
+            ("host", host.clone()),
+            ("x-amz-content-sha256", to_hex(&Sha256::digest(bytes))),
+        ];
+        let mut request = ureq::put(&format!("https://{}{}", host, path));
+        for (name, value) in self.credentials.sign("s3", "PUT", &path, headers, bytes) {
+            if name != "host" {
+                request = request.set(name, &value);
+            }
+        }
+        request
+            .send_bytes(bytes)
+            .map_err(|e| anyhow!("Failed to put s3://{}/{}: {}", self.bucket, key, e))?;
+        Ok(format!("s3://{}/{}", self.bucket, key))
+    }
+}
+
+// S3 canonical URIs percent-encode everything but unreserved characters and slashes
+#[cfg(feature = "s3-sink")]
+fn uri_encode(key: &str) -> String {
+    key.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
@@ -0,0 +1,167 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::RwLock;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+
+pub type Tags = BTreeMap<String, String>;
+
+const MAX_TAGS: usize = 32;
+const MAX_TAG_KEY_LENGTH: usize = 128;
+const MAX_TAG_VALUE_LENGTH: usize = 256;
+
+// Key/value tags on stored ciphertexts, indexed both ways: the tags of each ciphertext,
+// and the ciphertexts carrying each tag, so a query only visits the values it matches.
+pub struct TagIndex {
+    index: RwLock<Index>,
+}
+
+#[derive(Default)]
+struct Index {
+    by_ciphertext: HashMap<String, Tags>,
+    // Kept in order, so every value of one key sits together
+    by_tag: BTreeMap<(String, String), BTreeSet<String>>,
+}
+
+impl Index {
+    fn insert(&mut self, ciphertext_id: &str, tags: Tags) {
+        for tag in tags.clone() {
+            self.by_tag
+                .entry(tag)
+                .or_default()
+                .insert(ciphertext_id.to_string());
+        }
+        if !tags.is_empty() {
+            self.by_ciphertext.insert(ciphertext_id.to_string(), tags);
+        }
+    }
+
+    fn remove(&mut self, ciphertext_id: &str) {
+        let tags = self.by_ciphertext.remove(ciphertext_id).unwrap_or_default();
+        for tag in tags {
+            if let Some(ids) = self.by_tag.get_mut(&tag) {
+                ids.remove(ciphertext_id);
+                if ids.is_empty() {
+                    self.by_tag.remove(&tag);
+                }
+            }
+        }
+    }
+
+    // Ciphertexts with the key set, to the value if one is given
+    fn matching(&self, key: &str, value: Option<&str>) -> BTreeSet<String> {
+        if let Some(value) = value {
+            let tag = (key.to_string(), value.to_string());
+            return self.by_tag.get(&tag).cloned().unwrap_or_default();
+        }
+        self.by_tag
+            .range((key.to_string(), String::new())..)
+            .take_while(|((tag_key, _), _)| tag_key == key)
+            .flat_map(|(_, ids)| ids.iter().cloned())
+            .collect()
+    }
+}
+
+impl TagIndex {
+    pub fn new() -> Self {
+        Self {
+            index: RwLock::new(Index::default()),
+        }
+    }
+
+    // Set and remove tags on a ciphertext, or with replace drop every tag it had before
+    // setting. Nothing changes if the result would break a limit. Returns its tags after.
+    pub fn update(&self, ciphertext_id: &str, set: Tags, remove: &[String], replace: bool) -> Result<Tags> {
+        for (key, value) in &set {
+            check_tag(key, value)?;
+        }
+        let mut index = self.index.write().unwrap();
+        let mut tags = match replace {
+            true => Tags::new(),
+            false => index
+                .by_ciphertext
+                .get(ciphertext_id)
+                .cloned()
+                .unwrap_or_default(),
+        };
+        for key in remove {
+            tags.remove(key);
+        }
+        tags.extend(set);
+        if tags.len() > MAX_TAGS {
+            return Err(anyhow!(
+                "A ciphertext takes at most {} tags, not {}",
+                MAX_TAGS,
+                tags.len()
+            ));
+        }
+        index.remove(ciphertext_id);
+        index.insert(ciphertext_id, tags.clone());
+        Ok(tags)
+    }
+
+    pub fn tags(&self, ciphertext_id: &str) -> Tags {
+        let index = self.index.read().unwrap();
+        index
+            .by_ciphertext
+            .get(ciphertext_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    // Ciphertexts matching every filter, each a key and, unless empty, the value it must
+    // have. Ciphertexts since freed are included until forgotten.
+    pub fn query(&self, filters: &[(String, String)]) -> BTreeSet<String> {
+        let index = self.index.read().unwrap();
+        let mut matched: Option<BTreeSet<String>> = None;
+        for (key, value) in filters {
+            let value = Some(value.as_str()).filter(|value| !value.is_empty());
+            let ids = index.matching(key, value);
+            matched = Some(match matched {
+                Some(matched) => matched.intersection(&ids).cloned().collect(),
+                None => ids,
+            });
+        }
+        matched.unwrap_or_default()
+    }
+
+    // Drop a freed ciphertext's tags
+    pub fn forget(&self, ciphertext_id: &str) {
+        self.index.write().unwrap().remove(ciphertext_id);
+    }
+}
+
+impl Default for TagIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn check_tag(key: &str, value: &str) -> Result<()> {
+    if key.is_empty() || key.len() > MAX_TAG_KEY_LENGTH {
+        return Err(anyhow!("Tag keys must be 1 to {} bytes", MAX_TAG_KEY_LENGTH));
+    }
+    if value.len() > MAX_TAG_VALUE_LENGTH {
+        return Err(anyhow!(
+            "Tag '{}' has a value over {} bytes",
+            key,
+            MAX_TAG_VALUE_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+// Where a page of QueryCiphertexts ends: the creation time, since the epoch, and ID of its
+// last value. The next page starts past it, so values stored or freed in between don't
+// shift the pages after.
+pub fn page_token(position: &(Duration, String)) -> String {
+    format!("{}.{}", position.0.as_nanos(), position.1)
+}
+
+pub fn parse_page_token(token: &str) -> Result<(Duration, String)> {
+    let position = token.split_once('.').and_then(|(nanos, id)| {
+        let nanos: u64 = nanos.parse().ok()?;
+        Some((Duration::from_nanos(nanos), id.to_string()))
+    });
+    position.ok_or_else(|| anyhow!("Malformed page token '{}'", token))
+}
@@ -0,0 +1,151 @@
+use std::env;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use tonic::transport::{Identity, Server, ServerTlsConfig};
+use zeroize::Zeroizing;
+
+use crate::service::fhe_service::MAX_MESSAGE_BYTES;
+
+// Ping idle HTTP/2 connections this often, so load balancers and NATs that drop quiet
+// connections don't cut off a client waiting on a long evaluation
+pub const DEFAULT_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(60);
+pub const DEFAULT_KEEPALIVE_TIMEOUT: Duration = Duration::from_secs(20);
+
+// Connection-level settings for the gRPC servers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TransportConfig {
+    // Largest request message decoded and largest response message sent
+    pub max_request_bytes: usize,
+    pub max_response_bytes: usize,
+    // HTTP/2 pings on idle connections; a connection that doesn't answer one within the
+    // timeout is closed. None turns pings off.
+    pub keepalive_interval: Option<Duration>,
+    pub keepalive_timeout: Duration,
+    // TCP keepalive probes, off unless set
+    pub tcp_keepalive: Option<Duration>,
+    // Requests served at once on one connection, and streams one client may open on it;
+    // None leaves them unbounded
+    pub concurrency_per_connection: Option<usize>,
+    pub max_concurrent_streams: Option<u32>,
+}
+
+impl Default for TransportConfig {
+    fn default() -> Self {
+        Self {
+            max_request_bytes: MAX_MESSAGE_BYTES,
+            max_response_bytes: MAX_MESSAGE_BYTES,
+            keepalive_interval: Some(DEFAULT_KEEPALIVE_INTERVAL),
+            keepalive_timeout: DEFAULT_KEEPALIVE_TIMEOUT,
+            tcp_keepalive: None,
+            concurrency_per_connection: None,
+            max_concurrent_streams: None,
+        }
+    }
+}
+
+impl TransportConfig {
+    // Read the settings below from the environment, falling back to the defaults:
+    // HERMETIC_FHE_MAX_REQUEST_BYTES, HERMETIC_FHE_MAX_RESPONSE_BYTES,
+    // HERMETIC_FHE_KEEPALIVE_INTERVAL_SECONDS (0 turns pings off),
+    // HERMETIC_FHE_KEEPALIVE_TIMEOUT_SECONDS, HERMETIC_FHE_TCP_KEEPALIVE_SECONDS,
+    // HERMETIC_FHE_CONNECTION_CONCURRENCY and HERMETIC_FHE_MAX_CONCURRENT_STREAMS
+    pub fn from_env() -> Result<Self> {
+        let mut config = Self::default();
+        if let Some(bytes) = env_positive("HERMETIC_FHE_MAX_REQUEST_BYTES")? {
+            config.max_request_bytes = bytes as usize;
+        }
+        if let Some(bytes) = env_positive("HERMETIC_FHE_MAX_RESPONSE_BYTES")? {
+            config.max_response_bytes = bytes as usize;
+        }
+        if let Ok(seconds) = env::var("HERMETIC_FHE_KEEPALIVE_INTERVAL_SECONDS") {
+            let seconds: u64 = seconds.trim().parse().map_err(|_| {
+                anyhow!("HERMETIC_FHE_KEEPALIVE_INTERVAL_SECONDS must be a non-negative integer")
+            })?;
+            config.keepalive_interval = (seconds > 0).then(|| Duration::from_secs(seconds));
+        }
+        if let Some(seconds) = env_positive("HERMETIC_FHE_KEEPALIVE_TIMEOUT_SECONDS")? {
+            config.keepalive_timeout = Duration::from_secs(seconds);
+        }
+        if let Some(seconds) = env_positive("HERMETIC_FHE_TCP_KEEPALIVE_SECONDS")? {
+            config.tcp_keepalive = Some(Duration::from_secs(seconds));
+        }
+        if let Some(limit) = env_positive("HERMETIC_FHE_CONNECTION_CONCURRENCY")? {
+            config.concurrency_per_connection = Some(limit as usize);
+        }
+        if let Some(streams) = env_positive("HERMETIC_FHE_MAX_CONCURRENT_STREAMS")? {
+            config.max_concurrent_streams = Some(
+                u32::try_from(streams)
+                    .map_err(|_| anyhow!("HERMETIC_FHE_MAX_CONCURRENT_STREAMS is too large"))?,
+            );
+        }
+        Ok(config)
+    }
+
+    // The connection settings; message sizes are set per service, since tonic keeps them
+    // on the generated servers rather than the transport
+    pub fn server(&self) -> Server {
+        let mut server = Server::builder()
+            .http2_keepalive_interval(self.keepalive_interval)
+            .http2_keepalive_timeout(Some(self.keepalive_timeout))
+            .tcp_keepalive(self.tcp_keepalive)
+            .max_concurrent_streams(self.max_concurrent_streams);
+        if let Some(limit) = self.concurrency_per_connection {
+            server = server.concurrency_limit_per_connection(limit);
+        }
+        server
+    }
+}
+
+// Certificate chain and private key the servers present, both PEM. Without them they
+// serve plaintext and expect TLS to end at a proxy in front.
+pub struct TlsIdentity {
+    pub certificate: Vec<u8>,
+    key: Zeroizing<Vec<u8>>,
+}
+
+impl TlsIdentity {
+    pub fn new(certificate: Vec<u8>, key: Vec<u8>) -> Self {
+        Self {
+            certificate,
+            key: Zeroizing::new(key),
+        }
+    }
+
+    // HERMETIC_FHE_TLS_CERT and HERMETIC_FHE_TLS_KEY are the paths of the PEM files; one
+    // without the other is an error
+    pub fn from_env() -> Result<Option<Self>> {
+        let read = |name: &str| -> Result<Option<Vec<u8>>> {
+            match env::var(name) {
+                Ok(path) => std::fs::read(&path)
+                    .map(Some)
+                    .map_err(|e| anyhow!("Failed to read {} from {}: {}", name, path, e)),
+                Err(_) => Ok(None),
+            }
+        };
+        match (read("HERMETIC_FHE_TLS_CERT")?, read("HERMETIC_FHE_TLS_KEY")?) {
+            (Some(certificate), Some(key)) => Ok(Some(Self::new(certificate, key))),
+            (None, None) => Ok(None),
+            _ => Err(anyhow!(
+                "HERMETIC_FHE_TLS_CERT and HERMETIC_FHE_TLS_KEY must be set together"
+            )),
+        }
+    }
+
+    pub fn server_config(&self) -> ServerTlsConfig {
+        ServerTlsConfig::new().identity(Identity::from_pem(&self.certificate, self.key.as_slice()))
+    }
+}
+
+fn env_positive(name: &str) -> Result<Option<u64>> {
+    match env::var(name) {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .ok()
+            .filter(|value| *value > 0)
+            .map(Some)
+            .ok_or_else(|| anyhow!("{} must be a positive integer", name)),
+        Err(_) => Ok(None),
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+// Metadata header naming the tenant a request is billed to
+pub const TENANT_HEADER: &str = "x-tenant-id";
+
+pub const DEFAULT_EXPORT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// Who a piece of work is billed to and what it was
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct UsageTag {
+    pub tenant: String,
+    pub key_id: String,
+    pub operation: &'static str,
+}
+
+impl UsageTag {
+    pub fn new(tenant: impl Into<String>, key_id: impl Into<String>, operation: &'static str) -> Self {
+        Self {
+            tenant: tenant.into(),
+            key_id: key_id.into(),
+            operation,
+        }
+    }
+}
+
+// Cumulative usage of one operation by one key of one tenant
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct UsageRecord {
+    pub tenant: String,
+    pub key_id: String,
+    pub operation: String,
+    pub count: u64,
+    pub compute_seconds: f64,
+}
+
+#[derive(Default)]
+struct Totals {
+    count: u64,
+    compute: Duration,
+}
+
+// Running totals of operations and FHE compute time since startup, for charging
+// internal teams. Compute is the time spent evaluating, not waiting in the queue.
+#[derive(Default)]
+pub struct UsageLedger {
+    totals: Mutex<BTreeMap<UsageTag, Totals>>,
+}
+
+impl UsageLedger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, tag: &UsageTag, compute: Duration) {
+        let mut totals = self.totals.lock().unwrap();
+        let entry = totals.entry(tag.clone()).or_default();
+        entry.count += 1;
+        entry.compute += compute;
+    }
+
+    // Every record, sorted by tenant, key and operation; an empty tenant means all tenants
+    pub fn records(&self, tenant: &str) -> Vec<UsageRecord> {
+        self.totals
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(tag, _)| tenant.is_empty() || tag.tenant == tenant)
+            .map(|(tag, totals)| UsageRecord {
+                tenant: tag.tenant.clone(),
+                key_id: tag.key_id.clone(),
+                operation: tag.operation.to_string(),
+                count: totals.count,
+                compute_seconds: totals.compute.as_secs_f64(),
+            })
+            .collect()
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("tenant,key_id,operation,count,compute_seconds\n");
+        for record in self.records("") {
+            csv.push_str(&format!(
+                "{},{},{},{},{:.6}\n",
+                csv_field(&record.tenant),
+                csv_field(&record.key_id),
+                record.operation,
+                record.count,
+                record.compute_seconds
+            ));
+        }
+        csv
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(&self.records(""))
+            .map_err(|e| anyhow!("Failed to encode usage: {}", e))
+    }
+}
+
+// Tenant names come from clients, so quote anything that could break a row apart
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+// Periodic snapshot of the ledger written to a file, replacing the previous one
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UsageExport {
+    pub path: PathBuf,
+    pub format: ExportFormat,
+    pub interval: Duration,
+}
+
+impl UsageExport {
+    // HERMETIC_FHE_USAGE_EXPORT names the file (.csv for CSV, anything else for JSON) and
+    // HERMETIC_FHE_USAGE_EXPORT_INTERVAL the seconds between writes. None when no file is set.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(path) = std::env::var("HERMETIC_FHE_USAGE_EXPORT") else {
+            return Ok(None);
+        };
+        let path = PathBuf::from(path);
+        let format = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("csv") => ExportFormat::Csv,
+            _ => ExportFormat::Json,
+        };
+        let interval = match std::env::var("HERMETIC_FHE_USAGE_EXPORT_INTERVAL") {
+            Ok(value) => match value.trim().parse::<u64>() {
+                Ok(seconds) if seconds > 0 => Duration::from_secs(seconds),
+                _ => return Err(anyhow!("HERMETIC_FHE_USAGE_EXPORT_INTERVAL must be a positive integer")),
+            },
+            Err(_) => DEFAULT_EXPORT_INTERVAL,
+        };
+        Ok(Some(Self { path, format, interval }))
+    }
+
+    pub fn write(&self, ledger: &UsageLedger) -> Result<()> {
+        let contents = match self.format {
+            ExportFormat::Csv => ledger.to_csv(),
+            ExportFormat::Json => ledger.to_json()?,
+        };
+        let staging = self.path.with_extension("tmp");
+        fs::write(&staging, contents)
+            .and_then(|_| fs::rename(&staging, &self.path))
+            .map_err(|e| anyhow!("Failed to write {}: {}", self.path.display(), e))
+    }
+}
@@ -0,0 +1,43 @@
+use anyhow::{anyhow, Result};
+use tonic::codegen::http::{HeaderName, HeaderValue, Method};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use crate::service::usage::TENANT_HEADER;
+
+// Headers a gRPC-Web client sends, and those it must be able to read in responses
+const REQUEST_HEADERS: [&str; 6] = [
+    "content-type",
+    "authorization",
+    "x-grpc-web",
+    "x-user-agent",
+    "grpc-timeout",
+    TENANT_HEADER,
+];
+const RESPONSE_HEADERS: [&str; 3] = ["grpc-status", "grpc-message", "grpc-status-details-bin"];
+
+// CORS for browser clients reaching the service over gRPC-Web from another origin. Only the
+// origins listed in HERMETIC_FHE_WEB_ORIGINS (comma-separated) are allowed; with none set,
+// only pages served from the service's own origin can call it.
+pub fn cors_from_env() -> Result<CorsLayer> {
+    let origins = match std::env::var("HERMETIC_FHE_WEB_ORIGINS") {
+        Ok(origins) => parse_origins(&origins)?,
+        Err(_) => Vec::new(),
+    };
+    Ok(CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_methods([Method::POST])
+        .allow_headers(REQUEST_HEADERS.map(HeaderName::from_static))
+        .expose_headers(RESPONSE_HEADERS.map(HeaderName::from_static)))
+}
+
+fn parse_origins(origins: &str) -> Result<Vec<HeaderValue>> {
+    origins
+        .split(',')
+        .map(str::trim)
+        .filter(|origin| !origin.is_empty())
+        .map(|origin| {
+            HeaderValue::from_str(origin)
+                .map_err(|_| anyhow!("HERMETIC_FHE_WEB_ORIGINS has an invalid origin: {}", origin))
+        })
+        .collect()
+}
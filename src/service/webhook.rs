@@ -0,0 +1,202 @@
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use tracing::{info, warn};
+use zeroize::Zeroizing;
+
+use crate::crypto::fingerprint::to_hex;
+
+// Header carrying a payload's signature, `sha256=` and the hex HMAC-SHA256 of the body
+// under the server's webhook secret
+pub const SIGNATURE_HEADER: &str = "X-Hermetic-Signature";
+
+// Attempts per notification; the pause before a retry doubles each time
+const DELIVERY_ATTEMPTS: u32 = 3;
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+// What a finished job reports to its callback
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct JobEvent {
+    pub job_id: String,
+    // "map" for map and join jobs, "migration" for migrations
+    pub kind: &'static str,
+    // "succeeded" if every record did, "failed" if any did not
+    pub status: &'static str,
+    pub total: usize,
+    pub failed: usize,
+    // Seconds since the epoch, so receivers can refuse replayed notifications
+    pub finished_at: u64,
+}
+
+impl JobEvent {
+    pub fn new(job_id: &str, kind: &'static str, total: usize, failed: usize) -> Self {
+        Self {
+            job_id: job_id.to_string(),
+            kind,
+            status: if failed == 0 { "succeeded" } else { "failed" },
+            total,
+            failed,
+            finished_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        }
+    }
+}
+
+// Where finished jobs may send notifications, and the secret that signs them. Without a
+// secret every callback is refused.
+#[derive(Clone, Default)]
+pub struct WebhookPolicy {
+    secret: Option<Arc<Zeroizing<Vec<u8>>>>,
+    // URL prefixes callbacks must start with, each ending in a slash so a prefix never
+    // matches a longer host name
+    allowed: Vec<String>,
+}
+
+impl WebhookPolicy {
+    pub fn new(secret: impl Into<Vec<u8>>, allowed: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            secret: Some(Arc::new(Zeroizing::new(secret.into()))),
+            allowed: prefixes(allowed),
+        }
+    }
+
+    // The same secret with other URL prefixes
+    pub fn with_allowed(&self, allowed: impl IntoIterator<Item = String>) -> Result<Self> {
+        let allowed = prefixes(allowed);
+        if self.secret.is_none() && !allowed.is_empty() {
+            return Err(anyhow!("Webhook URLs need HERMETIC_FHE_WEBHOOK_SECRET to be set"));
+        }
+        Ok(Self {
+            secret: self.secret.clone(),
+            allowed,
+        })
+    }
+
+    // HERMETIC_FHE_WEBHOOK_SECRET signs notifications and HERMETIC_FHE_WEBHOOK_URLS is a
+    // comma-separated list of URL prefixes callbacks may use. Both are needed to enable
+    // webhooks.
+    pub fn from_env() -> Result<Self> {
+        let secret = std::env::var("HERMETIC_FHE_WEBHOOK_SECRET").ok();
+        let allowed: Vec<String> = std::env::var("HERMETIC_FHE_WEBHOOK_URLS")
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|prefix| !prefix.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        if secret.is_none() && allowed.is_empty() {
+            return Ok(Self::default());
+        }
+        if !cfg!(feature = "webhooks") {
+            return Err(anyhow!(
+                "HERMETIC_FHE_WEBHOOK_* settings require the webhooks feature"
+            ));
+        }
+        match secret {
+            Some(secret) if !allowed.is_empty() => Ok(Self::new(secret, allowed)),
+            _ => Err(anyhow!(
+                "HERMETIC_FHE_WEBHOOK_SECRET and HERMETIC_FHE_WEBHOOK_URLS must be set together"
+            )),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        cfg!(feature = "webhooks") && self.secret.is_some()
+    }
+
+    pub fn allowed(&self) -> &[String] {
+        &self.allowed
+    }
+
+    // Check a callback URL when the job is started, rather than when it finishes
+    pub fn check(&self, url: &str) -> Result<(), String> {
+        if !self.enabled() {
+            return Err("Webhooks are not enabled on this server".to_string());
+        }
+        if !self.allowed.iter().any(|prefix| url.starts_with(prefix.as_str())) {
+            return Err(format!(
+                "Callback URL {} is not under an allowed webhook prefix",
+                url
+            ));
+        }
+        Ok(())
+    }
+
+    // The SIGNATURE_HEADER value for a payload
+    pub fn sign(&self, body: &[u8]) -> Option<String> {
+        let secret = self.secret.as_ref()?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_slice()).expect("HMAC accepts any key length");
+        mac.update(body);
+        Some(format!("sha256={}", to_hex(&mac.finalize().into_bytes())))
+    }
+
+    // POST the event to the callback as signed JSON, retrying failed attempts. Blocks
+    // for the whole delivery, so it belongs on a blocking thread.
+    pub fn deliver(&self, url: &str, event: &JobEvent) {
+        let Ok(body) = serde_json::to_vec(event) else {
+            return;
+        };
+        let Some(signature) = self.sign(&body) else {
+            return;
+        };
+
+        let mut delay = FIRST_RETRY_DELAY;
+        for attempt in 1..=DELIVERY_ATTEMPTS {
+            match post(url, &signature, &body) {
+                Ok(()) => {
+                    info!("Notified {} that job {} finished", url, event.job_id);
+                    return;
+                }
+                Err(e) if attempt < DELIVERY_ATTEMPTS => {
+                    warn!(
+                        "Notifying {} of job {} failed, retrying: {}",
+                        url, event.job_id, e
+                    );
+                    std::thread::sleep(delay);
+                    delay *= 2;
+                }
+                Err(e) => warn!("Gave up notifying {} of job {}: {}", url, event.job_id, e),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "webhooks")]
+fn post(url: &str, signature: &str, body: &[u8]) -> Result<()> {
+    ureq::post(url)
+        .timeout(Duration::from_secs(10))
+        .set("Content-Type", "application/json")
+        .set(SIGNATURE_HEADER, signature)
+        .send_bytes(body)
+        .map(|_| ())
+        .map_err(|e| anyhow!("{}", e))
+}
+
+#[cfg(not(feature = "webhooks"))]
+fn post(_url: &str, _signature: &str, _body: &[u8]) -> Result<()> {
+    Err(anyhow!("Built without the webhooks feature"))
+}
+
+// Each prefix ends in a slash, so it never matches a longer host name
+fn prefixes(allowed: impl IntoIterator<Item = String>) -> Vec<String> {
+    allowed
+        .into_iter()
+        .map(|prefix| {
+            if prefix.ends_with('/') {
+                prefix
+            } else {
+                format!("{}/", prefix)
+            }
+        })
+        .collect()
+}
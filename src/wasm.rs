@@ -0,0 +1,100 @@
+use tfhe::{FheBool, FheUint8};
+use wasm_bindgen::prelude::*;
+
+use crate::client::{export_ciphertext, import_ciphertext, FheClient};
+use crate::crypto::canonical::{self, Canonical};
+
+// Browser bindings for the client module, so a web page can hold its own client key.
+// Ciphertexts travel in the bytes-plus-fingerprint form of ImportCiphertext and
+// ExportCiphertext, which the page sends over gRPC-Web; the key itself never leaves it.
+#[wasm_bindgen]
+pub struct WasmClient {
+    client: FheClient,
+}
+
+#[wasm_bindgen]
+impl WasmClient {
+    // A fresh client key under DEFAULT, FAST or SECURE
+    pub fn generate(parameter_set: &str) -> Result<WasmClient, JsError> {
+        Ok(Self {
+            client: FheClient::with_parameters(parameter_set).map_err(js_error)?,
+        })
+    }
+
+    // Restore a client saved with toBytes
+    #[wasm_bindgen(js_name = fromBytes)]
+    pub fn from_bytes(bytes: &[u8]) -> Result<WasmClient, JsError> {
+        Ok(Self {
+            client: FheClient::from_bytes(bytes).map_err(js_error)?,
+        })
+    }
+
+    // The serialized client key, for IndexedDB or similar. Anyone holding it can decrypt.
+    #[wasm_bindgen(js_name = toBytes)]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, JsError> {
+        Ok(self.client.to_bytes().map_err(js_error)?.to_vec())
+    }
+
+    // The serialized server key for this client key. Building it takes a while.
+    #[wasm_bindgen(js_name = serverKey)]
+    pub fn server_key(&self) -> Result<Vec<u8>, JsError> {
+        canonical::encode(&self.client.server_key()).map_err(js_error)
+    }
+
+    #[wasm_bindgen(js_name = encryptBoolean)]
+    pub fn encrypt_boolean(&self, value: bool) -> Result<ExportedCiphertext, JsError> {
+        let ciphertext = self.client.encrypt_boolean(value).map_err(js_error)?;
+        ExportedCiphertext::new(&ciphertext)
+    }
+
+    #[wasm_bindgen(js_name = encryptInteger)]
+    pub fn encrypt_integer(&self, value: u8) -> Result<ExportedCiphertext, JsError> {
+        let ciphertext = self.client.encrypt_integer(value).map_err(js_error)?;
+        ExportedCiphertext::new(&ciphertext)
+    }
+
+    // Decrypt the bytes and fingerprint of an ExportCiphertext response
+    #[wasm_bindgen(js_name = decryptBoolean)]
+    pub fn decrypt_boolean(&self, data: &[u8], fingerprint: &str) -> Result<bool, JsError> {
+        let ciphertext: FheBool = import_ciphertext(data, fingerprint).map_err(js_error)?;
+        Ok(self.client.decrypt_boolean(&ciphertext))
+    }
+
+    #[wasm_bindgen(js_name = decryptInteger)]
+    pub fn decrypt_integer(&self, data: &[u8], fingerprint: &str) -> Result<u8, JsError> {
+        let ciphertext: FheUint8 = import_ciphertext(data, fingerprint).map_err(js_error)?;
+        Ok(self.client.decrypt_integer(&ciphertext))
+    }
+}
+
+// A serialized ciphertext and its fingerprint, the serialized_data and fingerprint of an
+// ImportCiphertext request
+#[wasm_bindgen]
+pub struct ExportedCiphertext {
+    data: Vec<u8>,
+    fingerprint: String,
+}
+
+impl ExportedCiphertext {
+    fn new<T: Canonical>(ciphertext: &T) -> Result<Self, JsError> {
+        let (data, fingerprint) = export_ciphertext(ciphertext).map_err(js_error)?;
+        Ok(Self { data, fingerprint })
+    }
+}
+
+#[wasm_bindgen]
+impl ExportedCiphertext {
+    #[wasm_bindgen(getter)]
+    pub fn data(&self) -> Vec<u8> {
+        self.data.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn fingerprint(&self) -> String {
+        self.fingerprint.clone()
+    }
+}
+
+fn js_error(error: anyhow::Error) -> JsError {
+    JsError::new(&error.to_string())
+}
@@ -0,0 +1,134 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::Request;
+
+use hermetic_fhe::api::{FheService, MetricsRequest, ServerInfoRequest};
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::admission::{AdmissionConfig, AdmissionControl, FairShareConfig};
+use hermetic_fhe::service::FheServiceImpl;
+
+#[tokio::test]
+async fn test_admission_rejects_when_queue_is_full() {
+    let admission = Arc::new(AdmissionControl::new(AdmissionConfig { workers: 1, queue_depth: 1 }));
+    
+    // The only worker is taken
+    let running = admission.admit().await.unwrap();
+    
+    // The next caller waits in the queue
+    let waiter = {
+        let admission = admission.clone();
+        tokio::spawn(async move { admission.admit().await.is_ok() })
+    };
+    while admission.metrics().queued == 0 {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+    
+    // With the queue full, further callers are turned away immediately
+    assert!(admission.admit().await.is_err(), "Should reject when the queue is full");
+    let metrics = admission.metrics();
+    assert_eq!((metrics.running, metrics.queued, metrics.rejected), (1, 1, 1));
+    
+    // Finishing the running work lets the queued caller in
+    drop(running);
+    assert!(waiter.await.unwrap());
+    assert_eq!(admission.metrics().queued, 0);
+}
+
+#[tokio::test]
+async fn test_fair_share_lets_a_light_tenant_ahead() {
+    let admission = AdmissionControl::new(AdmissionConfig { workers: 1, queue_depth: 8 });
+    let admission = Arc::new(admission.with_fair_share(FairShareConfig::default()));
+    let running = admission.admit_as("heavy", "EvaluateCircuit").await.unwrap();
+    
+    // The heavy tenant queues more work before the light one asks for any
+    let (order, mut admitted) = tokio::sync::mpsc::unbounded_channel();
+    for (queued, tenant) in ["heavy", "heavy", "light"].into_iter().enumerate() {
+        let admission = admission.clone();
+        let order = order.clone();
+        tokio::spawn(async move {
+            let _permit = admission.admit_as(tenant, "EvaluateOperation").await.unwrap();
+            order.send(tenant).unwrap();
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        });
+        while admission.metrics().queued == queued {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+    assert_eq!(admission.metrics().queued, 3);
+    
+    // Having used the worker, the heavy tenant goes behind the light one
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    drop(running);
+    assert_eq!(admitted.recv().await, Some("light"));
+    assert_eq!(admitted.recv().await, Some("heavy"));
+    assert_eq!(admitted.recv().await, Some("heavy"));
+    
+    let tenants = admission.tenant_metrics();
+    assert_eq!(tenants.len(), 2);
+    assert_eq!((tenants[0].tenant.as_str(), tenants[0].admitted), ("heavy", 3));
+    assert_eq!((tenants[1].tenant.as_str(), tenants[1].admitted), ("light", 1));
+    assert!(tenants[0].compute >= Duration::from_millis(20));
+}
+
+#[tokio::test]
+async fn test_tenant_and_rpc_caps() {
+    let fair_share = FairShareConfig {
+        tenant_max_workers: Some(1),
+        tenant_queue_depth: Some(1),
+        rpc_max_workers: [("RunInference".to_string(), 1)].into(),
+        ..Default::default()
+    };
+    let admission = AdmissionControl::new(AdmissionConfig { workers: 3, queue_depth: 8 });
+    let admission = Arc::new(admission.with_fair_share(fair_share));
+    
+    // A tenant holding its one worker waits even with workers free, and can only queue one
+    let first = admission.admit_as("acme", "EvaluateOperation").await.unwrap();
+    let waiter = {
+        let admission = admission.clone();
+        tokio::spawn(async move { admission.admit_as("acme", "EvaluateOperation").await.is_ok() })
+    };
+    while admission.metrics().queued == 0 {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+    assert!(admission.admit_as("acme", "EvaluateOperation").await.is_err());
+    assert_eq!(admission.tenant_metrics()[0].rejected, 1);
+    
+    // Other tenants still get the free workers, except past an RPC's cap
+    let inference = admission.admit_as("globex", "RunInference").await.unwrap();
+    let capped = {
+        let admission = admission.clone();
+        tokio::spawn(async move { admission.admit_as("initech", "RunInference").await.is_ok() })
+    };
+    while admission.metrics().queued < 2 {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+    assert_eq!(admission.metrics().running, 2);
+    
+    drop(first);
+    assert!(waiter.await.unwrap());
+    drop(inference);
+    assert!(capped.await.unwrap());
+    assert_eq!(admission.metrics().queued, 0);
+}
+
+#[tokio::test]
+async fn test_get_metrics_reports_worker_pool() {
+    let key_store = Arc::new(KeyStore::new());
+    let ciphertext_store = Arc::new(CiphertextStore::new());
+    let admission = AdmissionControl::new(AdmissionConfig { workers: 2, queue_depth: 8 });
+    let service = FheServiceImpl::with_admission_control(key_store, ciphertext_store, admission);
+    
+    let metrics = service.get_metrics(Request::new(MetricsRequest {})).await.unwrap().into_inner();
+    let pool = metrics.worker_pool.unwrap();
+    assert_eq!((pool.workers, pool.running, pool.queued, pool.queue_depth), (2, 0, 0, 8));
+    assert_eq!(pool.rejected_total, 0);
+    
+    // Nothing has been stored yet
+    assert_eq!(metrics.key_store.unwrap().entries, 0);
+    assert_eq!(metrics.ciphertext_store.unwrap().entries, 0);
+    
+    // The same limits are advertised to clients
+    let info = service.get_server_info(Request::new(ServerInfoRequest {})).await.unwrap().into_inner();
+    let limits = info.limits.unwrap();
+    assert_eq!((limits.max_concurrent_evaluations, limits.max_queued_evaluations), (2, 8));
+}
@@ -0,0 +1,251 @@
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tonic::{Request, Status};
+
+use hermetic_fhe::api::{
+    contribution::Value, Contribution, CreateAggregationRequest, DecryptIntegerRequest,
+    DeleteAggregationRequest, EncryptIntegerRequest, ExportCiphertextRequest, FheService,
+    KeyGenerationRequest, ListAggregationWindowsRequest, ReadAggregationRequest,
+};
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::errors::ErrorReason;
+use hermetic_fhe::service::FheServiceImpl;
+
+async fn encrypt(service: &FheServiceImpl, client_key_id: &str, value: i64) -> String {
+    let request = Request::new(EncryptIntegerRequest {
+        client_key_id: client_key_id.to_string(),
+        value,
+        num_bits: 8,
+        ..Default::default()
+    });
+    service
+        .encrypt_integer(request)
+        .await
+        .unwrap()
+        .into_inner()
+        .encrypted_data_id
+}
+
+fn contribution(aggregation_id: &str, metric: &str, value: Value) -> Result<Contribution, Status> {
+    Ok(Contribution {
+        aggregation_id: aggregation_id.to_string(),
+        metric: metric.to_string(),
+        value: Some(value),
+        ..Default::default()
+    })
+}
+
+// Each metric's name, contribution count and decrypted sum, in the window starting at
+// window_start, or for all time when it is 0 and the aggregation has no windows
+async fn read_window(
+    service: &FheServiceImpl,
+    client_key_id: &str,
+    aggregation_id: &str,
+    window_start: u64,
+) -> Vec<(String, u64, i64)> {
+    let request = Request::new(ReadAggregationRequest {
+        aggregation_id: aggregation_id.to_string(),
+        window_start_unix_seconds: window_start,
+        ..Default::default()
+    });
+    let snapshot = service.read_aggregation(request).await.unwrap().into_inner();
+    let mut metrics = Vec::new();
+    for metric in snapshot.metrics {
+        let request = Request::new(DecryptIntegerRequest {
+            client_key_id: client_key_id.to_string(),
+            encrypted_data_id: metric.sum_id,
+            ..Default::default()
+        });
+        let sum = service.decrypt_integer(request).await.unwrap().into_inner().value;
+        metrics.push((metric.metric, metric.contributions, sum));
+    }
+    metrics
+}
+
+async fn read(
+    service: &FheServiceImpl,
+    client_key_id: &str,
+    aggregation_id: &str,
+) -> Vec<(String, u64, i64)> {
+    read_window(service, client_key_id, aggregation_id, 0).await
+}
+
+#[tokio::test]
+async fn test_contributions_are_summed_per_metric() {
+    let service = FheServiceImpl::new(Arc::new(KeyStore::new()), Arc::new(CiphertextStore::new()));
+    let keys = service
+        .generate_keys(Request::new(KeyGenerationRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    let request = Request::new(CreateAggregationRequest {
+        server_key_id: keys.server_key_id.clone(),
+    });
+    let aggregation_id = service
+        .create_aggregation(request)
+        .await
+        .unwrap()
+        .into_inner()
+        .aggregation_id;
+    
+    // One contributor refers to stored values, another uploads its own
+    let three = encrypt(&service, &keys.client_key_id, 3).await;
+    let four = encrypt(&service, &keys.client_key_id, 4).await;
+    let five = encrypt(&service, &keys.client_key_id, 5).await;
+    let request = Request::new(ExportCiphertextRequest {
+        encrypted_data_id: five,
+    });
+    let export = service.export_ciphertext(request).await.unwrap().into_inner();
+    
+    let stream = vec![
+        contribution(&aggregation_id, "latency", Value::ValueId(three)),
+        contribution(&aggregation_id, "errors", Value::ValueId(four)),
+    ];
+    let summary = service.contribute("", tokio_stream::iter(stream)).await.unwrap();
+    assert_eq!(summary.accepted, 2);
+    let mut uploaded = contribution(
+        &aggregation_id,
+        "latency",
+        Value::SerializedValue(export.serialized_data),
+    )
+    .unwrap();
+    uploaded.fingerprint = export.fingerprint;
+    let summary = service
+        .contribute("", tokio_stream::iter(vec![Ok(uploaded.clone())]))
+        .await
+        .unwrap();
+    assert_eq!(summary.accepted, 1);
+    
+    let metrics = read(&service, &keys.client_key_id, &aggregation_id).await;
+    assert_eq!(
+        metrics,
+        vec![("errors".to_string(), 1, 4), ("latency".to_string(), 2, 8)]
+    );
+    
+    // A bad contribution ends the stream, but the ones before it count
+    let mut corrupted = uploaded.clone();
+    corrupted.fingerprint = "0".repeat(64);
+    let status = service
+        .contribute("", tokio_stream::iter(vec![Ok(uploaded), Ok(corrupted)]))
+        .await
+        .unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::FingerprintMismatch));
+    let metrics = read(&service, &keys.client_key_id, &aggregation_id).await;
+    assert_eq!(metrics[1], ("latency".to_string(), 3, 13));
+    
+    let request = Request::new(DeleteAggregationRequest {
+        aggregation_id: aggregation_id.clone(),
+    });
+    let deleted = service.delete_aggregation(request).await.unwrap().into_inner();
+    assert_eq!(deleted.contributions, 4);
+    let request = Request::new(ReadAggregationRequest {
+        aggregation_id,
+        ..Default::default()
+    });
+    let status = service.read_aggregation(request).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::AggregationNotFound));
+}
+
+#[tokio::test]
+async fn test_windowed_aggregation() {
+    let service = FheServiceImpl::new(Arc::new(KeyStore::new()), Arc::new(CiphertextStore::new()));
+    let keys = service
+        .generate_keys(Request::new(KeyGenerationRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    let create = |window_seconds, slide_seconds| {
+        service.create_aggregation(Request::new(CreateAggregationRequest {
+            server_key_id: keys.server_key_id.clone(),
+            window_seconds,
+            slide_seconds,
+        }))
+    };
+    let status = create(2, 3).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::InvalidRequest));
+    let status = create(48, 1).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::LimitExceeded));
+    let tumbling = create(2, 0).await.unwrap().into_inner().aggregation_id;
+    let sliding = create(2, 1).await.unwrap().into_inner().aggregation_id;
+    
+    let three = encrypt(&service, &keys.client_key_id, 3).await;
+    let four = encrypt(&service, &keys.client_key_id, 4).await;
+    for aggregation_id in [&tumbling, &sliding] {
+        let stream = vec![
+            contribution(aggregation_id, "requests", Value::ValueId(three.clone())),
+            contribution(aggregation_id, "requests", Value::ValueId(four.clone())),
+        ];
+        service.contribute("", tokio_stream::iter(stream)).await.unwrap();
+    }
+    
+    // Wait out the windows the contributions fell in, so their sums are final
+    let request = Request::new(ListAggregationWindowsRequest {
+        aggregation_id: sliding.clone(),
+    });
+    let windows = service
+        .list_aggregation_windows(request)
+        .await
+        .unwrap()
+        .into_inner()
+        .windows;
+    let last_end = windows
+        .iter()
+        .map(|window| window.end_unix_seconds)
+        .max()
+        .unwrap();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    tokio::time::sleep(Duration::from_secs(last_end.saturating_sub(now) + 1)).await;
+    
+    // Each window sums what arrived during it; a sliding window's contributions also count
+    // in the window before or after
+    for (aggregation_id, overlap) in [(&tumbling, 1), (&sliding, 2)] {
+        let request = Request::new(ListAggregationWindowsRequest {
+            aggregation_id: aggregation_id.clone(),
+        });
+        let windows = service
+            .list_aggregation_windows(request)
+            .await
+            .unwrap()
+            .into_inner()
+            .windows;
+        assert!(windows.iter().all(|window| window.closed));
+        assert_eq!(
+            windows.iter().map(|window| window.contributions).sum::<u64>(),
+            2 * overlap
+        );
+        let mut total = 0;
+        for window in &windows {
+            let metrics = read_window(
+                &service,
+                &keys.client_key_id,
+                aggregation_id,
+                window.start_unix_seconds,
+            )
+            .await;
+            let (metric, contributions, sum) = &metrics[0];
+            assert_eq!(metric, "requests");
+            assert_eq!(*contributions, window.contributions);
+            total += sum;
+        }
+        assert_eq!(total, 7 * overlap as i64);
+    }
+    
+    // Without a window, the most recently closed one is read
+    let request = Request::new(ReadAggregationRequest {
+        aggregation_id: tumbling.clone(),
+        ..Default::default()
+    });
+    let snapshot = service.read_aggregation(request).await.unwrap().into_inner();
+    assert!(snapshot.closed);
+    assert_eq!(
+        snapshot.window_end_unix_seconds - snapshot.window_start_unix_seconds,
+        2
+    );
+    let request = Request::new(ReadAggregationRequest {
+        aggregation_id: tumbling,
+        window_start_unix_seconds: 1,
+        ..Default::default()
+    });
+    let status = service.read_aggregation(request).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::InvalidRequest));
+}
@@ -0,0 +1,283 @@
+use std::sync::Arc;
+use tonic::Request;
+
+use hermetic_fhe::api::{
+    DecryptIntegerRequest, DeleteKeyAliasRequest, EncryptIntegerRequest, EvaluationRequest, FheService,
+    KeyGenerationRequest, ListKeyAliasesRequest, OperationType, SetKeyAliasRequest,
+};
+use hermetic_fhe::crypto::alias::AliasError;
+use hermetic_fhe::crypto::envelope::MasterKey;
+use hermetic_fhe::crypto::key_directory::KeyDirectory;
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::errors::ErrorReason;
+use hermetic_fhe::service::usage::TENANT_HEADER;
+use hermetic_fhe::service::FheServiceImpl;
+
+fn setup_service() -> FheServiceImpl {
+    FheServiceImpl::new(Arc::new(KeyStore::new()), Arc::new(CiphertextStore::new()))
+}
+
+// A request from the tenant
+fn from<T>(tenant: &str, message: T) -> Request<T> {
+    let mut request = Request::new(message);
+    request
+        .metadata_mut()
+        .insert(TENANT_HEADER, tenant.parse().unwrap());
+    request
+}
+
+async fn generate_keys(
+    service: &FheServiceImpl,
+    tenant: &str,
+    alias: &str,
+) -> Result<(String, String), ErrorReason> {
+    let request = from(
+        tenant,
+        KeyGenerationRequest {
+            alias: alias.to_string(),
+            ..Default::default()
+        },
+    );
+    match service.generate_keys(request).await {
+        Ok(response) => {
+            let keys = response.into_inner();
+            Ok((keys.client_key_id, keys.server_key_id))
+        }
+        Err(status) => Err(ErrorReason::of(&status).unwrap()),
+    }
+}
+
+async fn set_alias(
+    service: &FheServiceImpl,
+    tenant: &str,
+    alias: &str,
+    key_id: &str,
+    replace: bool,
+) -> Result<String, ErrorReason> {
+    let request = from(
+        tenant,
+        SetKeyAliasRequest {
+            alias: alias.to_string(),
+            key_id: key_id.to_string(),
+            replace,
+        },
+    );
+    match service.set_key_alias(request).await {
+        Ok(response) => Ok(response.into_inner().server_key_id),
+        Err(status) => Err(ErrorReason::of(&status).unwrap()),
+    }
+}
+
+#[tokio::test]
+async fn test_aliases_stand_in_for_key_ids() {
+    let service = setup_service();
+    generate_keys(&service, "acme", "prod-analytics-key")
+        .await
+        .unwrap();
+    
+    // Both kinds of key field take the alias, each resolving to its own key of the pair
+    let mut ids = Vec::new();
+    for value in [20, 22] {
+        let request = from(
+            "acme",
+            EncryptIntegerRequest {
+                client_key_id: "prod-analytics-key".to_string(),
+                value,
+                num_bits: 8,
+                ..Default::default()
+            },
+        );
+        ids.push(
+            service
+                .encrypt_integer(request)
+                .await
+                .unwrap()
+                .into_inner()
+                .encrypted_data_id,
+        );
+    }
+    let request = from(
+        "acme",
+        EvaluationRequest {
+            server_key_id: "prod-analytics-key".to_string(),
+            operation: OperationType::Add as i32,
+            operand_ids: ids,
+            ..Default::default()
+        },
+    );
+    let result_id = service
+        .evaluate_operation(request)
+        .await
+        .unwrap()
+        .into_inner()
+        .result_id;
+    let request = from(
+        "acme",
+        DecryptIntegerRequest {
+            client_key_id: "prod-analytics-key".to_string(),
+            encrypted_data_id: result_id,
+            ..Default::default()
+        },
+    );
+    assert_eq!(
+        service.decrypt_integer(request).await.unwrap().into_inner().value,
+        42
+    );
+    
+    // Another tenant's requests don't see it
+    let request = from(
+        "globex",
+        EncryptIntegerRequest {
+            client_key_id: "prod-analytics-key".to_string(),
+            value: 1,
+            num_bits: 8,
+            ..Default::default()
+        },
+    );
+    let status = service.encrypt_integer(request).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::KeyNotFound));
+}
+
+#[tokio::test]
+async fn test_aliases_are_unique_per_tenant() {
+    let service = setup_service();
+    let (_, first_id) = generate_keys(&service, "acme", "analytics").await.unwrap();
+    
+    // A second pair can't take the alias, but another tenant can use the same one
+    assert_eq!(
+        generate_keys(&service, "acme", "analytics").await,
+        Err(ErrorReason::AliasTaken)
+    );
+    let (_, globex_id) = generate_keys(&service, "globex", "analytics").await.unwrap();
+    let (second_client_id, second_id) = generate_keys(&service, "acme", "").await.unwrap();
+    assert_eq!(
+        set_alias(&service, "acme", "analytics", &second_id, false).await,
+        Err(ErrorReason::AliasTaken)
+    );
+    
+    // Pointing it at the same pair again is harmless; moving it takes replace, and either
+    // key of the pair names it
+    assert_eq!(
+        set_alias(&service, "acme", "analytics", &first_id, false).await,
+        Ok(first_id.clone())
+    );
+    assert_eq!(
+        set_alias(&service, "acme", "analytics", &second_client_id, true).await,
+        Ok(second_id.clone())
+    );
+    assert_eq!(
+        set_alias(&service, "acme", "current", "analytics", false).await,
+        Ok(second_id.clone())
+    );
+    
+    let request = from("acme", ListKeyAliasesRequest {});
+    let aliases = service
+        .list_key_aliases(request)
+        .await
+        .unwrap()
+        .into_inner()
+        .aliases;
+    let names: Vec<(&str, &str)> = aliases
+        .iter()
+        .map(|alias| (alias.alias.as_str(), alias.server_key_id.as_str()))
+        .collect();
+    assert_eq!(
+        names,
+        vec![("analytics", second_id.as_str()), ("current", second_id.as_str())]
+    );
+    let request = from("globex", ListKeyAliasesRequest {});
+    let aliases = service
+        .list_key_aliases(request)
+        .await
+        .unwrap()
+        .into_inner()
+        .aliases;
+    assert_eq!(aliases.len(), 1);
+    assert_eq!(aliases[0].server_key_id, globex_id);
+    
+    let delete = |alias: &str| {
+        from(
+            "acme",
+            DeleteKeyAliasRequest {
+                alias: alias.to_string(),
+            },
+        )
+    };
+    assert!(
+        service
+            .delete_key_alias(delete("current"))
+            .await
+            .unwrap()
+            .into_inner()
+            .deleted
+    );
+    assert!(
+        !service
+            .delete_key_alias(delete("current"))
+            .await
+            .unwrap()
+            .into_inner()
+            .deleted
+    );
+}
+
+#[tokio::test]
+async fn test_invalid_aliases_are_refused() {
+    let service = setup_service();
+    let (_, server_key_id) = generate_keys(&service, "acme", "").await.unwrap();
+    
+    // An alias can't be mistaken for a key ID, or climb out of anything
+    let uuid = uuid::Uuid::new_v4().to_string();
+    let long = "x".repeat(65);
+    for alias in [
+        "",
+        "-leading-dash",
+        "has space",
+        "../up",
+        uuid.as_str(),
+        long.as_str(),
+    ] {
+        assert_eq!(
+            set_alias(&service, "acme", alias, &server_key_id, false).await,
+            Err(ErrorReason::InvalidRequest),
+            "{:?}",
+            alias
+        );
+    }
+    assert_eq!(
+        generate_keys(&service, "acme", "has space").await,
+        Err(ErrorReason::InvalidRequest)
+    );
+    assert_eq!(
+        set_alias(&service, "acme", "analytics", "no-such-key", false).await,
+        Err(ErrorReason::KeyNotFound)
+    );
+}
+
+#[test]
+fn test_aliases_persist_in_the_key_directory() {
+    let path = std::env::temp_dir().join(format!("hermetic-fhe-keys-{}", uuid::Uuid::new_v4()));
+    let source = KeyStore::with_master_key(MasterKey::from_bytes([5u8; 32]))
+        .with_key_directory(KeyDirectory::open(&path).unwrap());
+    let (client_key_id, server_key_id) = source.generate_keys("DEFAULT").unwrap();
+    let alias = source
+        .set_alias("acme", "analytics", &client_key_id, false)
+        .unwrap();
+    assert_eq!(alias.server_key_id, server_key_id);
+    assert!(matches!(
+        source.set_alias("acme", "analytics", "no-such-key", false),
+        Err(AliasError::KeyNotFound(_))
+    ));
+    
+    let restarted = KeyStore::with_master_key(MasterKey::from_bytes([5u8; 32]))
+        .with_key_directory(KeyDirectory::open(&path).unwrap());
+    assert_eq!(restarted.load_aliases().unwrap(), 1);
+    assert_eq!(restarted.alias("acme", "analytics"), Some(alias));
+    assert_eq!(restarted.alias("globex", "analytics"), None);
+    
+    // Deleting the pair takes its aliases with it
+    source.delete_key_pair(&server_key_id).unwrap();
+    assert_eq!(source.alias("acme", "analytics"), None);
+    assert_eq!(restarted.load_aliases().unwrap(), 0);
+    std::fs::remove_dir_all(&path).unwrap();
+}
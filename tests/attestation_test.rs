@@ -0,0 +1,106 @@
+use std::sync::Arc;
+use tonic::Request;
+
+use hermetic_fhe::api::{attestation_response, AttestationRequest, FheService, ServerInfoRequest};
+use hermetic_fhe::crypto::attestation::{report_data, verify_quote, AttestationError, TeePlatform};
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::attestation::{Attestor, QuoteProvider};
+use hermetic_fhe::service::errors::ErrorReason;
+use hermetic_fhe::service::FheServiceImpl;
+
+const CERTIFICATE: &[u8] = b"-----BEGIN CERTIFICATE-----\ntest\n-----END CERTIFICATE-----\n";
+
+// A quote laid out like the platform's, with the measurement and report data where a
+// real one carries them and zeros elsewhere
+fn quote(platform: TeePlatform, measurement: &[u8], data: &[u8; 64]) -> Vec<u8> {
+    let (measurement_at, data_at) = match platform {
+        TeePlatform::Sgx => (112, 368),
+        TeePlatform::SevSnp => (0x90, 0x50),
+        TeePlatform::Tdx => (184, 568),
+    };
+    let mut quote = vec![0u8; 1184];
+    quote[measurement_at..measurement_at + measurement.len()].copy_from_slice(measurement);
+    quote[data_at..data_at + 64].copy_from_slice(data);
+    quote
+}
+
+// Stand in for DCAP or snpguest, accepting every quote or none
+fn genuine(_: TeePlatform, _: &[u8]) -> Result<(), String> {
+    Ok(())
+}
+
+fn forged(_: TeePlatform, _: &[u8]) -> Result<(), String> {
+    Err("not signed by Intel or AMD".to_string())
+}
+
+// Quotes as a TDX guest running the build with this measurement would produce them
+struct FakeTdx {
+    measurement: Vec<u8>,
+}
+
+impl QuoteProvider for FakeTdx {
+    fn quote(&self, report_data: &[u8; 64]) -> anyhow::Result<Vec<u8>> {
+        Ok(quote(TeePlatform::Tdx, &self.measurement, report_data))
+    }
+}
+
+#[test]
+fn test_quotes_bind_the_certificate_nonce_and_build() {
+    for (platform, measurement) in [
+        (TeePlatform::Sgx, vec![1u8; 32]),
+        (TeePlatform::SevSnp, vec![2u8; 48]),
+        (TeePlatform::Tdx, vec![3u8; 48]),
+    ] {
+        let nonce = b"fresh nonce";
+        let quote = quote(platform, &measurement, &report_data(CERTIFICATE, nonce));
+        let fields = verify_quote(platform, &quote, CERTIFICATE, nonce, &measurement, &genuine).unwrap();
+        assert_eq!(fields.measurement, measurement);
+    
+        // Another certificate, a replayed nonce or another build is refused
+        let other = b"-----BEGIN CERTIFICATE-----\nother\n-----END CERTIFICATE-----\n";
+        let result = verify_quote(platform, &quote, other, nonce, &measurement, &genuine);
+        assert_eq!(result.unwrap_err(), AttestationError::NotBound);
+        let result = verify_quote(platform, &quote, CERTIFICATE, b"old nonce", &measurement, &genuine);
+        assert_eq!(result.unwrap_err(), AttestationError::NotBound);
+        let other_build = &[9u8; 48][..measurement.len()];
+        let result = verify_quote(platform, &quote, CERTIFICATE, nonce, other_build, &genuine);
+        assert!(matches!(result, Err(AttestationError::MeasurementMismatch { .. })));
+    
+        let result = verify_quote(platform, &quote[..100], CERTIFICATE, nonce, &measurement, &genuine);
+        assert!(matches!(result, Err(AttestationError::Malformed(_))));
+    
+        // A quote whose signature doesn't check out is refused, however well it binds
+        let result = verify_quote(platform, &quote, CERTIFICATE, nonce, &measurement, &forged);
+        assert!(matches!(result, Err(AttestationError::SignatureRejected(_))));
+    }
+}
+
+#[tokio::test]
+async fn test_get_attestation() {
+    let service = FheServiceImpl::new(Arc::new(KeyStore::new()), Arc::new(CiphertextStore::new()));
+    let request = Request::new(AttestationRequest { nonce: vec![7; 32] });
+    let status = service.get_attestation(request).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::Unsupported));
+    
+    let measurement = vec![5u8; 48];
+    let provider = FakeTdx {
+        measurement: measurement.clone(),
+    };
+    let attestor = Attestor::new(TeePlatform::Tdx, CERTIFICATE.to_vec(), Box::new(provider));
+    let service = service.with_attestor(attestor);
+    let info = service.get_server_info(Request::new(ServerInfoRequest {})).await.unwrap().into_inner();
+    assert!(info.features.unwrap().attestation);
+    
+    let nonce = vec![7; 32];
+    let request = Request::new(AttestationRequest { nonce: nonce.clone() });
+    let response = service.get_attestation(request).await.unwrap().into_inner();
+    assert_eq!(response.platform(), attestation_response::Platform::Tdx);
+    assert_eq!(response.certificate, CERTIFICATE);
+    assert_eq!(response.measurement, measurement);
+    verify_quote(TeePlatform::Tdx, &response.quote, CERTIFICATE, &nonce, &measurement, &genuine).unwrap();
+    
+    for nonce in [vec![], vec![0; 65]] {
+        let status = service.get_attestation(Request::new(AttestationRequest { nonce })).await.unwrap_err();
+        assert_eq!(ErrorReason::of(&status), Some(ErrorReason::InvalidRequest));
+    }
+}
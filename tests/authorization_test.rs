@@ -0,0 +1,106 @@
+use std::sync::Arc;
+use tonic::metadata::MetadataValue;
+use tonic::Request;
+
+use hermetic_fhe::api::{EncryptIntegerRequest, FheService, KeyGenerationRequest, ServerInfoRequest};
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::authorization::{AuthorizationInput, PolicyEngine, RulePolicy};
+use hermetic_fhe::service::errors::ErrorReason;
+use hermetic_fhe::service::FheServiceImpl;
+
+fn input(principal: &str, tenant: &str, method: &str, key_id: &str) -> AuthorizationInput {
+    AuthorizationInput {
+        principal: principal.to_string(),
+        tenant: tenant.to_string(),
+        method: method.to_string(),
+        key_id: key_id.to_string(),
+    }
+}
+
+fn rules(json: &str) -> RulePolicy {
+    serde_json::from_str(json).unwrap()
+}
+
+fn as_alice<T>(message: T) -> Request<T> {
+    let mut request = Request::new(message);
+    request.metadata_mut().insert("x-principal-id", MetadataValue::from_static("alice"));
+    request.metadata_mut().insert("x-tenant-id", MetadataValue::from_static("acme"));
+    request
+}
+
+// A policy engine that can't be reached
+struct Unreachable;
+
+impl PolicyEngine for Unreachable {
+    fn allows(&self, _input: &AuthorizationInput) -> anyhow::Result<bool> {
+        Err(anyhow::anyhow!("connection refused"))
+    }
+}
+
+#[test]
+fn test_rules_deny_unless_allowed_and_deny_wins() {
+    let policy = rules(
+        r#"{"rules": [
+            {"effect": "allow", "tenants": ["acme"]},
+            {"effect": "deny", "principals": ["intern"], "methods": ["Decrypt*"]},
+            {"effect": "allow", "principals": ["auditor"], "methods": ["GetServerInfo", "GetMetrics"]}
+        ]}"#,
+    );
+    assert!(policy.allows(&input("alice", "acme", "DecryptInteger", "k1")).unwrap());
+    assert!(policy.allows(&input("intern", "acme", "EvaluateOperation", "k1")).unwrap());
+    assert!(!policy.allows(&input("intern", "acme", "DecryptInteger", "k1")).unwrap());
+    assert!(policy.allows(&input("auditor", "", "GetMetrics", "")).unwrap());
+    assert!(!policy.allows(&input("auditor", "", "DecryptInteger", "k1")).unwrap());
+    assert!(!policy.allows(&input("alice", "globex", "EvaluateOperation", "k1")).unwrap());
+    
+    // Keys can be fenced off too
+    let policy = rules(r#"{"rules": [{"effect": "allow", "keys": ["team-a-*", ""]}]}"#);
+    assert!(policy.allows(&input("", "", "EncryptInteger", "team-a-1")).unwrap());
+    assert!(policy.allows(&input("", "", "GetServerInfo", "")).unwrap());
+    assert!(!policy.allows(&input("", "", "EncryptInteger", "team-b-1")).unwrap());
+}
+
+#[tokio::test]
+async fn test_calls_are_put_to_the_policy() {
+    let policy = rules(
+        r#"{"rules": [
+            {"effect": "allow", "methods": ["GetServerInfo"]},
+            {"effect": "allow", "principals": ["alice"], "tenants": ["acme"]}
+        ]}"#,
+    );
+    let service = FheServiceImpl::new(Arc::new(KeyStore::new()), Arc::new(CiphertextStore::new()))
+        .with_policy(Box::new(policy));
+    service.get_server_info(Request::new(ServerInfoRequest {})).await.unwrap();
+    
+    let status = service
+        .generate_keys(Request::new(KeyGenerationRequest::default()))
+        .await
+        .unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::PermissionDenied));
+    assert_eq!(status.code(), tonic::Code::PermissionDenied);
+    
+    // The principal and tenant come from the request's metadata
+    let keys = service
+        .generate_keys(as_alice(KeyGenerationRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    let request = EncryptIntegerRequest {
+        client_key_id: keys.client_key_id,
+        value: 5,
+        num_bits: 8,
+        ..Default::default()
+    };
+    service.encrypt_integer(as_alice(request.clone())).await.unwrap();
+    let status = service.encrypt_integer(Request::new(request)).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::PermissionDenied));
+}
+
+#[tokio::test]
+async fn test_an_unreachable_policy_refuses_calls() {
+    let service = FheServiceImpl::new(Arc::new(KeyStore::new()), Arc::new(CiphertextStore::new()))
+        .with_policy(Box::new(Unreachable));
+    let status = service.get_server_info(Request::new(ServerInfoRequest {})).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::PolicyUnavailable));
+    assert_eq!(status.code(), tonic::Code::Unavailable);
+}
@@ -0,0 +1,36 @@
+use std::sync::Arc;
+
+use hermetic_fhe::backend::{BackendError, FheBackend, Operation, TfheBackend};
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+
+#[test]
+fn test_tfhe_backend() {
+    let backend: Box<dyn FheBackend> = Box::new(TfheBackend::new(
+        Arc::new(KeyStore::new()),
+        Arc::new(CiphertextStore::new()),
+    ));
+    assert_eq!(backend.scheme(), "TFHE");
+    let (client_key_id, server_key_id) = backend.generate_keys("DEFAULT").unwrap();
+    
+    let a = backend.encrypt_integer(&client_key_id, 200).unwrap();
+    let b = backend.encrypt_integer(&client_key_id, 100).unwrap();
+    let sum = backend.evaluate(&server_key_id, Operation::Add, &[&a, &b]).unwrap();
+    assert_eq!(backend.decrypt_integer(&client_key_id, &sum).unwrap(), 44, "Addition should wrap at 8 bits");
+    
+    let t = backend.encrypt_boolean(&client_key_id, true).unwrap();
+    let negated = backend.evaluate(&server_key_id, Operation::Not, &[&t]).unwrap();
+    assert!(!backend.decrypt_boolean(&client_key_id, &negated).unwrap());
+    
+    // Failures come back as variants callers can map without knowing the scheme
+    let error = backend.decrypt_boolean(&client_key_id, &sum).unwrap_err();
+    assert!(matches!(error, BackendError::TypeMismatch { expected: "FheBool", found: "FheUint8" }));
+    let error = backend.encrypt_boolean("no-such-key", true).unwrap_err();
+    assert!(matches!(error, BackendError::ClientKeyNotFound));
+    let error = backend.evaluate("no-such-key", Operation::Not, &[&t]).unwrap_err();
+    assert!(matches!(error, BackendError::ServerKeyNotFound));
+    assert!(backend.evaluate(&server_key_id, Operation::Add, &[&a]).is_err(), "Wrong arity should fail");
+    
+    assert!(backend.remove(&sum));
+    let error = backend.decrypt_integer(&client_key_id, &sum).unwrap_err();
+    assert!(matches!(error, BackendError::CiphertextNotFound(_)));
+}
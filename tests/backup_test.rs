@@ -0,0 +1,240 @@
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+use tonic::{Request, Status};
+
+use hermetic_fhe::api::{
+    BackupChunk, CloseSessionRequest, CreateBackupRequest, CreateSessionRequest, DecryptBooleanRequest,
+    EncryptBooleanRequest, FheAdminService, FheService, KeyGenerationRequest, ListKeysRequest,
+    ListSessionsRequest, ListSubjectsRequest, ShredSubjectRequest,
+};
+use hermetic_fhe::api::v1::key_generation_request::Scheme;
+use hermetic_fhe::crypto::envelope::MasterKey;
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore, MAX_SUBJECT_ID_BYTES};
+use hermetic_fhe::service::admin::FheAdminServiceImpl;
+use hermetic_fhe::service::backup::BACKUP_ID_HEADER;
+use hermetic_fhe::service::errors::ErrorReason;
+use hermetic_fhe::service::FheServiceImpl;
+
+fn setup_services(master_key: u8) -> (FheServiceImpl, FheAdminServiceImpl) {
+    let key_store = Arc::new(KeyStore::with_master_key(MasterKey::from_bytes([master_key; 32])));
+    let ciphertext_store = Arc::new(CiphertextStore::new());
+    let service = FheServiceImpl::new(key_store, ciphertext_store);
+    let admin = FheAdminServiceImpl::new(service.clone());
+    (service, admin)
+}
+
+// The backup's ID and its archive chunks
+async fn take_backup(admin: &FheAdminServiceImpl, base_backup_id: &str) -> (String, Vec<Result<BackupChunk, Status>>) {
+    let request = Request::new(CreateBackupRequest {
+        base_backup_id: base_backup_id.to_string(),
+    });
+    let response = admin.create_backup(request).await.unwrap();
+    let backup_id = response.metadata().get(BACKUP_ID_HEADER).unwrap().to_str().unwrap().to_string();
+    let chunks = response.into_inner().collect().await;
+    (backup_id, chunks)
+}
+
+async fn encrypt(service: &FheServiceImpl, client_key_id: &str, value: bool, session_id: &str) -> String {
+    let request = Request::new(EncryptBooleanRequest {
+        client_key_id: client_key_id.to_string(),
+        value,
+        session_id: session_id.to_string(),
+        ..Default::default()
+    });
+    service.encrypt_boolean(request).await.unwrap().into_inner().encrypted_data_id
+}
+
+async fn decrypt(service: &FheServiceImpl, client_key_id: &str, encrypted_data_id: &str) -> Result<bool, Status> {
+    let request = Request::new(DecryptBooleanRequest {
+        client_key_id: client_key_id.to_string(),
+        encrypted_data_id: encrypted_data_id.to_string(),
+        serialized_data: vec![],
+    });
+    service.decrypt_boolean(request).await.map(|response| response.into_inner().value)
+}
+
+async fn create_session(service: &FheServiceImpl) -> String {
+    let request = Request::new(CreateSessionRequest { idle_timeout_seconds: 600 });
+    service.create_session(request).await.unwrap().into_inner().session_id
+}
+
+#[tokio::test]
+async fn test_full_backup_restores_on_another_server() {
+    let (source, source_admin) = setup_services(5);
+    let (target, target_admin) = setup_services(5);
+    
+    let keys = source
+        .generate_keys(Request::new(KeyGenerationRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    let ckks_request = Request::new(KeyGenerationRequest {
+        scheme: Scheme::Ckks as i32,
+        ..Default::default()
+    });
+    source.generate_keys(ckks_request).await.unwrap();
+    
+    let loose_id = encrypt(&source, &keys.client_key_id, true, "").await;
+    let session_id = create_session(&source).await;
+    let session_ciphertext_id = encrypt(&source, &keys.client_key_id, false, &session_id).await;
+    
+    let (backup_id, chunks) = take_backup(&source_admin, "").await;
+    let restored = target_admin.restore(tokio_stream::iter(chunks)).await.unwrap();
+    assert_eq!(restored.backup_id, backup_id);
+    assert_eq!((restored.key_pairs, restored.ciphertexts, restored.sessions), (2, 2, 1));
+    
+    // Same IDs, same keys, same plaintexts
+    assert!(decrypt(&target, &keys.client_key_id, &loose_id).await.unwrap());
+    assert!(!decrypt(&target, &keys.client_key_id, &session_ciphertext_id).await.unwrap());
+    
+    let source_keys = source_admin.list_keys(Request::new(ListKeysRequest {})).await.unwrap().into_inner();
+    let target_keys = target_admin.list_keys(Request::new(ListKeysRequest {})).await.unwrap().into_inner();
+    assert_eq!(source_keys, target_keys);
+    
+    let sessions = target_admin
+        .list_sessions(Request::new(ListSessionsRequest {}))
+        .await
+        .unwrap()
+        .into_inner()
+        .sessions;
+    assert_eq!(sessions.len(), 1);
+    assert_eq!((sessions[0].session_id.as_str(), sessions[0].ciphertexts), (session_id.as_str(), 1));
+}
+
+#[tokio::test]
+async fn test_incremental_backup_carries_changes_and_deletions() {
+    let (source, source_admin) = setup_services(6);
+    let (target, target_admin) = setup_services(6);
+    
+    let keys = source
+        .generate_keys(Request::new(KeyGenerationRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    let kept_id = encrypt(&source, &keys.client_key_id, true, "").await;
+    let session_id = create_session(&source).await;
+    let freed_id = encrypt(&source, &keys.client_key_id, true, &session_id).await;
+    
+    let (full_id, chunks) = take_backup(&source_admin, "").await;
+    target_admin.restore(tokio_stream::iter(chunks)).await.unwrap();
+    
+    // Free one ciphertext and add another, then back up only the difference
+    let request = Request::new(CloseSessionRequest {
+        session_id: session_id.clone(),
+    });
+    source.close_session(request).await.unwrap();
+    let added_id = encrypt(&source, &keys.client_key_id, false, "").await;
+    
+    let (_, chunks) = take_backup(&source_admin, &full_id).await;
+    let restored = target_admin.restore(tokio_stream::iter(chunks)).await.unwrap();
+    assert_eq!(restored.key_pairs, 0, "The unchanged key pair should not be sent again");
+    assert_eq!(restored.ciphertexts, 1, "Only the new ciphertext should be sent");
+    assert_eq!(restored.deleted, 2, "The freed ciphertext and its session should be deleted");
+    
+    assert!(decrypt(&target, &keys.client_key_id, &kept_id).await.unwrap());
+    assert!(!decrypt(&target, &keys.client_key_id, &added_id).await.unwrap());
+    assert!(decrypt(&target, &keys.client_key_id, &freed_id).await.is_err());
+}
+
+#[tokio::test]
+async fn test_shredded_subject_cannot_be_restored_from_backup() {
+    let (service, admin) = setup_services(9);
+    
+    let keys = service
+        .generate_keys(Request::new(KeyGenerationRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    let request = Request::new(EncryptBooleanRequest {
+        client_key_id: keys.client_key_id.clone(),
+        value: true,
+        subject_id: "alice".to_string(),
+        ..Default::default()
+    });
+    let alice_id = service.encrypt_boolean(request).await.unwrap().into_inner().encrypted_data_id;
+    let loose_id = encrypt(&service, &keys.client_key_id, true, "").await;
+    
+    let subjects = admin.list_subjects(Request::new(ListSubjectsRequest {})).await.unwrap().into_inner().subjects;
+    assert_eq!(subjects.len(), 1);
+    assert_eq!((subjects[0].subject_id.as_str(), subjects[0].ciphertexts), ("alice", 1));
+    
+    let (_, chunks) = take_backup(&admin, "").await;
+    
+    let request = Request::new(ShredSubjectRequest {
+        subject_id: "alice".to_string(),
+    });
+    let shredded = admin.shred_subject(request).await.unwrap().into_inner();
+    assert!(shredded.key_destroyed);
+    assert_eq!(shredded.freed_ciphertexts, 1);
+    assert!(decrypt(&service, &keys.client_key_id, &alice_id).await.is_err());
+    
+    // The backup still holds the subject's ciphertext, but sealed under the destroyed key
+    let restored = admin.restore(tokio_stream::iter(chunks)).await.unwrap();
+    assert_eq!((restored.ciphertexts, restored.shredded), (1, 1));
+    assert!(decrypt(&service, &keys.client_key_id, &alice_id).await.is_err());
+    assert!(decrypt(&service, &keys.client_key_id, &loose_id).await.unwrap());
+    
+    let request = Request::new(ShredSubjectRequest {
+        subject_id: "alice".to_string(),
+    });
+    assert!(!admin.shred_subject(request).await.unwrap().into_inner().key_destroyed);
+}
+
+#[tokio::test]
+async fn test_subject_ids_too_long_for_a_key_file_are_refused() {
+    let (service, admin) = setup_services(10);
+    let keys = service
+        .generate_keys(Request::new(KeyGenerationRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    let tag = |subject_id: String| {
+        Request::new(EncryptBooleanRequest {
+            client_key_id: keys.client_key_id.clone(),
+            value: true,
+            subject_id,
+            ..Default::default()
+        })
+    };
+    
+    // Refused before anything is stored, so one client can't break every later backup
+    let status = service.encrypt_boolean(tag("x".repeat(MAX_SUBJECT_ID_BYTES + 1))).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::InvalidRequest));
+    service.encrypt_boolean(tag("x".repeat(MAX_SUBJECT_ID_BYTES))).await.unwrap();
+    
+    let subjects = admin.list_subjects(Request::new(ListSubjectsRequest {})).await.unwrap().into_inner().subjects;
+    assert_eq!(subjects.len(), 1);
+    let (_, chunks) = take_backup(&admin, "").await;
+    assert!(chunks.iter().all(|chunk| chunk.is_ok()));
+}
+
+#[tokio::test]
+async fn test_restore_rejects_foreign_incomplete_and_unanchored_archives() {
+    let (_, source_admin) = setup_services(7);
+    let (_, target_admin) = setup_services(7);
+    let (_, stranger_admin) = setup_services(8);
+    
+    let (full_id, chunks) = take_backup(&source_admin, "").await;
+    
+    // Signed under another master key
+    let status = stranger_admin.restore(tokio_stream::iter(chunks.clone())).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::FingerprintMismatch));
+    
+    // Cut off before the footer
+    let mut truncated: Vec<u8> = chunks.iter().flat_map(|chunk| chunk.as_ref().unwrap().data.clone()).collect();
+    truncated.truncate(truncated.len() - 1);
+    let truncated = vec![Ok(BackupChunk { data: truncated })];
+    let status = target_admin.restore(tokio_stream::iter(truncated)).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::InvalidRequest));
+    
+    // An incremental backup needs its base restored first
+    let (_, incremental) = take_backup(&source_admin, &full_id).await;
+    let status = target_admin.restore(tokio_stream::iter(incremental)).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::BackupNotFound));
+    
+    let request = Request::new(CreateBackupRequest {
+        base_backup_id: "no-such-backup".to_string(),
+    });
+    let status = source_admin.create_backup(request).await.err().unwrap();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::BackupNotFound));
+}
@@ -0,0 +1,108 @@
+use std::sync::Arc;
+use tonic::Request;
+
+use hermetic_fhe::api::{
+    CastBallotRequest, CloseElectionRequest, CreateElectionRequest, DecryptIntegerRequest,
+    EncryptIntegerRequest, FheService, GetTallyRequest, KeyGenerationRequest,
+};
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::errors::ErrorReason;
+use hermetic_fhe::service::FheServiceImpl;
+
+async fn setup_service() -> FheServiceImpl {
+    let key_store = Arc::new(KeyStore::new());
+    let ciphertext_store = Arc::new(CiphertextStore::new());
+    FheServiceImpl::new(key_store, ciphertext_store)
+}
+
+async fn encrypt(service: &FheServiceImpl, client_key_id: &str, value: i64) -> String {
+    let encrypt_request = Request::new(EncryptIntegerRequest {
+        client_key_id: client_key_id.to_string(),
+        value,
+        num_bits: 8,
+        ..Default::default()
+    });
+    
+    service.encrypt_integer(encrypt_request).await.unwrap().into_inner().encrypted_data_id
+}
+
+async fn cast(
+    service: &FheServiceImpl,
+    election_id: &str,
+    choice_ids: Vec<String>,
+) -> Result<u64, tonic::Status> {
+    let request = Request::new(CastBallotRequest {
+        election_id: election_id.to_string(),
+        choice_ids,
+    });
+    Ok(service.cast_ballot(request).await?.into_inner().ballots)
+}
+
+#[tokio::test]
+async fn test_election_tally() {
+    let service = setup_service().await;
+    
+    let key_gen_request = Request::new(KeyGenerationRequest {
+        parameter_set: 0, // DEFAULT
+        ..Default::default()
+    });
+    let keys = service.generate_keys(key_gen_request).await.unwrap().into_inner();
+    let (zero, one) = (
+        encrypt(&service, &keys.client_key_id, 0).await,
+        encrypt(&service, &keys.client_key_id, 1).await,
+    );
+    
+    let request = Request::new(CreateElectionRequest {
+        server_key_id: keys.server_key_id,
+        num_options: 2,
+    });
+    let election = service.create_election(request).await.unwrap().into_inner();
+    assert_eq!((election.num_options, election.ballots, election.closed), (2, 0, false));
+    let election_id = election.election_id;
+    
+    cast(&service, &election_id, vec![one.clone(), zero.clone()]).await.unwrap();
+    cast(&service, &election_id, vec![zero.clone(), one.clone()]).await.unwrap();
+    cast(&service, &election_id, vec![one.clone(), zero.clone()]).await.unwrap();
+    // Voting for both options is accepted but counts for neither
+    let ballots = cast(&service, &election_id, vec![one.clone(), one.clone()]).await.unwrap();
+    assert_eq!(ballots, 4);
+    
+    let status = cast(&service, &election_id, vec![one.clone()]).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::ShapeMismatch));
+    
+    // Totals stay sealed while the election is open
+    let request = Request::new(GetTallyRequest {
+        election_id: election_id.clone(),
+        ..Default::default()
+    });
+    let status = service.get_tally(request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::ElectionOpen));
+    
+    let request = Request::new(CloseElectionRequest {
+        election_id: election_id.clone(),
+    });
+    assert!(service.close_election(request).await.unwrap().into_inner().closed);
+    
+    let status = cast(&service, &election_id, vec![zero, one]).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::ElectionClosed));
+    
+    let request = Request::new(GetTallyRequest {
+        election_id,
+        ..Default::default()
+    });
+    let tally = service.get_tally(request).await.unwrap().into_inner();
+    assert_eq!(tally.ballots, 4);
+    
+    let mut totals = Vec::new();
+    for tally_id in tally.tally_ids {
+        let decrypt_request = Request::new(DecryptIntegerRequest {
+            client_key_id: keys.client_key_id.clone(),
+            encrypted_data_id: tally_id,
+            serialized_data: vec![],
+            ..Default::default()
+        });
+        totals.push(service.decrypt_integer(decrypt_request).await.unwrap().into_inner().value);
+    }
+    assert_eq!(totals, vec![2, 1]);
+}
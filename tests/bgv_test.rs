@@ -0,0 +1,169 @@
+use std::sync::Arc;
+use tonic::Request;
+
+use hermetic_fhe::api::v1::key_generation_request::Scheme;
+use hermetic_fhe::api::{
+    DecryptIntegerBatchRequest, EncryptIntegerBatchRequest, EncryptRealVectorRequest, FheService,
+    IntegerBatchEvaluationRequest, IntegerBatchOperation, KeyGenerationRequest,
+};
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::errors::ErrorReason;
+use hermetic_fhe::service::FheServiceImpl;
+
+async fn setup_service() -> FheServiceImpl {
+    let key_store = Arc::new(KeyStore::new());
+    let ciphertext_store = Arc::new(CiphertextStore::new());
+    FheServiceImpl::new(key_store, ciphertext_store)
+}
+
+// Generate keys for a scheme, returning (client_key_id, server_key_id)
+async fn generate_keys(service: &FheServiceImpl, scheme: Scheme) -> (String, String) {
+    let key_gen_request = Request::new(KeyGenerationRequest {
+        scheme: scheme as i32,
+        ..Default::default()
+    });
+    
+    let keys = service.generate_keys(key_gen_request).await.unwrap().into_inner();
+    (keys.client_key_id, keys.server_key_id)
+}
+
+async fn encrypt(service: &FheServiceImpl, client_key_id: &str, values: Vec<i64>) -> Result<String, tonic::Status> {
+    let request = Request::new(EncryptIntegerBatchRequest {
+        client_key_id: client_key_id.to_string(),
+        values,
+        ..Default::default()
+    });
+    
+    Ok(service.encrypt_integer_batch(request).await?.into_inner().encrypted_data_id)
+}
+
+async fn decrypt(service: &FheServiceImpl, client_key_id: &str, encrypted_data_id: &str) -> Vec<i64> {
+    let request = Request::new(DecryptIntegerBatchRequest {
+        client_key_id: client_key_id.to_string(),
+        encrypted_data_id: encrypted_data_id.to_string(),
+    });
+    
+    service.decrypt_integer_batch(request).await.unwrap().into_inner().values
+}
+
+async fn evaluate(
+    service: &FheServiceImpl,
+    server_key_id: &str,
+    operation: IntegerBatchOperation,
+    operand_ids: &[&str],
+    rotation: i64,
+) -> Result<String, tonic::Status> {
+    let request = Request::new(IntegerBatchEvaluationRequest {
+        server_key_id: server_key_id.to_string(),
+        operation: operation as i32,
+        operand_ids: operand_ids.iter().map(|id| id.to_string()).collect(),
+        rotation,
+        ..Default::default()
+    });
+    
+    Ok(service.evaluate_integer_batch(request).await?.into_inner().result_id)
+}
+
+#[tokio::test]
+async fn test_integer_batch_round_trip_and_add() {
+    let service = setup_service().await;
+    let (client_key_id, server_key_id) = generate_keys(&service, Scheme::Bgv).await;
+    
+    let x = encrypt(&service, &client_key_id, vec![1, -2, 300, 32768]).await.unwrap();
+    assert_eq!(decrypt(&service, &client_key_id, &x).await, vec![1, -2, 300, 32768]);
+    
+    // A shorter operand is padded with zeros, and sums wrap modulo 65537
+    let y = encrypt(&service, &client_key_id, vec![5, 6, -7, 2]).await.unwrap();
+    let sum = evaluate(&service, &server_key_id, IntegerBatchOperation::BatchAdd, &[&x, &y], 0).await.unwrap();
+    assert_eq!(decrypt(&service, &client_key_id, &sum).await, vec![6, 4, 293, -32767]);
+}
+
+#[tokio::test]
+async fn test_integer_batch_multiply_until_depth_runs_out() {
+    let service = setup_service().await;
+    let (client_key_id, server_key_id) = generate_keys(&service, Scheme::Bgv).await;
+    
+    let x = encrypt(&service, &client_key_id, vec![3, -4, 100]).await.unwrap();
+    let y = encrypt(&service, &client_key_id, vec![5, 6, -7]).await.unwrap();
+    let product = evaluate(&service, &server_key_id, IntegerBatchOperation::BatchMultiply, &[&x, &y], 0)
+        .await
+        .unwrap();
+    assert_eq!(decrypt(&service, &client_key_id, &product).await, vec![15, -24, -700]);
+    
+    // A fresh operand meets the product at its lower level; -70000 wraps to -4463
+    let cubed = evaluate(&service, &server_key_id, IntegerBatchOperation::BatchMultiply, &[&product, &x], 0)
+        .await
+        .unwrap();
+    assert_eq!(decrypt(&service, &client_key_id, &cubed).await, vec![45, 96, -4463]);
+    
+    // Addition still works at the bottom level, but another multiplication does not
+    let sum = evaluate(&service, &server_key_id, IntegerBatchOperation::BatchAdd, &[&cubed, &y], 0).await.unwrap();
+    assert_eq!(decrypt(&service, &client_key_id, &sum).await, vec![50, 102, -4470]);
+    let status = evaluate(&service, &server_key_id, IntegerBatchOperation::BatchMultiply, &[&cubed, &x], 0)
+        .await
+        .unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::LimitExceeded));
+}
+
+#[tokio::test]
+async fn test_integer_batch_rotate_and_sum_slots() {
+    let service = setup_service().await;
+    let (client_key_id, server_key_id) = generate_keys(&service, Scheme::Bgv).await;
+    
+    let x = encrypt(&service, &client_key_id, vec![1, 2, 3, 4]).await.unwrap();
+    let left = evaluate(&service, &server_key_id, IntegerBatchOperation::BatchRotate, &[&x], 1).await.unwrap();
+    assert_eq!(decrypt(&service, &client_key_id, &left).await, vec![2, 3, 4, 0]);
+    let right = evaluate(&service, &server_key_id, IntegerBatchOperation::BatchRotate, &[&x], -2).await.unwrap();
+    assert_eq!(decrypt(&service, &client_key_id, &right).await, vec![0, 0, 1, 2]);
+    
+    // Summing every slot into the first takes log2(slots) rotations and additions
+    let values: Vec<i64> = (0..4096).map(|i| i % 16).collect();
+    let mut total = encrypt(&service, &client_key_id, values.clone()).await.unwrap();
+    let mut steps = 1;
+    while steps < 4096 {
+        let rotated = evaluate(&service, &server_key_id, IntegerBatchOperation::BatchRotate, &[&total], steps)
+            .await
+            .unwrap();
+        total = evaluate(&service, &server_key_id, IntegerBatchOperation::BatchAdd, &[&total, &rotated], 0)
+            .await
+            .unwrap();
+        steps *= 2;
+    }
+    let sums = decrypt(&service, &client_key_id, &total).await;
+    assert_eq!(sums[0], values.iter().sum::<i64>());
+    assert!(sums.iter().all(|sum| *sum == sums[0]));
+}
+
+#[tokio::test]
+async fn test_integer_batch_rejections() {
+    let service = setup_service().await;
+    let (client_key_id, server_key_id) = generate_keys(&service, Scheme::Bgv).await;
+    let (ckks_client_key_id, _) = generate_keys(&service, Scheme::Ckks).await;
+    
+    let status = encrypt(&service, &client_key_id, vec![0; 4097]).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::LimitExceeded));
+    let status = encrypt(&service, &client_key_id, vec![32769]).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::ValueOutOfRange));
+    
+    // Keys and ciphertexts of the other schemes are not interchangeable
+    let status = encrypt(&service, &ckks_client_key_id, vec![1]).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::KeyNotFound));
+    let real_vector_id = service
+        .encrypt_real_vector(Request::new(EncryptRealVectorRequest {
+            client_key_id: ckks_client_key_id,
+            values: vec![1.0],
+            ..Default::default()
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .encrypted_data_id;
+    let status = evaluate(&service, &server_key_id, IntegerBatchOperation::BatchRotate, &[&real_vector_id], 1)
+        .await
+        .unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::TypeMismatch));
+    
+    let x = encrypt(&service, &client_key_id, vec![1]).await.unwrap();
+    let status = evaluate(&service, &server_key_id, IntegerBatchOperation::BatchAdd, &[&x], 0).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::ArityMismatch));
+}
@@ -0,0 +1,194 @@
+use std::sync::Arc;
+use tonic::Request;
+
+use hermetic_fhe::api::{
+    BloomFilterMerge, CreateBloomFilterRequest, DecryptBooleanRequest, DeleteBloomFilterRequest,
+    EncryptBooleanRequest, FheService, KeyGenerationRequest, MergeBloomFiltersRequest,
+    QueryBloomFilterRequest,
+};
+use hermetic_fhe::crypto::bloom;
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::errors::ErrorReason;
+use hermetic_fhe::service::FheServiceImpl;
+
+const NUM_BITS: usize = 32;
+const NUM_HASHES: usize = 2;
+
+struct Setup {
+    service: FheServiceImpl,
+    client_key_id: String,
+    server_key_id: String,
+}
+
+async fn setup_service() -> Setup {
+    let service = FheServiceImpl::new(Arc::new(KeyStore::new()), Arc::new(CiphertextStore::new()));
+    let keys = service
+        .generate_keys(Request::new(KeyGenerationRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    Setup {
+        service,
+        client_key_id: keys.client_key_id,
+        server_key_id: keys.server_key_id,
+    }
+}
+
+// Encrypts the bits a client would set for the elements and makes a filter of them
+async fn create_filter(setup: &Setup, bits: &[bool]) -> String {
+    let mut bit_ids = Vec::new();
+    for bit in bits {
+        let request = Request::new(EncryptBooleanRequest {
+            client_key_id: setup.client_key_id.clone(),
+            value: *bit,
+            ..Default::default()
+        });
+        bit_ids.push(
+            setup
+                .service
+                .encrypt_boolean(request)
+                .await
+                .unwrap()
+                .into_inner()
+                .encrypted_data_id,
+        );
+    }
+    let request = Request::new(CreateBloomFilterRequest {
+        server_key_id: setup.server_key_id.clone(),
+        bit_ids,
+        num_hashes: NUM_HASHES as u32,
+    });
+    let response = setup
+        .service
+        .create_bloom_filter(request)
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(response.num_bits, bits.len() as u32);
+    response.filter_id
+}
+
+async fn query(setup: &Setup, filter_id: &str, elements: &[&str]) -> Vec<bool> {
+    let request = Request::new(QueryBloomFilterRequest {
+        filter_id: filter_id.to_string(),
+        elements: elements
+            .iter()
+            .map(|element| element.as_bytes().to_vec())
+            .collect(),
+        ..Default::default()
+    });
+    let response = setup
+        .service
+        .query_bloom_filter(request)
+        .await
+        .unwrap()
+        .into_inner();
+    let mut results = Vec::new();
+    for id in response.result_ids {
+        let request = Request::new(DecryptBooleanRequest {
+            client_key_id: setup.client_key_id.clone(),
+            encrypted_data_id: id,
+            ..Default::default()
+        });
+        results.push(
+            setup
+                .service
+                .decrypt_boolean(request)
+                .await
+                .unwrap()
+                .into_inner()
+                .value,
+        );
+    }
+    results
+}
+
+// What a plaintext filter with these bits answers
+fn lookup(bits: &[bool], element: &str) -> bool {
+    bloom::positions(element.as_bytes(), bits.len(), NUM_HASHES)
+        .into_iter()
+        .all(|position| bits[position])
+}
+
+#[tokio::test]
+async fn test_bloom_filter_membership_and_merging() {
+    let setup = setup_service().await;
+    let first_bits = bloom::filter_bits(&["alice", "bob"], NUM_BITS, NUM_HASHES);
+    let second_bits = bloom::filter_bits(&["bob", "carol"], NUM_BITS, NUM_HASHES);
+    let first = create_filter(&setup, &first_bits).await;
+    let second = create_filter(&setup, &second_bits).await;
+    
+    // Some element the first filter can rule out
+    let outsider = (0..)
+        .map(|i| format!("outsider-{}", i))
+        .find(|element| !lookup(&first_bits, element))
+        .unwrap();
+    let results = query(&setup, &first, &["alice", "bob", &outsider]).await;
+    assert_eq!(results, vec![true, true, false]);
+    
+    // Merged filters answer as the bitwise OR or AND of their bits would
+    let elements = ["alice", "bob", "carol", outsider.as_str()];
+    for operation in [BloomFilterMerge::Union, BloomFilterMerge::Intersection] {
+        let request = Request::new(MergeBloomFiltersRequest {
+            filter_ids: vec![first.clone(), second.clone()],
+            operation: operation as i32,
+        });
+        let merged = setup
+            .service
+            .merge_bloom_filters(request)
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(merged.num_bits, NUM_BITS as u32);
+        assert_eq!(merged.num_hashes, NUM_HASHES as u32);
+        let bits: Vec<bool> = first_bits
+            .iter()
+            .zip(&second_bits)
+            .map(|(a, b)| match operation {
+                BloomFilterMerge::Union => *a || *b,
+                BloomFilterMerge::Intersection => *a && *b,
+            })
+            .collect();
+        let expected: Vec<bool> = elements.iter().map(|element| lookup(&bits, element)).collect();
+        assert_eq!(query(&setup, &merged.filter_id, &elements).await, expected);
+    }
+    
+    // The filters merged are kept
+    let results = query(&setup, &first, &["alice"]).await;
+    assert_eq!(results, vec![true]);
+}
+
+#[tokio::test]
+async fn test_bloom_filter_errors() {
+    let setup = setup_service().await;
+    let large = create_filter(&setup, &bloom::filter_bits(&["alice"], 4, NUM_HASHES)).await;
+    let small = create_filter(&setup, &bloom::filter_bits(&["alice"], 2, NUM_HASHES)).await;
+    
+    let request = Request::new(MergeBloomFiltersRequest {
+        filter_ids: vec![large.clone(), small.clone()],
+        operation: BloomFilterMerge::Union as i32,
+    });
+    let status = setup.service.merge_bloom_filters(request).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::ShapeMismatch));
+    
+    let request = Request::new(CreateBloomFilterRequest {
+        server_key_id: setup.server_key_id.clone(),
+        bit_ids: vec!["missing".to_string()],
+        num_hashes: 0,
+    });
+    let status = setup.service.create_bloom_filter(request).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::InvalidRequest));
+    
+    // A deleted filter can't be queried
+    let request = Request::new(DeleteBloomFilterRequest {
+        filter_id: small.clone(),
+    });
+    setup.service.delete_bloom_filter(request).await.unwrap();
+    let request = Request::new(QueryBloomFilterRequest {
+        filter_id: small,
+        elements: vec![b"alice".to_vec()],
+        ..Default::default()
+    });
+    let status = setup.service.query_bloom_filter(request).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::BloomFilterNotFound));
+}
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use tonic::Request;
+
+use hermetic_fhe::api::{
+    DecryptBooleanRequest, DecryptIntegerRequest, EncryptBooleanRequest, EncryptIntegerRequest, FheService,
+    KeyGenerationRequest,
+};
+use hermetic_fhe::circuit::IssueKind;
+use hermetic_fhe::client::circuit::{CircuitBuildError, CircuitBuilder};
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::FheServiceImpl;
+
+#[tokio::test]
+async fn test_built_circuit_evaluates_on_the_service() {
+    let service = FheServiceImpl::new(Arc::new(KeyStore::new()), Arc::new(CiphertextStore::new()));
+    let keys = service
+        .generate_keys(Request::new(KeyGenerationRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    
+    // The sum of two integers, and whether they differ while a flag is set
+    let mut builder = CircuitBuilder::new();
+    let x = builder.input_int("x");
+    let y = builder.input_int("y");
+    let flag = builder.input_bool("flag");
+    let sum = builder.add(x, y);
+    let difference = builder.subtract(x, y);
+    let equal = builder.is_zero(difference);
+    let differ = builder.not(equal);
+    let flagged = builder.and(flag, differ);
+    builder.output(sum).output(flagged);
+    let circuit = builder.build().unwrap();
+    assert_eq!(circuit.input_names(), vec!["x", "y", "flag"]);
+    assert_eq!(circuit.outputs_boolean(), vec![false, true]);
+    
+    // The server agrees the circuit is sound before anything is encrypted
+    let report = service
+        .validate_circuit(Request::new(circuit.validation_request()))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(report.valid, "{:?}", report.issues);
+    assert_eq!(report.depth, 4);
+    
+    let mut ids = HashMap::new();
+    for (name, value) in [("x", 20), ("y", 22)] {
+        let request = Request::new(EncryptIntegerRequest {
+            client_key_id: keys.client_key_id.clone(),
+            value,
+            num_bits: 8,
+            ..Default::default()
+        });
+        let id = service
+            .encrypt_integer(request)
+            .await
+            .unwrap()
+            .into_inner()
+            .encrypted_data_id;
+        ids.insert(name, id);
+    }
+    let request = Request::new(EncryptBooleanRequest {
+        client_key_id: keys.client_key_id.clone(),
+        value: true,
+        ..Default::default()
+    });
+    let id = service
+        .encrypt_boolean(request)
+        .await
+        .unwrap()
+        .into_inner()
+        .encrypted_data_id;
+    ids.insert("flag", id);
+    
+    // Inputs are bound by name, whatever order they were given in
+    let input_ids: HashMap<&str, &str> = ids.iter().map(|(name, id)| (*name, id.as_str())).collect();
+    let request = circuit.request(&keys.server_key_id, &input_ids).unwrap();
+    let outputs = service
+        .evaluate_circuit(Request::new(request))
+        .await
+        .unwrap()
+        .into_inner()
+        .output_ids;
+    assert_eq!(outputs.len(), 2);
+    
+    let request = Request::new(DecryptIntegerRequest {
+        client_key_id: keys.client_key_id.clone(),
+        encrypted_data_id: outputs[0].clone(),
+        ..Default::default()
+    });
+    assert_eq!(
+        service.decrypt_integer(request).await.unwrap().into_inner().value,
+        42
+    );
+    let request = Request::new(DecryptBooleanRequest {
+        client_key_id: keys.client_key_id.clone(),
+        encrypted_data_id: outputs[1].clone(),
+        ..Default::default()
+    });
+    assert!(service.decrypt_boolean(request).await.unwrap().into_inner().value);
+    
+    let input_ids = HashMap::from([("x", "a"), ("y", "b")]);
+    assert_eq!(
+        circuit.request(&keys.server_key_id, &input_ids).unwrap_err(),
+        CircuitBuildError::MissingInput("flag".to_string())
+    );
+}
+
+#[test]
+fn test_builder_refuses_broken_circuits() {
+    let mut builder = CircuitBuilder::new();
+    let a = builder.input_bool("a");
+    let b = builder.input_bool("a");
+    let both = builder.and(a, b);
+    builder.output(both);
+    assert_eq!(
+        builder.build().unwrap_err(),
+        CircuitBuildError::DuplicateInput("a".to_string())
+    );
+    
+    // Signals belong to the builder that made them
+    let mut first = CircuitBuilder::new();
+    let a = first.input_bool("a");
+    let mut second = CircuitBuilder::new();
+    let b = second.input_bool("b");
+    let either = second.or(a, b);
+    second.output(either);
+    assert_eq!(second.build().unwrap_err(), CircuitBuildError::ForeignSignal);
+    
+    // Whatever the server's validation would refuse is refused at build time
+    let mut builder = CircuitBuilder::new();
+    let a = builder.input_int("a");
+    builder.is_non_zero(a);
+    match builder.build() {
+        Err(CircuitBuildError::Invalid(issue)) => assert_eq!(issue.kind, IssueKind::NoOutputs),
+        other => panic!(
+            "expected a missing output to be refused, got {:?}",
+            other.map(|_| ())
+        ),
+    }
+}
+
+#[test]
+fn test_fhe_macro_compiles_expressions() {
+    let circuit = hermetic_fhe::fhe!((a - b) * k).unwrap();
+    assert_eq!(circuit.input_names(), vec!["a", "b", "k"]);
+    assert_eq!(circuit.outputs_boolean(), vec![false]);
+    assert_eq!(circuit.gates.len(), 2);
+    
+    let circuit = hermetic_fhe::fhe! { x & !y }.unwrap();
+    assert_eq!(circuit.input_names(), vec!["x", "y"]);
+    assert_eq!(circuit.outputs_boolean(), vec![true]);
+    
+    // Inputs take their types from the operators applied to them
+    match hermetic_fhe::fhe!(a & (a + b)) {
+        Err(CircuitBuildError::Invalid(issue)) => assert_eq!(issue.kind, IssueKind::TypeMismatch),
+        other => panic!("expected a mixed-type input to be refused, got {:?}", other.map(|_| ())),
+    }
+    match hermetic_fhe::fhe!((a + b) & c) {
+        Err(CircuitBuildError::Invalid(issue)) => assert_eq!(issue.kind, IssueKind::TypeMismatch),
+        other => panic!("expected an integer operand to & to be refused, got {:?}", other.map(|_| ())),
+    }
+    assert!(matches!(
+        hermetic_fhe::fhe!(a && b),
+        Err(CircuitBuildError::Expression(_))
+    ));
+}
@@ -0,0 +1,843 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tfhe::{prelude::FheDecrypt, prelude::FheTryEncrypt, FheBool};
+use tonic::Request;
+
+use hermetic_fhe::api::{
+    circuit_wire::Source, plaintext_value, CheckpointOptions, CiphertextType,
+    CircuitEvaluationRequest, CircuitGate, CircuitIssueKind, CircuitWire, DeclaredInput,
+    DecryptBooleanRequest, DecryptIntegerRequest, EncryptAndEvaluateRequest, EncryptBooleanRequest,
+    EstimateCostRequest, EvaluateAndDecryptRequest, FheService, KeyGenerationRequest,
+    LibraryCircuitRequest, ListLibraryCircuitsRequest, OperationType, PlaintextValue,
+    ValidateCircuitRequest,
+};
+use hermetic_fhe::cancellation::{Cancellation, Cancelled};
+use hermetic_fhe::circuit::checkpoint::{Checkpoint, SavedValue};
+use hermetic_fhe::circuit::cost::CostModel;
+use hermetic_fhe::circuit::expression::{self, MAX_NESTING};
+use hermetic_fhe::circuit::library;
+use hermetic_fhe::circuit::{Circuit, EvaluationOptions, Gate, Operation, Value, Wire};
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::checkpoint::CheckpointPolicy;
+use hermetic_fhe::service::errors::ErrorReason;
+use hermetic_fhe::service::FheServiceImpl;
+
+async fn setup_service() -> FheServiceImpl {
+    let key_store = Arc::new(KeyStore::new());
+    let ciphertext_store = Arc::new(CiphertextStore::new());
+    FheServiceImpl::new(key_store, ciphertext_store)
+}
+
+fn input(index: u32) -> CircuitWire {
+    CircuitWire { source: Some(Source::Input(index)) }
+}
+
+fn gate(index: u32) -> CircuitWire {
+    CircuitWire { source: Some(Source::Gate(index)) }
+}
+
+// XOR the running value with the alternating input at every step: a deep but narrow circuit
+fn xor_chain(depth: u32) -> Vec<CircuitGate> {
+    (0..depth)
+        .map(|i| CircuitGate {
+            operation: OperationType::Xor as i32,
+            operands: vec![if i == 0 { input(0) } else { gate(i - 1) }, input(1)],
+        })
+        .collect()
+}
+
+async fn encrypt(service: &FheServiceImpl, client_key_id: &str, value: bool) -> String {
+    let encrypt_request = Request::new(EncryptBooleanRequest {
+        client_key_id: client_key_id.to_string(),
+        value,
+        ..Default::default()
+    });
+    
+    let encrypt_response = service.encrypt_boolean(encrypt_request).await.unwrap();
+    encrypt_response.get_ref().encrypted_data_id.clone()
+}
+
+async fn decrypt(service: &FheServiceImpl, client_key_id: &str, encrypted_data_id: &str) -> bool {
+    let decrypt_request = Request::new(DecryptBooleanRequest {
+        client_key_id: client_key_id.to_string(),
+        encrypted_data_id: encrypted_data_id.to_string(),
+        serialized_data: vec![],
+    });
+    
+    service.decrypt_boolean(decrypt_request).await.unwrap().get_ref().value
+}
+
+#[tokio::test]
+async fn test_circuit_frees_intermediates() {
+    let service = setup_service().await;
+    
+    // Generate keys
+    let key_gen_request = Request::new(KeyGenerationRequest {
+        parameter_set: 0, // DEFAULT
+        ..Default::default()
+    });
+    
+    let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
+    let client_key_id = key_gen_response.get_ref().client_key_id.clone();
+    let server_key_id = key_gen_response.get_ref().server_key_id.clone();
+    
+    let false_id = encrypt(&service, &client_key_id, false).await;
+    let true_id = encrypt(&service, &client_key_id, true).await;
+    
+    // false XOR true, five times over, ends up true
+    let eval_request = Request::new(CircuitEvaluationRequest {
+        server_key_id: server_key_id.clone(),
+        input_ids: vec![false_id.clone(), true_id.clone()],
+        gates: xor_chain(5),
+        outputs: vec![gate(4)],
+        ..Default::default()
+    });
+    let response = service.evaluate_circuit(eval_request).await.unwrap().into_inner();
+    
+    assert_eq!(response.output_ids.len(), 1);
+    assert_eq!(response.output_fingerprints.len(), 1);
+    assert!(response.intermediates.is_empty(), "Intermediates should not be kept by default");
+    assert!(response.peak_live_ciphertexts <= 2, "Chain should only hold its frontier, held {}", response.peak_live_ciphertexts);
+    assert!(decrypt(&service, &client_key_id, &response.output_ids[0]).await, "Circuit output should decrypt to true");
+}
+
+#[tokio::test]
+async fn test_circuit_keep_intermediates() {
+    let service = setup_service().await;
+    
+    // Generate keys
+    let key_gen_request = Request::new(KeyGenerationRequest {
+        parameter_set: 0, // DEFAULT
+        ..Default::default()
+    });
+    
+    let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
+    let client_key_id = key_gen_response.get_ref().client_key_id.clone();
+    let server_key_id = key_gen_response.get_ref().server_key_id.clone();
+    
+    let false_id = encrypt(&service, &client_key_id, false).await;
+    let true_id = encrypt(&service, &client_key_id, true).await;
+    
+    let eval_request = Request::new(CircuitEvaluationRequest {
+        server_key_id: server_key_id.clone(),
+        input_ids: vec![false_id, true_id],
+        gates: xor_chain(3),
+        outputs: vec![gate(2)],
+        keep_intermediates: true,
+        ..Default::default()
+    });
+    let response = service.evaluate_circuit(eval_request).await.unwrap().into_inner();
+    
+    assert_eq!(response.peak_live_ciphertexts, 3, "Every gate output should stay live");
+    assert_eq!(response.intermediates.len(), 2, "Both non-output gates should be stored");
+    
+    // Intermediates alternate true, false as the chain toggles
+    for intermediate in &response.intermediates {
+        let value = decrypt(&service, &client_key_id, &intermediate.encrypted_data_id).await;
+        let expected = intermediate.gate.is_multiple_of(2);
+        assert_eq!(value, expected, "Unexpected value for gate {}", intermediate.gate);
+    }
+    assert!(decrypt(&service, &client_key_id, &response.output_ids[0]).await);
+}
+
+#[tokio::test]
+async fn test_circuit_rejects_forward_reference() {
+    let service = setup_service().await;
+    
+    // Generate keys
+    let key_gen_request = Request::new(KeyGenerationRequest {
+        parameter_set: 0, // DEFAULT
+        ..Default::default()
+    });
+    
+    let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
+    let client_key_id = key_gen_response.get_ref().client_key_id.clone();
+    let server_key_id = key_gen_response.get_ref().server_key_id.clone();
+    
+    let a_id = encrypt(&service, &client_key_id, true).await;
+    
+    // Gate 0 reads gate 1, which has not been evaluated yet
+    let eval_request = Request::new(CircuitEvaluationRequest {
+        server_key_id,
+        input_ids: vec![a_id],
+        gates: vec![
+            CircuitGate { operation: OperationType::Not as i32, operands: vec![gate(1)] },
+            CircuitGate { operation: OperationType::Not as i32, operands: vec![input(0)] },
+        ],
+        outputs: vec![gate(0)],
+        ..Default::default()
+    });
+    
+    let status = service.evaluate_circuit(eval_request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn test_evaluate_and_decrypt() {
+    let service = setup_service().await;
+    
+    // Generate keys
+    let key_gen_request = Request::new(KeyGenerationRequest {
+        parameter_set: 0, // DEFAULT
+        ..Default::default()
+    });
+    
+    let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
+    let client_key_id = key_gen_response.get_ref().client_key_id.clone();
+    let server_key_id = key_gen_response.get_ref().server_key_id.clone();
+    
+    let false_id = encrypt(&service, &client_key_id, false).await;
+    let true_id = encrypt(&service, &client_key_id, true).await;
+    
+    // Output both the last gate and one in the middle of the chain
+    let request = Request::new(EvaluateAndDecryptRequest {
+        client_key_id: client_key_id.clone(),
+        server_key_id: server_key_id.clone(),
+        input_ids: vec![false_id, true_id],
+        gates: xor_chain(2),
+        outputs: vec![gate(1), gate(0)],
+    });
+    let response = service.evaluate_and_decrypt(request).await.unwrap().into_inner();
+    
+    let values: Vec<_> = response.values.into_iter().map(|value| value.value).collect();
+    assert_eq!(
+        values,
+        vec![Some(plaintext_value::Value::Boolean(false)), Some(plaintext_value::Value::Boolean(true))]
+    );
+}
+
+#[tokio::test]
+async fn test_evaluate_and_decrypt_requires_client_key() {
+    let service = setup_service().await;
+    
+    // Generate keys
+    let key_gen_request = Request::new(KeyGenerationRequest {
+        parameter_set: 0, // DEFAULT
+        ..Default::default()
+    });
+    
+    let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
+    let client_key_id = key_gen_response.get_ref().client_key_id.clone();
+    let server_key_id = key_gen_response.get_ref().server_key_id.clone();
+    
+    let a_id = encrypt(&service, &client_key_id, true).await;
+    
+    let request = Request::new(EvaluateAndDecryptRequest {
+        client_key_id: "non-existent-key".to_string(),
+        server_key_id,
+        input_ids: vec![a_id],
+        gates: vec![CircuitGate { operation: OperationType::Not as i32, operands: vec![input(0)] }],
+        outputs: vec![gate(0)],
+    });
+    
+    let status = service.evaluate_and_decrypt(request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::NotFound);
+    assert!(status.message().contains("Client key not found"));
+}
+
+#[tokio::test]
+async fn test_encrypt_and_evaluate() {
+    let service = setup_service().await;
+    
+    // Generate keys
+    let key_gen_request = Request::new(KeyGenerationRequest {
+        parameter_set: 0, // DEFAULT
+        ..Default::default()
+    });
+    
+    let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
+    let client_key_id = key_gen_response.get_ref().client_key_id.clone();
+    let server_key_id = key_gen_response.get_ref().server_key_id.clone();
+    
+    // (3 + 4) * 5, with all inputs sent in the clear
+    let request = Request::new(EncryptAndEvaluateRequest {
+        client_key_id: client_key_id.clone(),
+        server_key_id,
+        inputs: [3, 4, 5]
+            .iter()
+            .map(|value| PlaintextValue { value: Some(plaintext_value::Value::Integer(*value)) })
+            .collect(),
+        gates: vec![
+            CircuitGate { operation: OperationType::Add as i32, operands: vec![input(0), input(1)] },
+            CircuitGate { operation: OperationType::Multiply as i32, operands: vec![gate(0), input(2)] },
+        ],
+        outputs: vec![gate(1)],
+        ..Default::default()
+    });
+    let response = service.encrypt_and_evaluate(request).await.unwrap().into_inner();
+    
+    assert_eq!(response.output_ids.len(), 1);
+    assert!(!response.output_fingerprints[0].is_empty());
+    
+    let decrypt_request = Request::new(DecryptIntegerRequest {
+        client_key_id,
+        encrypted_data_id: response.output_ids[0].clone(),
+        serialized_data: vec![],
+        ..Default::default()
+    });
+    let value = service.decrypt_integer(decrypt_request).await.unwrap().get_ref().value;
+    assert_eq!(value, 35);
+}
+
+#[tokio::test]
+async fn test_encrypt_and_evaluate_rejects_out_of_range_input() {
+    let service = setup_service().await;
+    
+    // Generate keys
+    let key_gen_request = Request::new(KeyGenerationRequest {
+        parameter_set: 0, // DEFAULT
+        ..Default::default()
+    });
+    
+    let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
+    let client_key_id = key_gen_response.get_ref().client_key_id.clone();
+    let server_key_id = key_gen_response.get_ref().server_key_id.clone();
+    
+    let request = Request::new(EncryptAndEvaluateRequest {
+        client_key_id,
+        server_key_id,
+        inputs: vec![
+            PlaintextValue { value: Some(plaintext_value::Value::Integer(256)) },
+            PlaintextValue { value: Some(plaintext_value::Value::Integer(1)) },
+        ],
+        gates: vec![CircuitGate { operation: OperationType::Add as i32, operands: vec![input(0), input(1)] }],
+        outputs: vec![gate(0)],
+        ..Default::default()
+    });
+    
+    let status = service.encrypt_and_evaluate(request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn test_circuit_respects_deadline() {
+    let service = setup_service().await;
+    
+    // Generate keys
+    let key_gen_request = Request::new(KeyGenerationRequest {
+        parameter_set: 0, // DEFAULT
+        ..Default::default()
+    });
+    
+    let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
+    let client_key_id = key_gen_response.get_ref().client_key_id.clone();
+    let server_key_id = key_gen_response.get_ref().server_key_id.clone();
+    
+    let false_id = encrypt(&service, &client_key_id, false).await;
+    let true_id = encrypt(&service, &client_key_id, true).await;
+    
+    // A one nanosecond deadline has passed before the first gate runs
+    let mut eval_request = Request::new(CircuitEvaluationRequest {
+        server_key_id,
+        input_ids: vec![false_id, true_id],
+        gates: xor_chain(100),
+        outputs: vec![gate(99)],
+        ..Default::default()
+    });
+    eval_request.metadata_mut().insert("grpc-timeout", "1n".parse().unwrap());
+    
+    let status = service.evaluate_circuit(eval_request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::DeadlineExceeded);
+}
+
+#[test]
+fn test_cancel_on_drop() {
+    let cancellation = Cancellation::new();
+    assert_eq!(cancellation.check(), Ok(()));
+    
+    // A disarmed guard leaves the work running
+    cancellation.cancel_on_drop().disarm();
+    assert_eq!(cancellation.check(), Ok(()));
+    
+    // Dropping an armed guard, as happens when a request future is abandoned, cancels it
+    drop(cancellation.cancel_on_drop());
+    assert_eq!(cancellation.check(), Err(Cancelled::ByCaller));
+}
+
+fn declared(ciphertext_type: CiphertextType, num_bits: u32) -> DeclaredInput {
+    DeclaredInput {
+        ciphertext_type: ciphertext_type as i32,
+        num_bits,
+    }
+}
+
+#[tokio::test]
+async fn test_validate_circuit_reports_depth() {
+    let service = setup_service().await;
+    
+    // Nothing is evaluated, so inputs can be declared instead of uploaded
+    let request = Request::new(ValidateCircuitRequest {
+        declared_inputs: vec![declared(CiphertextType::Boolean, 0), declared(CiphertextType::Boolean, 0)],
+        gates: xor_chain(5),
+        outputs: vec![gate(4)],
+        ..Default::default()
+    });
+    let report = service.validate_circuit(request).await.unwrap().into_inner();
+    
+    assert!(report.valid, "Unexpected issues: {:?}", report.issues);
+    assert_eq!(report.depth, 5);
+    assert_eq!(report.multiplicative_depth, 0);
+    assert_eq!(report.peak_live_ciphertexts, 2, "Should match what EvaluateCircuit would hold");
+    assert_eq!(report.operation_counts.len(), 1);
+    assert_eq!(report.operation_counts[0].operation, OperationType::Xor as i32);
+    assert_eq!(report.operation_counts[0].count, 5);
+}
+
+#[tokio::test]
+async fn test_validate_circuit_reports_every_issue() {
+    let service = setup_service().await;
+    
+    // Input 0 is a stored ID that doesn't exist; inputs 1-3 are declared
+    let request = Request::new(ValidateCircuitRequest {
+        input_ids: vec!["missing".to_string()],
+        declared_inputs: vec![
+            declared(CiphertextType::Boolean, 0),
+            declared(CiphertextType::Integer, 8),
+            declared(CiphertextType::Integer, 16),
+        ],
+        gates: vec![
+            // Boolean AND on an integer operand
+            CircuitGate {
+                operation: OperationType::And as i32,
+                operands: vec![input(1), input(2)],
+            },
+            // 8-bit plus 16-bit
+            CircuitGate {
+                operation: OperationType::Add as i32,
+                operands: vec![input(2), input(3)],
+            },
+            // Reads the missing input; reported once, at the input
+            CircuitGate {
+                operation: OperationType::Not as i32,
+                operands: vec![input(0)],
+            },
+            // Reads a gate that doesn't exist yet
+            CircuitGate {
+                operation: OperationType::Multiply as i32,
+                operands: vec![input(2), gate(7)],
+            },
+        ],
+        outputs: vec![gate(0), gate(1), gate(2), gate(3)],
+    });
+    let report = service.validate_circuit(request).await.unwrap().into_inner();
+    
+    assert!(!report.valid);
+    let kinds: Vec<(i32, i32)> = report.issues.iter().map(|issue| (issue.kind, issue.gate)).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (CircuitIssueKind::WidthMismatch as i32, -1), // 16 bits isn't supported
+            (CircuitIssueKind::MissingInput as i32, -1),
+            (CircuitIssueKind::TypeMismatch as i32, 0),
+            (CircuitIssueKind::WidthMismatch as i32, 1),
+            (CircuitIssueKind::InvalidWire as i32, 3),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_validate_malformed_circuit() {
+    let service = setup_service().await;
+    
+    // Comparisons can't appear in circuits, so the circuit can't be built at all
+    let request = Request::new(ValidateCircuitRequest {
+        declared_inputs: vec![declared(CiphertextType::Integer, 8), declared(CiphertextType::Integer, 8)],
+        gates: vec![CircuitGate {
+            operation: OperationType::Equal as i32,
+            operands: vec![input(0), input(1)],
+        }],
+        outputs: vec![gate(0)],
+        ..Default::default()
+    });
+    let report = service.validate_circuit(request).await.unwrap().into_inner();
+    
+    assert!(!report.valid);
+    assert_eq!(report.issues.len(), 1);
+    assert_eq!(report.issues[0].kind, CircuitIssueKind::Malformed as i32);
+    
+    // Nor can an operation that doesn't exist, rather than being taken for AND
+    let request = Request::new(ValidateCircuitRequest {
+        declared_inputs: vec![declared(CiphertextType::Boolean, 1), declared(CiphertextType::Boolean, 1)],
+        gates: vec![CircuitGate { operation: 99, operands: vec![input(0), input(1)] }],
+        outputs: vec![gate(0)],
+        ..Default::default()
+    });
+    let report = service.validate_circuit(request).await.unwrap().into_inner();
+    
+    assert!(!report.valid);
+    assert_eq!(report.issues[0].kind, CircuitIssueKind::Malformed as i32);
+    assert!(report.issues[0].message.contains("Unknown operation 99"));
+}
+
+#[tokio::test]
+async fn test_estimate_cost() {
+    let service = setup_service().await;
+    
+    let estimate = |operation: OperationType| EstimateCostRequest {
+        operation: operation as i32,
+        ..Default::default()
+    };
+    let add = service.estimate_cost(Request::new(estimate(OperationType::Add))).await.unwrap().into_inner();
+    let multiply = service.estimate_cost(Request::new(estimate(OperationType::Multiply))).await.unwrap().into_inner();
+    let xor = service.estimate_cost(Request::new(estimate(OperationType::Xor))).await.unwrap().into_inner();
+    assert!(!add.calibrated, "Built-in costs should be reported as such");
+    assert!(multiply.latency_seconds > add.latency_seconds);
+    assert!(add.memory_bytes > xor.memory_bytes, "Integers should take more memory than booleans");
+    
+    // A chain costs every gate in turn, but only ever holds two outputs
+    let request = Request::new(EstimateCostRequest {
+        gates: xor_chain(5),
+        outputs: vec![gate(4)],
+        ..Default::default()
+    });
+    let chain = service.estimate_cost(request).await.unwrap().into_inner();
+    assert!((chain.latency_seconds - 5.0 * xor.latency_seconds).abs() < 1e-9);
+    assert_eq!(chain.memory_bytes, 2 * xor.memory_bytes);
+    
+    let request = Request::new(EstimateCostRequest {
+        parameter_set: 7,
+        ..Default::default()
+    });
+    let status = service.estimate_cost(request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+}
+
+#[test]
+fn test_calibrate_cost_model() {
+    let model = CostModel::calibrate(&["FAST"], 1).unwrap();
+    assert!(model.is_calibrated());
+    
+    let costs = model.parameter_costs("FAST").unwrap();
+    assert_eq!(costs.gate_latency.len(), 7, "Every gate should be timed");
+    assert!(costs.gate_latency.values().all(|latency| !latency.is_zero()));
+    assert!(costs.integer_bytes > costs.boolean_bytes && costs.boolean_bytes > 0);
+    
+    // Sets that weren't measured keep their built-in costs
+    assert!(model.parameter_costs("SECURE").is_some());
+}
+
+// A cost table with every parameter set's XOR taking this long
+fn cost_table(name: &str, xor_micros: u64) -> PathBuf {
+    let sizes = r#""boolean_bytes": 16384, "integer_bytes": 65536, "server_key_bytes": 1000"#;
+    let costs = format!(r#"{{"gate_latency_micros": {{"Xor": {}}}, {}}}"#, xor_micros, sizes);
+    let table = format!(r#"{{"parameter_sets": {{"DEFAULT": {0}, "FAST": {0}, "SECURE": {0}}}}}"#, costs);
+    let path = std::env::temp_dir().join(format!("hermetic-fhe-{}-{}.json", name, uuid::Uuid::new_v4()));
+    std::fs::write(&path, table).unwrap();
+    path
+}
+
+#[test]
+fn test_cost_table_saved_and_loaded() {
+    assert!(!CostModel::default().is_calibrated());
+    
+    let path = cost_table("costs", 10_000_000);
+    let model = CostModel::load(&path).unwrap();
+    assert!(model.is_calibrated() && model.is_measured("FAST"));
+    let costs = model.parameter_costs("FAST").unwrap();
+    assert_eq!(costs.gate_latency[&Operation::Xor], Duration::from_secs(10));
+    assert_eq!(costs.server_key_bytes, 1000);
+    // Operations the table leaves out keep their built-in timings
+    let builtin = CostModel::default();
+    let builtin = builtin.parameter_costs("FAST").unwrap();
+    assert_eq!(costs.gate_latency[&Operation::Add], builtin.gate_latency[&Operation::Add]);
+    
+    // Saved and loaded again, the measured sets come back as they were
+    let saved = std::env::temp_dir().join(format!("hermetic-fhe-saved-{}.json", uuid::Uuid::new_v4()));
+    model.save(&saved).unwrap();
+    let reloaded = CostModel::load(&saved).unwrap();
+    let costs = reloaded.parameter_costs("SECURE").unwrap();
+    assert_eq!(costs.gate_latency[&Operation::Xor], Duration::from_secs(10));
+    assert_eq!(costs.gate_latency.len(), builtin.gate_latency.len());
+    
+    // Only what the server knows is accepted
+    let sizes = r#""boolean_bytes": 1, "integer_bytes": 1, "server_key_bytes": 1"#;
+    for (parameter_set, operation, unknown) in [("TURBO", "Xor", "TURBO"), ("FAST", "Xnor", "Xnor")] {
+        let table = format!(
+            r#"{{"parameter_sets": {{"{}": {{"gate_latency_micros": {{"{}": 1}}, {}}}}}}}"#,
+            parameter_set, operation, sizes
+        );
+        std::fs::write(&path, table).unwrap();
+        let error = CostModel::load(&path).unwrap_err().to_string();
+        assert!(error.contains(unknown), "{}", error);
+    }
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&saved).unwrap();
+}
+
+#[tokio::test]
+async fn test_measured_costs_refuse_hopeless_deadlines() {
+    let key_store = Arc::new(KeyStore::new());
+    let ciphertext_store = Arc::new(CiphertextStore::new());
+    let path = cost_table("slow", 100_000_000);
+    let service = FheServiceImpl::new(key_store.clone(), ciphertext_store.clone())
+        .with_cost_model(CostModel::load(&path).unwrap());
+    std::fs::remove_file(&path).unwrap();
+    
+    // Two 100s gates, with nothing queued, are worth a 400s deadline
+    let request = Request::new(EstimateCostRequest {
+        gates: xor_chain(2),
+        outputs: vec![gate(1)],
+        ..Default::default()
+    });
+    let estimate = service.estimate_cost(request).await.unwrap().into_inner();
+    assert!(estimate.calibrated);
+    assert_eq!(estimate.latency_seconds, 200.0);
+    assert_eq!(estimate.queue_wait_seconds, 0.0);
+    assert_eq!(estimate.suggested_deadline_seconds, 400.0);
+    
+    let keys = service
+        .generate_keys(Request::new(KeyGenerationRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    let false_id = encrypt(&service, &keys.client_key_id, false).await;
+    let true_id = encrypt(&service, &keys.client_key_id, true).await;
+    let evaluate = || {
+        let mut request = Request::new(CircuitEvaluationRequest {
+            server_key_id: keys.server_key_id.clone(),
+            input_ids: vec![false_id.clone(), true_id.clone()],
+            gates: xor_chain(2),
+            outputs: vec![gate(1)],
+            ..Default::default()
+        });
+        request.metadata_mut().insert("grpc-timeout", "60S".parse().unwrap());
+        request
+    };
+    let status = service.evaluate_circuit(evaluate()).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::DeadlineExceeded));
+    assert!(status.message().contains("at least 400s"), "{}", status.message());
+    
+    // Built-in timings are only a guess for this machine, so nothing is refused on them
+    let service = FheServiceImpl::new(key_store, ciphertext_store);
+    service.evaluate_circuit(evaluate()).await.unwrap();
+}
+
+#[test]
+fn test_parse_expression() {
+    let parsed = expression::parse("(a - b) * c").unwrap();
+    assert_eq!(parsed.variables, vec!["a", "b", "c"]);
+    let operations: Vec<Operation> = parsed.circuit.gates.iter().map(|gate| gate.operation).collect();
+    assert_eq!(operations, vec![Operation::Subtract, Operation::Multiply]);
+    assert_eq!(parsed.circuit.gates[1].inputs, vec![Wire::Gate(0), Wire::Input(2)]);
+    assert_eq!(parsed.circuit.outputs, vec![Wire::Gate(1)]);
+    
+    // & binds tighter than |, ! tightest, and a repeated variable is one input
+    let parsed = expression::parse("x | !y & x").unwrap();
+    assert_eq!(parsed.variables, vec!["x", "y"]);
+    let operations: Vec<Operation> = parsed.circuit.gates.iter().map(|gate| gate.operation).collect();
+    assert_eq!(operations, vec![Operation::Not, Operation::And, Operation::Or]);
+    assert_eq!(parsed.circuit.gates[2].inputs, vec![Wire::Input(0), Wire::Gate(1)]);
+    
+    for (source, offset) in [("a +", 3), ("(a * b", 6), ("a $ b", 2), ("a b", 2), ("", 0)] {
+        let error = expression::parse(source).unwrap_err();
+        assert_eq!(error.offset, offset, "Wrong error offset for {:?}: {}", source, error);
+    }
+    
+    let nested = format!("{}a{}", "(".repeat(MAX_NESTING + 1), ")".repeat(MAX_NESTING + 1));
+    assert!(expression::parse(&nested).is_err(), "Deep nesting should be refused");
+    assert!(expression::parse(&"!".repeat(10_000)).is_err(), "Long expressions should be refused");
+}
+
+// Evaluate a boolean circuit in the clear, to check library constructions exhaustively
+fn evaluate_plain(circuit: &Circuit, inputs: &[bool]) -> Vec<bool> {
+    let mut values: Vec<bool> = Vec::new();
+    let read = |values: &[bool], wire: Wire| match wire {
+        Wire::Input(index) => inputs[index],
+        Wire::Gate(index) => values[index],
+    };
+    for gate in &circuit.gates {
+        let operands: Vec<bool> = gate.inputs.iter().map(|wire| read(&values, *wire)).collect();
+        values.push(match gate.operation {
+            Operation::And => operands[0] & operands[1],
+            Operation::Or => operands[0] | operands[1],
+            Operation::Xor => operands[0] ^ operands[1],
+            Operation::Not => !operands[0],
+            operation => panic!("{:?} in a boolean circuit", operation),
+        });
+    }
+    circuit.outputs.iter().map(|wire| read(&values, *wire)).collect()
+}
+
+fn bits(value: usize, width: usize) -> Vec<bool> {
+    (0..width).map(|bit| (value >> bit) & 1 == 1).collect()
+}
+
+fn number(bits: &[bool]) -> usize {
+    bits.iter().rev().fold(0, |value, bit| (value << 1) | *bit as usize)
+}
+
+#[test]
+fn test_library_circuits_in_the_clear() {
+    let width = 3;
+    let adder = library::build("ripple_adder", width, 0).unwrap();
+    let comparator = library::build("comparator", width, 0).unwrap();
+    for a in 0..1 << width {
+        for b in 0..1 << width {
+            let inputs = [bits(a, width), bits(b, width)].concat();
+            assert_eq!(number(&evaluate_plain(&adder, &inputs)), a + b, "{} + {}", a, b);
+            assert_eq!(evaluate_plain(&comparator, &inputs), vec![a > b, a == b], "{} vs {}", a, b);
+        }
+    }
+    
+    let max = library::build("max", 2, 3).unwrap();
+    let parity = library::build("parity", 0, 5).unwrap();
+    let majority = library::build("majority", 0, 5).unwrap();
+    for pattern in 0usize..1 << 6 {
+        let values = [pattern & 3, (pattern >> 2) & 3, (pattern >> 4) & 3];
+        let outputs = evaluate_plain(&max, &bits(pattern, 6));
+        assert_eq!(number(&outputs), *values.iter().max().unwrap(), "max of {:?}", values);
+    }
+    for pattern in 0usize..1 << 5 {
+        let set = pattern.count_ones();
+        assert_eq!(evaluate_plain(&parity, &bits(pattern, 5)), vec![set % 2 == 1]);
+        assert_eq!(evaluate_plain(&majority, &bits(pattern, 5)), vec![set >= 3]);
+    }
+    
+    assert!(library::build("majority", 0, 4).is_err(), "Even majorities have no clear winner");
+    assert!(library::build("ripple_adder", 0, 0).is_err());
+    assert!(library::build("multiplier", 8, 0).is_err());
+}
+
+#[tokio::test]
+async fn test_evaluate_library_circuit() {
+    let service = setup_service().await;
+    
+    let key_gen_request = Request::new(KeyGenerationRequest {
+        parameter_set: 0, // DEFAULT
+        ..Default::default()
+    });
+    let keys = service.generate_keys(key_gen_request).await.unwrap().into_inner();
+    
+    let listed = service
+        .list_library_circuits(Request::new(ListLibraryCircuitsRequest {}))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(listed.circuits.iter().any(|circuit| circuit.name == "ripple_adder"));
+    
+    // 3 + 2 over two-bit values, least significant bit first
+    let mut input_ids = Vec::new();
+    for bit in [true, true, false, true] {
+        input_ids.push(encrypt(&service, &keys.client_key_id, bit).await);
+    }
+    let request = Request::new(LibraryCircuitRequest {
+        server_key_id: keys.server_key_id.clone(),
+        name: "ripple_adder".to_string(),
+        width: 2,
+        input_ids: input_ids.clone(),
+        ..Default::default()
+    });
+    let response = service.evaluate_library_circuit(request).await.unwrap().into_inner();
+    let mut sum = Vec::new();
+    for output_id in &response.output_ids {
+        sum.push(decrypt(&service, &keys.client_key_id, output_id).await);
+    }
+    assert_eq!(sum, vec![true, false, true], "3 + 2 should be 0b101");
+    
+    let request = Request::new(LibraryCircuitRequest {
+        server_key_id: keys.server_key_id,
+        name: "ripple_adder".to_string(),
+        width: 3,
+        input_ids,
+        ..Default::default()
+    });
+    let status = service.evaluate_library_circuit(request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::InvalidArgument, "Input count should match the width");
+}
+
+#[test]
+fn test_circuit_resumes_from_checkpoint() {
+    let key_store = KeyStore::new();
+    let (client_key_id, server_key_id) = key_store.generate_keys("DEFAULT").unwrap();
+    let client_key = key_store.get_client_key(&client_key_id).unwrap();
+    let server_key = key_store.get_server_key(&server_key_id).unwrap();
+    tfhe::set_server_key((*server_key).clone());
+    let encrypt = |value: bool| Value::Boolean(Arc::new(FheBool::try_encrypt(value, &*client_key).unwrap()));
+    let decrypt = |value: &Value| match value {
+        Value::Boolean(ciphertext) => ciphertext.decrypt(&*client_key),
+        Value::Integer(_) => panic!("Expected a boolean"),
+    };
+    
+    // false ^ true ^ true ^ true ^ true, one XOR per gate
+    let circuit = Circuit {
+        gates: (0..4)
+            .map(|i| Gate {
+                operation: Operation::Xor,
+                inputs: vec![if i == 0 { Wire::Input(0) } else { Wire::Gate(i - 1) }, Wire::Input(1)],
+            })
+            .collect(),
+        outputs: vec![Wire::Gate(3)],
+    };
+    let inputs = vec![encrypt(false), encrypt(true)];
+    let dir = std::env::temp_dir().join(format!("hermetic-fhe-checkpoint-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("chain.checkpoint");
+    let evaluate = |job: &str| {
+        let checkpoint = Checkpoint::new(&path, job, Duration::from_secs(3600));
+        let options = EvaluationOptions {
+            checkpoint: Some(Arc::new(checkpoint)),
+            ..Default::default()
+        };
+        circuit.evaluate(&server_key, &inputs, options).unwrap()
+    };
+    
+    // Progress as a run cut short after two gates saved it, laid out as the number of gates
+    // done and the outputs still needed. Gate 1 really gives false; saving true there shows
+    // the rest of the circuit ran from the checkpoint.
+    let progress = (2usize, vec![(1usize, SavedValue::new(&encrypt(true)).unwrap())]);
+    Checkpoint::new(&path, "chain", Duration::ZERO).save(&progress).unwrap();
+    let result = evaluate("chain");
+    assert_eq!(result.resumed_gates, 2);
+    assert!(decrypt(&result.outputs[0]));
+    assert!(!path.exists(), "A finished circuit should remove its checkpoint");
+    
+    // Progress another job saved under the same name is not picked up
+    Checkpoint::new(&path, "other", Duration::ZERO).save(&progress).unwrap();
+    let result = evaluate("chain");
+    assert_eq!(result.resumed_gates, 0);
+    assert!(!decrypt(&result.outputs[0]));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_evaluate_circuit_with_checkpoint() {
+    let service = setup_service().await;
+    let keys = service
+        .generate_keys(Request::new(KeyGenerationRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    let false_id = encrypt(&service, &keys.client_key_id, false).await;
+    let true_id = encrypt(&service, &keys.client_key_id, true).await;
+    let request = |name: &str| {
+        Request::new(CircuitEvaluationRequest {
+            server_key_id: keys.server_key_id.clone(),
+            input_ids: vec![false_id.clone(), true_id.clone()],
+            gates: xor_chain(3),
+            outputs: vec![gate(2)],
+            checkpoint: Some(CheckpointOptions {
+                name: name.to_string(),
+                interval_seconds: 1,
+            }),
+            ..Default::default()
+        })
+    };
+    
+    // Refused until the server has somewhere to keep checkpoints
+    let status = service.evaluate_circuit(request("chain")).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::Unsupported));
+    
+    let dir = std::env::temp_dir().join(format!("hermetic-fhe-checkpoint-{}", uuid::Uuid::new_v4()));
+    let service = service.with_checkpoint_policy(CheckpointPolicy::default().with_dir(&dir).unwrap());
+    for name in ["", "../escape", ".hidden", "a/b"] {
+        let status = service.evaluate_circuit(request(name)).await.unwrap_err();
+        assert_eq!(ErrorReason::of(&status), Some(ErrorReason::InvalidRequest), "{}", name);
+    }
+    
+    let response = service.evaluate_circuit(request("chain")).await.unwrap().into_inner();
+    assert_eq!(response.resumed_gates, 0);
+    assert!(decrypt(&service, &keys.client_key_id, &response.output_ids[0]).await);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0, "Nothing should be left once it finishes");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,172 @@
+use std::sync::Arc;
+use tonic::Request;
+
+use hermetic_fhe::api::v1::key_generation_request::Scheme;
+use hermetic_fhe::api::{
+    DecryptRealVectorRequest, EncryptIntegerRequest, EncryptRealVectorRequest, FheService,
+    KeyGenerationRequest, RealVectorEvaluationRequest, RealVectorOperation,
+};
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::errors::ErrorReason;
+use hermetic_fhe::service::FheServiceImpl;
+
+// CKKS is approximate; fresh results are good to about 2^-20 of the values' magnitude
+const TOLERANCE: f64 = 1e-3;
+
+async fn setup_service() -> FheServiceImpl {
+    let key_store = Arc::new(KeyStore::new());
+    let ciphertext_store = Arc::new(CiphertextStore::new());
+    FheServiceImpl::new(key_store, ciphertext_store)
+}
+
+// Generate keys for a scheme, returning (client_key_id, server_key_id)
+async fn generate_keys(service: &FheServiceImpl, scheme: Scheme) -> (String, String) {
+    let key_gen_request = Request::new(KeyGenerationRequest {
+        scheme: scheme as i32,
+        ..Default::default()
+    });
+    
+    let keys = service.generate_keys(key_gen_request).await.unwrap().into_inner();
+    (keys.client_key_id, keys.server_key_id)
+}
+
+async fn encrypt(service: &FheServiceImpl, client_key_id: &str, values: Vec<f64>) -> Result<String, tonic::Status> {
+    let request = Request::new(EncryptRealVectorRequest {
+        client_key_id: client_key_id.to_string(),
+        values,
+        ..Default::default()
+    });
+    
+    Ok(service.encrypt_real_vector(request).await?.into_inner().encrypted_data_id)
+}
+
+async fn decrypt(service: &FheServiceImpl, client_key_id: &str, encrypted_data_id: &str) -> Vec<f64> {
+    let request = Request::new(DecryptRealVectorRequest {
+        client_key_id: client_key_id.to_string(),
+        encrypted_data_id: encrypted_data_id.to_string(),
+    });
+    
+    service.decrypt_real_vector(request).await.unwrap().into_inner().values
+}
+
+async fn evaluate(
+    service: &FheServiceImpl,
+    server_key_id: &str,
+    operation: RealVectorOperation,
+    operand_ids: &[&str],
+    rotation: i64,
+) -> Result<String, tonic::Status> {
+    let request = Request::new(RealVectorEvaluationRequest {
+        server_key_id: server_key_id.to_string(),
+        operation: operation as i32,
+        operand_ids: operand_ids.iter().map(|id| id.to_string()).collect(),
+        rotation,
+        ..Default::default()
+    });
+    
+    Ok(service.evaluate_real_vector(request).await?.into_inner().result_id)
+}
+
+fn assert_close(actual: &[f64], expected: &[f64]) {
+    assert_eq!(actual.len(), expected.len(), "{:?} vs {:?}", actual, expected);
+    for (a, e) in actual.iter().zip(expected) {
+        assert!((a - e).abs() < TOLERANCE, "{:?} is not close to {:?}", actual, expected);
+    }
+}
+
+#[tokio::test]
+async fn test_real_vector_round_trip_and_add() {
+    let service = setup_service().await;
+    let (client_key_id, server_key_id) = generate_keys(&service, Scheme::Ckks).await;
+    
+    let x = encrypt(&service, &client_key_id, vec![1.5, -2.25, 3.0, 1000.125]).await.unwrap();
+    assert_close(&decrypt(&service, &client_key_id, &x).await, &[1.5, -2.25, 3.0, 1000.125]);
+    
+    // A shorter operand is padded with zeros
+    let y = encrypt(&service, &client_key_id, vec![0.5, 0.25]).await.unwrap();
+    let sum = evaluate(&service, &server_key_id, RealVectorOperation::RealAdd, &[&x, &y], 0).await.unwrap();
+    assert_close(&decrypt(&service, &client_key_id, &sum).await, &[2.0, -2.0, 3.0, 1000.125]);
+}
+
+#[tokio::test]
+async fn test_real_vector_multiply_until_depth_runs_out() {
+    let service = setup_service().await;
+    let (client_key_id, server_key_id) = generate_keys(&service, Scheme::Ckks).await;
+    
+    let x = encrypt(&service, &client_key_id, vec![1.5, -2.0, 3.0]).await.unwrap();
+    let y = encrypt(&service, &client_key_id, vec![2.0, 0.5, -1.0]).await.unwrap();
+    let product = evaluate(&service, &server_key_id, RealVectorOperation::RealMultiply, &[&x, &y], 0)
+        .await
+        .unwrap();
+    assert_close(&decrypt(&service, &client_key_id, &product).await, &[3.0, -1.0, -3.0]);
+    
+    // A fresh operand meets the product at its lower level
+    let cubed = evaluate(&service, &server_key_id, RealVectorOperation::RealMultiply, &[&product, &x], 0)
+        .await
+        .unwrap();
+    assert_close(&decrypt(&service, &client_key_id, &cubed).await, &[4.5, 2.0, -9.0]);
+    
+    // Addition still works at the bottom level, but another multiplication does not
+    let sum = evaluate(&service, &server_key_id, RealVectorOperation::RealAdd, &[&cubed, &y], 0).await.unwrap();
+    assert_close(&decrypt(&service, &client_key_id, &sum).await, &[6.5, 2.5, -10.0]);
+    let status = evaluate(&service, &server_key_id, RealVectorOperation::RealMultiply, &[&cubed, &x], 0)
+        .await
+        .unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::LimitExceeded));
+}
+
+#[tokio::test]
+async fn test_real_vector_rotate() {
+    let service = setup_service().await;
+    let (client_key_id, server_key_id) = generate_keys(&service, Scheme::Ckks).await;
+    
+    let x = encrypt(&service, &client_key_id, vec![1.0, 2.0, 3.0, 4.0]).await.unwrap();
+    
+    // Rotation is over every slot, so zeros come in from beyond the encrypted values
+    let left = evaluate(&service, &server_key_id, RealVectorOperation::RealRotate, &[&x], 1).await.unwrap();
+    assert_close(&decrypt(&service, &client_key_id, &left).await, &[2.0, 3.0, 4.0, 0.0]);
+    let right = evaluate(&service, &server_key_id, RealVectorOperation::RealRotate, &[&x], -2).await.unwrap();
+    assert_close(&decrypt(&service, &client_key_id, &right).await, &[0.0, 0.0, 1.0, 2.0]);
+    
+    // Steps that aren't a power of two are composed from ones that are
+    let back = evaluate(&service, &server_key_id, RealVectorOperation::RealRotate, &[&right], 4095).await.unwrap();
+    let back = evaluate(&service, &server_key_id, RealVectorOperation::RealRotate, &[&back], 3).await.unwrap();
+    assert_close(&decrypt(&service, &client_key_id, &back).await, &[1.0, 2.0, 3.0, 4.0]);
+}
+
+#[tokio::test]
+async fn test_real_vector_rejections() {
+    let service = setup_service().await;
+    let (client_key_id, server_key_id) = generate_keys(&service, Scheme::Ckks).await;
+    let (tfhe_client_key_id, _) = generate_keys(&service, Scheme::Tfhe).await;
+    
+    let status = encrypt(&service, &client_key_id, vec![0.0; 4097]).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::LimitExceeded));
+    let status = encrypt(&service, &client_key_id, vec![f64::NAN]).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::ValueOutOfRange));
+    let status = encrypt(&service, &client_key_id, vec![1e9]).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::ValueOutOfRange));
+    
+    // Keys and ciphertexts of the other scheme are not interchangeable
+    let status = encrypt(&service, &tfhe_client_key_id, vec![1.0]).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::KeyNotFound));
+    let integer_id = service
+        .encrypt_integer(Request::new(EncryptIntegerRequest {
+            client_key_id: tfhe_client_key_id,
+            value: 7,
+            num_bits: 8,
+            ..Default::default()
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .encrypted_data_id;
+    let status = evaluate(&service, &server_key_id, RealVectorOperation::RealRotate, &[&integer_id], 1)
+        .await
+        .unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::TypeMismatch));
+    
+    let x = encrypt(&service, &client_key_id, vec![1.0]).await.unwrap();
+    let status = evaluate(&service, &server_key_id, RealVectorOperation::RealAdd, &[&x], 0).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::ArityMismatch));
+}
@@ -0,0 +1,84 @@
+use std::sync::Arc;
+use hermetic_fhe::circuit::{Circuit, EvaluationOptions, Gate, Operation, Value, Wire};
+use hermetic_fhe::client::{export_ciphertext, import_ciphertext, FheClient};
+use hermetic_fhe::crypto::canonical;
+use tfhe::{FheBool, FheUint8};
+
+#[test]
+fn test_embedded_circuit_evaluation() {
+    // Generate keys and evaluate in-process, without any gRPC involved
+    let (client, server_key) = FheClient::generate();
+    tfhe::set_server_key(server_key.clone());
+    
+    let a = client.encrypt_integer(6).unwrap();
+    let b = client.encrypt_integer(7).unwrap();
+    
+    // (a + b) * a
+    let circuit = Circuit {
+        gates: vec![
+            Gate { operation: Operation::Add, inputs: vec![Wire::Input(0), Wire::Input(1)] },
+            Gate { operation: Operation::Multiply, inputs: vec![Wire::Gate(0), Wire::Input(0)] },
+        ],
+        outputs: vec![Wire::Gate(1)],
+    };
+    
+    let result = circuit
+        .evaluate(&server_key, &[Value::Integer(Arc::new(a)), Value::Integer(Arc::new(b))], EvaluationOptions::default())
+        .unwrap();
+    
+    match &result.outputs[0] {
+        Value::Integer(ct) => assert_eq!(client.decrypt_integer(ct), 78, "(6 + 7) * 6 should be 78"),
+        Value::Boolean(_) => panic!("Expected an integer output"),
+    }
+}
+
+#[test]
+fn test_client_ciphertext_round_trip() {
+    let (client, _server_key) = FheClient::generate();
+    
+    let ciphertext = client.encrypt_integer(42).unwrap();
+    let (bytes, fingerprint) = export_ciphertext(&ciphertext).unwrap();
+    
+    let imported: FheUint8 = import_ciphertext(&bytes, &fingerprint).unwrap();
+    assert_eq!(client.decrypt_integer(&imported), 42);
+    
+    // Corrupted bytes are caught by the fingerprint
+    let mut corrupted = bytes.clone();
+    corrupted[0] ^= 0xff;
+    assert!(import_ciphertext::<FheUint8>(&corrupted, &fingerprint).is_err());
+}
+
+#[test]
+fn test_client_key_saved_and_restored() {
+    // What a browser does: generate only the client key, keep it, and restore it later
+    let client = FheClient::with_parameters("FAST").unwrap();
+    let ciphertext = client.encrypt_integer(42).unwrap();
+    
+    let restored = FheClient::from_bytes(&client.to_bytes().unwrap()).unwrap();
+    assert_eq!(restored.decrypt_integer(&ciphertext), 42);
+    
+    assert!(FheClient::with_parameters("TURBO").is_err());
+    assert!(FheClient::from_bytes(&[1, 2, 3]).is_err());
+}
+
+#[test]
+fn test_canonical_encoding_checks_its_header() {
+    let (client, _) = FheClient::generate();
+    let ciphertext = client.encrypt_integer(42).unwrap();
+    let bytes = canonical::encode(&ciphertext).unwrap();
+    assert!(canonical::is_canonical(&bytes));
+    assert_eq!(client.decrypt_integer(&canonical::decode::<FheUint8>(&bytes).unwrap()), 42);
+    
+    // An integer isn't read as a boolean, nor under other parameters or format versions
+    assert!(canonical::decode::<FheBool>(&bytes).is_err());
+    for position in [4, 6] {
+        let mut altered = bytes.clone();
+        altered[position] ^= 0x01;
+        assert!(canonical::decode::<FheUint8>(&altered).is_err(), "byte {}", position);
+    }
+    
+    // Plain bincode from before the format is still read
+    let legacy = bincode::serialize(&ciphertext).unwrap();
+    assert!(!canonical::is_canonical(&legacy));
+    assert_eq!(client.decrypt_integer(&canonical::decode::<FheUint8>(&legacy).unwrap()), 42);
+}
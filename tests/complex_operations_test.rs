@@ -21,6 +21,7 @@ async fn test_chained_boolean_operations() {
     // Generate keys
     let key_gen_request = Request::new(KeyGenerationRequest {
         parameter_set: 0, // DEFAULT
+        ..Default::default()
     });
     
     let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
@@ -31,6 +32,7 @@ async fn test_chained_boolean_operations() {
     let encrypt_true1_request = Request::new(EncryptBooleanRequest {
         client_key_id: client_key_id.clone(),
         value: true,
+        ..Default::default()
     });
     let encrypt_true1_response = service.encrypt_boolean(encrypt_true1_request).await.unwrap();
     let true1_id = encrypt_true1_response.get_ref().encrypted_data_id.clone();
@@ -38,6 +40,7 @@ async fn test_chained_boolean_operations() {
     let encrypt_false_request = Request::new(EncryptBooleanRequest {
         client_key_id: client_key_id.clone(),
         value: false,
+        ..Default::default()
     });
     let encrypt_false_response = service.encrypt_boolean(encrypt_false_request).await.unwrap();
     let false_id = encrypt_false_response.get_ref().encrypted_data_id.clone();
@@ -45,6 +48,7 @@ async fn test_chained_boolean_operations() {
     let encrypt_true2_request = Request::new(EncryptBooleanRequest {
         client_key_id: client_key_id.clone(),
         value: true,
+        ..Default::default()
     });
     let encrypt_true2_response = service.encrypt_boolean(encrypt_true2_request).await.unwrap();
     let true2_id = encrypt_true2_response.get_ref().encrypted_data_id.clone();
@@ -54,6 +58,7 @@ async fn test_chained_boolean_operations() {
         server_key_id: server_key_id.clone(),
         operation: OperationType::And as i32,
         operand_ids: vec![true1_id, false_id],
+        ..Default::default()
     });
     let eval_response1 = service.evaluate_operation(eval_request1).await.unwrap();
     let intermediate_result_id = eval_response1.get_ref().result_id.clone();
@@ -63,6 +68,7 @@ async fn test_chained_boolean_operations() {
         server_key_id: server_key_id.clone(),
         operation: OperationType::Or as i32,
         operand_ids: vec![intermediate_result_id, true2_id],
+        ..Default::default()
     });
     let eval_response2 = service.evaluate_operation(eval_request2).await.unwrap();
     let final_result_id = eval_response2.get_ref().result_id.clone();
@@ -77,7 +83,7 @@ async fn test_chained_boolean_operations() {
     let result = decrypt_response.get_ref().value;
     
     // We expect (true AND false) OR true = (false) OR true = true
-    assert_eq!(result, true, "Chained boolean operation result should be true");
+    assert!(result, "Chained boolean operation result should be true");
 }
 
 #[tokio::test]
@@ -87,6 +93,7 @@ async fn test_complex_integer_operations() {
     // Generate keys
     let key_gen_request = Request::new(KeyGenerationRequest {
         parameter_set: 0, // DEFAULT
+        ..Default::default()
     });
     
     let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
@@ -98,6 +105,7 @@ async fn test_complex_integer_operations() {
         client_key_id: client_key_id.clone(),
         value: 5,
         num_bits: 8,
+        ..Default::default()
     });
     let encrypt_a_response = service.encrypt_integer(encrypt_a_request).await.unwrap();
     let a_id = encrypt_a_response.get_ref().encrypted_data_id.clone();
@@ -106,6 +114,7 @@ async fn test_complex_integer_operations() {
         client_key_id: client_key_id.clone(),
         value: 3,
         num_bits: 8,
+        ..Default::default()
     });
     let encrypt_b_response = service.encrypt_integer(encrypt_b_request).await.unwrap();
     let b_id = encrypt_b_response.get_ref().encrypted_data_id.clone();
@@ -114,6 +123,7 @@ async fn test_complex_integer_operations() {
         client_key_id: client_key_id.clone(),
         value: 2,
         num_bits: 8,
+        ..Default::default()
     });
     let encrypt_c_response = service.encrypt_integer(encrypt_c_request).await.unwrap();
     let c_id = encrypt_c_response.get_ref().encrypted_data_id.clone();
@@ -123,6 +133,7 @@ async fn test_complex_integer_operations() {
         server_key_id: server_key_id.clone(),
         operation: OperationType::Multiply as i32,
         operand_ids: vec![a_id, b_id],
+        ..Default::default()
     });
     let eval_response1 = service.evaluate_operation(eval_request1).await.unwrap();
     let intermediate_result_id = eval_response1.get_ref().result_id.clone();
@@ -132,6 +143,7 @@ async fn test_complex_integer_operations() {
         server_key_id: server_key_id.clone(),
         operation: OperationType::Subtract as i32,
         operand_ids: vec![intermediate_result_id, c_id],
+        ..Default::default()
     });
     let eval_response2 = service.evaluate_operation(eval_request2).await.unwrap();
     let final_result_id = eval_response2.get_ref().result_id.clone();
@@ -141,6 +153,7 @@ async fn test_complex_integer_operations() {
         client_key_id: client_key_id.clone(),
         encrypted_data_id: final_result_id,
         serialized_data: vec![],
+        ..Default::default()
     });
     let decrypt_response = service.decrypt_integer(decrypt_request).await.unwrap();
     let result = decrypt_response.get_ref().value;
@@ -156,6 +169,7 @@ async fn test_larger_integers() {
     // Generate keys
     let key_gen_request = Request::new(KeyGenerationRequest {
         parameter_set: 0, // DEFAULT
+        ..Default::default()
     });
     
     let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
@@ -167,6 +181,7 @@ async fn test_larger_integers() {
         client_key_id: client_key_id.clone(),
         value: value_a,
         num_bits: 8,
+        ..Default::default()
     });
     
     let encrypt_a_response = service.encrypt_integer(encrypt_a_request).await.unwrap();
@@ -177,6 +192,7 @@ async fn test_larger_integers() {
         client_key_id: client_key_id.clone(),
         encrypted_data_id,
         serialized_data: vec![],
+        ..Default::default()
     });
     
     let decrypt_response = service.decrypt_integer(decrypt_request).await.unwrap();
@@ -203,6 +219,7 @@ async fn test_with_parameter_set(parameter_set: i32) {
     // Generate keys with the specified parameter set
     let key_gen_request = Request::new(KeyGenerationRequest {
         parameter_set,
+        ..Default::default()
     });
     
     let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
@@ -217,6 +234,7 @@ async fn test_with_parameter_set(parameter_set: i32) {
         client_key_id: client_key_id.clone(),
         value: value_a,
         num_bits: 8,
+        ..Default::default()
     });
     let encrypt_a_response = service.encrypt_integer(encrypt_a_request).await.unwrap();
     let a_id = encrypt_a_response.get_ref().encrypted_data_id.clone();
@@ -225,6 +243,7 @@ async fn test_with_parameter_set(parameter_set: i32) {
         client_key_id: client_key_id.clone(),
         value: value_b,
         num_bits: 8,
+        ..Default::default()
     });
     let encrypt_b_response = service.encrypt_integer(encrypt_b_request).await.unwrap();
     let b_id = encrypt_b_response.get_ref().encrypted_data_id.clone();
@@ -234,6 +253,7 @@ async fn test_with_parameter_set(parameter_set: i32) {
         server_key_id: server_key_id.clone(),
         operation: OperationType::Add as i32,
         operand_ids: vec![a_id, b_id],
+        ..Default::default()
     });
     let eval_response = service.evaluate_operation(eval_request).await.unwrap();
     let result_id = eval_response.get_ref().result_id.clone();
@@ -243,6 +263,7 @@ async fn test_with_parameter_set(parameter_set: i32) {
         client_key_id: client_key_id.clone(),
         encrypted_data_id: result_id,
         serialized_data: vec![],
+        ..Default::default()
     });
     let decrypt_response = service.decrypt_integer(decrypt_request).await.unwrap();
     let result = decrypt_response.get_ref().value;
@@ -257,6 +278,7 @@ async fn test_xor_operation() {
     // Generate keys
     let key_gen_request = Request::new(KeyGenerationRequest {
         parameter_set: 0, // DEFAULT
+        ..Default::default()
     });
     
     let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
@@ -276,6 +298,7 @@ async fn test_xor_operation() {
         let encrypt_a_request = Request::new(EncryptBooleanRequest {
             client_key_id: client_key_id.clone(),
             value: a_val,
+            ..Default::default()
         });
         let encrypt_a_response = service.encrypt_boolean(encrypt_a_request).await.unwrap();
         let a_id = encrypt_a_response.get_ref().encrypted_data_id.clone();
@@ -284,6 +307,7 @@ async fn test_xor_operation() {
         let encrypt_b_request = Request::new(EncryptBooleanRequest {
             client_key_id: client_key_id.clone(),
             value: b_val,
+            ..Default::default()
         });
         let encrypt_b_response = service.encrypt_boolean(encrypt_b_request).await.unwrap();
         let b_id = encrypt_b_response.get_ref().encrypted_data_id.clone();
@@ -293,6 +317,7 @@ async fn test_xor_operation() {
             server_key_id: server_key_id.clone(),
             operation: OperationType::Xor as i32,
             operand_ids: vec![a_id, b_id],
+            ..Default::default()
         });
         let eval_response = service.evaluate_operation(eval_request).await.unwrap();
         let result_id = eval_response.get_ref().result_id.clone();
@@ -0,0 +1,123 @@
+use std::sync::Arc;
+use tonic::Request;
+
+use hermetic_fhe::api::{
+    increment_counter_request::Delta, CreateCounterRequest, DecryptIntegerRequest,
+    DeleteCounterRequest, EncryptIntegerRequest, FheService, IncrementCounterRequest,
+    KeyGenerationRequest, ReadCounterRequest,
+};
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::errors::ErrorReason;
+use hermetic_fhe::service::FheServiceImpl;
+
+async fn setup_service() -> FheServiceImpl {
+    let key_store = Arc::new(KeyStore::new());
+    let ciphertext_store = Arc::new(CiphertextStore::new());
+    FheServiceImpl::new(key_store, ciphertext_store)
+}
+
+async fn increment(service: &FheServiceImpl, counter_id: &str, delta: Delta) -> u64 {
+    let request = Request::new(IncrementCounterRequest {
+        counter_id: counter_id.to_string(),
+        delta: Some(delta),
+    });
+    service.increment_counter(request).await.unwrap().into_inner().increments
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_increments() {
+    let service = setup_service().await;
+    
+    let key_gen_request = Request::new(KeyGenerationRequest {
+        parameter_set: 0, // DEFAULT
+        ..Default::default()
+    });
+    let keys = service.generate_keys(key_gen_request).await.unwrap().into_inner();
+    
+    let request = Request::new(CreateCounterRequest {
+        server_key_id: keys.server_key_id.clone(),
+        ..Default::default()
+    });
+    let counter_id = service.create_counter(request).await.unwrap().into_inner().counter_id;
+    
+    let request = Request::new(EncryptIntegerRequest {
+        client_key_id: keys.client_key_id.clone(),
+        value: 5,
+        num_bits: 8,
+        ..Default::default()
+    });
+    let delta_id = service.encrypt_integer(request).await.unwrap().into_inner().encrypted_data_id;
+    
+    // Plaintext and encrypted increments racing on one counter must all land
+    let mut tasks = Vec::new();
+    for i in 0..6 {
+        let (service, counter_id) = (service.clone(), counter_id.clone());
+        let delta = if i % 2 == 0 { Delta::Amount(1) } else { Delta::DeltaId(delta_id.clone()) };
+        tasks.push(tokio::spawn(async move { increment(&service, &counter_id, delta).await }));
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+    
+    let request = Request::new(ReadCounterRequest {
+        counter_id: counter_id.clone(),
+        ..Default::default()
+    });
+    let read = service.read_counter(request).await.unwrap().into_inner();
+    assert_eq!(read.increments, 6);
+    assert!(!read.value_fingerprint.is_empty());
+    
+    let decrypt_request = Request::new(DecryptIntegerRequest {
+        client_key_id: keys.client_key_id,
+        encrypted_data_id: read.value_id,
+        serialized_data: vec![],
+        ..Default::default()
+    });
+    let value = service.decrypt_integer(decrypt_request).await.unwrap().into_inner().value;
+    assert_eq!(value, 3 + 3 * 5, "Three increments of 1 and three of 5");
+}
+
+#[tokio::test]
+async fn test_counter_errors() {
+    let service = setup_service().await;
+    
+    let key_gen_request = Request::new(KeyGenerationRequest {
+        parameter_set: 0, // DEFAULT
+        ..Default::default()
+    });
+    let keys = service.generate_keys(key_gen_request).await.unwrap().into_inner();
+    
+    let request = Request::new(CreateCounterRequest {
+        server_key_id: keys.server_key_id,
+        ..Default::default()
+    });
+    let counter_id = service.create_counter(request).await.unwrap().into_inner().counter_id;
+    
+    // Amounts must fit the counter's width
+    let request = Request::new(IncrementCounterRequest {
+        counter_id: counter_id.clone(),
+        delta: Some(Delta::Amount(300)),
+    });
+    let status = service.increment_counter(request).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::ValueOutOfRange));
+    
+    let request = Request::new(IncrementCounterRequest {
+        counter_id: counter_id.clone(),
+        delta: None,
+    });
+    let status = service.increment_counter(request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    
+    let request = Request::new(DeleteCounterRequest {
+        counter_id: counter_id.clone(),
+    });
+    service.delete_counter(request).await.unwrap();
+    
+    let request = Request::new(ReadCounterRequest {
+        counter_id,
+        ..Default::default()
+    });
+    let status = service.read_counter(request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::NotFound);
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::CounterNotFound));
+}
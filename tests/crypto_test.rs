@@ -1,5 +1,18 @@
-use hermetic_fhe::crypto::{KeyStore, CiphertextStore, operations};
-use tfhe::{FheBool, FheUint8, prelude::FheTryEncrypt, prelude::FheDecrypt};
+use std::sync::Arc;
+use hermetic_fhe::crypto::{KeyStore, KeyPolicy, Ciphertext, CiphertextKind, CiphertextStore, operations};
+use hermetic_fhe::crypto::canonical;
+use hermetic_fhe::crypto::ckks::{self, CkksCiphertext};
+use hermetic_fhe::crypto::compression::CompressionConfig;
+use hermetic_fhe::crypto::deterministic::Determinism;
+use hermetic_fhe::crypto::envelope::{self, MasterKey};
+use hermetic_fhe::crypto::key_directory::{KeyDirectory, KeyPreload};
+use hermetic_fhe::crypto::key_pool::KeyPoolConfig;
+use hermetic_fhe::crypto::retention::RetentionConfig;
+use hermetic_fhe::crypto::matrix::EncryptedMatrix;
+use hermetic_fhe::crypto::kms::{EnvMasterKeyProvider, FileMasterKeyProvider, MasterKeyProvider};
+use hermetic_fhe::crypto::fingerprint::verify_fingerprint;
+use hermetic_fhe::crypto::sharded::ShardedMap;
+use tfhe::{FheBool, FheUint8, prelude::FheTryEncrypt, prelude::FheTryTrivialEncrypt, prelude::FheDecrypt};
 
 #[test]
 fn test_key_generation() {
@@ -49,11 +62,118 @@ fn test_ciphertext_store() {
     assert!(retrieved.is_some(), "Stored ciphertext should be retrievable");
     
     let decrypted_value = retrieved.unwrap().decrypt(client_key_ref);
-    assert_eq!(decrypted_value, true, "Decrypted value should match the original");
+    assert!(decrypted_value, "Decrypted value should match the original");
     
     // Test with nonexistent ID
     let not_found = ciphertext_store.get_boolean("nonexistent-id");
     assert!(not_found.is_none(), "Nonexistent ciphertext ID should return None");
+    
+    // Reads share the stored ciphertext instead of copying it
+    let first = ciphertext_store.get_boolean(&id).unwrap();
+    let second = ciphertext_store.get_boolean(&id).unwrap();
+    assert!(Arc::ptr_eq(&first, &second), "Reads should share one ciphertext");
+}
+
+#[test]
+fn test_ciphertext_store_tracks_kinds() {
+    let key_store = KeyStore::new();
+    let ciphertext_store = CiphertextStore::new();
+    
+    let (client_key_id, _) = key_store.generate_keys("DEFAULT").unwrap();
+    let client_key = key_store.get_client_key(&client_key_id).unwrap();
+    
+    let boolean_id = ciphertext_store.store_boolean(FheBool::try_encrypt(true, &*client_key).unwrap());
+    let integer_id = ciphertext_store.store_integer(FheUint8::try_encrypt(7u8, &*client_key).unwrap());
+    assert_eq!(ciphertext_store.kind(&boolean_id), Some(CiphertextKind::Boolean));
+    assert_eq!(ciphertext_store.kind(&integer_id), Some(CiphertextKind::Integer));
+    assert_eq!(ciphertext_store.kind("nonexistent-id"), None);
+    
+    // Removing an ID drops its value and metadata together
+    assert!(ciphertext_store.remove(&integer_id));
+    assert_eq!(ciphertext_store.kind(&integer_id), None);
+    assert!(ciphertext_store.get_integer(&integer_id).is_none());
+    assert!(!ciphertext_store.remove(&integer_id), "Second removal should find nothing");
+    assert_eq!(ciphertext_store.len(), 1);
+}
+
+#[test]
+fn test_soft_deleted_ciphertexts_are_purged_after_retention() {
+    let key_store = KeyStore::new();
+    let retention = RetentionConfig {
+        retention: std::time::Duration::from_millis(200),
+    };
+    let ciphertext_store = CiphertextStore::new().with_retention(retention);
+    
+    let (client_key_id, _) = key_store.generate_keys("DEFAULT").unwrap();
+    let client_key = key_store.get_client_key(&client_key_id).unwrap();
+    let id = ciphertext_store.store_integer(FheUint8::try_encrypt(7u8, &*client_key).unwrap());
+    let bytes = ciphertext_store.memory_bytes();
+    
+    // Deleted values vanish from lookups but are still held, and can come back
+    assert!(ciphertext_store.soft_delete(&id));
+    assert!(ciphertext_store.get_integer(&id).is_none());
+    assert_eq!(ciphertext_store.len(), 0);
+    assert_eq!(ciphertext_store.memory_bytes(), bytes);
+    let deleted = ciphertext_store.list_deleted();
+    assert_eq!(deleted.len(), 1);
+    assert_eq!((deleted[0].id.as_str(), deleted[0].kind), (id.as_str(), CiphertextKind::Integer));
+    assert_eq!(ciphertext_store.purge_deleted(), 0, "Nothing is purged inside the window");
+    assert!(ciphertext_store.undelete(&id));
+    assert!(ciphertext_store.get_integer(&id).is_some());
+    assert!(!ciphertext_store.undelete(&id), "A live value has nothing to undelete");
+    
+    // Once the window passes, a purge frees it for good
+    assert!(ciphertext_store.soft_delete(&id));
+    std::thread::sleep(retention.retention);
+    assert_eq!(ciphertext_store.purge_deleted(), 1);
+    assert_eq!(ciphertext_store.memory_bytes(), 0);
+    assert!(ciphertext_store.list_deleted().is_empty());
+    assert!(!ciphertext_store.undelete(&id));
+    
+    // With no window, deletion frees at once
+    let immediate = CiphertextStore::new().with_retention(RetentionConfig {
+        retention: std::time::Duration::ZERO,
+    });
+    let id = immediate.store_integer(FheUint8::try_encrypt(7u8, &*client_key).unwrap());
+    assert!(immediate.soft_delete(&id));
+    assert!(immediate.list_deleted().is_empty());
+    assert_eq!(immediate.memory_bytes(), 0);
+}
+
+#[test]
+fn test_ciphertext_store_single_map() {
+    let key_store = KeyStore::new();
+    let ciphertext_store = CiphertextStore::new();
+    
+    let (client_key_id, _) = key_store.generate_keys("DEFAULT").unwrap();
+    let client_key = key_store.get_client_key(&client_key_id).unwrap();
+    
+    let boolean_id = ciphertext_store.store(FheBool::try_encrypt(false, &*client_key).unwrap());
+    let integer_id = ciphertext_store.store(FheUint8::try_encrypt(9u8, &*client_key).unwrap());
+    assert!(matches!(ciphertext_store.get(&boolean_id), Some(Ciphertext::Boolean(_))));
+    assert_eq!(ciphertext_store.get(&integer_id).unwrap().kind(), CiphertextKind::Integer);
+    
+    // Typed lookups only find values of their own kind
+    assert!(ciphertext_store.get_integer(&boolean_id).is_none());
+    assert!(ciphertext_store.get_boolean(&integer_id).is_none());
+    
+    let mut expected = vec![
+        (boolean_id.clone(), CiphertextKind::Boolean),
+        (integer_id.clone(), CiphertextKind::Integer),
+    ];
+    expected.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(ciphertext_store.list(), expected);
+    assert!(ciphertext_store.get_fingerprint(&boolean_id).is_some());
+    
+    // Replacing keeps the ID but not the old value, and never changes the kind
+    let before = ciphertext_store.get_integer(&integer_id).unwrap();
+    let fingerprint = ciphertext_store.get_fingerprint(&integer_id);
+    assert!(ciphertext_store.replace(&integer_id, FheUint8::try_encrypt(10u8, &*client_key).unwrap()));
+    assert_ne!(ciphertext_store.get_fingerprint(&integer_id), fingerprint);
+    let decrypted: u8 = before.decrypt(&*client_key);
+    assert_eq!(decrypted, 9, "Readers of the old value should be unaffected");
+    assert!(!ciphertext_store.replace(&integer_id, FheBool::try_encrypt(true, &*client_key).unwrap()));
+    assert!(!ciphertext_store.replace("nonexistent-id", FheBool::try_encrypt(true, &*client_key).unwrap()));
 }
 
 #[test]
@@ -76,22 +196,22 @@ fn test_boolean_operations() {
     // Test AND operation
     let and_result = operations::boolean_and(&server_key, &true_cipher, &false_cipher);
     let decrypted_and = and_result.decrypt(client_key_ref);
-    assert_eq!(decrypted_and, false, "true AND false should be false");
+    assert!(!decrypted_and, "true AND false should be false");
     
     // Test OR operation
     let or_result = operations::boolean_or(&server_key, &true_cipher, &false_cipher);
     let decrypted_or = or_result.decrypt(client_key_ref);
-    assert_eq!(decrypted_or, true, "true OR false should be true");
+    assert!(decrypted_or, "true OR false should be true");
     
     // Test XOR operation
     let xor_result = operations::boolean_xor(&server_key, &true_cipher, &false_cipher);
     let decrypted_xor = xor_result.decrypt(client_key_ref);
-    assert_eq!(decrypted_xor, true, "true XOR false should be true");
+    assert!(decrypted_xor, "true XOR false should be true");
     
     // Test NOT operation
     let not_result = operations::boolean_not(&server_key, &true_cipher);
     let decrypted_not = not_result.decrypt(client_key_ref);
-    assert_eq!(decrypted_not, false, "NOT true should be false");
+    assert!(!decrypted_not, "NOT true should be false");
 }
 
 #[test]
@@ -125,4 +245,479 @@ fn test_integer_operations() {
     let mul_result = operations::integer_multiply(&a, &b);
     let decrypted_mul = <FheUint8 as FheDecrypt<u8>>::decrypt(&mul_result, client_key_ref);
     assert_eq!(decrypted_mul, 15u8, "5 * 3 should be 15");
-} 
\ No newline at end of file
+} 
+#[test]
+fn test_cold_ciphertexts_are_compressed() {
+    let key_store = KeyStore::new();
+    let config = CompressionConfig { level: 3, cold_after: std::time::Duration::from_secs(60) };
+    let ciphertext_store = CiphertextStore::new().with_compression(config);
+    
+    let (client_key_id, server_key_id) = key_store.generate_keys("DEFAULT").unwrap();
+    let client_key = key_store.get_client_key(&client_key_id).unwrap();
+    tfhe::set_server_key((*key_store.get_server_key(&server_key_id).unwrap()).clone());
+    
+    // A trivial encryption has an all-zero mask, so it is sure to compress
+    let id = ciphertext_store.store_integer(FheUint8::try_encrypt_trivial(42u8).unwrap());
+    let fingerprint = ciphertext_store.get_fingerprint(&id).unwrap();
+    let stored_bytes = ciphertext_store.memory_bytes();
+    
+    // Freshly stored values survive one sweep; the next finds them cold
+    assert_eq!(ciphertext_store.compress_cold(), 0);
+    assert_eq!(ciphertext_store.is_compressed(&id), Some(false));
+    assert_eq!(ciphertext_store.compress_cold(), 1);
+    assert_eq!(ciphertext_store.is_compressed(&id), Some(true));
+    assert!(ciphertext_store.memory_bytes() < stored_bytes, "Compressed entries should count their compressed size");
+    assert_eq!(ciphertext_store.kind(&id), Some(CiphertextKind::Integer));
+    assert_eq!(ciphertext_store.get_fingerprint(&id), Some(fingerprint));
+    
+    // Reading decompresses transparently and keeps the value uncompressed
+    let value: u8 = ciphertext_store.get_integer(&id).unwrap().decrypt(&*client_key);
+    assert_eq!(value, 42);
+    assert_eq!(ciphertext_store.is_compressed(&id), Some(false));
+    assert_eq!(ciphertext_store.memory_bytes(), stored_bytes);
+    
+    // A value read since the last sweep isn't cold
+    assert_eq!(ciphertext_store.compress_cold(), 0);
+    
+    // Without a compression config nothing is compressed
+    let plain_store = CiphertextStore::new();
+    let plain_id = plain_store.store_integer(FheUint8::try_encrypt_trivial(42u8).unwrap());
+    plain_store.compress_cold();
+    assert_eq!(plain_store.compress_cold(), 0);
+    assert_eq!(plain_store.is_compressed(&plain_id), Some(false));
+}
+
+#[test]
+fn test_fingerprints() {
+    let key_store = KeyStore::new();
+    let ciphertext_store = CiphertextStore::new();
+    
+    // Both keys should be fingerprinted at generation time
+    let (client_key_id, server_key_id) = key_store.generate_keys("DEFAULT").unwrap();
+    let client_fingerprint = key_store.get_fingerprint(&client_key_id).unwrap();
+    let server_fingerprint = key_store.get_fingerprint(&server_key_id).unwrap();
+    assert_eq!(client_fingerprint.len(), 64, "Fingerprint should be a hex SHA-256 digest");
+    assert_ne!(client_fingerprint, server_fingerprint, "Keys should have distinct fingerprints");
+    
+    // A stored ciphertext's fingerprint should match its serialized bytes
+    let client_key = key_store.get_client_key(&client_key_id).unwrap();
+    let ciphertext = FheBool::try_encrypt(true, &*client_key).unwrap();
+    let id = ciphertext_store.store_boolean(ciphertext);
+    
+    let (bytes, fingerprint) = canonical::encode_with_fingerprint(&*ciphertext_store.get_boolean(&id).unwrap()).unwrap();
+    assert_eq!(ciphertext_store.get_fingerprint(&id), Some(fingerprint.clone()));
+    assert!(verify_fingerprint(&bytes, &fingerprint).is_ok(), "Intact bytes should verify");
+    
+    // Flipping a single byte must be detected
+    let mut corrupted = bytes.clone();
+    let last = corrupted.len() - 1;
+    corrupted[last] ^= 0x01;
+    assert!(verify_fingerprint(&corrupted, &fingerprint).is_err(), "Corrupted bytes should not verify");
+}
+
+// Generate a key pair and store one encryption under a store pair seeded with `seed`
+fn seeded_run(seed: u64) -> (String, String, String, String) {
+    let determinism = Arc::new(Determinism::new(seed));
+    let key_store = KeyStore::new().with_determinism(determinism.clone());
+    let ciphertext_store = CiphertextStore::new().with_determinism(determinism.clone());
+    
+    let (client_key_id, server_key_id) = key_store.generate_keys("DEFAULT").unwrap();
+    let client_key = key_store.get_client_key(&client_key_id).unwrap();
+    key_store.reseed_thread();
+    let id = ciphertext_store.store_integer(FheUint8::try_encrypt(42u8, &*client_key).unwrap());
+    assert_eq!(determinism.draws(), 5, "Keys, their IDs, the encryption and its ID each draw once");
+    
+    let fingerprint = ciphertext_store.get_fingerprint(&id).unwrap();
+    (server_key_id, key_store.get_fingerprint(&server_key_id).unwrap(), id, fingerprint)
+}
+
+#[test]
+fn test_deterministic_replay() {
+    // The same seed reproduces the same IDs, keys and ciphertexts
+    let original = seeded_run(7);
+    assert_eq!(seeded_run(7), original);
+    
+    let other = seeded_run(8);
+    assert_ne!(other.0, original.0, "Another seed should name keys differently");
+    assert_ne!(other.1, original.1, "Another seed should generate different keys");
+    assert_ne!(other.3, original.3, "Another seed should encrypt differently");
+}
+
+#[test]
+fn test_client_keys_sealed_at_rest() {
+    let key_store = KeyStore::with_master_key(MasterKey::from_bytes([7u8; 32]));
+    let (client_key_id, _) = key_store.generate_keys("DEFAULT").unwrap();
+    
+    // The stored blob only opens with the right master key and key ID
+    let sealed = key_store.get_sealed_client_key(&client_key_id).unwrap();
+    let opened = envelope::open(&MasterKey::from_bytes([7u8; 32]), &client_key_id, &sealed);
+    assert!(opened.is_ok(), "Sealed key should open with the configuring master key");
+    
+    let wrong_master = envelope::open(&MasterKey::from_bytes([8u8; 32]), &client_key_id, &sealed);
+    assert!(wrong_master.is_err(), "Sealed key should not open with a different master key");
+    
+    let wrong_id = envelope::open(&MasterKey::from_bytes([7u8; 32]), "other-key", &sealed);
+    assert!(wrong_id.is_err(), "Sealed key should be bound to its key ID");
+    
+    // Unsealing through the store still yields a working key
+    let client_key = key_store.get_client_key(&client_key_id).unwrap();
+    let ciphertext = FheBool::try_encrypt(true, &*client_key).unwrap();
+    assert!(ciphertext.decrypt(&client_key), "Unsealed key should encrypt and decrypt");
+}
+
+#[test]
+fn test_master_key_from_hex() {
+    assert!(MasterKey::from_hex(&"ab".repeat(32)).is_ok(), "64 hex characters should parse");
+    assert!(MasterKey::from_hex("abcd").is_err(), "Short keys should be rejected");
+    assert!(MasterKey::from_hex(&"zz".repeat(32)).is_err(), "Non-hex keys should be rejected");
+    // 64 bytes, but a slice at byte 2 would fall inside the first 'é'
+    let multi_byte = format!("a{}a", "é".repeat(31));
+    assert!(MasterKey::from_hex(&multi_byte).is_err(), "Multi-byte characters should be rejected");
+    assert!(MasterKey::from_hex(&"+f".repeat(32)).is_err(), "Signs should be rejected");
+}
+
+#[test]
+fn test_master_key_providers() {
+    let hex = "11".repeat(32);
+    
+    // Environment provider reads hex from the named variable
+    std::env::set_var("CRYPTO_TEST_MASTER_KEY", &hex);
+    let env_key = EnvMasterKeyProvider::new("CRYPTO_TEST_MASTER_KEY").master_key().unwrap();
+    assert!(EnvMasterKeyProvider::new("CRYPTO_TEST_UNSET_KEY").master_key().is_err(), "Missing variable should be an error");
+    
+    // File provider accepts both hex and raw bytes
+    let hex_path = std::env::temp_dir().join(format!("hermetic-fhe-hex-{}", std::process::id()));
+    let raw_path = std::env::temp_dir().join(format!("hermetic-fhe-raw-{}", std::process::id()));
+    std::fs::write(&hex_path, format!("{}\n", hex)).unwrap();
+    std::fs::write(&raw_path, [0x11u8; 32]).unwrap();
+    let hex_file_key = FileMasterKeyProvider::new(&hex_path).master_key().unwrap();
+    let raw_file_key = FileMasterKeyProvider::new(&raw_path).master_key().unwrap();
+    std::fs::remove_file(&hex_path).unwrap();
+    std::fs::remove_file(&raw_path).unwrap();
+    
+    // All three describe the same key, so signatures must agree
+    let signature = env_key.sign(b"payload");
+    assert!(hex_file_key.verify_signature(b"payload", &signature).is_ok());
+    assert!(raw_file_key.verify_signature(b"payload", &signature).is_ok());
+    assert!(raw_file_key.verify_signature(b"tampered", &signature).is_err(), "Signature should cover the payload");
+}
+
+#[test]
+fn test_key_bundle_transfer() {
+    let source = KeyStore::with_master_key(MasterKey::from_bytes([3u8; 32]));
+    let (client_key_id, server_key_id) = source.generate_keys("DEFAULT").unwrap();
+    let bundle = source.export_key_bundle(&client_key_id, &server_key_id).unwrap();
+    
+    // A store with a different master key must reject the bundle
+    let stranger = KeyStore::with_master_key(MasterKey::from_bytes([4u8; 32]));
+    assert!(stranger.import_key_bundle(bundle.clone()).is_err(), "Bundle signature should not verify under another master key");
+    
+    // Tampering with the bundle invalidates the signature
+    let mut tampered = bundle.clone();
+    tampered.server_key_id = "swapped".to_string();
+    let destination = KeyStore::with_master_key(MasterKey::from_bytes([3u8; 32]));
+    assert!(destination.import_key_bundle(tampered).is_err(), "Tampered bundle should be rejected");
+    
+    // The intact bundle installs a working key pair with the same fingerprints
+    destination.import_key_bundle(bundle).unwrap();
+    assert_eq!(destination.get_fingerprint(&client_key_id), source.get_fingerprint(&client_key_id));
+    assert_eq!(destination.get_fingerprint(&server_key_id), source.get_fingerprint(&server_key_id));
+    let client_key = destination.get_client_key(&client_key_id).unwrap();
+    let ciphertext = FheBool::try_encrypt(false, &*client_key).unwrap();
+    assert!(!ciphertext.decrypt(&client_key), "Imported client key should decrypt");
+}
+
+
+#[test]
+fn test_sharded_map_under_concurrency() {
+    let map = Arc::new(ShardedMap::<usize>::new());
+    
+    // Writers on separate keys spread across shards without losing entries
+    let writers: Vec<_> = (0..8)
+        .map(|thread| {
+            let map = map.clone();
+            std::thread::spawn(move || {
+                for i in 0..100 {
+                    map.insert(format!("{}-{}", thread, i), i);
+                }
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+    
+    assert_eq!(map.len(), 800);
+    assert_eq!(map.get("3-42"), Some(42));
+    assert_eq!(map.remove("3-42"), Some(42));
+    assert_eq!(map.get("3-42"), None);
+    
+    let metrics = map.metrics();
+    assert!(metrics.acquisitions >= 800, "Every access should be counted");
+    assert!(metrics.contended <= metrics.acquisitions);
+}
+
+#[test]
+fn test_store_lock_metrics() {
+    let key_store = KeyStore::new();
+    let ciphertext_store = CiphertextStore::new();
+    assert!(key_store.is_empty() && ciphertext_store.is_empty());
+    
+    let (client_key_id, _) = key_store.generate_keys("DEFAULT").unwrap();
+    let client_key = key_store.get_client_key(&client_key_id).unwrap();
+    let id = ciphertext_store.store_integer(FheUint8::try_encrypt(7u8, &*client_key).unwrap());
+    
+    assert_eq!(key_store.len(), 2, "A client and a server key should be held");
+    assert_eq!(ciphertext_store.len(), 1);
+    assert!(key_store.lock_metrics().acquisitions > 0);
+    
+    let before = ciphertext_store.lock_metrics().acquisitions;
+    ciphertext_store.get_integer(&id).unwrap();
+    assert!(ciphertext_store.lock_metrics().acquisitions > before, "Reads should be counted");
+}
+
+#[test]
+fn test_key_directory_preload() {
+    let path = std::env::temp_dir().join(format!("hermetic-fhe-keys-{}", uuid::Uuid::new_v4()));
+    
+    // Generated key pairs are written to the directory
+    let source = KeyStore::with_master_key(MasterKey::from_bytes([5u8; 32]))
+        .with_key_directory(KeyDirectory::open(&path).unwrap());
+    let (client_key_id, server_key_id) = source.generate_keys("DEFAULT").unwrap();
+    let (_, other_server_key_id) = source.generate_keys("DEFAULT").unwrap();
+    
+    let mut stored = vec![server_key_id.clone(), other_server_key_id.clone()];
+    stored.sort();
+    assert_eq!(KeyDirectory::open(&path).unwrap().server_key_ids().unwrap(), stored);
+    
+    // A restarted store loads only the selected pair
+    let restarted = KeyStore::with_master_key(MasterKey::from_bytes([5u8; 32]))
+        .with_key_directory(KeyDirectory::open(&path).unwrap());
+    let loaded = restarted.preload(&KeyPreload::Only(vec![server_key_id.clone()])).unwrap();
+    assert_eq!(loaded, vec![server_key_id.clone()]);
+    assert!(restarted.get_server_key(&server_key_id).is_some());
+    assert!(restarted.get_client_key(&client_key_id).is_some(), "Client key should come with its pair");
+    assert!(restarted.get_server_key(&other_server_key_id).is_none());
+    assert!(restarted.warm(&loaded).unwrap() > 0);
+    
+    // "all" picks up the rest
+    assert_eq!(restarted.preload(&KeyPreload::parse("all")).unwrap().len(), 2);
+    assert!(restarted.get_server_key(&other_server_key_id).is_some());
+    
+    // IDs that are not key IDs never reach the filesystem
+    assert!(restarted.preload(&KeyPreload::parse("../escape")).is_err());
+    
+    std::fs::remove_dir_all(&path).unwrap();
+}
+
+#[test]
+fn test_subject_keys_persist_until_shredded() {
+    let path = std::env::temp_dir().join(format!("hermetic-fhe-keys-{}", uuid::Uuid::new_v4()));
+    let open_store = || {
+        KeyStore::with_master_key(MasterKey::from_bytes([5u8; 32]))
+            .with_key_directory(KeyDirectory::open(&path).unwrap())
+    };
+    
+    // Any subject ID is safe, since key files are named by its hex
+    let subject_id = "../alice@example.com";
+    let sealed = open_store().seal_for_subject(subject_id, b"ciphertext bytes").unwrap();
+    let restarted = open_store();
+    assert_eq!(restarted.open_for_subject(subject_id, &sealed).unwrap(), b"ciphertext bytes");
+    assert!(restarted.open_for_subject("bob", &sealed).is_err(), "Data is bound to its subject");
+    
+    // Shredding removes the key from disk too, so no later store can open the data
+    assert!(restarted.shred_subject(subject_id).unwrap());
+    assert!(restarted.open_for_subject(subject_id, &sealed).is_err());
+    assert!(open_store().open_for_subject(subject_id, &sealed).is_err());
+    assert!(!open_store().shred_subject(subject_id).unwrap());
+    
+    std::fs::remove_dir_all(&path).unwrap();
+}
+
+#[test]
+fn test_idle_server_keys_unload_and_reload() {
+    let path = std::env::temp_dir().join(format!("hermetic-fhe-keys-{}", uuid::Uuid::new_v4()));
+    let key_store = KeyStore::with_master_key(MasterKey::from_bytes([6u8; 32]))
+        .with_key_directory(KeyDirectory::open(&path).unwrap());
+    let (client_key_id, idle_id) = key_store.generate_keys("DEFAULT").unwrap();
+    let (_, busy_id) = key_store.generate_keys("DEFAULT").unwrap();
+    let loaded_bytes = key_store.memory_bytes();
+    
+    // Fresh keys count as used, so the first sweep only starts their clocks
+    assert_eq!(key_store.unload_idle(), 0);
+    key_store.get_server_key(&busy_id).unwrap();
+    assert_eq!(key_store.unload_idle(), 1, "Only the key unused since the last sweep should go");
+    assert_eq!(key_store.is_loaded(&idle_id), Some(false));
+    assert_eq!(key_store.is_loaded(&busy_id), Some(true));
+    assert!(key_store.memory_bytes() < loaded_bytes);
+    
+    // Its metadata stays, and exporting it doesn't load it
+    assert_eq!(key_store.key_pairs().len(), 2);
+    assert!(key_store.get_fingerprint(&idle_id).is_some());
+    assert!(key_store.export_key_bundle(&client_key_id, &idle_id).is_ok());
+    assert_eq!(key_store.is_loaded(&idle_id), Some(false));
+    
+    // The next use reloads it, and it works as before
+    let server_key = key_store.get_server_key(&idle_id).expect("Unloaded key should reload");
+    assert_eq!(key_store.is_loaded(&idle_id), Some(true));
+    assert_eq!(key_store.memory_bytes(), loaded_bytes);
+    let client_key = key_store.get_client_key(&client_key_id).unwrap();
+    tfhe::set_server_key((*server_key).clone());
+    let a = FheBool::try_encrypt(true, &*client_key).unwrap();
+    let b = FheBool::try_encrypt(false, &*client_key).unwrap();
+    assert!(operations::boolean_or(&server_key, &a, &b).decrypt(&client_key));
+    
+    // Without a key directory nothing is ever unloaded
+    let in_memory = KeyStore::new();
+    let (_, server_key_id) = in_memory.generate_keys("DEFAULT").unwrap();
+    in_memory.unload_idle();
+    assert_eq!(in_memory.unload_idle(), 0);
+    assert_eq!(in_memory.is_loaded(&server_key_id), Some(true));
+    
+    std::fs::remove_dir_all(&path).unwrap();
+}
+
+#[test]
+fn test_key_policy_travels_with_bundle() {
+    let path = std::env::temp_dir().join(format!("hermetic-fhe-keys-{}", uuid::Uuid::new_v4()));
+    let policy = KeyPolicy {
+        integer_bits: 8,
+        deny_booleans: true,
+        ..Default::default()
+    };
+    let source = KeyStore::with_master_key(MasterKey::from_bytes([7u8; 32]))
+        .with_key_directory(KeyDirectory::open(&path).unwrap());
+    let (client_key_id, server_key_id) = source.generate_keys_with_policy("DEFAULT", policy).unwrap();
+    let (_, unrestricted_id) = source.generate_keys("DEFAULT").unwrap();
+    assert_eq!(source.policy(&client_key_id), policy);
+    assert_eq!(source.policy(&server_key_id), policy);
+    assert_eq!(source.policy(&unrestricted_id), KeyPolicy::default());
+    
+    // A restart reads it back from the key directory
+    let restarted = KeyStore::with_master_key(MasterKey::from_bytes([7u8; 32]))
+        .with_key_directory(KeyDirectory::open(&path).unwrap());
+    restarted.preload(&KeyPreload::All).unwrap();
+    assert_eq!(restarted.policy(&client_key_id), policy);
+    assert_eq!(restarted.policy(&unrestricted_id), KeyPolicy::default());
+    
+    // The policy is signed, so it can't be lifted by editing the bundle
+    let mut lifted = source.export_key_bundle(&client_key_id, &server_key_id).unwrap();
+    lifted.policy = KeyPolicy::default();
+    let destination = KeyStore::with_master_key(MasterKey::from_bytes([7u8; 32]));
+    assert!(destination.import_key_bundle(lifted).is_err(), "Bundle with its policy removed should be rejected");
+    
+    std::fs::remove_dir_all(&path).unwrap();
+}
+
+#[test]
+fn test_rejected_bundle_is_not_persisted() {
+    let path = std::env::temp_dir().join(format!("hermetic-fhe-keys-{}", uuid::Uuid::new_v4()));
+    let source = KeyStore::with_master_key(MasterKey::from_bytes([9u8; 32]));
+    let (client_key_id, server_key_id) = source.generate_keys("DEFAULT").unwrap();
+    let mut forged = source.export_key_bundle(&client_key_id, &server_key_id).unwrap();
+    forged.server_key.truncate(forged.server_key.len() / 2);
+    
+    let destination = KeyStore::with_master_key(MasterKey::from_bytes([9u8; 32]))
+        .with_key_directory(KeyDirectory::open(&path).unwrap());
+    assert!(destination.import_key_bundle(forged).is_err(), "Forged bundle should be rejected");
+    assert!(destination.key_pairs().is_empty(), "Nothing should be installed");
+    
+    // Nothing was written, so a restart preloading every key still starts
+    assert!(KeyDirectory::open(&path).unwrap().server_key_ids().unwrap().is_empty());
+    let restarted = KeyStore::with_master_key(MasterKey::from_bytes([9u8; 32]))
+        .with_key_directory(KeyDirectory::open(&path).unwrap());
+    assert!(restarted.preload(&KeyPreload::All).unwrap().is_empty());
+    
+    std::fs::remove_dir_all(&path).unwrap();
+}
+
+#[test]
+fn test_allowed_operations_outlive_a_restart() {
+    let path = std::env::temp_dir().join(format!("hermetic-fhe-keys-{}", uuid::Uuid::new_v4()));
+    let source = KeyStore::with_master_key(MasterKey::from_bytes([7u8; 32]))
+        .with_key_directory(KeyDirectory::open(&path).unwrap());
+    let (client_key_id, server_key_id) = source.generate_keys("DEFAULT").unwrap();
+    
+    // Bit 4 is ADD; everything else is refused
+    let policy = source.set_allowed_operations(&server_key_id, 1 << 4).unwrap();
+    assert!(policy.allows_operation(4));
+    assert!(!policy.allows_operation(10));
+    assert_eq!(source.policy(&client_key_id), policy);
+    
+    let restarted = KeyStore::with_master_key(MasterKey::from_bytes([7u8; 32]))
+        .with_key_directory(KeyDirectory::open(&path).unwrap());
+    restarted.preload(&KeyPreload::All).unwrap();
+    assert_eq!(restarted.policy(&server_key_id), policy);
+    
+    // Lifting the list leaves an unrestricted pair, signed as before operation lists
+    source.set_allowed_operations(&server_key_id, 0).unwrap();
+    assert_eq!(source.policy(&server_key_id), KeyPolicy::default());
+    let bundle = source.export_key_bundle(&client_key_id, &server_key_id).unwrap();
+    KeyStore::with_master_key(MasterKey::from_bytes([7u8; 32])).import_key_bundle(bundle).unwrap();
+    
+    assert!(source.set_allowed_operations("nonexistent-key", 0).is_err());
+    std::fs::remove_dir_all(&path).unwrap();
+}
+
+#[test]
+fn test_key_pool_hands_out_pregenerated_pairs() {
+    let config = KeyPoolConfig::new(1, vec!["DEFAULT".to_string()]).unwrap();
+    let key_store = KeyStore::new().with_key_pool(config);
+    assert_eq!(key_store.fill_key_pool().unwrap(), 1);
+    assert_eq!(key_store.fill_key_pool().unwrap(), 0, "A full pool should not grow");
+    let pool = key_store.key_pool().unwrap();
+    assert_eq!(pool.available("DEFAULT"), 1);
+    assert!(pool.next_to_fill().is_none());
+    
+    // A pooled pair is registered like a fresh one, with the policy asked for
+    let policy = KeyPolicy {
+        deny_booleans: true,
+        ..Default::default()
+    };
+    let (client_key_id, server_key_id) = key_store.generate_keys_with_policy("DEFAULT", policy).unwrap();
+    assert_eq!(pool.available("DEFAULT"), 0);
+    assert_eq!(pool.next_to_fill().as_deref(), Some("DEFAULT"));
+    assert_eq!(key_store.policy(&server_key_id), policy);
+    assert!(key_store.get_fingerprint(&client_key_id).is_some());
+    let client_key = key_store.get_client_key(&client_key_id).unwrap();
+    let server_key = key_store.get_server_key(&server_key_id).unwrap();
+    tfhe::set_server_key((*server_key).clone());
+    let a = FheUint8::try_encrypt(20u8, &*client_key).unwrap();
+    let b = FheUint8::try_encrypt(22u8, &*client_key).unwrap();
+    let sum: u8 = operations::integer_add(&a, &b).decrypt(&client_key);
+    assert_eq!(sum, 42);
+    
+    // An empty pool, or a parameter set it doesn't keep, falls back to generating
+    let (_, other_id) = key_store.generate_keys("FAST").unwrap();
+    assert!(key_store.get_server_key(&other_id).is_some());
+    assert!(KeyPoolConfig::new(1, vec!["HUGE".to_string()]).is_err());
+}
+
+#[test]
+fn test_decoding_rejects_values_of_impossible_shape() {
+    // A matrix whose dimensions don't match its elements
+    let key_store = KeyStore::new();
+    let (client_key_id, _) = key_store.generate_keys("DEFAULT").unwrap();
+    let client_key = key_store.get_client_key(&client_key_id).unwrap();
+    let elements = (0..4u8).map(|value| FheUint8::try_encrypt(value, &*client_key).unwrap()).collect();
+    let matrix = EncryptedMatrix::new(2, 2, elements).unwrap();
+    let mut bytes = canonical::encode(&matrix).unwrap();
+    assert!(canonical::decode::<EncryptedMatrix>(&bytes).is_ok());
+    // rows is the payload's first word, after the 14-byte header
+    bytes[14..22].copy_from_slice(&3u64.to_le_bytes());
+    assert!(canonical::decode::<EncryptedMatrix>(&bytes).is_err());
+    
+    // A CKKS ciphertext with a residue past its prime, or more slots than exist
+    let (secret, _) = ckks::generate_keys();
+    let ciphertext = ckks::encrypt(&secret, &[1.0, 2.0]).unwrap();
+    let bytes = canonical::encode(&ciphertext).unwrap();
+    assert!(canonical::decode::<CkksCiphertext>(&bytes).is_ok());
+    let mut residue = bytes.clone();
+    // After the header and the lengths of c0 and of its first limb
+    residue[30..38].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert!(canonical::decode::<CkksCiphertext>(&residue).is_err());
+    let mut slots = bytes.clone();
+    let end = slots.len();
+    slots[end - 8..].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert!(canonical::decode::<CkksCiphertext>(&slots).is_err());
+}
@@ -0,0 +1,92 @@
+use std::sync::Arc;
+use tonic::Request;
+
+use hermetic_fhe::api::{
+    DecryptIntegerRequest, EncryptIntegerRequest, EvaluationRequest, KeyGenerationRequest, OperationType,
+};
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::embedded;
+use hermetic_fhe::service::errors::ErrorReason;
+use hermetic_fhe::service::usage::TENANT_HEADER;
+use hermetic_fhe::service::FheServiceImpl;
+
+#[tokio::test]
+async fn test_embedded_client_calls_the_service_in_process() {
+    let key_store = Arc::new(KeyStore::new());
+    let ciphertext_store = Arc::new(CiphertextStore::new());
+    let mut client = embedded::new(key_store.clone(), ciphertext_store.clone());
+    
+    let keys = client
+        .generate_keys(KeyGenerationRequest::default())
+        .await
+        .unwrap()
+        .into_inner();
+    let mut ids = Vec::new();
+    for value in [20, 22] {
+        let request = EncryptIntegerRequest {
+            client_key_id: keys.client_key_id.clone(),
+            value,
+            num_bits: 8,
+            ..Default::default()
+        };
+        ids.push(
+            client
+                .encrypt_integer(request)
+                .await
+                .unwrap()
+                .into_inner()
+                .encrypted_data_id,
+        );
+    }
+    
+    // Metadata reaches the handlers as it would over the network
+    let mut request = Request::new(EvaluationRequest {
+        server_key_id: keys.server_key_id.clone(),
+        operation: OperationType::Add as i32,
+        operand_ids: ids,
+        ..Default::default()
+    });
+    request
+        .metadata_mut()
+        .insert(TENANT_HEADER, "acme".parse().unwrap());
+    let result_id = client
+        .evaluate_operation(request)
+        .await
+        .unwrap()
+        .into_inner()
+        .result_id;
+    
+    // A second client over the same stores sees the keys and ciphertexts of the first
+    let mut other = embedded::client(FheServiceImpl::new(key_store, ciphertext_store));
+    let request = DecryptIntegerRequest {
+        client_key_id: keys.client_key_id.clone(),
+        encrypted_data_id: result_id,
+        ..Default::default()
+    };
+    assert_eq!(
+        other.decrypt_integer(request).await.unwrap().into_inner().value,
+        42
+    );
+    
+    // Errors come back as the same statuses, with their reasons
+    let request = DecryptIntegerRequest {
+        client_key_id: keys.client_key_id,
+        encrypted_data_id: "no-such-ciphertext".to_string(),
+        ..Default::default()
+    };
+    let status = other.decrypt_integer(request).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::CiphertextNotFound));
+}
+
+#[tokio::test]
+async fn test_embedded_client_enforces_the_message_limit() {
+    let service = FheServiceImpl::new(Arc::new(KeyStore::new()), Arc::new(CiphertextStore::new()))
+        .with_max_message_bytes(1024);
+    let mut client = embedded::client(service);
+    let request = EvaluationRequest {
+        operand_ids: vec!["x".repeat(2048)],
+        ..Default::default()
+    };
+    let status = client.evaluate_operation(request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::OutOfRange);
+}
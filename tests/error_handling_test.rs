@@ -2,11 +2,12 @@ use std::sync::Arc;
 use tonic::Request;
 
 use hermetic_fhe::api::{
-    DecryptBooleanRequest, EncryptBooleanRequest, 
-    EncryptIntegerRequest, EvaluationRequest, FheService, 
-    KeyGenerationRequest, OperationType,
+    circuit_wire::Source, CiphertextType, CircuitEvaluationRequest, CircuitGate, CircuitWire,
+    DecryptBooleanRequest, EncryptBooleanRequest, EncryptIntegerRequest, EvaluationRequest,
+    FheService, ImportCiphertextRequest, KeyGenerationRequest, OperationType,
 };
 use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::errors::ErrorReason;
 use hermetic_fhe::service::FheServiceImpl;
 
 async fn setup_service() -> impl FheService {
@@ -22,6 +23,7 @@ async fn test_invalid_parameter_set() {
     // Try to generate keys with an invalid parameter set
     let request = Request::new(KeyGenerationRequest {
         parameter_set: 99, // Invalid parameter set
+        ..Default::default()
     });
     
     let response = service.generate_keys(request).await;
@@ -41,6 +43,7 @@ async fn test_client_key_not_found() {
     let encrypt_request = Request::new(EncryptBooleanRequest {
         client_key_id: "non-existent-key".to_string(),
         value: true,
+        ..Default::default()
     });
     
     let response = service.encrypt_boolean(encrypt_request).await;
@@ -48,6 +51,7 @@ async fn test_client_key_not_found() {
     
     if let Err(status) = response {
         assert_eq!(status.code(), tonic::Code::NotFound);
+        assert_eq!(ErrorReason::of(&status), Some(ErrorReason::KeyNotFound));
         assert!(status.message().contains("Client key not found"));
     }
 }
@@ -59,6 +63,7 @@ async fn test_server_key_not_found() {
     // Generate client key
     let key_gen_request = Request::new(KeyGenerationRequest {
         parameter_set: 0, // DEFAULT
+        ..Default::default()
     });
     
     let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
@@ -68,6 +73,7 @@ async fn test_server_key_not_found() {
     let encrypt_request = Request::new(EncryptBooleanRequest {
         client_key_id: client_key_id.clone(),
         value: true,
+        ..Default::default()
     });
     
     let encrypt_response = service.encrypt_boolean(encrypt_request).await.unwrap();
@@ -78,6 +84,7 @@ async fn test_server_key_not_found() {
         server_key_id: "non-existent-key".to_string(),
         operation: OperationType::Not as i32,
         operand_ids: vec![encrypted_id],
+        ..Default::default()
     });
     
     let response = service.evaluate_operation(eval_request).await;
@@ -96,6 +103,7 @@ async fn test_encrypted_data_not_found() {
     // Generate keys
     let key_gen_request = Request::new(KeyGenerationRequest {
         parameter_set: 0, // DEFAULT
+        ..Default::default()
     });
     
     let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
@@ -124,6 +132,7 @@ async fn test_integer_out_of_range() {
     // Generate keys
     let key_gen_request = Request::new(KeyGenerationRequest {
         parameter_set: 0, // DEFAULT
+        ..Default::default()
     });
     
     let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
@@ -134,6 +143,7 @@ async fn test_integer_out_of_range() {
         client_key_id: client_key_id.clone(),
         value: 256, // Out of range for uint8 (0-255)
         num_bits: 8,
+        ..Default::default()
     });
     
     let response = service.encrypt_integer(encrypt_request).await;
@@ -152,6 +162,7 @@ async fn test_invalid_operation_operands() {
     // Generate keys
     let key_gen_request = Request::new(KeyGenerationRequest {
         parameter_set: 0, // DEFAULT
+        ..Default::default()
     });
     
     let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
@@ -162,6 +173,7 @@ async fn test_invalid_operation_operands() {
     let encrypt_request = Request::new(EncryptBooleanRequest {
         client_key_id: client_key_id.clone(),
         value: true,
+        ..Default::default()
     });
     
     let encrypt_response = service.encrypt_boolean(encrypt_request).await.unwrap();
@@ -172,6 +184,7 @@ async fn test_invalid_operation_operands() {
         server_key_id: server_key_id.clone(),
         operation: OperationType::And as i32, // AND requires 2 operands
         operand_ids: vec![encrypted_id], // But we only provide 1
+        ..Default::default()
     });
     
     let response = service.evaluate_operation(eval_request).await;
@@ -179,6 +192,121 @@ async fn test_invalid_operation_operands() {
     
     if let Err(status) = response {
         assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert_eq!(ErrorReason::of(&status), Some(ErrorReason::ArityMismatch));
         assert!(status.message().contains("Binary operation requires 2 operands"));
     }
-} 
\ No newline at end of file
+}
+
+#[tokio::test]
+async fn test_import_fingerprint_mismatch() {
+    let service = setup_service().await;
+    
+    // Upload bytes that don't match the claimed fingerprint
+    let import_request = Request::new(ImportCiphertextRequest {
+        ciphertext_type: CiphertextType::Boolean as i32,
+        serialized_data: vec![1, 2, 3, 4],
+        fingerprint: "0".repeat(64),
+        ..Default::default()
+    });
+    
+    let response = service.import_ciphertext(import_request).await;
+    assert!(response.is_err(), "Should reject data that fails fingerprint verification");
+    
+    if let Err(status) = response {
+        assert_eq!(status.code(), tonic::Code::DataLoss);
+        assert_eq!(ErrorReason::of(&status), Some(ErrorReason::FingerprintMismatch));
+        assert!(status.message().contains("Fingerprint mismatch"));
+    }
+}
+
+#[tokio::test]
+async fn test_circuit_type_mismatch_reason() {
+    let service = setup_service().await;
+    
+    let key_gen_request = Request::new(KeyGenerationRequest {
+        parameter_set: 0, // DEFAULT
+        ..Default::default()
+    });
+    let keys = service.generate_keys(key_gen_request).await.unwrap().into_inner();
+    
+    let encrypt_request = Request::new(EncryptIntegerRequest {
+        client_key_id: keys.client_key_id.clone(),
+        value: 5,
+        num_bits: 8,
+        ..Default::default()
+    });
+    let integer_id = service.encrypt_integer(encrypt_request).await.unwrap().into_inner().encrypted_data_id;
+    
+    // AND needs booleans; the reason says so without parsing the message
+    let input = |index| CircuitWire { source: Some(Source::Input(index)) };
+    let eval_request = Request::new(CircuitEvaluationRequest {
+        server_key_id: keys.server_key_id,
+        input_ids: vec![integer_id.clone(), integer_id],
+        gates: vec![CircuitGate {
+            operation: OperationType::And as i32,
+            operands: vec![input(0), input(1)],
+        }],
+        outputs: vec![CircuitWire { source: Some(Source::Gate(0)) }],
+        ..Default::default()
+    });
+    
+    let status = service.evaluate_circuit(eval_request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::TypeMismatch));
+}
+
+#[test]
+fn test_error_reason_round_trip() {
+    let status = ErrorReason::Overloaded.status("Evaluation queue is full, retry later");
+    assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::Overloaded));
+    
+    // Statuses without our ErrorInfo, e.g. from a proxy, have no reason
+    assert_eq!(ErrorReason::of(&tonic::Status::unavailable("upstream down")), None);
+}
+
+#[tokio::test]
+async fn test_operand_type_mismatch() {
+    let service = setup_service().await;
+    
+    let key_gen_request = Request::new(KeyGenerationRequest {
+        parameter_set: 0, // DEFAULT
+        ..Default::default()
+    });
+    let keys = service.generate_keys(key_gen_request).await.unwrap().into_inner();
+    
+    let encrypt_request = Request::new(EncryptBooleanRequest {
+        client_key_id: keys.client_key_id.clone(),
+        value: true,
+        ..Default::default()
+    });
+    let boolean_id = service.encrypt_boolean(encrypt_request).await.unwrap().into_inner().encrypted_data_id;
+    
+    let encrypt_request = Request::new(EncryptIntegerRequest {
+        client_key_id: keys.client_key_id.clone(),
+        value: 5,
+        num_bits: 8,
+        ..Default::default()
+    });
+    let integer_id = service.encrypt_integer(encrypt_request).await.unwrap().into_inner().encrypted_data_id;
+    
+    // An integer where AND needs a boolean is reported as such, not as missing
+    let eval_request = Request::new(EvaluationRequest {
+        server_key_id: keys.server_key_id,
+        operation: OperationType::And as i32,
+        operand_ids: vec![boolean_id, integer_id.clone()],
+        ..Default::default()
+    });
+    let status = service.evaluate_operation(eval_request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::TypeMismatch));
+    assert!(status.message().contains("type mismatch: expected FheBool, found FheUint8"));
+    
+    let decrypt_request = Request::new(DecryptBooleanRequest {
+        client_key_id: keys.client_key_id,
+        encrypted_data_id: integer_id,
+        serialized_data: vec![],
+    });
+    let status = service.decrypt_boolean(decrypt_request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+}
@@ -0,0 +1,107 @@
+use std::sync::Arc;
+
+use prost::Message;
+use tonic::Request;
+
+use hermetic_fhe::api::{
+    event_request, event_response, DecryptIntegerRequest, EncryptIntegerRequest, EvaluationRequest, EventRequest,
+    EventResponse, FheService, KeyGenerationRequest, OperationType,
+};
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::events;
+use hermetic_fhe::service::FheServiceImpl;
+
+fn setup_service() -> FheServiceImpl {
+    FheServiceImpl::new(Arc::new(KeyStore::new()), Arc::new(CiphertextStore::new()))
+}
+
+async fn send(service: &FheServiceImpl, request: &EventRequest) -> EventResponse {
+    EventResponse::decode(events::handle(service, &request.encode_to_vec()).await.as_slice()).unwrap()
+}
+
+fn error_reason(response: &EventResponse) -> &str {
+    match &response.result {
+        Some(event_response::Result::Error(error)) => &error.reason,
+        other => panic!("Expected an error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_evaluation_request_over_an_event() {
+    let service = setup_service();
+    let keys = service
+        .generate_keys(Request::new(KeyGenerationRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    let mut operand_ids = Vec::new();
+    for value in [20, 22] {
+        let request = Request::new(EncryptIntegerRequest {
+            client_key_id: keys.client_key_id.clone(),
+            value,
+            num_bits: 8,
+            ..Default::default()
+        });
+        operand_ids.push(service.encrypt_integer(request).await.unwrap().into_inner().encrypted_data_id);
+    }
+    
+    let request = EventRequest {
+        request_id: "req-1".to_string(),
+        tenant: "events".to_string(),
+        timeout_ms: 600_000,
+        request: Some(event_request::Request::Evaluate(EvaluationRequest {
+            server_key_id: keys.server_key_id.clone(),
+            operation: OperationType::Add as i32,
+            operand_ids,
+            ..Default::default()
+        })),
+    };
+    let response = send(&service, &request).await;
+    assert_eq!(response.request_id, "req-1");
+    let Some(event_response::Result::Evaluation(evaluation)) = response.result else {
+        panic!("Expected an evaluation, got {:?}", response.result);
+    };
+    
+    let request = Request::new(DecryptIntegerRequest {
+        client_key_id: keys.client_key_id.clone(),
+        encrypted_data_id: evaluation.result_id,
+        ..Default::default()
+    });
+    assert_eq!(service.decrypt_integer(request).await.unwrap().into_inner().value, 42);
+    
+    // The event's tenant is billed as if it had come in the gRPC metadata
+    let records = service.usage().records("events");
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].operation, "EvaluateOperation");
+}
+
+#[tokio::test]
+async fn test_event_errors_carry_the_reason() {
+    let service = setup_service();
+    
+    let request = EventRequest {
+        request_id: "req-2".to_string(),
+        request: Some(event_request::Request::Evaluate(EvaluationRequest {
+            server_key_id: "nonexistent-key".to_string(),
+            operation: OperationType::Add as i32,
+            operand_ids: vec!["a".to_string(), "b".to_string()],
+            ..Default::default()
+        })),
+        ..Default::default()
+    };
+    let response = send(&service, &request).await;
+    assert_eq!(response.request_id, "req-2");
+    assert_eq!(error_reason(&response), "KEY_NOT_FOUND");
+    
+    // An event with nothing to run
+    let request = EventRequest {
+        request_id: "req-3".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(error_reason(&send(&service, &request).await), "INVALID_REQUEST");
+    
+    // Bytes that aren't an EventRequest still get an answer, without a request ID
+    let response = EventResponse::decode(events::handle(&service, b"\xff\xff\xff").await.as_slice()).unwrap();
+    assert!(response.request_id.is_empty());
+    assert_eq!(error_reason(&response), "INVALID_REQUEST");
+}
@@ -0,0 +1,208 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tonic::{Request, Status};
+
+use hermetic_fhe::api::{
+    circuit_wire, result_sink, CiphertextType, CircuitGate, CircuitWire, EncryptIntegerRequest,
+    EncryptedRecord, ExportCiphertextRequest, FheService, GetMapJobRequest, ImportCiphertextRequest,
+    KeyGenerationRequest, MapJobStatus, MapOperationRequest, OperationType, ResultSink,
+};
+use hermetic_fhe::crypto::fingerprint::fingerprint_bytes;
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::faults::{FaultConfig, FaultySink};
+use hermetic_fhe::service::sink::{self, ResultSink as _, SinkPolicy};
+use hermetic_fhe::service::FheServiceImpl;
+
+// A service whose sinks are files under a fresh directory, behind the given faults
+fn setup_service(faults: FaultConfig) -> (FheServiceImpl, std::path::PathBuf) {
+    let root = std::env::temp_dir().join(format!("hermetic-fhe-faults-{}", uuid::Uuid::new_v4()));
+    let policy = SinkPolicy::default().with_root(&root).unwrap().with_faults(faults);
+    let service = FheServiceImpl::new(Arc::new(KeyStore::new()), Arc::new(CiphertextStore::new()))
+        .with_sink_policy(policy);
+    (service, root)
+}
+
+// Generate keys and ingest one labeled integer per value, returning the server key ID
+async fn ingest(service: &FheServiceImpl, values: &[i64]) -> String {
+    let keys = service
+        .generate_keys(Request::new(KeyGenerationRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    let mut records = Vec::new();
+    for (index, value) in values.iter().enumerate() {
+        let request = Request::new(EncryptIntegerRequest {
+            client_key_id: keys.client_key_id.clone(),
+            value: *value,
+            num_bits: 8,
+            ..Default::default()
+        });
+        let encrypted = service.encrypt_integer(request).await.unwrap().into_inner();
+        let request = Request::new(ExportCiphertextRequest {
+            encrypted_data_id: encrypted.encrypted_data_id,
+        });
+        let export = service.export_ciphertext(request).await.unwrap().into_inner();
+        records.push(Ok::<_, Status>(EncryptedRecord {
+            ciphertext_type: CiphertextType::Integer as i32,
+            serialized_data: export.serialized_data,
+            fingerprint: export.fingerprint,
+            label: format!("scores/{}", index),
+            ..Default::default()
+        }));
+    }
+    service.ingest(tokio_stream::iter(records)).await.unwrap();
+    keys.server_key_id
+}
+
+// Double every score into the exports directory and wait for the job
+async fn export_doubled(service: &FheServiceImpl, server_key_id: &str) -> MapJobStatus {
+    let input = CircuitWire {
+        source: Some(circuit_wire::Source::Input(0)),
+    };
+    let request = Request::new(MapOperationRequest {
+        server_key_id: server_key_id.to_string(),
+        label_prefix: "scores/".to_string(),
+        result_prefix: "doubled/".to_string(),
+        gates: vec![CircuitGate {
+            operation: OperationType::Add as i32,
+            operands: vec![input.clone(), input],
+        }],
+        sink: Some(ResultSink {
+            target: Some(result_sink::Target::Path("exports".to_string())),
+        }),
+        ..Default::default()
+    });
+    let job_id = service.map_operation(request).await.unwrap().into_inner().job_id;
+    loop {
+        let request = Request::new(GetMapJobRequest { job_id: job_id.clone() });
+        let status = service.get_map_job(request).await.unwrap().into_inner();
+        if status.done {
+            return status;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+// A sink that remembers what it was given, failing the first `failures` writes
+#[derive(Clone, Default)]
+struct Recorder {
+    writes: Arc<Mutex<Vec<Vec<u8>>>>,
+    failures: Arc<Mutex<u32>>,
+}
+
+impl Recorder {
+    fn failing(failures: u32) -> Self {
+        Self {
+            failures: Arc::new(Mutex::new(failures)),
+            ..Default::default()
+        }
+    }
+    
+    fn writes(&self) -> Vec<Vec<u8>> {
+        self.writes.lock().unwrap().clone()
+    }
+}
+
+impl sink::ResultSink for Recorder {
+    fn describe(&self) -> String {
+        "recorder".to_string()
+    }
+    
+    fn write(&self, name: &str, bytes: &[u8]) -> anyhow::Result<String> {
+        let mut failures = self.failures.lock().unwrap();
+        if *failures > 0 {
+            *failures -= 1;
+            anyhow::bail!("storage unavailable");
+        }
+        self.writes.lock().unwrap().push(bytes.to_vec());
+        Ok(name.to_string())
+    }
+}
+
+#[test]
+fn test_faults_follow_their_probabilities_and_seed() {
+    let writes = |faults: FaultConfig| {
+        let sink = FaultySink::new(Box::new(Recorder::default()), faults);
+        (0..200).map(|_| sink.write("result", b"ciphertext").is_ok()).collect::<Vec<bool>>()
+    };
+    
+    assert!(writes(FaultConfig::default()).iter().all(|ok| *ok));
+    assert!(writes(FaultConfig::default().with_errors(1.0)).iter().all(|ok| !*ok));
+    
+    // Some writes fail at a moderate rate, the same ones for the same seed
+    let failing = writes(FaultConfig::default().with_errors(0.3).with_seed(7));
+    let failed = failing.iter().filter(|ok| !**ok).count();
+    assert!((30..90).contains(&failed), "{} of 200 failed", failed);
+    assert_eq!(failing, writes(FaultConfig::default().with_errors(0.3).with_seed(7)));
+    
+    // A partial failure stores a prefix, then fails
+    let recorder = Recorder::default();
+    let sink = FaultySink::new(Box::new(recorder.clone()), FaultConfig::default().with_partial_failures(1.0));
+    assert!(sink.write("result", b"ciphertext").is_err());
+    assert_eq!(recorder.writes(), vec![b"ciphe".to_vec()]);
+    
+    let sink = FaultySink::new(
+        Box::new(Recorder::default()),
+        FaultConfig::default().with_latency(Duration::from_millis(20), 1.0),
+    );
+    let started = Instant::now();
+    sink.write("result", b"ciphertext").unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(20));
+}
+
+#[test]
+fn test_failed_writes_are_retried() {
+    let recorder = Recorder::failing(2);
+    assert_eq!(sink::write_with_retries(&recorder, "result", b"ciphertext").unwrap(), "result");
+    assert_eq!(recorder.writes(), vec![b"ciphertext".to_vec()]);
+    
+    // Giving up says so, with the last failure
+    let recorder = Recorder::failing(3);
+    let error = sink::write_with_retries(&recorder, "result", b"ciphertext").unwrap_err().to_string();
+    assert!(error.contains("after 3 attempts: storage unavailable"), "{}", error);
+    assert!(recorder.writes().is_empty());
+}
+
+#[tokio::test]
+async fn test_map_jobs_survive_flaky_sinks() {
+    // Errors, partial writes and latency on some writes; retries cover them
+    let faults = FaultConfig::default()
+        .with_errors(0.15)
+        .with_partial_failures(0.15)
+        .with_latency(Duration::from_millis(10), 0.5)
+        .with_seed(11);
+    let (service, root) = setup_service(faults);
+    let server_key_id = ingest(&service, &[1, 2, 3, 4, 5, 6]).await;
+    
+    let status = export_doubled(&service, &server_key_id).await;
+    assert_eq!(status.mapped + status.failed, 6);
+    assert!(status.mapped >= 4, "{} of 6 records failed", status.failed);
+    
+    // Every record reported written holds a whole ciphertext, never a torn prefix
+    for record in status.records.iter().filter(|record| record.error.is_empty()) {
+        let bytes = std::fs::read(&record.sink_object).unwrap();
+        let request = Request::new(ImportCiphertextRequest {
+            ciphertext_type: CiphertextType::Integer as i32,
+            fingerprint: fingerprint_bytes(&bytes),
+            serialized_data: bytes,
+            ..Default::default()
+        });
+        service.import_ciphertext(request).await.unwrap();
+    }
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_map_jobs_fail_records_cleanly_when_storage_is_down() {
+    let (service, root) = setup_service(FaultConfig::default().with_errors(1.0));
+    let server_key_id = ingest(&service, &[1, 2]).await;
+    
+    // Each record fails on its own with the reason, and nothing half-written is reported
+    let status = export_doubled(&service, &server_key_id).await;
+    assert_eq!((status.mapped, status.failed), (0, 2));
+    for record in &status.records {
+        assert!(record.sink_object.is_empty());
+        assert!(record.error.contains("after 3 attempts"), "{}", record.error);
+    }
+    let _ = std::fs::remove_dir_all(&root);
+}
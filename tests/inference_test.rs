@@ -0,0 +1,114 @@
+use std::sync::Arc;
+use tonic::Request;
+
+use hermetic_fhe::api::{
+    DecryptIntegerRequest, EncryptIntegerRequest, FheService, InferenceRequest,
+    KeyGenerationRequest, ModelLayer,
+};
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::FheServiceImpl;
+
+async fn setup_service() -> FheServiceImpl {
+    let key_store = Arc::new(KeyStore::new());
+    let ciphertext_store = Arc::new(CiphertextStore::new());
+    FheServiceImpl::new(key_store, ciphertext_store)
+}
+
+// Generate keys, returning (client_key_id, server_key_id)
+async fn generate_keys(service: &FheServiceImpl) -> (String, String) {
+    let key_gen_request = Request::new(KeyGenerationRequest {
+        parameter_set: 0, // DEFAULT
+        ..Default::default()
+    });
+    
+    let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
+    let keys = key_gen_response.into_inner();
+    (keys.client_key_id, keys.server_key_id)
+}
+
+async fn encrypt(service: &FheServiceImpl, client_key_id: &str, value: i64) -> String {
+    let encrypt_request = Request::new(EncryptIntegerRequest {
+        client_key_id: client_key_id.to_string(),
+        value,
+        num_bits: 8,
+        ..Default::default()
+    });
+    
+    let encrypt_response = service.encrypt_integer(encrypt_request).await.unwrap();
+    encrypt_response.get_ref().encrypted_data_id.clone()
+}
+
+async fn decrypt(service: &FheServiceImpl, client_key_id: &str, encrypted_data_id: &str) -> i64 {
+    let decrypt_request = Request::new(DecryptIntegerRequest {
+        client_key_id: client_key_id.to_string(),
+        encrypted_data_id: encrypted_data_id.to_string(),
+        serialized_data: vec![],
+        ..Default::default()
+    });
+    
+    service.decrypt_integer(decrypt_request).await.unwrap().get_ref().value
+}
+
+// Step activation: 1 at or above the threshold, 0 below
+fn step(threshold: i64) -> Vec<i64> {
+    (0..256).map(|x| (x >= threshold) as i64).collect()
+}
+
+#[tokio::test]
+async fn test_run_inference() {
+    let service = setup_service().await;
+    let (client_key_id, server_key_id) = generate_keys(&service).await;
+    
+    let input_ids = vec![
+        encrypt(&service, &client_key_id, 3).await,
+        encrypt(&service, &client_key_id, 5).await,
+    ];
+    
+    // Hidden layer: [3 + 2*5 + 1, 2*3 + 5] = [14, 11], stepped at 12 to [1, 0]
+    // Output layer: 5*1 + 7*0 = 5
+    let request = Request::new(InferenceRequest {
+        server_key_id,
+        input_ids,
+        layers: vec![
+            ModelLayer {
+                outputs: 2,
+                weights: vec![1, 2, 2, 1],
+                bias: vec![1, 0],
+                activation: step(12),
+            },
+            ModelLayer {
+                outputs: 1,
+                weights: vec![5, 7],
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    });
+    let response = service.run_inference(request).await.unwrap().into_inner();
+    
+    assert_eq!(response.prediction_ids.len(), 1);
+    assert_eq!(response.prediction_fingerprints.len(), 1);
+    assert_eq!(decrypt(&service, &client_key_id, &response.prediction_ids[0]).await, 5);
+}
+
+#[tokio::test]
+async fn test_run_inference_rejects_mismatched_layers() {
+    let service = setup_service().await;
+    let (client_key_id, server_key_id) = generate_keys(&service).await;
+    
+    let input_ids = vec![encrypt(&service, &client_key_id, 1).await];
+    
+    // The first layer produces 2 values but the second expects 3
+    let request = Request::new(InferenceRequest {
+        server_key_id,
+        input_ids,
+        layers: vec![
+            ModelLayer { outputs: 2, weights: vec![1, 1], ..Default::default() },
+            ModelLayer { outputs: 1, weights: vec![1, 1, 1], ..Default::default() },
+        ],
+        ..Default::default()
+    });
+    
+    let status = service.run_inference(request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+}
@@ -0,0 +1,151 @@
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+use tonic::{Request, Status};
+
+use hermetic_fhe::api::{
+    CiphertextType, CloseSessionRequest, CreateSessionRequest, DecryptIntegerRequest, EncryptIntegerRequest,
+    EncryptedRecord, ExportCiphertextRequest, ExportCiphertextResponse, FheService, KeyGenerationRequest,
+};
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::errors::ErrorReason;
+use hermetic_fhe::service::fhe_service::INGEST_BATCH_SIZE;
+use hermetic_fhe::service::FheServiceImpl;
+
+fn setup_service() -> (FheServiceImpl, Arc<CiphertextStore>) {
+    let key_store = Arc::new(KeyStore::new());
+    let ciphertext_store = Arc::new(CiphertextStore::new());
+    (FheServiceImpl::new(key_store, ciphertext_store.clone()), ciphertext_store)
+}
+
+// Encrypt an integer on the server and export it, as a client holding the key would
+// produce it; returns the client key ID and the export
+async fn exported_integer(service: &FheServiceImpl, value: i64) -> (String, ExportCiphertextResponse) {
+    let keys = service
+        .generate_keys(Request::new(KeyGenerationRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    let request = Request::new(EncryptIntegerRequest {
+        client_key_id: keys.client_key_id.clone(),
+        value,
+        num_bits: 8,
+        ..Default::default()
+    });
+    let encrypted_data_id = service.encrypt_integer(request).await.unwrap().into_inner().encrypted_data_id;
+    let request = Request::new(ExportCiphertextRequest { encrypted_data_id });
+    (keys.client_key_id, service.export_ciphertext(request).await.unwrap().into_inner())
+}
+
+fn record(export: &ExportCiphertextResponse, label: String, session_id: &str) -> Result<EncryptedRecord, Status> {
+    Ok(EncryptedRecord {
+        ciphertext_type: CiphertextType::Integer as i32,
+        serialized_data: export.serialized_data.clone(),
+        fingerprint: export.fingerprint.clone(),
+        label,
+        session_id: session_id.to_string(),
+        ..Default::default()
+    })
+}
+
+#[tokio::test]
+async fn test_ingest_stores_records_across_batches() {
+    let (service, ciphertext_store) = setup_service();
+    let (client_key_id, export) = exported_integer(&service, 42).await;
+    let stored_before = ciphertext_store.len();
+    
+    // Enough records to fill one batch and start another
+    let count = INGEST_BATCH_SIZE + 10;
+    let records = (0..count).map(|i| record(&export, format!("row-{}", i), ""));
+    let summary = service.ingest(tokio_stream::iter(records)).await.unwrap();
+    
+    assert_eq!(summary.records.len(), count);
+    assert_eq!(ciphertext_store.len(), stored_before + count);
+    for (i, ingested) in summary.records.iter().enumerate() {
+        assert_eq!(ingested.label, format!("row-{}", i), "Records should come back in stream order");
+    }
+    
+    let last = summary.records.last().unwrap();
+    let request = Request::new(DecryptIntegerRequest {
+        client_key_id,
+        encrypted_data_id: last.encrypted_data_id.clone(),
+        serialized_data: vec![],
+        ..Default::default()
+    });
+    assert_eq!(service.decrypt_integer(request).await.unwrap().into_inner().value, 42);
+}
+
+#[tokio::test]
+async fn test_rejected_record_removes_the_whole_stream() {
+    let (service, ciphertext_store) = setup_service();
+    let (_, export) = exported_integer(&service, 7).await;
+    let stored_before = ciphertext_store.len();
+    
+    // The bad record sits in the second batch, after a whole batch has been stored
+    let bad = INGEST_BATCH_SIZE + 3;
+    let records = (0..INGEST_BATCH_SIZE * 2).map(|i| {
+        let mut record = record(&export, format!("row-{}", i), "");
+        if i == bad {
+            record.as_mut().unwrap().serialized_data[0] ^= 0xff;
+        }
+        record
+    });
+    let status = service.ingest(tokio_stream::iter(records)).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::FingerprintMismatch));
+    assert!(status.message().starts_with(&format!("Record {}:", bad)), "{}", status.message());
+    assert_eq!(ciphertext_store.len(), stored_before, "No record of a failed stream should be kept");
+    
+    // A failure from the client side of the stream does the same
+    let records = vec![record(&export, "kept".to_string(), ""), Err(Status::cancelled("client went away"))];
+    assert!(service.ingest(tokio_stream::iter(records)).await.is_err());
+    assert_eq!(ciphertext_store.len(), stored_before);
+}
+
+#[tokio::test]
+async fn test_ingested_records_join_their_session() {
+    let (service, _) = setup_service();
+    let (_, export) = exported_integer(&service, 1).await;
+    
+    let request = Request::new(CreateSessionRequest { idle_timeout_seconds: 600 });
+    let session_id = service.create_session(request).await.unwrap().into_inner().session_id;
+    
+    let records = vec![
+        record(&export, "a".to_string(), &session_id),
+        record(&export, "b".to_string(), &session_id),
+        record(&export, "loose".to_string(), ""),
+    ];
+    service.ingest(tokio_stream::iter(records)).await.unwrap();
+    
+    let request = Request::new(CloseSessionRequest { session_id });
+    let closed = service.close_session(request).await.unwrap().into_inner();
+    assert_eq!(closed.freed_ciphertexts, 2);
+    
+    // Records naming an unknown session are refused
+    let records = vec![record(&export, "orphan".to_string(), "no-such-session")];
+    let status = service.ingest(tokio_stream::iter(records)).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::SessionNotFound));
+}
+
+#[tokio::test]
+async fn test_records_whose_session_closes_mid_stream_are_freed() {
+    let (service, ciphertext_store) = setup_service();
+    let (_, export) = exported_integer(&service, 1).await;
+    let request = Request::new(CreateSessionRequest { idle_timeout_seconds: 600 });
+    let session_id = service.create_session(request).await.unwrap().into_inner().session_id;
+    let stored_before = ciphertext_store.len();
+    
+    // A whole batch is stored for the session, which closes before the stream ends
+    let records = tokio_stream::iter(0..=INGEST_BATCH_SIZE).then(|i| {
+        let (service, export, session_id) = (&service, &export, session_id.clone());
+        async move {
+            if i < INGEST_BATCH_SIZE {
+                return record(export, format!("row-{}", i), &session_id);
+            }
+            let request = Request::new(CloseSessionRequest { session_id });
+            service.close_session(request).await.unwrap();
+            record(export, "loose".to_string(), "")
+        }
+    });
+    let status = service.ingest(Box::pin(records)).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::SessionNotFound));
+    assert_eq!(ciphertext_store.len(), stored_before, "Nothing would ever free records of a closed session");
+}
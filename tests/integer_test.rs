@@ -1,8 +1,10 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 use tonic::Request;
 
+use hermetic_fhe::api::v1::evaluation_request::OverflowBehavior;
 use hermetic_fhe::api::{
-    DecryptIntegerRequest, EncryptIntegerRequest, EvaluationRequest,
+    DecryptBooleanRequest, DecryptIntegerRequest, EncryptIntegerRequest, EvaluationRequest,
     FheService, KeyGenerationRequest, OperationType,
 };
 use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
@@ -21,6 +23,7 @@ async fn test_encrypt_decrypt_integer() {
     // Generate keys
     let key_gen_request = Request::new(KeyGenerationRequest {
         parameter_set: 0, // DEFAULT
+        ..Default::default()
     });
     
     let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
@@ -32,6 +35,7 @@ async fn test_encrypt_decrypt_integer() {
         client_key_id: client_key_id.clone(),
         value,
         num_bits: 8, // 8-bit integer
+        ..Default::default()
     });
     
     let encrypt_response = service.encrypt_integer(encrypt_request).await.unwrap();
@@ -42,6 +46,7 @@ async fn test_encrypt_decrypt_integer() {
         client_key_id: client_key_id.clone(),
         encrypted_data_id,
         serialized_data: vec![],
+        ..Default::default()
     });
     
     let decrypt_response = service.decrypt_integer(decrypt_request).await.unwrap();
@@ -57,6 +62,7 @@ async fn test_integer_addition() {
     // Generate keys
     let key_gen_request = Request::new(KeyGenerationRequest {
         parameter_set: 0, // DEFAULT
+        ..Default::default()
     });
     
     let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
@@ -69,6 +75,7 @@ async fn test_integer_addition() {
         client_key_id: client_key_id.clone(),
         value: value_a,
         num_bits: 8,
+        ..Default::default()
     });
     
     let encrypt_a_response = service.encrypt_integer(encrypt_a_request).await.unwrap();
@@ -80,6 +87,7 @@ async fn test_integer_addition() {
         client_key_id: client_key_id.clone(),
         value: value_b,
         num_bits: 8,
+        ..Default::default()
     });
     
     let encrypt_b_response = service.encrypt_integer(encrypt_b_request).await.unwrap();
@@ -90,6 +98,7 @@ async fn test_integer_addition() {
         server_key_id: server_key_id.clone(),
         operation: OperationType::Add as i32,
         operand_ids: vec![a_id, b_id],
+        ..Default::default()
     });
     
     let eval_response = service.evaluate_operation(eval_request).await.unwrap();
@@ -100,6 +109,7 @@ async fn test_integer_addition() {
         client_key_id: client_key_id.clone(),
         encrypted_data_id: result_id,
         serialized_data: vec![],
+        ..Default::default()
     });
     
     let decrypt_response = service.decrypt_integer(decrypt_request).await.unwrap();
@@ -115,6 +125,7 @@ async fn test_integer_subtraction() {
     // Generate keys
     let key_gen_request = Request::new(KeyGenerationRequest {
         parameter_set: 0, // DEFAULT
+        ..Default::default()
     });
     
     let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
@@ -127,6 +138,7 @@ async fn test_integer_subtraction() {
         client_key_id: client_key_id.clone(),
         value: value_a,
         num_bits: 8,
+        ..Default::default()
     });
     
     let encrypt_a_response = service.encrypt_integer(encrypt_a_request).await.unwrap();
@@ -138,6 +150,7 @@ async fn test_integer_subtraction() {
         client_key_id: client_key_id.clone(),
         value: value_b,
         num_bits: 8,
+        ..Default::default()
     });
     
     let encrypt_b_response = service.encrypt_integer(encrypt_b_request).await.unwrap();
@@ -148,6 +161,7 @@ async fn test_integer_subtraction() {
         server_key_id: server_key_id.clone(),
         operation: OperationType::Subtract as i32,
         operand_ids: vec![a_id, b_id],
+        ..Default::default()
     });
     
     let eval_response = service.evaluate_operation(eval_request).await.unwrap();
@@ -158,6 +172,7 @@ async fn test_integer_subtraction() {
         client_key_id: client_key_id.clone(),
         encrypted_data_id: result_id,
         serialized_data: vec![],
+        ..Default::default()
     });
     
     let decrypt_response = service.decrypt_integer(decrypt_request).await.unwrap();
@@ -173,6 +188,7 @@ async fn test_integer_multiplication() {
     // Generate keys
     let key_gen_request = Request::new(KeyGenerationRequest {
         parameter_set: 0, // DEFAULT
+        ..Default::default()
     });
     
     let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
@@ -185,6 +201,7 @@ async fn test_integer_multiplication() {
         client_key_id: client_key_id.clone(),
         value: value_a,
         num_bits: 8,
+        ..Default::default()
     });
     
     let encrypt_a_response = service.encrypt_integer(encrypt_a_request).await.unwrap();
@@ -196,6 +213,7 @@ async fn test_integer_multiplication() {
         client_key_id: client_key_id.clone(),
         value: value_b,
         num_bits: 8,
+        ..Default::default()
     });
     
     let encrypt_b_response = service.encrypt_integer(encrypt_b_request).await.unwrap();
@@ -206,6 +224,7 @@ async fn test_integer_multiplication() {
         server_key_id: server_key_id.clone(),
         operation: OperationType::Multiply as i32,
         operand_ids: vec![a_id, b_id],
+        ..Default::default()
     });
     
     let eval_response = service.evaluate_operation(eval_request).await.unwrap();
@@ -216,10 +235,327 @@ async fn test_integer_multiplication() {
         client_key_id: client_key_id.clone(),
         encrypted_data_id: result_id,
         serialized_data: vec![],
+        ..Default::default()
     });
     
     let decrypt_response = service.decrypt_integer(decrypt_request).await.unwrap();
     let result = decrypt_response.get_ref().value;
     
     assert_eq!(result, value_a * value_b, "6 * 7 should be 42");
-} 
\ No newline at end of file
+} 
+#[tokio::test]
+async fn test_integer_zero_tests() {
+    let service = setup_service().await;
+    
+    let keys = service
+        .generate_keys(Request::new(KeyGenerationRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    
+    for (value, is_zero) in [(0, true), (1, false), (128, false)] {
+        let encrypt_request = Request::new(EncryptIntegerRequest {
+            client_key_id: keys.client_key_id.clone(),
+            value,
+            num_bits: 8,
+            ..Default::default()
+        });
+        let value_id = service.encrypt_integer(encrypt_request).await.unwrap().into_inner().encrypted_data_id;
+        
+        for (operation, expected) in [(OperationType::IsZero, is_zero), (OperationType::IsNonZero, !is_zero)] {
+            let eval_request = Request::new(EvaluationRequest {
+                server_key_id: keys.server_key_id.clone(),
+                operation: operation as i32,
+                operand_ids: vec![value_id.clone()],
+                ..Default::default()
+            });
+            let result_id = service.evaluate_operation(eval_request).await.unwrap().into_inner().result_id;
+            
+            // The result is an encrypted boolean
+            let decrypt_request = Request::new(DecryptBooleanRequest {
+                client_key_id: keys.client_key_id.clone(),
+                encrypted_data_id: result_id,
+                serialized_data: vec![],
+            });
+            let result = service.decrypt_boolean(decrypt_request).await.unwrap().into_inner().value;
+            assert_eq!(result, expected, "{:?} of {}", operation, value);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_running_sum_in_place() {
+    let service = setup_service().await;
+    
+    let key_gen_request = Request::new(KeyGenerationRequest {
+        parameter_set: 0, // DEFAULT
+        ..Default::default()
+    });
+    let keys = service.generate_keys(key_gen_request).await.unwrap().into_inner();
+    
+    let mut encrypted_ids = Vec::new();
+    for value in [0, 3, 4, 5] {
+        let request = Request::new(EncryptIntegerRequest {
+            client_key_id: keys.client_key_id.clone(),
+            value,
+            num_bits: 8,
+            ..Default::default()
+        });
+        encrypted_ids.push(service.encrypt_integer(request).await.unwrap().into_inner().encrypted_data_id);
+    }
+    let total_id = encrypted_ids[0].clone();
+    
+    // Each step adds into the running total instead of minting a new ID
+    for value_id in &encrypted_ids[1..] {
+        let request = Request::new(EvaluationRequest {
+            server_key_id: keys.server_key_id.clone(),
+            operation: OperationType::Add as i32,
+            operand_ids: vec![total_id.clone(), value_id.clone()],
+            overwrite_id: total_id.clone(),
+            ..Default::default()
+        });
+        let response = service.evaluate_operation(request).await.unwrap().into_inner();
+        assert_eq!(response.result_id, total_id);
+    }
+    
+    let decrypt_request = Request::new(DecryptIntegerRequest {
+        client_key_id: keys.client_key_id.clone(),
+        encrypted_data_id: total_id.clone(),
+        serialized_data: vec![],
+        ..Default::default()
+    });
+    let total = service.decrypt_integer(decrypt_request).await.unwrap().into_inner().value;
+    assert_eq!(total, 12, "0 + 3 + 4 + 5 should be 12");
+    
+    // Only an operand may be overwritten
+    let request = Request::new(EvaluationRequest {
+        server_key_id: keys.server_key_id,
+        operation: OperationType::Add as i32,
+        operand_ids: vec![total_id, encrypted_ids[1].clone()],
+        overwrite_id: encrypted_ids[2].clone(),
+        ..Default::default()
+    });
+    let status = service.evaluate_operation(request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn test_evaluate_expression() {
+    let service = setup_service().await;
+    
+    let key_gen_request = Request::new(KeyGenerationRequest {
+        parameter_set: 0, // DEFAULT
+        ..Default::default()
+    });
+    let keys = service.generate_keys(key_gen_request).await.unwrap().into_inner();
+    
+    let mut variables = HashMap::new();
+    for (name, value) in [("a", 15), ("b", 7), ("c", 3)] {
+        let request = Request::new(EncryptIntegerRequest {
+            client_key_id: keys.client_key_id.clone(),
+            value,
+            num_bits: 8,
+            ..Default::default()
+        });
+        let id = service.encrypt_integer(request).await.unwrap().into_inner().encrypted_data_id;
+        variables.insert(name.to_string(), id);
+    }
+    
+    let request = Request::new(EvaluationRequest {
+        server_key_id: keys.server_key_id.clone(),
+        expression: "(a - b) * c + a".to_string(),
+        variables: variables.clone(),
+        ..Default::default()
+    });
+    let result_id = service.evaluate_operation(request).await.unwrap().into_inner().result_id;
+    
+    let decrypt_request = Request::new(DecryptIntegerRequest {
+        client_key_id: keys.client_key_id.clone(),
+        encrypted_data_id: result_id,
+        serialized_data: vec![],
+        ..Default::default()
+    });
+    let value = service.decrypt_integer(decrypt_request).await.unwrap().into_inner().value;
+    assert_eq!(value, (15 - 7) * 3 + 15);
+    
+    // Syntax errors, unbound variables and boolean operators on integers are all refused
+    let cases = [
+        ("(a - b", tonic::Code::InvalidArgument),
+        ("a + d", tonic::Code::InvalidArgument),
+        ("a & b", tonic::Code::FailedPrecondition),
+    ];
+    for (expression, code) in cases {
+        let request = Request::new(EvaluationRequest {
+            server_key_id: keys.server_key_id.clone(),
+            expression: expression.to_string(),
+            variables: variables.clone(),
+            ..Default::default()
+        });
+        let status = service.evaluate_operation(request).await.unwrap_err();
+        assert_eq!(status.code(), code, "Unexpected status for {}: {}", expression, status.message());
+    }
+}
+
+#[tokio::test]
+async fn test_non_wrapping_arithmetic() {
+    let service = setup_service().await;
+    
+    let keys = service
+        .generate_keys(Request::new(KeyGenerationRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    let mut variables = HashMap::new();
+    for (name, value) in [("a", 200), ("b", 100)] {
+        let request = Request::new(EncryptIntegerRequest {
+            client_key_id: keys.client_key_id.clone(),
+            value,
+            num_bits: 8,
+            ..Default::default()
+        });
+        let id = service.encrypt_integer(request).await.unwrap().into_inner().encrypted_data_id;
+        variables.insert(name.to_string(), id);
+    }
+    let (a, b) = (variables["a"].clone(), variables["b"].clone());
+    
+    let evaluate = |request: EvaluationRequest| {
+        let service = &service;
+        let client_key_id = keys.client_key_id.clone();
+        async move {
+            let result_id = service.evaluate_operation(Request::new(request)).await?.into_inner().result_id;
+            let decrypt_request = Request::new(DecryptIntegerRequest {
+                client_key_id,
+                encrypted_data_id: result_id,
+                serialized_data: vec![],
+                ..Default::default()
+            });
+            Ok::<_, tonic::Status>(service.decrypt_integer(decrypt_request).await?.into_inner().value)
+        }
+    };
+    let request = |operation: OperationType, operands: [&String; 2], overflow: OverflowBehavior| {
+        EvaluationRequest {
+            server_key_id: keys.server_key_id.clone(),
+            operation: operation as i32,
+            operand_ids: operands.into_iter().cloned().collect(),
+            overflow: overflow as i32,
+            ..Default::default()
+        }
+    };
+    
+    // The dedicated operations
+    let cases = [
+        (OperationType::AbsDiff, [&b, &a], 100),
+        (OperationType::SaturatingAdd, [&a, &b], 255),
+        (OperationType::SaturatingAdd, [&b, &b], 200),
+        (OperationType::SaturatingSub, [&b, &a], 0),
+        (OperationType::SaturatingSub, [&a, &b], 100),
+    ];
+    for (op, operands, expected) in cases {
+        let value = evaluate(request(op, operands, OverflowBehavior::Wrap)).await.unwrap();
+        assert_eq!(value, expected, "{:?}", op);
+    }
+    
+    // The overflow flag turns plain addition and subtraction into the saturating forms
+    let cases = [
+        (OperationType::Add, [&a, &b], OverflowBehavior::Wrap, 44),
+        (OperationType::Add, [&a, &b], OverflowBehavior::Saturate, 255),
+        (OperationType::Subtract, [&b, &a], OverflowBehavior::Saturate, 0),
+    ];
+    for (op, operands, overflow, expected) in cases {
+        let value = evaluate(request(op, operands, overflow)).await.unwrap();
+        assert_eq!(value, expected, "{:?} with {:?}", op, overflow);
+    }
+    let expression = EvaluationRequest {
+        server_key_id: keys.server_key_id.clone(),
+        expression: "b - a + b".to_string(),
+        variables: variables.clone(),
+        overflow: OverflowBehavior::Saturate as i32,
+        ..Default::default()
+    };
+    assert_eq!(evaluate(expression).await.unwrap(), 100);
+    
+    // Multiplication has no saturating form
+    let multiply = request(OperationType::Multiply, [&a, &b], OverflowBehavior::Saturate);
+    let status = evaluate(multiply).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::Unimplemented);
+}
+
+#[tokio::test]
+async fn test_overflow_detection() {
+    let service = setup_service().await;
+    
+    let keys = service
+        .generate_keys(Request::new(KeyGenerationRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    let mut ids = HashMap::new();
+    for value in [3, 4, 20, 100, 200] {
+        let request = Request::new(EncryptIntegerRequest {
+            client_key_id: keys.client_key_id.clone(),
+            value,
+            num_bits: 8,
+            ..Default::default()
+        });
+        let id = service.encrypt_integer(request).await.unwrap().into_inner().encrypted_data_id;
+        ids.insert(value, id);
+    }
+    let request = |operation: OperationType, a: u32, b: u32| EvaluationRequest {
+        server_key_id: keys.server_key_id.clone(),
+        operation: operation as i32,
+        operand_ids: vec![ids[&a].clone(), ids[&b].clone()],
+        detect_overflow: true,
+        ..Default::default()
+    };
+    
+    let cases = [
+        (OperationType::Add, 200, 100, 44, true),
+        (OperationType::Add, 100, 100, 200, false),
+        (OperationType::Subtract, 100, 200, 156, true),
+        (OperationType::Subtract, 200, 100, 100, false),
+        (OperationType::Multiply, 20, 20, 144, true),
+        (OperationType::Multiply, 3, 4, 12, false),
+    ];
+    for (operation, a, b, expected, expected_overflow) in cases {
+        let request = Request::new(request(operation, a, b));
+        let response = service.evaluate_operation(request).await.unwrap().into_inner();
+        
+        let decrypt_request = Request::new(DecryptIntegerRequest {
+            client_key_id: keys.client_key_id.clone(),
+            encrypted_data_id: response.result_id,
+            serialized_data: vec![],
+            ..Default::default()
+        });
+        let value = service.decrypt_integer(decrypt_request).await.unwrap().into_inner().value;
+        assert_eq!(value, expected, "{:?} {} {}", operation, a, b);
+        
+        // The overflow bit is a separate encrypted boolean
+        let decrypt_request = Request::new(DecryptBooleanRequest {
+            client_key_id: keys.client_key_id.clone(),
+            encrypted_data_id: response.overflow_id,
+            serialized_data: vec![],
+        });
+        let overflowed = service.decrypt_boolean(decrypt_request).await.unwrap().into_inner().value;
+        assert_eq!(overflowed, expected_overflow, "{:?} {} {}", operation, a, b);
+    }
+    
+    // Without the flag there is no bit
+    let mut plain = request(OperationType::Add, 3, 4);
+    plain.detect_overflow = false;
+    let response = service.evaluate_operation(Request::new(plain)).await.unwrap().into_inner();
+    assert!(response.overflow_id.is_empty());
+    
+    // Operations that can't wrap, saturating ones and expressions have nothing to detect
+    let mut saturating = request(OperationType::Add, 3, 4);
+    saturating.overflow = OverflowBehavior::Saturate as i32;
+    let mut expression = request(OperationType::Add, 3, 4);
+    expression.expression = "a + b".to_string();
+    expression.variables = HashMap::from([
+        ("a".to_string(), ids[&3].clone()),
+        ("b".to_string(), ids[&4].clone()),
+    ]);
+    for invalid in [request(OperationType::AbsDiff, 3, 4), saturating, expression] {
+        let status = service.evaluate_operation(Request::new(invalid)).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+}
@@ -0,0 +1,80 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tonic::metadata::MetadataValue;
+use tonic::service::Interceptor;
+use tonic::{Code, Request, Status};
+
+use hermetic_fhe::api::fhe_service_client::FheServiceClient;
+use hermetic_fhe::api::KeyGenerationRequest;
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::interceptors::{fhe_service_server, Interceptors};
+use hermetic_fhe::service::transport::TransportConfig;
+use hermetic_fhe::service::usage::TENANT_HEADER;
+use hermetic_fhe::service::FheServiceImpl;
+
+// Refuses calls without the API key
+fn require_api_key(request: Request<()>) -> Result<Request<()>, Status> {
+    match request.metadata().get("x-api-key") {
+        Some(key) if key == "secret" => Ok(request),
+        _ => Err(Status::unauthenticated("API key required")),
+    }
+}
+
+#[test]
+fn test_interceptors_run_in_order() {
+    // Each sees what the ones before it set
+    let mut interceptors = Interceptors::new()
+        .with(|mut request: Request<()>| -> Result<Request<()>, Status> {
+            request
+                .metadata_mut()
+                .insert("x-api-key", MetadataValue::from_static("secret"));
+            Ok(request)
+        })
+        .with(require_api_key)
+        .with(|mut request: Request<()>| -> Result<Request<()>, Status> {
+            request
+                .metadata_mut()
+                .insert(TENANT_HEADER, MetadataValue::from_static("acme"));
+            Ok(request)
+        });
+    assert_eq!(interceptors.len(), 3);
+    let request = interceptors.call(Request::new(())).unwrap();
+    assert_eq!(request.metadata().get(TENANT_HEADER).unwrap(), "acme");
+    
+    // One that refuses the call stops the rest
+    let reached = Arc::new(AtomicUsize::new(0));
+    let counter = reached.clone();
+    let mut interceptors = Interceptors::new().with(require_api_key).with(
+        move |request: Request<()>| -> Result<Request<()>, Status> {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok(request)
+        },
+    );
+    let status = interceptors.call(Request::new(())).unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+    assert_eq!(reached.load(Ordering::SeqCst), 0);
+    
+    assert!(Interceptors::new().is_empty());
+    assert!(Interceptors::new().call(Request::new(())).is_ok());
+}
+
+#[tokio::test]
+async fn test_interceptors_guard_the_service() {
+    let service = FheServiceImpl::new(Arc::new(KeyStore::new()), Arc::new(CiphertextStore::new()));
+    let interceptors = Interceptors::new().with(require_api_key);
+    let server = fhe_service_server(service, &TransportConfig::default(), interceptors);
+    let mut client = FheServiceClient::new(server);
+    
+    let status = client
+        .generate_keys(KeyGenerationRequest::default())
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unauthenticated);
+    
+    let mut request = Request::new(KeyGenerationRequest::default());
+    request
+        .metadata_mut()
+        .insert("x-api-key", MetadataValue::from_static("secret"));
+    let keys = client.generate_keys(request).await.unwrap().into_inner();
+    assert!(!keys.server_key_id.is_empty());
+}
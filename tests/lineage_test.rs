@@ -0,0 +1,108 @@
+use std::sync::Arc;
+use tonic::Request;
+
+use hermetic_fhe::api::{
+    DeleteCiphertextsRequest, EncryptIntegerRequest, EvaluationRequest, FheService,
+    GetLineageRequest, KeyGenerationRequest, LineageResponse, OperationType,
+};
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::errors::ErrorReason;
+use hermetic_fhe::service::FheServiceImpl;
+
+// A service with 1, 2 and 3 encrypted under a fresh key; returns the server key ID and
+// the ciphertext IDs
+async fn setup_service() -> (FheServiceImpl, String, Vec<String>) {
+    let service = FheServiceImpl::new(Arc::new(KeyStore::new()), Arc::new(CiphertextStore::new()));
+    let keys = service
+        .generate_keys(Request::new(KeyGenerationRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    let mut ids = Vec::new();
+    for value in [1, 2, 3] {
+        let request = Request::new(EncryptIntegerRequest {
+            client_key_id: keys.client_key_id.clone(),
+            value,
+            num_bits: 8,
+            ..Default::default()
+        });
+        ids.push(service.encrypt_integer(request).await.unwrap().into_inner().encrypted_data_id);
+    }
+    (service, keys.server_key_id, ids)
+}
+
+async fn add(service: &FheServiceImpl, server_key_id: &str, a: &str, b: &str) -> String {
+    let request = Request::new(EvaluationRequest {
+        server_key_id: server_key_id.to_string(),
+        operation: OperationType::Add as i32,
+        operand_ids: vec![a.to_string(), b.to_string()],
+        ..Default::default()
+    });
+    service.evaluate_operation(request).await.unwrap().into_inner().result_id
+}
+
+async fn lineage(service: &FheServiceImpl, id: &str, max_nodes: u32) -> LineageResponse {
+    let request = Request::new(GetLineageRequest {
+        encrypted_data_id: id.to_string(),
+        max_nodes,
+    });
+    service.get_lineage(request).await.unwrap().into_inner()
+}
+
+#[tokio::test]
+async fn test_lineage_outlives_deleted_intermediates() {
+    let (service, server_key_id, ids) = setup_service().await;
+    let ab = add(&service, &server_key_id, &ids[0], &ids[1]).await;
+    let abc = add(&service, &server_key_id, &ab, &ids[2]).await;
+    let request = Request::new(DeleteCiphertextsRequest {
+        encrypted_data_ids: vec![ab.clone()],
+    });
+    service.delete_ciphertexts(request).await.unwrap();
+    
+    let response = lineage(&service, &abc, 0).await;
+    assert!(!response.truncated);
+    let nodes = response.nodes;
+    assert_eq!(nodes.len(), 5);
+    assert_eq!(nodes[0].encrypted_data_id, abc);
+    assert_eq!(nodes[0].operation, "EvaluateOperation");
+    assert_eq!(nodes[0].detail, "ADD");
+    
+    // Breadth first, so the intermediate and c come next, then a and b
+    assert_eq!(nodes[0].parent_indices, vec![1, 2]);
+    assert_eq!(nodes[1].encrypted_data_id, ab);
+    assert_eq!(nodes[1].parent_indices, vec![3, 4]);
+    for (node, id) in nodes[2..].iter().zip([&ids[2], &ids[0], &ids[1]]) {
+        assert_eq!(&node.encrypted_data_id, id);
+        assert!(node.operation.is_empty());
+        assert!(node.parent_indices.is_empty());
+    }
+}
+
+#[tokio::test]
+async fn test_lineage_lists_each_value_once() {
+    let (service, server_key_id, ids) = setup_service().await;
+    let aa = add(&service, &server_key_id, &ids[0], &ids[0]).await;
+    let aaa = add(&service, &server_key_id, &aa, &ids[0]).await;
+    
+    let nodes = lineage(&service, &aaa, 0).await.nodes;
+    assert_eq!(nodes.len(), 3);
+    assert_eq!(nodes[0].parent_indices, vec![1, 2]);
+    assert_eq!(nodes[1].parent_indices, vec![2, 2]);
+    
+    // Cut short, the value still lists the parents that made it in
+    let response = lineage(&service, &aaa, 2).await;
+    assert!(response.truncated);
+    assert_eq!(response.nodes.len(), 2);
+    assert_eq!(response.nodes[0].parent_indices, vec![1]);
+    
+    // A fresh value is its own whole lineage, and an unknown one has none
+    let nodes = lineage(&service, &ids[1], 0).await.nodes;
+    assert_eq!(nodes.len(), 1);
+    assert!(nodes[0].operation.is_empty());
+    let request = Request::new(GetLineageRequest {
+        encrypted_data_id: "missing".to_string(),
+        max_nodes: 0,
+    });
+    let status = service.get_lineage(request).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::CiphertextNotFound));
+}
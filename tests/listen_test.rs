@@ -0,0 +1,98 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use hermetic_fhe::api::fhe_service_client::FheServiceClient;
+use hermetic_fhe::api::{FheServiceServer, ServerInfoRequest};
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::listen::{
+    self, bind_unix, client_endpoint, resolve, ListenFlags, DEFAULT_ENDPOINT,
+};
+use hermetic_fhe::service::FheServiceImpl;
+use tonic::transport::Server;
+
+fn flags(args: &[&str]) -> anyhow::Result<ListenFlags> {
+    ListenFlags::parse(args.iter().map(|arg| arg.to_string()))
+}
+
+#[test]
+fn test_resolve_defaults_and_overrides() {
+    let addr = |s: &str| s.parse::<SocketAddr>().unwrap();
+    
+    // IPv4 loopback unless told otherwise, since not every container has ::1
+    assert_eq!(resolve(None, None, false).unwrap(), addr("127.0.0.1:50051"));
+    assert_eq!(resolve(None, Some("8080"), true).unwrap(), addr("0.0.0.0:8080"));
+    assert_eq!(resolve(Some("::"), None, false).unwrap(), addr("[::]:50051"));
+    assert_eq!(resolve(Some("[::1]"), Some(" 9000 "), false).unwrap(), addr("[::1]:9000"));
+    
+    assert!(resolve(Some("localhost"), None, false).is_err());
+    assert!(resolve(None, Some("65536"), false).is_err());
+    assert!(resolve(Some("10.0.0.1"), None, true).is_err());
+}
+
+#[test]
+fn test_parse_flags() {
+    let parsed = flags(&["--host", "10.0.0.1", "--port=9000"]).unwrap();
+    assert_eq!(parsed.host.as_deref(), Some("10.0.0.1"));
+    assert_eq!(parsed.port.as_deref(), Some("9000"));
+    assert!(!parsed.bind_all);
+    
+    assert!(flags(&["--bind-all"]).unwrap().bind_all);
+    assert_eq!(flags(&[]).unwrap(), ListenFlags::default());
+    
+    assert!(flags(&["--port"]).is_err(), "A flag without its value should be rejected");
+    assert!(flags(&["--prot", "9000"]).is_err(), "A mistyped flag should be rejected");
+    assert!(flags(&["--bind-all=yes"]).is_err());
+    
+    let parsed = flags(&["--unix-socket", "/run/fhe.sock", "--no-tcp"]).unwrap();
+    assert_eq!(parsed.unix_socket.as_deref(), Some("/run/fhe.sock"));
+    assert!(parsed.no_tcp);
+    let listeners = parsed.listeners().unwrap();
+    assert_eq!(listeners.tcp, None, "No network port should be opened");
+    assert_eq!(listeners.unix_socket, Some(PathBuf::from("/run/fhe.sock")));
+    if std::env::var("HERMETIC_FHE_UNIX_SOCKET").is_err() {
+        assert!(flags(&["--no-tcp"]).unwrap().listeners().is_err(), "Something must be listened on");
+    }
+}
+
+#[test]
+fn test_client_endpoint() {
+    let endpoint = |args: &[&str]| client_endpoint(args.iter().map(|arg| arg.to_string()));
+    
+    assert_eq!(endpoint(&["https://fhe.example.com"]), "https://fhe.example.com");
+    assert_eq!(endpoint(&["fhe.internal:50051"]), "http://fhe.internal:50051");
+    assert_eq!(endpoint(&["unix:/run/fhe.sock"]), "unix:/run/fhe.sock");
+    if std::env::var("HERMETIC_FHE_ENDPOINT").is_err() {
+        assert_eq!(endpoint(&[]), DEFAULT_ENDPOINT);
+    }
+}
+
+#[tokio::test]
+async fn test_serve_over_unix_socket() {
+    let dir = std::env::temp_dir().join(format!("hermetic-fhe-socket-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("fhe.sock");
+    
+    // A socket left behind by an earlier run is replaced, but no other kind of file is
+    drop(bind_unix(&path).unwrap());
+    let incoming = bind_unix(&path).unwrap();
+    let file = dir.join("not-a-socket");
+    std::fs::write(&file, b"keep").unwrap();
+    assert!(bind_unix(&file).is_err());
+    assert_eq!(std::fs::read(&file).unwrap(), b"keep");
+    
+    let service = FheServiceImpl::new(Arc::new(KeyStore::new()), Arc::new(CiphertextStore::new()));
+    tokio::spawn(
+        Server::builder()
+            .add_service(FheServiceServer::new(service))
+            .serve_with_incoming(incoming),
+    );
+    let channel = listen::connect(&format!("unix:{}", path.display())).await.unwrap();
+    let info = FheServiceClient::new(channel)
+        .get_server_info(ServerInfoRequest {})
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(!info.api_versions.is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,60 @@
+use tonic::codegen::http::{HeaderMap, HeaderValue};
+
+use hermetic_fhe::api::{
+    plaintext_value, EncryptAndEvaluateRequest, EncryptIntegerRequest, IntegerResponse, PlaintextValue,
+};
+use hermetic_fhe::service::logging::{LoggedMetadata, Redacted};
+use hermetic_fhe::service::usage::TENANT_HEADER;
+
+#[test]
+fn test_plaintext_fields_are_redacted_in_debug() {
+    let request = EncryptIntegerRequest {
+        client_key_id: "client-key".to_string(),
+        value: 8675309,
+        num_bits: 8,
+        ..Default::default()
+    };
+    let logged = format!("{:?}", request);
+    assert!(logged.contains("client-key"), "IDs should still be shown: {}", logged);
+    assert!(logged.contains("value: <redacted>"), "{}", logged);
+    assert!(!logged.contains("8675309"), "Plaintext leaked: {}", logged);
+    
+    let response = IntegerResponse {
+        value: 8675309,
+        ..Default::default()
+    };
+    assert!(!format!("{:?}", response).contains("8675309"));
+    
+    // Redaction holds inside nested messages and oneofs too
+    let request = EncryptAndEvaluateRequest {
+        inputs: vec![PlaintextValue {
+            value: Some(plaintext_value::Value::Integer(8675309)),
+        }],
+        ..Default::default()
+    };
+    assert!(!format!("{:?}", request).contains("8675309"));
+    assert!(!format!("{:#?}", request).contains("8675309"));
+}
+
+#[test]
+fn test_metadata_values_outside_the_allowlist_are_redacted() {
+    let mut headers = HeaderMap::new();
+    headers.insert(TENANT_HEADER, HeaderValue::from_static("acme"));
+    headers.insert("grpc-timeout", HeaderValue::from_static("5S"));
+    headers.insert("authorization", HeaderValue::from_static("Bearer hunter2hunter2hunter2hunter2"));
+    headers.insert("x-custom-bin", HeaderValue::from_static("c2VjcmV0"));
+    
+    let logged = LoggedMetadata(&headers).to_string();
+    assert!(logged.contains("x-tenant-id=acme"), "{}", logged);
+    assert!(logged.contains("grpc-timeout=5S"), "{}", logged);
+    assert!(logged.contains("authorization=<redacted>"), "{}", logged);
+    assert!(logged.contains("x-custom-bin=<redacted>"), "{}", logged);
+    assert!(!logged.contains("hunter2"), "Token leaked: {}", logged);
+    assert!(!logged.contains("c2VjcmV0"), "Binary metadata leaked: {}", logged);
+}
+
+#[test]
+fn test_redacted_hides_the_value() {
+    assert_eq!(format!("{}", Redacted("secret")), "<redacted>");
+    assert_eq!(format!("{:?}", Redacted(vec![1, 2, 3])), "<redacted>");
+}
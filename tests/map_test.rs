@@ -0,0 +1,564 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::{Request, Status};
+
+use hermetic_fhe::api::{
+    circuit_wire, result_sink, CheckpointOptions, CiphertextType, CircuitGate, CircuitWire,
+    DecryptIntegerRequest, EncryptBooleanRequest, EncryptIntegerRequest, EncryptedRecord,
+    ExportCiphertextRequest, FheService, GetMapJobRequest, ImportCiphertextRequest,
+    JoinOperationRequest, KeyGenerationRequest, MapJobStatus, MapOperationRequest, OperationType,
+    ReduceOperationRequest, Reduction, ResultSink, S3Location,
+};
+use hermetic_fhe::api::v1::evaluation_request::OverflowBehavior;
+use hermetic_fhe::crypto::fingerprint::fingerprint_bytes;
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::checkpoint::CheckpointPolicy;
+use hermetic_fhe::service::errors::ErrorReason;
+use hermetic_fhe::service::journal::JobJournal;
+use hermetic_fhe::service::sink::SinkPolicy;
+use hermetic_fhe::service::FheServiceImpl;
+
+async fn setup_service() -> FheServiceImpl {
+    let key_store = Arc::new(KeyStore::new());
+    let ciphertext_store = Arc::new(CiphertextStore::new());
+    FheServiceImpl::new(key_store, ciphertext_store)
+}
+
+// Generate keys, returning (client_key_id, server_key_id)
+async fn generate_keys(service: &FheServiceImpl) -> (String, String) {
+    let keys = service
+        .generate_keys(Request::new(KeyGenerationRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    (keys.client_key_id, keys.server_key_id)
+}
+
+async fn encrypt_integer(service: &FheServiceImpl, client_key_id: &str, value: i64) -> String {
+    let request = Request::new(EncryptIntegerRequest {
+        client_key_id: client_key_id.to_string(),
+        value,
+        num_bits: 8,
+        ..Default::default()
+    });
+    service.encrypt_integer(request).await.unwrap().into_inner().encrypted_data_id
+}
+
+async fn decrypt_integer(service: &FheServiceImpl, client_key_id: &str, encrypted_data_id: &str) -> i64 {
+    let request = Request::new(DecryptIntegerRequest {
+        client_key_id: client_key_id.to_string(),
+        encrypted_data_id: encrypted_data_id.to_string(),
+        serialized_data: vec![],
+        ..Default::default()
+    });
+    service.decrypt_integer(request).await.unwrap().into_inner().value
+}
+
+// Ingest one labeled integer per (label, value)
+async fn ingest(service: &FheServiceImpl, client_key_id: &str, rows: &[(&str, i64)]) {
+    let mut records = Vec::new();
+    for (label, value) in rows {
+        let encrypted_data_id = encrypt_integer(service, client_key_id, *value).await;
+        let request = Request::new(ExportCiphertextRequest { encrypted_data_id });
+        let export = service.export_ciphertext(request).await.unwrap().into_inner();
+        records.push(Ok::<_, Status>(EncryptedRecord {
+            ciphertext_type: CiphertextType::Integer as i32,
+            serialized_data: export.serialized_data,
+            fingerprint: export.fingerprint,
+            label: label.to_string(),
+            session_id: String::new(),
+            ..Default::default()
+        }));
+    }
+    service.ingest(tokio_stream::iter(records)).await.unwrap();
+}
+
+async fn wait_for_job(service: &FheServiceImpl, job_id: &str) -> MapJobStatus {
+    loop {
+        let request = Request::new(GetMapJobRequest {
+            job_id: job_id.to_string(),
+        });
+        let status = service.get_map_job(request).await.unwrap().into_inner();
+        if status.done {
+            return status;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+fn input(index: u32) -> CircuitWire {
+    CircuitWire {
+        source: Some(circuit_wire::Source::Input(index)),
+    }
+}
+
+#[tokio::test]
+async fn test_map_operation_over_labeled_records() {
+    let service = setup_service().await;
+    let (client_key_id, server_key_id) = generate_keys(&service).await;
+    ingest(
+        &service,
+        &client_key_id,
+        &[("salaries/row-1", 30), ("salaries/row-2", 40), ("salaries/row-3", 50), ("bonuses/row-1", 5)],
+    )
+    .await;
+    let raise_id = encrypt_integer(&service, &client_key_id, 10).await;
+    
+    let request = Request::new(MapOperationRequest {
+        server_key_id: server_key_id.clone(),
+        label_prefix: "salaries/".to_string(),
+        result_prefix: "raised/".to_string(),
+        operation: OperationType::Add as i32,
+        operand_ids: vec![raise_id.clone()],
+        ..Default::default()
+    });
+    let started = service.map_operation(request).await.unwrap().into_inner();
+    assert_eq!(started.total, 3, "Only the salaries should be selected");
+    
+    let status = wait_for_job(&service, &started.job_id).await;
+    assert_eq!((status.mapped, status.failed), (3, 0));
+    let mut records = status.records;
+    records.sort_by(|a, b| a.label.cmp(&b.label));
+    for (record, expected) in records.iter().zip([40, 50, 60]) {
+        assert_eq!(record.result_label, record.label.replace("salaries/", "raised/"));
+        assert_eq!(decrypt_integer(&service, &client_key_id, &record.result_id).await, expected);
+    }
+    
+    // The results are a labeled set of their own, here run through a circuit
+    let request = Request::new(MapOperationRequest {
+        server_key_id,
+        label_prefix: "raised/".to_string(),
+        result_prefix: "reverted/".to_string(),
+        operand_ids: vec![raise_id],
+        gates: vec![CircuitGate {
+            operation: OperationType::Subtract as i32,
+            operands: vec![input(0), input(1)],
+        }],
+        ..Default::default()
+    });
+    let started = service.map_operation(request).await.unwrap().into_inner();
+    let status = wait_for_job(&service, &started.job_id).await;
+    assert_eq!((status.total, status.mapped), (3, 3));
+    let reverted = status.records.iter().find(|record| record.label == "raised/row-2").unwrap();
+    assert_eq!(reverted.result_label, "reverted/row-2");
+    assert_eq!(decrypt_integer(&service, &client_key_id, &reverted.result_id).await, 40);
+}
+
+#[tokio::test]
+async fn test_map_operation_rejections() {
+    let service = setup_service().await;
+    let (client_key_id, server_key_id) = generate_keys(&service).await;
+    ingest(&service, &client_key_id, &[("scores/a", 1)]).await;
+    
+    let map_request = |label_prefix: &str, result_prefix: &str, operation: OperationType| {
+        Request::new(MapOperationRequest {
+            server_key_id: server_key_id.clone(),
+            label_prefix: label_prefix.to_string(),
+            result_prefix: result_prefix.to_string(),
+            operation: operation as i32,
+            ..Default::default()
+        })
+    };
+    
+    let status = service
+        .map_operation(map_request("scores/", "scores/", OperationType::IsZero))
+        .await
+        .unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::InvalidRequest));
+    
+    let status = service
+        .map_operation(map_request("nothing/", "out/", OperationType::IsZero))
+        .await
+        .unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::CiphertextNotFound));
+    
+    // NOT needs a boolean, which the first record shows up front
+    let status = service
+        .map_operation(map_request("scores/", "out/", OperationType::Not))
+        .await
+        .unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::TypeMismatch));
+    
+    let request = Request::new(GetMapJobRequest {
+        job_id: "no-such-job".to_string(),
+    });
+    let status = service.get_map_job(request).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::JobNotFound));
+}
+
+#[tokio::test]
+async fn test_map_results_written_to_a_sink() {
+    let root = std::env::temp_dir().join(format!("hermetic-fhe-sink-{}", uuid::Uuid::new_v4()));
+    let service = setup_service().await.with_sink_policy(SinkPolicy::default().with_root(&root).unwrap());
+    let (client_key_id, server_key_id) = generate_keys(&service).await;
+    ingest(&service, &client_key_id, &[("scores/a", 4), ("scores/b", 9)]).await;
+    
+    let map_request = |target: result_sink::Target| {
+        Request::new(MapOperationRequest {
+            server_key_id: server_key_id.clone(),
+            label_prefix: "scores/".to_string(),
+            result_prefix: "doubled/".to_string(),
+            gates: vec![CircuitGate {
+                operation: OperationType::Add as i32,
+                operands: vec![input(0), input(0)],
+            }],
+            sink: Some(ResultSink { target: Some(target) }),
+            ..Default::default()
+        })
+    };
+    
+    let started = service
+        .map_operation(map_request(result_sink::Target::Path("exports".to_string())))
+        .await
+        .unwrap()
+        .into_inner();
+    let status = wait_for_job(&service, &started.job_id).await;
+    assert_eq!((status.mapped, status.failed), (2, 0));
+    
+    // Nothing is stored or labeled; each result is a file named after its label
+    let record = status.records.iter().find(|record| record.label == "scores/b").unwrap();
+    assert!(record.result_id.is_empty());
+    let path = root.join("exports").join("doubled/b");
+    assert_eq!(record.sink_object, path.display().to_string());
+    let bytes = std::fs::read(&path).unwrap();
+    let request = Request::new(ImportCiphertextRequest {
+        ciphertext_type: CiphertextType::Integer as i32,
+        fingerprint: fingerprint_bytes(&bytes),
+        serialized_data: bytes,
+        ..Default::default()
+    });
+    let imported_id = service.import_ciphertext(request).await.unwrap().into_inner().encrypted_data_id;
+    assert_eq!(decrypt_integer(&service, &client_key_id, &imported_id).await, 18);
+    std::fs::remove_dir_all(&root).unwrap();
+    
+    // Paths may not leave the sink directory, and buckets must be allowed
+    let status = service
+        .map_operation(map_request(result_sink::Target::Path("../escape".to_string())))
+        .await
+        .unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::InvalidRequest));
+    let location = S3Location {
+        bucket: "not-allowed".to_string(),
+        prefix: String::new(),
+    };
+    let status = service
+        .map_operation(map_request(result_sink::Target::S3(location)))
+        .await
+        .unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::PolicyViolation));
+}
+
+#[tokio::test]
+async fn test_map_job_resumes_from_checkpoint() {
+    let dir = std::env::temp_dir().join(format!("hermetic-fhe-checkpoint-{}", uuid::Uuid::new_v4()));
+    let policy = CheckpointPolicy::default().with_dir(&dir).unwrap();
+    let service = setup_service().await.with_checkpoint_policy(policy);
+    let (client_key_id, server_key_id) = generate_keys(&service).await;
+    ingest(&service, &client_key_id, &[("scores/a", 4), ("scores/b", 9)]).await;
+    
+    // A boolean can't be added to, so its record fails every time
+    let request = Request::new(EncryptBooleanRequest {
+        client_key_id: client_key_id.clone(),
+        value: true,
+        ..Default::default()
+    });
+    let encrypted_data_id = service.encrypt_boolean(request).await.unwrap().into_inner().encrypted_data_id;
+    let request = Request::new(ExportCiphertextRequest { encrypted_data_id });
+    let export = service.export_ciphertext(request).await.unwrap().into_inner();
+    let record = EncryptedRecord {
+        ciphertext_type: CiphertextType::Boolean as i32,
+        serialized_data: export.serialized_data,
+        fingerprint: export.fingerprint,
+        label: "scores/c".to_string(),
+        ..Default::default()
+    };
+    service.ingest(tokio_stream::iter([Ok::<_, Status>(record)])).await.unwrap();
+    
+    let map_request = || {
+        Request::new(MapOperationRequest {
+            server_key_id: server_key_id.clone(),
+            label_prefix: "scores/".to_string(),
+            result_prefix: "doubled/".to_string(),
+            gates: vec![CircuitGate {
+                operation: OperationType::Add as i32,
+                operands: vec![input(0), input(0)],
+            }],
+            checkpoint: Some(CheckpointOptions {
+                name: "doubling".to_string(),
+                interval_seconds: 3600,
+            }),
+            ..Default::default()
+        })
+    };
+    let started = service.map_operation(map_request()).await.unwrap().into_inner();
+    let status = wait_for_job(&service, &started.job_id).await;
+    assert_eq!((status.mapped, status.failed, status.resumed), (2, 1, 0));
+    
+    // The checkpoint outlives a job with failures, and a rerun only tries those again
+    assert!(dir.join("doubling.checkpoint").exists());
+    let started = service.map_operation(map_request()).await.unwrap().into_inner();
+    let status = wait_for_job(&service, &started.job_id).await;
+    assert_eq!((status.total, status.mapped, status.failed, status.resumed), (3, 2, 1, 2));
+    let record = status.records.iter().find(|record| record.label == "scores/b").unwrap();
+    assert_eq!(record.result_label, "doubled/b");
+    assert_eq!(decrypt_integer(&service, &client_key_id, &record.result_id).await, 18);
+    let failed = status.records.iter().find(|record| record.label == "scores/c").unwrap();
+    assert!(!failed.error.is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// Doubles the scores under result_prefix, naming the job with the token if one is given
+fn deduplicated(server_key_id: &str, result_prefix: &str, dedup_token: &str) -> Request<MapOperationRequest> {
+    Request::new(MapOperationRequest {
+        server_key_id: server_key_id.to_string(),
+        label_prefix: "scores/".to_string(),
+        result_prefix: result_prefix.to_string(),
+        gates: vec![CircuitGate {
+            operation: OperationType::Add as i32,
+            operands: vec![input(0), input(0)],
+        }],
+        dedup_token: dedup_token.to_string(),
+        ..Default::default()
+    })
+}
+
+// The result ID of a record, once its job is done
+async fn result_of(service: &FheServiceImpl, job_id: &str, label: &str) -> String {
+    let status = wait_for_job(service, job_id).await;
+    let record = status.records.into_iter().find(|record| record.label == label).unwrap();
+    record.result_id
+}
+
+#[tokio::test]
+async fn test_dedup_token_runs_a_job_once() {
+    let service = setup_service().await;
+    let (client_key_id, server_key_id) = generate_keys(&service).await;
+    ingest(&service, &client_key_id, &[("scores/a", 4), ("scores/b", 9)]).await;
+    
+    // A retry gets the job the first call started, done or not, rather than a second one
+    let request = deduplicated(&server_key_id, "doubled/", "nightly-doubling");
+    let started = service.map_operation(request).await.unwrap().into_inner();
+    let status = wait_for_job(&service, &started.job_id).await;
+    assert_eq!((status.mapped, status.failed), (2, 0));
+    let request = deduplicated(&server_key_id, "doubled/", "nightly-doubling");
+    let retried = service.map_operation(request).await.unwrap().into_inner();
+    assert_eq!(retried.job_id, started.job_id);
+    assert!(retried.done);
+    assert_eq!(retried.records.len(), 2);
+    
+    // The token can't name a different request, and must be one a file name could hold
+    let request = deduplicated(&server_key_id, "tripled/", "nightly-doubling");
+    let status = service.map_operation(request).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::DedupConflict));
+    let request = deduplicated(&server_key_id, "doubled/", "../nightly");
+    let status = service.map_operation(request).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::InvalidRequest));
+    
+    // Without a token every call is a job of its own
+    let request = deduplicated(&server_key_id, "doubled/", "");
+    let another = service.map_operation(request).await.unwrap().into_inner();
+    assert_ne!(another.job_id, started.job_id);
+    wait_for_job(&service, &another.job_id).await;
+    let record = result_of(&service, &started.job_id, "scores/b").await;
+    assert_eq!(decrypt_integer(&service, &client_key_id, &record).await, 18);
+}
+
+#[tokio::test]
+async fn test_journaled_results_survive_a_restart() {
+    let dir = std::env::temp_dir().join(format!("hermetic-fhe-journal-{}", uuid::Uuid::new_v4()));
+    let key_store = Arc::new(KeyStore::new());
+    let service = FheServiceImpl::new(key_store.clone(), Arc::new(CiphertextStore::new()))
+        .with_job_journal(JobJournal::with_dir(&dir).unwrap());
+    let (client_key_id, server_key_id) = generate_keys(&service).await;
+    ingest(&service, &client_key_id, &[("scores/a", 4), ("scores/b", 9)]).await;
+    let request = deduplicated(&server_key_id, "doubled/", "nightly-doubling");
+    let started = service.map_operation(request).await.unwrap().into_inner();
+    let result_id = result_of(&service, &started.job_id, "scores/b").await;
+    
+    // Drop the Finished entry, its 8-byte length and 4-byte variant, as a crash before the
+    // job finished would. A crash mid-append leaves a torn entry, which replay drops.
+    let journal = std::fs::read_dir(&dir).unwrap().next().unwrap().unwrap().path();
+    let mut torn = std::fs::read(&journal).unwrap();
+    torn.truncate(torn.len() - 12);
+    torn.extend_from_slice(&[0, 0, 0, 0, 0, 0, 1, 0, 7]);
+    std::fs::write(&journal, torn).unwrap();
+    
+    // The restarted server has lost every ciphertext, but stores the job's results again
+    // under the IDs and labels they had
+    let restarted = FheServiceImpl::new(key_store, Arc::new(CiphertextStore::new()))
+        .with_job_journal(JobJournal::with_dir(&dir).unwrap());
+    assert_eq!(restarted.replay_job_journal().unwrap(), 1);
+    let request = Request::new(GetMapJobRequest {
+        job_id: started.job_id.clone(),
+    });
+    let status = restarted.get_map_job(request).await.unwrap().into_inner();
+    assert_eq!((status.total, status.mapped, status.failed, status.done), (2, 2, 0, false));
+    let record = status.records.iter().find(|record| record.label == "scores/b").unwrap();
+    assert_eq!(record.result_id, result_id);
+    assert_eq!(decrypt_integer(&restarted, &client_key_id, &result_id).await, 18);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_finished_journals_expire() {
+    let dir = std::env::temp_dir().join(format!("hermetic-fhe-journal-{}", uuid::Uuid::new_v4()));
+    let key_store = Arc::new(KeyStore::new());
+    let service = FheServiceImpl::new(key_store.clone(), Arc::new(CiphertextStore::new()))
+        .with_job_journal(JobJournal::with_dir(&dir).unwrap());
+    let (client_key_id, server_key_id) = generate_keys(&service).await;
+    ingest(&service, &client_key_id, &[("scores/a", 4), ("scores/b", 9)]).await;
+    let request = deduplicated(&server_key_id, "doubled/", "nightly-doubling");
+    let started = service.map_operation(request).await.unwrap().into_inner();
+    let result_id = result_of(&service, &started.job_id, "scores/b").await;
+    
+    // A finished job's token outlives a restart, but its results aren't stored again, since
+    // the client may have fetched or deleted them already
+    let ciphertext_store = Arc::new(CiphertextStore::new());
+    let restarted = FheServiceImpl::new(key_store.clone(), ciphertext_store.clone())
+        .with_job_journal(JobJournal::with_dir(&dir).unwrap());
+    assert_eq!(restarted.replay_job_journal().unwrap(), 1);
+    assert!(wait_for_job(&restarted, &started.job_id).await.done);
+    assert!(ciphertext_store.get(&result_id).is_none(), "Finished results should not come back");
+    
+    // Past the retention its journal is deleted at startup
+    let journal = JobJournal::with_dir(&dir).unwrap().with_retention(Duration::ZERO);
+    let expired = FheServiceImpl::new(key_store, Arc::new(CiphertextStore::new())).with_job_journal(journal);
+    assert_eq!(expired.replay_job_journal().unwrap(), 0);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+    std::fs::remove_dir_all(&dir).unwrap();
+    
+    // and a running server forgets the token, so it starts a new job
+    let service = service.with_job_journal(JobJournal::default().with_retention(Duration::ZERO));
+    let request = deduplicated(&server_key_id, "doubled/", "nightly-doubling");
+    let first = service.map_operation(request).await.unwrap().into_inner();
+    wait_for_job(&service, &first.job_id).await;
+    let request = deduplicated(&server_key_id, "doubled/", "nightly-doubling");
+    let second = service.map_operation(request).await.unwrap().into_inner();
+    assert_ne!(second.job_id, first.job_id);
+}
+
+#[tokio::test]
+async fn test_join_operation_pairs_records_by_key() {
+    let service = setup_service().await;
+    let (client_key_id, server_key_id) = generate_keys(&service).await;
+    ingest(
+        &service,
+        &client_key_id,
+        &[
+            ("price/order-1", 12),
+            ("price/order-2", 7),
+            ("price/order-3", 9),
+            ("quantity/order-1", 3),
+            ("quantity/order-2", 5),
+            ("quantity/order-4", 2),
+        ],
+    )
+    .await;
+    
+    let join_request = |result_prefix: &str, operation: OperationType| {
+        Request::new(JoinOperationRequest {
+            server_key_id: server_key_id.clone(),
+            left_prefix: "price/".to_string(),
+            right_prefix: "quantity/".to_string(),
+            result_prefix: result_prefix.to_string(),
+            operation: operation as i32,
+            ..Default::default()
+        })
+    };
+    
+    // Orders 3 and 4 have no partner and are left out
+    let started = service
+        .join_operation(join_request("total/", OperationType::Multiply))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(started.total, 2);
+    let status = wait_for_job(&service, &started.job_id).await;
+    assert_eq!((status.mapped, status.failed), (2, 0));
+    let mut records = status.records;
+    records.sort_by(|a, b| a.label.cmp(&b.label));
+    for (record, (label, expected)) in records.iter().zip([("total/order-1", 36), ("total/order-2", 35)]) {
+        assert_eq!(record.result_label, label);
+        assert_eq!(decrypt_integer(&service, &client_key_id, &record.result_id).await, expected);
+    }
+    
+    // The totals are a labeled set like any other
+    let request = Request::new(ReduceOperationRequest {
+        server_key_id: server_key_id.clone(),
+        label_prefix: "total/".to_string(),
+        ..Default::default()
+    });
+    let result_id = service.reduce_operation(request).await.unwrap().into_inner().result_id;
+    assert_eq!(decrypt_integer(&service, &client_key_id, &result_id).await, 71);
+    
+    let status = service
+        .join_operation(join_request("price/", OperationType::Multiply))
+        .await
+        .unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::InvalidRequest));
+    
+    // Each pair is two inputs, which a unary operation cannot take
+    let status = service
+        .join_operation(join_request("out/", OperationType::IsZero))
+        .await
+        .unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::ArityMismatch));
+}
+
+#[tokio::test]
+async fn test_reduce_operation_over_labeled_records() {
+    let service = setup_service().await;
+    let (client_key_id, server_key_id) = generate_keys(&service).await;
+    // An odd count, so one record sits out a round of the tree
+    let rows: Vec<(String, i64)> = (1..=7).map(|i| (format!("readings/{}", i), i * 30)).collect();
+    let rows: Vec<(&str, i64)> = rows.iter().map(|(label, value)| (label.as_str(), *value)).collect();
+    ingest(&service, &client_key_id, &rows).await;
+    
+    let reduce_request = |reduction: Reduction, overflow: OverflowBehavior, result_label: &str| {
+        Request::new(ReduceOperationRequest {
+            server_key_id: server_key_id.clone(),
+            label_prefix: "readings/".to_string(),
+            reduction: reduction as i32,
+            overflow: overflow as i32,
+            result_label: result_label.to_string(),
+            session_id: String::new(),
+        })
+    };
+    
+    // 30 + 60 + ... + 210 = 840, which wraps to 72 unless saturated
+    for (reduction, overflow, expected) in [
+        (Reduction::Sum, OverflowBehavior::Wrap, 840 % 256),
+        (Reduction::Sum, OverflowBehavior::Saturate, 255),
+        (Reduction::Min, OverflowBehavior::Wrap, 30),
+        (Reduction::Max, OverflowBehavior::Wrap, 210),
+    ] {
+        let request = reduce_request(reduction, overflow, "");
+        let result_id = service.reduce_operation(request).await.unwrap().into_inner().result_id;
+        assert_eq!(decrypt_integer(&service, &client_key_id, &result_id).await, expected, "{:?}", reduction);
+    }
+    
+    // A labeled result joins the dataset, here as a record of its own prefix
+    let request = reduce_request(Reduction::Max, OverflowBehavior::Wrap, "summary/max");
+    service.reduce_operation(request).await.unwrap();
+    let request = Request::new(MapOperationRequest {
+        server_key_id: server_key_id.clone(),
+        label_prefix: "summary/".to_string(),
+        result_prefix: "checked/".to_string(),
+        operation: OperationType::IsZero as i32,
+        ..Default::default()
+    });
+    let started = service.map_operation(request).await.unwrap().into_inner();
+    assert_eq!(started.total, 1);
+    
+    // ANY needs booleans, and overflow only applies to SUM
+    let status = service
+        .reduce_operation(reduce_request(Reduction::Any, OverflowBehavior::Wrap, ""))
+        .await
+        .unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::TypeMismatch));
+    let status = service
+        .reduce_operation(reduce_request(Reduction::Max, OverflowBehavior::Saturate, ""))
+        .await
+        .unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::InvalidRequest));
+}
@@ -0,0 +1,262 @@
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+use tonic::Request;
+
+use hermetic_fhe::api::{
+    DecryptIntegerRequest, DecryptMatrixRequest, EncryptIntegerRequest, EncryptMatrixRequest,
+    FheService, ImportCiphertextRequest, KeyGenerationRequest, MatrixAddRequest,
+    MatrixScaleRequest, MatrixVectorProductRequest, StreamCiphertextsRequest,
+};
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::FheServiceImpl;
+
+async fn setup_service() -> FheServiceImpl {
+    let key_store = Arc::new(KeyStore::new());
+    let ciphertext_store = Arc::new(CiphertextStore::new());
+    FheServiceImpl::new(key_store, ciphertext_store)
+}
+
+// Generate keys, returning (client_key_id, server_key_id)
+async fn generate_keys(service: &FheServiceImpl) -> (String, String) {
+    let key_gen_request = Request::new(KeyGenerationRequest {
+        parameter_set: 0, // DEFAULT
+        ..Default::default()
+    });
+    
+    let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
+    let keys = key_gen_response.into_inner();
+    (keys.client_key_id, keys.server_key_id)
+}
+
+async fn encrypt_matrix(service: &FheServiceImpl, client_key_id: &str, rows: u32, cols: u32, values: Vec<i64>) -> String {
+    let request = Request::new(EncryptMatrixRequest {
+        client_key_id: client_key_id.to_string(),
+        rows,
+        cols,
+        values,
+        ..Default::default()
+    });
+    
+    service.encrypt_matrix(request).await.unwrap().into_inner().matrix_id
+}
+
+async fn decrypt_matrix(service: &FheServiceImpl, client_key_id: &str, matrix_id: &str) -> Vec<i64> {
+    let request = Request::new(DecryptMatrixRequest {
+        client_key_id: client_key_id.to_string(),
+        matrix_id: matrix_id.to_string(),
+    });
+    
+    service.decrypt_matrix(request).await.unwrap().into_inner().values
+}
+
+async fn decrypt_integer(service: &FheServiceImpl, client_key_id: &str, encrypted_data_id: &str) -> i64 {
+    let decrypt_request = Request::new(DecryptIntegerRequest {
+        client_key_id: client_key_id.to_string(),
+        encrypted_data_id: encrypted_data_id.to_string(),
+        serialized_data: vec![],
+        ..Default::default()
+    });
+    
+    service.decrypt_integer(decrypt_request).await.unwrap().get_ref().value
+}
+
+#[tokio::test]
+async fn test_encrypt_decrypt_matrix() {
+    let service = setup_service().await;
+    let (client_key_id, _) = generate_keys(&service).await;
+    
+    let request = Request::new(EncryptMatrixRequest {
+        client_key_id: client_key_id.clone(),
+        rows: 2,
+        cols: 2,
+        values: vec![1, 2, 3, 4],
+        ..Default::default()
+    });
+    let response = service.encrypt_matrix(request).await.unwrap().into_inner();
+    assert_eq!((response.rows, response.cols), (2, 2));
+    assert!(!response.fingerprint.is_empty());
+    
+    assert_eq!(decrypt_matrix(&service, &client_key_id, &response.matrix_id).await, vec![1, 2, 3, 4]);
+}
+
+#[tokio::test]
+async fn test_matrix_vector_product() {
+    let service = setup_service().await;
+    let (client_key_id, server_key_id) = generate_keys(&service).await;
+    
+    // [[1, 2, 3], [4, 5, 6]]
+    let matrix_id = encrypt_matrix(&service, &client_key_id, 2, 3, vec![1, 2, 3, 4, 5, 6]).await;
+    
+    // Plaintext weights
+    let request = Request::new(MatrixVectorProductRequest {
+        server_key_id: server_key_id.clone(),
+        matrix_id: matrix_id.clone(),
+        plaintext_vector: vec![1, 0, 2],
+        ..Default::default()
+    });
+    let response = service.matrix_vector_product(request).await.unwrap().into_inner();
+    assert_eq!(response.result_ids.len(), 2);
+    assert_eq!(decrypt_integer(&service, &client_key_id, &response.result_ids[0]).await, 7);
+    assert_eq!(decrypt_integer(&service, &client_key_id, &response.result_ids[1]).await, 16);
+    
+    // Encrypted vector
+    let mut vector_ids = Vec::new();
+    for value in [2, 1, 1] {
+        let encrypt_request = Request::new(EncryptIntegerRequest {
+            client_key_id: client_key_id.clone(),
+            value,
+            num_bits: 8,
+            ..Default::default()
+        });
+        vector_ids.push(service.encrypt_integer(encrypt_request).await.unwrap().into_inner().encrypted_data_id);
+    }
+    let request = Request::new(MatrixVectorProductRequest {
+        server_key_id,
+        matrix_id,
+        vector_ids,
+        ..Default::default()
+    });
+    let response = service.matrix_vector_product(request).await.unwrap().into_inner();
+    assert_eq!(decrypt_integer(&service, &client_key_id, &response.result_ids[0]).await, 7);
+    assert_eq!(decrypt_integer(&service, &client_key_id, &response.result_ids[1]).await, 19);
+}
+
+#[tokio::test]
+async fn test_matrix_add_and_scale() {
+    let service = setup_service().await;
+    let (client_key_id, server_key_id) = generate_keys(&service).await;
+    
+    let a_id = encrypt_matrix(&service, &client_key_id, 2, 2, vec![1, 2, 3, 4]).await;
+    let b_id = encrypt_matrix(&service, &client_key_id, 2, 2, vec![10, 20, 30, 40]).await;
+    
+    let request = Request::new(MatrixAddRequest {
+        server_key_id: server_key_id.clone(),
+        a_id,
+        b_id,
+        ..Default::default()
+    });
+    let sum_id = service.matrix_add(request).await.unwrap().into_inner().matrix_id;
+    assert_eq!(decrypt_matrix(&service, &client_key_id, &sum_id).await, vec![11, 22, 33, 44]);
+    
+    let request = Request::new(MatrixScaleRequest {
+        server_key_id,
+        matrix_id: sum_id,
+        scalar: 3,
+        ..Default::default()
+    });
+    let scaled_id = service.matrix_scale(request).await.unwrap().into_inner().matrix_id;
+    assert_eq!(decrypt_matrix(&service, &client_key_id, &scaled_id).await, vec![33, 66, 99, 132]);
+}
+
+#[tokio::test]
+async fn test_matrix_shape_mismatch() {
+    let service = setup_service().await;
+    let (client_key_id, server_key_id) = generate_keys(&service).await;
+    
+    // Element count does not match the shape
+    let request = Request::new(EncryptMatrixRequest {
+        client_key_id: client_key_id.clone(),
+        rows: 2,
+        cols: 2,
+        values: vec![1, 2, 3],
+        ..Default::default()
+    });
+    let status = service.encrypt_matrix(request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    
+    // Vector length does not match the column count
+    let matrix_id = encrypt_matrix(&service, &client_key_id, 1, 2, vec![1, 2]).await;
+    let request = Request::new(MatrixVectorProductRequest {
+        server_key_id,
+        matrix_id,
+        plaintext_vector: vec![1, 2, 3],
+        ..Default::default()
+    });
+    let status = service.matrix_vector_product(request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn test_stream_matrix_resumes_from_offset() {
+    let service = setup_service().await;
+    let (client_key_id, _) = generate_keys(&service).await;
+    let matrix_id = encrypt_matrix(&service, &client_key_id, 2, 3, vec![1, 2, 3, 4, 5, 6]).await;
+    
+    // First page of two elements, then resume after the last index received
+    let request = Request::new(StreamCiphertextsRequest {
+        matrix_id: matrix_id.clone(),
+        limit: 2,
+        ..Default::default()
+    });
+    let stream = service.stream_ciphertexts(request).await.unwrap().into_inner();
+    let first: Vec<_> = stream.collect::<Result<_, _>>().await.unwrap();
+    assert_eq!(first.iter().map(|c| c.index).collect::<Vec<_>>(), vec![0, 1]);
+    assert!(first.iter().all(|c| c.total == 6));
+    
+    let request = Request::new(StreamCiphertextsRequest {
+        matrix_id,
+        offset: first.last().unwrap().index + 1,
+        ..Default::default()
+    });
+    let stream = service.stream_ciphertexts(request).await.unwrap().into_inner();
+    let rest: Vec<_> = stream.collect::<Result<_, _>>().await.unwrap();
+    assert_eq!(rest.iter().map(|c| c.index).collect::<Vec<_>>(), vec![2, 3, 4, 5]);
+    
+    // Every chunk imports as a standalone ciphertext holding the matching element
+    let mut values = Vec::new();
+    for chunk in first.into_iter().chain(rest) {
+        let request = Request::new(ImportCiphertextRequest {
+            ciphertext_type: chunk.ciphertext_type,
+            serialized_data: chunk.serialized_data,
+            fingerprint: chunk.fingerprint,
+            ..Default::default()
+        });
+        let id = service.import_ciphertext(request).await.unwrap().into_inner().encrypted_data_id;
+        values.push(decrypt_integer(&service, &client_key_id, &id).await);
+    }
+    assert_eq!(values, vec![1, 2, 3, 4, 5, 6]);
+}
+
+#[tokio::test]
+async fn test_stream_vector_elements() {
+    let service = setup_service().await;
+    let (client_key_id, _) = generate_keys(&service).await;
+    
+    let mut ids = Vec::new();
+    for value in [7, 8, 9] {
+        let request = Request::new(EncryptIntegerRequest {
+            client_key_id: client_key_id.clone(),
+            value,
+            num_bits: 8,
+            ..Default::default()
+        });
+        ids.push(service.encrypt_integer(request).await.unwrap().into_inner().encrypted_data_id);
+    }
+    
+    let request = Request::new(StreamCiphertextsRequest {
+        encrypted_data_ids: ids.clone(),
+        offset: 1,
+        ..Default::default()
+    });
+    let stream = service.stream_ciphertexts(request).await.unwrap().into_inner();
+    let chunks: Vec<_> = stream.collect::<Result<_, _>>().await.unwrap();
+    assert_eq!(chunks.iter().map(|c| c.index).collect::<Vec<_>>(), vec![1, 2]);
+    
+    // An offset past the end is rejected rather than returning an empty page
+    let request = Request::new(StreamCiphertextsRequest {
+        encrypted_data_ids: ids,
+        offset: 4,
+        ..Default::default()
+    });
+    let status = service.stream_ciphertexts(request).await.err().unwrap();
+    assert_eq!(status.code(), tonic::Code::OutOfRange);
+    
+    // A missing element fails the stream when it is reached
+    let request = Request::new(StreamCiphertextsRequest {
+        encrypted_data_ids: vec!["missing".to_string()],
+        ..Default::default()
+    });
+    let mut stream = service.stream_ciphertexts(request).await.unwrap().into_inner();
+    let status = stream.next().await.unwrap().unwrap_err();
+    assert_eq!(status.code(), tonic::Code::NotFound);
+}
@@ -0,0 +1,123 @@
+use std::sync::Arc;
+use tonic::Request;
+
+use hermetic_fhe::api::{
+    CloseSessionRequest, CreateSessionRequest, DeleteKeyPairRequest, EncryptBooleanRequest, FheAdminService,
+    FheService, KeyGenerationRequest, MetricsRequest, MetricsResponse, StatsRequest,
+};
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::admin::FheAdminServiceImpl;
+use hermetic_fhe::service::errors::ErrorReason;
+use hermetic_fhe::service::memory::{MemoryLimit, MemoryPolicy};
+use hermetic_fhe::service::FheServiceImpl;
+
+async fn generate_keys(service: &FheServiceImpl) -> String {
+    let key_gen_request = Request::new(KeyGenerationRequest {
+        parameter_set: 0, // DEFAULT
+        ..Default::default()
+    });
+    service.generate_keys(key_gen_request).await.unwrap().into_inner().client_key_id
+}
+
+fn encrypt_request(client_key_id: &str, session_id: &str) -> Request<EncryptBooleanRequest> {
+    Request::new(EncryptBooleanRequest {
+        client_key_id: client_key_id.to_string(),
+        value: true,
+        session_id: session_id.to_string(),
+        ..Default::default()
+    })
+}
+
+async fn metrics(service: &FheServiceImpl) -> MetricsResponse {
+    service.get_metrics(Request::new(MetricsRequest {})).await.unwrap().into_inner()
+}
+
+#[tokio::test]
+async fn test_store_memory_is_reported() {
+    let key_store = Arc::new(KeyStore::new());
+    let ciphertext_store = Arc::new(CiphertextStore::new());
+    let service = FheServiceImpl::new(key_store.clone(), ciphertext_store.clone());
+    
+    let client_key_id = generate_keys(&service).await;
+    let encrypted = service.encrypt_boolean(encrypt_request(&client_key_id, "")).await.unwrap().into_inner();
+    
+    // Estimates are serialized sizes
+    let metrics = metrics(&service).await;
+    let key_bytes = metrics.key_store.unwrap().memory_bytes;
+    let ciphertext_bytes = metrics.ciphertext_store.unwrap().memory_bytes;
+    let (serialized, _) = ciphertext_store.get(&encrypted.encrypted_data_id).unwrap().serialize_with_fingerprint().unwrap();
+    assert_eq!(ciphertext_bytes, serialized.len() as u64);
+    assert!(key_bytes > ciphertext_bytes, "A key pair should outweigh one ciphertext");
+    
+    let memory = metrics.memory.unwrap();
+    assert_eq!(memory.used_bytes, key_bytes + ciphertext_bytes);
+    assert_eq!(memory.limit_bytes, 0, "No limit is set by default");
+    
+    // The admin service reports the same figures
+    let admin = FheAdminServiceImpl::new(service.clone());
+    let stats = admin.get_stats(Request::new(StatsRequest {})).await.unwrap().into_inner();
+    assert_eq!(stats.metrics.unwrap().memory.unwrap().used_bytes, memory.used_bytes);
+    
+    // Freeing entries gives their bytes back
+    assert!(ciphertext_store.remove(&encrypted.encrypted_data_id));
+    admin
+        .delete_key_pair(Request::new(DeleteKeyPairRequest { key_id: client_key_id }))
+        .await
+        .unwrap();
+    assert_eq!((key_store.memory_bytes(), ciphertext_store.memory_bytes()), (0, 0));
+}
+
+#[tokio::test]
+async fn test_memory_limit_rejects_new_data() {
+    let service = FheServiceImpl::new(Arc::new(KeyStore::new()), Arc::new(CiphertextStore::new()))
+        .with_memory_limit(MemoryLimit { max_bytes: 1, policy: MemoryPolicy::Reject });
+    
+    // The stores start empty, so the keys are let in and take them over the limit
+    let client_key_id = generate_keys(&service).await;
+    
+    let status = service.encrypt_boolean(encrypt_request(&client_key_id, "")).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::Overloaded));
+    
+    let memory = metrics(&service).await.memory.unwrap();
+    assert_eq!((memory.limit_bytes, memory.rejected_total), (1, 1));
+}
+
+#[tokio::test]
+async fn test_memory_limit_evicts_idle_sessions() {
+    let key_store = Arc::new(KeyStore::new());
+    let ciphertext_store = Arc::new(CiphertextStore::new());
+    let client_key_id = generate_keys(&FheServiceImpl::new(key_store.clone(), ciphertext_store.clone())).await;
+    
+    // Room for the keys and nothing else
+    let limit = MemoryLimit {
+        max_bytes: key_store.memory_bytes() + 1,
+        policy: MemoryPolicy::EvictSessions,
+    };
+    let service = FheServiceImpl::new(key_store, ciphertext_store.clone()).with_memory_limit(limit);
+    
+    let session_id = service
+        .create_session(Request::new(CreateSessionRequest { idle_timeout_seconds: 0 }))
+        .await
+        .unwrap()
+        .into_inner()
+        .session_id;
+    let in_session = service.encrypt_boolean(encrypt_request(&client_key_id, &session_id)).await.unwrap().into_inner();
+    
+    // Now over the limit: the idle session is closed to make room
+    let outside = service.encrypt_boolean(encrypt_request(&client_key_id, "")).await.unwrap().into_inner();
+    assert!(ciphertext_store.get(&in_session.encrypted_data_id).is_none(), "Session ciphertext should be evicted");
+    assert!(ciphertext_store.get(&outside.encrypted_data_id).is_some());
+    let status = service
+        .close_session(Request::new(CloseSessionRequest { session_id }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::NotFound);
+    
+    // Ciphertexts outside sessions are never evicted, so with none left the next request is turned away
+    let status = service.encrypt_boolean(encrypt_request(&client_key_id, "")).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+    
+    let memory = metrics(&service).await.memory.unwrap();
+    assert_eq!((memory.evicted_sessions_total, memory.rejected_total), (1, 1));
+}
@@ -0,0 +1,222 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::Request;
+
+use hermetic_fhe::api::v1::key_generation_request::Scheme;
+use hermetic_fhe::api::{
+    DecryptBooleanRequest, DecryptIntegerBatchRequest, DecryptIntegerRequest, DecryptMatrixRequest,
+    DecryptRealVectorRequest, EncryptBooleanRequest, EncryptIntegerBatchRequest, EncryptIntegerRequest,
+    EncryptMatrixRequest, EncryptRealVectorRequest, FheAdminService, FheService, GetMigrationRequest,
+    KeyGenerationRequest, MigrationStatus, StartMigrationRequest,
+};
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::admin::FheAdminServiceImpl;
+use hermetic_fhe::service::errors::ErrorReason;
+use hermetic_fhe::service::FheServiceImpl;
+
+async fn setup_services() -> (FheServiceImpl, FheAdminServiceImpl) {
+    let key_store = Arc::new(KeyStore::new());
+    let ciphertext_store = Arc::new(CiphertextStore::new());
+    let service = FheServiceImpl::new(key_store, ciphertext_store);
+    let admin = FheAdminServiceImpl::new(service.clone());
+    (service, admin)
+}
+
+// Generate a client key for a scheme and parameter set
+async fn generate_client_key(service: &FheServiceImpl, scheme: Scheme, parameter_set: i32) -> String {
+    let key_gen_request = Request::new(KeyGenerationRequest {
+        scheme: scheme as i32,
+        parameter_set,
+        ..Default::default()
+    });
+    
+    service.generate_keys(key_gen_request).await.unwrap().into_inner().client_key_id
+}
+
+async fn migrate(
+    admin: &FheAdminServiceImpl,
+    source_client_key_id: &str,
+    target_client_key_id: &str,
+    ids: &[&str],
+    delete_source: bool,
+) -> Result<MigrationStatus, tonic::Status> {
+    let request = Request::new(StartMigrationRequest {
+        source_client_key_id: source_client_key_id.to_string(),
+        target_client_key_id: target_client_key_id.to_string(),
+        encrypted_data_ids: ids.iter().map(|id| id.to_string()).collect(),
+        delete_source,
+        ..Default::default()
+    });
+    
+    Ok(admin.start_migration(request).await?.into_inner())
+}
+
+// Poll a migration until its job has processed every ciphertext
+async fn wait_for(admin: &FheAdminServiceImpl, migration_id: &str) -> MigrationStatus {
+    loop {
+        let request = Request::new(GetMigrationRequest {
+            migration_id: migration_id.to_string(),
+        });
+        let status = admin.get_migration(request).await.unwrap().into_inner();
+        if status.done {
+            return status;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[tokio::test]
+async fn test_migrate_to_another_parameter_set() {
+    let (service, admin) = setup_services().await;
+    let source_key_id = generate_client_key(&service, Scheme::Tfhe, 1).await; // FAST
+    let target_key_id = generate_client_key(&service, Scheme::Tfhe, 0).await; // DEFAULT
+    
+    let boolean_id = service
+        .encrypt_boolean(Request::new(EncryptBooleanRequest {
+            client_key_id: source_key_id.clone(),
+            value: true,
+            ..Default::default()
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .encrypted_data_id;
+    let integer_id = service
+        .encrypt_integer(Request::new(EncryptIntegerRequest {
+            client_key_id: source_key_id.clone(),
+            value: 42,
+            num_bits: 8,
+            ..Default::default()
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .encrypted_data_id;
+    let matrix_id = service
+        .encrypt_matrix(Request::new(EncryptMatrixRequest {
+            client_key_id: source_key_id.clone(),
+            rows: 2,
+            cols: 2,
+            values: vec![1, 2, 3, 4],
+            ..Default::default()
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .matrix_id;
+    
+    let ids = [boolean_id.as_str(), &integer_id, &matrix_id];
+    let started = migrate(&admin, &source_key_id, &target_key_id, &ids, true).await.unwrap();
+    assert_eq!(started.total, 3);
+    assert_eq!(started.source_scheme, Scheme::Tfhe as i32);
+    
+    let status = wait_for(&admin, &started.migration_id).await;
+    assert_eq!((status.migrated, status.failed), (3, 0));
+    let target_ids: Vec<String> = status.ciphertexts.iter().map(|c| c.target_id.clone()).collect();
+    assert_eq!(status.ciphertexts[0].source_id, boolean_id);
+    
+    // Everything decrypts to the same values under the target key
+    let request = Request::new(DecryptBooleanRequest {
+        client_key_id: target_key_id.clone(),
+        encrypted_data_id: target_ids[0].clone(),
+        ..Default::default()
+    });
+    assert!(service.decrypt_boolean(request).await.unwrap().into_inner().value);
+    let request = Request::new(DecryptIntegerRequest {
+        client_key_id: target_key_id.clone(),
+        encrypted_data_id: target_ids[1].clone(),
+        ..Default::default()
+    });
+    assert_eq!(service.decrypt_integer(request).await.unwrap().into_inner().value, 42);
+    let request = Request::new(DecryptMatrixRequest {
+        client_key_id: target_key_id.clone(),
+        matrix_id: target_ids[2].clone(),
+    });
+    let matrix = service.decrypt_matrix(request).await.unwrap().into_inner();
+    assert_eq!((matrix.rows, matrix.cols, matrix.values), (2, 2, vec![1, 2, 3, 4]));
+    
+    // The sources were deleted as they were migrated
+    let request = Request::new(DecryptIntegerRequest {
+        client_key_id: source_key_id,
+        encrypted_data_id: integer_id,
+        ..Default::default()
+    });
+    let status = service.decrypt_integer(request).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::CiphertextNotFound));
+}
+
+#[tokio::test]
+async fn test_migrate_across_schemes() {
+    let (service, admin) = setup_services().await;
+    let bgv_key_id = generate_client_key(&service, Scheme::Bgv, 0).await;
+    let ckks_key_id = generate_client_key(&service, Scheme::Ckks, 0).await;
+    
+    let batch_id = service
+        .encrypt_integer_batch(Request::new(EncryptIntegerBatchRequest {
+            client_key_id: bgv_key_id.clone(),
+            values: vec![3, -7, 1000],
+            ..Default::default()
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .encrypted_data_id;
+    let vector_id = service
+        .encrypt_real_vector(Request::new(EncryptRealVectorRequest {
+            client_key_id: ckks_key_id.clone(),
+            values: vec![0.5],
+            ..Default::default()
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .encrypted_data_id;
+    
+    // Exact integers become reals
+    let started = migrate(&admin, &bgv_key_id, &ckks_key_id, &[&batch_id], false).await.unwrap();
+    let status = wait_for(&admin, &started.migration_id).await;
+    assert_eq!((status.source_scheme, status.target_scheme), (Scheme::Bgv as i32, Scheme::Ckks as i32));
+    let request = Request::new(DecryptRealVectorRequest {
+        client_key_id: ckks_key_id.clone(),
+        encrypted_data_id: status.ciphertexts[0].target_id.clone(),
+    });
+    let values = service.decrypt_real_vector(request).await.unwrap().into_inner().values;
+    for (actual, expected) in values.iter().zip([3.0, -7.0, 1000.0]) {
+        assert!((actual - expected).abs() < 1e-3, "{:?}", values);
+    }
+    
+    // But reals can't become integers, and data of the wrong scheme is reported per ciphertext
+    let ids = [vector_id.as_str(), &batch_id, "missing"];
+    let started = migrate(&admin, &ckks_key_id, &bgv_key_id, &ids, false).await.unwrap();
+    let status = wait_for(&admin, &started.migration_id).await;
+    assert_eq!((status.migrated, status.failed), (0, 3));
+    assert!(status.ciphertexts[0].error.contains("approximate"), "{}", status.ciphertexts[0].error);
+    assert!(status.ciphertexts[1].error.contains("type mismatch"), "{}", status.ciphertexts[1].error);
+    assert!(status.ciphertexts.iter().all(|c| c.target_id.is_empty()));
+    
+    // Failed sources are left in place
+    let request = Request::new(DecryptIntegerBatchRequest {
+        client_key_id: bgv_key_id,
+        encrypted_data_id: batch_id,
+    });
+    assert_eq!(service.decrypt_integer_batch(request).await.unwrap().into_inner().values, vec![3, -7, 1000]);
+}
+
+#[tokio::test]
+async fn test_migration_rejections() {
+    let (service, admin) = setup_services().await;
+    let bgv_key_id = generate_client_key(&service, Scheme::Bgv, 0).await;
+    
+    let status = migrate(&admin, &bgv_key_id, "missing", &["data"], false).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::KeyNotFound));
+    let status = migrate(&admin, &bgv_key_id, &bgv_key_id, &["data"], false).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::InvalidRequest));
+    let status = migrate(&admin, &bgv_key_id, "missing", &[], false).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::InvalidRequest));
+    
+    let request = Request::new(GetMigrationRequest {
+        migration_id: "missing".to_string(),
+    });
+    let status = admin.get_migration(request).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::MigrationNotFound));
+}
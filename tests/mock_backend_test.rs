@@ -0,0 +1,116 @@
+use hermetic_fhe::backend::mock::{MockFheBackend, MockValue};
+use hermetic_fhe::backend::{BackendError, FheBackend};
+use hermetic_fhe::circuit::{Circuit, Gate, Operation, Wire};
+
+#[test]
+fn test_mock_backend_operations() {
+    let backend = MockFheBackend::new();
+    let (client_key_id, server_key_id) = backend.generate_keys("DEFAULT").unwrap();
+    assert!(backend.generate_keys("UNKNOWN").is_err(), "Unknown parameter sets should be refused");
+    
+    // Integer arithmetic wraps at 8 bits, as it does under tfhe
+    let a = backend.encrypt_integer(&client_key_id, 200).unwrap();
+    let b = backend.encrypt_integer(&client_key_id, 100).unwrap();
+    let sum = backend.evaluate(&server_key_id, Operation::Add, &[&a, &b]).unwrap();
+    assert_eq!(backend.decrypt_integer(&client_key_id, &sum).unwrap(), 44);
+    let difference = backend.evaluate(&server_key_id, Operation::Subtract, &[&b, &a]).unwrap();
+    assert_eq!(backend.decrypt_integer(&client_key_id, &difference).unwrap(), 156);
+    
+    // Unless asked not to
+    let clamped = [
+        (Operation::SaturatingAdd, [&a, &b], 255),
+        (Operation::SaturatingSub, [&b, &a], 0),
+        (Operation::AbsDiff, [&b, &a], 100),
+    ];
+    for (operation, operands, expected) in clamped {
+        let result = backend.evaluate(&server_key_id, operation, &operands).unwrap();
+        assert_eq!(backend.decrypt_integer(&client_key_id, &result).unwrap(), expected, "{:?}", operation);
+    }
+    
+    let t = backend.encrypt_boolean(&client_key_id, true).unwrap();
+    let negated = backend.evaluate(&server_key_id, Operation::Not, &[&t]).unwrap();
+    assert_eq!(backend.peek(&negated), Some(MockValue::Boolean(false)));
+    
+    // Mixed types, wrong arity and wrong kinds on decryption are all errors
+    assert!(backend.evaluate(&server_key_id, Operation::And, &[&t, &a]).is_err());
+    assert!(backend.evaluate(&server_key_id, Operation::Add, &[&a]).is_err());
+    let error = backend.decrypt_boolean(&client_key_id, &sum).unwrap_err();
+    assert!(matches!(error, BackendError::TypeMismatch { expected: "FheBool", found: "FheUint8" }));
+    
+    // Values can't cross key pairs
+    let (other_client_key_id, other_server_key_id) = backend.generate_keys("DEFAULT").unwrap();
+    let error = backend.decrypt_integer(&other_client_key_id, &sum).unwrap_err();
+    assert!(matches!(error, BackendError::KeyPairMismatch(_)));
+    assert!(backend.evaluate(&other_server_key_id, Operation::Add, &[&a, &b]).is_err());
+    let error = backend.encrypt_integer(&server_key_id, 1).unwrap_err();
+    assert!(matches!(error, BackendError::ClientKeyNotFound), "Server keys can't encrypt");
+    
+    assert!(backend.remove(&sum));
+    let error = backend.decrypt_integer(&client_key_id, &sum).unwrap_err();
+    assert!(matches!(error, BackendError::CiphertextNotFound(_)));
+}
+
+#[test]
+fn test_mock_backend_circuit() {
+    let backend = MockFheBackend::new();
+    let (client_key_id, server_key_id) = backend.generate_keys("DEFAULT").unwrap();
+    
+    // (a + b) * c, also returning the input c unchanged
+    let circuit = Circuit {
+        gates: vec![
+            Gate { operation: Operation::Add, inputs: vec![Wire::Input(0), Wire::Input(1)] },
+            Gate { operation: Operation::Multiply, inputs: vec![Wire::Gate(0), Wire::Input(2)] },
+        ],
+        outputs: vec![Wire::Gate(1), Wire::Input(2)],
+    };
+    let inputs: Vec<String> = [2, 3, 4]
+        .into_iter()
+        .map(|value| backend.encrypt_integer(&client_key_id, value).unwrap())
+        .collect();
+    let input_ids: Vec<&str> = inputs.iter().map(String::as_str).collect();
+    
+    let outputs = backend.evaluate_circuit(&server_key_id, &circuit, &input_ids).unwrap();
+    assert_eq!(backend.decrypt_integer(&client_key_id, &outputs[0]).unwrap(), 20);
+    assert_eq!(backend.decrypt_integer(&client_key_id, &outputs[1]).unwrap(), 4);
+    
+    // Circuits are validated just as they are before a real evaluation
+    let boolean = backend.encrypt_boolean(&client_key_id, true).unwrap();
+    let result = backend.evaluate_circuit(&server_key_id, &circuit, &[&boolean, input_ids[1], input_ids[2]]);
+    assert!(result.is_err(), "A boolean input to an addition should be rejected");
+}
+
+#[test]
+fn test_mock_backend_zero_tests_feed_boolean_gates() {
+    let backend = MockFheBackend::new();
+    let (client_key_id, server_key_id) = backend.generate_keys("DEFAULT").unwrap();
+    
+    // "balance is empty and the account is open": an integer predicate ANDed with a boolean
+    let circuit = Circuit {
+        gates: vec![
+            Gate { operation: Operation::IsZero, inputs: vec![Wire::Input(0)] },
+            Gate { operation: Operation::And, inputs: vec![Wire::Gate(0), Wire::Input(1)] },
+        ],
+        outputs: vec![Wire::Gate(1)],
+    };
+    let open = backend.encrypt_boolean(&client_key_id, true).unwrap();
+    for (balance, expected) in [(0, true), (1, false), (255, false)] {
+        let balance = backend.encrypt_integer(&client_key_id, balance).unwrap();
+        let outputs = backend.evaluate_circuit(&server_key_id, &circuit, &[&balance, &open]).unwrap();
+        assert_eq!(backend.decrypt_boolean(&client_key_id, &outputs[0]).unwrap(), expected);
+        
+        let non_zero = backend.evaluate(&server_key_id, Operation::IsNonZero, &[&balance]).unwrap();
+        assert_eq!(backend.decrypt_boolean(&client_key_id, &non_zero).unwrap(), !expected);
+    }
+    
+    // They only take integers, and their booleans can't go back into arithmetic
+    assert!(backend.evaluate(&server_key_id, Operation::IsZero, &[&open]).is_err());
+    let mixed = Circuit {
+        gates: vec![
+            Gate { operation: Operation::IsZero, inputs: vec![Wire::Input(0)] },
+            Gate { operation: Operation::Add, inputs: vec![Wire::Gate(0), Wire::Input(0)] },
+        ],
+        outputs: vec![Wire::Gate(1)],
+    };
+    let balance = backend.encrypt_integer(&client_key_id, 0).unwrap();
+    assert!(backend.evaluate_circuit(&server_key_id, &mixed, &[&balance]).is_err());
+}
@@ -0,0 +1,316 @@
+use std::sync::Arc;
+use proptest::prelude::*;
+use proptest::sample::Index;
+use proptest::test_runner::{Config, TestRunner};
+use tokio::runtime::Runtime;
+use tonic::Request;
+
+use hermetic_fhe::api::{
+    circuit_wire::Source, plaintext_value, CircuitGate, CircuitWire, DecryptBooleanRequest,
+    DecryptIntegerRequest, EncryptBooleanRequest, EncryptIntegerRequest, EvaluateAndDecryptRequest,
+    EvaluationRequest, FheService, KeyGenerationRequest, OperationType,
+};
+use hermetic_fhe::backend::mock::{MockFheBackend, MockValue};
+use hermetic_fhe::backend::FheBackend;
+use hermetic_fhe::circuit::{Circuit, Gate, Operation, ValueType, Wire};
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::FheServiceImpl;
+
+// Every operation a circuit gate can run
+const OPERATIONS: [Operation; 12] = [
+    Operation::And,
+    Operation::Or,
+    Operation::Xor,
+    Operation::Not,
+    Operation::Add,
+    Operation::Subtract,
+    Operation::Multiply,
+    Operation::IsZero,
+    Operation::IsNonZero,
+    Operation::AbsDiff,
+    Operation::SaturatingAdd,
+    Operation::SaturatingSub,
+];
+
+// Gates in a generated circuit. Each costs up to a few seconds under tfhe, so circuits
+// stay small and the cases few; PROPTEST_CASES runs more.
+const MAX_GATES: usize = 6;
+const DEFAULT_CASES: u32 = 8;
+
+// The service under test, and the keys every case runs under
+struct Services {
+    service: FheServiceImpl,
+    client_key_id: String,
+    server_key_id: String,
+}
+
+impl Services {
+    fn new(runtime: &Runtime) -> Self {
+        let service = FheServiceImpl::new(Arc::new(KeyStore::new()), Arc::new(CiphertextStore::new()));
+        let request = Request::new(KeyGenerationRequest {
+            parameter_set: 0, // DEFAULT
+            ..Default::default()
+        });
+        let keys = runtime
+            .block_on(service.generate_keys(request))
+            .unwrap()
+            .into_inner();
+        Self {
+            service,
+            client_key_id: keys.client_key_id,
+            server_key_id: keys.server_key_id,
+        }
+    }
+}
+
+// A random well-typed circuit over random inputs, integers first, then booleans. Every
+// gate is an output, so a wrong intermediate can't hide behind a later gate.
+#[derive(Clone, Debug)]
+struct Case {
+    inputs: Vec<MockValue>,
+    circuit: Circuit,
+}
+
+impl Case {
+    // Each choice picks an operation, then operands among the inputs and earlier gates of
+    // the type it takes. There is always at least one input of each type to pick.
+    fn new(integers: Vec<u8>, booleans: Vec<bool>, choices: Vec<(usize, Index, Index)>) -> Self {
+        let inputs: Vec<MockValue> = integers
+            .into_iter()
+            .map(MockValue::Integer)
+            .chain(booleans.into_iter().map(MockValue::Boolean))
+            .collect();
+        let mut wires: Vec<(Wire, ValueType)> = inputs
+            .iter()
+            .enumerate()
+            .map(|(index, value)| (Wire::Input(index), value.value_type()))
+            .collect();
+    
+        let mut circuit = Circuit::default();
+        for (operation, first, second) in choices {
+            let operation = OPERATIONS[operation];
+            let (operand_type, value_type) = types(operation);
+            let candidates: Vec<Wire> = wires
+                .iter()
+                .filter(|(_, value_type)| *value_type == operand_type)
+                .map(|(wire, _)| *wire)
+                .collect();
+            let operands = [first.get(&candidates), second.get(&candidates)];
+            circuit.gates.push(Gate {
+                operation,
+                inputs: operands[..operation.arity()].iter().map(|wire| **wire).collect(),
+            });
+            let gate = Wire::Gate(circuit.gates.len() - 1);
+            circuit.outputs.push(gate);
+            wires.push((gate, value_type));
+        }
+        Self { inputs, circuit }
+    }
+}
+
+// Operand and result types of an operation
+fn types(operation: Operation) -> (ValueType, ValueType) {
+    match operation {
+        Operation::And | Operation::Or | Operation::Xor | Operation::Not => {
+            (ValueType::Boolean, ValueType::Boolean)
+        }
+        Operation::IsZero | Operation::IsNonZero => (ValueType::Integer, ValueType::Boolean),
+        _ => (ValueType::Integer, ValueType::Integer),
+    }
+}
+
+fn operation_type(operation: Operation) -> OperationType {
+    match operation {
+        Operation::And => OperationType::And,
+        Operation::Or => OperationType::Or,
+        Operation::Xor => OperationType::Xor,
+        Operation::Not => OperationType::Not,
+        Operation::Add => OperationType::Add,
+        Operation::Subtract => OperationType::Subtract,
+        Operation::Multiply => OperationType::Multiply,
+        Operation::IsZero => OperationType::IsZero,
+        Operation::IsNonZero => OperationType::IsNonZero,
+        Operation::AbsDiff => OperationType::AbsDiff,
+        Operation::SaturatingAdd => OperationType::SaturatingAdd,
+        Operation::SaturatingSub => OperationType::SaturatingSub,
+    }
+}
+
+fn wire(wire: Wire) -> CircuitWire {
+    let source = match wire {
+        Wire::Input(index) => Source::Input(index as u32),
+        Wire::Gate(index) => Source::Gate(index as u32),
+    };
+    CircuitWire { source: Some(source) }
+}
+
+// Integers biased towards the edges where wrapping and saturation show
+fn integer() -> impl Strategy<Value = u8> {
+    prop_oneof![Just(0u8), Just(1), Just(127), Just(128), Just(255), any::<u8>()]
+}
+
+fn case() -> impl Strategy<Value = Case> {
+    let choice = (0..OPERATIONS.len(), any::<Index>(), any::<Index>());
+    (
+        prop::collection::vec(integer(), 1..=3),
+        prop::collection::vec(any::<bool>(), 1..=2),
+        prop::collection::vec(choice, 1..=MAX_GATES),
+    )
+        .prop_map(|(integers, booleans, choices)| Case::new(integers, booleans, choices))
+}
+
+fn runner() -> TestRunner {
+    let mut config = Config::default();
+    if std::env::var("PROPTEST_CASES").is_err() {
+        config.cases = DEFAULT_CASES;
+    }
+    TestRunner::new(config)
+}
+
+// What the plaintext stand-in makes of the circuit
+fn expected(case: &Case) -> Vec<MockValue> {
+    let backend = MockFheBackend::new();
+    let (client_key_id, server_key_id) = backend.generate_keys("DEFAULT").unwrap();
+    let input_ids: Vec<String> = case
+        .inputs
+        .iter()
+        .map(|input| match *input {
+            MockValue::Integer(value) => backend.encrypt_integer(&client_key_id, value).unwrap(),
+            MockValue::Boolean(value) => backend.encrypt_boolean(&client_key_id, value).unwrap(),
+        })
+        .collect();
+    let input_ids: Vec<&str> = input_ids.iter().map(String::as_str).collect();
+    backend
+        .evaluate_circuit(&server_key_id, &case.circuit, &input_ids)
+        .unwrap()
+        .iter()
+        .map(|id| backend.peek(id).unwrap())
+        .collect()
+}
+
+// What the service makes of it, encrypting the inputs and decrypting the outputs through
+// its RPCs
+async fn evaluated(services: &Services, case: &Case) -> Vec<MockValue> {
+    let service = &services.service;
+    let mut input_ids = Vec::new();
+    for input in &case.inputs {
+        let response = match *input {
+            MockValue::Integer(value) => {
+                let request = Request::new(EncryptIntegerRequest {
+                    client_key_id: services.client_key_id.clone(),
+                    value: value.into(),
+                    ..Default::default()
+                });
+                service.encrypt_integer(request).await
+            }
+            MockValue::Boolean(value) => {
+                let request = Request::new(EncryptBooleanRequest {
+                    client_key_id: services.client_key_id.clone(),
+                    value,
+                    ..Default::default()
+                });
+                service.encrypt_boolean(request).await
+            }
+        };
+        input_ids.push(response.unwrap().into_inner().encrypted_data_id);
+    }
+    
+    let gates = case
+        .circuit
+        .gates
+        .iter()
+        .map(|gate| CircuitGate {
+            operation: operation_type(gate.operation) as i32,
+            operands: gate.inputs.iter().map(|input| wire(*input)).collect(),
+        })
+        .collect();
+    let request = Request::new(EvaluateAndDecryptRequest {
+        client_key_id: services.client_key_id.clone(),
+        server_key_id: services.server_key_id.clone(),
+        input_ids,
+        gates,
+        outputs: case.circuit.outputs.iter().map(|output| wire(*output)).collect(),
+    });
+    let response = service.evaluate_and_decrypt(request).await.unwrap().into_inner();
+    response
+        .values
+        .into_iter()
+        .map(|value| match value.value.unwrap() {
+            plaintext_value::Value::Boolean(value) => MockValue::Boolean(value),
+            plaintext_value::Value::Integer(value) => MockValue::Integer(u8::try_from(value).unwrap()),
+        })
+        .collect()
+}
+
+#[test]
+fn test_circuits_match_the_plaintext_model() {
+    let runtime = Runtime::new().unwrap();
+    let services = Services::new(&runtime);
+    
+    runner()
+        .run(&case(), |case| {
+            let evaluated = runtime.block_on(evaluated(&services, &case));
+            prop_assert_eq!(evaluated, expected(&case), "{:?}", case);
+            Ok(())
+        })
+        .unwrap();
+}
+
+#[test]
+fn test_overflow_detection_matches_plaintext_arithmetic() {
+    let runtime = Runtime::new().unwrap();
+    let services = Services::new(&runtime);
+    
+    let operations = prop::sample::select(vec![Operation::Add, Operation::Subtract, Operation::Multiply]);
+    runner()
+        .run(
+            &(operations, integer(), integer()),
+            |(operation, a, b)| {
+                let (expected, expected_overflow) = match operation {
+                    Operation::Add => a.overflowing_add(b),
+                    Operation::Subtract => a.overflowing_sub(b),
+                    _ => a.overflowing_mul(b),
+                };
+                let service = &services.service;
+                let (value, overflowed) = runtime.block_on(async {
+                    let mut operand_ids = Vec::new();
+                    for value in [a, b] {
+                        let request = Request::new(EncryptIntegerRequest {
+                            client_key_id: services.client_key_id.clone(),
+                            value: value.into(),
+                            ..Default::default()
+                        });
+                        let response = service.encrypt_integer(request).await.unwrap();
+                        operand_ids.push(response.into_inner().encrypted_data_id);
+                    }
+    
+                    let request = Request::new(EvaluationRequest {
+                        server_key_id: services.server_key_id.clone(),
+                        operation: operation_type(operation) as i32,
+                        operand_ids,
+                        detect_overflow: true,
+                        ..Default::default()
+                    });
+                    let response = service.evaluate_operation(request).await.unwrap().into_inner();
+    
+                    let request = Request::new(DecryptIntegerRequest {
+                        client_key_id: services.client_key_id.clone(),
+                        encrypted_data_id: response.result_id,
+                        ..Default::default()
+                    });
+                    let value = service.decrypt_integer(request).await.unwrap().into_inner().value;
+                    let request = Request::new(DecryptBooleanRequest {
+                        client_key_id: services.client_key_id.clone(),
+                        encrypted_data_id: response.overflow_id,
+                        ..Default::default()
+                    });
+                    let overflowed = service.decrypt_boolean(request).await.unwrap().into_inner().value;
+                    (value, overflowed)
+                });
+                prop_assert_eq!(value, i64::from(expected), "{:?} {} {}", operation, a, b);
+                prop_assert_eq!(overflowed, expected_overflow, "{:?} {} {}", operation, a, b);
+                Ok(())
+            },
+        )
+        .unwrap();
+}
@@ -0,0 +1,228 @@
+use std::sync::Arc;
+use tonic::Request;
+
+use hermetic_fhe::api::{
+    DecryptIntegerRequest, EncryptBooleanRequest, EncryptIntegerRequest, FheService,
+    InvokeCustomOperationRequest, KeyGenerationRequest, ListCustomOperationsRequest,
+};
+use hermetic_fhe::backend::{BackendError, OperationPlugin, OperationRegistry, PluginContext};
+use hermetic_fhe::circuit::Value;
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::errors::ErrorReason;
+use hermetic_fhe::service::FheServiceImpl;
+
+// Sum and difference of two integers, standing in for a proprietary circuit
+struct SumAndDifference;
+
+impl OperationPlugin for SumAndDifference {
+    fn name(&self) -> &str {
+        "sum_and_difference"
+    }
+    
+    fn description(&self) -> &str {
+        "a + b and a - b of integers a and b"
+    }
+    
+    fn arity(&self) -> Option<usize> {
+        Some(2)
+    }
+    
+    fn evaluate(&self, context: &PluginContext<'_>, inputs: &[Value]) -> Result<Vec<Value>, BackendError> {
+        context.check()?;
+        let integer = |value: &Value| match value {
+            Value::Integer(value) => Ok(value.clone()),
+            Value::Boolean(_) => Err(BackendError::TypeMismatch {
+                expected: "integer",
+                found: "boolean",
+            }),
+        };
+        let (a, b) = (integer(&inputs[0])?, integer(&inputs[1])?);
+        Ok(vec![
+            Value::Integer(Arc::new(&*a + &*b)),
+            Value::Integer(Arc::new(&*a - &*b)),
+        ])
+    }
+}
+
+// Any number of inputs, each passed straight through
+struct Identity;
+
+impl OperationPlugin for Identity {
+    fn name(&self) -> &str {
+        "identity"
+    }
+    
+    fn evaluate(&self, _context: &PluginContext<'_>, inputs: &[Value]) -> Result<Vec<Value>, BackendError> {
+        Ok(inputs.to_vec())
+    }
+}
+
+// A service with both plugins registered; returns the client and server key IDs
+async fn setup_service() -> (FheServiceImpl, String, String) {
+    let mut operations = OperationRegistry::new();
+    operations.register(Arc::new(SumAndDifference)).unwrap();
+    operations.register(Arc::new(Identity)).unwrap();
+    let service = FheServiceImpl::new(Arc::new(KeyStore::new()), Arc::new(CiphertextStore::new()))
+        .with_operations(operations);
+    let keys = service
+        .generate_keys(Request::new(KeyGenerationRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    (service, keys.client_key_id, keys.server_key_id)
+}
+
+async fn encrypt_integer(service: &FheServiceImpl, client_key_id: &str, value: i64) -> String {
+    let request = Request::new(EncryptIntegerRequest {
+        client_key_id: client_key_id.to_string(),
+        value,
+        num_bits: 8,
+        ..Default::default()
+    });
+    service
+        .encrypt_integer(request)
+        .await
+        .unwrap()
+        .into_inner()
+        .encrypted_data_id
+}
+
+async fn decrypt_integer(service: &FheServiceImpl, client_key_id: &str, encrypted_data_id: &str) -> i64 {
+    let request = Request::new(DecryptIntegerRequest {
+        client_key_id: client_key_id.to_string(),
+        encrypted_data_id: encrypted_data_id.to_string(),
+        ..Default::default()
+    });
+    service.decrypt_integer(request).await.unwrap().into_inner().value
+}
+
+fn invoke_request(server_key_id: &str, name: &str, input_ids: Vec<String>) -> InvokeCustomOperationRequest {
+    InvokeCustomOperationRequest {
+        server_key_id: server_key_id.to_string(),
+        name: name.to_string(),
+        input_ids,
+        ..Default::default()
+    }
+}
+
+async fn refused(service: &FheServiceImpl, request: InvokeCustomOperationRequest) -> ErrorReason {
+    let status = service
+        .invoke_custom_operation(Request::new(request))
+        .await
+        .unwrap_err();
+    ErrorReason::of(&status).unwrap()
+}
+
+#[tokio::test]
+async fn test_invoke_custom_operation() {
+    let (service, client_key_id, server_key_id) = setup_service().await;
+    let a = encrypt_integer(&service, &client_key_id, 30).await;
+    let b = encrypt_integer(&service, &client_key_id, 12).await;
+    
+    let request = invoke_request(&server_key_id, "sum_and_difference", vec![a.clone(), b.clone()]);
+    let response = service
+        .invoke_custom_operation(Request::new(request))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(response.output_ids.len(), 2);
+    assert_eq!(response.output_fingerprints.len(), 2);
+    assert_eq!(
+        decrypt_integer(&service, &client_key_id, &response.output_ids[0]).await,
+        42
+    );
+    assert_eq!(
+        decrypt_integer(&service, &client_key_id, &response.output_ids[1]).await,
+        18
+    );
+    
+    // Operations are listed in name order
+    let operations = service
+        .list_custom_operations(Request::new(ListCustomOperationsRequest {}))
+        .await
+        .unwrap()
+        .into_inner()
+        .operations;
+    let listed: Vec<(&str, u32)> = operations
+        .iter()
+        .map(|operation| (operation.name.as_str(), operation.arity))
+        .collect();
+    assert_eq!(listed, vec![("identity", 0), ("sum_and_difference", 2)]);
+    
+    let request = invoke_request(&server_key_id, "identity", vec![a, b]);
+    let response = service
+        .invoke_custom_operation(Request::new(request))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(
+        decrypt_integer(&service, &client_key_id, &response.output_ids[1]).await,
+        12
+    );
+}
+
+#[tokio::test]
+async fn test_custom_operation_refused() {
+    let (service, client_key_id, server_key_id) = setup_service().await;
+    let a = encrypt_integer(&service, &client_key_id, 30).await;
+    let request = Request::new(EncryptBooleanRequest {
+        client_key_id: client_key_id.clone(),
+        value: true,
+        ..Default::default()
+    });
+    let flag = service
+        .encrypt_boolean(request)
+        .await
+        .unwrap()
+        .into_inner()
+        .encrypted_data_id;
+    
+    let request = invoke_request(&server_key_id, "sum_and_product", vec![a.clone(), a.clone()]);
+    assert_eq!(refused(&service, request).await, ErrorReason::Unsupported);
+    let request = invoke_request(&server_key_id, "sum_and_difference", vec![a.clone()]);
+    assert_eq!(refused(&service, request).await, ErrorReason::ArityMismatch);
+    let request = invoke_request(
+        &server_key_id,
+        "sum_and_difference",
+        vec![a.clone(), "missing".to_string()],
+    );
+    assert_eq!(refused(&service, request).await, ErrorReason::CiphertextNotFound);
+    
+    // The plugin's own type check reaches the client as TYPE_MISMATCH
+    let request = invoke_request(&server_key_id, "sum_and_difference", vec![a, flag]);
+    assert_eq!(refused(&service, request).await, ErrorReason::TypeMismatch);
+}
+
+#[test]
+fn test_operation_registry_names() {
+    struct Named(String);
+    
+    impl OperationPlugin for Named {
+        fn name(&self) -> &str {
+            &self.0
+        }
+    
+        fn evaluate(
+            &self,
+            _context: &PluginContext<'_>,
+            _inputs: &[Value],
+        ) -> Result<Vec<Value>, BackendError> {
+            Ok(vec![])
+        }
+    }
+    
+    let mut operations = OperationRegistry::new();
+    assert!(operations
+        .register(Arc::new(Named("acme.score-v2".to_string())))
+        .is_ok());
+    assert!(operations
+        .register(Arc::new(Named("acme.score-v2".to_string())))
+        .is_err());
+    assert!(operations.register(Arc::new(Named("".to_string()))).is_err());
+    assert!(operations
+        .register(Arc::new(Named("has space".to_string())))
+        .is_err());
+    assert!(operations.register(Arc::new(Named("x".repeat(65)))).is_err());
+    assert_eq!(operations.len(), 1);
+    assert!(operations.get("acme.score-v2").is_some());
+}
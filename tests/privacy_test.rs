@@ -0,0 +1,323 @@
+use std::sync::Arc;
+use tonic::Request;
+
+use hermetic_fhe::api::v1::key_generation_request::Scheme;
+use hermetic_fhe::api::{
+    privacy_noise, DecryptBooleanRequest, DecryptIntegerBatchRequest, DecryptIntegerRequest,
+    DecryptMatrixRequest, EncryptIntegerBatchRequest, EncryptIntegerRequest, EvaluateAndDecryptRequest,
+    EvaluationRequest, FheService, IntegerBatchEvaluationRequest, IntegerBatchOperation, KeyGenerationRequest,
+    OperationType, PrivacyNoise,
+};
+use hermetic_fhe::crypto::key_directory::KeyDirectory;
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::errors::ErrorReason;
+use hermetic_fhe::service::privacy::{Budget, PrivacyConfig, PrivacyLedger, LEDGER_FILE};
+use hermetic_fhe::service::FheServiceImpl;
+
+// A service whose keys each get the config's budget, with 42 encrypted under a fresh key;
+// returns the client key ID and the ciphertext ID
+async fn setup_service(config: PrivacyConfig) -> (FheServiceImpl, String, String) {
+    let service = FheServiceImpl::new(Arc::new(KeyStore::new()), Arc::new(CiphertextStore::new()))
+        .with_privacy_config(config);
+    let keys = service
+        .generate_keys(Request::new(KeyGenerationRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    let request = Request::new(EncryptIntegerRequest {
+        client_key_id: keys.client_key_id.clone(),
+        value: 42,
+        num_bits: 8,
+        ..Default::default()
+    });
+    let id = service.encrypt_integer(request).await.unwrap().into_inner().encrypted_data_id;
+    (service, keys.client_key_id, id)
+}
+
+fn decrypt(client_key_id: &str, id: &str, noise: Option<PrivacyNoise>) -> Request<DecryptIntegerRequest> {
+    Request::new(DecryptIntegerRequest {
+        client_key_id: client_key_id.to_string(),
+        encrypted_data_id: id.to_string(),
+        noise,
+        ..Default::default()
+    })
+}
+
+fn laplace(epsilon: f64) -> Option<PrivacyNoise> {
+    Some(PrivacyNoise {
+        mechanism: privacy_noise::Mechanism::Laplace as i32,
+        epsilon,
+        sensitivity: 1.0,
+        ..Default::default()
+    })
+}
+
+#[tokio::test]
+async fn test_noised_decryptions_spend_the_key_budget() {
+    let config = PrivacyConfig {
+        epsilon: 8.0,
+        ..Default::default()
+    };
+    let (service, client_key_id, id) = setup_service(config).await;
+    
+    // At epsilon 5 the noise has scale 0.2, so the value is all but exact
+    let request = decrypt(&client_key_id, &id, laplace(5.0));
+    let response = service.decrypt_integer(request).await.unwrap().into_inner();
+    assert!((response.value - 42).abs() <= 10, "Noise far above its scale: {}", response.value);
+    let budget = response.budget.unwrap();
+    assert!((budget.epsilon_remaining - 3.0).abs() < 1e-9);
+    
+    // The key can't afford a second one, and nothing is spent by the refusal
+    let status = service.decrypt_integer(decrypt(&client_key_id, &id, laplace(5.0))).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::PrivacyBudgetExhausted));
+    assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+    let request = decrypt(&client_key_id, &id, laplace(3.0));
+    let response = service.decrypt_integer(request).await.unwrap().into_inner();
+    assert!(response.budget.unwrap().epsilon_remaining.abs() < 1e-9);
+    
+    // Exact decryptions are allowed unless the server requires noise, and cost nothing
+    let response = service.decrypt_integer(decrypt(&client_key_id, &id, None)).await.unwrap().into_inner();
+    assert_eq!(response.value, 42);
+    assert!(response.budget.is_none());
+}
+
+#[tokio::test]
+async fn test_noise_can_be_required() {
+    let config = PrivacyConfig {
+        require_noise: true,
+        ..Default::default()
+    };
+    let (service, client_key_id, id) = setup_service(config).await;
+    
+    let status = service.decrypt_integer(decrypt(&client_key_id, &id, None)).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::PolicyViolation));
+    service.decrypt_integer(decrypt(&client_key_id, &id, laplace(1.0))).await.unwrap();
+    
+    // So is every other call returning decrypted values, any of which could let it out exactly
+    let request = Request::new(EvaluateAndDecryptRequest {
+        client_key_id: client_key_id.clone(),
+        input_ids: vec![id.clone()],
+        ..Default::default()
+    });
+    let refusals = [
+        service.evaluate_and_decrypt(request).await.map(|_| ()),
+        service
+            .decrypt_boolean(Request::new(DecryptBooleanRequest {
+                client_key_id: client_key_id.clone(),
+                encrypted_data_id: id.clone(),
+                ..Default::default()
+            }))
+            .await
+            .map(|_| ()),
+        service
+            .decrypt_matrix(Request::new(DecryptMatrixRequest {
+                client_key_id: client_key_id.clone(),
+                matrix_id: id.clone(),
+            }))
+            .await
+            .map(|_| ()),
+        service
+            .decrypt_integer_batch(Request::new(DecryptIntegerBatchRequest {
+                client_key_id: client_key_id.clone(),
+                encrypted_data_id: id.clone(),
+            }))
+            .await
+            .map(|_| ()),
+    ];
+    for refusal in refusals {
+        assert_eq!(ErrorReason::of(&refusal.unwrap_err()), Some(ErrorReason::PolicyViolation));
+    }
+    
+    // Parameters no mechanism can honour are refused before anything is decrypted
+    let gaussian = |epsilon: f64, delta: f64| {
+        Some(PrivacyNoise {
+            mechanism: privacy_noise::Mechanism::Gaussian as i32,
+            epsilon,
+            delta,
+            sensitivity: 1.0,
+        })
+    };
+    for noise in [laplace(0.0), laplace(f64::NAN), gaussian(1.5, 1e-6), gaussian(0.5, 0.0)] {
+        let status = service.decrypt_integer(decrypt(&client_key_id, &id, noise)).await.unwrap_err();
+        assert_eq!(ErrorReason::of(&status), Some(ErrorReason::InvalidRequest));
+    }
+    service.decrypt_integer(decrypt(&client_key_id, &id, gaussian(0.5, 1e-6))).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_sensitivity_below_one_is_refused() {
+    let config = PrivacyConfig {
+        epsilon: 1.0,
+        ..Default::default()
+    };
+    let (service, client_key_id, id) = setup_service(config).await;
+    
+    // A tiny sensitivity would make the noise vanish in rounding for next to no epsilon
+    for sensitivity in [1e-9, 0.5] {
+        let noise = Some(PrivacyNoise {
+            mechanism: privacy_noise::Mechanism::Laplace as i32,
+            epsilon: 1e-9,
+            sensitivity,
+            ..Default::default()
+        });
+        let status = service.decrypt_integer(decrypt(&client_key_id, &id, noise)).await.unwrap_err();
+        assert_eq!(ErrorReason::of(&status), Some(ErrorReason::InvalidRequest));
+    }
+    
+    // Nothing was spent by the refusals
+    let request = decrypt(&client_key_id, &id, laplace(1.0));
+    let response = service.decrypt_integer(request).await.unwrap().into_inner();
+    assert!(response.budget.unwrap().epsilon_remaining.abs() < 1e-9);
+}
+
+#[tokio::test]
+async fn test_decryption_needs_enough_distinct_inputs() {
+    let config = PrivacyConfig {
+        min_inputs: 3,
+        ..Default::default()
+    };
+    let service = FheServiceImpl::new(Arc::new(KeyStore::new()), Arc::new(CiphertextStore::new()))
+        .with_privacy_config(config);
+    let keys = service
+        .generate_keys(Request::new(KeyGenerationRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    let (client_key_id, server_key_id) = (keys.client_key_id, keys.server_key_id);
+    let mut ids = Vec::new();
+    for value in [42, 1, 2] {
+        let request = Request::new(EncryptIntegerRequest {
+            client_key_id: client_key_id.clone(),
+            value,
+            num_bits: 8,
+            ..Default::default()
+        });
+        ids.push(service.encrypt_integer(request).await.unwrap().into_inner().encrypted_data_id);
+    }
+    let add = |x: &str, y: &str| {
+        Request::new(EvaluationRequest {
+            server_key_id: server_key_id.clone(),
+            operation: OperationType::Add as i32,
+            operand_ids: vec![x.to_string(), y.to_string()],
+            ..Default::default()
+        })
+    };
+    
+    // A fresh value and a sum of two are each too narrow to reveal
+    let a = ids[0].clone();
+    let ab = service.evaluate_operation(add(&a, &ids[1])).await.unwrap().into_inner().result_id;
+    for id in [&a, &ab] {
+        let status = service.decrypt_integer(decrypt(&client_key_id, id, None)).await.unwrap_err();
+        assert_eq!(ErrorReason::of(&status), Some(ErrorReason::PolicyViolation));
+    }
+    
+    let abc = service.evaluate_operation(add(&ab, &ids[2])).await.unwrap().into_inner().result_id;
+    let response = service.decrypt_integer(decrypt(&client_key_id, &abc, None)).await.unwrap().into_inner();
+    assert_eq!(response.value, 45);
+    
+    // Using the same input again doesn't count as another
+    let aa = service.evaluate_operation(add(&a, &a)).await.unwrap().into_inner().result_id;
+    let aaa = service.evaluate_operation(add(&aa, &a)).await.unwrap().into_inner().result_id;
+    let status = service.decrypt_integer(decrypt(&client_key_id, &aaa, None)).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::PolicyViolation));
+}
+
+#[tokio::test]
+async fn test_batch_decryption_needs_enough_distinct_inputs() {
+    let config = PrivacyConfig {
+        min_inputs: 2,
+        ..Default::default()
+    };
+    let service = FheServiceImpl::new(Arc::new(KeyStore::new()), Arc::new(CiphertextStore::new()))
+        .with_privacy_config(config);
+    let request = Request::new(KeyGenerationRequest {
+        scheme: Scheme::Bgv as i32,
+        ..Default::default()
+    });
+    let keys = service.generate_keys(request).await.unwrap().into_inner();
+    let mut ids = Vec::new();
+    for values in [vec![3, 4], vec![5, 6]] {
+        let request = Request::new(EncryptIntegerBatchRequest {
+            client_key_id: keys.client_key_id.clone(),
+            values,
+            ..Default::default()
+        });
+        ids.push(service.encrypt_integer_batch(request).await.unwrap().into_inner().encrypted_data_id);
+    }
+    let evaluate = |operation: IntegerBatchOperation, operand_ids: Vec<String>| {
+        Request::new(IntegerBatchEvaluationRequest {
+            server_key_id: keys.server_key_id.clone(),
+            operation: operation as i32,
+            operand_ids,
+            rotation: 1,
+            ..Default::default()
+        })
+    };
+    let decrypt_batch = |id: &str| {
+        Request::new(DecryptIntegerBatchRequest {
+            client_key_id: keys.client_key_id.clone(),
+            encrypted_data_id: id.to_string(),
+        })
+    };
+    
+    // Summing the slots of one batch by rotation leaves it a single input
+    let request = evaluate(IntegerBatchOperation::BatchRotate, vec![ids[0].clone()]);
+    let rotated = service.evaluate_integer_batch(request).await.unwrap().into_inner().result_id;
+    let request = evaluate(IntegerBatchOperation::BatchAdd, vec![ids[0].clone(), rotated]);
+    let summed = service.evaluate_integer_batch(request).await.unwrap().into_inner().result_id;
+    for id in [&ids[0], &summed] {
+        let status = service.decrypt_integer_batch(decrypt_batch(id)).await.unwrap_err();
+        assert_eq!(ErrorReason::of(&status), Some(ErrorReason::PolicyViolation));
+    }
+    
+    let request = evaluate(IntegerBatchOperation::BatchAdd, ids.clone());
+    let total = service.evaluate_integer_batch(request).await.unwrap().into_inner().result_id;
+    let values = service.decrypt_integer_batch(decrypt_batch(&total)).await.unwrap().into_inner().values;
+    assert_eq!(values, vec![8, 10]);
+}
+
+#[tokio::test]
+async fn test_budgets_outlive_a_restart_with_the_keys() {
+    let dir = std::env::temp_dir().join(format!("hermetic-fhe-keys-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let ledger_path = dir.join(LEDGER_FILE);
+    let config = PrivacyConfig {
+        epsilon: 8.0,
+        ..Default::default()
+    };
+    let ledger = PrivacyLedger::open(config, &ledger_path).unwrap();
+    assert!(ledger.is_durable());
+    let spent = Budget {
+        epsilon: 5.0,
+        delta: 0.0,
+    };
+    ledger.charge("client-key", spent).unwrap();
+    
+    // A restarted ledger has only what was left
+    let restarted = PrivacyLedger::open(config, &ledger_path).unwrap();
+    assert!((restarted.remaining("client-key").epsilon - 3.0).abs() < 1e-9);
+    assert!(restarted.charge("client-key", spent).is_err());
+    
+    // Keys kept in a key directory can't spend from a budget that a restart would reset
+    let key_store = KeyStore::new().with_key_directory(KeyDirectory::open(&dir).unwrap());
+    let service = FheServiceImpl::new(Arc::new(key_store), Arc::new(CiphertextStore::new()))
+        .with_privacy_config(config);
+    let keys = service
+        .generate_keys(Request::new(KeyGenerationRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    let request = Request::new(EncryptIntegerRequest {
+        client_key_id: keys.client_key_id.clone(),
+        value: 42,
+        num_bits: 8,
+        ..Default::default()
+    });
+    let id = service.encrypt_integer(request).await.unwrap().into_inner().encrypted_data_id;
+    let status = service.decrypt_integer(decrypt(&keys.client_key_id, &id, laplace(1.0))).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::Unsupported));
+    
+    let service = service.with_privacy_ledger(PrivacyLedger::open(config, &ledger_path).unwrap());
+    service.decrypt_integer(decrypt(&keys.client_key_id, &id, laplace(1.0))).await.unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
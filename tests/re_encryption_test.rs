@@ -0,0 +1,202 @@
+use std::sync::Arc;
+use tonic::Request;
+
+use hermetic_fhe::api::v1::key_generation_request::Scheme;
+use hermetic_fhe::api::{
+    DecryptIntegerBatchRequest, DecryptRealVectorRequest, DeleteKeyPairRequest, EncryptBooleanRequest,
+    EncryptIntegerBatchRequest, EncryptRealVectorRequest, FheAdminService, FheService,
+    IntegerBatchEvaluationRequest, IntegerBatchOperation, KeyGenerationRequest, ReEncryptRequest,
+    ReEncryptionKeyRequest,
+};
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::admin::FheAdminServiceImpl;
+use hermetic_fhe::service::errors::ErrorReason;
+use hermetic_fhe::service::FheServiceImpl;
+
+async fn setup_service() -> FheServiceImpl {
+    let key_store = Arc::new(KeyStore::new());
+    let ciphertext_store = Arc::new(CiphertextStore::new());
+    FheServiceImpl::new(key_store, ciphertext_store)
+}
+
+// Generate keys for a scheme, returning (client_key_id, server_key_id)
+async fn generate_keys(service: &FheServiceImpl, scheme: Scheme) -> (String, String) {
+    let key_gen_request = Request::new(KeyGenerationRequest {
+        scheme: scheme as i32,
+        ..Default::default()
+    });
+    
+    let keys = service.generate_keys(key_gen_request).await.unwrap().into_inner();
+    (keys.client_key_id, keys.server_key_id)
+}
+
+async fn generate_re_encryption_key(
+    service: &FheServiceImpl,
+    source_client_key_id: &str,
+    target_client_key_id: &str,
+) -> Result<String, tonic::Status> {
+    let request = Request::new(ReEncryptionKeyRequest {
+        source_client_key_id: source_client_key_id.to_string(),
+        target_client_key_id: target_client_key_id.to_string(),
+    });
+    
+    Ok(service.generate_re_encryption_key(request).await?.into_inner().re_encryption_key_id)
+}
+
+async fn re_encrypt(service: &FheServiceImpl, re_encryption_key_id: &str, encrypted_data_id: &str) -> Result<String, tonic::Status> {
+    let request = Request::new(ReEncryptRequest {
+        re_encryption_key_id: re_encryption_key_id.to_string(),
+        encrypted_data_id: encrypted_data_id.to_string(),
+        ..Default::default()
+    });
+    
+    Ok(service.re_encrypt(request).await?.into_inner().encrypted_data_id)
+}
+
+async fn encrypt_reals(service: &FheServiceImpl, client_key_id: &str, values: Vec<f64>) -> String {
+    let request = Request::new(EncryptRealVectorRequest {
+        client_key_id: client_key_id.to_string(),
+        values,
+        ..Default::default()
+    });
+    
+    service.encrypt_real_vector(request).await.unwrap().into_inner().encrypted_data_id
+}
+
+async fn decrypt_reals(service: &FheServiceImpl, client_key_id: &str, encrypted_data_id: &str) -> Vec<f64> {
+    let request = Request::new(DecryptRealVectorRequest {
+        client_key_id: client_key_id.to_string(),
+        encrypted_data_id: encrypted_data_id.to_string(),
+    });
+    
+    service.decrypt_real_vector(request).await.unwrap().into_inner().values
+}
+
+async fn encrypt_batch(service: &FheServiceImpl, client_key_id: &str, values: Vec<i64>) -> String {
+    let request = Request::new(EncryptIntegerBatchRequest {
+        client_key_id: client_key_id.to_string(),
+        values,
+        ..Default::default()
+    });
+    
+    service.encrypt_integer_batch(request).await.unwrap().into_inner().encrypted_data_id
+}
+
+async fn decrypt_batch(service: &FheServiceImpl, client_key_id: &str, encrypted_data_id: &str) -> Vec<i64> {
+    let request = Request::new(DecryptIntegerBatchRequest {
+        client_key_id: client_key_id.to_string(),
+        encrypted_data_id: encrypted_data_id.to_string(),
+    });
+    
+    service.decrypt_integer_batch(request).await.unwrap().into_inner().values
+}
+
+async fn multiply_batches(service: &FheServiceImpl, server_key_id: &str, a: &str, b: &str) -> String {
+    let request = Request::new(IntegerBatchEvaluationRequest {
+        server_key_id: server_key_id.to_string(),
+        operation: IntegerBatchOperation::BatchMultiply as i32,
+        operand_ids: vec![a.to_string(), b.to_string()],
+        ..Default::default()
+    });
+    
+    service.evaluate_integer_batch(request).await.unwrap().into_inner().result_id
+}
+
+fn assert_close(actual: &[f64], expected: &[f64]) {
+    assert_eq!(actual.len(), expected.len());
+    for (a, e) in actual.iter().zip(expected) {
+        assert!((a - e).abs() < 1e-3, "{:?} != {:?}", actual, expected);
+    }
+}
+
+#[tokio::test]
+async fn test_re_encrypt_real_vector_for_another_key() {
+    let service = setup_service().await;
+    let (alice_key_id, _) = generate_keys(&service, Scheme::Ckks).await;
+    let (bob_key_id, _) = generate_keys(&service, Scheme::Ckks).await;
+    
+    let x = encrypt_reals(&service, &alice_key_id, vec![1.5, -2.25, 1000.0]).await;
+    let re_encryption_key_id = generate_re_encryption_key(&service, &alice_key_id, &bob_key_id).await.unwrap();
+    let y = re_encrypt(&service, &re_encryption_key_id, &x).await.unwrap();
+    assert_ne!(x, y);
+    
+    // Bob reads the copy and Alice's original is untouched
+    assert_close(&decrypt_reals(&service, &bob_key_id, &y).await, &[1.5, -2.25, 1000.0]);
+    assert_close(&decrypt_reals(&service, &alice_key_id, &x).await, &[1.5, -2.25, 1000.0]);
+    
+    // Alice's key can't read the copy
+    let garbled = decrypt_reals(&service, &alice_key_id, &y).await;
+    assert!((garbled[0] - 1.5).abs() > 1.0, "{:?}", garbled);
+}
+
+#[tokio::test]
+async fn test_re_encrypt_integer_batch_keeps_computing() {
+    let service = setup_service().await;
+    let (alice_key_id, alice_server_key_id) = generate_keys(&service, Scheme::Bgv).await;
+    let (bob_key_id, bob_server_key_id) = generate_keys(&service, Scheme::Bgv).await;
+    
+    let x = encrypt_batch(&service, &alice_key_id, vec![3, -4, 100]).await;
+    let square = multiply_batches(&service, &alice_server_key_id, &x, &x).await;
+    let re_encryption_key_id = generate_re_encryption_key(&service, &alice_key_id, &bob_key_id).await.unwrap();
+    let y = re_encrypt(&service, &re_encryption_key_id, &square).await.unwrap();
+    assert_eq!(decrypt_batch(&service, &bob_key_id, &y).await, vec![9, 16, 10000]);
+    
+    // The copy keeps its remaining depth and works with Bob's server key; 10^8 wraps modulo 65537
+    let fourth = multiply_batches(&service, &bob_server_key_id, &y, &y).await;
+    assert_eq!(decrypt_batch(&service, &bob_key_id, &fourth).await, vec![81, 256, -9462]);
+}
+
+#[tokio::test]
+async fn test_re_encryption_rejections() {
+    let service = setup_service().await;
+    let (tfhe_alice_id, _) = generate_keys(&service, Scheme::Tfhe).await;
+    let (tfhe_bob_id, _) = generate_keys(&service, Scheme::Tfhe).await;
+    let (ckks_key_id, _) = generate_keys(&service, Scheme::Ckks).await;
+    let (bgv_alice_id, _) = generate_keys(&service, Scheme::Bgv).await;
+    let (bgv_bob_id, _) = generate_keys(&service, Scheme::Bgv).await;
+    
+    let status = generate_re_encryption_key(&service, &tfhe_alice_id, &tfhe_bob_id).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::Unsupported));
+    let status = generate_re_encryption_key(&service, &ckks_key_id, &bgv_bob_id).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::InvalidRequest));
+    let status = generate_re_encryption_key(&service, &bgv_alice_id, &bgv_alice_id).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::InvalidRequest));
+    let status = generate_re_encryption_key(&service, &bgv_alice_id, "missing").await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::KeyNotFound));
+    
+    let x = encrypt_batch(&service, &bgv_alice_id, vec![1]).await;
+    let status = re_encrypt(&service, "missing", &x).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::KeyNotFound));
+    
+    // A TFHE ciphertext can't go through a BGV re-encryption key
+    let request = Request::new(EncryptBooleanRequest {
+        client_key_id: tfhe_alice_id,
+        value: true,
+        ..Default::default()
+    });
+    let boolean_id = service.encrypt_boolean(request).await.unwrap().into_inner().encrypted_data_id;
+    let re_encryption_key_id = generate_re_encryption_key(&service, &bgv_alice_id, &bgv_bob_id).await.unwrap();
+    let status = re_encrypt(&service, &re_encryption_key_id, &boolean_id).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::TypeMismatch));
+    let status = re_encrypt(&service, &re_encryption_key_id, "missing").await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::CiphertextNotFound));
+}
+
+#[tokio::test]
+async fn test_deleting_a_key_pair_removes_its_re_encryption_keys() {
+    let service = setup_service().await;
+    let admin = FheAdminServiceImpl::new(service.clone());
+    let (alice_key_id, _) = generate_keys(&service, Scheme::Bgv).await;
+    let (bob_key_id, _) = generate_keys(&service, Scheme::Bgv).await;
+    
+    let x = encrypt_batch(&service, &alice_key_id, vec![7]).await;
+    let re_encryption_key_id = generate_re_encryption_key(&service, &alice_key_id, &bob_key_id).await.unwrap();
+    re_encrypt(&service, &re_encryption_key_id, &x).await.unwrap();
+    
+    let request = Request::new(DeleteKeyPairRequest {
+        key_id: bob_key_id,
+    });
+    admin.delete_key_pair(request).await.unwrap();
+    let status = re_encrypt(&service, &re_encryption_key_id, &x).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::KeyNotFound));
+}
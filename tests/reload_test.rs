@@ -0,0 +1,176 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tonic::Request;
+
+use hermetic_fhe::api::{
+    FheAdminService, FheService, KeyGenerationRequest, ReloadConfigRequest, ServerInfoRequest,
+};
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::admin::FheAdminServiceImpl;
+use hermetic_fhe::service::admission::{AdmissionConfig, AdmissionControl, FairShareConfig};
+use hermetic_fhe::service::authorization::PolicyFile;
+use hermetic_fhe::service::errors::ErrorReason;
+use hermetic_fhe::service::reload::{LogLevelSetter, RuntimeConfigFile};
+use hermetic_fhe::service::webhook::WebhookPolicy;
+use hermetic_fhe::service::FheServiceImpl;
+
+fn temp_file(name: &str, contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("hermetic-fhe-{}-{}", name, uuid::Uuid::new_v4()));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+#[tokio::test]
+async fn test_policy_file_reloads() {
+    let path = temp_file(
+        "policy",
+        r#"{"rules": [{"effect": "allow", "methods": ["GetServerInfo"]}]}"#,
+    );
+    let service = FheServiceImpl::new(Arc::new(KeyStore::new()), Arc::new(CiphertextStore::new()))
+        .with_policy(Box::new(PolicyFile::open(&path).unwrap()));
+    let admin = FheAdminServiceImpl::new(service.clone());
+    let generate = || service.generate_keys(Request::new(KeyGenerationRequest::default()));
+    let status = generate().await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::PermissionDenied));
+    
+    // Unchanged, the file isn't read again
+    assert!(service.reload_config(false).reloaded.is_empty());
+    
+    std::fs::write(&path, r#"{"rules": [{"effect": "allow"}]}"#).unwrap();
+    let response = admin
+        .reload_config(Request::new(ReloadConfigRequest {}))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(response.reloaded, vec!["policy"]);
+    generate().await.unwrap();
+    
+    // A broken file is reported, and the rules before it stay in force
+    std::fs::write(&path, r#"{"rules": [{"effect": "maybe"}]}"#).unwrap();
+    let status = admin
+        .reload_config(Request::new(ReloadConfigRequest {}))
+        .await
+        .unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::InvalidRequest));
+    generate().await.unwrap();
+    service
+        .get_server_info(Request::new(ServerInfoRequest {}))
+        .await
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_runtime_config_reloads() {
+    let admission = AdmissionControl::new(AdmissionConfig {
+        workers: 2,
+        queue_depth: 8,
+    })
+    .with_fair_share(FairShareConfig::default());
+    let level = Arc::new(Mutex::new(String::new()));
+    let set_level = level.clone();
+    let setter: LogLevelSetter = Box::new(move |filter: &str| {
+        if filter.contains(' ') {
+            return Err(anyhow::anyhow!("not a filter"));
+        }
+        *set_level.lock().unwrap() = filter.to_string();
+        Ok(())
+    });
+    let path = temp_file(
+        "runtime",
+        r#"{"log_level": "debug", "tenant_weights": {"acme": 4}, "rpc_max_workers": {"RunInference": 1}}"#,
+    );
+    let (key_store, ciphertext_store) = (Arc::new(KeyStore::new()), Arc::new(CiphertextStore::new()));
+    let service = FheServiceImpl::with_admission_control(key_store, ciphertext_store, admission)
+        .with_runtime_config(RuntimeConfigFile::new(&path, Some(setter)));
+    
+    assert_eq!(service.reload_config(false).reloaded, vec!["runtime config"]);
+    assert!(service.reload_config(false).reloaded.is_empty());
+    assert_eq!(*level.lock().unwrap(), "debug");
+    let fair_share = service.fair_share().unwrap();
+    assert_eq!(fair_share.weight("acme"), 4);
+    assert_eq!(fair_share.rpc_max_workers["RunInference"], 1);
+    
+    // Settings left out go back to their defaults
+    std::fs::write(&path, r#"{"tenant_weights": {"acme": 2}}"#).unwrap();
+    assert_eq!(service.reload_config(true).reloaded, vec!["runtime config"]);
+    assert_eq!(*level.lock().unwrap(), "info");
+    let fair_share = service.fair_share().unwrap();
+    assert_eq!(fair_share.weight("acme"), 2);
+    assert!(fair_share.rpc_max_workers.is_empty());
+    
+    // Nothing is applied from a version that is refused
+    for broken in [
+        r#"{"log_level": "not a filter", "tenant_weights": {"acme": 8}}"#,
+        r#"{"tenant_weights": {"acme": 0}}"#,
+        r#"{"tenant_weight": {"acme": 8}}"#,
+    ] {
+        std::fs::write(&path, broken).unwrap();
+        let outcome = service.reload_config(true);
+        assert!(outcome.reloaded.is_empty());
+        assert_eq!(outcome.errors.len(), 1);
+        assert_eq!(service.fair_share().unwrap().weight("acme"), 2);
+        assert_eq!(*level.lock().unwrap(), "info");
+    }
+    std::fs::remove_file(&path).unwrap();
+    
+    // Without fair share there are no weights to change
+    let admission = AdmissionControl::new(AdmissionConfig {
+        workers: 2,
+        queue_depth: 8,
+    });
+    assert!(!admission.set_fair_share(FairShareConfig::default()));
+    assert!(admission.fair_share().is_none());
+}
+
+async fn file_sinks(service: &FheServiceImpl) -> bool {
+    let info = service.get_server_info(Request::new(ServerInfoRequest {})).await.unwrap();
+    info.into_inner().features.unwrap().file_sinks
+}
+
+#[tokio::test]
+async fn test_runtime_config_overrides_sinks_and_webhooks() {
+    let sink_dir = std::env::temp_dir().join(format!("hermetic-fhe-sinks-{}", uuid::Uuid::new_v4()));
+    let path = temp_file("runtime", "{}");
+    let service = FheServiceImpl::new(Arc::new(KeyStore::new()), Arc::new(CiphertextStore::new()))
+        .with_runtime_config(RuntimeConfigFile::new(&path, None));
+    assert_eq!(service.reload_config(false).reloaded, vec!["runtime config"]);
+    assert!(!file_sinks(&service).await);
+    
+    let config = serde_json::json!({"sink_dir": sink_dir, "default_parameter_set": "FAST"});
+    std::fs::write(&path, config.to_string()).unwrap();
+    assert_eq!(service.reload_config(true).reloaded, vec!["runtime config"]);
+    assert!(sink_dir.is_dir());
+    assert!(file_sinks(&service).await);
+    
+    // Nothing is applied from a version that is refused, however much of it is valid
+    for broken in [
+        serde_json::json!({"default_parameter_set": "HUGE"}),
+        serde_json::json!({"webhook_urls": ["https://hooks.example"]}),
+    ] {
+        std::fs::write(&path, broken.to_string()).unwrap();
+        let outcome = service.reload_config(true);
+        assert!(outcome.reloaded.is_empty());
+        assert_eq!(outcome.errors.len(), 1);
+        assert!(file_sinks(&service).await);
+    }
+    
+    // Left out, the policy the server started with applies again
+    std::fs::write(&path, "{}").unwrap();
+    assert_eq!(service.reload_config(true).reloaded, vec!["runtime config"]);
+    assert!(!file_sinks(&service).await);
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_dir_all(&sink_dir).unwrap();
+}
+
+#[test]
+fn test_webhook_urls_keep_the_secret() {
+    let policy = WebhookPolicy::new(b"secret".to_vec(), ["https://hooks.example".to_string()]);
+    let replaced = policy.with_allowed(["https://other.example".to_string()]).unwrap();
+    assert_eq!(replaced.allowed(), ["https://other.example/"]);
+    assert_eq!(replaced.enabled(), policy.enabled());
+    
+    // Without a secret there is nothing to sign notifications with
+    assert!(WebhookPolicy::default().with_allowed(["https://other.example".to_string()]).is_err());
+    assert!(WebhookPolicy::default().with_allowed(Vec::new()).is_ok());
+}
@@ -0,0 +1,20 @@
+use hermetic_fhe::backend::self_test::{self, SelfTestConfig};
+
+#[test]
+fn test_self_test_checks_every_backend() {
+    let config = SelfTestConfig::new(vec!["DEFAULT".to_string()]).unwrap();
+    let checks = self_test::run(&config).unwrap();
+    let names: Vec<String> = checks.iter().map(|check| check.name()).collect();
+    assert_eq!(names, vec!["TFHE DEFAULT", "CKKS", "BGV"]);
+    assert!(checks.iter().all(|check| !check.key_generation.is_zero()));
+    
+    // CKKS and BGV are checked even with no TFHE parameter sets
+    let checks = self_test::run(&SelfTestConfig::new(Vec::new()).unwrap()).unwrap();
+    assert_eq!(checks.len(), 2);
+}
+
+#[test]
+fn test_self_test_rejects_unknown_parameter_sets() {
+    let error = SelfTestConfig::new(vec!["DEFAULT".to_string(), "TURBO".to_string()]).unwrap_err();
+    assert!(error.to_string().contains("TURBO"));
+}
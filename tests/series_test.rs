@@ -0,0 +1,242 @@
+use std::sync::Arc;
+use tonic::{Request, Status};
+
+use hermetic_fhe::api::v1::evaluation_request::OverflowBehavior;
+use hermetic_fhe::api::{
+    CiphertextType, DecryptIntegerRequest, EncryptIntegerRequest, EncryptedRecord, ExportCiphertextRequest,
+    FheService, KeyGenerationRequest, ReduceOperationRequest, SeriesTransform, TransformSeriesRequest,
+};
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::errors::ErrorReason;
+use hermetic_fhe::service::FheServiceImpl;
+
+// A service holding one labeled integer per (label, value); returns the client and server
+// key IDs
+async fn setup_service(rows: &[(&str, i64)]) -> (FheServiceImpl, String, String) {
+    let service = FheServiceImpl::new(Arc::new(KeyStore::new()), Arc::new(CiphertextStore::new()));
+    let keys = service
+        .generate_keys(Request::new(KeyGenerationRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    let mut records = Vec::new();
+    for (label, value) in rows {
+        let request = Request::new(EncryptIntegerRequest {
+            client_key_id: keys.client_key_id.clone(),
+            value: *value,
+            num_bits: 8,
+            ..Default::default()
+        });
+        let encrypted_data_id = service
+            .encrypt_integer(request)
+            .await
+            .unwrap()
+            .into_inner()
+            .encrypted_data_id;
+        let request = Request::new(ExportCiphertextRequest { encrypted_data_id });
+        let export = service.export_ciphertext(request).await.unwrap().into_inner();
+        records.push(Ok::<_, Status>(EncryptedRecord {
+            ciphertext_type: CiphertextType::Integer as i32,
+            serialized_data: export.serialized_data,
+            fingerprint: export.fingerprint,
+            label: label.to_string(),
+            ..Default::default()
+        }));
+    }
+    service.ingest(tokio_stream::iter(records)).await.unwrap();
+    (service, keys.client_key_id, keys.server_key_id)
+}
+
+async fn decrypt_integer(service: &FheServiceImpl, client_key_id: &str, encrypted_data_id: &str) -> i64 {
+    let request = Request::new(DecryptIntegerRequest {
+        client_key_id: client_key_id.to_string(),
+        encrypted_data_id: encrypted_data_id.to_string(),
+        ..Default::default()
+    });
+    service.decrypt_integer(request).await.unwrap().into_inner().value
+}
+
+fn transform_request(
+    server_key_id: &str,
+    result_prefix: &str,
+    transform: SeriesTransform,
+    overflow: OverflowBehavior,
+) -> TransformSeriesRequest {
+    TransformSeriesRequest {
+        server_key_id: server_key_id.to_string(),
+        label_prefix: "cpu/".to_string(),
+        result_prefix: result_prefix.to_string(),
+        transform: transform as i32,
+        overflow: overflow as i32,
+        ..Default::default()
+    }
+}
+
+// The transformed series as (label, value)
+async fn transform(
+    service: &FheServiceImpl,
+    client_key_id: &str,
+    request: TransformSeriesRequest,
+) -> Vec<(String, i64)> {
+    let response = service
+        .transform_series(Request::new(request))
+        .await
+        .unwrap()
+        .into_inner();
+    let mut points = Vec::new();
+    for point in response.points {
+        let value = decrypt_integer(service, client_key_id, &point.encrypted_data_id).await;
+        points.push((point.label, value));
+    }
+    points
+}
+
+async fn refused(service: &FheServiceImpl, request: TransformSeriesRequest) -> ErrorReason {
+    let status = service.transform_series(Request::new(request)).await.unwrap_err();
+    ErrorReason::of(&status).unwrap()
+}
+
+fn labeled(prefix: &str, points: &[(&str, i64)]) -> Vec<(String, i64)> {
+    points
+        .iter()
+        .map(|(suffix, value)| (format!("{}{}", prefix, suffix), *value))
+        .collect()
+}
+
+#[tokio::test]
+async fn test_series_deltas() {
+    // Out of order on purpose; the series follows label order
+    let rows = [
+        ("cpu/0003", 35),
+        ("cpu/0001", 20),
+        ("cpu/0002", 50),
+        ("cpu/0004", 35),
+    ];
+    let (service, client_key_id, server_key_id) = setup_service(&rows).await;
+    
+    // The drop of 15 wraps to 241
+    let request = transform_request(
+        &server_key_id,
+        "delta/",
+        SeriesTransform::Deltas,
+        OverflowBehavior::Wrap,
+    );
+    let points = transform(&service, &client_key_id, request).await;
+    assert_eq!(
+        points,
+        labeled("delta/", &[("0002", 30), ("0003", 241), ("0004", 0)])
+    );
+    
+    let request = transform_request(
+        &server_key_id,
+        "rise/",
+        SeriesTransform::Deltas,
+        OverflowBehavior::Saturate,
+    );
+    let points = transform(&service, &client_key_id, request).await;
+    assert_eq!(
+        points,
+        labeled("rise/", &[("0002", 30), ("0003", 0), ("0004", 0)])
+    );
+    
+    let request = transform_request(
+        &server_key_id,
+        "change/",
+        SeriesTransform::AbsoluteDeltas,
+        OverflowBehavior::Wrap,
+    );
+    let points = transform(&service, &client_key_id, request).await;
+    assert_eq!(
+        points,
+        labeled("change/", &[("0002", 30), ("0003", 15), ("0004", 0)])
+    );
+    
+    // The deltas are a labeled series like any other, summing to the last point less the first
+    let request = Request::new(ReduceOperationRequest {
+        server_key_id: server_key_id.clone(),
+        label_prefix: "delta/".to_string(),
+        ..Default::default()
+    });
+    let result_id = service
+        .reduce_operation(request)
+        .await
+        .unwrap()
+        .into_inner()
+        .result_id;
+    assert_eq!(decrypt_integer(&service, &client_key_id, &result_id).await, 15);
+}
+
+#[tokio::test]
+async fn test_series_cumulative_sum() {
+    let rows = [
+        ("cpu/0001", 100),
+        ("cpu/0002", 50),
+        ("cpu/0003", 70),
+        ("cpu/0004", 40),
+    ];
+    let (service, client_key_id, server_key_id) = setup_service(&rows).await;
+    
+    let request = transform_request(
+        &server_key_id,
+        "total/",
+        SeriesTransform::CumulativeSum,
+        OverflowBehavior::Wrap,
+    );
+    let points = transform(&service, &client_key_id, request).await;
+    let expected = [("0001", 100), ("0002", 150), ("0003", 220), ("0004", 4)];
+    assert_eq!(points, labeled("total/", &expected));
+    
+    let request = transform_request(
+        &server_key_id,
+        "capped/",
+        SeriesTransform::CumulativeSum,
+        OverflowBehavior::Saturate,
+    );
+    let points = transform(&service, &client_key_id, request).await;
+    let expected = [("0001", 100), ("0002", 150), ("0003", 220), ("0004", 255)];
+    assert_eq!(points, labeled("capped/", &expected));
+}
+
+#[tokio::test]
+async fn test_series_refused() {
+    let (service, _, server_key_id) = setup_service(&[("cpu/0001", 1)]).await;
+    
+    // One point has no deltas, but has a running sum
+    let request = transform_request(
+        &server_key_id,
+        "delta/",
+        SeriesTransform::Deltas,
+        OverflowBehavior::Wrap,
+    );
+    assert_eq!(refused(&service, request).await, ErrorReason::InvalidRequest);
+    let request = transform_request(
+        &server_key_id,
+        "total/",
+        SeriesTransform::CumulativeSum,
+        OverflowBehavior::Wrap,
+    );
+    assert!(service.transform_series(Request::new(request)).await.is_ok());
+    
+    let request = transform_request(
+        &server_key_id,
+        "cpu/",
+        SeriesTransform::CumulativeSum,
+        OverflowBehavior::Wrap,
+    );
+    assert_eq!(refused(&service, request).await, ErrorReason::InvalidRequest);
+    let request = transform_request(
+        &server_key_id,
+        "change/",
+        SeriesTransform::AbsoluteDeltas,
+        OverflowBehavior::Saturate,
+    );
+    assert_eq!(refused(&service, request).await, ErrorReason::InvalidRequest);
+    let mut request = transform_request(
+        &server_key_id,
+        "delta/",
+        SeriesTransform::Deltas,
+        OverflowBehavior::Wrap,
+    );
+    request.label_prefix = "memory/".to_string();
+    assert_eq!(refused(&service, request).await, ErrorReason::CiphertextNotFound);
+}
@@ -0,0 +1,85 @@
+use std::sync::Arc;
+use tonic::codegen::{http, Service};
+use tonic::transport::Body;
+use tonic::Request;
+
+use hermetic_fhe::api::{FheService, FheServiceServer, OperationType, ServerInfoRequest};
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::fhe_service::MAX_MESSAGE_BYTES;
+use hermetic_fhe::service::legacy::LegacyService;
+use hermetic_fhe::service::FheServiceImpl;
+
+async fn setup_service() -> FheServiceImpl {
+    let key_store = Arc::new(KeyStore::new());
+    let ciphertext_store = Arc::new(CiphertextStore::new());
+    FheServiceImpl::new(key_store, ciphertext_store)
+}
+
+// A raw gRPC request for GetServerInfo: an uncompressed, zero-length message frame
+fn server_info_request(service_name: &str) -> http::Request<Body> {
+    http::Request::builder()
+        .method("POST")
+        .uri(format!("http://localhost/{}/GetServerInfo", service_name))
+        .header("content-type", "application/grpc")
+        .header("te", "trailers")
+        .body(Body::from(vec![0u8; 5]))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_get_server_info() {
+    let service = setup_service().await;
+    
+    let response = service.get_server_info(Request::new(ServerInfoRequest {})).await.unwrap();
+    let info = response.get_ref();
+    
+    assert!(info.api_versions.contains(&"v1".to_string()), "Server should advertise v1");
+    assert!(info.operations.contains(&(OperationType::And as i32)));
+    assert!(info.operations.contains(&(OperationType::Multiply as i32)));
+    assert!(!info.operations.contains(&(OperationType::Equal as i32)), "Unimplemented operations should not be advertised");
+    assert_eq!(info.integer_widths, vec![8]);
+    assert_eq!(info.parameter_sets.len(), 3);
+    
+    // Build details and limits
+    assert!(info.tfhe_version.starts_with("0."), "Unexpected tfhe version {}", info.tfhe_version);
+    assert_eq!(info.max_integer_width, 8);
+    assert!(!info.features.as_ref().unwrap().comparisons, "Comparisons are not implemented yet");
+    assert!(info.features.as_ref().unwrap().ckks);
+    assert!(info.features.as_ref().unwrap().bgv);
+    assert!(info.features.as_ref().unwrap().re_encryption);
+    let limits = info.limits.as_ref().unwrap();
+    assert!(limits.max_circuit_gates > 0);
+    assert_eq!(limits.max_session_idle_timeout_seconds, 24 * 60 * 60);
+    assert!(limits.max_vector_length > 0);
+    assert_eq!(limits.max_real_vector_length, 4096);
+    assert_eq!(limits.max_integer_batch_length, 4096);
+    assert_eq!(limits.max_message_bytes as usize, MAX_MESSAGE_BYTES);
+}
+
+#[tokio::test]
+async fn test_server_info_reports_configured_message_limit() {
+    let service = setup_service().await.with_max_message_bytes(256 * 1024 * 1024);
+    
+    let info = service.get_server_info(Request::new(ServerInfoRequest {})).await.unwrap().into_inner();
+    assert_eq!(info.limits.unwrap().max_message_bytes, 256 * 1024 * 1024);
+}
+
+#[tokio::test]
+async fn test_legacy_package_is_routed_to_v1() {
+    let service = setup_service().await;
+    
+    // The v1 server alone does not know the unversioned path
+    let mut v1 = FheServiceServer::new(service.clone());
+    let response = v1.call(server_info_request("hermetic_fhe.FheService")).await.unwrap();
+    assert_eq!(
+        response.headers().get("grpc-status").map(|v| v.to_str().unwrap()),
+        Some("12"),
+        "Unversioned path should be unimplemented without the legacy alias"
+    );
+    
+    // Wrapped in the legacy alias, the same request reaches the v1 handler
+    let mut legacy = LegacyService::new(FheServiceServer::new(service));
+    let response = legacy.call(server_info_request("hermetic_fhe.FheService")).await.unwrap();
+    assert_eq!(response.status(), http::StatusCode::OK);
+    assert!(response.headers().get("grpc-status").is_none(), "Legacy call should succeed");
+}
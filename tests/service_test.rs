@@ -2,10 +2,13 @@ use std::sync::Arc;
 use tonic::Request;
 
 use hermetic_fhe::api::{
-    DecryptBooleanRequest, EncryptBooleanRequest, EvaluationRequest,
-    FheService, KeyGenerationRequest, OperationType,
+    CiphertextType, DecryptBooleanRequest, EncryptBooleanRequest, EncryptIntegerRequest,
+    EvaluationRequest, ExportCiphertextRequest, FheService, ImportCiphertextRequest,
+    KeyGenerationRequest, OperationType, WarmServerKeysRequest,
 };
+use hermetic_fhe::api::v1::key_generation_request::{Scheme, TypePolicy};
 use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::errors::ErrorReason;
 use hermetic_fhe::service::FheServiceImpl;
 
 async fn setup_service() -> impl FheService {
@@ -20,6 +23,7 @@ async fn test_key_generation() {
     
     let request = Request::new(KeyGenerationRequest {
         parameter_set: 0, // DEFAULT
+        ..Default::default()
     });
     
     let response = service.generate_keys(request).await.unwrap();
@@ -29,6 +33,30 @@ async fn test_key_generation() {
     assert!(!response_body.server_key_id.is_empty(), "Server key ID should not be empty");
 }
 
+#[tokio::test]
+async fn test_warm_server_keys() {
+    let service = setup_service().await;
+    
+    let key_gen_request = Request::new(KeyGenerationRequest {
+        parameter_set: 0, // DEFAULT
+        ..Default::default()
+    });
+    let server_key_id = service.generate_keys(key_gen_request).await.unwrap().into_inner().server_key_id;
+    
+    let request = Request::new(WarmServerKeysRequest {
+        server_key_ids: vec![server_key_id],
+    });
+    let response = service.warm_server_keys(request).await.unwrap();
+    assert!(response.get_ref().workers > 0, "Keys should be installed on at least one worker");
+    
+    // Unknown keys are rejected before any work is done
+    let request = Request::new(WarmServerKeysRequest {
+        server_key_ids: vec!["nonexistent-key".to_string()],
+    });
+    let status = service.warm_server_keys(request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::NotFound);
+}
+
 #[tokio::test]
 async fn test_encrypt_decrypt_boolean() {
     let service = setup_service().await;
@@ -36,6 +64,7 @@ async fn test_encrypt_decrypt_boolean() {
     // Generate keys
     let key_gen_request = Request::new(KeyGenerationRequest {
         parameter_set: 0, // DEFAULT
+        ..Default::default()
     });
     
     let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
@@ -45,6 +74,7 @@ async fn test_encrypt_decrypt_boolean() {
     let encrypt_request = Request::new(EncryptBooleanRequest {
         client_key_id: client_key_id.clone(),
         value: true,
+        ..Default::default()
     });
     
     let encrypt_response = service.encrypt_boolean(encrypt_request).await.unwrap();
@@ -60,7 +90,7 @@ async fn test_encrypt_decrypt_boolean() {
     let decrypt_response = service.decrypt_boolean(decrypt_request).await.unwrap();
     let decrypted_value = decrypt_response.get_ref().value;
     
-    assert_eq!(decrypted_value, true, "Decrypted value should match the original");
+    assert!(decrypted_value, "Decrypted value should match the original");
 }
 
 #[tokio::test]
@@ -70,6 +100,7 @@ async fn test_boolean_and_operation() {
     // Generate keys
     let key_gen_request = Request::new(KeyGenerationRequest {
         parameter_set: 0, // DEFAULT
+        ..Default::default()
     });
     
     let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
@@ -80,6 +111,7 @@ async fn test_boolean_and_operation() {
     let encrypt_true_request = Request::new(EncryptBooleanRequest {
         client_key_id: client_key_id.clone(),
         value: true,
+        ..Default::default()
     });
     
     let encrypt_true_response = service.encrypt_boolean(encrypt_true_request).await.unwrap();
@@ -89,6 +121,7 @@ async fn test_boolean_and_operation() {
     let encrypt_false_request = Request::new(EncryptBooleanRequest {
         client_key_id: client_key_id.clone(),
         value: false,
+        ..Default::default()
     });
     
     let encrypt_false_response = service.encrypt_boolean(encrypt_false_request).await.unwrap();
@@ -99,6 +132,7 @@ async fn test_boolean_and_operation() {
         server_key_id: server_key_id.clone(),
         operation: OperationType::And as i32,
         operand_ids: vec![true_id, false_id],
+        ..Default::default()
     });
     
     let eval_response = service.evaluate_operation(eval_request).await.unwrap();
@@ -114,7 +148,7 @@ async fn test_boolean_and_operation() {
     let decrypt_response = service.decrypt_boolean(decrypt_request).await.unwrap();
     let result = decrypt_response.get_ref().value;
     
-    assert_eq!(result, false, "true AND false should be false");
+    assert!(!result, "true AND false should be false");
 }
 
 #[tokio::test]
@@ -124,6 +158,7 @@ async fn test_boolean_or_operation() {
     // Generate keys
     let key_gen_request = Request::new(KeyGenerationRequest {
         parameter_set: 0, // DEFAULT
+        ..Default::default()
     });
     
     let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
@@ -134,6 +169,7 @@ async fn test_boolean_or_operation() {
     let encrypt_true_request = Request::new(EncryptBooleanRequest {
         client_key_id: client_key_id.clone(),
         value: true,
+        ..Default::default()
     });
     
     let encrypt_true_response = service.encrypt_boolean(encrypt_true_request).await.unwrap();
@@ -143,6 +179,7 @@ async fn test_boolean_or_operation() {
     let encrypt_false_request = Request::new(EncryptBooleanRequest {
         client_key_id: client_key_id.clone(),
         value: false,
+        ..Default::default()
     });
     
     let encrypt_false_response = service.encrypt_boolean(encrypt_false_request).await.unwrap();
@@ -153,6 +190,7 @@ async fn test_boolean_or_operation() {
         server_key_id: server_key_id.clone(),
         operation: OperationType::Or as i32,
         operand_ids: vec![true_id, false_id],
+        ..Default::default()
     });
     
     let eval_response = service.evaluate_operation(eval_request).await.unwrap();
@@ -168,7 +206,7 @@ async fn test_boolean_or_operation() {
     let decrypt_response = service.decrypt_boolean(decrypt_request).await.unwrap();
     let result = decrypt_response.get_ref().value;
     
-    assert_eq!(result, true, "true OR false should be true");
+    assert!(result, "true OR false should be true");
 }
 
 #[tokio::test]
@@ -178,6 +216,7 @@ async fn test_boolean_not_operation() {
     // Generate keys
     let key_gen_request = Request::new(KeyGenerationRequest {
         parameter_set: 0, // DEFAULT
+        ..Default::default()
     });
     
     let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
@@ -188,6 +227,7 @@ async fn test_boolean_not_operation() {
     let encrypt_request = Request::new(EncryptBooleanRequest {
         client_key_id: client_key_id.clone(),
         value: true,
+        ..Default::default()
     });
     
     let encrypt_response = service.encrypt_boolean(encrypt_request).await.unwrap();
@@ -198,6 +238,7 @@ async fn test_boolean_not_operation() {
         server_key_id: server_key_id.clone(),
         operation: OperationType::Not as i32,
         operand_ids: vec![id],
+        ..Default::default()
     });
     
     let eval_response = service.evaluate_operation(eval_request).await.unwrap();
@@ -213,5 +254,206 @@ async fn test_boolean_not_operation() {
     let decrypt_response = service.decrypt_boolean(decrypt_request).await.unwrap();
     let result = decrypt_response.get_ref().value;
     
-    assert_eq!(result, false, "NOT true should be false");
-} 
\ No newline at end of file
+    assert!(!result, "NOT true should be false");
+} 
+#[tokio::test]
+async fn test_export_import_ciphertext() {
+    let service = setup_service().await;
+    
+    // Generate keys
+    let key_gen_request = Request::new(KeyGenerationRequest {
+        parameter_set: 0, // DEFAULT
+        ..Default::default()
+    });
+    
+    let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
+    let client_key_id = key_gen_response.get_ref().client_key_id.clone();
+    assert!(!key_gen_response.get_ref().client_key_fingerprint.is_empty(), "Client key should be fingerprinted");
+    assert!(!key_gen_response.get_ref().server_key_fingerprint.is_empty(), "Server key should be fingerprinted");
+    
+    // Encrypt true
+    let encrypt_request = Request::new(EncryptBooleanRequest {
+        client_key_id: client_key_id.clone(),
+        value: true,
+        ..Default::default()
+    });
+    
+    let encrypt_response = service.encrypt_boolean(encrypt_request).await.unwrap();
+    let id = encrypt_response.get_ref().encrypted_data_id.clone();
+    let fingerprint = encrypt_response.get_ref().fingerprint.clone();
+    
+    // Download the ciphertext
+    let export_request = Request::new(ExportCiphertextRequest {
+        encrypted_data_id: id,
+    });
+    
+    let export_response = service.export_ciphertext(export_request).await.unwrap().into_inner();
+    assert_eq!(export_response.fingerprint, fingerprint, "Export fingerprint should match the one issued at encryption");
+    assert_eq!(export_response.ciphertext_type(), CiphertextType::Boolean);
+    
+    // Upload it again and decrypt the imported copy
+    let import_request = Request::new(ImportCiphertextRequest {
+        ciphertext_type: export_response.ciphertext_type,
+        serialized_data: export_response.serialized_data,
+        fingerprint: export_response.fingerprint,
+        ..Default::default()
+    });
+    
+    let import_response = service.import_ciphertext(import_request).await.unwrap();
+    let imported_id = import_response.get_ref().encrypted_data_id.clone();
+    assert_eq!(import_response.get_ref().fingerprint, fingerprint, "Imported copy should keep its fingerprint");
+    
+    let decrypt_request = Request::new(DecryptBooleanRequest {
+        client_key_id: client_key_id.clone(),
+        encrypted_data_id: imported_id,
+        serialized_data: vec![],
+    });
+    
+    let decrypt_response = service.decrypt_boolean(decrypt_request).await.unwrap();
+    assert!(decrypt_response.get_ref().value, "Imported ciphertext should decrypt to the original value");
+}
+
+#[tokio::test]
+async fn test_key_type_policy() {
+    let service = setup_service().await;
+    
+    let request = Request::new(KeyGenerationRequest {
+        policy: Some(TypePolicy {
+            integer_bits: 8,
+            deny_booleans: true,
+            ..Default::default()
+        }),
+        ..Default::default()
+    });
+    let keys = service.generate_keys(request).await.unwrap().into_inner();
+    
+    // Booleans are refused, whether encrypted or produced by an evaluation
+    let request = Request::new(EncryptBooleanRequest {
+        client_key_id: keys.client_key_id.clone(),
+        value: true,
+        session_id: String::new(),
+        ..Default::default()
+    });
+    let status = service.encrypt_boolean(request).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::PolicyViolation));
+    assert_eq!(status.code(), tonic::Code::PermissionDenied);
+    
+    // Integers take the key's width, and no other
+    let encrypt = |num_bits: u32| {
+        Request::new(EncryptIntegerRequest {
+            client_key_id: keys.client_key_id.clone(),
+            value: 7,
+            num_bits,
+            ..Default::default()
+        })
+    };
+    let status = service.encrypt_integer(encrypt(16)).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::WidthMismatch));
+    let id = service.encrypt_integer(encrypt(0)).await.unwrap().into_inner().encrypted_data_id;
+    
+    let evaluate = |operation: OperationType| {
+        Request::new(EvaluationRequest {
+            server_key_id: keys.server_key_id.clone(),
+            operation: operation as i32,
+            operand_ids: vec![id.clone(); operation_arity(operation)],
+            ..Default::default()
+        })
+    };
+    service.evaluate_operation(evaluate(OperationType::Add)).await.unwrap();
+    let status = service.evaluate_operation(evaluate(OperationType::IsZero)).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::PolicyViolation));
+    
+    // Policies the service can't honour are refused up front
+    let unsupported = [
+        TypePolicy {
+            allow_signed: true,
+            ..Default::default()
+        },
+        TypePolicy {
+            integer_bits: 16,
+            ..Default::default()
+        },
+    ];
+    for policy in unsupported {
+        let request = Request::new(KeyGenerationRequest {
+            policy: Some(policy),
+            ..Default::default()
+        });
+        let status = service.generate_keys(request).await.unwrap_err();
+        assert_eq!(ErrorReason::of(&status), Some(ErrorReason::Unsupported));
+    }
+    let request = Request::new(KeyGenerationRequest {
+        scheme: Scheme::Ckks as i32,
+        policy: Some(TypePolicy {
+            deny_booleans: true,
+            ..Default::default()
+        }),
+        ..Default::default()
+    });
+    let status = service.generate_keys(request).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::InvalidRequest));
+}
+
+#[tokio::test]
+async fn test_key_operation_allow_list() {
+    let service = setup_service().await;
+    
+    // An aggregation-only key: sums, but no predicates that could single a value out
+    let request = Request::new(KeyGenerationRequest {
+        policy: Some(TypePolicy {
+            allowed_operations: vec![OperationType::Add as i32],
+            ..Default::default()
+        }),
+        ..Default::default()
+    });
+    let keys = service.generate_keys(request).await.unwrap().into_inner();
+    let request = Request::new(EncryptIntegerRequest {
+        client_key_id: keys.client_key_id.clone(),
+        value: 7,
+        num_bits: 8,
+        ..Default::default()
+    });
+    let id = service.encrypt_integer(request).await.unwrap().into_inner().encrypted_data_id;
+    
+    let evaluate = |operation: OperationType| {
+        Request::new(EvaluationRequest {
+            server_key_id: keys.server_key_id.clone(),
+            operation: operation as i32,
+            operand_ids: vec![id.clone(); operation_arity(operation)],
+            ..Default::default()
+        })
+    };
+    service.evaluate_operation(evaluate(OperationType::Add)).await.unwrap();
+    for operation in [OperationType::IsZero, OperationType::Multiply] {
+        let status = service.evaluate_operation(evaluate(operation)).await.unwrap_err();
+        assert_eq!(ErrorReason::of(&status), Some(ErrorReason::PolicyViolation));
+    }
+    
+    // Expressions are held to the same list, gate by gate
+    let request = Request::new(EvaluationRequest {
+        server_key_id: keys.server_key_id.clone(),
+        expression: "a + a * a".to_string(),
+        variables: [("a".to_string(), id.clone())].into(),
+        ..Default::default()
+    });
+    let status = service.evaluate_operation(request).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::PolicyViolation));
+    
+    // Values that aren't operations are refused rather than ignored
+    let request = Request::new(KeyGenerationRequest {
+        policy: Some(TypePolicy {
+            allowed_operations: vec![99],
+            ..Default::default()
+        }),
+        ..Default::default()
+    });
+    let status = service.generate_keys(request).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::InvalidRequest));
+}
+
+fn operation_arity(operation: OperationType) -> usize {
+    match operation {
+        OperationType::Not | OperationType::IsZero | OperationType::IsNonZero => 1,
+        _ => 2,
+    }
+}
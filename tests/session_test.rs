@@ -0,0 +1,187 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::Request;
+
+use hermetic_fhe::api::{
+    CloseSessionRequest, CreateSessionRequest, DecryptBooleanRequest, EncryptBooleanRequest,
+    EvaluationRequest, FheService, KeyGenerationRequest, OperationType, ReleaseResultsRequest,
+};
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::FheServiceImpl;
+
+async fn setup_service() -> FheServiceImpl {
+    let key_store = Arc::new(KeyStore::new());
+    let ciphertext_store = Arc::new(CiphertextStore::new());
+    FheServiceImpl::new(key_store, ciphertext_store)
+}
+
+async fn encrypt_in_session(service: &FheServiceImpl, client_key_id: &str, session_id: &str, value: bool) -> String {
+    let encrypt_request = Request::new(EncryptBooleanRequest {
+        client_key_id: client_key_id.to_string(),
+        value,
+        session_id: session_id.to_string(),
+        ..Default::default()
+    });
+    
+    let encrypt_response = service.encrypt_boolean(encrypt_request).await.unwrap();
+    encrypt_response.get_ref().encrypted_data_id.clone()
+}
+
+async fn is_present(service: &FheServiceImpl, client_key_id: &str, encrypted_data_id: &str) -> bool {
+    let decrypt_request = Request::new(DecryptBooleanRequest {
+        client_key_id: client_key_id.to_string(),
+        encrypted_data_id: encrypted_data_id.to_string(),
+        serialized_data: vec![],
+    });
+    
+    service.decrypt_boolean(decrypt_request).await.is_ok()
+}
+
+#[tokio::test]
+async fn test_close_session_frees_ciphertexts() {
+    let service = setup_service().await;
+    
+    // Generate keys
+    let key_gen_request = Request::new(KeyGenerationRequest {
+        parameter_set: 0, // DEFAULT
+        ..Default::default()
+    });
+    
+    let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
+    let client_key_id = key_gen_response.get_ref().client_key_id.clone();
+    let server_key_id = key_gen_response.get_ref().server_key_id.clone();
+    
+    // Open a session
+    let create_response = service
+        .create_session(Request::new(CreateSessionRequest { idle_timeout_seconds: 0 }))
+        .await
+        .unwrap();
+    let session_id = create_response.get_ref().session_id.clone();
+    assert!(create_response.get_ref().idle_timeout_seconds > 0, "Default timeout should be applied");
+    
+    // Inputs and the intermediate result all belong to the session
+    let a_id = encrypt_in_session(&service, &client_key_id, &session_id, true).await;
+    let b_id = encrypt_in_session(&service, &client_key_id, &session_id, false).await;
+    
+    let eval_request = Request::new(EvaluationRequest {
+        server_key_id: server_key_id.clone(),
+        operation: OperationType::Or as i32,
+        operand_ids: vec![a_id.clone(), b_id.clone()],
+        session_id: session_id.clone(),
+        ..Default::default()
+    });
+    let result_id = service.evaluate_operation(eval_request).await.unwrap().into_inner().result_id;
+    
+    // A ciphertext created outside the session should survive the close
+    let outside_id = encrypt_in_session(&service, &client_key_id, "", true).await;
+    
+    let close_response = service
+        .close_session(Request::new(CloseSessionRequest { session_id: session_id.clone() }))
+        .await
+        .unwrap();
+    assert_eq!(close_response.get_ref().freed_ciphertexts, 3, "Both inputs and the result should be freed");
+    
+    for id in [&a_id, &b_id, &result_id] {
+        assert!(!is_present(&service, &client_key_id, id).await, "Session ciphertexts should be gone after close");
+    }
+    assert!(is_present(&service, &client_key_id, &outside_id).await, "Ciphertexts outside the session should remain");
+    
+    // The session itself is gone
+    let response = service
+        .close_session(Request::new(CloseSessionRequest { session_id: session_id.clone() }))
+        .await;
+    assert_eq!(response.unwrap_err().code(), tonic::Code::NotFound);
+}
+
+#[tokio::test]
+async fn test_session_idle_timeout() {
+    let service = setup_service().await;
+    
+    // Generate keys
+    let key_gen_request = Request::new(KeyGenerationRequest {
+        parameter_set: 0, // DEFAULT
+        ..Default::default()
+    });
+    
+    let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
+    let client_key_id = key_gen_response.get_ref().client_key_id.clone();
+    
+    // Open a session with a one second idle timeout
+    let session_id = service
+        .create_session(Request::new(CreateSessionRequest { idle_timeout_seconds: 1 }))
+        .await
+        .unwrap()
+        .into_inner()
+        .session_id;
+    
+    let id = encrypt_in_session(&service, &client_key_id, &session_id, true).await;
+    assert!(is_present(&service, &client_key_id, &id).await, "Ciphertext should exist while the session is live");
+    
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(service.reap_expired_sessions(), 1, "Expired session's ciphertext should be reaped");
+    assert!(!is_present(&service, &client_key_id, &id).await, "Ciphertext should be freed after the timeout");
+    
+    // Requests naming the expired session are rejected
+    let encrypt_request = Request::new(EncryptBooleanRequest {
+        client_key_id: client_key_id.clone(),
+        value: true,
+        session_id,
+        ..Default::default()
+    });
+    
+    let status = service.encrypt_boolean(encrypt_request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::NotFound);
+    assert!(status.message().contains("Session not found"));
+}
+
+#[tokio::test]
+async fn test_unfetched_results_outlive_their_session() {
+    let ciphertext_store = Arc::new(CiphertextStore::new());
+    let service = FheServiceImpl::new(Arc::new(KeyStore::new()), ciphertext_store.clone());
+    let keys = service
+        .generate_keys(Request::new(KeyGenerationRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    let session_id = service
+        .create_session(Request::new(CreateSessionRequest { idle_timeout_seconds: 1 }))
+        .await
+        .unwrap()
+        .into_inner()
+        .session_id;
+    let a_id = encrypt_in_session(&service, &keys.client_key_id, &session_id, true).await;
+    let b_id = encrypt_in_session(&service, &keys.client_key_id, &session_id, false).await;
+    
+    let evaluate = |operation: OperationType| {
+        Request::new(EvaluationRequest {
+            server_key_id: keys.server_key_id.clone(),
+            operation: operation as i32,
+            operand_ids: vec![a_id.clone(), b_id.clone()],
+            session_id: session_id.clone(),
+            ..Default::default()
+        })
+    };
+    let or = service.evaluate_operation(evaluate(OperationType::Or)).await.unwrap().into_inner();
+    let and = service.evaluate_operation(evaluate(OperationType::And)).await.unwrap().into_inner();
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+    assert!(or.lease_expires_unix_seconds > now, "Results in a session should be pinned");
+    
+    // A released result is no longer pinned; releasing it again finds nothing
+    let release = || {
+        Request::new(ReleaseResultsRequest {
+            result_ids: vec![and.result_id.clone()],
+        })
+    };
+    assert_eq!(service.release_results(release()).await.unwrap().into_inner().released, 1);
+    assert_eq!(service.release_results(release()).await.unwrap().into_inner().released, 0);
+    
+    // The session times out, but the result nobody has read yet stays
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(service.reap_expired_sessions(), 3, "Inputs and the released result should be freed");
+    assert!(ciphertext_store.kind(&or.result_id).is_some(), "Unfetched result should survive");
+    
+    // Once fetched, it goes the way of its session
+    assert!(is_present(&service, &keys.client_key_id, &or.result_id).await);
+    assert_eq!(service.reap_expired_sessions(), 1);
+    assert!(ciphertext_store.kind(&or.result_id).is_none());
+}
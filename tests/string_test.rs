@@ -0,0 +1,141 @@
+use std::sync::Arc;
+use tonic::Request;
+
+use hermetic_fhe::api::{
+    DecryptBooleanRequest, EncryptIntegerRequest, FheService, KeyGenerationRequest, MatchStringRequest,
+    StringMatch,
+};
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::errors::ErrorReason;
+use hermetic_fhe::service::FheServiceImpl;
+
+// A service holding "Alice" encrypted a byte at a time; returns the client and server key
+// IDs and the byte IDs
+async fn setup_service() -> (FheServiceImpl, String, String, Vec<String>) {
+    let service = FheServiceImpl::new(Arc::new(KeyStore::new()), Arc::new(CiphertextStore::new()));
+    let keys = service
+        .generate_keys(Request::new(KeyGenerationRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    let mut byte_ids = Vec::new();
+    for byte in "Alice".bytes() {
+        let request = Request::new(EncryptIntegerRequest {
+            client_key_id: keys.client_key_id.clone(),
+            value: byte as i64,
+            num_bits: 8,
+            ..Default::default()
+        });
+        byte_ids.push(
+            service
+                .encrypt_integer(request)
+                .await
+                .unwrap()
+                .into_inner()
+                .encrypted_data_id,
+        );
+    }
+    (service, keys.client_key_id, keys.server_key_id, byte_ids)
+}
+
+async fn decrypt(service: &FheServiceImpl, client_key_id: &str, encrypted_data_id: &str) -> bool {
+    let request = Request::new(DecryptBooleanRequest {
+        client_key_id: client_key_id.to_string(),
+        encrypted_data_id: encrypted_data_id.to_string(),
+        ..Default::default()
+    });
+    service.decrypt_boolean(request).await.unwrap().into_inner().value
+}
+
+// Which candidates match, and whether any does
+async fn matches(
+    service: &FheServiceImpl,
+    client_key_id: &str,
+    request: MatchStringRequest,
+) -> (Vec<bool>, bool) {
+    let response = service
+        .match_string(Request::new(request))
+        .await
+        .unwrap()
+        .into_inner();
+    let mut matched = Vec::new();
+    for id in &response.match_ids {
+        matched.push(decrypt(service, client_key_id, id).await);
+    }
+    (
+        matched,
+        decrypt(service, client_key_id, &response.any_match_id).await,
+    )
+}
+
+async fn refused(service: &FheServiceImpl, request: MatchStringRequest) -> ErrorReason {
+    let status = service.match_string(Request::new(request)).await.unwrap_err();
+    ErrorReason::of(&status).unwrap()
+}
+
+#[tokio::test]
+async fn test_match_string_against_candidates() {
+    let (service, client_key_id, server_key_id, byte_ids) = setup_service().await;
+    let candidates: Vec<String> = ["alice", "ALICE", "Alce", "Alicex", "Alicia", "Bob"]
+        .iter()
+        .map(|candidate| candidate.to_string())
+        .collect();
+    let request = |mode: StringMatch, case_sensitive: bool| MatchStringRequest {
+        server_key_id: server_key_id.clone(),
+        byte_ids: byte_ids.clone(),
+        candidates: candidates.clone(),
+        mode: mode as i32,
+        case_sensitive,
+        ..Default::default()
+    };
+    
+    // Case is ignored by default
+    let (matched, any) = matches(&service, &client_key_id, request(StringMatch::Equals, false)).await;
+    assert_eq!(matched, vec![true, true, false, false, false, false]);
+    assert!(any);
+    let (matched, any) = matches(&service, &client_key_id, request(StringMatch::Equals, true)).await;
+    assert_eq!(matched, vec![false; 6]);
+    assert!(!any);
+    
+    // One byte deleted or inserted is within one edit; "Alicia" is two away
+    let (matched, any) = matches(
+        &service,
+        &client_key_id,
+        request(StringMatch::WithinOneEdit, false),
+    )
+    .await;
+    assert_eq!(matched, vec![true, true, true, true, false, false]);
+    assert!(any);
+    let (matched, _) = matches(
+        &service,
+        &client_key_id,
+        request(StringMatch::WithinOneEdit, true),
+    )
+    .await;
+    assert_eq!(matched, vec![true, false, true, true, false, false]);
+}
+
+#[tokio::test]
+async fn test_match_string_limits() {
+    let (service, _, server_key_id, byte_ids) = setup_service().await;
+    let request = MatchStringRequest {
+        server_key_id: server_key_id.clone(),
+        byte_ids: byte_ids.clone(),
+        ..Default::default()
+    };
+    assert_eq!(refused(&service, request).await, ErrorReason::InvalidRequest);
+    let request = MatchStringRequest {
+        server_key_id: server_key_id.clone(),
+        byte_ids: byte_ids.clone(),
+        candidates: vec!["x".repeat(65)],
+        ..Default::default()
+    };
+    assert_eq!(refused(&service, request).await, ErrorReason::LimitExceeded);
+    let request = MatchStringRequest {
+        server_key_id,
+        byte_ids: byte_ids.iter().cycle().take(65).cloned().collect(),
+        candidates: vec!["alice".to_string()],
+        ..Default::default()
+    };
+    assert_eq!(refused(&service, request).await, ErrorReason::LimitExceeded);
+}
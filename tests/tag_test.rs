@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use tonic::Request;
+
+use hermetic_fhe::api::{
+    CreationOrder, DeleteCiphertextsRequest, EncryptIntegerRequest, FheService, KeyGenerationRequest,
+    QueryCiphertextsRequest, QueryCiphertextsResponse, TagCiphertextRequest, TagFilter,
+};
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::errors::ErrorReason;
+use hermetic_fhe::service::FheServiceImpl;
+
+// A service with five values encrypted one after another; returns their IDs, oldest first
+async fn setup_service() -> (FheServiceImpl, Vec<String>) {
+    let service = FheServiceImpl::new(Arc::new(KeyStore::new()), Arc::new(CiphertextStore::new()));
+    let keys = service
+        .generate_keys(Request::new(KeyGenerationRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    let mut ids = Vec::new();
+    for value in 0..5 {
+        let request = Request::new(EncryptIntegerRequest {
+            client_key_id: keys.client_key_id.clone(),
+            value,
+            num_bits: 8,
+            ..Default::default()
+        });
+        ids.push(
+            service
+                .encrypt_integer(request)
+                .await
+                .unwrap()
+                .into_inner()
+                .encrypted_data_id,
+        );
+    }
+    (service, ids)
+}
+
+async fn tag(
+    service: &FheServiceImpl,
+    id: &str,
+    tags: &[(&str, &str)],
+    remove: &[&str],
+    replace: bool,
+) -> Result<HashMap<String, String>, ErrorReason> {
+    let request = Request::new(TagCiphertextRequest {
+        encrypted_data_id: id.to_string(),
+        tags: tags
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect(),
+        remove: remove.iter().map(|key| key.to_string()).collect(),
+        replace,
+    });
+    match service.tag_ciphertext(request).await {
+        Ok(response) => Ok(response.into_inner().tags),
+        Err(status) => Err(ErrorReason::of(&status).unwrap()),
+    }
+}
+
+async fn query(service: &FheServiceImpl, request: QueryCiphertextsRequest) -> QueryCiphertextsResponse {
+    service
+        .query_ciphertexts(Request::new(request))
+        .await
+        .unwrap()
+        .into_inner()
+}
+
+// IDs of the values matching the filters, oldest first
+async fn matching(service: &FheServiceImpl, filters: &[(&str, &str)]) -> Vec<String> {
+    let request = QueryCiphertextsRequest {
+        filters: filters
+            .iter()
+            .map(|(key, value)| TagFilter {
+                key: key.to_string(),
+                value: value.to_string(),
+            })
+            .collect(),
+        ..Default::default()
+    };
+    let response = query(service, request).await;
+    response
+        .ciphertexts
+        .into_iter()
+        .map(|found| found.encrypted_data_id)
+        .collect()
+}
+
+#[tokio::test]
+async fn test_query_matches_every_filter() {
+    let (service, ids) = setup_service().await;
+    tag(
+        &service,
+        &ids[0],
+        &[("env", "prod"), ("team", "billing")],
+        &[],
+        false,
+    )
+    .await
+    .unwrap();
+    tag(
+        &service,
+        &ids[1],
+        &[("env", "dev"), ("team", "billing")],
+        &[],
+        false,
+    )
+    .await
+    .unwrap();
+    tag(&service, &ids[2], &[("env", "prod")], &[], false)
+        .await
+        .unwrap();
+    
+    assert_eq!(
+        matching(&service, &[("env", "prod")]).await,
+        vec![ids[0].clone(), ids[2].clone()]
+    );
+    assert_eq!(
+        matching(&service, &[("env", "prod"), ("team", "billing")]).await,
+        vec![ids[0].clone()]
+    );
+    // An empty value matches any value of the key
+    assert_eq!(
+        matching(&service, &[("team", "")]).await,
+        vec![ids[0].clone(), ids[1].clone()]
+    );
+    assert!(matching(&service, &[("env", "staging")]).await.is_empty());
+    // No filters match every stored value, tagged or not
+    assert_eq!(matching(&service, &[]).await, ids);
+    
+    // Tags are set, removed and replaced like a map, and the index follows
+    let tags = tag(&service, &ids[1], &[("env", "prod")], &["team"], false)
+        .await
+        .unwrap();
+    assert_eq!(tags, HashMap::from([("env".to_string(), "prod".to_string())]));
+    assert_eq!(
+        matching(&service, &[("env", "prod")]).await,
+        vec![ids[0].clone(), ids[1].clone(), ids[2].clone()]
+    );
+    assert_eq!(matching(&service, &[("team", "")]).await, vec![ids[0].clone()]);
+    tag(&service, &ids[0], &[("owner", "ada")], &[], true)
+        .await
+        .unwrap();
+    assert_eq!(
+        matching(&service, &[("env", "prod")]).await,
+        vec![ids[1].clone(), ids[2].clone()]
+    );
+    
+    // Deleted values drop out of every query
+    let request = Request::new(DeleteCiphertextsRequest {
+        encrypted_data_ids: vec![ids[1].clone()],
+    });
+    service.delete_ciphertexts(request).await.unwrap();
+    assert_eq!(matching(&service, &[("env", "prod")]).await, vec![ids[2].clone()]);
+}
+
+#[tokio::test]
+async fn test_invalid_tags_are_refused() {
+    let (service, ids) = setup_service().await;
+    assert_eq!(
+        tag(&service, "no-such-ciphertext", &[("env", "prod")], &[], false).await,
+        Err(ErrorReason::CiphertextNotFound)
+    );
+    assert_eq!(
+        tag(&service, &ids[0], &[("", "prod")], &[], false).await,
+        Err(ErrorReason::InvalidRequest)
+    );
+    let long = "x".repeat(257);
+    assert_eq!(
+        tag(&service, &ids[0], &[("env", long.as_str())], &[], false).await,
+        Err(ErrorReason::InvalidRequest)
+    );
+    
+    // A value takes at most 32 tags, and a refused update changes nothing
+    let keys: Vec<String> = (0..32).map(|i| format!("key-{}", i)).collect();
+    let tags: Vec<(&str, &str)> = keys.iter().map(|key| (key.as_str(), "1")).collect();
+    assert_eq!(tag(&service, &ids[0], &tags, &[], false).await.unwrap().len(), 32);
+    assert_eq!(
+        tag(&service, &ids[0], &[("one-more", "1")], &[], false).await,
+        Err(ErrorReason::InvalidRequest)
+    );
+    assert!(matching(&service, &[("one-more", "")]).await.is_empty());
+    assert_eq!(matching(&service, &[("key-31", "1")]).await, vec![ids[0].clone()]);
+}
+
+#[tokio::test]
+async fn test_query_pages_in_creation_order() {
+    let (service, ids) = setup_service().await;
+    for order in [CreationOrder::OldestFirst, CreationOrder::NewestFirst] {
+        let mut seen = Vec::new();
+        let mut page_token = String::new();
+        loop {
+            let request = QueryCiphertextsRequest {
+                page_size: 2,
+                page_token,
+                order: order as i32,
+                ..Default::default()
+            };
+            let response = query(&service, request).await;
+            assert!(response.ciphertexts.len() <= 2);
+            seen.extend(
+                response
+                    .ciphertexts
+                    .into_iter()
+                    .map(|found| found.encrypted_data_id),
+            );
+            if response.next_page_token.is_empty() {
+                break;
+            }
+            page_token = response.next_page_token;
+        }
+        let mut expected = ids.clone();
+        if order == CreationOrder::NewestFirst {
+            expected.reverse();
+        }
+        assert_eq!(seen, expected);
+    }
+    
+    let request = Request::new(QueryCiphertextsRequest {
+        page_token: "not-a-token".to_string(),
+        ..Default::default()
+    });
+    let status = service.query_ciphertexts(request).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::InvalidRequest));
+}
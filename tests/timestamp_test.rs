@@ -0,0 +1,187 @@
+use std::sync::Arc;
+use tonic::Request;
+
+use hermetic_fhe::api::compare_timestamp_request::Other;
+use hermetic_fhe::api::{
+    BucketTimestampRequest, CompareTimestampRequest, DecryptBooleanRequest, DecryptIntegerRequest,
+    DecryptTimestampRequest, EncryptTimestampRequest, FheService, KeyGenerationRequest, TimeUnit,
+    TimestampComparison, TimestampDifferenceRequest,
+};
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::errors::ErrorReason;
+use hermetic_fhe::service::FheServiceImpl;
+
+// 2000-01-01 and 2024-06-01 as days since the epoch
+const BIRTH_DATE: i64 = 10957;
+const TODAY: i64 = 19875;
+// 2006-06-01, the last birth date of anyone 18 or older today
+const AGE_CUTOFF: i64 = 13300;
+
+async fn setup_service() -> FheServiceImpl {
+    let key_store = Arc::new(KeyStore::new());
+    let ciphertext_store = Arc::new(CiphertextStore::new());
+    FheServiceImpl::new(key_store, ciphertext_store)
+}
+
+// Generate keys, returning (client_key_id, server_key_id)
+async fn generate_keys(service: &FheServiceImpl) -> (String, String) {
+    let key_gen_request = Request::new(KeyGenerationRequest {
+        parameter_set: 0, // DEFAULT
+        ..Default::default()
+    });
+    
+    let keys = service.generate_keys(key_gen_request).await.unwrap().into_inner();
+    (keys.client_key_id, keys.server_key_id)
+}
+
+async fn encrypt_timestamp(service: &FheServiceImpl, client_key_id: &str, value: i64, unit: TimeUnit) -> String {
+    let request = Request::new(EncryptTimestampRequest {
+        client_key_id: client_key_id.to_string(),
+        value,
+        unit: unit as i32,
+        session_id: String::new(),
+    });
+    
+    service.encrypt_timestamp(request).await.unwrap().into_inner().encrypted_data_id
+}
+
+fn compare_request(
+    server_key_id: &str,
+    timestamp_id: &str,
+    comparison: TimestampComparison,
+    other: Other,
+) -> Request<CompareTimestampRequest> {
+    Request::new(CompareTimestampRequest {
+        server_key_id: server_key_id.to_string(),
+        timestamp_id: timestamp_id.to_string(),
+        comparison: comparison as i32,
+        other: Some(other),
+        session_id: String::new(),
+    })
+}
+
+async fn decrypt_boolean(service: &FheServiceImpl, client_key_id: &str, encrypted_data_id: &str) -> bool {
+    let request = Request::new(DecryptBooleanRequest {
+        client_key_id: client_key_id.to_string(),
+        encrypted_data_id: encrypted_data_id.to_string(),
+        serialized_data: vec![],
+    });
+    
+    service.decrypt_boolean(request).await.unwrap().into_inner().value
+}
+
+#[tokio::test]
+async fn test_timestamp_round_trip_keeps_unit() {
+    let service = setup_service().await;
+    let (client_key_id, _) = generate_keys(&service).await;
+    
+    for (value, unit) in [(BIRTH_DATE, TimeUnit::Days), (1_717_200_000, TimeUnit::Seconds)] {
+        let timestamp_id = encrypt_timestamp(&service, &client_key_id, value, unit).await;
+        let request = Request::new(DecryptTimestampRequest {
+            client_key_id: client_key_id.clone(),
+            timestamp_id,
+        });
+        let decrypted = service.decrypt_timestamp(request).await.unwrap().into_inner();
+        assert_eq!((decrypted.value, decrypted.unit()), (value, unit));
+    }
+    
+    // Seconds run out in 2106
+    let request = Request::new(EncryptTimestampRequest {
+        client_key_id: client_key_id.clone(),
+        value: 1 << 32,
+        unit: TimeUnit::Seconds as i32,
+        session_id: String::new(),
+    });
+    let status = service.encrypt_timestamp(request).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::ValueOutOfRange));
+}
+
+#[tokio::test]
+async fn test_age_check_and_comparison() {
+    let service = setup_service().await;
+    let (client_key_id, server_key_id) = generate_keys(&service).await;
+    let birth_id = encrypt_timestamp(&service, &client_key_id, BIRTH_DATE, TimeUnit::Days).await;
+    
+    // Born on or before the cutoff means 18 or older
+    let request = compare_request(
+        &server_key_id,
+        &birth_id,
+        TimestampComparison::AtOrBefore,
+        Other::OtherValue(AGE_CUTOFF),
+    );
+    let result_id = service.compare_timestamp(request).await.unwrap().into_inner().result_id;
+    assert!(decrypt_boolean(&service, &client_key_id, &result_id).await);
+    
+    // Against another encrypted date
+    let today_id = encrypt_timestamp(&service, &client_key_id, TODAY, TimeUnit::Days).await;
+    let request = compare_request(
+        &server_key_id,
+        &birth_id,
+        TimestampComparison::After,
+        Other::OtherId(today_id.clone()),
+    );
+    let result_id = service.compare_timestamp(request).await.unwrap().into_inner().result_id;
+    assert!(!decrypt_boolean(&service, &client_key_id, &result_id).await);
+    
+    // Days never meet seconds
+    let instant_id = encrypt_timestamp(&service, &client_key_id, TODAY, TimeUnit::Seconds).await;
+    let request = compare_request(
+        &server_key_id,
+        &today_id,
+        TimestampComparison::At,
+        Other::OtherId(instant_id),
+    );
+    let status = service.compare_timestamp(request).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::TypeMismatch));
+}
+
+#[tokio::test]
+async fn test_difference_and_buckets() {
+    let service = setup_service().await;
+    let (client_key_id, server_key_id) = generate_keys(&service).await;
+    let birth_id = encrypt_timestamp(&service, &client_key_id, BIRTH_DATE, TimeUnit::Days).await;
+    let today_id = encrypt_timestamp(&service, &client_key_id, TODAY, TimeUnit::Days).await;
+    
+    // The later timestamp may come first
+    let request = Request::new(TimestampDifferenceRequest {
+        server_key_id: server_key_id.clone(),
+        a_id: today_id.clone(),
+        b_id: birth_id,
+        session_id: String::new(),
+    });
+    let difference_id = service.timestamp_difference(request).await.unwrap().into_inner().encrypted_data_id;
+    let request = Request::new(DecryptTimestampRequest {
+        client_key_id: client_key_id.clone(),
+        timestamp_id: difference_id,
+    });
+    let difference = service.decrypt_timestamp(request).await.unwrap().into_inner();
+    assert_eq!((difference.value, difference.unit()), (TODAY - BIRTH_DATE, TimeUnit::Days));
+    
+    // Today is past the first two boundaries
+    let bucket_request = |boundaries: Vec<i64>| {
+        Request::new(BucketTimestampRequest {
+            server_key_id: server_key_id.clone(),
+            timestamp_id: today_id.clone(),
+            boundaries,
+            session_id: String::new(),
+        })
+    };
+    let bucket_id = service
+        .bucket_timestamp(bucket_request(vec![AGE_CUTOFF, TODAY, TODAY + 365]))
+        .await
+        .unwrap()
+        .into_inner()
+        .result_id;
+    let request = Request::new(DecryptIntegerRequest {
+        client_key_id: client_key_id.clone(),
+        encrypted_data_id: bucket_id,
+        serialized_data: vec![],
+        ..Default::default()
+    });
+    assert_eq!(service.decrypt_integer(request).await.unwrap().into_inner().value, 2);
+    
+    let status = service.bucket_timestamp(bucket_request(vec![TODAY, AGE_CUTOFF])).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::InvalidRequest));
+    let status = service.bucket_timestamp(bucket_request(vec![-1, TODAY])).await.unwrap_err();
+    assert_eq!(ErrorReason::of(&status), Some(ErrorReason::ValueOutOfRange));
+}
@@ -0,0 +1,231 @@
+use std::sync::Arc;
+use tonic::Request;
+
+use hermetic_fhe::api::{
+    ArgMaxRequest, DecryptBooleanRequest, DecryptIntegerRequest, EncryptIntegerRequest, FheService,
+    KeyGenerationRequest, PirQueryRequest, SetMembershipRequest, SortVectorRequest,
+};
+use hermetic_fhe::crypto::vector::sorting_network;
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::FheServiceImpl;
+
+async fn setup_service() -> FheServiceImpl {
+    let key_store = Arc::new(KeyStore::new());
+    let ciphertext_store = Arc::new(CiphertextStore::new());
+    FheServiceImpl::new(key_store, ciphertext_store)
+}
+
+// Generate keys, returning (client_key_id, server_key_id)
+async fn generate_keys(service: &FheServiceImpl) -> (String, String) {
+    let key_gen_request = Request::new(KeyGenerationRequest {
+        parameter_set: 0, // DEFAULT
+        ..Default::default()
+    });
+    
+    let key_gen_response = service.generate_keys(key_gen_request).await.unwrap();
+    let keys = key_gen_response.into_inner();
+    (keys.client_key_id, keys.server_key_id)
+}
+
+async fn encrypt(service: &FheServiceImpl, client_key_id: &str, value: i64) -> String {
+    let encrypt_request = Request::new(EncryptIntegerRequest {
+        client_key_id: client_key_id.to_string(),
+        value,
+        num_bits: 8,
+        ..Default::default()
+    });
+    
+    let encrypt_response = service.encrypt_integer(encrypt_request).await.unwrap();
+    encrypt_response.get_ref().encrypted_data_id.clone()
+}
+
+async fn decrypt(service: &FheServiceImpl, client_key_id: &str, encrypted_data_id: &str) -> i64 {
+    let decrypt_request = Request::new(DecryptIntegerRequest {
+        client_key_id: client_key_id.to_string(),
+        encrypted_data_id: encrypted_data_id.to_string(),
+        serialized_data: vec![],
+        ..Default::default()
+    });
+    
+    service.decrypt_integer(decrypt_request).await.unwrap().get_ref().value
+}
+
+#[test]
+fn test_sorting_network_sorts_every_binary_input() {
+    // By the 0-1 principle, a network that sorts every 0/1 input sorts everything
+    for n in 0..=10 {
+        let network = sorting_network(n);
+        for bits in 0u32..(1 << n) {
+            let mut values: Vec<u32> = (0..n).map(|i| (bits >> i) & 1).collect();
+            for &(i, j) in &network {
+                assert!(i < j && j < n, "Bad comparator ({}, {}) for n = {}", i, j, n);
+                if values[i] > values[j] {
+                    values.swap(i, j);
+                }
+            }
+            assert!(values.windows(2).all(|pair| pair[0] <= pair[1]), "n = {} left {:?} unsorted", n, values);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_sort_vector() {
+    let service = setup_service().await;
+    let (client_key_id, server_key_id) = generate_keys(&service).await;
+    
+    let mut element_ids = Vec::new();
+    for value in [42, 7, 199, 7, 0] {
+        element_ids.push(encrypt(&service, &client_key_id, value).await);
+    }
+    
+    let request = Request::new(SortVectorRequest {
+        server_key_id,
+        element_ids,
+        ..Default::default()
+    });
+    let response = service.sort_vector(request).await.unwrap().into_inner();
+    assert_eq!(response.sorted_fingerprints.len(), response.sorted_ids.len());
+    
+    let mut sorted = Vec::new();
+    for id in &response.sorted_ids {
+        sorted.push(decrypt(&service, &client_key_id, id).await);
+    }
+    assert_eq!(sorted, vec![0, 7, 7, 42, 199]);
+}
+
+#[tokio::test]
+async fn test_sort_vector_missing_element() {
+    let service = setup_service().await;
+    let (client_key_id, server_key_id) = generate_keys(&service).await;
+    
+    let integer_id = encrypt(&service, &client_key_id, 1).await;
+    
+    let request = Request::new(SortVectorRequest {
+        server_key_id,
+        element_ids: vec![integer_id, "non-existent-element".to_string()],
+        ..Default::default()
+    });
+    
+    let status = service.sort_vector(request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::NotFound);
+}
+
+#[tokio::test]
+async fn test_argmax() {
+    let service = setup_service().await;
+    let (client_key_id, server_key_id) = generate_keys(&service).await;
+    
+    let mut element_ids = Vec::new();
+    for value in [3, 9, 2, 9, 5] {
+        element_ids.push(encrypt(&service, &client_key_id, value).await);
+    }
+    
+    let request = Request::new(ArgMaxRequest {
+        server_key_id,
+        element_ids,
+        ..Default::default()
+    });
+    let response = service.arg_max(request).await.unwrap().into_inner();
+    assert_eq!(response.elements.len(), 1, "k = 0 should return just the maximum");
+    
+    // Ties go to the lowest index
+    let winner = &response.elements[0];
+    assert_eq!(decrypt(&service, &client_key_id, &winner.value_id).await, 9);
+    assert_eq!(decrypt(&service, &client_key_id, &winner.index_id).await, 1);
+}
+
+#[tokio::test]
+async fn test_top_k() {
+    let service = setup_service().await;
+    let (client_key_id, server_key_id) = generate_keys(&service).await;
+    
+    let mut element_ids = Vec::new();
+    for value in [3, 9, 2, 7] {
+        element_ids.push(encrypt(&service, &client_key_id, value).await);
+    }
+    
+    let request = Request::new(ArgMaxRequest {
+        server_key_id,
+        element_ids,
+        k: 3,
+        ..Default::default()
+    });
+    let response = service.arg_max(request).await.unwrap().into_inner();
+    
+    let mut top = Vec::new();
+    for element in &response.elements {
+        top.push((
+            decrypt(&service, &client_key_id, &element.value_id).await,
+            decrypt(&service, &client_key_id, &element.index_id).await,
+        ));
+    }
+    assert_eq!(top, vec![(9, 1), (7, 3), (3, 0)]);
+}
+
+#[tokio::test]
+async fn test_set_membership() {
+    let service = setup_service().await;
+    let (client_key_id, server_key_id) = generate_keys(&service).await;
+    
+    let value_id = encrypt(&service, &client_key_id, 17).await;
+    let other_id = encrypt(&service, &client_key_id, 4).await;
+    let same_id = encrypt(&service, &client_key_id, 17).await;
+    
+    // (encrypted set, plaintext set, expected membership)
+    let cases = [
+        (vec![other_id.clone()], vec![3, 17, 200], true),
+        (vec![other_id.clone(), same_id], vec![], true),
+        (vec![other_id], vec![3, 200], false),
+        (vec![], vec![], false),
+    ];
+    
+    for (element_ids, plaintext_elements, expected) in cases {
+        let request = Request::new(SetMembershipRequest {
+            server_key_id: server_key_id.clone(),
+            value_id: value_id.clone(),
+            element_ids,
+            plaintext_elements: plaintext_elements.clone(),
+            ..Default::default()
+        });
+        let response = service.set_membership(request).await.unwrap().into_inner();
+        assert!(!response.result_fingerprint.is_empty());
+        
+        let decrypt_request = Request::new(DecryptBooleanRequest {
+            client_key_id: client_key_id.clone(),
+            encrypted_data_id: response.result_id,
+            serialized_data: vec![],
+        });
+        let member = service.decrypt_boolean(decrypt_request).await.unwrap().get_ref().value;
+        assert_eq!(member, expected, "Unexpected membership against {:?}", plaintext_elements);
+    }
+}
+
+#[tokio::test]
+async fn test_pir_query() {
+    let service = setup_service().await;
+    let (client_key_id, server_key_id) = generate_keys(&service).await;
+    
+    let table = vec![10, 20, 30, 40];
+    // Indices past the end of the table select 0
+    for (index, expected) in [(2, 30), (0, 10), (9, 0)] {
+        let index_id = encrypt(&service, &client_key_id, index).await;
+        let request = Request::new(PirQueryRequest {
+            server_key_id: server_key_id.clone(),
+            index_id,
+            table: table.clone(),
+            ..Default::default()
+        });
+        let response = service.pir_query(request).await.unwrap().into_inner();
+        assert_eq!(decrypt(&service, &client_key_id, &response.result_id).await, expected);
+    }
+    
+    let index_id = encrypt(&service, &client_key_id, 0).await;
+    let request = Request::new(PirQueryRequest {
+        server_key_id,
+        index_id,
+        table: vec![256],
+        ..Default::default()
+    });
+    let status = service.pir_query(request).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+}
@@ -0,0 +1,73 @@
+use std::borrow::Cow;
+
+use hermetic_fhe::crypto::envelope::MasterKey;
+use hermetic_fhe::crypto::key_directory::{KeyDirectory, KeyPreload};
+use hermetic_fhe::crypto::versioning::{self, DataVersion, TFHE_PARAMETERS, TFHE_SERIES, TFHE_VERSION};
+use hermetic_fhe::crypto::KeyStore;
+
+fn written_by(tfhe: &str) -> DataVersion {
+    DataVersion {
+        tfhe: tfhe.to_string(),
+        parameters: TFHE_PARAMETERS.to_string(),
+    }
+}
+
+#[test]
+fn test_envelopes_record_the_tfhe_version() {
+    let sealed = versioning::seal(b"payload").unwrap();
+    let (version, payload) = versioning::open(&sealed).unwrap();
+    assert_eq!(version, DataVersion::current());
+    assert_eq!(version.tfhe, TFHE_VERSION);
+    assert_eq!(payload, b"payload");
+    assert!(matches!(versioning::read(&sealed).unwrap(), (Cow::Borrowed(b"payload"), false)));
+    
+    // Bytes from before envelopes are tfhe-rs 0.5 data, read as they are but worth rewriting
+    let (version, payload) = versioning::open(b"payload").unwrap();
+    assert_eq!(version, DataVersion::legacy());
+    assert_eq!(payload, b"payload");
+    assert!(versioning::read(b"payload").unwrap().1);
+}
+
+#[test]
+fn test_only_known_releases_are_read() {
+    // The series is pinned, so it is known even when the build found no Cargo.lock, and
+    // data from before envelopes stays readable
+    assert!(TFHE_VERSION == TFHE_SERIES || TFHE_VERSION.starts_with(&format!("{}.", TFHE_SERIES)));
+    DataVersion::legacy().check().unwrap();
+    
+    // Patch releases of the series in use read each other's data
+    let patch = written_by(&format!("{}.99", TFHE_SERIES));
+    assert!(patch.is_current());
+    assert!(matches!(versioning::migrate(&patch, b"payload").unwrap(), Cow::Borrowed(_)));
+    
+    // A release nothing migrates from is refused by name rather than decoded into garbage
+    let foreign = written_by("0.4.1");
+    let error = foreign.check().unwrap_err().to_string();
+    assert!(error.contains("0.4.1"), "{}", error);
+    assert!(versioning::migrate(&foreign, b"payload").is_err());
+}
+
+#[test]
+fn test_key_directory_rewrites_bundles_from_before_envelopes() {
+    let path = std::env::temp_dir().join(format!("hermetic-fhe-keys-{}", uuid::Uuid::new_v4()));
+    let source = KeyStore::with_master_key(MasterKey::from_bytes([9u8; 32]))
+        .with_key_directory(KeyDirectory::open(&path).unwrap());
+    let (client_key_id, server_key_id) = source.generate_keys("DEFAULT").unwrap();
+    
+    // Strip the envelope, leaving the bundle as it was written before them
+    let file = path.join(format!("{}.bundle", server_key_id));
+    let sealed = std::fs::read(&file).unwrap();
+    let (_, bundle) = versioning::open(&sealed).unwrap();
+    std::fs::write(&file, bundle).unwrap();
+    
+    let restarted = KeyStore::with_master_key(MasterKey::from_bytes([9u8; 32]))
+        .with_key_directory(KeyDirectory::open(&path).unwrap());
+    restarted.preload(&KeyPreload::All).unwrap();
+    assert!(restarted.get_client_key(&client_key_id).is_some());
+    assert!(restarted.get_server_key(&server_key_id).is_some());
+    
+    let rewritten = std::fs::read(&file).unwrap();
+    assert_eq!(versioning::open(&rewritten).unwrap().0, DataVersion::current());
+    assert!(!versioning::read(&rewritten).unwrap().1);
+    std::fs::remove_dir_all(&path).unwrap();
+}
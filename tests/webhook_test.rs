@@ -0,0 +1,148 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tonic::{Request, Status};
+
+use hermetic_fhe::api::{
+    CiphertextType, EncryptIntegerRequest, EncryptedRecord, ExportCiphertextRequest, FheService, JobCallback,
+    KeyGenerationRequest, MapOperationRequest, OperationType,
+};
+use hermetic_fhe::crypto::fingerprint::to_hex;
+use hermetic_fhe::crypto::{CiphertextStore, KeyStore};
+use hermetic_fhe::service::errors::ErrorReason;
+use hermetic_fhe::service::webhook::WebhookPolicy;
+use hermetic_fhe::service::FheServiceImpl;
+
+const SECRET: &[u8] = b"webhook-test-secret";
+
+// Accept one request and answer 200, returning its headers (lowercased) and body
+fn receive_one(listener: TcpListener) -> mpsc::Receiver<(Vec<(String, String)>, Vec<u8>)> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut headers = Vec::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+            }
+        }
+        let length = headers
+            .iter()
+            .find(|(name, _)| name == "content-length")
+            .map(|(_, value)| value.parse::<usize>().unwrap())
+            .unwrap_or(0);
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        reader
+            .get_mut()
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        sender.send((headers, body)).unwrap();
+    });
+    receiver
+}
+
+// A service allowing callbacks to the listener, with two labeled records ingested;
+// returns the server key ID and the listener's base URL
+async fn setup_service(listener: &TcpListener) -> (FheServiceImpl, String, String) {
+    let base_url = format!("http://{}/", listener.local_addr().unwrap());
+    let service = FheServiceImpl::new(Arc::new(KeyStore::new()), Arc::new(CiphertextStore::new()))
+        .with_webhook_policy(WebhookPolicy::new(SECRET.to_vec(), [base_url.clone()]));
+    
+    let keys = service
+        .generate_keys(Request::new(KeyGenerationRequest::default()))
+        .await
+        .unwrap()
+        .into_inner();
+    let mut records = Vec::new();
+    for (label, value) in [("rows/a", 1), ("rows/b", 0)] {
+        let request = Request::new(EncryptIntegerRequest {
+            client_key_id: keys.client_key_id.clone(),
+            value,
+            num_bits: 8,
+            ..Default::default()
+        });
+        let encrypted_data_id = service.encrypt_integer(request).await.unwrap().into_inner().encrypted_data_id;
+        let request = Request::new(ExportCiphertextRequest { encrypted_data_id });
+        let export = service.export_ciphertext(request).await.unwrap().into_inner();
+        records.push(Ok::<_, Status>(EncryptedRecord {
+            ciphertext_type: CiphertextType::Integer as i32,
+            serialized_data: export.serialized_data,
+            fingerprint: export.fingerprint,
+            label: label.to_string(),
+            session_id: String::new(),
+            ..Default::default()
+        }));
+    }
+    service.ingest(tokio_stream::iter(records)).await.unwrap();
+    (service, keys.server_key_id, base_url)
+}
+
+fn map_request(server_key_id: &str, url: String) -> Request<MapOperationRequest> {
+    Request::new(MapOperationRequest {
+        server_key_id: server_key_id.to_string(),
+        label_prefix: "rows/".to_string(),
+        result_prefix: "zero/".to_string(),
+        operation: OperationType::IsZero as i32,
+        callback: Some(JobCallback { url }),
+        ..Default::default()
+    })
+}
+
+#[tokio::test]
+async fn test_finished_map_job_notifies_its_callback() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let (service, server_key_id, base_url) = setup_service(&listener).await;
+    let received = receive_one(listener);
+    
+    let request = map_request(&server_key_id, format!("{}hooks/map", base_url));
+    let job_id = service.map_operation(request).await.unwrap().into_inner().job_id;
+    let (headers, body) = tokio::task::spawn_blocking(move || received.recv_timeout(Duration::from_secs(600)))
+        .await
+        .unwrap()
+        .unwrap();
+    
+    // The signature covers the exact body with the shared secret
+    let signature = headers
+        .iter()
+        .find(|(name, _)| name == "x-hermetic-signature")
+        .map(|(_, value)| value.clone())
+        .unwrap();
+    let mut mac = Hmac::<Sha256>::new_from_slice(SECRET).unwrap();
+    mac.update(&body);
+    assert_eq!(signature, format!("sha256={}", to_hex(&mac.finalize().into_bytes())));
+    
+    let event: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(event["job_id"], job_id.as_str());
+    assert_eq!(event["kind"], "map");
+    assert_eq!(event["status"], "succeeded");
+    assert_eq!((event["total"].as_u64(), event["failed"].as_u64()), (Some(2), Some(0)));
+}
+
+#[tokio::test]
+async fn test_callbacks_outside_the_allowed_prefixes_are_refused() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let (service, server_key_id, base_url) = setup_service(&listener).await;
+    
+    // The allowed prefix ends at a slash, so a longer host name doesn't match it
+    let lookalike = format!("{}.evil.example/hook", base_url.trim_end_matches('/'));
+    for url in ["http://elsewhere.example/hook".to_string(), lookalike] {
+        let status = service.map_operation(map_request(&server_key_id, url)).await.unwrap_err();
+        assert_eq!(ErrorReason::of(&status), Some(ErrorReason::PolicyViolation));
+    }
+    
+    // Without a secret no callback is allowed
+    assert!(WebhookPolicy::default().check(&format!("{}hooks/map", base_url)).is_err());
+}
@@ -0,0 +1,57 @@
+import { ServiceError } from "@grpc/grpc-js";
+import { Reader } from "protobufjs/minimal";
+
+// Domain of the ErrorInfo details the service attaches to every error status
+const ERROR_DOMAIN = "hermetic-fhe.v1";
+const ERROR_INFO_TYPE = "type.googleapis.com/google.rpc.ErrorInfo";
+
+// A failed call, with the machine-readable reason the server gave (e.g. KEY_NOT_FOUND), or
+// undefined when the failure didn't come from the service itself
+export class FheError extends Error {
+  constructor(
+    message: string,
+    readonly code: number,
+    readonly reason: string | undefined,
+  ) {
+    super(message);
+    this.name = "FheError";
+  }
+
+  static fromServiceError(error: ServiceError): FheError {
+    const details = error.metadata?.get("grpc-status-details-bin")[0];
+    const reason = details instanceof Buffer ? errorReason(details) : undefined;
+    return new FheError(error.details || error.message, error.code, reason);
+  }
+}
+
+// Reason of the ErrorInfo in a serialized google.rpc.Status, decoded by field number so
+// the google.rpc protos don't have to be generated
+function errorReason(status: Uint8Array): string | undefined {
+  for (const any of fields(status, 3)) {
+    const [typeUrl] = fields(any, 1);
+    const [value] = fields(any, 2);
+    if (typeUrl && value && new TextDecoder().decode(typeUrl) === ERROR_INFO_TYPE) {
+      const [reason] = fields(value, 1);
+      const [domain] = fields(value, 2);
+      if (reason && domain && new TextDecoder().decode(domain) === ERROR_DOMAIN) {
+        return new TextDecoder().decode(reason);
+      }
+    }
+  }
+  return undefined;
+}
+
+// Every length-delimited value of one field in a message
+function fields(message: Uint8Array, field: number): Uint8Array[] {
+  const reader = Reader.create(message);
+  const values: Uint8Array[] = [];
+  while (reader.pos < reader.len) {
+    const tag = reader.uint32();
+    if (tag >>> 3 === field && (tag & 7) === 2) {
+      values.push(reader.bytes());
+    } else {
+      reader.skipType(tag & 7);
+    }
+  }
+  return values;
+}
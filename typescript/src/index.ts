@@ -0,0 +1,231 @@
+import { CallOptions, ChannelCredentials, ClientUnaryCall, credentials, Metadata, ServiceError } from "@grpc/grpc-js";
+
+import { FheError } from "./errors";
+import {
+  CircuitEvaluationRequest,
+  CloseSessionRequest,
+  CreateSessionRequest,
+  DecryptBooleanRequest,
+  DecryptIntegerRequest,
+  EncryptBooleanRequest,
+  EncryptIntegerRequest,
+  EvaluationRequest,
+  FheServiceClient,
+  KeyGenerationRequest,
+  KeyGenerationRequest_ParameterSet,
+  OperationType,
+} from "./generated/hermetic_fhe/v1/fhe_service";
+
+export { FheError } from "./errors";
+// The generated types, for calls the high-level client doesn't cover
+export * as api from "./generated/hermetic_fhe/v1/fhe_service";
+
+// Header the server bills usage to
+const TENANT_HEADER = "x-tenant-id";
+
+export type ParameterSet = "DEFAULT" | "FAST" | "SECURE";
+export type Operation = Exclude<keyof typeof OperationType, "UNRECOGNIZED">;
+
+export interface ClientOptions {
+  credentials?: ChannelCredentials; // Insecure by default
+  tenant?: string; // Sent as x-tenant-id on every call
+  timeoutMs?: number; // Deadline for each call; none by default
+}
+
+export interface KeyPair {
+  clientKeyId: string;
+  serverKeyId: string;
+}
+
+// Handle to a ciphertext stored on the server. The kind decides which decrypt call reads it.
+export class Ciphertext {
+  constructor(
+    readonly id: string,
+    readonly kind: "boolean" | "integer",
+    readonly fingerprint: string,
+  ) {}
+}
+
+// A group of ciphertexts the server frees together, on close or after the idle timeout
+export class Session {
+  constructor(
+    private readonly client: FheClient,
+    readonly id: string,
+    readonly idleTimeoutSeconds: number,
+  ) {}
+
+  // Free every ciphertext created in the session, returning how many there were
+  close(): Promise<number> {
+    return this.client.closeSession(this);
+  }
+}
+
+// Options for calls that store a new ciphertext
+export interface StoreOptions {
+  session?: Session; // Session that owns the result
+}
+
+// Operand of a circuit gate: a circuit input or the output of an earlier gate, by index
+export type Wire = { input: number } | { gate: number };
+
+export interface Gate {
+  operation: Operation;
+  operands: Wire[];
+}
+
+// A DAG of gates evaluated in one call, gates in topological order
+export interface Circuit {
+  gates: Gate[];
+  outputs: Wire[];
+}
+
+// High-level client mirroring the Rust SDK: keys and ciphertexts are handles, and calls
+// return promises that reject with FheError
+export class FheClient {
+  private readonly stub: FheServiceClient;
+
+  constructor(
+    address: string,
+    private readonly options: ClientOptions = {},
+  ) {
+    this.stub = new FheServiceClient(address, options.credentials ?? credentials.createInsecure());
+  }
+
+  async generateKeys(parameterSet: ParameterSet = "DEFAULT"): Promise<KeyPair> {
+    const request = KeyGenerationRequest.fromPartial({
+      parameterSet: KeyGenerationRequest_ParameterSet[parameterSet],
+    });
+    const response = await this.unary(this.stub.generateKeys, request);
+    return { clientKeyId: response.clientKeyId, serverKeyId: response.serverKeyId };
+  }
+
+  async encryptBoolean(clientKeyId: string, value: boolean, options: StoreOptions = {}): Promise<Ciphertext> {
+    const request = EncryptBooleanRequest.fromPartial({ clientKeyId, value, sessionId: options.session?.id });
+    const response = await this.unary(this.stub.encryptBoolean, request);
+    return new Ciphertext(response.encryptedDataId, "boolean", response.fingerprint);
+  }
+
+  async encryptInteger(clientKeyId: string, value: number, options: StoreOptions = {}): Promise<Ciphertext> {
+    const request = EncryptIntegerRequest.fromPartial({
+      clientKeyId,
+      value,
+      numBits: 8,
+      sessionId: options.session?.id,
+    });
+    const response = await this.unary(this.stub.encryptInteger, request);
+    return new Ciphertext(response.encryptedDataId, "integer", response.fingerprint);
+  }
+
+  async evaluate(
+    serverKeyId: string,
+    operation: Operation,
+    operands: Ciphertext[],
+    options: StoreOptions = {},
+  ): Promise<Ciphertext> {
+    const request = EvaluationRequest.fromPartial({
+      serverKeyId,
+      operation: OperationType[operation],
+      operandIds: operands.map((operand) => operand.id),
+      sessionId: options.session?.id,
+    });
+    const response = await this.unary(this.stub.evaluateOperation, request);
+    return new Ciphertext(response.resultId, resultKind(operation), response.resultFingerprint);
+  }
+
+  // Evaluate a circuit over the inputs, returning a handle per output
+  async evaluateCircuit(
+    serverKeyId: string,
+    circuit: Circuit,
+    inputs: Ciphertext[],
+    options: StoreOptions = {},
+  ): Promise<Ciphertext[]> {
+    const request = CircuitEvaluationRequest.fromPartial({
+      serverKeyId,
+      inputIds: inputs.map((input) => input.id),
+      gates: circuit.gates.map((gate) => ({ operation: OperationType[gate.operation], operands: gate.operands })),
+      outputs: circuit.outputs,
+      sessionId: options.session?.id,
+    });
+    const response = await this.unary(this.stub.evaluateCircuit, request);
+    return circuit.outputs.map((output, i) => {
+      const kind = "input" in output ? inputs[output.input].kind : resultKind(circuit.gates[output.gate].operation);
+      return new Ciphertext(response.outputIds[i], kind, response.outputFingerprints[i]);
+    });
+  }
+
+  async decryptBoolean(clientKeyId: string, ciphertext: Ciphertext): Promise<boolean> {
+    const request = DecryptBooleanRequest.fromPartial({ clientKeyId, encryptedDataId: ciphertext.id });
+    return (await this.unary(this.stub.decryptBoolean, request)).value;
+  }
+
+  async decryptInteger(clientKeyId: string, ciphertext: Ciphertext): Promise<number> {
+    const request = DecryptIntegerRequest.fromPartial({ clientKeyId, encryptedDataId: ciphertext.id });
+    return (await this.unary(this.stub.decryptInteger, request)).value;
+  }
+
+  // Decrypt with the call the handle's kind needs
+  decrypt(clientKeyId: string, ciphertext: Ciphertext): Promise<boolean | number> {
+    return ciphertext.kind === "boolean"
+      ? this.decryptBoolean(clientKeyId, ciphertext)
+      : this.decryptInteger(clientKeyId, ciphertext);
+  }
+
+  // A session with the given idle timeout, or the server default
+  async createSession(idleTimeoutSeconds = 0): Promise<Session> {
+    const request = CreateSessionRequest.fromPartial({ idleTimeoutSeconds });
+    const response = await this.unary(this.stub.createSession, request);
+    return new Session(this, response.sessionId, response.idleTimeoutSeconds);
+  }
+
+  async closeSession(session: Session): Promise<number> {
+    const request = CloseSessionRequest.fromPartial({ sessionId: session.id });
+    return (await this.unary(this.stub.closeSession, request)).freedCiphertexts;
+  }
+
+  // Run work in a fresh session that is closed afterwards, even if the work throws
+  async withSession<T>(work: (session: Session) => Promise<T>, idleTimeoutSeconds = 0): Promise<T> {
+    const session = await this.createSession(idleTimeoutSeconds);
+    try {
+      return await work(session);
+    } finally {
+      await session.close();
+    }
+  }
+
+  close(): void {
+    this.stub.close();
+  }
+
+  private unary<Request, Response>(
+    method: (
+      request: Request,
+      metadata: Metadata,
+      options: Partial<CallOptions>,
+      callback: (error: ServiceError | null, response: Response) => void,
+    ) => ClientUnaryCall,
+    request: Request,
+  ): Promise<Response> {
+    const metadata = new Metadata();
+    if (this.options.tenant) {
+      metadata.set(TENANT_HEADER, this.options.tenant);
+    }
+    const callOptions: Partial<CallOptions> = {};
+    if (this.options.timeoutMs !== undefined) {
+      callOptions.deadline = Date.now() + this.options.timeoutMs;
+    }
+    return new Promise((resolve, reject) => {
+      method.call(this.stub, request, metadata, callOptions, (error, response) => {
+        if (error) {
+          reject(FheError.fromServiceError(error));
+        } else {
+          resolve(response);
+        }
+      });
+    });
+  }
+}
+
+// Comparisons and boolean gates give booleans; arithmetic gives integers
+function resultKind(operation: Operation): "boolean" | "integer" {
+  return ["ADD", "SUBTRACT", "MULTIPLY"].includes(operation) ? "integer" : "boolean";
+}